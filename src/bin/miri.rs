@@ -25,13 +25,16 @@ mod log;
 use std::env;
 use std::num::{NonZero, NonZeroI32};
 use std::ops::Range;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::str::FromStr;
 use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
 
 use miri::{
-    BacktraceStyle, BorrowTrackerMethod, GenmcConfig, GenmcCtx, MiriConfig, MiriEntryFnType,
-    ProvenanceMode, TreeBorrowsParams, ValidationMode, run_genmc_mode,
+    BacktraceStyle, BorrowTrackerMethod, DporConfig, DporExplorer, GenmcConfig, GenmcCtx,
+    MiriConfig, MiriEntryFnType, PctConfig, ProvenanceMode, ScheduleTraceConfig, ThreadId,
+    TreeBorrowsParams, ValidationMode, parse_schedule_trace, run_dpor_mode, run_genmc_mode,
 };
 use rustc_abi::ExternAbi;
 use rustc_data_structures::sync::{self, DynSync};
@@ -203,18 +206,40 @@ impl rustc_driver::Callbacks for MiriCompilerCalls {
         let res = if config.genmc_config.is_some() {
             assert!(self.many_seeds.is_none());
             run_genmc_mode(tcx, &config, |genmc_ctx: Rc<GenmcCtx>| {
-                miri::eval_entry(tcx, entry_def_id, entry_type, &config, Some(genmc_ctx))
+                miri::eval_entry(tcx, entry_def_id, entry_type, &config, Some(genmc_ctx), None)
+            })
+        } else if let Some(dpor) = &config.dpor {
+            assert!(self.many_seeds.is_none());
+            run_dpor_mode(dpor, |explorer: Rc<DporExplorer>| {
+                miri::eval_entry(tcx, entry_def_id, entry_type, &config, None, Some(explorer))
             })
         } else if let Some(many_seeds) = self.many_seeds.take() {
             assert!(config.seed.is_none());
             run_many_seeds(many_seeds, |seed| {
                 let mut config = config.clone();
                 config.seed = Some(seed);
+                // Every seed gets its own trace file.
+                if let Some(ScheduleTraceConfig::Record(path)) = &mut config.schedule_trace {
+                    path.as_mut_os_string().push(format!(".{seed}"));
+                }
+                if let Some(path) = &mut config.deadlock_report {
+                    path.as_mut_os_string().push(format!(".{seed}"));
+                }
+                if let Some(path) = &mut config.thread_timeline {
+                    path.as_mut_os_string().push(format!(".{seed}"));
+                }
                 eprintln!("Trying seed: {seed}");
-                miri::eval_entry(tcx, entry_def_id, entry_type, &config, /* genmc_ctx */ None)
+                miri::eval_entry(
+                    tcx,
+                    entry_def_id,
+                    entry_type,
+                    &config,
+                    /* genmc_ctx */ None,
+                    /* dpor */ None,
+                )
             })
         } else {
-            miri::eval_entry(tcx, entry_def_id, entry_type, &config, None)
+            miri::eval_entry(tcx, entry_def_id, entry_type, &config, None, None)
         };
         // Process interpreter result.
         if let Err(return_code) = res {
@@ -485,8 +510,17 @@ fn main() {
     // Parse our arguments and split them across `rustc` and `miri`.
     let mut many_seeds: Option<Range<u32>> = None;
     let mut many_seeds_keep_going = false;
+    let mut pct_depth: Option<u32> = None;
+    let mut pct_steps: u64 = 100_000;
+    let mut dpor = false;
+    let mut dpor_max_executions: u64 = 10_000;
+    let mut dpor_schedule: Option<Vec<ThreadId>> = None;
     let mut miri_config = MiriConfig::default();
     miri_config.env = env_snapshot;
+    if let Ok(cwd) = env::current_dir() {
+        let args = args.iter().take_while(|arg| arg.as_str() != "--").cloned().collect();
+        miri_config.invocation = Some((cwd, args));
+    }
 
     let mut rustc_args = vec![];
     let mut after_dashdash = false;
@@ -539,6 +573,10 @@ fn main() {
             miri_config.collect_leak_backtraces = false;
         } else if arg == "-Zmiri-disable-weak-memory-emulation" {
             miri_config.weak_memory_emulation = false;
+        } else if arg == "-Zmiri-mixed-size-atomics" {
+            miri_config.mixed_size_atomics = true;
+        } else if arg == "-Zmiri-race-backtraces" {
+            miri_config.race_backtraces = true;
         } else if arg == "-Zmiri-track-weak-memory-loads" {
             miri_config.track_outdated_loads = true;
         } else if let Some(param) = arg.strip_prefix("-Zmiri-isolation-error=") {
@@ -579,6 +617,44 @@ fn main() {
             );
         } else if arg == "-Zmiri-fixed-schedule" {
             miri_config.fixed_scheduling = true;
+        } else if arg == "-Zmiri-fair-scheduling" {
+            miri_config.fair_scheduling = true;
+        } else if arg == "-Zmiri-livelock-detection" {
+            miri_config.livelock_detection = Some(10_000);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-livelock-detection=") {
+            let steps = param.parse::<u64>().unwrap_or_else(|err| {
+                fatal_error!("-Zmiri-livelock-detection requires a `u64`: {}", err)
+            });
+            if steps == 0 {
+                fatal_error!("-Zmiri-livelock-detection requires a positive number of steps");
+            }
+            miri_config.livelock_detection = Some(steps);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-record-schedule=") {
+            if miri_config.schedule_trace.is_some() {
+                fatal_error!(
+                    "only one of `-Zmiri-record-schedule` and `-Zmiri-replay-schedule` can be set"
+                );
+            }
+            miri_config.schedule_trace = Some(ScheduleTraceConfig::Record(PathBuf::from(param)));
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-replay-schedule=") {
+            if miri_config.schedule_trace.is_some() {
+                fatal_error!(
+                    "only one of `-Zmiri-record-schedule` and `-Zmiri-replay-schedule` can be set"
+                );
+            }
+            let text = std::fs::read_to_string(param).unwrap_or_else(|err| {
+                fatal_error!("-Zmiri-replay-schedule could not read `{param}`: {err}")
+            });
+            let decisions = parse_schedule_trace(&text).unwrap_or_else(|err| {
+                fatal_error!("-Zmiri-replay-schedule found an invalid trace in `{param}`: {err}")
+            });
+            miri_config.schedule_trace = Some(ScheduleTraceConfig::Replay(decisions));
+        } else if arg == "-Zmiri-deadlock-prediction" {
+            miri_config.deadlock_prediction = true;
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-deadlock-report=") {
+            miri_config.deadlock_report = Some(PathBuf::from(param));
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-thread-timeline=") {
+            miri_config.thread_timeline = Some(PathBuf::from(param));
         } else if arg == "-Zmiri-deterministic-concurrency" {
             miri_config.fixed_scheduling = true;
             miri_config.address_reuse_cross_thread_rate = 0.0;
@@ -611,6 +687,11 @@ fn main() {
                 fatal_error!("-Zmiri-env-set requires an argument of the form <name>=<value>");
             };
             miri_config.set_env_vars.insert(name.to_owned(), value.to_owned());
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-utf8-locale=") {
+            if param.is_empty() || param.contains(['/', ';', '=']) {
+                fatal_error!("-Zmiri-utf8-locale requires a locale name like `en_US.UTF-8`");
+            }
+            miri_config.utf8_locale = param.to_owned();
         } else if let Some(param) = arg.strip_prefix("-Zmiri-track-pointer-tag=") {
             let ids: Vec<u64> = parse_comma_list(param).unwrap_or_else(|err| {
                 fatal_error!("-Zmiri-track-pointer-tag requires a comma separated list of valid `u64` arguments: {err}")
@@ -639,6 +720,37 @@ fn main() {
             miri_config.cmpxchg_weak_failure_rate = parse_rate(param).unwrap_or_else(|err| {
                 fatal_error!("-Zmiri-compare-exchange-weak-failure-rate {err}")
             });
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-pct=") {
+            let depth = param
+                .parse::<u32>()
+                .unwrap_or_else(|err| fatal_error!("-Zmiri-pct requires a `u32`: {}", err));
+            if depth == 0 {
+                fatal_error!("-Zmiri-pct requires a depth of at least 1");
+            }
+            pct_depth = Some(depth);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-pct-steps=") {
+            pct_steps = param
+                .parse::<u64>()
+                .unwrap_or_else(|err| fatal_error!("-Zmiri-pct-steps requires a `u64`: {}", err));
+            if pct_steps == 0 {
+                fatal_error!("-Zmiri-pct-steps requires a positive number of steps");
+            }
+        } else if arg == "-Zmiri-dpor" {
+            dpor = true;
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-dpor-max-executions=") {
+            dpor_max_executions = param.parse::<u64>().unwrap_or_else(|err| {
+                fatal_error!("-Zmiri-dpor-max-executions requires a `u64`: {}", err)
+            });
+            if dpor_max_executions == 0 {
+                fatal_error!("-Zmiri-dpor-max-executions requires at least one execution");
+            }
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-dpor-schedule=") {
+            let ids = parse_comma_list::<u32>(param).unwrap_or_else(|err| {
+                fatal_error!(
+                    "-Zmiri-dpor-schedule requires a comma separated list of thread IDs: {err}"
+                )
+            });
+            dpor_schedule = Some(ids.into_iter().map(ThreadId::new_unchecked).collect());
         } else if let Some(param) = arg.strip_prefix("-Zmiri-preemption-rate=") {
             miri_config.preemption_rate = parse_rate(param)
                 .unwrap_or_else(|err| fatal_error!("-Zmiri-preemption-rate {err}"));
@@ -650,6 +762,14 @@ fn main() {
                 fatal_error!("-Zmiri-report-progress requires a `u32`: {}", err)
             });
             miri_config.report_progress = Some(interval);
+        } else if arg == "-Zmiri-report-thread-leaks" {
+            miri_config.thread_leak_report = Some(miri::ThreadLeakReport::Warn);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-report-thread-leaks=") {
+            miri_config.thread_leak_report = Some(match param {
+                "warn" => miri::ThreadLeakReport::Warn,
+                "error" => miri::ThreadLeakReport::Error,
+                _ => fatal_error!("-Zmiri-report-thread-leaks must be `warn` or `error`"),
+            });
         } else if let Some(param) = arg.strip_prefix("-Zmiri-provenance-gc=") {
             let interval = param.parse::<u32>().unwrap_or_else(|err| {
                 fatal_error!("-Zmiri-provenance-gc requires a `u32`: {}", err)
@@ -691,6 +811,80 @@ fn main() {
                 fatal_error!("-Zmiri-num-cpus must be in the range 1..={}", miri::MAX_CPUS);
             }
             miri_config.num_cpus = num_cpus;
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-realtime-epoch=") {
+            let secs = param.parse::<u64>().unwrap_or_else(|err| {
+                fatal_error!("-Zmiri-realtime-epoch requires a `u64`: {}", err)
+            });
+            miri_config.realtime_epoch = Duration::from_secs(secs);
+            if miri_config.realtime_epoch > miri::RealtimeClock::VIRTUAL_MAX {
+                fatal_error!(
+                    "-Zmiri-realtime-epoch must be at most {} seconds",
+                    miri::RealtimeClock::VIRTUAL_MAX.as_secs()
+                );
+            }
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-realtime-jump=") {
+            let Some((at, by)) = param.split_once(':') else {
+                fatal_error!("-Zmiri-realtime-jump requires an argument of the form `<at>:<by>`");
+            };
+            let at = at.parse::<u64>().unwrap_or_else(|err| {
+                fatal_error!("-Zmiri-realtime-jump requires a `u64` as `<at>`: {}", err)
+            });
+            let by = by.parse::<i64>().unwrap_or_else(|err| {
+                fatal_error!("-Zmiri-realtime-jump requires an `i64` as `<by>`: {}", err)
+            });
+            miri_config.realtime_jumps.push(miri::RealtimeJump {
+                at: Duration::from_millis(at),
+                by_nanos: i128::from(by) * 1_000_000,
+            });
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-rlimit=") {
+            let Some((resource, limits)) = param.split_once('=') else {
+                fatal_error!("-Zmiri-rlimit requires an argument of the form `<resource>=<limit>`");
+            };
+            let parse = |limit: &str| {
+                if limit == "unlimited" {
+                    None
+                } else {
+                    Some(limit.parse::<u64>().unwrap_or_else(|err| {
+                        fatal_error!(
+                            "-Zmiri-rlimit requires a `u64` or `unlimited` as limit: {err}"
+                        )
+                    }))
+                }
+            };
+            let (soft, hard) = match limits.split_once(':') {
+                Some((soft, hard)) => (parse(soft), parse(hard)),
+                None => (parse(limits), parse(limits)),
+            };
+            if hard.is_some_and(|hard| soft.is_none_or(|soft| soft > hard)) {
+                fatal_error!("-Zmiri-rlimit: the soft limit must not exceed the hard limit");
+            }
+            let Some(limit) = miri_config.rlimits.by_name_mut(resource) else {
+                fatal_error!(
+                    "-Zmiri-rlimit: unknown resource `{resource}`, must be `nofile`, `as`, `data`, or `stack`"
+                );
+            };
+            *limit = miri::ResourceLimit { soft, hard };
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-alloc-fail-nth=") {
+            let nth = param.parse::<u64>().unwrap_or_else(|err| {
+                fatal_error!("-Zmiri-alloc-fail-nth requires a `u64`: {}", err)
+            });
+            if nth == 0 {
+                fatal_error!("-Zmiri-alloc-fail-nth counts allocations starting at 1");
+            }
+            miri_config.alloc_failures.nth = Some(nth);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-alloc-fail-rate=") {
+            miri_config.alloc_failures.rate = parse_rate(param)
+                .unwrap_or_else(|err| fatal_error!("-Zmiri-alloc-fail-rate {err}"));
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-alloc-fail-above=") {
+            let above = param.parse::<u64>().unwrap_or_else(|err| {
+                fatal_error!("-Zmiri-alloc-fail-above requires a `u64`: {}", err)
+            });
+            miri_config.alloc_failures.above = Some(above);
+        } else if let Some(param) = arg.strip_prefix("-Zmiri-heap-limit=") {
+            let heap_limit = param
+                .parse::<u64>()
+                .unwrap_or_else(|err| fatal_error!("-Zmiri-heap-limit requires a `u64`: {}", err));
+            miri_config.alloc_failures.heap_limit = Some(heap_limit);
         } else if let Some(param) = arg.strip_prefix("-Zmiri-force-page-size=") {
             let page_size = param.parse::<u64>().unwrap_or_else(|err| {
                 fatal_error!("-Zmiri-force-page-size requires a `u64`: {}", err)
@@ -719,6 +913,64 @@ fn main() {
         fatal_error!("Only one of `-Zmiri-seed` and `-Zmiri-many-seeds can be set");
     }
 
+    if let Some(depth) = pct_depth {
+        if miri_config.fixed_scheduling {
+            fatal_error!("`-Zmiri-pct` cannot be combined with a fixed schedule");
+        }
+        if miri_config.genmc_config.is_some() {
+            fatal_error!("`-Zmiri-pct` cannot be used in GenMC mode");
+        }
+        if miri_config.fair_scheduling {
+            fatal_error!("`-Zmiri-pct` cannot be combined with `-Zmiri-fair-scheduling`");
+        }
+        miri_config.pct = Some(PctConfig { depth, steps: pct_steps });
+    }
+
+    // `-Zmiri-dpor-schedule` implies `-Zmiri-dpor`.
+    if dpor || dpor_schedule.is_some() {
+        if miri_config.genmc_config.is_some() {
+            fatal_error!("`-Zmiri-dpor` cannot be used in GenMC mode");
+        }
+        if many_seeds.is_some() {
+            fatal_error!("`-Zmiri-dpor` cannot be combined with `-Zmiri-many-seeds`");
+        }
+        if miri_config.fixed_scheduling || miri_config.pct.is_some() || miri_config.fair_scheduling
+        {
+            fatal_error!("`-Zmiri-dpor` cannot be combined with another scheduling strategy");
+        }
+        if miri_config.schedule_trace.is_some() {
+            fatal_error!("`-Zmiri-dpor` cannot record or replay a schedule trace");
+        }
+        // The explorer makes all scheduling decisions, and only covers sequentially consistent
+        // executions.
+        miri_config.preemption_rate = 0.0;
+        miri_config.address_reuse_cross_thread_rate = 0.0;
+        miri_config.cmpxchg_weak_failure_rate = 0.0;
+        miri_config.weak_memory_emulation = false;
+        miri_config.dpor =
+            Some(DporConfig { max_executions: dpor_max_executions, schedule: dpor_schedule });
+    }
+
+    // A replayed trace overrides the decisions that differ between seeds.
+    if many_seeds.is_some()
+        && matches!(miri_config.schedule_trace, Some(ScheduleTraceConfig::Replay(_)))
+    {
+        fatal_error!("`-Zmiri-replay-schedule` cannot be combined with `-Zmiri-many-seeds`");
+    }
+    if miri_config.genmc_config.is_some() && miri_config.mixed_size_atomics {
+        fatal_error!("`-Zmiri-mixed-size-atomics` cannot be used in GenMC mode");
+    }
+    if miri_config.genmc_config.is_some()
+        && (miri_config.livelock_detection.is_some() || miri_config.fair_scheduling)
+    {
+        fatal_error!(
+            "GenMC mode does its own scheduling and cannot detect livelocks or schedule fairly"
+        );
+    }
+    if miri_config.genmc_config.is_some() && miri_config.schedule_trace.is_some() {
+        fatal_error!("GenMC mode does its own scheduling and cannot record or replay a schedule");
+    }
+
     // Ensure we have parallelism for many-seeds mode.
     if many_seeds.is_some() && !rustc_args.iter().any(|arg| arg.starts_with("-Zthreads=")) {
         // Clamp to 20 threads; things get a less efficient beyond that due to lock contention.
@@ -1,5 +1,5 @@
 use std::cell::Cell;
-use std::time::{Duration, Instant as StdInstant};
+use std::time::{Duration, Instant as StdInstant, SystemTime};
 
 /// When using a virtual clock, this defines how many nanoseconds we pretend are passing for each
 /// basic block.
@@ -8,6 +8,12 @@ use std::time::{Duration, Instant as StdInstant};
 /// (See `tests/pass/shims/time-with-isolation*.rs`.)
 const NANOSECONDS_PER_BASIC_BLOCK: u128 = 5000;
 
+/// The CPU time we pretend a thread has used after executing the given number of basic blocks.
+/// Unlike the monotonic clock, this is the same with and without isolation.
+pub fn cpu_time_for_basic_blocks(blocks: u64) -> Duration {
+    Duration::from_nanos_u128(u128::from(blocks).strict_mul(NANOSECONDS_PER_BASIC_BLOCK))
+}
+
 #[derive(Debug)]
 pub struct Instant {
     kind: InstantKind,
@@ -128,4 +134,79 @@ impl MonotonicClock {
                 Instant { kind: InstantKind::Virtual { nanoseconds: nanoseconds.get() } },
         }
     }
+
+    /// Return the time that passed since the `epoch`, i.e. the current time as an absolute duration.
+    pub fn elapsed(&self) -> Duration {
+        self.now().duration_since(self.epoch())
+    }
+}
+
+/// A discontinuous change of the virtual wall clock, like the one caused by an NTP correction or
+/// by someone changing the system time.
+#[derive(Clone, Copy, Debug)]
+pub struct RealtimeJump {
+    /// How long after program start (as measured by the monotonic clock) the jump happens.
+    pub at: Duration,
+    /// How far the clock jumps, in nanoseconds. Negative values make it jump backwards.
+    pub by_nanos: i128,
+}
+
+/// A wall clock used for `SystemTime` simulation.
+#[derive(Debug)]
+pub struct RealtimeClock {
+    kind: RealtimeClockKind,
+}
+
+#[derive(Debug)]
+enum RealtimeClockKind {
+    Host,
+    Virtual {
+        /// The time since the Unix epoch at which the program starts.
+        epoch: Duration,
+        /// The jumps to apply, sorted by the time at which they happen.
+        jumps: Vec<RealtimeJump>,
+    },
+}
+
+impl RealtimeClock {
+    /// The latest time the virtual wall clock can show, as time since the Unix epoch. This is the
+    /// end of 64-bit nanosecond timestamps (in 2262), which `SystemTime` can represent on all
+    /// hosts.
+    pub const VIRTUAL_MAX: Duration = Duration::from_nanos(i64::MAX.cast_unsigned());
+
+    /// Create a new clock based on the availability of communication with the host.
+    /// `epoch` and `jumps` only matter for the virtual clock.
+    pub fn new(communicate: bool, epoch: Duration, mut jumps: Vec<RealtimeJump>) -> Self {
+        let kind = if communicate {
+            RealtimeClockKind::Host
+        } else {
+            jumps.sort_by_key(|jump| jump.at);
+            RealtimeClockKind::Virtual { epoch, jumps }
+        };
+
+        Self { kind }
+    }
+
+    /// The current wall clock time. The virtual clock advances in lockstep with the (virtual)
+    /// monotonic clock, except for the configured jumps. It never goes back further than the
+    /// Unix epoch, and stops at `VIRTUAL_MAX`.
+    pub fn now(&self, monotonic: &MonotonicClock) -> SystemTime {
+        match &self.kind {
+            RealtimeClockKind::Host => SystemTime::now(),
+            RealtimeClockKind::Virtual { epoch, jumps } => {
+                let elapsed = monotonic.elapsed();
+                let jumped: i128 = jumps
+                    .iter()
+                    .take_while(|jump| jump.at <= elapsed)
+                    .map(|jump| jump.by_nanos)
+                    .fold(0, i128::saturating_add);
+                let nanos = i128::try_from(epoch.saturating_add(elapsed).as_nanos())
+                    .unwrap()
+                    .saturating_add(jumped);
+                let nanos = u128::try_from(nanos).unwrap_or(0);
+                let since_epoch = Duration::from_nanos_u128(nanos).min(Self::VIRTUAL_MAX);
+                SystemTime::UNIX_EPOCH + since_epoch
+            }
+        }
+    }
 }
@@ -43,6 +43,7 @@
 use std::cell::{Cell, Ref, RefCell, RefMut};
 use std::fmt::Debug;
 use std::mem;
+use std::ops::Range;
 
 use rustc_abi::{Align, HasDataLayout, Size};
 use rustc_ast::Mutability;
@@ -53,10 +54,11 @@ use rustc_middle::mir;
 use rustc_middle::ty::Ty;
 use rustc_span::Span;
 
+use super::race_backtraces::AccessStacks;
 use super::vector_clock::{VClock, VTimestamp, VectorIdx};
 use super::weak_memory::EvalContextExt as _;
 use crate::concurrency::GlobalDataRaceHandler;
-use crate::diagnostics::RacingOp;
+use crate::diagnostics::{RacingOp, prune_stacktrace};
 use crate::intrinsics::AtomicRmwOp;
 use crate::*;
 
@@ -357,6 +359,12 @@ pub struct GlobalState {
 
     /// Whether weak memory emulation is enabled
     pub weak_memory: bool,
+
+    /// Whether to record the call stacks of accesses for race reports.
+    race_backtraces: bool,
+
+    /// Whether atomic accesses of different sizes may race.
+    pub mixed_size_atomics: bool,
 }
 
 impl VisitProvenance for GlobalState {
@@ -500,6 +508,15 @@ impl MemoryCellClocks {
         }
     }
 
+    /// With `-Zmiri-mixed-size-atomics`, unsynchronized atomic accesses of different sizes are
+    /// not a race. The location then takes the size of the current access, which makes
+    /// `atomic_access` accept it.
+    fn allow_mixed_size(&mut self, size: Size) {
+        if let Some(atomic) = &mut self.atomic_ops {
+            atomic.size = Some(size);
+        }
+    }
+
     /// Update memory cell data-race tracking for atomic
     /// load acquire semantics, is a no-op if this memory was
     /// not used previously as atomic memory.
@@ -722,6 +739,7 @@ pub trait EvalContextExt<'tcx>: MiriInterpCxExt<'tcx> {
     ) -> InterpResult<'tcx, Scalar> {
         let this = self.eval_context_ref();
         this.atomic_access_check(place, AtomicAccessType::Load(atomic))?;
+        this.dpor_atomic_access(place, /* is_write */ false);
         // This will read from the last store in the modification order of this location. In case
         // weak memory emulation is enabled, this may not be the store we will pick to actually read from and return.
         // This is fine with StackedBorrow and race checks because they don't concern metadata on
@@ -743,7 +761,9 @@ pub trait EvalContextExt<'tcx>: MiriInterpCxExt<'tcx> {
         let buffered_scalar = this.buffered_atomic_read(place, atomic, scalar, |sync_clock| {
             this.validate_atomic_load(place, atomic, sync_clock)
         })?;
-        interp_ok(buffered_scalar.ok_or_else(|| err_ub!(InvalidUninitBytes(None)))?)
+        let buffered_scalar = buffered_scalar.ok_or_else(|| err_ub!(InvalidUninitBytes(None)))?;
+        this.livelock_atomic_load(place, buffered_scalar);
+        interp_ok(buffered_scalar)
     }
 
     /// Perform an atomic write operation at the memory location.
@@ -755,6 +775,8 @@ pub trait EvalContextExt<'tcx>: MiriInterpCxExt<'tcx> {
     ) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
         this.atomic_access_check(dest, AtomicAccessType::Store)?;
+        this.dpor_atomic_access(dest, /* is_write */ true);
+        this.livelock_progress(this.active_thread());
 
         // Inform GenMC about the atomic store.
         if let Some(genmc_ctx) = this.machine.data_race.as_genmc_ref() {
@@ -791,6 +813,8 @@ pub trait EvalContextExt<'tcx>: MiriInterpCxExt<'tcx> {
     ) -> InterpResult<'tcx, ImmTy<'tcx>> {
         let this = self.eval_context_mut();
         this.atomic_access_check(place, AtomicAccessType::Rmw)?;
+        this.dpor_atomic_access(place, /* is_write */ true);
+        this.livelock_progress(this.active_thread());
 
         let old = this.allow_data_races_mut(|this| this.read_immediate(place))?;
 
@@ -845,6 +869,8 @@ pub trait EvalContextExt<'tcx>: MiriInterpCxExt<'tcx> {
     ) -> InterpResult<'tcx, Scalar> {
         let this = self.eval_context_mut();
         this.atomic_access_check(place, AtomicAccessType::Rmw)?;
+        this.dpor_atomic_access(place, /* is_write */ true);
+        this.livelock_progress(this.active_thread());
 
         let old = this.allow_data_races_mut(|this| this.read_scalar(place))?;
         this.allow_data_races_mut(|this| this.write_scalar(new, place))?;
@@ -923,10 +949,26 @@ pub trait EvalContextExt<'tcx>: MiriInterpCxExt<'tcx> {
         let success_rate = 1.0 - this.machine.cmpxchg_weak_failure_rate;
         let cmpxchg_success = eq.to_scalar().to_bool()?
             && if can_fail_spuriously {
-                this.machine.rng.get_mut().random_bool(success_rate)
+                let success = this.machine.rng.get_mut().random_bool(success_rate);
+                match &this.machine.schedule_trace {
+                    // Without spurious failures there is no choice to record.
+                    Some(trace) if success_rate < 1.0 => {
+                        let success = trace.compare_exchange_weak(success);
+                        this.machine.report_schedule_divergence();
+                        success
+                    }
+                    _ => success,
+                }
             } else {
                 true
             };
+        // A failed compare exchange only reads.
+        this.dpor_atomic_access(place, /* is_write */ cmpxchg_success);
+        if cmpxchg_success {
+            this.livelock_progress(this.active_thread());
+        } else {
+            this.livelock_atomic_load(place, old.to_scalar());
+        }
         let res = Immediate::ScalarPair(old.to_scalar(), Scalar::from_bool(cmpxchg_success));
 
         // Update ptr depending on comparison.
@@ -1003,6 +1045,8 @@ pub trait EvalContextExt<'tcx>: MiriInterpCxExt<'tcx> {
 pub struct VClockAlloc {
     /// Assigning each byte a MemoryCellClocks.
     alloc_ranges: RefCell<DedupRangeMap<MemoryCellClocks>>,
+    /// The call stacks of the recent accesses of each byte, with `-Zmiri-race-backtraces`.
+    access_stacks: Option<RefCell<DedupRangeMap<AccessStacks>>>,
 }
 
 impl VisitProvenance for VClockAlloc {
@@ -1054,6 +1098,36 @@ impl VClockAlloc {
                 len,
                 MemoryCellClocks::new(alloc_timestamp, alloc_index),
             )),
+            access_stacks: global
+                .race_backtraces
+                .then(|| RefCell::new(DedupRangeMap::new(len, AccessStacks::default()))),
+        }
+    }
+
+    /// Records the call stack of an access by the active thread, which had vector index `index`
+    /// and the given `timestamp`, for race reports.
+    fn record_access_stack(
+        access_stacks: Option<&RefCell<DedupRangeMap<AccessStacks>>>,
+        access_range: AllocRange,
+        index: VectorIdx,
+        timestamp: VTimestamp,
+        access: AccessType,
+        machine: &MiriMachine<'_>,
+    ) {
+        let Some(access_stacks) = access_stacks else {
+            return;
+        };
+        let Some(stack) = machine.intern_active_stack() else {
+            return;
+        };
+        let mut access_stacks = access_stacks.borrow_mut();
+        for (_range, stacks) in access_stacks.iter_mut(access_range.start, access_range.size) {
+            if matches!(access, AccessType::AtomicRmw) {
+                stacks.record(index, timestamp, /*write*/ false, /*atomic*/ true, stack);
+                stacks.record(index, timestamp, /*write*/ true, /*atomic*/ true, stack);
+            } else {
+                stacks.record(index, timestamp, !access.is_read(), access.is_atomic(), stack);
+            }
         }
     }
 
@@ -1093,18 +1167,24 @@ impl VClockAlloc {
     /// of data-race that occurred. This will also
     /// return info about the memory location the data-race
     /// occurred in. The `ty` parameter is used for diagnostics, letting
-    /// the user know which type was involved in the access.
+    /// the user know which type was involved in the access. `race_range`
+    /// is the part of the access where the race was found, which shares
+    /// `mem_clocks`.
     #[cold]
     #[inline(never)]
     fn report_data_race<'tcx>(
-        global: &GlobalState,
-        thread_mgr: &ThreadManager<'_>,
+        machine: &MiriMachine<'_>,
+        access_stacks: Option<&RefCell<DedupRangeMap<AccessStacks>>>,
         mem_clocks: &MemoryCellClocks,
         access: AccessType,
         access_size: Size,
-        ptr_dbg: interpret::Pointer<AllocId>,
+        alloc_id: AllocId,
+        race_range: Range<u64>,
         ty: Option<Ty<'_>>,
     ) -> InterpResult<'tcx> {
+        let ptr_dbg = interpret::Pointer::new(alloc_id, Size::from_bytes(race_range.start));
+        let global = machine.data_race.as_vclocks_ref().unwrap();
+        let thread_mgr = &machine.threads;
         let (active_index, active_clocks) = global.active_thread_state(thread_mgr);
         let mut other_size = None; // if `Some`, this was a size-mismatch race
         let write_clock;
@@ -1151,6 +1231,37 @@ impl VClockAlloc {
         let active_thread_info = global.print_thread_metadata(thread_mgr, active_index);
         let other_thread_info = global.print_thread_metadata(thread_mgr, other_thread);
         let involves_non_atomic = !access.is_atomic() || !other_access.is_atomic();
+        let other_timestamp = other_clock.as_slice()[other_thread.index()];
+
+        // Find the call stack of the earlier access, if it was recorded. Stacks are recorded per
+        // byte and only a bounded number of them, so the earlier access may only be found at some
+        // of the racing bytes.
+        let other_stack = access_stacks.and_then(|access_stacks| {
+            let start = Size::from_bytes(race_range.start);
+            let size = Size::from_bytes(race_range.end.strict_sub(race_range.start));
+            access_stacks.borrow().iter(start, size).find_map(|(_range, stacks)| {
+                stacks.find(
+                    other_thread,
+                    other_timestamp,
+                    !other_access.is_read(),
+                    other_access.is_atomic(),
+                )
+            })
+        });
+        let other_backtrace = match other_stack {
+            Some(stack) => {
+                let frames = machine.race_stacks.as_ref().unwrap().borrow().frames(stack);
+                let (frames, _was_pruned) = prune_stacktrace(frames, machine);
+                let source_map = machine.tcx.sess.source_map();
+                frames
+                    .iter()
+                    .map(|frame| {
+                        format!("{frame} at {}", source_map.span_to_embeddable_string(frame.span))
+                    })
+                    .collect()
+            }
+            None => Vec::new(),
+        };
 
         // Throw the data-race detection.
         let extra = if other_size.is_some() {
@@ -1169,12 +1280,14 @@ impl VClockAlloc {
             op1: RacingOp {
                 action: other_access.description(None, other_size),
                 thread_info: other_thread_info,
-                span: other_clock.as_slice()[other_thread.index()].span_data(),
+                span: other_timestamp.span_data(),
+                backtrace: other_backtrace,
             },
             op2: RacingOp {
                 action: access.description(ty, other_size.map(|_| access_size)),
                 thread_info: active_thread_info,
                 span: active_clocks.clock.as_slice()[active_index.index()].span_data(),
+                backtrace: Vec::new(),
             },
         }))?
     }
@@ -1221,16 +1334,25 @@ impl VClockAlloc {
                 drop(thread_clocks);
                 // Report data-race.
                 return Self::report_data_race(
-                    global,
-                    &machine.threads,
+                    machine,
+                    self.access_stacks.as_ref(),
                     mem_clocks,
                     AccessType::NaRead(read_type),
                     access_range.size,
-                    interpret::Pointer::new(alloc_id, Size::from_bytes(mem_clocks_range.start)),
+                    alloc_id,
+                    mem_clocks_range,
                     ty,
                 );
             }
         }
+        Self::record_access_stack(
+            self.access_stacks.as_ref(),
+            access_range,
+            index,
+            thread_clocks.clock[index],
+            AccessType::NaRead(read_type),
+            machine,
+        );
         interp_ok(())
     }
 
@@ -1248,7 +1370,7 @@ impl VClockAlloc {
         machine: &mut MiriMachine<'_>,
     ) -> InterpResult<'tcx> {
         let current_span = machine.current_user_relevant_span();
-        let global = machine.data_race.as_vclocks_mut().unwrap();
+        let global = machine.data_race.as_vclocks_ref().unwrap();
         if !global.race_detecting() {
             return interp_ok(());
         }
@@ -1262,16 +1384,25 @@ impl VClockAlloc {
                 drop(thread_clocks);
                 // Report data-race
                 return Self::report_data_race(
-                    global,
-                    &machine.threads,
+                    machine,
+                    self.access_stacks.as_ref(),
                     mem_clocks,
                     AccessType::NaWrite(write_type),
                     access_range.size,
-                    interpret::Pointer::new(alloc_id, Size::from_bytes(mem_clocks_range.start)),
+                    alloc_id,
+                    mem_clocks_range,
                     ty,
                 );
             }
         }
+        Self::record_access_stack(
+            self.access_stacks.as_ref(),
+            access_range,
+            index,
+            thread_clocks.clock[index],
+            AccessType::NaWrite(write_type),
+            machine,
+        );
         interp_ok(())
     }
 }
@@ -1608,23 +1739,32 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
                 for (mem_clocks_range, mem_clocks) in
                     alloc_meta.alloc_ranges.borrow_mut().iter_mut(base_offset, size)
                 {
+                    if data_race.mixed_size_atomics {
+                        mem_clocks.allow_mixed_size(size);
+                    }
                     if let Err(DataRace) = op(mem_clocks, &mut thread_clocks, index, atomic) {
                         mem::drop(thread_clocks);
                         return VClockAlloc::report_data_race(
-                            data_race,
-                            &this.machine.threads,
+                            &this.machine,
+                            alloc_meta.access_stacks.as_ref(),
                             mem_clocks,
                             access,
                             place.layout.size,
-                            interpret::Pointer::new(
-                                alloc_id,
-                                Size::from_bytes(mem_clocks_range.start),
-                            ),
+                            alloc_id,
+                            mem_clocks_range,
                             None,
                         )
                         .map(|_| true);
                     }
                 }
+                VClockAlloc::record_access_stack(
+                    alloc_meta.access_stacks.as_ref(),
+                    alloc_range(base_offset, size),
+                    index,
+                    thread_clocks.clock[index],
+                    access,
+                    &this.machine,
+                );
 
                 // This conservatively assumes all operations have release semantics
                 interp_ok(true)
@@ -1662,6 +1802,8 @@ impl GlobalState {
             last_sc_write_per_thread: RefCell::new(VClock::default()),
             track_outdated_loads: config.track_outdated_loads,
             weak_memory: config.weak_memory_emulation,
+            race_backtraces: config.race_backtraces,
+            mixed_size_atomics: config.mixed_size_atomics,
         };
 
         // Setup the main-thread since it is not explicitly created:
@@ -0,0 +1,104 @@
+//! The machine-readable form of a deadlock report, written with `-Zmiri-deadlock-report`.
+//!
+//! The report is a JSON object with one entry per blocked thread: why it is blocked, which threads
+//! it waits for, and its backtrace. Together, the entries form the wait-for graph of the program
+//! at the time it deadlocked.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use crate::diagnostics::prune_stacktrace;
+use crate::*;
+
+/// A short name for why a thread is blocked.
+pub(super) fn block_reason_name(reason: BlockReason) -> &'static str {
+    match reason {
+        BlockReason::Join(_) => "join",
+        BlockReason::Sleep => "sleep",
+        BlockReason::Mutex => "mutex",
+        BlockReason::Condvar => "condvar",
+        BlockReason::RwLock => "rwlock",
+        BlockReason::Futex => "futex",
+        BlockReason::InitOnce => "init-once",
+        BlockReason::Epoll => "epoll",
+        BlockReason::Eventfd => "eventfd",
+        BlockReason::UnnamedSocket => "unnamed-socket",
+        BlockReason::FileLock => "file-lock",
+        BlockReason::NamedPipe => "named-pipe",
+        BlockReason::ThreadPool => "thread-pool",
+        BlockReason::Alertable => "alertable",
+        BlockReason::Network => "network",
+        BlockReason::ChildProcess => "child-process",
+        BlockReason::Genmc => "genmc",
+    }
+}
+
+pub(super) fn json_string(s: &str) -> String {
+    let mut out = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c < ' ' => write!(out, "\\u{:04x}", u32::from(c)).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl<'tcx> MiriMachine<'tcx> {
+    /// Writes the wait-for graph of the deadlocked program to `path`.
+    pub(crate) fn write_deadlock_report(&self, path: &Path) -> Result<(), String> {
+        let source_map = self.tcx.sess.source_map();
+        let mut threads = Vec::new();
+        for (id, stack) in self.threads.all_blocked_stacks() {
+            let (reason, wait_object) = self.threads.blocked_on(id).unwrap();
+            let mut waits_for = Vec::new();
+            if let BlockReason::Join(joined) = reason {
+                waits_for.push(format!("{{ \"thread\": {} }}", joined.to_u32()));
+            }
+            let holders = match wait_object {
+                Some(wait_object) => wait_object.holders(),
+                None if reason == BlockReason::Futex =>
+                    self.std_mutex_holders(id).unwrap_or_default(),
+                None => vec![],
+            };
+            for holder in holders {
+                let acquired_at = match holder.acquired_at {
+                    Some(span) => json_string(&source_map.span_to_embeddable_string(span)),
+                    None => "null".to_owned(),
+                };
+                waits_for.push(format!(
+                    "{{ \"thread\": {}, \"shared\": {}, \"acquired_at\": {acquired_at} }}",
+                    holder.thread.to_u32(),
+                    holder.shared,
+                ));
+            }
+            let stacktrace = Frame::generate_stacktrace_from_stack(stack);
+            let (stacktrace, _was_pruned) = prune_stacktrace(stacktrace, self);
+            let backtrace: Vec<String> = stacktrace
+                .iter()
+                .map(|frame| {
+                    json_string(&format!(
+                        "{frame} at {}",
+                        source_map.span_to_embeddable_string(frame.span)
+                    ))
+                })
+                .collect();
+            threads.push(format!(
+                "    {{\n      \"id\": {},\n      \"name\": {},\n      \"blocked_on\": \"{}\",\n      \"waits_for\": [{}],\n      \"backtrace\": [{}]\n    }}",
+                id.to_u32(),
+                json_string(&self.threads.get_thread_display_name(id)),
+                block_reason_name(reason),
+                waits_for.join(", "),
+                backtrace.join(", "),
+            ));
+        }
+        let report = format!("{{\n  \"threads\": [\n{}\n  ]\n}}\n", threads.join(",\n"));
+        fs::write(path, report).map_err(|err| {
+            format!("failed to write deadlock report to `{}`: {err}", path.display())
+        })
+    }
+}
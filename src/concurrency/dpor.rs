@@ -0,0 +1,410 @@
+//! A built-in stateless model checker based on dynamic partial-order reduction (DPOR), from
+//! "Dynamic Partial-Order Reduction for Model Checking Software" by Flanagan and Godefroid.
+//!
+//! With `-Zmiri-dpor`, Miri runs the program again and again with a different interleaving of its
+//! threads, until it has covered all sequentially consistent interleavings that matter or found an
+//! error. Atomic accesses and operations on Miri's own synchronization objects (the mutexes,
+//! read-write locks, condition variables and futexes behind the pthread, Windows and macOS shims)
+//! are the visible events: after each of them, the explorer picks the thread that runs next. By
+//! default it keeps running the active thread, so every execution is a sequence of choice points
+//! ("nodes") with one visible event each.
+//!
+//! Two interleavings that only differ in the order of independent events (accesses to disjoint
+//! memory, or two loads) behave the same, so only one of them is explored. After every event,
+//! the explorer uses vector clocks to find the last earlier event it depends on that does not
+//! happen-before it, and remembers to try the other order at the node of that earlier event. Once
+//! an execution ends, the next one replays the choices up to the deepest node with a thread left
+//! to try, and makes the default choices from there.
+//!
+//! Weak memory emulation, spurious `compare_exchange_weak` failures and random preemption are
+//! disabled in this mode, so executions only differ where the explorer made different choices.
+
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeSet;
+use std::mem;
+use std::num::NonZeroI32;
+use std::ops::Bound;
+use std::rc::Rc;
+
+use rustc_abi::Size;
+use rustc_data_structures::fx::FxHashMap;
+use rustc_index::IndexVec;
+use rustc_span::DUMMY_SP;
+
+use super::vector_clock::{VClock, VectorIdx};
+use crate::*;
+
+/// The configuration of `-Zmiri-dpor`.
+#[derive(Debug, Clone)]
+pub struct DporConfig {
+    /// The number of executions after which the exploration stops.
+    pub max_executions: u64,
+    /// The choices to make instead of exploring, as given to `-Zmiri-dpor-schedule`.
+    pub schedule: Option<Vec<ThreadId>>,
+}
+
+/// What visible events access. Atomic accesses are dependent if they overlap in at least one byte
+/// and one of them is a write, so every byte is a location of its own. All operations on the same
+/// synchronization object are dependent.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Location {
+    Byte(AllocId, Size),
+    /// A `MutexRef`, `RwLockRef`, `CondvarRef` or `FutexRef`, by the address of its state.
+    Sync(usize),
+}
+
+#[derive(Debug)]
+struct Event {
+    is_write: bool,
+    /// The clock of the thread right after the event.
+    clock: VClock,
+}
+
+/// A point of the execution where the explorer picked the thread to run next.
+#[derive(Debug)]
+struct Node {
+    /// The threads that could run.
+    enabled: BTreeSet<ThreadId>,
+    /// The thread that runs in the current execution.
+    chosen: ThreadId,
+    /// The threads that have to run here in some execution.
+    backtrack: BTreeSet<ThreadId>,
+    /// The threads that ran here in some execution.
+    done: BTreeSet<ThreadId>,
+    /// The first visible event of `chosen` after this point in the current execution.
+    event: Option<Event>,
+}
+
+#[derive(Debug, Default)]
+struct LocationState {
+    /// The clock of the last write.
+    write: VClock,
+    /// The join of the clocks of the reads since the last write.
+    reads: VClock,
+    /// The nodes whose event accessed this location, oldest first.
+    nodes: Vec<usize>,
+    /// For a synchronization object, keeps it alive so that its address is not reused during this
+    /// execution.
+    object: Option<Rc<dyn Any>>,
+}
+
+/// The state of the current execution.
+#[derive(Debug, Default)]
+struct Execution {
+    /// The number of nodes passed so far.
+    position: usize,
+    /// The number of choices taken from the replayed schedule so far.
+    replayed: usize,
+    /// The clock of each thread, which only advances at visible events.
+    thread_clocks: IndexVec<ThreadId, VClock>,
+    locations: FxHashMap<Location, LocationState>,
+    /// Whether there was a visible event since the last choice.
+    choice_pending: bool,
+    /// Whether the active thread explicitly yielded since the last choice.
+    yielded: bool,
+}
+
+impl Execution {
+    fn thread_clock(&mut self, thread: ThreadId) -> &mut VClock {
+        self.thread_clocks.ensure_contains_elem(thread, VClock::default)
+    }
+}
+
+#[derive(Debug)]
+pub struct DporExplorer {
+    max_executions: u64,
+    /// The schedule given to `-Zmiri-dpor-schedule`, if any.
+    replay: Option<Vec<ThreadId>>,
+    /// The nodes of the current execution. At the start of an execution, these are the nodes
+    /// whose choices are replayed.
+    nodes: RefCell<Vec<Node>>,
+    execution: RefCell<Execution>,
+    /// The number of executions started so far.
+    executions: Cell<u64>,
+}
+
+impl DporExplorer {
+    fn new(config: &DporConfig) -> Self {
+        DporExplorer {
+            max_executions: config.max_executions,
+            replay: config.schedule.clone(),
+            nodes: RefCell::new(Vec::new()),
+            execution: RefCell::new(Execution::default()),
+            executions: Cell::new(0),
+        }
+    }
+
+    /// Whether the scheduler has to ask the explorer for the next thread, even if the active
+    /// thread could keep running.
+    pub fn choice_pending(&self) -> bool {
+        self.execution.borrow().choice_pending
+    }
+
+    /// The active thread explicitly yielded, so the next default choice is another thread.
+    pub fn yielded(&self) {
+        self.execution.borrow_mut().yielded = true;
+    }
+
+    /// Picks the thread to run among `enabled`. Fails if the program does not behave the same
+    /// way as in the execution whose choices are being replayed.
+    pub fn choose(
+        &self,
+        active: ThreadId,
+        enabled: impl Iterator<Item = ThreadId>,
+    ) -> Result<Option<ThreadId>, String> {
+        let enabled: BTreeSet<ThreadId> = enabled.collect();
+        let mut execution = self.execution.borrow_mut();
+        execution.choice_pending = false;
+        let yielded = mem::take(&mut execution.yielded);
+        if enabled.is_empty() {
+            return Ok(None);
+        }
+        let position = execution.position;
+        execution.position = position.strict_add(1);
+
+        let mut nodes = self.nodes.borrow_mut();
+        if let Some(node) = nodes.get(position) {
+            if node.enabled != enabled {
+                return Err(format!(
+                    "the program behaved differently when it was re-executed by the DPOR explorer \
+                    (at choice {position}); this can happen if it depends on the host, for \
+                    instance with isolation disabled"
+                ));
+            }
+            return Ok(Some(node.chosen));
+        }
+
+        let chosen = if let Some(replay) = &self.replay
+            && enabled.len() > 1
+            && let Some(&id) = replay.get(execution.replayed)
+        {
+            if !enabled.contains(&id) {
+                return Err(format!(
+                    "the DPOR schedule runs thread {} at choice {}, but that thread cannot run there",
+                    id.to_u32(),
+                    execution.replayed,
+                ));
+            }
+            execution.replayed = execution.replayed.strict_add(1);
+            id
+        } else if enabled.contains(&active) && !yielded {
+            active
+        } else {
+            // Move on to the next thread after the active one.
+            *enabled
+                .range((Bound::Excluded(active), Bound::Unbounded))
+                .next()
+                .or_else(|| enabled.first())
+                .unwrap()
+        };
+        nodes.push(Node {
+            enabled,
+            chosen,
+            backtrack: BTreeSet::from([chosen]),
+            done: BTreeSet::from([chosen]),
+            event: None,
+        });
+        Ok(Some(chosen))
+    }
+
+    /// Records an atomic access by `thread` to `size` bytes at `offset` in `alloc_id`.
+    pub fn atomic_access(
+        &self,
+        thread: ThreadId,
+        alloc_id: AllocId,
+        offset: Size,
+        size: Size,
+        is_write: bool,
+    ) {
+        let locations: Vec<Location> = (0..size.bytes())
+            .map(|i| Location::Byte(alloc_id, offset + Size::from_bytes(i)))
+            .collect();
+        self.access(thread, &locations, is_write);
+    }
+
+    /// Records an operation by `thread` on a synchronization object. Since such operations can
+    /// enable or disable other threads, they all count as writes.
+    pub fn sync_access(&self, thread: ThreadId, object: Rc<dyn Any>) {
+        let location = Location::Sync(Rc::as_ptr(&object).addr());
+        let mut execution = self.execution.borrow_mut();
+        execution.locations.entry(location).or_default().object.get_or_insert(object);
+        drop(execution);
+        self.access(thread, &[location], /* is_write */ true);
+    }
+
+    /// Records an event of `thread` that accesses `locations`, and remembers to try running
+    /// `thread` before the last earlier event it races with.
+    fn access(&self, thread: ThreadId, locations: &[Location], is_write: bool) {
+        let mut execution = self.execution.borrow_mut();
+        let execution = &mut *execution;
+        execution.choice_pending = true;
+        let mut nodes = self.nodes.borrow_mut();
+        let clock = execution.thread_clocks.ensure_contains_elem(thread, VClock::default);
+
+        let racing = locations
+            .iter()
+            .filter_map(|location| {
+                let state = execution.locations.get(location)?;
+                state.nodes.iter().rev().copied().find(|&i| {
+                    let node = &nodes[i];
+                    let event = node.event.as_ref().unwrap();
+                    node.chosen != thread && (is_write || event.is_write) && !event.clock.le(clock)
+                })
+            })
+            .max();
+        if let Some(i) = racing {
+            let node = &mut nodes[i];
+            if node.enabled.contains(&thread) {
+                node.backtrack.insert(thread);
+            } else {
+                // `thread` could not run there, so try everything that could.
+                node.backtrack.extend(node.enabled.iter().copied());
+            }
+        }
+
+        for location in locations {
+            let state = execution.locations.entry(*location).or_default();
+            clock.join(&state.write);
+            if is_write {
+                clock.join(&state.reads);
+            }
+        }
+        clock.increment_index(VectorIdx::from(thread.to_u32()), DUMMY_SP);
+
+        // The event belongs to the last node if that node chose this thread and has no event yet.
+        // Otherwise, for instance for accesses made while unblocking another thread, it cannot be
+        // reordered.
+        let node = execution
+            .position
+            .checked_sub(1)
+            .filter(|&i| nodes[i].chosen == thread && nodes[i].event.is_none());
+        if let Some(i) = node {
+            nodes[i].event = Some(Event { is_write, clock: clock.clone() });
+        }
+        for location in locations {
+            let state = execution.locations.get_mut(location).unwrap();
+            if is_write {
+                state.write = clock.clone();
+                state.reads = VClock::default();
+            } else {
+                state.reads.join(clock);
+            }
+            if let Some(i) = node {
+                state.nodes.push(i);
+            }
+        }
+    }
+
+    /// The new thread `child` starts with everything `parent` did so far.
+    pub fn thread_created(&self, parent: ThreadId, child: ThreadId) {
+        let mut execution = self.execution.borrow_mut();
+        let clock = execution.thread_clock(parent).clone();
+        *execution.thread_clock(child) = clock;
+    }
+
+    /// `thread` has joined `joined`, and so comes after everything it did.
+    pub fn thread_joined(&self, thread: ThreadId, joined: ThreadId) {
+        let mut execution = self.execution.borrow_mut();
+        let clock = execution.thread_clock(joined).clone();
+        execution.thread_clock(thread).join(&clock);
+    }
+
+    /// The choices of the current execution at the points where more than one thread could run,
+    /// in the format of `-Zmiri-dpor-schedule`.
+    fn schedule(&self) -> String {
+        let nodes = self.nodes.borrow();
+        let position = self.execution.borrow().position;
+        let choices: Vec<String> = nodes[..position]
+            .iter()
+            .filter(|node| node.enabled.len() > 1)
+            .map(|node| node.chosen.to_u32().to_string())
+            .collect();
+        choices.join(",")
+    }
+
+    /// Prepares the next execution. Returns `false` if there is nothing left to explore.
+    fn next_execution(&self) -> bool {
+        *self.execution.borrow_mut() = Execution::default();
+        if self.replay.is_some() {
+            return false;
+        }
+        let mut nodes = self.nodes.borrow_mut();
+        // Go back to the deepest node with a thread left to try.
+        while let Some(node) = nodes.last_mut() {
+            if let Some(&next) = node.backtrack.difference(&node.done).next() {
+                node.chosen = next;
+                node.done.insert(next);
+                break;
+            }
+            nodes.pop();
+        }
+        // The events of the replayed nodes are recorded again.
+        for node in nodes.iter_mut() {
+            node.event = None;
+        }
+        !nodes.is_empty()
+    }
+}
+
+fn executions(n: u64) -> String {
+    if n == 1 { "1 execution".to_owned() } else { format!("{n} executions") }
+}
+
+/// Runs the program in DPOR mode: `eval_entry` is called once per execution, until an execution
+/// fails, all relevant interleavings are explored, or the limit of executions is reached.
+pub fn run_dpor_mode(
+    config: &DporConfig,
+    eval_entry: impl Fn(Rc<DporExplorer>) -> Result<(), NonZeroI32>,
+) -> Result<(), NonZeroI32> {
+    let explorer = Rc::new(DporExplorer::new(config));
+    loop {
+        let rep = explorer.executions.get().strict_add(1);
+        explorer.executions.set(rep);
+
+        if let Err(err) = eval_entry(explorer.clone()) {
+            eprintln!(
+                "DPOR found a failing execution after exploring {}; replay it with `-Zmiri-dpor-schedule={}`",
+                executions(rep),
+                explorer.schedule(),
+            );
+            return Err(err);
+        }
+        if !explorer.next_execution() {
+            eprintln!("DPOR explored {}", executions(rep));
+            return Ok(());
+        }
+        if rep >= explorer.max_executions {
+            eprintln!(
+                "DPOR stopped after {} without covering all interleavings; raise the limit with `-Zmiri-dpor-max-executions`",
+                executions(rep),
+            );
+            return Ok(());
+        }
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Tells the DPOR explorer, if any, about an atomic access by the active thread.
+    fn dpor_atomic_access(&self, place: &MPlaceTy<'tcx>, is_write: bool) {
+        let this = self.eval_context_ref();
+        let Some(dpor) = &this.machine.dpor else {
+            return;
+        };
+        let (alloc_id, offset, _prov) = this
+            .ptr_try_get_alloc_id(place.ptr(), 0)
+            .expect("there are no zero-sized atomic accesses");
+        dpor.atomic_access(this.active_thread(), alloc_id, offset, place.layout.size, is_write);
+    }
+
+    /// Tells the DPOR explorer, if any, about an operation of the active thread on the state of a
+    /// synchronization object.
+    fn dpor_sync_access<T: 'static>(&self, object: &Rc<T>) {
+        let this = self.eval_context_ref();
+        let Some(dpor) = &this.machine.dpor else {
+            return;
+        };
+        dpor.sync_access(this.active_thread(), object.clone());
+    }
+}
@@ -0,0 +1,177 @@
+//! Detecting threads that spin without making progress (`-Zmiri-livelock-detection`).
+//!
+//! A thread takes a spin step whenever it performs an atomic load that returns the same value as
+//! its previous load from that location, or yields with `spin_loop`, `sched_yield` or one of their
+//! equivalents. An atomic write by the thread, a load that returns a new value, and being woken up
+//! after blocking all count as progress, and start a new count. Once the active thread has taken
+//! the configured number of steps without progress, the other threads are checked:
+//! - If no thread that can run has made progress in that many steps either, and no thread waits
+//!   for a timeout, the program is reported as livelocked.
+//! - If some other thread could have run, but none executed anything since the active thread last
+//!   made progress, the scheduler starved them, and that is reported instead.
+//!
+//! Both can be artifacts of the scheduler rather than bugs of the program. With
+//! `-Zmiri-fair-scheduling`, every spin step hands over to the next thread in round-robin order, so
+//! threads cannot be starved, and a livelock reported in that mode does not depend on the schedule.
+
+use rustc_abi::Size;
+use rustc_data_structures::fx::FxHashMap;
+
+use crate::*;
+
+/// The maximal number of locations per thread whose last loaded value is remembered.
+const MAX_LOCATIONS: usize = 64;
+
+type Location = (AllocId, Size);
+
+#[derive(Debug, Default)]
+struct SpinState {
+    /// The number of spin steps since the last progress.
+    steps: u64,
+    /// The value of the last load from each location, with pointers represented by their address.
+    observed: FxHashMap<Location, u128>,
+    /// The location of the last atomic load.
+    location: Option<Location>,
+    /// The number of basic blocks executed by all threads, and by this thread, at the last
+    /// progress.
+    blocks_at_progress: (u64, u64),
+}
+
+#[derive(Debug)]
+pub struct LivelockDetector {
+    /// The number of spin steps without progress after which a thread counts as stuck.
+    max_steps: u64,
+    threads: FxHashMap<ThreadId, SpinState>,
+    /// Whether the active thread took a spin step since the scheduler last ran.
+    spun: bool,
+}
+
+impl LivelockDetector {
+    pub fn new(max_steps: u64) -> Self {
+        Self { max_steps, threads: FxHashMap::default(), spun: false }
+    }
+
+    fn progress(&mut self, thread: ThreadId, blocks: (u64, u64)) {
+        let state = self.threads.entry(thread).or_default();
+        state.steps = 0;
+        state.blocks_at_progress = blocks;
+    }
+
+    fn spin(&mut self, thread: ThreadId) {
+        let state = self.threads.entry(thread).or_default();
+        state.steps = state.steps.saturating_add(1);
+        self.spun = true;
+    }
+
+    fn load(&mut self, thread: ThreadId, location: Location, val: u128, blocks: (u64, u64)) {
+        let state = self.threads.entry(thread).or_default();
+        state.location = Some(location);
+        if state.observed.len() >= MAX_LOCATIONS && !state.observed.contains_key(&location) {
+            state.observed.clear();
+        }
+        if state.observed.insert(location, val) == Some(val) {
+            self.spin(thread);
+        } else {
+            self.progress(thread, blocks);
+        }
+    }
+
+    /// The number of spin steps `thread` took since its last progress.
+    pub fn steps(&self, thread: ThreadId) -> u64 {
+        self.threads.get(&thread).map_or(0, |state| state.steps)
+    }
+
+    /// The location `thread` last loaded from.
+    pub fn location(&self, thread: ThreadId) -> Option<interpret::Pointer<AllocId>> {
+        let (alloc_id, offset) = self.threads.get(&thread)?.location?;
+        Some(interpret::Pointer::new(alloc_id, offset))
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Tells the livelock detector, if any, that the active thread loaded `val` from `place`.
+    fn livelock_atomic_load(&self, place: &MPlaceTy<'tcx>, val: Scalar) {
+        let this = self.eval_context_ref();
+        let Some(detector) = &this.machine.livelock else {
+            return;
+        };
+        let (alloc_id, offset, _prov) = this
+            .ptr_try_get_alloc_id(place.ptr(), 0)
+            .expect("there are no zero-sized atomic accesses");
+        let val = match val {
+            Scalar::Int(int) => int.to_bits_unchecked(),
+            Scalar::Ptr(ptr, _size) => u128::from(ptr.addr().bytes()),
+        };
+        let thread = this.active_thread();
+        let blocks = (
+            this.machine.basic_block_count,
+            this.machine.threads.get_thread_basic_block_count(thread),
+        );
+        detector.borrow_mut().load(thread, (alloc_id, offset), val, blocks);
+    }
+
+    /// Tells the livelock detector, if any, that `thread` made progress: it wrote to an atomic
+    /// location, or it was woken up.
+    fn livelock_progress(&self, thread: ThreadId) {
+        let this = self.eval_context_ref();
+        let Some(detector) = &this.machine.livelock else {
+            return;
+        };
+        let blocks = (
+            this.machine.basic_block_count,
+            this.machine.threads.get_thread_basic_block_count(thread),
+        );
+        detector.borrow_mut().progress(thread, blocks);
+    }
+
+    /// Tells the livelock detector, if any, that the active thread yielded.
+    fn livelock_yield(&self) {
+        let this = self.eval_context_ref();
+        if let Some(detector) = &this.machine.livelock {
+            detector.borrow_mut().spin(this.active_thread());
+        }
+    }
+
+    /// Called by the scheduler. Reports a livelock or starvation if the active thread has been
+    /// spinning for too long. Returns whether the active thread took a spin step since the last
+    /// call.
+    fn livelock_check(&self) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_ref();
+        let Some(detector) = &this.machine.livelock else {
+            return interp_ok(false);
+        };
+        let mut detector = detector.borrow_mut();
+        if !std::mem::take(&mut detector.spun) {
+            return interp_ok(false);
+        }
+        let threads = &this.machine.threads;
+        let active = threads.active_thread();
+        let steps = detector.steps(active);
+        if steps < detector.max_steps {
+            return interp_ok(true);
+        }
+        if !threads.has_pending_timeout()
+            && threads
+                .enabled_program_threads()
+                .all(|thread| detector.steps(thread) >= detector.max_steps)
+        {
+            throw_machine_stop!(TerminationInfo::Livelock {
+                steps,
+                fair: threads.fair_scheduling()
+            });
+        }
+        let (all_blocks, own_blocks) = detector.threads[&active].blocks_at_progress;
+        let other_blocks = this
+            .machine
+            .basic_block_count
+            .strict_sub(all_blocks)
+            .strict_sub(threads.get_thread_basic_block_count(active).strict_sub(own_blocks));
+        let starved: Vec<ThreadId> =
+            threads.enabled_program_threads().filter(|&thread| thread != active).collect();
+        if other_blocks == 0 && !starved.is_empty() {
+            throw_machine_stop!(TerminationInfo::SpinStarvation { steps, starved });
+        }
+        interp_ok(true)
+    }
+}
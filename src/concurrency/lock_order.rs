@@ -0,0 +1,406 @@
+//! Predicting deadlocks from the order in which threads acquire locks.
+//!
+//! When a thread acquires lock `L` while it holds lock `H`, that adds an edge `H -> L` to a global
+//! lock-order graph. A cycle in that graph whose edges come from different threads means that
+//! those threads acquire the same locks in inconsistent order, so under some other schedule they
+//! can each hold one lock while waiting for the next one, even if they did not deadlock in this
+//! execution. This is the "lock graph" (or "goodlock") algorithm.
+//!
+//! To avoid reporting acquisitions that can never overlap, edges carry the vector clock of their
+//! thread right before it acquired the second lock, and an edge only counts if it does not
+//! happen-before the moment the current thread started holding its lock. That moment is taken
+//! before the lock is acquired, so the synchronization through the locks of the cycle themselves
+//! does not hide the inversion. A cycle on which some lock is only ever held shared cannot
+//! deadlock either, so it is not reported.
+//!
+//! The locks are Miri's own mutexes and read-write locks, which back the pthread, Windows and macOS
+//! lock shims, and the `Mutex` of the standard library, which is recognized through its
+//! diagnostic items since it is built on atomics and futexes on most targets. The standard
+//! library's `RwLock` is only covered on targets where it uses the pthread shims.
+
+use std::collections::VecDeque;
+use std::fmt;
+
+use rustc_abi::Size;
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_middle::ty;
+use rustc_span::{Span, SpanData};
+
+use super::vector_clock::VClock;
+use crate::diagnostics::prune_stacktrace;
+use crate::*;
+
+/// Identifies one of Miri's own mutexes or read-write locks. A lock gets its id from the machine
+/// the first time it is acquired.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SyncLockId(u64);
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum LockId {
+    /// A `MutexRef` or `RwLockRef`.
+    Sync(SyncLockId),
+    /// A `Mutex` of the standard library, by its address.
+    Std(AllocId, Size),
+}
+
+impl fmt::Display for LockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockId::Sync(SyncLockId(id)) => write!(f, "lock {id}"),
+            LockId::Std(alloc_id, offset) => write!(f, "{alloc_id:?}+{:#x}", offset.bytes()),
+        }
+    }
+}
+
+/// A `Mutex` of the standard library that a thread is in the middle of acquiring.
+#[derive(Debug)]
+pub struct StdMutexAcquisition {
+    lock: LockId,
+    /// The depth of the stack frame that acquires it; the acquisition ends when that frame
+    /// returns.
+    depth: usize,
+    /// The clock of the thread when it started acquiring the mutex.
+    clock: Option<VClock>,
+}
+
+/// A lock that a thread currently holds.
+#[derive(Debug)]
+struct HeldLock {
+    lock: LockId,
+    shared: bool,
+    /// Where the thread acquired it.
+    span: Span,
+    /// The clock of the thread right before it acquired the lock.
+    clock: Option<VClock>,
+}
+
+/// Some thread acquired `to` while holding `from`.
+#[derive(Clone, Debug)]
+struct Edge<'tcx> {
+    from: LockId,
+    to: LockId,
+    thread: ThreadId,
+    from_shared: bool,
+    to_shared: bool,
+    /// Where `to` was acquired.
+    span: Span,
+    /// The clock of the thread right before it acquired `to`.
+    clock: Option<VClock>,
+    /// The pruned backtrace of the acquisition of `to`.
+    backtrace: Vec<FrameInfo<'tcx>>,
+}
+
+impl Edge<'_> {
+    /// Whether this acquisition could overlap with a thread that started holding a lock at
+    /// `clock`.
+    fn concurrent_with(&self, clock: Option<&VClock>) -> bool {
+        match (&self.clock, clock) {
+            (Some(edge_clock), Some(clock)) => !edge_clock.le(clock),
+            // Without the data race detector, there are no clocks to tell.
+            _ => true,
+        }
+    }
+}
+
+/// An acquisition on a lock-order cycle, as reported in a potential deadlock. The locks are
+/// numbered along the cycle, starting with the one whose acquisition closed it.
+#[derive(Debug)]
+pub struct CycleAcquisition {
+    /// The name of the thread that acquired the lock.
+    pub thread: String,
+    /// The number of the lock that the thread held.
+    pub held: usize,
+    /// The number of the lock that the thread acquired.
+    pub acquired: usize,
+    pub span: SpanData,
+    /// The backtrace of the acquisition, one frame per line.
+    pub backtrace: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct LockOrder<'tcx> {
+    /// The locks each thread holds, in the order it acquired them.
+    held: FxHashMap<ThreadId, Vec<HeldLock>>,
+    /// The edges of the lock-order graph, by the lock they start at. There is at most one edge
+    /// per pair of locks and thread, with the data of the latest such acquisition.
+    edges: FxHashMap<LockId, Vec<Edge<'tcx>>>,
+    /// The acquisition sites of the cycles reported so far, sorted.
+    reported: FxHashSet<Vec<Span>>,
+}
+
+/// Finds a path of `edges` from `from` to `to` that are not by `thread` and could overlap with a
+/// thread that started holding a lock at `clock`.
+fn find_path<'a, 'tcx>(
+    edges: &'a FxHashMap<LockId, Vec<Edge<'tcx>>>,
+    from: LockId,
+    to: LockId,
+    thread: ThreadId,
+    clock: Option<&VClock>,
+) -> Option<Vec<&'a Edge<'tcx>>> {
+    let mut predecessors: FxHashMap<LockId, &Edge<'tcx>> = FxHashMap::default();
+    let mut queue = VecDeque::from([from]);
+    while let Some(lock) = queue.pop_front() {
+        if lock == to {
+            let mut path = Vec::new();
+            let mut current = to;
+            while current != from {
+                let edge = predecessors[&current];
+                path.push(edge);
+                current = edge.from;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for edge in edges.get(&lock).into_iter().flatten() {
+            if edge.thread != thread
+                && edge.to != from
+                && !predecessors.contains_key(&edge.to)
+                && edge.concurrent_with(clock)
+            {
+                predecessors.insert(edge.to, edge);
+                queue.push_back(edge.to);
+            }
+        }
+    }
+    None
+}
+
+impl<'tcx> LockOrder<'tcx> {
+    /// Records that `thread` acquired `lock`. If that closes a new cycle, returns where the thread
+    /// acquired the lock it holds on the cycle, and the acquisitions of the other threads.
+    fn acquire(
+        &mut self,
+        thread: ThreadId,
+        lock: LockId,
+        shared: bool,
+        span: Span,
+        clock: Option<VClock>,
+        backtrace: impl FnOnce() -> Vec<FrameInfo<'tcx>>,
+    ) -> Option<(Span, Vec<Edge<'tcx>>)> {
+        let mut held = self.held.remove(&thread).unwrap_or_default();
+        let mut cycle = None;
+        for h in held.iter().filter(|h| h.lock != lock) {
+            let Some(path) = find_path(&self.edges, lock, h.lock, thread, h.clock.as_ref()) else {
+                continue;
+            };
+            // The threads can only block each other if every lock on the cycle is acquired
+            // exclusively at least once.
+            let mut accesses = vec![(shared, path[0].from_shared)];
+            accesses.extend(path.windows(2).map(|w| (w[0].to_shared, w[1].from_shared)));
+            accesses.push((path.last().unwrap().to_shared, h.shared));
+            if accesses.iter().any(|&(a, b)| a && b) {
+                continue;
+            }
+            let mut sites: Vec<Span> = path.iter().map(|edge| edge.span).collect();
+            sites.push(span);
+            sites.sort();
+            if self.reported.insert(sites) {
+                cycle = Some((h.span, path.into_iter().cloned().collect()));
+                break;
+            }
+        }
+
+        if held.iter().any(|h| h.lock != lock) {
+            let backtrace = backtrace();
+            for h in held.iter().filter(|h| h.lock != lock) {
+                let edge = Edge {
+                    from: h.lock,
+                    to: lock,
+                    thread,
+                    from_shared: h.shared,
+                    to_shared: shared,
+                    span,
+                    clock: clock.clone(),
+                    backtrace: backtrace.clone(),
+                };
+                let edges = self.edges.entry(h.lock).or_default();
+                match edges.iter_mut().find(|e| e.to == lock && e.thread == thread) {
+                    Some(e) => *e = edge,
+                    None => edges.push(edge),
+                }
+            }
+        }
+
+        held.push(HeldLock { lock, shared, span, clock });
+        self.held.insert(thread, held);
+        cycle
+    }
+
+    /// Records that `thread` released `lock`.
+    fn release(&mut self, thread: ThreadId, lock: LockId) {
+        if let Some(held) = self.held.get_mut(&thread)
+            && let Some(pos) = held.iter().rposition(|h| h.lock == lock)
+        {
+            held.remove(pos);
+        }
+    }
+
+    /// The threads that hold `lock`.
+    fn holders(&self, lock: LockId) -> Vec<LockHolder> {
+        let mut holders: Vec<LockHolder> = self
+            .held
+            .iter()
+            .filter_map(|(&thread, held)| {
+                let h = held.iter().find(|h| h.lock == lock)?;
+                Some(LockHolder { thread, acquired_at: Some(h.span), shared: h.shared })
+            })
+            .collect();
+        holders.sort_by_key(|holder| holder.thread);
+        holders
+    }
+}
+
+impl<'tcx> MiriMachine<'tcx> {
+    /// If `thread` is in the middle of acquiring a `Mutex` of the standard library, returns the
+    /// threads that hold that mutex. This is only known with `-Zmiri-deadlock-prediction`.
+    pub(crate) fn std_mutex_holders(&self, thread: ThreadId) -> Option<Vec<LockHolder>> {
+        let lock_order = self.lock_order.as_ref()?;
+        let lock = self.std_mutex_acquiring.borrow().get(&thread)?.lock;
+        Some(lock_order.borrow().holders(lock))
+    }
+}
+
+impl<'tcx> EvalContextExtPriv<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// The clock of the active thread, if it is needed to predict deadlocks.
+    fn lock_order_clock(&self) -> Option<VClock> {
+        let this = self.eval_context_ref();
+        this.machine.lock_order.as_ref()?;
+        this.machine.data_race.as_vclocks_ref().map(|data_race| {
+            let (_index, clocks) = data_race.active_thread_state(&this.machine.threads);
+            clocks.clock.clone()
+        })
+    }
+
+    /// Records that the active thread acquired `lock`, which it started acquiring when its clock
+    /// was `clock`, and reports a potential deadlock if that acquisition is in inconsistent order
+    /// with those of other threads.
+    fn record_lock_acquired(&self, lock: LockId, shared: bool, clock: Option<VClock>) {
+        let this = self.eval_context_ref();
+        this.timeline_lock(lock, Some(shared));
+        let Some(lock_order) = &this.machine.lock_order else {
+            return;
+        };
+        let thread = this.active_thread();
+        let span = this.machine.current_user_relevant_span();
+        let cycle = lock_order.borrow_mut().acquire(thread, lock, shared, span, clock, || {
+            prune_stacktrace(this.generate_stacktrace(), &this.machine).0
+        });
+        let Some((held_span, path)) = cycle else {
+            return;
+        };
+
+        let source_map = this.tcx.sess.source_map();
+        let others = path
+            .iter()
+            .enumerate()
+            .map(|(i, edge)| {
+                CycleAcquisition {
+                    thread: this.machine.threads.get_thread_display_name(edge.thread),
+                    held: i.strict_add(1),
+                    acquired: i.strict_add(2),
+                    span: edge.span.data(),
+                    backtrace: edge
+                        .backtrace
+                        .iter()
+                        .map(|frame| {
+                            format!(
+                                "{frame} at {}",
+                                source_map.span_to_embeddable_string(frame.span)
+                            )
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+        this.emit_diagnostic(NonHaltingDiagnostic::PotentialDeadlock {
+            held_span: held_span.data(),
+            others,
+        });
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Returns the id stored in `id`, assigning the next unused one if there is none yet.
+    fn sync_lock_id(&self, id: &mut Option<SyncLockId>) -> SyncLockId {
+        let this = self.eval_context_ref();
+        *id.get_or_insert_with(|| {
+            let next = this.machine.next_sync_lock_id.get();
+            this.machine.next_sync_lock_id.set(next.strict_add(1));
+            SyncLockId(next)
+        })
+    }
+
+    /// Records that the active thread acquired `lock`, and reports a potential deadlock if that
+    /// acquisition is in inconsistent order with those of other threads.
+    fn lock_acquired(&self, lock: LockId, shared: bool) {
+        let this = self.eval_context_ref();
+        let clock = this.lock_order_clock();
+        this.record_lock_acquired(lock, shared, clock);
+    }
+
+    /// Records that the active thread released `lock`.
+    fn lock_released(&self, lock: LockId) {
+        let this = self.eval_context_ref();
+        this.timeline_lock(lock, None);
+        if let Some(lock_order) = &this.machine.lock_order {
+            lock_order.borrow_mut().release(this.active_thread(), lock);
+        }
+    }
+
+    /// Records the releases of the standard library's `Mutex` when calling the function that
+    /// implements them, and the start of its acquisitions, which end in `lock_order_std_return`.
+    fn lock_order_std_call(
+        &self,
+        instance: ty::Instance<'tcx>,
+        args: &[FnArg<'tcx>],
+    ) -> InterpResult<'tcx> {
+        use rustc_span::sym;
+        let this = self.eval_context_ref();
+        let def_id = instance.def_id();
+        let acquire = if this.tcx.is_diagnostic_item(sym::sys_mutex_lock, def_id) {
+            true
+        } else if this.tcx.is_diagnostic_item(sym::sys_mutex_unlock, def_id) {
+            false
+        } else {
+            return interp_ok(());
+        };
+        let args = this.copy_fn_args(args);
+        let [mutex] = &*args else {
+            panic!("{instance} is a diagnostic item expected to have 1 argument");
+        };
+        let mutex = this.deref_pointer(mutex)?;
+        let (alloc_id, offset, _prov) = this.ptr_get_alloc_id(mutex.ptr(), 0)?;
+        let lock = LockId::Std(alloc_id, offset);
+        if acquire {
+            let acquisition = StdMutexAcquisition {
+                lock,
+                depth: this.active_thread_stack().len(),
+                clock: this.lock_order_clock(),
+            };
+            this.machine.std_mutex_acquiring.borrow_mut().insert(this.active_thread(), acquisition);
+        } else {
+            this.lock_released(lock);
+        }
+        interp_ok(())
+    }
+
+    /// Records that the active thread returned from a stack frame. If that frame was acquiring a
+    /// `Mutex` of the standard library, the mutex is now held, unless the frame is unwinding.
+    fn lock_order_std_return(&self, unwinding: bool) {
+        let this = self.eval_context_ref();
+        let thread = this.active_thread();
+        let mut acquiring = this.machine.std_mutex_acquiring.borrow_mut();
+        if !acquiring.get(&thread).is_some_and(|a| a.depth == this.active_thread_stack().len()) {
+            return;
+        }
+        let acquisition = acquiring.remove(&thread).unwrap();
+        drop(acquiring);
+        if !unwinding {
+            // The clock from before the thread started acquiring the mutex, so that waiting for
+            // the mutex does not hide an inversion.
+            this.record_lock_acquired(acquisition.lock, false, acquisition.clock);
+        }
+    }
+}
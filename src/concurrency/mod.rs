@@ -1,9 +1,17 @@
 pub mod cpu_affinity;
 pub mod data_race;
 mod data_race_handler;
+mod deadlock_report;
+pub mod dpor;
 pub mod init_once;
+pub mod livelock;
+pub mod lock_order;
+pub mod pct;
+pub mod race_backtraces;
+pub mod schedule_trace;
 pub mod sync;
 pub mod thread;
+pub mod timeline;
 mod vector_clock;
 pub mod weak_memory;
 
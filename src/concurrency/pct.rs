@@ -0,0 +1,106 @@
+//! The PCT ("probabilistic concurrency testing") scheduling strategy, from "A Randomized Scheduler
+//! with Probabilistic Guarantees of Finding Bugs" by Burckhardt et al.
+//!
+//! Every thread gets a random priority when it is created, and the scheduler always runs the
+//! enabled thread with the highest priority. At `depth - 1` randomly chosen steps of the execution
+//! (the "change points"), the priority of the running thread drops below that of all other threads.
+//! If a run takes at most `steps` steps with at most `n` threads, then a bug that needs `depth`
+//! specific ordering constraints is found with probability at least `1 / (n * steps^(depth-1))`.
+//!
+//! A step is a basic block of any thread. Since a thread that spins while waiting for a
+//! lower-priority thread would never let it run, explicit yields (such as `sched_yield` or spin
+//! loop hints) move the yielding thread below all other threads.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rustc_index::IndexVec;
+
+use crate::*;
+
+/// The configuration of `-Zmiri-pct`.
+#[derive(Debug, Clone, Copy)]
+pub struct PctConfig {
+    /// The number of ordering constraints the scheduler aims to cover.
+    pub depth: u32,
+    /// The estimated number of steps of an execution.
+    pub steps: u64,
+}
+
+/// The priorities of new threads. Change points set priorities below this range, starting at
+/// `CHANGE_POINT_PRIORITIES`, so there is room below them for yielding threads.
+const INITIAL_PRIORITIES: std::ops::Range<u64> = (1 << 32)..(1 << 33);
+const CHANGE_POINT_PRIORITIES: u64 = 1 << 31;
+
+#[derive(Debug)]
+pub struct PctScheduler {
+    /// The random number generator for the priorities. It is separate from the machine's, so that
+    /// priorities only depend on the seed and the order in which threads are created.
+    rng: StdRng,
+    /// The priority of each thread. Higher priorities run first.
+    priorities: IndexVec<ThreadId, u64>,
+    /// The remaining change points, as the step at which they happen and the priority the running
+    /// thread gets, sorted so that the next one comes last.
+    change_points: Vec<(u64, u64)>,
+    /// The number of steps taken so far.
+    steps: u64,
+}
+
+impl PctScheduler {
+    pub fn new(config: PctConfig, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut change_points: Vec<(u64, u64)> = (1..u64::from(config.depth))
+            .map(|i| (rng.random_range(1..=config.steps), CHANGE_POINT_PRIORITIES.strict_add(i)))
+            .collect();
+        change_points.sort_unstable_by(|a, b| b.cmp(a));
+        let mut pct = PctScheduler { rng, priorities: IndexVec::new(), change_points, steps: 0 };
+        // The main thread.
+        pct.thread_created();
+        pct
+    }
+
+    /// Gives the thread that was just created a random priority.
+    pub fn thread_created(&mut self) {
+        let priority = self.rng.random_range(INITIAL_PRIORITIES);
+        self.priorities.push(priority);
+    }
+
+    /// Whether thread `a` should run rather than thread `b`. Ties, which are very unlikely, go to
+    /// the older thread.
+    pub fn prefers(&self, a: ThreadId, b: ThreadId) -> bool {
+        (self.priorities[a], b) > (self.priorities[b], a)
+    }
+
+    /// Picks the thread to run among `enabled`.
+    pub fn choose(&self, enabled: impl Iterator<Item = ThreadId>) -> Option<ThreadId> {
+        enabled.reduce(|best, id| if self.prefers(id, best) { id } else { best })
+    }
+
+    /// Called once per step while `active` is running. Returns whether this step is a change
+    /// point, in which case `active` got its new priority and should be preempted.
+    pub fn step(&mut self, active: ThreadId) -> bool {
+        self.steps = self.steps.saturating_add(1);
+        match self.change_points.last() {
+            Some(&(step, priority)) if step <= self.steps => {
+                self.change_points.pop();
+                self.priorities[active] = priority;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Called when `active` explicitly yields: moves it below all other threads.
+    pub fn yielded(&mut self, active: ThreadId) {
+        let lowest = self
+            .priorities
+            .iter_enumerated()
+            .filter(|&(id, _)| id != active)
+            .map(|(_, &priority)| priority)
+            .min();
+        if let Some(lowest) = lowest
+            && lowest <= self.priorities[active]
+        {
+            self.priorities[active] = lowest.saturating_sub(1);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Call stacks of memory accesses, so that data race reports can show a full backtrace for the
+//! earlier of the two racing accesses (`-Zmiri-race-backtraces`).
+//!
+//! The data race detector only remembers the span of each access, as part of its vector clock
+//! timestamp. In this mode, every access made while race detection is active also interns the call
+//! stack of the accessing thread, and records it for the accessed location together with the
+//! vector index and timestamp that the race detector stores for that access. When a race is found,
+//! the timestamp of the earlier access identifies the matching stack.
+//!
+//! Stacks are interned as a tree of frames, so stacks that share a prefix share its nodes, and each
+//! access only stores the id of its top frame. Per location, the last non-atomic write is kept, and
+//! of the accesses since then at most one per thread and kind of access, and at most
+//! `MAX_ACCESSES` in total. The tree is limited to `MAX_NODES` frames; once it is full, accesses
+//! with new stacks are not recorded, and their races are reported with a span only. Interning the
+//! stack on every access makes execution slower in proportion to the depth of the call stack.
+
+use std::num::NonZeroU32;
+
+use rustc_data_structures::fx::FxIndexSet;
+use rustc_middle::ty;
+use rustc_span::Span;
+use smallvec::SmallVec;
+
+use super::vector_clock::{VTimestamp, VectorIdx};
+use crate::*;
+
+/// The maximal number of interned frames.
+const MAX_NODES: usize = 1 << 20;
+
+/// The maximal number of accesses recorded per location.
+const MAX_ACCESSES: usize = 8;
+
+/// Identifies an interned call stack by its top frame.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct StackId(NonZeroU32);
+
+impl StackId {
+    fn new(index: usize) -> Self {
+        StackId(NonZeroU32::new(u32::try_from(index).unwrap().strict_add(1)).unwrap())
+    }
+
+    fn index(self) -> usize {
+        usize::try_from(self.0.get().strict_sub(1)).unwrap()
+    }
+}
+
+/// The interned call stacks. Each node is a frame, identified by its function and the span it is
+/// executing, together with the stack of its caller.
+#[derive(Debug, Default)]
+pub struct StackInterner<'tcx> {
+    nodes: FxIndexSet<(Option<StackId>, ty::Instance<'tcx>, Span)>,
+}
+
+impl<'tcx> StackInterner<'tcx> {
+    /// Interns `stack`, given from the bottom frame up. Returns `None` if the stack is empty or
+    /// there is no room for its frames.
+    fn intern(&mut self, stack: &[Frame<'tcx, Provenance, FrameExtra<'tcx>>]) -> Option<StackId> {
+        let mut id = None;
+        for frame in stack {
+            let node = (id, frame.instance(), frame.current_span());
+            let index = match self.nodes.get_index_of(&node) {
+                Some(index) => index,
+                None => {
+                    if self.nodes.len() >= MAX_NODES {
+                        return None;
+                    }
+                    self.nodes.insert_full(node).0
+                }
+            };
+            id = Some(StackId::new(index));
+        }
+        id
+    }
+
+    /// The frames of the stack `id`, from the top frame down.
+    pub fn frames(&self, id: StackId) -> Vec<FrameInfo<'tcx>> {
+        let mut frames = Vec::new();
+        let mut next = Some(id);
+        while let Some(id) = next {
+            let &(parent, instance, span) = self.nodes.get_index(id.index()).unwrap();
+            frames.push(FrameInfo { instance, span });
+            next = parent;
+        }
+        frames
+    }
+}
+
+/// An access whose call stack was recorded.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct RecordedAccess {
+    index: VectorIdx,
+    timestamp: VTimestamp,
+    write: bool,
+    atomic: bool,
+    stack: StackId,
+}
+
+/// The recorded accesses of a location.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub(super) struct AccessStacks {
+    accesses: SmallVec<[RecordedAccess; 2]>,
+}
+
+impl AccessStacks {
+    /// Records an access by the thread with vector index `index` at `timestamp`.
+    pub(super) fn record(
+        &mut self,
+        index: VectorIdx,
+        timestamp: VTimestamp,
+        write: bool,
+        atomic: bool,
+        stack: StackId,
+    ) {
+        if write && !atomic {
+            // Like the race detector, forget everything before a non-atomic write.
+            self.accesses.clear();
+        } else {
+            self.accesses.retain(|a| !(a.index == index && a.write == write && a.atomic == atomic));
+            if self.accesses.len() >= MAX_ACCESSES {
+                self.accesses.remove(0);
+            }
+        }
+        self.accesses.push(RecordedAccess { index, timestamp, write, atomic, stack });
+    }
+
+    /// The stack of the access by the thread with vector index `index` at `timestamp`, if it was
+    /// recorded.
+    pub(super) fn find(
+        &self,
+        index: VectorIdx,
+        timestamp: VTimestamp,
+        write: bool,
+        atomic: bool,
+    ) -> Option<StackId> {
+        self.accesses
+            .iter()
+            .rev()
+            .find(|a| {
+                a.index == index
+                    && a.timestamp == timestamp
+                    && a.write == write
+                    && a.atomic == atomic
+            })
+            .map(|a| a.stack)
+    }
+}
+
+impl<'tcx> MiriMachine<'tcx> {
+    /// Interns the call stack of the active thread, if `-Zmiri-race-backtraces` is set.
+    pub(super) fn intern_active_stack(&self) -> Option<StackId> {
+        let interner = self.race_stacks.as_ref()?;
+        interner.borrow_mut().intern(self.threads.active_thread_stack())
+    }
+}
@@ -0,0 +1,261 @@
+//! Recording and replaying the nondeterministic choices that decide how threads interleave.
+//!
+//! With `-Zmiri-record-schedule`, Miri writes every such choice to a file: which thread the
+//! scheduler switches to, where the active thread gets preempted, which store a weak memory load
+//! reads from, and whether a `compare_exchange_weak` fails spuriously. With
+//! `-Zmiri-replay-schedule`, Miri makes the recorded choices again, no matter what the random
+//! number generator says. If the program changed and a recorded choice no longer fits, Miri reports
+//! the first divergence and makes its own choices from then on.
+//!
+//! The choices are still drawn from the random number generator in both modes, so recording does
+//! not change the behavior of a seed.
+
+use std::cell::{Cell, RefCell};
+use std::fmt::{self, Write as _};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::{fs, mem};
+
+use crate::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleDecision {
+    /// The scheduler switched to this thread.
+    Thread(ThreadId),
+    /// The active thread was preempted at this preemption point. Preemption points are counted from
+    /// the start of the execution, and those where no preemption happened are not recorded.
+    Preempt(u64),
+    /// A weak memory load read from the store with this index among the stores it could read from,
+    /// counting from the latest one.
+    Load(usize),
+    /// Whether a `compare_exchange_weak` that could have succeeded did succeed.
+    CompareExchangeWeak(bool),
+}
+
+impl ScheduleDecision {
+    fn kind(self) -> &'static str {
+        match self {
+            ScheduleDecision::Thread(_) => "a thread switch",
+            ScheduleDecision::Preempt(_) => "a preemption",
+            ScheduleDecision::Load(_) => "a weak memory load",
+            ScheduleDecision::CompareExchangeWeak(_) => "a `compare_exchange_weak`",
+        }
+    }
+}
+
+impl fmt::Display for ScheduleDecision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleDecision::Thread(id) => write!(f, "thread {}", id.to_u32()),
+            ScheduleDecision::Preempt(point) => write!(f, "preempt {point}"),
+            ScheduleDecision::Load(index) => write!(f, "load {index}"),
+            ScheduleDecision::CompareExchangeWeak(success) =>
+                write!(f, "cmpxchg-weak {}", if *success { "success" } else { "failure" }),
+        }
+    }
+}
+
+impl FromStr for ScheduleDecision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let Some((kind, arg)) = s.split_once(' ') else {
+            return Err(format!("expected `<kind> <argument>`, found `{s}`"));
+        };
+        let parse_err = |err: &dyn fmt::Display| format!("invalid argument `{arg}`: {err}");
+        match kind {
+            "thread" =>
+                arg.parse()
+                    .map(|id| Self::Thread(ThreadId::new_unchecked(id)))
+                    .map_err(|e| parse_err(&e)),
+            "preempt" => arg.parse().map(Self::Preempt).map_err(|e| parse_err(&e)),
+            "load" => arg.parse().map(Self::Load).map_err(|e| parse_err(&e)),
+            "cmpxchg-weak" =>
+                match arg {
+                    "success" => Ok(Self::CompareExchangeWeak(true)),
+                    "failure" => Ok(Self::CompareExchangeWeak(false)),
+                    _ => Err(parse_err(&"expected `success` or `failure`")),
+                },
+            _ => Err(format!("unknown decision `{kind}`")),
+        }
+    }
+}
+
+/// Parses the contents of a schedule trace file. Empty lines and lines starting with `#` are
+/// ignored.
+pub fn parse_schedule_trace(text: &str) -> Result<Vec<ScheduleDecision>, String> {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| (i, line.trim()))
+        .filter(|(_i, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(i, line)| line.parse().map_err(|err| format!("line {}: {err}", i.strict_add(1))))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub enum ScheduleTraceConfig {
+    /// Record the decisions to this file.
+    Record(PathBuf),
+    /// Replay these decisions.
+    Replay(Vec<ScheduleDecision>),
+}
+
+pub struct ScheduleTrace {
+    /// The file to write the recorded decisions to, or `None` when replaying.
+    record_to: Option<PathBuf>,
+    /// The decisions recorded so far, or the decisions to replay.
+    decisions: RefCell<Vec<ScheduleDecision>>,
+    /// When replaying, the index of the next decision to replay. This is `None` when recording
+    /// and after the replay diverged.
+    next: Cell<Option<usize>>,
+    /// The number of preemption points passed so far.
+    preemption_points: Cell<u64>,
+    /// A divergence from the replayed trace that has not been reported yet.
+    divergence: RefCell<Option<NonHaltingDiagnostic>>,
+}
+
+impl ScheduleTrace {
+    pub fn new(config: &ScheduleTraceConfig) -> Self {
+        let (record_to, decisions, next) = match config {
+            ScheduleTraceConfig::Record(path) => (Some(path.clone()), Vec::new(), None),
+            ScheduleTraceConfig::Replay(decisions) => (None, decisions.clone(), Some(0)),
+        };
+        ScheduleTrace {
+            record_to,
+            decisions: RefCell::new(decisions),
+            next: Cell::new(next),
+            preemption_points: Cell::new(0),
+            divergence: RefCell::new(None),
+        }
+    }
+
+    /// Stops replaying, and remembers the divergence so the caller can report it.
+    fn diverge(&self, index: usize, reason: String) {
+        self.next.set(None);
+        *self.divergence.borrow_mut() =
+            Some(NonHaltingDiagnostic::ScheduleReplayDiverged { index, reason });
+    }
+
+    /// Takes the decision `made` by the random number generator. When recording, this records it.
+    /// When replaying, this returns the recorded decision instead, as long as it is of the same kind
+    /// and `valid`.
+    fn decide(
+        &self,
+        made: ScheduleDecision,
+        valid: impl Fn(ScheduleDecision) -> bool,
+    ) -> ScheduleDecision {
+        let Some(next) = self.next.get() else {
+            if self.record_to.is_some() {
+                self.decisions.borrow_mut().push(made);
+            }
+            return made;
+        };
+        let recorded = self.decisions.borrow().get(next).copied();
+        match recorded {
+            Some(recorded)
+                if mem::discriminant(&recorded) == mem::discriminant(&made) && valid(recorded) =>
+            {
+                self.next.set(Some(next.strict_add(1)));
+                recorded
+            }
+            Some(recorded) if mem::discriminant(&recorded) == mem::discriminant(&made) => {
+                self.diverge(
+                    next,
+                    format!("the trace says `{recorded}`, which is not possible here"),
+                );
+                made
+            }
+            Some(recorded) => {
+                self.diverge(
+                    next,
+                    format!("the trace says `{recorded}`, but the program reached {}", made.kind()),
+                );
+                made
+            }
+            None => {
+                self.diverge(
+                    next,
+                    format!("the trace ended, but the program reached {}", made.kind()),
+                );
+                made
+            }
+        }
+    }
+
+    /// The scheduler picked `made` among the enabled threads.
+    pub fn choose_thread(&self, made: ThreadId, enabled: impl Fn(ThreadId) -> bool) -> ThreadId {
+        let decision = self.decide(ScheduleDecision::Thread(made), |recorded| {
+            let ScheduleDecision::Thread(id) = recorded else { unreachable!() };
+            enabled(id)
+        });
+        let ScheduleDecision::Thread(id) = decision else { unreachable!() };
+        id
+    }
+
+    /// A preemption point was reached, and the active thread is preempted if `made` is true.
+    pub fn preempt(&self, made: bool) -> bool {
+        let point = self.preemption_points.get();
+        self.preemption_points.set(point.strict_add(1));
+        let Some(next) = self.next.get() else {
+            if made && self.record_to.is_some() {
+                self.decisions.borrow_mut().push(ScheduleDecision::Preempt(point));
+            }
+            return made;
+        };
+        // Only the preemptions are recorded, so this can only diverge at the next decision of
+        // another kind.
+        if self.decisions.borrow().get(next) == Some(&ScheduleDecision::Preempt(point)) {
+            self.next.set(Some(next.strict_add(1)));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// A weak memory load picked the store with index `made` among `candidates` stores.
+    pub fn choose_load(&self, made: usize, candidates: usize) -> usize {
+        let decision = self.decide(ScheduleDecision::Load(made), |recorded| {
+            let ScheduleDecision::Load(index) = recorded else { unreachable!() };
+            index < candidates
+        });
+        let ScheduleDecision::Load(index) = decision else { unreachable!() };
+        index
+    }
+
+    /// A `compare_exchange_weak` that could succeed succeeds if `made` is true.
+    pub fn compare_exchange_weak(&self, made: bool) -> bool {
+        let decision = self.decide(ScheduleDecision::CompareExchangeWeak(made), |_| true);
+        let ScheduleDecision::CompareExchangeWeak(success) = decision else { unreachable!() };
+        success
+    }
+
+    /// Returns the divergence from the replayed trace that was just found, if any.
+    pub fn take_divergence(&self) -> Option<NonHaltingDiagnostic> {
+        self.divergence.borrow_mut().take()
+    }
+
+    /// When recording, writes the trace file.
+    pub fn write(&self) -> Result<(), String> {
+        let Some(path) = &self.record_to else {
+            return Ok(());
+        };
+        let mut text =
+            String::from("# Miri schedule trace, replay with `-Zmiri-replay-schedule`\n");
+        for decision in self.decisions.borrow().iter() {
+            writeln!(text, "{decision}").unwrap();
+        }
+        fs::write(path, text)
+            .map_err(|err| format!("failed to write schedule trace to `{}`: {err}", path.display()))
+    }
+}
+
+impl<'tcx> MiriMachine<'tcx> {
+    /// Reports a divergence from the replayed schedule trace at the current location.
+    pub(crate) fn report_schedule_divergence(&self) {
+        if let Some(trace) = &self.schedule_trace
+            && let Some(divergence) = trace.take_divergence()
+        {
+            self.emit_diagnostic(divergence);
+        }
+    }
+}
@@ -10,7 +10,9 @@ use std::{fmt, iter};
 
 use rustc_abi::Size;
 use rustc_data_structures::fx::FxHashMap;
+use rustc_span::Span;
 
+use super::lock_order::{LockId, SyncLockId};
 use super::vector_clock::VClock;
 use crate::*;
 
@@ -71,6 +73,10 @@ struct Mutex {
     queue: VecDeque<ThreadId>,
     /// Mutex clock. This tracks the moment of the last unlock.
     clock: VClock,
+    /// Where the owner acquired the mutex.
+    acquired_at: Option<Span>,
+    /// Identifies the mutex in the lock-order graph.
+    id: Option<SyncLockId>,
 }
 
 #[derive(Default, Clone, Debug)]
@@ -89,6 +95,16 @@ impl MutexRef {
     pub fn queue_is_empty(&self) -> bool {
         self.0.borrow().queue.is_empty()
     }
+
+    /// The thread that holds this lock, if any, and where it acquired it.
+    fn holders(&self) -> Vec<LockHolder> {
+        let mutex = self.0.borrow();
+        mutex
+            .owner
+            .map(|thread| LockHolder { thread, acquired_at: mutex.acquired_at, shared: false })
+            .into_iter()
+            .collect()
+    }
 }
 
 impl VisitProvenance for MutexRef {
@@ -108,6 +124,9 @@ struct RwLock {
     writer_queue: VecDeque<ThreadId>,
     /// The queue of reader threads waiting for this lock.
     reader_queue: VecDeque<ThreadId>,
+    /// Where the writer, and each of the readers, acquired the lock.
+    writer_acquired_at: Option<Span>,
+    readers_acquired_at: FxHashMap<ThreadId, Span>,
     /// Data race clock for writers. Tracks the happens-before
     /// ordering between each write access to a rwlock and is updated
     /// after a sequence of concurrent readers to track the happens-
@@ -128,6 +147,8 @@ struct RwLock {
     /// locks.
     /// This is only relevant when there is an active reader.
     clock_current_readers: VClock,
+    /// Identifies the lock in the lock-order graph.
+    id: Option<SyncLockId>,
 }
 
 impl RwLock {
@@ -166,10 +187,38 @@ impl RwLockRef {
         self.0.borrow().is_write_locked()
     }
 
+    /// Get the id of the thread that currently holds this lock in write mode, if any.
+    pub fn writer(&self) -> Option<ThreadId> {
+        self.0.borrow().writer
+    }
+
+    /// Check whether `thread` currently holds this lock in read mode.
+    pub fn is_read_locked_by(&self, thread: ThreadId) -> bool {
+        self.0.borrow().readers.contains_key(&thread)
+    }
+
     pub fn queue_is_empty(&self) -> bool {
         let inner = self.0.borrow();
         inner.reader_queue.is_empty() && inner.writer_queue.is_empty()
     }
+
+    /// The threads that hold this lock, and where they acquired it.
+    fn holders(&self) -> Vec<LockHolder> {
+        let rwlock = self.0.borrow();
+        let writer = rwlock.writer.map(|thread| {
+            LockHolder { thread, acquired_at: rwlock.writer_acquired_at, shared: false }
+        });
+        let mut readers: Vec<LockHolder> = rwlock
+            .readers
+            .keys()
+            .map(|&thread| {
+                let acquired_at = rwlock.readers_acquired_at.get(&thread).copied();
+                LockHolder { thread, acquired_at, shared: true }
+            })
+            .collect();
+        readers.sort_by_key(|holder| holder.thread);
+        writer.into_iter().chain(readers).collect()
+    }
 }
 
 impl VisitProvenance for RwLockRef {
@@ -177,6 +226,41 @@ impl VisitProvenance for RwLockRef {
     fn visit_provenance(&self, _visit: &mut VisitWith<'_>) {}
 }
 
+/// The lock that a blocked thread waits for.
+#[derive(Clone, Debug)]
+pub enum WaitObject {
+    Mutex(MutexRef),
+    RwLock(RwLockRef),
+}
+
+/// A thread that holds a lock.
+#[derive(Clone, Copy, Debug)]
+pub struct LockHolder {
+    pub thread: ThreadId,
+    /// Where the thread acquired the lock, if known.
+    pub acquired_at: Option<Span>,
+    /// Whether the thread holds the lock in read mode.
+    pub shared: bool,
+}
+
+impl WaitObject {
+    /// A name for the kind of lock, for diagnostics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WaitObject::Mutex(_) => "mutex",
+            WaitObject::RwLock(_) => "read-write lock",
+        }
+    }
+
+    /// The threads that hold the lock, and where they acquired it.
+    pub fn holders(&self) -> Vec<LockHolder> {
+        match self {
+            WaitObject::Mutex(mutex_ref) => mutex_ref.holders(),
+            WaitObject::RwLock(rwlock_ref) => rwlock_ref.holders(),
+        }
+    }
+}
+
 /// The conditional variable state.
 #[derive(Default, Debug)]
 struct Condvar {
@@ -267,6 +351,31 @@ pub(super) trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
         }
         interp_ok(())
     }
+
+    fn condvar_reacquire_rwlock(
+        &mut self,
+        rwlock_ref: RwLockRef,
+        shared: bool,
+        retval: Scalar,
+        dest: MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        if shared {
+            if rwlock_ref.is_write_locked() {
+                this.rwlock_enqueue_and_block_reader(rwlock_ref, Some((retval, dest)));
+                return interp_ok(());
+            }
+            this.rwlock_reader_lock(&rwlock_ref)?;
+        } else {
+            if rwlock_ref.is_locked() {
+                this.rwlock_enqueue_and_block_writer(rwlock_ref, Some((retval, dest)));
+                return interp_ok(());
+            }
+            this.rwlock_writer_lock(&rwlock_ref)?;
+        }
+        this.write_scalar(retval, &dest)?;
+        interp_ok(())
+    }
 }
 
 impl<'tcx> AllocExtra<'tcx> {
@@ -408,10 +517,22 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         interp_ok(Some(alloc_extra.get_sync::<T>(offset).unwrap()))
     }
 
+    /// Called when the active thread found `mutex_ref` locked and gave up on acquiring it, so
+    /// that the DPOR explorer also tries the order in which the lock was still free.
+    fn mutex_lock_failed(&self, mutex_ref: &MutexRef) {
+        self.eval_context_ref().dpor_sync_access(&mutex_ref.0);
+    }
+
+    /// Like `mutex_lock_failed`, for a read-write lock.
+    fn rwlock_lock_failed(&self, rwlock_ref: &RwLockRef) {
+        self.eval_context_ref().dpor_sync_access(&rwlock_ref.0);
+    }
+
     /// Lock by setting the mutex owner and increasing the lock count.
     fn mutex_lock(&mut self, mutex_ref: &MutexRef) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
         let thread = this.active_thread();
+        this.dpor_sync_access(&mutex_ref.0);
         let mut mutex = mutex_ref.0.borrow_mut();
         if let Some(current_owner) = mutex.owner {
             assert_eq!(thread, current_owner, "mutex already locked by another thread");
@@ -421,6 +542,9 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             );
         } else {
             mutex.owner = Some(thread);
+            mutex.acquired_at = Some(this.machine.current_user_relevant_span());
+            let id = this.sync_lock_id(&mut mutex.id);
+            this.lock_acquired(LockId::Sync(id), /* shared */ false);
         }
         mutex.lock_count = mutex.lock_count.strict_add(1);
         this.acquire_clock(&mutex.clock)?;
@@ -440,10 +564,14 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 // Only the owner can unlock the mutex.
                 return interp_ok(None);
             }
+            this.dpor_sync_access(&mutex_ref.0);
             let old_lock_count = mutex.lock_count;
             mutex.lock_count = old_lock_count.strict_sub(1);
             if mutex.lock_count == 0 {
                 mutex.owner = None;
+                mutex.acquired_at = None;
+                let id = this.sync_lock_id(&mut mutex.id);
+                this.lock_released(LockId::Sync(id));
                 // The mutex is completely unlocked. Try transferring ownership
                 // to another thread.
 
@@ -475,10 +603,12 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     ) {
         let this = self.eval_context_mut();
         let thread = this.active_thread();
+        this.dpor_sync_access(&mutex_ref.0);
         let mut mutex = mutex_ref.0.borrow_mut();
         mutex.queue.push_back(thread);
         assert!(mutex.owner.is_some(), "queuing on unlocked mutex");
         drop(mutex);
+        let wait_object = WaitObject::Mutex(mutex_ref.clone());
         this.block_thread(
             BlockReason::Mutex,
             None,
@@ -501,6 +631,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 }
             ),
         );
+        this.machine.threads.set_wait_object(wait_object);
     }
 
     /// Read-lock the lock by adding the `reader` the list of threads that own
@@ -509,10 +640,17 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let this = self.eval_context_mut();
         let thread = this.active_thread();
         trace!("rwlock_reader_lock: now also held (one more time) by {:?}", thread);
+        this.dpor_sync_access(&rwlock_ref.0);
         let mut rwlock = rwlock_ref.0.borrow_mut();
         assert!(!rwlock.is_write_locked(), "the lock is write locked");
         let count = rwlock.readers.entry(thread).or_insert(0);
+        let first = *count == 0;
         *count = count.strict_add(1);
+        if first {
+            rwlock.readers_acquired_at.insert(thread, this.machine.current_user_relevant_span());
+            let id = this.sync_lock_id(&mut rwlock.id);
+            this.lock_acquired(LockId::Sync(id), /* shared */ true);
+        }
         this.acquire_clock(&rwlock.clock_unlocked)?;
         interp_ok(())
     }
@@ -523,6 +661,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let this = self.eval_context_mut();
         let thread = this.active_thread();
         let mut rwlock = rwlock_ref.0.borrow_mut();
+        let id = this.sync_lock_id(&mut rwlock.id);
         match rwlock.readers.entry(thread) {
             Entry::Occupied(mut entry) => {
                 let count = entry.get_mut();
@@ -531,12 +670,15 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 if *count == 0 {
                     trace!("rwlock_reader_unlock: no longer held by {:?}", thread);
                     entry.remove();
+                    rwlock.readers_acquired_at.remove(&thread);
+                    this.lock_released(LockId::Sync(id));
                 } else {
                     trace!("rwlock_reader_unlock: held one less time by {:?}", thread);
                 }
             }
             Entry::Vacant(_) => return interp_ok(false), // we did not even own this lock
         }
+        this.dpor_sync_access(&rwlock_ref.0);
         // Add this to the shared-release clock of all concurrent readers.
         this.release_clock(|clock| rwlock.clock_current_readers.join(clock))?;
 
@@ -557,37 +699,41 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     }
 
     /// Put the reader in the queue waiting for the lock and block it.
-    /// Once the lock becomes available, `retval` will be written to `dest`.
+    /// Once the lock becomes available and if it exists, `retval_dest.0` will be written to
+    /// `retval_dest.1`.
     #[inline]
     fn rwlock_enqueue_and_block_reader(
         &mut self,
         rwlock_ref: RwLockRef,
-        retval: Scalar,
-        dest: MPlaceTy<'tcx>,
+        retval_dest: Option<(Scalar, MPlaceTy<'tcx>)>,
     ) {
         let this = self.eval_context_mut();
         let thread = this.active_thread();
+        this.dpor_sync_access(&rwlock_ref.0);
         let mut rwlock = rwlock_ref.0.borrow_mut();
         rwlock.reader_queue.push_back(thread);
         assert!(rwlock.is_write_locked(), "read-queueing on not write locked rwlock");
         drop(rwlock);
+        let wait_object = WaitObject::RwLock(rwlock_ref.clone());
         this.block_thread(
             BlockReason::RwLock,
             None,
             callback!(
                 @capture<'tcx> {
                     rwlock_ref: RwLockRef,
-                    retval: Scalar,
-                    dest: MPlaceTy<'tcx>,
+                    retval_dest: Option<(Scalar, MPlaceTy<'tcx>)>,
                 }
                 |this, unblock: UnblockKind| {
                     assert_eq!(unblock, UnblockKind::Ready);
                     this.rwlock_reader_lock(&rwlock_ref)?;
-                    this.write_scalar(retval, &dest)?;
+                    if let Some((retval, dest)) = retval_dest {
+                        this.write_scalar(retval, &dest)?;
+                    }
                     interp_ok(())
                 }
             ),
         );
+        this.machine.threads.set_wait_object(wait_object);
     }
 
     /// Lock by setting the writer that owns the lock.
@@ -596,10 +742,14 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let this = self.eval_context_mut();
         let thread = this.active_thread();
         trace!("rwlock_writer_lock: now held by {:?}", thread);
+        this.dpor_sync_access(&rwlock_ref.0);
 
         let mut rwlock = rwlock_ref.0.borrow_mut();
         assert!(!rwlock.is_locked(), "the rwlock is already locked");
         rwlock.writer = Some(thread);
+        rwlock.writer_acquired_at = Some(this.machine.current_user_relevant_span());
+        let id = this.sync_lock_id(&mut rwlock.id);
+        this.lock_acquired(LockId::Sync(id), /* shared */ false);
         this.acquire_clock(&rwlock.clock_unlocked)?;
         interp_ok(())
     }
@@ -616,8 +766,12 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 // Only the owner can unlock the rwlock.
                 return interp_ok(false);
             }
+            this.dpor_sync_access(&rwlock_ref.0);
             rwlock.writer = None;
+            rwlock.writer_acquired_at = None;
             trace!("rwlock_writer_unlock: unlocked by {:?}", thread);
+            let id = this.sync_lock_id(&mut rwlock.id);
+            this.lock_released(LockId::Sync(id));
             // Record release clock for next lock holder.
             this.release_clock(|clock| rwlock.clock_unlocked.clone_from(clock))?;
 
@@ -644,37 +798,41 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     }
 
     /// Put the writer in the queue waiting for the lock.
-    /// Once the lock becomes available, `retval` will be written to `dest`.
+    /// Once the lock becomes available and if it exists, `retval_dest.0` will be written to
+    /// `retval_dest.1`.
     #[inline]
     fn rwlock_enqueue_and_block_writer(
         &mut self,
         rwlock_ref: RwLockRef,
-        retval: Scalar,
-        dest: MPlaceTy<'tcx>,
+        retval_dest: Option<(Scalar, MPlaceTy<'tcx>)>,
     ) {
         let this = self.eval_context_mut();
         let thread = this.active_thread();
+        this.dpor_sync_access(&rwlock_ref.0);
         let mut rwlock = rwlock_ref.0.borrow_mut();
         rwlock.writer_queue.push_back(thread);
         assert!(rwlock.is_locked(), "write-queueing on unlocked rwlock");
         drop(rwlock);
+        let wait_object = WaitObject::RwLock(rwlock_ref.clone());
         this.block_thread(
             BlockReason::RwLock,
             None,
             callback!(
                 @capture<'tcx> {
                     rwlock_ref: RwLockRef,
-                    retval: Scalar,
-                    dest: MPlaceTy<'tcx>,
+                    retval_dest: Option<(Scalar, MPlaceTy<'tcx>)>,
                 }
                 |this, unblock: UnblockKind| {
                     assert_eq!(unblock, UnblockKind::Ready);
                     this.rwlock_writer_lock(&rwlock_ref)?;
-                    this.write_scalar(retval, &dest)?;
+                    if let Some((retval, dest)) = retval_dest {
+                        this.write_scalar(retval, &dest)?;
+                    }
                     interp_ok(())
                 }
             ),
         );
+        this.machine.threads.set_wait_object(wait_object);
     }
 
     /// Release the mutex and let the current thread wait on the given condition variable.
@@ -703,6 +861,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         }
         let thread = this.active_thread();
 
+        this.dpor_sync_access(&condvar_ref.0);
         condvar_ref.0.borrow_mut().waiters.push_back(thread);
         this.block_thread(
             BlockReason::Condvar,
@@ -741,10 +900,79 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         interp_ok(())
     }
 
+    /// Like `condvar_wait`, but the condition variable is protected by a reader-writer lock
+    /// instead of a mutex. The lock must be held by the current thread in the given mode (read mode
+    /// if `shared` is set, write mode otherwise), and it will be reacquired in that same mode.
+    fn condvar_wait_rwlock(
+        &mut self,
+        condvar_ref: CondvarRef,
+        rwlock_ref: RwLockRef,
+        shared: bool,
+        timeout: Option<(TimeoutClock, TimeoutAnchor, Duration)>,
+        retval_succ: Scalar,
+        retval_timeout: Scalar,
+        dest: MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let released = if shared {
+            this.rwlock_reader_unlock(&rwlock_ref)?
+        } else {
+            this.rwlock_writer_unlock(&rwlock_ref)?
+        };
+        if !released {
+            throw_ub_format!(
+                "awaiting a condvar on a lock that is not held in {} mode by the current thread",
+                if shared { "shared" } else { "exclusive" },
+            );
+        }
+        if shared && rwlock_ref.is_read_locked_by(this.active_thread()) {
+            throw_unsup_format!(
+                "awaiting a condvar on a lock acquired multiple times is not supported"
+            );
+        }
+        let thread = this.active_thread();
+
+        this.dpor_sync_access(&condvar_ref.0);
+        condvar_ref.0.borrow_mut().waiters.push_back(thread);
+        this.block_thread(
+            BlockReason::Condvar,
+            timeout,
+            callback!(
+                @capture<'tcx> {
+                    condvar_ref: CondvarRef,
+                    rwlock_ref: RwLockRef,
+                    shared: bool,
+                    retval_succ: Scalar,
+                    retval_timeout: Scalar,
+                    dest: MPlaceTy<'tcx>,
+                }
+                |this, unblock: UnblockKind| {
+                    match unblock {
+                        UnblockKind::Ready => {
+                            // The condvar was signaled. Make sure we get the clock for that.
+                            this.acquire_clock(&condvar_ref.0.borrow().clock)?;
+                            this.condvar_reacquire_rwlock(rwlock_ref, shared, retval_succ, dest)
+                        }
+                        UnblockKind::TimedOut => {
+                            // We have to remove the waiter from the queue again.
+                            let thread = this.active_thread();
+                            let waiters = &mut condvar_ref.0.borrow_mut().waiters;
+                            waiters.retain(|waiter| *waiter != thread);
+                            // Now get back the lock.
+                            this.condvar_reacquire_rwlock(rwlock_ref, shared, retval_timeout, dest)
+                        }
+                    }
+                }
+            ),
+        );
+        interp_ok(())
+    }
+
     /// Wake up some thread (if there is any) sleeping on the conditional
     /// variable. Returns `true` iff any thread was woken up.
     fn condvar_signal(&mut self, condvar_ref: &CondvarRef) -> InterpResult<'tcx, bool> {
         let this = self.eval_context_mut();
+        this.dpor_sync_access(&condvar_ref.0);
         let mut condvar = condvar_ref.0.borrow_mut();
 
         // Each condvar signal happens-before the end of the condvar wake
@@ -768,6 +996,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     ) {
         let this = self.eval_context_mut();
         let thread = this.active_thread();
+        this.dpor_sync_access(&futex_ref.0);
         let mut futex = futex_ref.0.borrow_mut();
         let waiters = &mut futex.waiters;
         assert!(waiters.iter().all(|waiter| waiter.thread != thread), "thread is already waiting");
@@ -812,6 +1041,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         count: usize,
     ) -> InterpResult<'tcx, usize> {
         let this = self.eval_context_mut();
+        this.dpor_sync_access(&futex_ref.0);
         let mut futex = futex_ref.0.borrow_mut();
 
         // Each futex-wake happens-before the end of the futex wait
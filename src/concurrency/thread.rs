@@ -29,6 +29,9 @@ enum SchedulingAction {
     ExecuteTimeoutCallback,
     /// Wait for a bit, until there is a timeout to be called.
     Sleep(Duration),
+    /// Wait until a child process makes progress, or for at most the given time until there is a
+    /// timeout to be called.
+    WaitForChildProcess(Option<Duration>),
 }
 
 /// What to do with TLS allocations from terminated threads
@@ -111,6 +114,19 @@ pub enum BlockReason {
     Eventfd,
     /// Blocked on unnamed_socket.
     UnnamedSocket,
+    /// Blocked on a file lock (`flock`, `fcntl` or `LockFileEx`).
+    FileLock,
+    /// Blocked in `ConnectNamedPipe`, waiting for a client.
+    NamedPipe,
+    /// Blocked on the Windows thread pool: waiting for callbacks to complete, or for a timer to
+    /// expire.
+    ThreadPool,
+    /// Blocked in an alertable wait (`SleepEx`), until an APC is queued or the timeout expires.
+    Alertable,
+    /// Blocked on a socket of the loopback network.
+    Network,
+    /// Waiting for a child process to produce output or to exit.
+    ChildProcess,
     /// Blocked for any reason related to GenMC, such as `assume` statements (GenMC mode only).
     /// Will be implicitly unblocked when GenMC schedules this thread again.
     Genmc,
@@ -121,7 +137,13 @@ enum ThreadState<'tcx> {
     /// The thread is enabled and can be executed.
     Enabled,
     /// The thread is blocked on something.
-    Blocked { reason: BlockReason, timeout: Option<Timeout>, callback: DynUnblockCallback<'tcx> },
+    Blocked {
+        reason: BlockReason,
+        timeout: Option<Timeout>,
+        callback: DynUnblockCallback<'tcx>,
+        /// The lock the thread waits for, if it is blocked on a mutex or read-write lock.
+        wait_object: Option<WaitObject>,
+    },
     /// The thread has terminated its execution. We do not delete terminated
     /// threads (FIXME: why?).
     Terminated,
@@ -152,6 +174,16 @@ impl<'tcx> ThreadState<'tcx> {
     }
 }
 
+/// How to report threads that are still running when the main thread exits
+/// (`-Zmiri-report-thread-leaks`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThreadLeakReport {
+    /// Print a warning for each such thread.
+    Warn,
+    /// Report each such thread as an error, like a memory leak.
+    Error,
+}
+
 /// The join status of a thread.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 enum ThreadJoinStatus {
@@ -189,6 +221,10 @@ pub struct Thread<'tcx> {
     /// The join status.
     join_status: ThreadJoinStatus,
 
+    /// Whether this thread belongs to the emulated runtime (like a thread pool worker) rather than
+    /// to the program. The program does not have to wait for such threads before it exits.
+    runtime_owned: bool,
+
     /// Stack of active unwind payloads for the current thread. Used for storing
     /// the argument of the call to `miri_start_unwind` (the payload) when unwinding.
     /// This is pointer-sized, and matches the `Payload` type in `src/libpanic_unwind/miri.rs`.
@@ -201,6 +237,22 @@ pub struct Thread<'tcx> {
 
     /// Last OS error location in memory. It is a 32-bit integer.
     pub(crate) last_error: Option<MPlaceTy<'tcx>>,
+
+    /// The locale object installed with `uselocale`, or `None` if the thread uses the global
+    /// locale.
+    pub(crate) locale: Option<Pointer>,
+
+    /// The number of basic blocks this thread has executed. This is the basis for the thread's
+    /// emulated CPU time.
+    pub(crate) basic_block_count: u64,
+
+    /// When this thread was created and when it terminated, measured on the monotonic clock
+    /// (relative to its epoch).
+    created_at: Duration,
+    terminated_at: Option<Duration>,
+
+    /// A backtrace to where this thread was spawned, if `-Zmiri-report-thread-leaks` is set.
+    spawn_backtrace: Option<Vec<FrameInfo<'tcx>>>,
 }
 
 pub type StackEmptyCallback<'tcx> =
@@ -305,8 +357,14 @@ impl<'tcx> Thread<'tcx> {
             stack: Vec::new(),
             top_user_relevant_frame: None,
             join_status: ThreadJoinStatus::Joinable,
+            runtime_owned: false,
             unwind_payloads: Vec::new(),
             last_error: None,
+            locale: None,
+            basic_block_count: 0,
+            created_at: Duration::ZERO,
+            terminated_at: None,
+            spawn_backtrace: None,
             on_stack_empty,
         }
     }
@@ -317,11 +375,17 @@ impl VisitProvenance for Thread<'_> {
         let Thread {
             unwind_payloads: panic_payload,
             last_error,
+            locale,
             stack,
             top_user_relevant_frame: _,
             state: _,
             thread_name: _,
             join_status: _,
+            runtime_owned: _,
+            basic_block_count: _,
+            created_at: _,
+            terminated_at: _,
+            spawn_backtrace: _,
             on_stack_empty: _, // we assume the closure captures no GC-relevant state
         } = self;
 
@@ -329,6 +393,7 @@ impl VisitProvenance for Thread<'_> {
             payload.visit_provenance(visit);
         }
         last_error.visit_provenance(visit);
+        locale.visit_provenance(visit);
         for frame in stack {
             frame.visit_provenance(visit)
         }
@@ -374,11 +439,11 @@ enum Timeout {
 
 impl Timeout {
     /// How long do we have to wait from now until the specified time?
-    fn get_wait_time(&self, clock: &MonotonicClock) -> Duration {
+    fn get_wait_time(&self, clock: &MonotonicClock, realtime_clock: &RealtimeClock) -> Duration {
         match self {
             Timeout::Monotonic(instant) => instant.duration_since(clock.now()),
             Timeout::RealTime(time) =>
-                time.duration_since(SystemTime::now()).unwrap_or(Duration::ZERO),
+                time.duration_since(realtime_clock.now(clock)).unwrap_or(Duration::ZERO),
         }
     }
 
@@ -431,6 +496,13 @@ pub struct ThreadManager<'tcx> {
     yield_active_thread: bool,
     /// A flag that indicates that we should do round robin scheduling of threads else randomized scheduling is used.
     fixed_scheduling: bool,
+    /// The thread priorities, if the PCT scheduling strategy is used.
+    pct: Option<PctScheduler>,
+    /// A flag that indicates that a thread spinning without progress hands over to the next thread
+    /// in round-robin order (`-Zmiri-fair-scheduling`).
+    fair_scheduling: bool,
+    /// How to report threads that are still running when the main thread exits, if at all.
+    thread_leak_report: Option<ThreadLeakReport>,
 }
 
 impl VisitProvenance for ThreadManager<'_> {
@@ -441,6 +513,9 @@ impl VisitProvenance for ThreadManager<'_> {
             active_thread: _,
             yield_active_thread: _,
             fixed_scheduling: _,
+            pct: _,
+            fair_scheduling: _,
+            thread_leak_report: _,
         } = self;
 
         for thread in threads {
@@ -463,6 +538,9 @@ impl<'tcx> ThreadManager<'tcx> {
             thread_local_allocs: Default::default(),
             yield_active_thread: false,
             fixed_scheduling: config.fixed_scheduling,
+            pct: config.pct.map(|pct| PctScheduler::new(pct, config.seed.unwrap_or(0))),
+            fair_scheduling: config.fair_scheduling,
+            thread_leak_report: config.thread_leak_report,
         }
     }
 
@@ -515,6 +593,15 @@ impl<'tcx> ThreadManager<'tcx> {
         &mut self.threads[self.active_thread].stack
     }
 
+    /// Why `thread` is blocked and which lock it waits for, or `None` if it is not blocked.
+    pub fn blocked_on(&self, thread: ThreadId) -> Option<(BlockReason, Option<&WaitObject>)> {
+        match &self.threads[thread].state {
+            ThreadState::Blocked { reason, wait_object, .. } =>
+                Some((*reason, wait_object.as_ref())),
+            _ => None,
+        }
+    }
+
     pub fn all_blocked_stacks(
         &self,
     ) -> impl Iterator<Item = (ThreadId, &[Frame<'tcx, Provenance, FrameExtra<'tcx>>])> {
@@ -524,10 +611,65 @@ impl<'tcx> ThreadManager<'tcx> {
             .map(|(id, t)| (id, &t.stack[..]))
     }
 
+    /// The threads that belong to the program, as opposed to the emulated runtime, and can run.
+    pub fn enabled_program_threads(&self) -> impl Iterator<Item = ThreadId> {
+        self.threads
+            .iter_enumerated()
+            .filter(|(_id, t)| t.state.is_enabled() && !t.runtime_owned)
+            .map(|(id, _t)| id)
+    }
+
+    /// Borrow the stack of the given thread.
+    pub fn thread_stack(&self, thread: ThreadId) -> &[Frame<'tcx, Provenance, FrameExtra<'tcx>>] {
+        &self.threads[thread].stack
+    }
+
+    /// Whether some thread is blocked with a timeout, and hence will be woken up eventually.
+    pub fn has_pending_timeout(&self) -> bool {
+        self.threads
+            .iter()
+            .any(|t| matches!(t.state, ThreadState::Blocked { timeout: Some(_), .. }))
+    }
+
+    /// Whether `-Zmiri-fair-scheduling` is set.
+    pub fn fair_scheduling(&self) -> bool {
+        self.fair_scheduling
+    }
+
+    /// How to report threads that are still running when the main thread exits, if at all.
+    pub fn thread_leak_report(&self) -> Option<ThreadLeakReport> {
+        self.thread_leak_report
+    }
+
+    /// The threads of the program that have neither terminated nor been detached. When the main
+    /// thread exits, these are the threads the program forgot to join.
+    pub fn leaked_threads(&self) -> impl Iterator<Item = ThreadId> {
+        self.threads
+            .iter_enumerated()
+            .filter(|(_id, t)| {
+                !t.state.is_terminated()
+                    && !t.runtime_owned
+                    && t.join_status != ThreadJoinStatus::Detached
+            })
+            .map(|(id, _t)| id)
+    }
+
+    /// The backtrace of where `thread` was spawned, if it was recorded.
+    pub fn spawn_backtrace(&self, thread: ThreadId) -> Option<&[FrameInfo<'tcx>]> {
+        self.threads[thread].spawn_backtrace.as_deref()
+    }
+
     /// Create a new thread and returns its id.
     fn create_thread(&mut self, on_stack_empty: StackEmptyCallback<'tcx>) -> ThreadId {
         let new_thread_id = ThreadId::new(self.threads.len());
         self.threads.push(Thread::new(None, Some(on_stack_empty)));
+        if let Some(pct) = &mut self.pct {
+            pct.thread_created();
+            // The new thread runs right away if it has a higher priority.
+            if pct.prefers(new_thread_id, self.active_thread) {
+                self.yield_active_thread = true;
+            }
+        }
         new_thread_id
     }
 
@@ -558,6 +700,17 @@ impl<'tcx> ThreadManager<'tcx> {
         self.threads.iter().filter(|t| !t.state.is_terminated()).count()
     }
 
+    /// Get the number of live threads other than the active one that belong to the program, as
+    /// opposed to the emulated runtime.
+    pub fn get_other_live_program_thread_count(&self) -> usize {
+        self.threads
+            .iter_enumerated()
+            .filter(|(id, t)| {
+                *id != self.active_thread && !t.state.is_terminated() && !t.runtime_owned
+            })
+            .count()
+    }
+
     /// Has the given thread terminated?
     fn has_terminated(&self, thread_id: ThreadId) -> bool {
         self.threads[thread_id].state.is_terminated()
@@ -565,7 +718,7 @@ impl<'tcx> ThreadManager<'tcx> {
 
     /// Have all threads terminated?
     fn have_all_terminated(&self) -> bool {
-        self.threads.iter().all(|thread| thread.state.is_terminated())
+        self.threads.iter().all(|thread| thread.state.is_terminated() || thread.runtime_owned)
     }
 
     /// Enable the thread for execution. The thread must be terminated.
@@ -624,6 +777,18 @@ impl<'tcx> ThreadManager<'tcx> {
         self.threads[thread].thread_display_name(thread)
     }
 
+    /// Get the number of basic blocks the given thread has executed so far.
+    pub fn get_thread_basic_block_count(&self, thread: ThreadId) -> u64 {
+        self.threads[thread].basic_block_count
+    }
+
+    /// Get the time at which the given thread was created and, if it already terminated, the time
+    /// at which it did so. Both are measured on the monotonic clock, relative to its epoch.
+    pub fn get_thread_lifetime(&self, thread: ThreadId) -> (Duration, Option<Duration>) {
+        let thread = &self.threads[thread];
+        (thread.created_at, thread.terminated_at)
+    }
+
     /// Put the thread into the blocked state.
     fn block_thread(
         &mut self,
@@ -633,7 +798,16 @@ impl<'tcx> ThreadManager<'tcx> {
     ) {
         let state = &mut self.threads[self.active_thread].state;
         assert!(state.is_enabled());
-        *state = ThreadState::Blocked { reason, timeout, callback }
+        *state = ThreadState::Blocked { reason, timeout, callback, wait_object: None }
+    }
+
+    /// Records which lock the active thread, which was just blocked, waits for.
+    pub fn set_wait_object(&mut self, object: WaitObject) {
+        let ThreadState::Blocked { wait_object, .. } = &mut self.threads[self.active_thread].state
+        else {
+            panic!("set_wait_object: thread is not blocked");
+        };
+        *wait_object = Some(object);
     }
 
     /// Change the active thread to some enabled thread.
@@ -645,13 +819,17 @@ impl<'tcx> ThreadManager<'tcx> {
     }
 
     /// Get the wait time for the next timeout, or `None` if no timeout is pending.
-    fn next_callback_wait_time(&self, clock: &MonotonicClock) -> Option<Duration> {
+    fn next_callback_wait_time(
+        &self,
+        clock: &MonotonicClock,
+        realtime_clock: &RealtimeClock,
+    ) -> Option<Duration> {
         self.threads
             .iter()
             .filter_map(|t| {
                 match &t.state {
                     ThreadState::Blocked { timeout: Some(timeout), .. } =>
-                        Some(timeout.get_wait_time(clock)),
+                        Some(timeout.get_wait_time(clock, realtime_clock)),
                     _ => None,
                 }
             })
@@ -670,7 +848,10 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
         for (id, thread) in this.machine.threads.threads.iter_enumerated_mut() {
             match &thread.state {
                 ThreadState::Blocked { timeout: Some(timeout), .. }
-                    if timeout.get_wait_time(&this.machine.monotonic_clock) == Duration::ZERO =>
+                    if timeout.get_wait_time(
+                        &this.machine.monotonic_clock,
+                        &this.machine.realtime_clock,
+                    ) == Duration::ZERO =>
                 {
                     let old_state = mem::replace(&mut thread.state, ThreadState::Enabled);
                     let ThreadState::Blocked { callback, .. } = old_state else { unreachable!() };
@@ -682,6 +863,7 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
             }
         }
         if let Some((thread, callback)) = found_callback {
+            this.timeline_unblocked(thread);
             // This back-and-forth with `set_active_thread` is here because of two
             // design decisions:
             // 1. Make the caller and not the callback responsible for changing
@@ -691,6 +873,7 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
             let old_thread = this.machine.threads.set_active_thread_id(thread);
             callback.call(this, UnblockKind::TimedOut)?;
             this.machine.threads.set_active_thread_id(old_thread);
+            this.livelock_progress(thread);
         }
         // found_callback can remain None if the computer's clock
         // was shifted after calling the scheduler and before the call
@@ -721,7 +904,8 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
     /// long as we can and switch only when we have to (the active thread was
     /// blocked, terminated, or has explicitly asked to be preempted).
     ///
-    /// If GenMC mode is active, the scheduling is instead handled by GenMC.
+    /// If GenMC mode is active, the scheduling is instead handled by GenMC. In DPOR mode, the
+    /// explorer also picks the next thread after every atomic access.
     fn schedule(&mut self) -> InterpResult<'tcx, SchedulingAction> {
         let this = self.eval_context_mut();
 
@@ -754,12 +938,32 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
         }
 
         // We are not in GenMC mode, so we control the scheduling.
+        // Threads that wait for child processes check again once one of them made progress.
+        if this.machine.processes.take_events() {
+            let waiters: Vec<ThreadId> = this
+                .machine
+                .threads
+                .threads
+                .iter_enumerated()
+                .filter(|(_id, thread)| thread.state.is_blocked_on(BlockReason::ChildProcess))
+                .map(|(id, _thread)| id)
+                .collect();
+            for thread in waiters {
+                this.unblock_thread(thread, BlockReason::ChildProcess)?;
+            }
+        }
+        // With fair scheduling, a thread that spins hands over to the next thread.
+        if this.livelock_check()? && this.machine.threads.fair_scheduling {
+            this.machine.threads.yield_active_thread = true;
+        }
         let thread_manager = &mut this.machine.threads;
         let clock = &this.machine.monotonic_clock;
+        let realtime_clock = &this.machine.realtime_clock;
         let rng = this.machine.rng.get_mut();
         // This thread and the program can keep going.
         if thread_manager.threads[thread_manager.active_thread].state.is_enabled()
             && !thread_manager.yield_active_thread
+            && !this.machine.dpor.as_ref().is_some_and(|dpor| dpor.choice_pending())
         {
             // The currently active thread is still enabled, just continue with it.
             return interp_ok(SchedulingAction::ExecuteStep);
@@ -770,7 +974,7 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
         // `pthread_cond_timedwait`, "an error is returned if [...] the absolute time specified by
         // abstime has already been passed at the time of the call".
         // <https://pubs.opengroup.org/onlinepubs/9699919799/functions/pthread_cond_timedwait.html>
-        let potential_sleep_time = thread_manager.next_callback_wait_time(clock);
+        let potential_sleep_time = thread_manager.next_callback_wait_time(clock, realtime_clock);
         if potential_sleep_time == Some(Duration::ZERO) {
             return interp_ok(SchedulingAction::ExecuteTimeoutCallback);
         }
@@ -792,13 +996,29 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
             )
             .filter(|(_id, thread)| thread.state.is_enabled());
         // Pick a new thread, and switch to it.
-        let new_thread = if thread_manager.fixed_scheduling {
-            threads_iter.next()
+        let mut new_thread = if let Some(dpor) = &this.machine.dpor {
+            dpor.choose(thread_manager.active_thread, threads_iter.map(|(id, _thread)| id))
+                .map_err(|err| err_unsup_format!("{err}"))?
+        } else if thread_manager.fixed_scheduling || thread_manager.fair_scheduling {
+            threads_iter.next().map(|(id, _thread)| id)
+        } else if let Some(pct) = &thread_manager.pct {
+            pct.choose(threads_iter.map(|(id, _thread)| id))
         } else {
-            threads_iter.choose(rng)
+            threads_iter.choose(rng).map(|(id, _thread)| id)
         };
+        if let Some(trace) = &this.machine.schedule_trace
+            && !thread_manager.fixed_scheduling
+            && let Some(made) = new_thread
+        {
+            let threads = &thread_manager.threads;
+            new_thread = Some(trace.choose_thread(made, |id| {
+                threads.get(id).is_some_and(|thread| thread.state.is_enabled())
+            }));
+            this.machine.report_schedule_divergence();
+        }
 
-        if let Some((id, _thread)) = new_thread {
+        let thread_manager = &mut this.machine.threads;
+        if let Some(id) = new_thread {
             if thread_manager.active_thread != id {
                 info!(
                     "---------- Now executing on thread `{}` (previous: `{}`) ----------------------------------------",
@@ -817,6 +1037,9 @@ trait EvalContextPrivExt<'tcx>: MiriInterpCxExt<'tcx> {
         // We have not found a thread to execute.
         if thread_manager.threads.iter().all(|thread| thread.state.is_terminated()) {
             unreachable!("all threads terminated without the main thread terminating?!");
+        } else if this.machine.processes.has_waiters() {
+            // Child processes run on the host, so they can still wake up the blocked threads.
+            interp_ok(SchedulingAction::WaitForChildProcess(potential_sleep_time))
         } else if let Some(sleep_time) = potential_sleep_time {
             // All threads are currently blocked, but we have unexecuted
             // timeout_callbacks, which may unblock some of the threads. Hence,
@@ -895,7 +1118,17 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             let mut state = tls::TlsDtorsState::default();
             Box::new(move |m| state.on_stack_empty(m))
         });
+        this.machine.threads.threads[new_thread_id].created_at =
+            this.machine.monotonic_clock.elapsed();
+        if this.machine.threads.thread_leak_report.is_some() {
+            this.machine.threads.threads[new_thread_id].spawn_backtrace =
+                Some(this.generate_stacktrace());
+        }
+        this.timeline_spawned(new_thread_id);
         let current_span = this.machine.current_user_relevant_span();
+        if let Some(dpor) = &this.machine.dpor {
+            dpor.thread_created(this.machine.threads.active_thread, new_thread_id);
+        }
         match &mut this.machine.data_race {
             GlobalDataRaceHandler::None => {}
             GlobalDataRaceHandler::Vclocks(data_race) =>
@@ -948,6 +1181,43 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         interp_ok(new_thread_id)
     }
 
+    /// Start a thread with an empty stack. Its `on_stack_empty` callback is invoked as soon as the
+    /// thread is scheduled, and decides what the thread runs. This is used for threads that belong
+    /// to an emulated runtime, such as the workers of the Windows thread pool, so the program does
+    /// not have to wait for them before it exits.
+    fn start_thread_with_empty_stack(
+        &mut self,
+        on_stack_empty: StackEmptyCallback<'tcx>,
+    ) -> InterpResult<'tcx, ThreadId> {
+        let this = self.eval_context_mut();
+
+        let new_thread_id = this.machine.threads.create_thread(on_stack_empty);
+        let new_thread = &mut this.machine.threads.threads[new_thread_id];
+        new_thread.created_at = this.machine.monotonic_clock.elapsed();
+        new_thread.runtime_owned = true;
+        let current_span = this.machine.current_user_relevant_span();
+        if let Some(dpor) = &this.machine.dpor {
+            dpor.thread_created(this.machine.threads.active_thread, new_thread_id);
+        }
+        match &mut this.machine.data_race {
+            GlobalDataRaceHandler::None => {}
+            GlobalDataRaceHandler::Vclocks(data_race) =>
+                data_race.thread_created(&this.machine.threads, new_thread_id, current_span),
+            GlobalDataRaceHandler::Genmc(_genmc_ctx) =>
+                throw_unsup_format!(
+                    "threads without a start routine are not supported in GenMC mode"
+                ),
+        }
+
+        // The new thread inherits its creator's cpu affinity.
+        let active_thread = this.active_thread();
+        if let Some(cpuset) = this.machine.thread_cpu_affinity.get(&active_thread).cloned() {
+            this.machine.thread_cpu_affinity.insert(new_thread_id, cpuset);
+        }
+
+        interp_ok(new_thread_id)
+    }
+
     /// Handles thread termination of the active thread: wakes up threads joining on this one,
     /// and deals with the thread's thread-local statics according to `tls_alloc_action`.
     ///
@@ -956,9 +1226,12 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let this = self.eval_context_mut();
 
         // Mark thread as terminated.
+        let now = this.machine.monotonic_clock.elapsed();
         let thread = this.active_thread_mut();
         assert!(thread.stack.is_empty(), "only threads with an empty stack can be terminated");
         thread.state = ThreadState::Terminated;
+        thread.terminated_at = Some(now);
+        this.timeline_terminated();
 
         // Deallocate TLS.
         let gone_thread = this.active_thread();
@@ -1032,16 +1305,12 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         }
         let timeout = timeout.map(|(clock, anchor, duration)| {
             let anchor = match clock {
-                TimeoutClock::RealTime => {
-                    assert!(
-                        this.machine.communicate(),
-                        "cannot have `RealTime` timeout with isolation enabled!"
-                    );
+                TimeoutClock::RealTime =>
                     Timeout::RealTime(match anchor {
                         TimeoutAnchor::Absolute => SystemTime::UNIX_EPOCH,
-                        TimeoutAnchor::Relative => SystemTime::now(),
-                    })
-                }
+                        TimeoutAnchor::Relative =>
+                            this.machine.realtime_clock.now(&this.machine.monotonic_clock),
+                    }),
                 TimeoutClock::Monotonic =>
                     Timeout::Monotonic(match anchor {
                         TimeoutAnchor::Absolute => this.machine.monotonic_clock.epoch(),
@@ -1050,6 +1319,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             };
             anchor.add_lossy(duration)
         });
+        this.timeline_blocked(reason);
         this.machine.threads.block_thread(reason, timeout, callback);
     }
 
@@ -1069,10 +1339,17 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             }
             _ => panic!("unblock_thread: thread was not blocked"),
         };
+        this.timeline_unblocked(thread);
         // The callback must be executed in the previously blocked thread.
         let old_thread = this.machine.threads.set_active_thread_id(thread);
         callback.call(this, UnblockKind::Ready)?;
         this.machine.threads.set_active_thread_id(old_thread);
+        this.livelock_progress(thread);
+        // With PCT, the unblocked thread runs right away if it has a higher priority.
+        let threads = &mut this.machine.threads;
+        if threads.pct.as_ref().is_some_and(|pct| pct.prefers(thread, threads.active_thread)) {
+            threads.yield_active_thread = true;
+        }
         interp_ok(())
     }
 
@@ -1109,6 +1386,9 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             return_dest: &MPlaceTy<'tcx>,
         ) -> InterpResult<'tcx> {
             let threads = &this.machine.threads;
+            if let Some(dpor) = &this.machine.dpor {
+                dpor.thread_joined(threads.active_thread, joined_thread_id);
+            }
             match &mut this.machine.data_race {
                 GlobalDataRaceHandler::None => {}
                 GlobalDataRaceHandler::Vclocks(data_race) =>
@@ -1116,6 +1396,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 GlobalDataRaceHandler::Genmc(genmc_ctx) =>
                     genmc_ctx.handle_thread_join(threads.active_thread, joined_thread_id)?,
             }
+            this.timeline_joined(joined_thread_id);
             this.write_scalar(success_retval, return_dest)?;
             interp_ok(())
         }
@@ -1150,6 +1431,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     }
                 ),
             );
+            this.timeline_blocked(BlockReason::Join(joined_thread_id));
         } else {
             // The thread has already terminated - establish happens-before and write the return value.
             after_join(this, joined_thread_id, success_retval, return_dest)?;
@@ -1254,7 +1536,16 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
     #[inline]
     fn yield_active_thread(&mut self) {
-        self.eval_context_mut().machine.threads.yield_active_thread();
+        let this = self.eval_context_mut();
+        if let Some(dpor) = &this.machine.dpor {
+            dpor.yielded();
+        }
+        this.livelock_yield();
+        let threads = &mut this.machine.threads;
+        if let Some(pct) = &mut threads.pct {
+            pct.yielded(threads.active_thread);
+        }
+        threads.yield_active_thread();
     }
 
     #[inline]
@@ -1262,10 +1553,20 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         use rand::Rng as _;
 
         let this = self.eval_context_mut();
-        if !this.machine.threads.fixed_scheduling
-            && this.machine.rng.get_mut().random_bool(this.machine.preemption_rate)
-        {
-            this.yield_active_thread();
+        if this.machine.threads.fixed_scheduling {
+            return;
+        }
+        let threads = &mut this.machine.threads;
+        let mut preempt = match &mut threads.pct {
+            Some(pct) => pct.step(threads.active_thread),
+            None => this.machine.rng.get_mut().random_bool(this.machine.preemption_rate),
+        };
+        if let Some(trace) = &this.machine.schedule_trace {
+            preempt = trace.preempt(preempt);
+        }
+        if preempt {
+            // This is not an explicit yield, so it must not count as one for PCT.
+            this.machine.threads.yield_active_thread();
         }
     }
 
@@ -1280,6 +1581,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             }
             match this.schedule()? {
                 SchedulingAction::ExecuteStep => {
+                    this.timeline_running();
                     if !this.step()? {
                         // See if this thread can do something else.
                         match this.run_on_stack_empty()? {
@@ -1295,6 +1597,11 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 SchedulingAction::Sleep(duration) => {
                     this.machine.monotonic_clock.sleep(duration);
                 }
+                SchedulingAction::WaitForChildProcess(timeout) => {
+                    // Child processes can only be started without isolation, so the monotonic
+                    // clock is the host clock and keeps going while we wait.
+                    this.machine.processes.wait_for_events(timeout);
+                }
             }
         }
     }
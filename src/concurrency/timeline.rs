@@ -0,0 +1,262 @@
+//! A timeline of the interpreted threads in the Chrome trace format, written with
+//! `-Zmiri-thread-timeline`.
+//!
+//! The timeline has one track per thread. It shows when the thread ran, when it was blocked and on
+//! what, the locks it acquired and released, and the threads it spawned and joined. Timestamps are
+//! taken from Miri's monotonic clock, which advances with the executed basic blocks unless isolation
+//! is disabled, so they reflect the interleaving rather than the time Miri took to interpret it.
+//! The file can be opened with `chrome://tracing` or <https://ui.perfetto.dev>.
+
+use std::cell::{Cell, RefCell};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use rustc_data_structures::fx::FxHashMap;
+use rustc_index::Idx;
+
+use super::deadlock_report::{block_reason_name, json_string};
+use super::lock_order::LockId;
+use crate::*;
+
+/// Formats `time` in microseconds, the unit of the Chrome trace format.
+fn micros(time: Duration) -> String {
+    format!("{}.{:03}", time.as_micros(), time.subsec_nanos() % 1000)
+}
+
+/// Formats the arguments of an event as a JSON object.
+fn json_args(args: &[(&str, String)]) -> String {
+    let args: Vec<String> = args.iter().map(|(key, val)| format!("\"{key}\": {val}")).collect();
+    format!("{{ {} }}", args.join(", "))
+}
+
+#[derive(Debug)]
+pub struct ThreadTimeline {
+    /// The file to write the timeline to.
+    path: PathBuf,
+    /// The events recorded so far, in JSON.
+    events: RefCell<Vec<String>>,
+    /// The thread that is running, and since when.
+    running: Cell<Option<(ThreadId, Duration)>>,
+    /// The threads that are blocked, since when, and the name and arguments of that event.
+    blocked: RefCell<FxHashMap<ThreadId, (Duration, String, String)>>,
+}
+
+impl ThreadTimeline {
+    pub fn new(path: PathBuf) -> Self {
+        ThreadTimeline {
+            path,
+            events: RefCell::new(Vec::new()),
+            running: Cell::new(None),
+            blocked: RefCell::new(FxHashMap::default()),
+        }
+    }
+
+    /// Records an event that lasted from `start` to `end`.
+    fn complete(&self, thread: ThreadId, name: &str, start: Duration, end: Duration, args: &str) {
+        self.events.borrow_mut().push(format!(
+            "{{ \"name\": {}, \"ph\": \"X\", \"pid\": 0, \"tid\": {}, \"ts\": {}, \"dur\": {}, \"args\": {args} }}",
+            json_string(name),
+            thread.to_u32(),
+            micros(start),
+            micros(end.saturating_sub(start)),
+        ));
+    }
+
+    /// Records an event that happened at `now`.
+    fn instant(&self, thread: ThreadId, name: &str, now: Duration, args: &[(&str, String)]) {
+        self.events.borrow_mut().push(format!(
+            "{{ \"name\": {}, \"ph\": \"i\", \"s\": \"t\", \"pid\": 0, \"tid\": {}, \"ts\": {}, \"args\": {} }}",
+            json_string(name),
+            thread.to_u32(),
+            micros(now),
+            json_args(args),
+        ));
+    }
+
+    /// Ends the slice of the running thread, if any, at `now`.
+    fn stop_running(&self, now: Duration) {
+        if let Some((thread, since)) = self.running.take() {
+            self.complete(thread, "running", since, now, "{}");
+        }
+    }
+
+    /// Records that `thread` runs at `now`.
+    fn running(&self, thread: ThreadId, now: Duration) {
+        if self.running.get().is_some_and(|(running, _since)| running == thread) {
+            return;
+        }
+        self.stop_running(now);
+        self.running.set(Some((thread, now)));
+    }
+
+    /// Records that the running `thread` got blocked.
+    fn blocked(&self, thread: ThreadId, now: Duration, name: String, args: String) {
+        self.stop_running(now);
+        self.blocked.borrow_mut().insert(thread, (now, name, args));
+    }
+
+    /// Records that `thread` was woken up.
+    fn unblocked(&self, thread: ThreadId, now: Duration) {
+        if let Some((since, name, args)) = self.blocked.borrow_mut().remove(&thread) {
+            self.complete(thread, &name, since, now, &args);
+        }
+    }
+
+    /// Writes the timeline to its file. Threads that are still running or blocked end at `now`.
+    fn write(&self, threads: &ThreadManager<'_>, now: Duration) -> Result<(), String> {
+        self.stop_running(now);
+        for (thread, (since, name, args)) in self.blocked.take() {
+            self.complete(thread, &name, since, now, &args);
+        }
+        let mut text = String::from("{\n  \"displayTimeUnit\": \"ns\",\n  \"traceEvents\": [\n");
+        for idx in 0..threads.get_total_thread_count() {
+            let thread = ThreadId::new(idx);
+            writeln!(
+                text,
+                "    {{ \"name\": \"thread_name\", \"ph\": \"M\", \"pid\": 0, \"tid\": {}, \"args\": {{ \"name\": {} }} }},",
+                thread.to_u32(),
+                json_string(&threads.get_thread_display_name(thread)),
+            )
+            .unwrap();
+        }
+        text.push_str(
+            &self
+                .events
+                .borrow()
+                .iter()
+                .map(|event| format!("    {event}"))
+                .collect::<Vec<_>>()
+                .join(",\n"),
+        );
+        text.push_str("\n  ]\n}\n");
+        fs::write(&self.path, text).map_err(|err| {
+            format!("failed to write thread timeline to `{}`: {err}", self.path.display())
+        })
+    }
+}
+
+impl<'tcx> MiriMachine<'tcx> {
+    /// Where the active thread currently is, as a JSON string.
+    fn timeline_location(&self) -> String {
+        let span = self.current_user_relevant_span();
+        json_string(&self.tcx.sess.source_map().span_to_embeddable_string(span))
+    }
+
+    /// Writes the thread timeline, if `-Zmiri-thread-timeline` is set.
+    pub(crate) fn write_thread_timeline(&self) -> Result<(), String> {
+        let Some(timeline) = &self.thread_timeline else {
+            return Ok(());
+        };
+        timeline.write(&self.threads, self.monotonic_clock.elapsed())
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Tells the timeline, if any, that the active thread is about to take a step.
+    fn timeline_running(&self) {
+        let this = self.eval_context_ref();
+        if let Some(timeline) = &this.machine.thread_timeline {
+            timeline.running(this.active_thread(), this.machine.monotonic_clock.elapsed());
+        }
+    }
+
+    /// Tells the timeline, if any, that the active thread got blocked.
+    fn timeline_blocked(&self, reason: BlockReason) {
+        let this = self.eval_context_ref();
+        let Some(timeline) = &this.machine.thread_timeline else {
+            return;
+        };
+        let mut args = vec![
+            ("reason", format!("\"{}\"", block_reason_name(reason))),
+            ("at", this.machine.timeline_location()),
+        ];
+        if let BlockReason::Join(joined) = reason {
+            args.push(("thread", joined.to_u32().to_string()));
+        }
+        timeline.blocked(
+            this.active_thread(),
+            this.machine.monotonic_clock.elapsed(),
+            format!("blocked on {}", block_reason_name(reason)),
+            json_args(&args),
+        );
+    }
+
+    /// Tells the timeline, if any, that `thread` was woken up.
+    fn timeline_unblocked(&self, thread: ThreadId) {
+        let this = self.eval_context_ref();
+        if let Some(timeline) = &this.machine.thread_timeline {
+            timeline.unblocked(thread, this.machine.monotonic_clock.elapsed());
+        }
+    }
+
+    /// Tells the timeline, if any, that the active thread terminated.
+    fn timeline_terminated(&self) {
+        let this = self.eval_context_ref();
+        if let Some(timeline) = &this.machine.thread_timeline {
+            let now = this.machine.monotonic_clock.elapsed();
+            timeline.stop_running(now);
+            timeline.instant(this.active_thread(), "exit", now, &[]);
+        }
+    }
+
+    /// Tells the timeline, if any, that the active thread spawned `thread`.
+    fn timeline_spawned(&self, thread: ThreadId) {
+        let this = self.eval_context_ref();
+        if let Some(timeline) = &this.machine.thread_timeline {
+            timeline.instant(
+                this.active_thread(),
+                &format!("spawn thread {}", thread.to_u32()),
+                this.machine.monotonic_clock.elapsed(),
+                &[
+                    ("thread", thread.to_u32().to_string()),
+                    ("at", this.machine.timeline_location()),
+                ],
+            );
+        }
+    }
+
+    /// Tells the timeline, if any, that the active thread joined `thread`.
+    fn timeline_joined(&self, thread: ThreadId) {
+        let this = self.eval_context_ref();
+        if let Some(timeline) = &this.machine.thread_timeline {
+            timeline.instant(
+                this.active_thread(),
+                &format!("join thread {}", thread.to_u32()),
+                this.machine.monotonic_clock.elapsed(),
+                &[
+                    ("thread", thread.to_u32().to_string()),
+                    ("at", this.machine.timeline_location()),
+                ],
+            );
+        }
+    }
+
+    /// Tells the timeline, if any, that the active thread acquired (`Some(shared)`) or released
+    /// (`None`) `lock`.
+    fn timeline_lock(&self, lock: LockId, acquired: Option<bool>) {
+        let this = self.eval_context_ref();
+        let Some(timeline) = &this.machine.thread_timeline else {
+            return;
+        };
+        let mut args = vec![
+            ("lock", json_string(&lock.to_string())),
+            ("at", this.machine.timeline_location()),
+        ];
+        let name = match acquired {
+            Some(shared) => {
+                args.push(("shared", shared.to_string()));
+                format!("acquire {lock}")
+            }
+            None => format!("release {lock}"),
+        };
+        timeline.instant(
+            this.active_thread(),
+            &name,
+            this.machine.monotonic_clock.elapsed(),
+            &args,
+        );
+    }
+}
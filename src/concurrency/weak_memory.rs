@@ -60,6 +60,18 @@
 //! A mixed atomicity read that races with writes, or a write that races with reads or writes will still cause UBs to be thrown.
 //! Mixed size atomic accesses must not race with any other atomic access, whether read or write, or a UB will be thrown.
 //! You can refer to test cases in weak_memory/extra_cpp.rs and weak_memory/extra_cpp_unsafe.rs for examples of these operations.
+//!
+//! With `-Zmiri-mixed-size-atomics`, mixed size atomic accesses may race. Their store buffers then
+//! work as follows:
+//! - A store to a part of an atomic object is merged into a store to the whole object, whose value
+//!   combines the new part with the latest value of the rest of the object.
+//! - A load from a part of an atomic object picks a store of the whole object like any other load,
+//!   and reads its part of the value.
+//! - Any other store replaces the atomic objects it overlaps with a new one, whose history starts
+//!   at this store. Any other load reads the latest value, and counts as a load from the latest
+//!   store of every atomic object it overlaps.
+//! Loads hence only observe combinations of values that existed at some point of the modification
+//! order. Parts of stored pointers and of uninitialized values cannot be read this way.
 
 // Our and the author's own implementation (tsan11) of the paper have some deviations from the provided operational semantics in §5.3:
 // 1. In the operational semantics, loads acquire the vector clock of the atomic location
@@ -89,6 +101,7 @@
 use std::cell::{Ref, RefCell};
 use std::collections::VecDeque;
 
+use rustc_abi::Endian;
 use rustc_data_structures::fx::FxHashMap;
 
 use super::AllocDataRaceHandler;
@@ -218,12 +231,62 @@ impl StoreBufferAlloc {
         interp_ok(Some(store_buffer))
     }
 
+    /// Gets the store buffer of the atomic object that `range` is a strict part of, together with
+    /// the range of that object.
+    fn get_containing_store_buffer(
+        &self,
+        range: AllocRange,
+    ) -> Option<(Ref<'_, StoreBuffer>, AllocRange)> {
+        let buffers = self.store_buffers.borrow();
+        let pos = containing_pos(&buffers, range)?;
+        let object = buffers.range_at(pos);
+        Some((Ref::map(buffers, |buffers| &buffers[pos]), object))
+    }
+
+    /// Loads from the latest store of every atomic object that `range` imperfectly overlaps.
+    fn read_from_overlapping_stores(
+        &self,
+        range: AllocRange,
+        global: &DataRaceState,
+        thread_mgr: &ThreadManager<'_>,
+        is_seqcst: bool,
+    ) {
+        let buffers = self.store_buffers.borrow();
+        if let AccessType::ImperfectlyOverlapping(pos_range) = buffers.access_type(range) {
+            for pos in pos_range {
+                buffers[pos].read_from_last_store(global, thread_mgr, is_seqcst);
+            }
+        }
+    }
+
+    /// If `range` is a strict part of an atomic object, turns a store of `val` to `range` into a
+    /// store to the whole object, combining `val` with the latest value of the object. Returns the
+    /// range and value to store.
+    fn merge_into_containing_store(
+        &self,
+        range: AllocRange,
+        val: Scalar,
+        endian: Endian,
+    ) -> (AllocRange, Scalar) {
+        let buffers = self.store_buffers.borrow();
+        if let Some(pos) = containing_pos(&buffers, range) {
+            let object = buffers.range_at(pos);
+            let latest = buffers[pos].buffer.back().and_then(|store_elem| store_elem.val);
+            if let Some(merged) = write_part(latest, object, range, val, endian) {
+                return (object, merged);
+            }
+        }
+        // The object is replaced by a new one for this store.
+        (range, val)
+    }
+
     /// Gets a mutable store buffer associated with an atomic object in this allocation,
     /// or creates one with the specified initial value if no atomic object exists yet.
     fn get_or_create_store_buffer_mut<'tcx>(
         &mut self,
         range: AllocRange,
         init: Result<Option<Scalar>, ()>,
+        mixed_size_atomics: bool,
     ) -> InterpResult<'tcx, &mut StoreBuffer> {
         let buffers = self.store_buffers.get_mut();
         let access_type = buffers.access_type(range);
@@ -236,12 +299,19 @@ impl StoreBufferAlloc {
                 pos
             }
             AccessType::ImperfectlyOverlapping(pos_range) => {
-                // Once we reach here we would've already checked that this access is not racy.
-                let init = init.expect(
-                    "cannot have partially overlapping store buffer when previous write was atomic",
-                );
+                // Once we reach here we would've already checked that this access is not racy,
+                // unless mixed-size accesses may race. Then the new object starts out empty, and
+                // its history begins with the store that is about to be added.
+                let buffer = match init {
+                    Ok(init) => StoreBuffer::new(init),
+                    Err(()) if mixed_size_atomics => StoreBuffer { buffer: VecDeque::new() },
+                    Err(()) =>
+                        panic!(
+                            "cannot have partially overlapping store buffer when previous write was atomic"
+                        ),
+                };
                 buffers.remove_pos_range(pos_range.clone());
-                buffers.insert_at_pos(pos_range.start, range, StoreBuffer::new(init));
+                buffers.insert_at_pos(pos_range.start, range, buffer);
                 pos_range.start
             }
         };
@@ -249,6 +319,58 @@ impl StoreBufferAlloc {
     }
 }
 
+/// The position of the atomic object that `range` is a strict part of, if any.
+fn containing_pos(buffers: &RangeObjectMap<StoreBuffer>, range: AllocRange) -> Option<usize> {
+    let AccessType::ImperfectlyOverlapping(pos_range) = buffers.access_type(range) else {
+        return None;
+    };
+    let object = buffers.range_at(pos_range.start);
+    (pos_range.len() == 1 && object.start <= range.start && range.end() <= object.end())
+        .then_some(pos_range.start)
+}
+
+/// The number of bits that `part` of the atomic object at `object` is shifted by in its value.
+fn part_shift(object: AllocRange, part: AllocRange, endian: Endian) -> u64 {
+    let offset = match endian {
+        Endian::Little => part.start - object.start,
+        Endian::Big => object.end() - part.end(),
+    };
+    offset.bits()
+}
+
+/// Reads `part` of the value `val` of the atomic object at `object`. Returns `None` if `val` is
+/// not an integer.
+fn read_part(
+    val: Option<Scalar>,
+    object: AllocRange,
+    part: AllocRange,
+    endian: Endian,
+) -> Option<Scalar> {
+    let Some(Scalar::Int(int)) = val else {
+        return None;
+    };
+    let bits = int.to_bits(object.size) >> part_shift(object, part, endian);
+    Some(Scalar::from_uint(part.size.truncate(bits), part.size))
+}
+
+/// Replaces `part` of the value `val` of the atomic object at `object` by `new`. Returns `None` if
+/// either value is not an integer.
+fn write_part(
+    val: Option<Scalar>,
+    object: AllocRange,
+    part: AllocRange,
+    new: Scalar,
+    endian: Endian,
+) -> Option<Scalar> {
+    let (Some(Scalar::Int(int)), Scalar::Int(new)) = (val, new) else {
+        return None;
+    };
+    let shift = part_shift(object, part, endian);
+    let mask = part.size.unsigned_int_max() << shift;
+    let bits = (int.to_bits(object.size) & !mask) | (new.to_bits(part.size) << shift);
+    Some(Scalar::from_uint(bits, object.size))
+}
+
 impl<'tcx> StoreBuffer {
     fn new(init: Option<Scalar>) -> Self {
         let mut buffer = VecDeque::new();
@@ -287,6 +409,7 @@ impl<'tcx> StoreBuffer {
         thread_mgr: &ThreadManager<'_>,
         is_seqcst: bool,
         rng: &mut (impl rand::Rng + ?Sized),
+        trace: Option<&ScheduleTrace>,
         validate: impl FnOnce(Option<&VClock>) -> InterpResult<'tcx>,
     ) -> InterpResult<'tcx, (Option<Scalar>, LoadRecency)> {
         // Having a live borrow to store_buffer while calling validate_atomic_load is fine
@@ -297,7 +420,7 @@ impl<'tcx> StoreBuffer {
             // as the race detector will update it
             let (.., clocks) = global.active_thread_state(thread_mgr);
             // Load from a valid entry in the store buffer
-            self.fetch_store(is_seqcst, &clocks, &mut *rng)
+            self.fetch_store(is_seqcst, &clocks, &mut *rng, trace)
         };
 
         // Unlike in buffered_atomic_write, thread clock updates have to be done
@@ -331,6 +454,7 @@ impl<'tcx> StoreBuffer {
         is_seqcst: bool,
         clocks: &ThreadClockSet,
         rng: &mut R,
+        trace: Option<&ScheduleTrace>,
     ) -> (&StoreElement, LoadRecency) {
         use rand::seq::IteratorRandom;
         let mut found_sc = false;
@@ -400,7 +524,21 @@ impl<'tcx> StoreBuffer {
                 }
             });
 
-        let chosen = candidates.choose(rng).expect("store buffer cannot be empty");
+        let chosen = match trace {
+            None => candidates.choose(rng).expect("store buffer cannot be empty"),
+            Some(trace) => {
+                // Make the random choice exactly like above, so recording does not change it.
+                let mut all = Vec::new();
+                let mut candidates = candidates.inspect(|&store_elem| all.push(store_elem));
+                let (made, _) = candidates
+                    .by_ref()
+                    .enumerate()
+                    .choose(rng)
+                    .expect("store buffer cannot be empty");
+                candidates.for_each(drop);
+                all[trace.choose_load(made, all.len())]
+            }
+        };
         if std::ptr::eq(chosen, self.buffer.back().expect("store buffer cannot be empty")) {
             (chosen, LoadRecency::Latest)
         } else {
@@ -478,6 +616,7 @@ pub(super) trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         init: Scalar,
     ) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
+        let endian = this.tcx.data_layout().endian;
         let (alloc_id, base_offset, ..) = this.ptr_get_alloc_id(place.ptr(), 0)?;
         if let (
             crate::AllocExtra {
@@ -493,9 +632,18 @@ pub(super) trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 global.sc_read(threads);
                 global.sc_write(threads);
             }
-            let range = alloc_range(base_offset, place.layout.size);
+            let mut range = alloc_range(base_offset, place.layout.size);
+            let mut new_val = new_val;
+            if global.mixed_size_atomics {
+                (range, new_val) =
+                    alloc_buffers.merge_into_containing_store(range, new_val, endian);
+            }
             let sync_clock = data_race_clocks.sync_clock(range);
-            let buffer = alloc_buffers.get_or_create_store_buffer_mut(range, Ok(Some(init)))?;
+            let buffer = alloc_buffers.get_or_create_store_buffer_mut(
+                range,
+                Ok(Some(init)),
+                global.mixed_size_atomics,
+            )?;
             // The RMW always reads from the most recent store.
             buffer.read_from_last_store(global, threads, atomic == AtomicRwOrd::SeqCst);
             buffer.buffered_write(
@@ -529,19 +677,52 @@ pub(super) trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                         global.sc_read(&this.machine.threads);
                     }
                     let mut rng = this.machine.rng.borrow_mut();
-                    let Some(buffer) = alloc_buffers
-                        .get_store_buffer(alloc_range(base_offset, place.layout.size))?
-                    else {
+                    let range = alloc_range(base_offset, place.layout.size);
+                    let is_seqcst = atomic == AtomicReadOrd::SeqCst;
+                    let (loaded, recency) = if let Some(buffer) =
+                        alloc_buffers.get_store_buffer(range)?
+                    {
+                        buffer.buffered_read(
+                            global,
+                            &this.machine.threads,
+                            is_seqcst,
+                            &mut *rng,
+                            this.machine.schedule_trace.as_ref(),
+                            validate,
+                        )?
+                    } else if global.mixed_size_atomics
+                        && let Some((buffer, object)) =
+                            alloc_buffers.get_containing_store_buffer(range)
+                    {
+                        let (loaded, recency) = buffer.buffered_read(
+                            global,
+                            &this.machine.threads,
+                            is_seqcst,
+                            &mut *rng,
+                            this.machine.schedule_trace.as_ref(),
+                            validate,
+                        )?;
+                        let endian = this.tcx.data_layout().endian;
+                        let Some(loaded) = read_part(loaded, object, range, endian) else {
+                            throw_unsup_format!(
+                                "mixed-size atomic load from {:?} cannot read a part of a stored value that is not an integer",
+                                place.ptr()
+                            );
+                        };
+                        (Some(loaded), recency)
+                    } else {
+                        if global.mixed_size_atomics {
+                            alloc_buffers.read_from_overlapping_stores(
+                                range,
+                                global,
+                                &this.machine.threads,
+                                is_seqcst,
+                            );
+                        }
                         // No old writes available, fall back to base case.
                         break 'fallback;
                     };
-                    let (loaded, recency) = buffer.buffered_read(
-                        global,
-                        &this.machine.threads,
-                        atomic == AtomicReadOrd::SeqCst,
-                        &mut *rng,
-                        validate,
-                    )?;
+                    this.machine.report_schedule_divergence();
                     if global.track_outdated_loads && recency == LoadRecency::Outdated {
                         this.emit_diagnostic(NonHaltingDiagnostic::WeakMemoryOutdatedLoad {
                             ptr: place.ptr(),
@@ -573,6 +754,7 @@ pub(super) trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         init: Result<Option<Scalar>, ()>,
     ) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
+        let endian = this.tcx.data_layout().endian;
         let (alloc_id, base_offset, ..) = this.ptr_get_alloc_id(dest.ptr(), 0)?;
         if let (
             crate::AllocExtra {
@@ -588,11 +770,19 @@ pub(super) trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 global.sc_write(threads);
             }
 
-            let range = alloc_range(base_offset, dest.layout.size);
+            let mut range = alloc_range(base_offset, dest.layout.size);
+            let mut val = val;
+            if global.mixed_size_atomics {
+                (range, val) = alloc_buffers.merge_into_containing_store(range, val, endian);
+            }
             // It's a bit annoying that we have to go back to the data race part to get the clock...
             // but it does make things a lot simpler.
             let sync_clock = data_race_clocks.sync_clock(range);
-            let buffer = alloc_buffers.get_or_create_store_buffer_mut(range, init)?;
+            let buffer = alloc_buffers.get_or_create_store_buffer_mut(
+                range,
+                init,
+                global.mixed_size_atomics,
+            )?;
             buffer.buffered_write(
                 val,
                 global,
@@ -625,9 +815,16 @@ pub(super) trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             if let Some(alloc_buffers) =
                 this.get_alloc_extra(alloc_id)?.data_race.as_weak_memory_ref()
             {
-                let Some(buffer) =
-                    alloc_buffers.get_store_buffer(alloc_range(base_offset, size))?
-                else {
+                let range = alloc_range(base_offset, size);
+                let Some(buffer) = alloc_buffers.get_store_buffer(range)? else {
+                    if global.mixed_size_atomics {
+                        alloc_buffers.read_from_overlapping_stores(
+                            range,
+                            global,
+                            &this.machine.threads,
+                            atomic == AtomicReadOrd::SeqCst,
+                        );
+                    }
                     // No store buffer, nothing to do.
                     return interp_ok(());
                 };
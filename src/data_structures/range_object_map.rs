@@ -122,6 +122,11 @@ impl<T> RangeObjectMap<T> {
         self.v.remove(pos);
     }
 
+    /// The range occupied by the object at the given position.
+    pub fn range_at(&self, pos: Position) -> AllocRange {
+        self.v[pos].range
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &T> {
         self.v.iter().map(|e| &e.data)
     }
@@ -227,6 +232,7 @@ mod tests {
         //  0 1 2 3 ^ ^ ^ ^ 8
         assert_eq!(map.find_offset(four), Ok(1));
         assert_eq!(map.access_type(alloc_range(four, four)), AccessType::PerfectlyOverlapping(1));
+        assert_eq!(map.range_at(1).start, four);
     }
 
     #[test]
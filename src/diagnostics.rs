@@ -33,6 +33,16 @@ pub enum TerminationInfo {
     },
     Int2PtrWithStrictProvenance,
     Deadlock,
+    /// All threads that can run spun for `steps` steps without progress.
+    Livelock {
+        steps: u64,
+        fair: bool,
+    },
+    /// The active thread spun for `steps` steps while the `starved` threads did not run.
+    SpinStarvation {
+        steps: u64,
+        starved: Vec<ThreadId>,
+    },
     MultipleSymbolDefinitions {
         link_name: Symbol,
         first: SpanData,
@@ -59,6 +69,9 @@ pub struct RacingOp {
     pub action: String,
     pub thread_info: String,
     pub span: SpanData,
+    /// The backtrace of the operation, one frame per line, if it was recorded with
+    /// `-Zmiri-race-backtraces`.
+    pub backtrace: Vec<String>,
 }
 
 impl fmt::Display for TerminationInfo {
@@ -77,6 +90,16 @@ impl fmt::Display for TerminationInfo {
             StackedBorrowsUb { msg, .. } => write!(f, "{msg}"),
             TreeBorrowsUb { title, .. } => write!(f, "{title}"),
             Deadlock => write!(f, "the evaluated program deadlocked"),
+            Livelock { steps, .. } =>
+                write!(
+                    f,
+                    "the evaluated program seems to be livelocked: no thread made progress in {steps} spin steps"
+                ),
+            SpinStarvation { steps, .. } =>
+                write!(
+                    f,
+                    "the evaluated program seems to be starving threads: this thread spun for {steps} steps while other threads did not get to run"
+                ),
             MultipleSymbolDefinitions { link_name, .. } =>
                 write!(f, "multiple definitions of symbol `{link_name}`"),
             SymbolShimClashing { link_name, .. } =>
@@ -139,6 +162,30 @@ pub enum NonHaltingDiagnostic {
         ptr: Pointer,
     },
     ExternTypeReborrow,
+    /// An allocation of this size was made to fail by `-Zmiri-alloc-fail-*` or
+    /// `-Zmiri-heap-limit`. The string explains which flag caused it.
+    InjectedAllocFailure {
+        size: u64,
+        reason: String,
+    },
+    /// An exit handler started running while this many other threads were still running.
+    ExitHandlerWithLiveThreads {
+        live_threads: usize,
+    },
+    /// The replayed schedule trace stopped matching the execution at the decision with this index.
+    /// The string explains why.
+    ScheduleReplayDiverged {
+        index: usize,
+        reason: String,
+    },
+    /// The active thread acquires a lock while holding another one, in an order that is
+    /// inconsistent with the acquisitions of other threads that could run concurrently.
+    PotentialDeadlock {
+        /// Where the active thread acquired the lock it holds.
+        held_span: SpanData,
+        /// The acquisitions of the other threads, along the cycle.
+        others: Vec<CycleAcquisition>,
+    },
     GenmcCompareExchangeWeak,
     GenmcCompareExchangeOrderingMismatch {
         success_ordering: AtomicRwOrd,
@@ -164,6 +211,87 @@ macro_rules! note_span {
     ($span:expr, $($tt:tt)*) => { (Some($span), format!($($tt)*)) };
 }
 
+/// Explains how long a thread has been spinning, and on which location.
+fn spinning_thread_notes<'tcx>(
+    machine: &MiriMachine<'tcx>,
+    thread: ThreadId,
+) -> Vec<(Option<SpanData>, String)> {
+    let Some(detector) = &machine.livelock else {
+        return vec![];
+    };
+    let detector = detector.borrow();
+    let steps = detector.steps(thread);
+    match detector.location(thread) {
+        Some(ptr) =>
+            vec![note!(
+                "this thread has taken {steps} spin steps without progress, and last loaded from {ptr:?}"
+            )],
+        None => vec![note!("this thread has taken {steps} spin steps without progress")],
+    }
+}
+
+/// Explains what a deadlocked thread waits for: the reason it is blocked and, for locks, which
+/// threads hold the lock and where they acquired it.
+fn blocked_thread_notes<'tcx>(
+    machine: &MiriMachine<'tcx>,
+    thread: ThreadId,
+) -> Vec<(Option<SpanData>, String)> {
+    let Some((reason, wait_object)) = machine.threads.blocked_on(thread) else {
+        return vec![];
+    };
+    let waiting = match reason {
+        BlockReason::Join(joined) =>
+            format!(
+                "waiting for thread `{}` to terminate",
+                machine.threads.get_thread_display_name(joined)
+            ),
+        BlockReason::Sleep => format!("sleeping"),
+        BlockReason::Mutex => format!("waiting to acquire a mutex"),
+        BlockReason::Condvar => format!("waiting on a condition variable"),
+        BlockReason::RwLock => format!("waiting to acquire a read-write lock"),
+        BlockReason::Futex => format!("waiting on a futex"),
+        BlockReason::InitOnce => format!("waiting for a one-time initialization to complete"),
+        BlockReason::Epoll => format!("waiting for an epoll event"),
+        BlockReason::Eventfd => format!("waiting to read from or write to an eventfd"),
+        BlockReason::UnnamedSocket => format!("waiting to read from or write to a socket or pipe"),
+        BlockReason::FileLock => format!("waiting for a file lock"),
+        BlockReason::NamedPipe => format!("waiting for a client to connect to a named pipe"),
+        BlockReason::ThreadPool => format!("waiting for the thread pool"),
+        BlockReason::Alertable => format!("in an alertable wait"),
+        BlockReason::Network => format!("waiting on a network socket"),
+        BlockReason::ChildProcess => format!("waiting for a child process"),
+        BlockReason::Genmc => format!("blocked by GenMC"),
+    };
+    let mut notes = vec![note!("this thread is {waiting}")];
+    let (kind, holders) = match wait_object {
+        Some(wait_object) => (wait_object.kind(), wait_object.holders()),
+        // The standard library's `Mutex` blocks on a futex on many targets.
+        None if reason == BlockReason::Futex =>
+            match machine.std_mutex_holders(thread) {
+                Some(holders) => ("mutex", holders),
+                None => return notes,
+            },
+        None => return notes,
+    };
+    for holder in holders {
+        let held = match (wait_object, holder.shared) {
+            (Some(WaitObject::RwLock(_)), false) => "write-locked",
+            (Some(WaitObject::RwLock(_)), true) => "read-locked",
+            _ => "held",
+        };
+        let name = machine.threads.get_thread_display_name(holder.thread);
+        notes.push(match holder.acquired_at {
+            Some(span) =>
+                note_span!(
+                    span.data(),
+                    "the {kind} is {held} by thread `{name}`, which acquired it here"
+                ),
+            None => note!("the {kind} is {held} by thread `{name}`"),
+        });
+    }
+    notes
+}
+
 /// Attempts to prune a stacktrace to omit the Rust runtime, and returns a bool indicating if any
 /// frames were pruned. If the stacktrace does not have any local frames, we conclude that it must
 /// be pointing to a problem in the Rust runtime itself, and do not prune it at all.
@@ -249,6 +377,10 @@ pub fn report_result<'tcx>(
                 labels.push(format!("this thread got stuck here"));
                 None
             }
+            Livelock { .. } | SpinStarvation { .. } => {
+                labels.push(format!("this thread is spinning here"));
+                None
+            }
             MultipleSymbolDefinitions { .. } | SymbolShimClashing { .. } => None,
         };
         #[rustfmt::skip]
@@ -300,11 +432,21 @@ pub fn report_result<'tcx>(
                 ],
             SymbolShimClashing { link_name, span } =>
                 vec![note_span!(*span, "the `{link_name}` symbol is defined here")],
+            Livelock { fair: true, .. } =>
+                vec![note!("the scheduler was fair, so this does not depend on the order in which the threads were scheduled")],
+            Livelock { fair: false, .. } =>
+                vec![note!("this can also be caused by Miri's scheduler; run with `MIRIFLAGS=-Zmiri-fair-scheduling` to check whether the threads also spin when each of them gets to run in turn")],
+            SpinStarvation { .. } =>
+                vec![note!("this is likely caused by Miri's scheduler; run with `MIRIFLAGS=-Zmiri-fair-scheduling` to make spinning threads hand over to the other threads")],
             Int2PtrWithStrictProvenance =>
                 vec![note!("use Strict Provenance APIs (https://doc.rust-lang.org/nightly/std/ptr/index.html#strict-provenance, https://crates.io/crates/sptr) instead")],
             DataRace { op1, extra, retag_explain, .. } => {
                 labels.push(format!("(2) just happened here"));
                 let mut helps = vec![note_span!(op1.span, "and (1) occurred earlier here")];
+                if !op1.backtrace.is_empty() {
+                    helps.push(note!("backtrace of (1):"));
+                    helps.extend(op1.backtrace.iter().map(|frame| note!("{frame}")));
+                }
                 if let Some(extra) = extra {
                     helps.push(note!("{extra}"));
                     helps.push(note!("see https://doc.rust-lang.org/nightly/std/sync/atomic/index.html#memory-model-for-atomic-accesses for more information about the Rust memory model"));
@@ -407,6 +549,9 @@ pub fn report_result<'tcx>(
     let (stacktrace, mut any_pruned) = prune_stacktrace(stacktrace, &ecx.machine);
 
     let mut show_all_threads = false;
+    let mut notes = vec![];
+    // Other threads to show for livelocks: their message, their label, and whether they spin.
+    let mut other_threads = vec![];
 
     // We want to dump the allocation if this is `InvalidUninitBytes`.
     // Since `format_interp_error` consumes `e`, we compute the outut early.
@@ -426,6 +571,31 @@ pub fn report_result<'tcx>(
             match info {
                 TerminationInfo::Deadlock => {
                     show_all_threads = true;
+                    notes = blocked_thread_notes(&ecx.machine, ecx.active_thread());
+                }
+                TerminationInfo::Livelock { .. } => {
+                    notes = spinning_thread_notes(&ecx.machine, ecx.active_thread());
+                    for thread in ecx.machine.threads.enabled_program_threads() {
+                        if thread != ecx.active_thread() {
+                            other_threads.push((
+                                thread,
+                                "the evaluated program seems to be livelocked",
+                                "this thread is spinning here",
+                                true,
+                            ));
+                        }
+                    }
+                }
+                TerminationInfo::SpinStarvation { starved, .. } => {
+                    notes = spinning_thread_notes(&ecx.machine, ecx.active_thread());
+                    for &thread in starved {
+                        other_threads.push((
+                            thread,
+                            "this thread did not get to run while another thread was spinning",
+                            "this thread was starved here",
+                            false,
+                        ));
+                    }
                 }
                 _ => {}
             }
@@ -447,7 +617,7 @@ pub fn report_result<'tcx>(
         DiagLevel::Error,
         primary_msg,
         labels,
-        vec![],
+        notes,
         helps,
         &stacktrace,
         Some(ecx.active_thread()),
@@ -466,7 +636,7 @@ pub fn report_result<'tcx>(
                     DiagLevel::Error,
                     format!("the evaluated program deadlocked"),
                     vec![format!("this thread got stuck here")],
-                    vec![],
+                    blocked_thread_notes(&ecx.machine, thread),
                     vec![],
                     &stacktrace,
                     Some(thread),
@@ -476,6 +646,23 @@ pub fn report_result<'tcx>(
         }
     }
 
+    for (thread, msg, label, spinning) in other_threads {
+        let stacktrace =
+            Frame::generate_stacktrace_from_stack(ecx.machine.threads.thread_stack(thread));
+        let (stacktrace, was_pruned) = prune_stacktrace(stacktrace, &ecx.machine);
+        any_pruned |= was_pruned;
+        report_msg(
+            DiagLevel::Error,
+            msg.to_owned(),
+            vec![label.to_owned()],
+            if spinning { spinning_thread_notes(&ecx.machine, thread) } else { vec![] },
+            vec![],
+            &stacktrace,
+            Some(thread),
+            &ecx.machine,
+        )
+    }
+
     // Include a note like `std` does when we omit frames from a backtrace
     if any_pruned {
         ecx.tcx.dcx().note(
@@ -533,6 +720,66 @@ pub fn report_leaks<'tcx>(
     }
 }
 
+/// Reports the threads that the program did not join before the main thread exited, with where
+/// they were spawned and where they are now. Returns whether there were any.
+pub fn report_thread_leaks<'tcx>(
+    ecx: &InterpCx<'tcx, MiriMachine<'tcx>>,
+    level: ThreadLeakReport,
+) -> bool {
+    let threads = &ecx.machine.threads;
+    let source_map = ecx.tcx.sess.source_map();
+    let mut any_leaked = false;
+    let mut any_pruned = false;
+    for thread in threads.leaked_threads() {
+        any_leaked = true;
+        let name = threads.get_thread_display_name(thread);
+        let mut notes = vec![];
+        if let Some(spawn_backtrace) = threads.spawn_backtrace(thread) {
+            let (spawn_backtrace, pruned) =
+                prune_stacktrace(spawn_backtrace.to_vec(), &ecx.machine);
+            any_pruned |= pruned;
+            // Point at the frame that spawned the thread, skipping the runtime's spawn logic.
+            let spawner = spawn_backtrace
+                .iter()
+                .find(|frame| ecx.machine.is_local(frame.instance))
+                .or(spawn_backtrace.first());
+            if let Some(spawner) = spawner {
+                notes.push(note_span!(spawner.span.data(), "thread `{name}` was spawned here"));
+            }
+            notes.push(note!("backtrace of the spawn:"));
+            notes.extend(spawn_backtrace.iter().map(|frame| {
+                note!("{frame} at {}", source_map.span_to_embeddable_string(frame.span))
+            }));
+        }
+        let stacktrace = Frame::generate_stacktrace_from_stack(threads.thread_stack(thread));
+        let (stacktrace, pruned) = prune_stacktrace(stacktrace, &ecx.machine);
+        any_pruned |= pruned;
+        report_msg(
+            match level {
+                ThreadLeakReport::Warn => DiagLevel::Warning,
+                ThreadLeakReport::Error => DiagLevel::Error,
+            },
+            format!(
+                "thread `{name}` is still running, but the main thread exited without joining it"
+            ),
+            vec![format!("thread `{name}` is currently here")],
+            notes,
+            vec![note!(
+                "join the thread before returning from `main`, or detach it if it is meant to keep running"
+            )],
+            &stacktrace,
+            Some(thread),
+            &ecx.machine,
+        );
+    }
+    if any_pruned {
+        ecx.tcx.dcx().note(
+            "some details are omitted, run with `MIRIFLAGS=-Zmiri-backtrace=full` for a verbose backtrace",
+        );
+    }
+    any_leaked
+}
+
 /// Report an error or note (depending on the `error` argument) with the given stacktrace.
 /// Also emits a full stacktrace of the interpreter stack.
 /// We want to present a multi-line span message for some errors. Diagnostics do not support this
@@ -641,6 +888,20 @@ impl<'tcx> MiriMachine<'tcx> {
                 ),
             ExternTypeReborrow =>
                 ("reborrow of reference to `extern type`".to_string(), DiagLevel::Warning),
+            InjectedAllocFailure { .. } =>
+                ("allocation failure injected here".to_string(), DiagLevel::Note),
+            ExitHandlerWithLiveThreads { .. } =>
+                ("exit handlers run from here".to_string(), DiagLevel::Warning),
+            ScheduleReplayDiverged { .. } =>
+                ("the execution diverged from the trace here".to_string(), DiagLevel::Warning),
+            PotentialDeadlock { others, .. } =>
+                (
+                    format!(
+                        "this thread acquires lock (1) while holding lock ({})",
+                        others.len().strict_add(1)
+                    ),
+                    DiagLevel::Warning,
+                ),
             GenmcCompareExchangeWeak | GenmcCompareExchangeOrderingMismatch { .. } =>
                 ("GenMC might miss possible behaviors of this code".to_string(), DiagLevel::Warning),
             CreatedPointerTag(..)
@@ -683,6 +944,16 @@ impl<'tcx> MiriMachine<'tcx> {
                 format!("weak memory emulation: outdated value returned from load at {ptr}"),
             ExternTypeReborrow =>
                 format!("reborrow of a reference to `extern type` is not properly supported"),
+            InjectedAllocFailure { size, .. } =>
+                format!("injected failure for an allocation of {size} bytes"),
+            ExitHandlerWithLiveThreads { live_threads } =>
+                format!(
+                    "an exit handler is running while {live_threads} other thread(s) are still running"
+                ),
+            ScheduleReplayDiverged { index, .. } =>
+                format!("schedule replay diverged at decision {index}"),
+            PotentialDeadlock { .. } =>
+                format!("potential deadlock: threads acquire locks in inconsistent order"),
             GenmcCompareExchangeWeak =>
                 "GenMC currently does not model spurious failures of `compare_exchange_weak`. Miri with GenMC might miss bugs related to spurious failures."
                     .to_string(),
@@ -706,6 +977,43 @@ impl<'tcx> MiriMachine<'tcx> {
             ProgressReport { block_count } => {
                 vec![note!("so far, {block_count} basic blocks have been executed")]
             }
+            InjectedAllocFailure { reason, .. } => vec![note!("{reason}")],
+            ExitHandlerWithLiveThreads { .. } =>
+                vec![
+                    note!(
+                        "exit handlers run concurrently with all threads that have not been joined"
+                    ),
+                    note!(
+                        "those threads may still access the state that the exit handler tears down"
+                    ),
+                ],
+            ScheduleReplayDiverged { reason, .. } =>
+                vec![
+                    note!("{reason}"),
+                    note!("from here on, Miri makes its own scheduling decisions"),
+                ],
+            PotentialDeadlock { held_span, others } => {
+                let mut notes = vec![note_span!(
+                    *held_span,
+                    "lock ({}) was acquired by this thread here",
+                    others.len().strict_add(1)
+                )];
+                for acquisition in others {
+                    notes.push(note_span!(
+                        acquisition.span,
+                        "thread `{}` acquired lock ({}) here while holding lock ({})",
+                        acquisition.thread,
+                        acquisition.acquired,
+                        acquisition.held,
+                    ));
+                    notes.push(note!("backtrace of that acquisition:"));
+                    notes.extend(acquisition.backtrace.iter().map(|frame| note!("{frame}")));
+                }
+                notes.push(note!(
+                    "if these threads run at the same time, each of them can end up waiting for a lock that another one holds"
+                ));
+                notes
+            }
             _ => vec![],
         };
 
@@ -6,6 +6,7 @@ use std::panic::{self, AssertUnwindSafe};
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::task::Poll;
+use std::time::Duration;
 use std::{iter, thread};
 
 use rustc_abi::ExternAbi;
@@ -19,7 +20,7 @@ use rustc_target::spec::Os;
 
 use crate::concurrency::GenmcCtx;
 use crate::concurrency::thread::TlsAllocAction;
-use crate::diagnostics::report_leaks;
+use crate::diagnostics::{report_leaks, report_thread_leaks};
 use crate::shims::{global_ctor, tls};
 use crate::*;
 
@@ -54,6 +55,8 @@ pub struct MiriConfig {
     pub forwarded_env_vars: Vec<String>,
     /// Additional environment variables that should be set in the interpreted program.
     pub set_env_vars: FxHashMap<String, String>,
+    /// The name of the UTF-8 locale that `setlocale` and `newlocale` accept besides "C" and "POSIX".
+    pub utf8_locale: String,
     /// Command-line arguments passed to the interpreted program.
     pub args: Vec<String>,
     /// The seed to use when non-determinism or randomness are required (e.g. ptr-to-int cast, `getrandom()`).
@@ -87,6 +90,11 @@ pub struct MiriConfig {
     pub mute_stdout_stderr: bool,
     /// The probability of the active thread being preempted at the end of each basic block.
     pub preemption_rate: f64,
+    /// The wall clock time at which the program starts when isolation is enabled, as a duration
+    /// since the Unix epoch.
+    pub realtime_epoch: Duration,
+    /// Jumps of the virtual wall clock to inject when isolation is enabled.
+    pub realtime_jumps: Vec<RealtimeJump>,
     /// Report the current instruction being executed every N basic blocks.
     pub report_progress: Option<u32>,
     /// The location of the shared object files to load when calling external functions
@@ -97,6 +105,13 @@ pub struct MiriConfig {
     pub gc_interval: u32,
     /// The number of CPUs to be reported by miri.
     pub num_cpus: u32,
+    /// The initial resource limits of the program (`getrlimit`).
+    pub rlimits: ResourceLimits,
+    /// Which heap allocations to fail on purpose.
+    pub alloc_failures: AllocFailures,
+    /// The working directory and command-line arguments (up to `--`) Miri was started with, used
+    /// to run child processes in a nested interpreter.
+    pub invocation: Option<(PathBuf, Vec<String>)>,
     /// Requires Miri to emulate pages of a certain size.
     pub page_size: Option<u64>,
     /// Whether to collect a backtrace when each allocation is created, just in case it leaks.
@@ -107,6 +122,29 @@ pub struct MiriConfig {
     pub address_reuse_cross_thread_rate: f64,
     /// Round Robin scheduling with no preemption.
     pub fixed_scheduling: bool,
+    /// Use the PCT scheduling strategy instead of random scheduling with preemption.
+    pub pct: Option<PctConfig>,
+    /// Whether to record the scheduling decisions to a file, or to replay them.
+    pub schedule_trace: Option<ScheduleTraceConfig>,
+    /// Explore the interleavings of the program with DPOR instead of running it once.
+    pub dpor: Option<DporConfig>,
+    /// Whether to report potential deadlocks from inconsistent lock acquisition order.
+    pub deadlock_prediction: bool,
+    /// The file to write the wait-for graph to if the program deadlocks.
+    pub deadlock_report: Option<PathBuf>,
+    /// Whether to record the call stacks of memory accesses, to show them in data race reports.
+    pub race_backtraces: bool,
+    /// Whether unsynchronized atomic accesses of different sizes are allowed.
+    pub mixed_size_atomics: bool,
+    /// The number of spin steps without progress after which a livelock is reported, if
+    /// `-Zmiri-livelock-detection` is set.
+    pub livelock_detection: Option<u64>,
+    /// Whether a spinning thread always hands over to the next thread.
+    pub fair_scheduling: bool,
+    /// The file to write a timeline of the interpreted threads to, if any.
+    pub thread_timeline: Option<PathBuf>,
+    /// How to report threads that are still running when the main thread exits, if at all.
+    pub thread_leak_report: Option<ThreadLeakReport>,
     /// Always prefer the intrinsic fallback body over the native Miri implementation.
     pub force_intrinsic_fallback: bool,
     /// Whether floating-point operations can behave non-deterministically.
@@ -130,6 +168,7 @@ impl Default for MiriConfig {
             ignore_leaks: false,
             forwarded_env_vars: vec![],
             set_env_vars: FxHashMap::default(),
+            utf8_locale: "C.UTF-8".to_owned(),
             args: vec![],
             seed: None,
             tracked_pointer_tags: FxHashSet::default(),
@@ -145,16 +184,33 @@ impl Default for MiriConfig {
             provenance_mode: ProvenanceMode::Default,
             mute_stdout_stderr: false,
             preemption_rate: 0.01, // 1%
+            // 2025-01-01 00:00:00 UTC
+            realtime_epoch: Duration::from_secs(1_735_689_600),
+            realtime_jumps: vec![],
             report_progress: None,
             native_lib: vec![],
             native_lib_enable_tracing: false,
             gc_interval: 10_000,
             num_cpus: 1,
+            rlimits: ResourceLimits::default(),
+            alloc_failures: AllocFailures::default(),
+            invocation: None,
             page_size: None,
             collect_leak_backtraces: true,
             address_reuse_rate: 0.5,
             address_reuse_cross_thread_rate: 0.1,
             fixed_scheduling: false,
+            pct: None,
+            schedule_trace: None,
+            dpor: None,
+            deadlock_prediction: false,
+            deadlock_report: None,
+            race_backtraces: false,
+            mixed_size_atomics: false,
+            livelock_detection: None,
+            fair_scheduling: false,
+            thread_timeline: None,
+            thread_leak_report: None,
             force_intrinsic_fallback: false,
             float_nondet: true,
             float_rounding_error: FloatRoundingErrorMode::Random,
@@ -178,6 +234,8 @@ enum MainThreadState<'tcx> {
     },
     Running,
     TlsDtors(tls::TlsDtorsState<'tcx>),
+    /// Running the handlers registered with `atexit`, like returning from a C `main` does.
+    ExitHandlers,
     Yield {
         remaining: u32,
     },
@@ -206,23 +264,25 @@ impl<'tcx> MainThreadState<'tcx> {
             TlsDtors(state) =>
                 match state.on_stack_empty(this)? {
                     Poll::Pending => {} // just keep going
-                    Poll::Ready(()) => {
-                        if this.machine.data_race.as_genmc_ref().is_some() {
-                            // In GenMC mode, we don't yield at the end of the main thread.
-                            // Instead, the `GenmcCtx` will ensure that unfinished threads get a chance to run at this point.
-                            *self = Done;
+                    Poll::Ready(()) => *self = ExitHandlers,
+                },
+            ExitHandlers =>
+                if !this.run_exit_handlers_after_main()? {
+                    if this.machine.data_race.as_genmc_ref().is_some() {
+                        // In GenMC mode, we don't yield at the end of the main thread.
+                        // Instead, the `GenmcCtx` will ensure that unfinished threads get a chance to run at this point.
+                        *self = Done;
+                    } else {
+                        // Give background threads a chance to finish by yielding the main thread a
+                        // couple of times -- but only if we would also preempt threads randomly.
+                        if this.machine.preemption_rate > 0.0 {
+                            // There is a non-zero chance they will yield back to us often enough to
+                            // make Miri terminate eventually.
+                            *self = Yield { remaining: MAIN_THREAD_YIELDS_AT_SHUTDOWN };
                         } else {
-                            // Give background threads a chance to finish by yielding the main thread a
-                            // couple of times -- but only if we would also preempt threads randomly.
-                            if this.machine.preemption_rate > 0.0 {
-                                // There is a non-zero chance they will yield back to us often enough to
-                                // make Miri terminate eventually.
-                                *self = Yield { remaining: MAIN_THREAD_YIELDS_AT_SHUTDOWN };
-                            } else {
-                                // The other threads did not get preempted, so no need to yield back to
-                                // them.
-                                *self = Done;
-                            }
+                            // The other threads did not get preempted, so no need to yield back to
+                            // them.
+                            *self = Done;
                         }
                     }
                 },
@@ -278,6 +338,7 @@ pub fn create_ecx<'tcx>(
     entry_type: MiriEntryFnType,
     config: &MiriConfig,
     genmc_ctx: Option<Rc<GenmcCtx>>,
+    dpor: Option<Rc<DporExplorer>>,
 ) -> InterpResult<'tcx, InterpCx<'tcx, MiriMachine<'tcx>>> {
     let typing_env = ty::TypingEnv::fully_monomorphized();
     let layout_cx = LayoutCx::new(tcx, typing_env);
@@ -285,7 +346,7 @@ pub fn create_ecx<'tcx>(
         tcx,
         rustc_span::DUMMY_SP,
         typing_env,
-        MiriMachine::new(config, layout_cx, genmc_ctx),
+        MiriMachine::new(config, layout_cx, genmc_ctx, dpor),
     );
 
     // Make sure we have MIR. We check MIR for some stable monomorphic function in libcore.
@@ -460,11 +521,14 @@ pub fn eval_entry<'tcx>(
     entry_type: MiriEntryFnType,
     config: &MiriConfig,
     genmc_ctx: Option<Rc<GenmcCtx>>,
+    dpor: Option<Rc<DporExplorer>>,
 ) -> Result<(), NonZeroI32> {
     // Copy setting before we move `config`.
     let ignore_leaks = config.ignore_leaks;
+    let deadlock_report = config.deadlock_report.clone();
 
-    let mut ecx = match create_ecx(tcx, entry_id, entry_type, config, genmc_ctx).report_err() {
+    let mut ecx = match create_ecx(tcx, entry_id, entry_type, config, genmc_ctx, dpor).report_err()
+    {
         Ok(v) => v,
         Err(err) => {
             let (kind, backtrace) = err.into_parts();
@@ -484,6 +548,23 @@ pub fn eval_entry<'tcx>(
     // indicate an error.
     let Err(res) = res.report_err();
 
+    // Write the schedule trace first: it matters most when the execution failed.
+    if let Some(trace) = &ecx.machine.schedule_trace
+        && let Err(err) = trace.write()
+    {
+        tcx.dcx().warn(err);
+    }
+    if let Err(err) = ecx.machine.write_thread_timeline() {
+        tcx.dcx().warn(err);
+    }
+    if let Some(path) = &deadlock_report
+        && let InterpErrorKind::MachineStop(info) = res.kind()
+        && let Some(TerminationInfo::Deadlock) = info.downcast_ref::<TerminationInfo>()
+        && let Err(err) = ecx.machine.write_deadlock_report(path)
+    {
+        tcx.dcx().warn(err);
+    }
+
     // Error reporting: if we survive all checks, we return the exit code the program gave us.
     'miri_error: {
         // Show diagnostic, if any.
@@ -492,16 +573,24 @@ pub fn eval_entry<'tcx>(
         };
 
         // If we get here there was no fatal error -- yet.
-        // Possibly check for memory leaks.
-        if leak_check && !ignore_leaks {
-            // Check for thread leaks.
-            if !ecx.have_all_terminated() {
+        // Check for thread leaks.
+        if leak_check {
+            if let Some(level) = ecx.machine.threads.thread_leak_report() {
+                // This lists the threads that were neither joined nor detached, and replaces the
+                // check below, so detached threads may keep running.
+                if report_thread_leaks(&ecx, level) && level == ThreadLeakReport::Error {
+                    break 'miri_error;
+                }
+            } else if !ignore_leaks && !ecx.have_all_terminated() {
                 tcx.dcx()
                     .err("the main thread terminated without waiting for all remaining threads");
                 tcx.dcx().note("set `MIRIFLAGS=-Zmiri-ignore-leaks` to disable this check");
                 break 'miri_error;
             }
-            // Check for memory leaks.
+        }
+        // Possibly check for memory leaks. Threads that are still running may still use their
+        // memory, so it is not leaked.
+        if leak_check && !ignore_leaks && ecx.have_all_terminated() {
             info!("Additional static roots: {:?}", ecx.machine.static_roots);
             let leaks = ecx.take_leaked_allocations(|ecx| &ecx.machine.static_roots);
             if !leaks.is_empty() {
@@ -121,17 +121,30 @@ pub use crate::borrow_tracker::tree_borrows::{EvalContextExt as _, Tree};
 pub use crate::borrow_tracker::{
     BorTag, BorrowTrackerMethod, EvalContextExt as _, TreeBorrowsParams,
 };
-pub use crate::clock::{Instant, MonotonicClock};
+pub use crate::clock::{
+    Instant, MonotonicClock, RealtimeClock, RealtimeJump, cpu_time_for_basic_blocks,
+};
 pub use crate::concurrency::cpu_affinity::MAX_CPUS;
 pub use crate::concurrency::data_race::{
     AtomicFenceOrd, AtomicReadOrd, AtomicRwOrd, AtomicWriteOrd, EvalContextExt as _,
 };
+pub use crate::concurrency::dpor::{DporConfig, DporExplorer, EvalContextExt as _, run_dpor_mode};
 pub use crate::concurrency::init_once::{EvalContextExt as _, InitOnceRef};
-pub use crate::concurrency::sync::{CondvarRef, EvalContextExt as _, MutexRef, RwLockRef};
+pub use crate::concurrency::livelock::{EvalContextExt as _, LivelockDetector};
+pub use crate::concurrency::lock_order::{CycleAcquisition, EvalContextExt as _, LockOrder};
+pub use crate::concurrency::pct::{PctConfig, PctScheduler};
+pub use crate::concurrency::race_backtraces::StackInterner;
+pub use crate::concurrency::schedule_trace::{
+    ScheduleDecision, ScheduleTrace, ScheduleTraceConfig, parse_schedule_trace,
+};
+pub use crate::concurrency::sync::{
+    CondvarRef, EvalContextExt as _, LockHolder, MutexRef, RwLockRef, WaitObject,
+};
 pub use crate::concurrency::thread::{
     BlockReason, DynUnblockCallback, EvalContextExt as _, StackEmptyCallback, ThreadId,
-    ThreadManager, TimeoutAnchor, TimeoutClock, UnblockKind,
+    ThreadLeakReport, ThreadManager, TimeoutAnchor, TimeoutClock, UnblockKind,
 };
+pub use crate::concurrency::timeline::{EvalContextExt as _, ThreadTimeline};
 pub use crate::concurrency::{GenmcConfig, GenmcCtx, run_genmc_mode};
 pub use crate::data_structures::dedup_range_map::DedupRangeMap;
 pub use crate::data_structures::mono_hash_map::MonoHashMap;
@@ -149,8 +162,9 @@ pub use crate::machine::{
 };
 pub use crate::operator::EvalContextExt as _;
 pub use crate::provenance_gc::{EvalContextExt as _, LiveAllocs, VisitProvenance, VisitWith};
-pub use crate::shims::EmulateItemResult;
+pub use crate::shims::calendar::EvalContextExt as _;
 pub use crate::shims::env::{EnvVars, EvalContextExt as _};
+pub use crate::shims::exit::EvalContextExt as _;
 pub use crate::shims::foreign_items::{DynSym, EvalContextExt as _};
 pub use crate::shims::io_error::{EvalContextExt as _, IoError, LibcError};
 pub use crate::shims::os_str::EvalContextExt as _;
@@ -159,6 +173,7 @@ pub use crate::shims::sig::EvalContextExt as _;
 pub use crate::shims::time::EvalContextExt as _;
 pub use crate::shims::tls::TlsData;
 pub use crate::shims::unwind::{CatchUnwindData, EvalContextExt as _};
+pub use crate::shims::{AllocFailures, EmulateItemResult, ResourceLimit, ResourceLimits};
 
 /// Insert rustc arguments at the beginning of the argument list that Miri wants to be
 /// set per default, for maximal validation power.
@@ -20,12 +20,12 @@ use rustc_data_structures::static_assert_size;
 use rustc_hir::attrs::InlineAttr;
 use rustc_log::tracing;
 use rustc_middle::middle::codegen_fn_attrs::TargetFeatureKind;
-use rustc_middle::mir;
 use rustc_middle::query::TyCtxtAt;
 use rustc_middle::ty::layout::{
     HasTyCtxt, HasTypingEnv, LayoutCx, LayoutError, LayoutOf, TyAndLayout,
 };
 use rustc_middle::ty::{self, Instance, Ty, TyCtxt};
+use rustc_middle::{mir, throw_exhaust};
 use rustc_session::config::InliningThreshold;
 use rustc_span::def_id::{CrateNum, DefId};
 use rustc_span::{Span, SpanData, Symbol};
@@ -36,6 +36,7 @@ use rustc_target::spec::{Arch, Os};
 use crate::alloc_addresses::EvalContextExt;
 use crate::concurrency::cpu_affinity::{self, CpuAffinityMask};
 use crate::concurrency::data_race::{self, NaReadType, NaWriteType};
+use crate::concurrency::lock_order::StdMutexAcquisition;
 use crate::concurrency::sync::SyncObj;
 use crate::concurrency::{
     AllocDataRaceHandler, GenmcCtx, GenmcEvalContextExt as _, GlobalDataRaceHandler, weak_memory,
@@ -148,13 +149,25 @@ pub struct FrameExtra<'tcx> {
 
     /// Data race detector per-frame data.
     pub data_race: Option<data_race::FrameState>,
+
+    /// If this is Some(), then this frame was pushed by a shim that calls back into the program,
+    /// like `qsort` calling its comparison function. When this frame returns normally, the caller
+    /// does not jump to a return block; instead, this continuation is invoked, which either calls
+    /// the program again or makes the shim return.
+    pub on_return: Option<DynMachineCallback<'tcx, ()>>,
 }
 
 impl<'tcx> std::fmt::Debug for FrameExtra<'tcx> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        // Omitting `timing`, it does not support `Debug`.
-        let FrameExtra { borrow_tracker, catch_unwind, timing: _, user_relevance, data_race } =
-            self;
+        // Omitting `timing` and `on_return`, they do not support `Debug`.
+        let FrameExtra {
+            borrow_tracker,
+            catch_unwind,
+            timing: _,
+            user_relevance,
+            data_race,
+            on_return: _,
+        } = self;
         f.debug_struct("FrameData")
             .field("borrow_tracker", borrow_tracker)
             .field("catch_unwind", catch_unwind)
@@ -166,11 +179,20 @@ impl<'tcx> std::fmt::Debug for FrameExtra<'tcx> {
 
 impl VisitProvenance for FrameExtra<'_> {
     fn visit_provenance(&self, visit: &mut VisitWith<'_>) {
-        let FrameExtra { catch_unwind, borrow_tracker, timing: _, user_relevance: _, data_race: _ } =
-            self;
+        let FrameExtra {
+            catch_unwind,
+            borrow_tracker,
+            timing: _,
+            user_relevance: _,
+            data_race: _,
+            on_return,
+        } = self;
 
         catch_unwind.visit_provenance(visit);
         borrow_tracker.visit_provenance(visit);
+        if let Some(on_return) = on_return {
+            on_return.visit_provenance(visit);
+        }
     }
 }
 
@@ -225,6 +247,16 @@ impl MayLeak for MiriMemoryKind {
 }
 
 impl MiriMemoryKind {
+    /// Whether this is program heap memory, i.e., counts towards `RLIMIT_AS` and `RLIMIT_DATA`.
+    /// `miri_alloc` memory is Miri's own and does not count.
+    pub fn is_heap(self) -> bool {
+        use self::MiriMemoryKind::*;
+        match self {
+            Rust | C | WinHeap | WinLocal | Mmap => true,
+            Miri | Machine | Global | ExternStatic | Tls | Runtime => false,
+        }
+    }
+
     /// Whether we have a useful allocation span for an allocation of this kind.
     fn should_save_allocation_span(self) -> bool {
         use self::MiriMemoryKind::*;
@@ -524,14 +556,42 @@ pub struct MiriMachine<'tcx> {
 
     /// The table of file descriptors.
     pub(crate) fds: shims::FdTable,
+    /// The resource limits (`getrlimit`), some of which we enforce.
+    pub(crate) rlimits: shims::ResourceLimits,
+    /// The total size of all live heap allocations, for enforcing `RLIMIT_AS` and `RLIMIT_DATA`.
+    pub(crate) heap_bytes: Cell<u64>,
+    /// Which heap allocations to fail on purpose.
+    pub(crate) alloc_failures: shims::AllocFailures,
+    /// The number of heap allocations requested so far, for `-Zmiri-alloc-fail-nth`.
+    pub(crate) alloc_count: Cell<u64>,
+    /// The statistics epoch reported and advanced by `mallctl("epoch", ...)`.
+    pub(crate) malloc_stats_epoch: u64,
+    /// The handlers registered with `atexit` and friends.
+    pub(crate) exit_handlers: shims::ExitHandlers,
+    /// The global locale and the locale objects.
+    pub(crate) locale: shims::LocaleState<'tcx>,
     /// The table of directory descriptors.
     pub(crate) dirs: shims::DirTable,
+    /// The child processes started with `posix_spawn`.
+    pub(crate) processes: shims::ProcessTable,
+    /// The advisory locks held on files.
+    pub(crate) file_locks: shims::FileLocks,
+    /// The modes of the Windows console.
+    pub(crate) console: shims::WindowsConsole,
+    /// The Windows thread pool and the queued APCs.
+    pub(crate) threadpool: shims::WindowsThreadPool,
+    /// The loopback network that sockets communicate over.
+    pub(crate) network: shims::LoopbackNetwork,
+    /// How often `WSAStartup` was called without a matching `WSACleanup`.
+    pub(crate) winsock_startups: u32,
 
     /// The list of all EpollEventInterest.
     pub(crate) epoll_interests: shims::EpollInterestTable,
 
     /// This machine's monotone clock.
     pub(crate) monotonic_clock: MonotonicClock,
+    /// This machine's wall clock.
+    pub(crate) realtime_clock: RealtimeClock,
 
     /// The set of threads.
     pub(crate) threads: ThreadManager<'tcx>,
@@ -571,6 +631,34 @@ pub struct MiriMachine<'tcx> {
     /// Needs to be queried by ptr_to_int, hence needs interior mutability.
     pub(crate) rng: RefCell<StdRng>,
 
+    /// The recorded or replayed scheduling decisions, if `-Zmiri-record-schedule` or
+    /// `-Zmiri-replay-schedule` is set.
+    pub(crate) schedule_trace: Option<ScheduleTrace>,
+
+    /// The DPOR explorer that picks the interleaving of this execution, if `-Zmiri-dpor` is set.
+    /// It persists across executions.
+    pub(crate) dpor: Option<Rc<DporExplorer>>,
+
+    /// The lock-order graph used to predict deadlocks, if `-Zmiri-deadlock-prediction` is set and
+    /// we are not in GenMC mode.
+    pub(crate) lock_order: Option<RefCell<LockOrder<'tcx>>>,
+    /// The id that the next mutex or read-write lock to be acquired gets in the lock-order graph
+    /// and the thread timeline.
+    pub(crate) next_sync_lock_id: Cell<u64>,
+    /// The `Mutex` of the standard library that each thread is acquiring. Only tracked if
+    /// `lock_order` or `thread_timeline` is set.
+    pub(crate) std_mutex_acquiring: RefCell<FxHashMap<ThreadId, StdMutexAcquisition>>,
+
+    /// The call stacks of memory accesses, if `-Zmiri-race-backtraces` is set.
+    pub(crate) race_stacks: Option<RefCell<StackInterner<'tcx>>>,
+
+    /// The timeline of the interpreted threads, if `-Zmiri-thread-timeline` is set.
+    pub(crate) thread_timeline: Option<ThreadTimeline>,
+
+    /// The spin steps of each thread, if `-Zmiri-livelock-detection` or `-Zmiri-fair-scheduling` is
+    /// set.
+    pub(crate) livelock: Option<RefCell<LivelockDetector>>,
+
     /// The allocator used for the machine's `AllocBytes` in native-libs mode.
     pub(crate) allocator: Option<Rc<RefCell<crate::alloc::isolated_alloc::IsolatedAlloc>>>,
 
@@ -663,6 +751,7 @@ impl<'tcx> MiriMachine<'tcx> {
         config: &MiriConfig,
         layout_cx: LayoutCx<'tcx>,
         genmc_ctx: Option<Rc<GenmcCtx>>,
+        dpor: Option<Rc<DporExplorer>>,
     ) -> Self {
         let tcx = layout_cx.tcx();
         let user_relevant_crates = Self::get_user_relevant_crates(tcx, config);
@@ -745,8 +834,21 @@ impl<'tcx> MiriMachine<'tcx> {
             isolated_op: config.isolated_op,
             validation: config.validation,
             fds: shims::FdTable::init(config.mute_stdout_stderr),
+            rlimits: config.rlimits.clone(),
+            heap_bytes: Cell::new(0),
+            alloc_failures: config.alloc_failures.clone(),
+            alloc_count: Cell::new(0),
+            malloc_stats_epoch: 0,
+            exit_handlers: Default::default(),
+            locale: shims::LocaleState::new(config.utf8_locale.clone()),
             epoll_interests: shims::EpollInterestTable::new(),
             dirs: Default::default(),
+            processes: shims::ProcessTable::new(config.invocation.clone()),
+            file_locks: Default::default(),
+            console: Default::default(),
+            threadpool: Default::default(),
+            network: Default::default(),
+            winsock_startups: 0,
             layouts,
             threads,
             thread_cpu_affinity,
@@ -758,6 +860,22 @@ impl<'tcx> MiriMachine<'tcx> {
             user_relevant_crates,
             extern_statics: FxHashMap::default(),
             rng: RefCell::new(rng),
+            schedule_trace: config.schedule_trace.as_ref().map(ScheduleTrace::new),
+            dpor,
+            lock_order: (config.deadlock_prediction && config.genmc_config.is_none())
+                .then(|| RefCell::new(LockOrder::default())),
+            next_sync_lock_id: Cell::new(0),
+            std_mutex_acquiring: RefCell::new(FxHashMap::default()),
+            race_stacks: (config.race_backtraces
+                && config.data_race_detector
+                && config.genmc_config.is_none())
+            .then(|| RefCell::new(StackInterner::default())),
+            thread_timeline: config.thread_timeline.clone().map(ThreadTimeline::new),
+            // Fair scheduling needs to know when threads spin, even if livelocks are not reported.
+            livelock: config
+                .livelock_detection
+                .or(config.fair_scheduling.then_some(u64::MAX))
+                .map(|steps| RefCell::new(LivelockDetector::new(steps))),
             allocator: (!config.native_lib.is_empty())
                 .then(|| Rc::new(RefCell::new(crate::alloc::isolated_alloc::IsolatedAlloc::new()))),
             tracked_alloc_ids: config.tracked_alloc_ids.clone(),
@@ -768,6 +886,11 @@ impl<'tcx> MiriMachine<'tcx> {
             report_progress: config.report_progress,
             basic_block_count: 0,
             monotonic_clock: MonotonicClock::new(config.isolated_op == IsolatedOp::Allow),
+            realtime_clock: RealtimeClock::new(
+                config.isolated_op == IsolatedOp::Allow,
+                config.realtime_epoch,
+                config.realtime_jumps.clone(),
+            ),
             #[cfg(all(unix, feature = "native-lib"))]
             native_lib: config.native_lib.iter().map(|lib_file_path| {
                 let host_triple = rustc_session::config::host_tuple();
@@ -875,6 +998,7 @@ impl<'tcx> MiriMachine<'tcx> {
         on_main_stack_empty: StackEmptyCallback<'tcx>,
     ) -> InterpResult<'tcx> {
         EnvVars::init(ecx, config)?;
+        shims::LocaleState::init(ecx)?;
         MiriMachine::init_extern_statics(ecx)?;
         ThreadManager::init(ecx, on_main_stack_empty);
         interp_ok(())
@@ -934,6 +1058,9 @@ impl<'tcx> MiriMachine<'tcx> {
         if ecx.machine.tracked_alloc_ids.contains(&id) {
             ecx.emit_diagnostic(NonHaltingDiagnostic::TrackingAlloc(id, size, align));
         }
+        if matches!(kind, MemoryKind::Machine(kind) if kind.is_heap()) {
+            ecx.machine.heap_bytes.update(|bytes| bytes + size.bytes());
+        }
 
         let borrow_tracker = ecx
             .machine
@@ -1000,15 +1127,29 @@ impl VisitProvenance for MiriMachine<'_> {
             cmd_line,
             extern_statics,
             dirs,
+            processes,
+            file_locks: _,
+            console: _,
+            threadpool,
+            network: _,
+            winsock_startups: _,
             borrow_tracker,
             data_race,
             alloc_addresses,
             fds,
+            rlimits: _,
+            heap_bytes: _,
+            alloc_failures: _,
+            alloc_count: _,
+            malloc_stats_epoch: _,
+            exit_handlers,
+            locale,
             epoll_interests:_,
             tcx: _,
             isolated_op: _,
             validation: _,
             monotonic_clock: _,
+            realtime_clock: _,
             layouts: _,
             static_roots: _,
             profiler: _,
@@ -1017,6 +1158,14 @@ impl VisitProvenance for MiriMachine<'_> {
             backtrace_style: _,
             user_relevant_crates: _,
             rng: _,
+            schedule_trace: _,
+            dpor: _,
+            lock_order: _,
+            next_sync_lock_id: _,
+            std_mutex_acquiring: _,
+            race_stacks: _,
+            thread_timeline: _,
+            livelock: _,
             allocator: _,
             tracked_alloc_ids: _,
             track_alloc_accesses: _,
@@ -1051,6 +1200,8 @@ impl VisitProvenance for MiriMachine<'_> {
         tls.visit_provenance(visit);
         env_vars.visit_provenance(visit);
         dirs.visit_provenance(visit);
+        processes.visit_provenance(visit);
+        threadpool.visit_provenance(visit);
         fds.visit_provenance(visit);
         data_race.visit_provenance(visit);
         borrow_tracker.visit_provenance(visit);
@@ -1062,6 +1213,8 @@ impl VisitProvenance for MiriMachine<'_> {
         for ptr in extern_statics.values() {
             ptr.visit_provenance(visit);
         }
+        exit_handlers.visit_provenance(visit);
+        locale.visit_provenance(visit);
     }
 }
 
@@ -1240,6 +1393,10 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
             return interp_ok(None);
         }
 
+        if ecx.machine.lock_order.is_some() || ecx.machine.thread_timeline.is_some() {
+            ecx.lock_order_std_call(instance, args)?;
+        }
+
         // Otherwise, load the MIR.
         let _trace = enter_trace_span!("load_mir");
         interp_ok(Some((ecx.load_mir(instance.def, None)?, instance)))
@@ -1634,6 +1791,9 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
         {
             *deallocated_at = Some(machine.current_user_relevant_span());
         }
+        if matches!(kind, MemoryKind::Machine(kind) if kind.is_heap()) {
+            machine.heap_bytes.update(|bytes| bytes - size.bytes());
+        }
         machine.free_alloc_id(alloc_id, size, align, kind);
         interp_ok(())
     }
@@ -1716,6 +1876,7 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
                 .data_race
                 .as_vclocks_ref()
                 .map(|_| data_race::FrameState::default()),
+            on_return: None,
         };
 
         interp_ok(frame.with_extra(extra))
@@ -1735,6 +1896,7 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
 
     fn before_terminator(ecx: &mut InterpCx<'tcx, Self>) -> InterpResult<'tcx> {
         ecx.machine.basic_block_count += 1u64; // a u64 that is only incremented by 1 will "never" overflow
+        ecx.machine.threads.active_thread_mut().basic_block_count += 1u64;
         ecx.machine.since_gc += 1;
         // Possibly report our progress. This will point at the terminator we are about to execute.
         if let Some(report_progress) = ecx.machine.report_progress {
@@ -1766,6 +1928,14 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
 
     #[inline(always)]
     fn after_stack_push(ecx: &mut InterpCx<'tcx, Self>) -> InterpResult<'tcx> {
+        // Enforce `RLIMIT_STACK` for the main thread. Most programs never set it, so check that
+        // first to keep frame pushes cheap.
+        if let Some(max_frames) = ecx.machine.rlimits.max_stack_frames()
+            && ecx.active_thread() == ThreadId::MAIN_THREAD
+            && ecx.active_thread_stack().len().to_u64() > max_frames
+        {
+            throw_exhaust!(StackFrameLimitReached);
+        }
         if ecx.frame().extra.user_relevance >= ecx.active_thread_ref().current_user_relevance() {
             // We just pushed a frame that's at least as relevant as the so-far most relevant frame.
             // That means we are now the most relevant frame.
@@ -1808,15 +1978,28 @@ impl<'tcx> Machine<'tcx> for MiriMachine<'tcx> {
         frame: Frame<'tcx, Provenance, FrameExtra<'tcx>>,
         unwinding: bool,
     ) -> InterpResult<'tcx, ReturnAction> {
-        let res = {
+        let (res, on_return) = {
             // Move `frame` into a sub-scope so we control when it will be dropped.
             let mut frame = frame;
             let timing = frame.extra.timing.take();
+            let on_return = frame.extra.on_return.take();
             let res = ecx.handle_stack_pop_unwind(frame.extra, unwinding);
             if let Some(profiler) = ecx.machine.profiler.as_ref() {
                 profiler.finish_recording_interval_event(timing.unwrap());
             }
-            res
+            (res, on_return)
+        };
+        if ecx.machine.lock_order.is_some() || ecx.machine.thread_timeline.is_some() {
+            ecx.lock_order_std_return(unwinding);
+        }
+        let res = match on_return {
+            // The shim that pushed this frame continues, and decides where to go from here.
+            Some(on_return) if !unwinding => {
+                res?;
+                on_return.call(ecx, ())?;
+                interp_ok(ReturnAction::NoJump)
+            }
+            _ => res,
         };
         // Needs to be done after dropping frame to show up on the right nesting level.
         // (Cc https://github.com/rust-lang/miri/issues/2266)
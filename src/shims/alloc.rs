@@ -1,3 +1,4 @@
+use rand::Rng;
 use rustc_abi::{Align, AlignFromBytesError, CanonAbi, Size};
 use rustc_ast::expand::allocator::SpecialAllocatorMethod;
 use rustc_middle::ty::Ty;
@@ -7,6 +8,20 @@ use rustc_target::spec::{Arch, Os};
 
 use crate::*;
 
+/// Makes heap allocations fail on purpose, to test out-of-memory handling.
+#[derive(Clone, Debug, Default)]
+pub struct AllocFailures {
+    /// Fail the allocation with this index (counting from 1, including allocations made by the
+    /// runtime before `main`).
+    pub nth: Option<u64>,
+    /// The probability with which each allocation fails.
+    pub rate: f64,
+    /// Fail all allocations larger than this many bytes.
+    pub above: Option<u64>,
+    /// Fail all allocations that would make the live heap larger than this many bytes.
+    pub heap_limit: Option<u64>,
+}
+
 impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
 pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     /// Returns the alignment that `malloc` would guarantee for requests of the given size.
@@ -112,6 +127,66 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         interp_ok(())
     }
 
+    /// Checks whether a heap allocation of `size` bytes may succeed: it has to stay within
+    /// `RLIMIT_AS` and `RLIMIT_DATA`, and must not be picked for failure by `-Zmiri-alloc-fail-*`
+    /// or `-Zmiri-heap-limit`. If the allocation replaces `old_ptr` (as in `realloc`), the old
+    /// allocation does not count.
+    ///
+    /// This counts the allocation attempt for `-Zmiri-alloc-fail-nth` and may emit a diagnostic
+    /// when it picks the allocation for failure, so it must be called exactly once per attempt.
+    fn check_heap_limit_for_alloc(
+        &self,
+        size: u64,
+        old_ptr: Option<Pointer>,
+    ) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_ref();
+        let failures = &this.machine.alloc_failures;
+        let max_heap_bytes = this.machine.rlimits.max_heap_bytes();
+        // Only look at `old_ptr` if we need to, so that invalid pointers get reported by the
+        // allocation function itself.
+        let new_heap_bytes = if max_heap_bytes.is_some() || failures.heap_limit.is_some() {
+            let mut heap_bytes = this.machine.heap_bytes.get();
+            if let Some(old_ptr) = old_ptr {
+                let (alloc_id, _offset, _extra) = this.ptr_get_alloc_id(old_ptr, 0)?;
+                heap_bytes = heap_bytes.saturating_sub(this.get_alloc_info(alloc_id).size.bytes());
+            }
+            heap_bytes.checked_add(size)
+        } else {
+            Some(0)
+        };
+        if let Some(max_heap_bytes) = max_heap_bytes
+            && new_heap_bytes.is_none_or(|total| total > max_heap_bytes)
+        {
+            return interp_ok(false);
+        }
+
+        let count = this.machine.alloc_count.get().strict_add(1);
+        this.machine.alloc_count.set(count);
+        let reason = if failures.nth == Some(count) {
+            format!("`-Zmiri-alloc-fail-nth={count}` makes allocation number {count} fail")
+        } else if let Some(above) = failures.above
+            && size > above
+        {
+            format!(
+                "`-Zmiri-alloc-fail-above={above}` makes all allocations larger than {above} bytes fail"
+            )
+        } else if let Some(heap_limit) = failures.heap_limit
+            && new_heap_bytes.is_none_or(|total| total > heap_limit)
+        {
+            format!(
+                "`-Zmiri-heap-limit={heap_limit}` caps the total size of live heap allocations, \
+                 which currently is {} bytes",
+                this.machine.heap_bytes.get()
+            )
+        } else if failures.rate > 0.0 && this.machine.rng.borrow_mut().random_bool(failures.rate) {
+            format!("`-Zmiri-alloc-fail-rate={}` makes allocations fail at random", failures.rate)
+        } else {
+            return interp_ok(true);
+        };
+        this.emit_diagnostic(NonHaltingDiagnostic::InjectedAllocFailure { size, reason });
+        interp_ok(false)
+    }
+
     fn rust_special_allocator_method(
         &mut self,
         method: SpecialAllocatorMethod,
@@ -130,6 +205,9 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let align = this.read_target_usize(align)?;
 
                 this.check_rust_alloc_request(size, align)?;
+                if !this.check_heap_limit_for_alloc(size, None)? {
+                    return this.write_null(dest);
+                }
 
                 let ptr = this.allocate_ptr(
                     Size::from_bytes(size),
@@ -168,6 +246,9 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 // No need to check old_size; we anyway check that they match the allocation.
 
                 this.check_rust_alloc_request(new_size, align)?;
+                if !this.check_heap_limit_for_alloc(new_size, Some(ptr))? {
+                    return this.write_null(dest);
+                }
 
                 let align = Align::from_bytes(align).unwrap();
                 let new_ptr = this.reallocate_ptr(
@@ -185,6 +266,12 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
     fn malloc(&mut self, size: u64, init: AllocInit) -> InterpResult<'tcx, Pointer> {
         let this = self.eval_context_mut();
+        if !this.check_heap_limit_for_alloc(size, None)? {
+            if this.target_os_is_unix() {
+                this.set_last_error(LibcError("ENOMEM"))?;
+            }
+            return interp_ok(Pointer::null());
+        }
         let align = this.malloc_align(size);
         let ptr =
             this.allocate_ptr(Size::from_bytes(size), align, MiriMemoryKind::C.into(), init)?;
@@ -206,6 +293,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         // But failure to adhere to this is not UB, it's an error condition.
         if !align.is_power_of_two() || align < this.pointer_size().bytes() {
             interp_ok(this.eval_libc("EINVAL"))
+        } else if !this.check_heap_limit_for_alloc(size, None)? {
+            interp_ok(this.eval_libc("ENOMEM"))
         } else {
             let ptr = this.allocate_ptr(
                 Size::from_bytes(size),
@@ -237,6 +326,11 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 // C, in their infinite wisdom, made this UB.
                 // <https://www.open-std.org/jtc1/sc22/wg14/www/docs/n2464.pdf>
                 throw_ub_format!("`realloc` with a size of zero");
+            } else if !this.check_heap_limit_for_alloc(new_size, Some(old_ptr))? {
+                if this.target_os_is_unix() {
+                    this.set_last_error(LibcError("ENOMEM"))?;
+                }
+                interp_ok(Pointer::null())
             } else {
                 let new_ptr = this.reallocate_ptr(
                     old_ptr,
@@ -280,6 +374,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         // FreeBSD: https://man.freebsd.org/cgi/man.cgi?query=aligned_alloc&apropos=0&sektion=3&manpath=FreeBSD+9-current&format=html
         match size.checked_rem(align) {
             Some(0) if align.is_power_of_two() => {
+                if !this.check_heap_limit_for_alloc(size, None)? {
+                    this.set_last_error(LibcError("ENOMEM"))?;
+                    return interp_ok(Pointer::null());
+                }
                 let align = align.max(this.malloc_align(size).bytes());
                 let ptr = this.allocate_ptr(
                     Size::from_bytes(size),
@@ -292,4 +390,158 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             _ => interp_ok(Pointer::null()),
         }
     }
+
+    /// Computes the alignment of a `mallocx`-style allocation of the given size. `flags` may
+    /// request a larger alignment via `MALLOCX_LG_ALIGN`; `None` means that alignment is not
+    /// supported.
+    fn mallocx_align(&self, size: u64, flags: i32) -> Option<Align> {
+        let this = self.eval_context_ref();
+        // `MALLOCX_LG_ALIGN(la)` is just `la` in the low 6 bits.
+        let lg_align = u32::try_from(flags & 0x3f).unwrap();
+        let align = Align::from_bytes(1u64.strict_shl(lg_align)).ok()?;
+        Some(align.max(this.malloc_align(size)))
+    }
+
+    /// Returns the size of the live `malloc` allocation that `ptr` points to the start of, as
+    /// reported by `malloc_usable_size`. Miri never rounds up allocation sizes, so this is
+    /// always exactly the requested size.
+    fn malloc_usable_size_of(&self, ptr: Pointer, name: &str) -> InterpResult<'tcx, u64> {
+        let this = self.eval_context_ref();
+        let (alloc_id, offset, _extra) = this.ptr_get_alloc_id(ptr, 0)?;
+        let is_malloc = this
+            .memory
+            .alloc_map()
+            .get(alloc_id)
+            .is_some_and(|(kind, _)| *kind == MiriMemoryKind::C.into());
+        if offset != Size::ZERO || !is_malloc {
+            throw_ub_format!(
+                "`{name}` called on a pointer that does not point to the start of a live `malloc` allocation"
+            );
+        }
+        interp_ok(this.get_alloc_info(alloc_id).size.bytes())
+    }
+
+    /// The total size of all live `malloc` allocations.
+    fn malloc_live_bytes(&self) -> u64 {
+        let this = self.eval_context_ref();
+        this.memory.alloc_map().iter(|it| {
+            it.filter(|(_id, (kind, _alloc))| *kind == MiriMemoryKind::C.into())
+                .map(|(_id, (_kind, alloc))| alloc.size().bytes())
+                .sum()
+        })
+    }
+
+    fn mallocx(&mut self, size: u64, flags: i32) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+        // jemalloc documents this as undefined behavior.
+        if size == 0 {
+            throw_ub_format!("`mallocx` with a size of zero");
+        }
+        let Some(align) = this.mallocx_align(size, flags) else {
+            return interp_ok(Pointer::null());
+        };
+        if size > this.max_size_of_val().bytes() || !this.check_heap_limit_for_alloc(size, None)? {
+            return interp_ok(Pointer::null());
+        }
+        // `MALLOCX_ZERO`. The tcache and arena selectors have no observable effect in Miri.
+        let init = if flags & 0x40 != 0 { AllocInit::Zero } else { AllocInit::Uninit };
+        let ptr =
+            this.allocate_ptr(Size::from_bytes(size), align, MiriMemoryKind::C.into(), init)?;
+        interp_ok(ptr.into())
+    }
+
+    fn rallocx(&mut self, ptr: Pointer, size: u64, flags: i32) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+        if size == 0 {
+            throw_ub_format!("`rallocx` with a size of zero");
+        }
+        this.malloc_usable_size_of(ptr, "rallocx")?;
+        let Some(align) = this.mallocx_align(size, flags) else {
+            return interp_ok(Pointer::null());
+        };
+        if size > this.max_size_of_val().bytes()
+            || !this.check_heap_limit_for_alloc(size, Some(ptr))?
+        {
+            return interp_ok(Pointer::null());
+        }
+        let init = if flags & 0x40 != 0 { AllocInit::Zero } else { AllocInit::Uninit };
+        let new_ptr = this.reallocate_ptr(
+            ptr,
+            None,
+            Size::from_bytes(size),
+            align,
+            MiriMemoryKind::C.into(),
+            init,
+        )?;
+        interp_ok(new_ptr.into())
+    }
+
+    fn sdallocx(&mut self, ptr: Pointer, size: u64) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        // The size may be anything between the requested size and the usable size, which are
+        // the same for us.
+        let usable_size = this.malloc_usable_size_of(ptr, "sdallocx")?;
+        if size != usable_size {
+            throw_ub_format!(
+                "`sdallocx` called with size {size}, but the allocation has size {usable_size}"
+            );
+        }
+        this.deallocate_ptr(ptr, None, MiriMemoryKind::C.into())
+    }
+
+    fn mallctl(
+        &mut self,
+        name: &OpTy<'tcx>,
+        oldp: &OpTy<'tcx>,
+        oldlenp: &OpTy<'tcx>,
+        newp: &OpTy<'tcx>,
+        newlen: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+        let name = this.read_pointer(name)?;
+        let oldp = this.read_pointer(oldp)?;
+        let oldlenp = this.read_pointer(oldlenp)?;
+        let newp = this.read_pointer(newp)?;
+        let newlen = this.read_target_usize(newlen)?;
+
+        // The statistics describe the `malloc` heap. Miri does not have pages, arenas or
+        // allocator metadata, so all the different notions of "used memory" coincide.
+        let name = this.read_c_str(name)?.to_owned();
+        let (mut value, layout) = match name.as_slice() {
+            b"epoch" =>
+                (Scalar::from_u64(this.machine.malloc_stats_epoch), this.machine.layouts.u64),
+            b"config.stats" => (Scalar::from_bool(true), this.machine.layouts.bool),
+            b"arenas.narenas" => (Scalar::from_u32(1), this.machine.layouts.u32),
+            b"stats.allocated" | b"stats.active" | b"stats.resident" | b"stats.mapped" =>
+                (
+                    Scalar::from_target_usize(this.malloc_live_bytes(), this),
+                    this.machine.layouts.usize,
+                ),
+            b"stats.metadata" | b"stats.retained" =>
+                (Scalar::from_target_usize(0, this), this.machine.layouts.usize),
+            _ => return interp_ok(this.eval_libc("ENOENT")),
+        };
+
+        // Only `epoch` is writable. Writing it would refresh the statistics, but ours are always
+        // up-to-date, so all that happens is that the epoch advances.
+        if !this.ptr_is_null(newp)? || newlen != 0 {
+            if name != b"epoch" {
+                return interp_ok(this.eval_libc("EPERM"));
+            }
+            if newlen != layout.size.bytes() {
+                return interp_ok(this.eval_libc("EINVAL"));
+            }
+            this.read_scalar(&this.ptr_to_mplace(newp, layout))?;
+            this.machine.malloc_stats_epoch = this.machine.malloc_stats_epoch.strict_add(1);
+            value = Scalar::from_u64(this.machine.malloc_stats_epoch);
+        }
+        if !this.ptr_is_null(oldp)? && !this.ptr_is_null(oldlenp)? {
+            let oldlen = this.ptr_to_mplace(oldlenp, this.machine.layouts.usize);
+            if this.read_target_usize(&oldlen)? != layout.size.bytes() {
+                return interp_ok(this.eval_libc("EINVAL"));
+            }
+            this.write_scalar(value, &this.ptr_to_mplace(oldp, layout))?;
+        }
+        interp_ok(Scalar::from_i32(0))
+    }
 }
@@ -0,0 +1,758 @@
+//! Conversions between calendar time (seconds since an epoch) and broken-down time.
+//!
+//! Everything in here is a pure function of its input, the emulated `TZ` environment variable,
+//! and (for `GetTimeZoneInformation`) the current wall clock time, so all of it is available
+//! under isolation.
+//! Time zones are resolved via the IANA database shipped with `chrono-tz`; an unset or
+//! unknown `TZ` means UTC.
+
+use std::ffi::OsStr;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use chrono::{
+    DateTime, Datelike, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeDelta, TimeZone,
+    Timelike, Utc,
+};
+use chrono_tz::{OffsetComponents, OffsetName, Tz};
+use rustc_abi::Size;
+use rustc_apfloat::Float;
+use rustc_apfloat::ieee::Double;
+use rustc_middle::ty::Ty;
+use rustc_target::spec::Os;
+
+use crate::*;
+
+const WEEKDAY_NAMES: [&str; 7] =
+    ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+const MONTH_NAMES: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Size of the Windows `TIME_ZONE_INFORMATION` struct. It is not part of the bindings in std,
+/// so we have to hard-code its layout.
+const TIME_ZONE_INFORMATION_SIZE: u64 = 172;
+
+/// The fields of a C `struct tm` that are shared by all targets.
+#[derive(Clone, Copy, Debug)]
+struct Tm {
+    sec: i32,
+    min: i32,
+    hour: i32,
+    mday: i32,
+    mon: i32,
+    year: i32,
+    wday: i32,
+    yday: i32,
+    isdst: i32,
+}
+
+impl Tm {
+    /// Turns the fields into a date and time, normalizing out-of-range values the way `mktime`
+    /// is specified to (e.g. `tm_mday = 32` in January is the 1st of February).
+    /// `tm_wday` and `tm_yday` are ignored. Returns `None` if the result is not representable.
+    fn to_naive(self) -> Option<NaiveDateTime> {
+        let year = i64::from(self.year)
+            .checked_add(1900)?
+            .checked_add(i64::from(self.mon).div_euclid(12))?;
+        let month = u32::try_from(self.mon.rem_euclid(12)).unwrap();
+        let first_of_month =
+            NaiveDate::from_ymd_opt(year.try_into().ok()?, month.strict_add(1), 1)?;
+        first_of_month
+            .and_hms_opt(0, 0, 0)?
+            .checked_add_signed(TimeDelta::try_days(i64::from(self.mday).checked_sub(1)?)?)?
+            .checked_add_signed(TimeDelta::try_hours(self.hour.into())?)?
+            .checked_add_signed(TimeDelta::try_minutes(self.min.into())?)?
+            .checked_add_signed(TimeDelta::try_seconds(self.sec.into())?)
+    }
+}
+
+/// Reads the fields of the `struct tm` at `tm` that are shared by all targets.
+fn read_tm<'tcx>(ecx: &MiriInterpCx<'tcx>, tm: &MPlaceTy<'tcx>) -> InterpResult<'tcx, Tm> {
+    let field = |name: &str| -> InterpResult<'tcx, i32> {
+        ecx.read_scalar(&ecx.project_field_named(tm, name)?)?.to_i32()
+    };
+    interp_ok(Tm {
+        sec: field("tm_sec")?,
+        min: field("tm_min")?,
+        hour: field("tm_hour")?,
+        mday: field("tm_mday")?,
+        mon: field("tm_mon")?,
+        year: field("tm_year")?,
+        wday: field("tm_wday")?,
+        yday: field("tm_yday")?,
+        isdst: field("tm_isdst")?,
+    })
+}
+
+/// Resolves a local date and time in `tz` to an instant, the way `mktime` does.
+///
+/// If the local time is ambiguous (because the clocks were turned back), `isdst` picks which of
+/// the two instants is meant; without a hint we pick the earlier one. If the local time does not
+/// exist (because the clocks were turned forward), it is interpreted with the offset that was in
+/// effect before the transition, which moves it forward past the gap like glibc does.
+fn resolve_local(tz: Tz, naive: NaiveDateTime, isdst: i32) -> Option<DateTime<Tz>> {
+    match tz.from_local_datetime(&naive) {
+        LocalResult::Single(dt) => Some(dt),
+        LocalResult::Ambiguous(earlier, later) => {
+            let is_dst = |dt: &DateTime<Tz>| !dt.offset().dst_offset().is_zero();
+            Some(match isdst {
+                0 if is_dst(&earlier) => later,
+                1.. if !is_dst(&earlier) => later,
+                _ => earlier,
+            })
+        }
+        LocalResult::None => {
+            // Time zone transitions are never a day apart, so a day earlier is definitely still
+            // before the gap.
+            let before = naive.checked_sub_signed(TimeDelta::days(1))?;
+            let offset = tz.offset_from_utc_datetime(&before).fix().local_minus_utc();
+            let utc = naive.checked_sub_signed(TimeDelta::seconds(offset.into()))?;
+            Some(tz.from_utc_datetime(&utc))
+        }
+    }
+}
+
+/// The start of the first day of `month` in `year` in `tz`. If local midnight does not exist on
+/// that day because the clocks changed at midnight, this is noon UTC of that day instead.
+fn first_of_month(tz: Tz, year: i32, month: u32) -> Option<DateTime<Tz>> {
+    tz.with_ymd_and_hms(year, month, 1, 0, 0, 0).earliest().or_else(|| {
+        let noon = NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(12, 0, 0)?;
+        Some(tz.from_utc_datetime(&noon))
+    })
+}
+
+/// The time zone abbreviation to report for the given date, e.g. "CEST". Zones that do not have
+/// an abbreviation in the IANA database get a numeric one like "+0530", which is also what the
+/// database itself uses for them.
+fn zone_abbreviation(dt: &DateTime<Tz>) -> String {
+    if let Some(abbreviation) = dt.offset().abbreviation() {
+        return abbreviation.to_owned();
+    }
+    let offset_in_seconds = dt.offset().fix().local_minus_utc();
+    let mut tm_zone = String::new();
+    if offset_in_seconds < 0 {
+        tm_zone.push('-');
+    } else {
+        tm_zone.push('+');
+    }
+    let offset_hour = offset_in_seconds.abs() / 3600;
+    write!(tm_zone, "{offset_hour:02}").unwrap();
+    let offset_min = (offset_in_seconds.abs() % 3600) / 60;
+    if offset_min != 0 {
+        write!(tm_zone, "{offset_min:02}").unwrap();
+    }
+    tm_zone
+}
+
+/// The Windows representation of a DST transition: a `SYSTEMTIME` in "day-in-month" format,
+/// with `wYear = 0` and `wDay` being the week of the month (where 5 means "last").
+struct TransitionRule {
+    month: u16,
+    day_of_week: u16,
+    week: u16,
+    hour: u16,
+    minute: u16,
+}
+
+/// Finds the DST start and end in the given year, as `(daylight_date, standard_date)`.
+/// Like Windows, the transition times are given in the local time before the transition.
+fn dst_transitions(tz: Tz, year: i32) -> Option<(TransitionRule, TransitionRule)> {
+    let is_dst = |utc: &NaiveDateTime| !tz.offset_from_utc_datetime(utc).dst_offset().is_zero();
+    let mut start = None;
+    let mut end = None;
+    // Scan the year hour by hour, and then narrow down each change to the second.
+    let mut prev = NaiveDate::from_ymd_opt(year, 1, 1)?.and_hms_opt(0, 0, 0)?;
+    let year_end = NaiveDate::from_ymd_opt(year.checked_add(1)?, 1, 1)?.and_hms_opt(0, 0, 0)?;
+    while prev < year_end {
+        let next = prev.checked_add_signed(TimeDelta::hours(1))?;
+        if is_dst(&prev) != is_dst(&next) {
+            let (mut lo, mut hi) = (prev, next);
+            while hi.signed_duration_since(lo) > TimeDelta::seconds(1) {
+                let half = TimeDelta::seconds(hi.signed_duration_since(lo).num_seconds() / 2);
+                let mid = lo.checked_add_signed(half)?;
+                if is_dst(&mid) == is_dst(&lo) {
+                    lo = mid;
+                } else {
+                    hi = mid;
+                }
+            }
+            let local = tz.offset_from_utc_datetime(&lo).fix().local_minus_utc();
+            let local = hi.checked_add_signed(TimeDelta::seconds(local.into()))?;
+            let last_in_month = local
+                .date()
+                .checked_add_signed(TimeDelta::weeks(1))
+                .is_none_or(|d| d.month() != local.month());
+            let rule = TransitionRule {
+                month: local.month().try_into().unwrap(),
+                day_of_week: local.weekday().num_days_from_sunday().try_into().unwrap(),
+                week: if last_in_month {
+                    5
+                } else {
+                    (local.day0() / 7).strict_add(1).try_into().unwrap()
+                },
+                hour: local.hour().try_into().unwrap(),
+                minute: local.minute().try_into().unwrap(),
+            };
+            if is_dst(&next) {
+                start = Some(rule);
+            } else {
+                end = Some(rule);
+            }
+        }
+        prev = next;
+    }
+    Some((start?, end?))
+}
+
+/// ISO 8601 week-based year and week number, used by `%G`, `%g` and `%V`.
+fn iso_week(tm: &Tm) -> Option<(i32, u32)> {
+    let date = NaiveDate::from_yo_opt(
+        tm.year.checked_add(1900)?,
+        u32::try_from(tm.yday).ok()?.checked_add(1)?,
+    )?;
+    let week = date.iso_week();
+    Some((week.year(), week.week()))
+}
+
+/// Formats `tm` according to the `strftime` conversion specification `format`, in the "C" locale.
+/// `epoch_secs` and `zone` provide the values for `%s`, `%z` and `%Z`.
+#[allow(clippy::arithmetic_side_effects)] // all inputs are `c_int` widened to `i64`
+fn strftime_format(
+    format: &[u8],
+    tm: &Tm,
+    epoch_secs: impl Fn() -> Option<i64>,
+    gmtoff: i64,
+    zone: &[u8],
+) -> Result<Vec<u8>, u8> {
+    let mut out = Vec::new();
+    let mut bytes = format.iter().copied();
+    let (sec, min, hour) = (i64::from(tm.sec), i64::from(tm.min), i64::from(tm.hour));
+    let (mday, mon, year) = (i64::from(tm.mday), i64::from(tm.mon), i64::from(tm.year) + 1900);
+    let (wday, yday) = (i64::from(tm.wday), i64::from(tm.yday));
+    let weekday = usize::try_from(tm.wday).ok().and_then(|i| WEEKDAY_NAMES.get(i).copied());
+    let month = usize::try_from(tm.mon).ok().and_then(|i| MONTH_NAMES.get(i).copied());
+    let hour12 = if hour % 12 == 0 { 12 } else { hour % 12 };
+
+    while let Some(b) = bytes.next() {
+        if b != b'%' {
+            out.push(b);
+            continue;
+        }
+        let mut conv = bytes.next().ok_or(b'%')?;
+        // The `E` and `O` modifiers select alternative representations that only differ from
+        // the normal ones in other locales.
+        if conv == b'E' || conv == b'O' {
+            conv = bytes.next().ok_or(conv)?;
+        }
+        let s = match conv {
+            b'a' => weekday.map_or("?", |w| &w[..3]).to_owned(),
+            b'A' => weekday.map_or("?", |w| w).to_owned(),
+            b'b' | b'h' => month.map_or("?", |m| &m[..3]).to_owned(),
+            b'B' => month.map_or("?", |m| m).to_owned(),
+            b'c' => {
+                let date = format!(
+                    "{} {} {mday:2}",
+                    weekday.map_or("?", |w| &w[..3]),
+                    month.map_or("?", |m| &m[..3])
+                );
+                format!("{date} {hour:02}:{min:02}:{sec:02} {year}")
+            }
+            b'C' => format!("{:02}", year.div_euclid(100)),
+            b'd' => format!("{mday:02}"),
+            b'D' | b'x' => format!("{:02}/{mday:02}/{:02}", mon + 1, year.rem_euclid(100)),
+            b'e' => format!("{mday:2}"),
+            b'F' => format!("{year}-{:02}-{mday:02}", mon + 1),
+            b'g' =>
+                iso_week(tm).map_or("?".to_owned(), |(y, _)| format!("{:02}", y.rem_euclid(100))),
+            b'G' => iso_week(tm).map_or("?".to_owned(), |(y, _)| y.to_string()),
+            b'H' => format!("{hour:02}"),
+            b'I' => format!("{hour12:02}"),
+            b'j' => format!("{:03}", yday + 1),
+            b'k' => format!("{hour:2}"),
+            b'l' => format!("{hour12:2}"),
+            b'm' => format!("{:02}", mon + 1),
+            b'M' => format!("{min:02}"),
+            b'n' => "\n".to_owned(),
+            b'p' => (if hour < 12 { "AM" } else { "PM" }).to_owned(),
+            b'P' => (if hour < 12 { "am" } else { "pm" }).to_owned(),
+            b'r' =>
+                format!("{hour12:02}:{min:02}:{sec:02} {}", if hour < 12 { "AM" } else { "PM" }),
+            b'R' => format!("{hour:02}:{min:02}"),
+            b's' => epoch_secs().map_or("?".to_owned(), |s| s.to_string()),
+            b'S' => format!("{sec:02}"),
+            b't' => "\t".to_owned(),
+            b'T' | b'X' => format!("{hour:02}:{min:02}:{sec:02}"),
+            b'u' => format!("{}", (wday + 6).rem_euclid(7) + 1),
+            b'U' => format!("{:02}", (yday + 7 - wday) / 7),
+            b'V' => iso_week(tm).map_or("?".to_owned(), |(_, w)| format!("{w:02}")),
+            b'w' => format!("{wday}"),
+            b'W' => format!("{:02}", (yday + 7 - (wday + 6).rem_euclid(7)) / 7),
+            b'y' => format!("{:02}", year.rem_euclid(100)),
+            b'Y' => format!("{year}"),
+            b'z' => {
+                let sign = if gmtoff < 0 { '-' } else { '+' };
+                let minutes = gmtoff.abs() / 60;
+                format!("{sign}{:02}{:02}", minutes / 60, minutes % 60)
+            }
+            b'Z' => {
+                out.extend_from_slice(zone);
+                continue;
+            }
+            b'%' => "%".to_owned(),
+            _ => return Err(conv),
+        };
+        out.extend_from_slice(s.as_bytes());
+    }
+    Ok(out)
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// The time zone described by the emulated `TZ` environment variable. Like glibc, we fall
+    /// back to UTC if it is unset or names a time zone we do not know.
+    fn emulated_time_zone(&mut self) -> InterpResult<'tcx, Tz> {
+        let this = self.eval_context_mut();
+        let Some(tz) = this.get_env_var(OsStr::new("TZ"))? else {
+            return interp_ok(Tz::UTC);
+        };
+        let tz = tz.to_str().unwrap_or("");
+        // A leading colon means "implementation-defined"; for glibc that is a file name relative
+        // to the zoneinfo directory, i.e., an IANA time zone name.
+        let tz = tz.strip_prefix(':').unwrap_or(tz);
+        interp_ok(Tz::from_str(tz).unwrap_or(Tz::UTC))
+    }
+
+    /// Writes the broken-down representation of `dt` to the `struct tm` at `tm`. Returns `false`
+    /// if the year does not fit into `tm_year`.
+    fn write_tm(&mut self, dt: &DateTime<Tz>, tm: &MPlaceTy<'tcx>) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+
+        let Some(tm_year) = dt.year().checked_sub(1900) else {
+            return interp_ok(false);
+        };
+        let tm_isdst = i32::from(!dt.offset().dst_offset().is_zero());
+        this.write_int_fields_named(
+            &[
+                ("tm_sec", dt.second().into()),
+                ("tm_min", dt.minute().into()),
+                ("tm_hour", dt.hour().into()),
+                ("tm_mday", dt.day().into()),
+                ("tm_mon", dt.month0().into()),
+                ("tm_year", tm_year.into()),
+                ("tm_wday", dt.weekday().num_days_from_sunday().into()),
+                ("tm_yday", dt.ordinal0().into()),
+                ("tm_isdst", tm_isdst.into()),
+            ],
+            tm,
+        )?;
+
+        // `tm_gmtoff` and `tm_zone` are extensions that not all targets have; for instance,
+        // solaris/illumos do not.
+        // https://docs.oracle.com/cd/E36784_01/html/E36874/localtime-r-3c.html
+        if let Some(tm_gmtoff) = this.try_project_field_named(tm, "tm_gmtoff")? {
+            this.write_int(dt.offset().fix().local_minus_utc(), &tm_gmtoff)?;
+        }
+        if let Some(tm_zone) = this.try_project_field_named(tm, "tm_zone")? {
+            let mut zone = zone_abbreviation(dt);
+            // Add null terminator for C string compatibility.
+            zone.push('\0');
+            // Deduplicate and allocate the string.
+            let zone_ptr = this.allocate_bytes_dedup(zone.as_bytes())?;
+            this.write_pointer(zone_ptr, &tm_zone)?;
+        }
+        interp_ok(true)
+    }
+
+    /// Shared implementation of `localtime_r` and `gmtime_r`.
+    fn time_to_tm(
+        &mut self,
+        name: &str,
+        tz: Tz,
+        timep: &OpTy<'tcx>,
+        result_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+
+        this.assert_target_os_is_unix(name);
+
+        let time_layout = this.libc_ty_layout("time_t");
+        let timep = this.deref_pointer_as(timep, time_layout)?;
+        let result = this.deref_pointer_as(result_op, this.libc_ty_layout("tm"))?;
+
+        // The input "represents the number of seconds elapsed since the Epoch,
+        // 1970-01-01 00:00:00 +0000 (UTC)".
+        let sec_since_epoch = this.read_scalar(&timep)?.to_int(time_layout.size)?;
+        let dt_utc: Option<DateTime<Utc>> =
+            i64::try_from(sec_since_epoch).ok().and_then(|sec| DateTime::from_timestamp(sec, 0));
+
+        // Convert that to the requested time zone, then return the broken-down time value.
+        let written = match dt_utc {
+            Some(dt_utc) => this.write_tm(&dt_utc.with_timezone(&tz), &result)?,
+            None => false,
+        };
+        if !written {
+            this.set_last_error(LibcError("EOVERFLOW"))?;
+            return interp_ok(Pointer::null());
+        }
+        interp_ok(result.ptr())
+    }
+
+    // The localtime() function shall convert the time in seconds since the Epoch pointed to by
+    // timer into a broken-down time, expressed as a local time.
+    // https://linux.die.net/man/3/localtime_r
+    fn localtime_r(
+        &mut self,
+        timep: &OpTy<'tcx>,
+        result_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+        let tz = this.emulated_time_zone()?;
+        this.time_to_tm("localtime_r", tz, timep, result_op)
+    }
+
+    // The gmtime() function shall convert the time in seconds since the Epoch pointed to by
+    // timer into a broken-down time, expressed as Coordinated Universal Time (UTC).
+    // https://pubs.opengroup.org/onlinepubs/9799919799/functions/gmtime.html
+    fn gmtime_r(
+        &mut self,
+        timep: &OpTy<'tcx>,
+        result_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Pointer> {
+        // glibc and the BSDs call the zone "GMT" here.
+        self.eval_context_mut().time_to_tm("gmtime_r", Tz::GMT, timep, result_op)
+    }
+
+    /// Shared implementation of `mktime` and `timegm`: the inverse of `time_to_tm`, which also
+    /// normalizes the fields of the `struct tm` it is given.
+    fn tm_to_time(
+        &mut self,
+        name: &str,
+        tz: Tz,
+        tm_op: &OpTy<'tcx>,
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        this.assert_target_os_is_unix(name);
+
+        let tm = this.deref_pointer_as(tm_op, this.libc_ty_layout("tm"))?;
+        let fields = read_tm(this, &tm)?;
+
+        let time_t_size = this.libc_ty_layout("time_t").size;
+        let dt = fields.to_naive().and_then(|naive| resolve_local(tz, naive, fields.isdst));
+        let secs = dt.as_ref().map(|dt| i128::from(dt.timestamp())).filter(|&secs| {
+            time_t_size.signed_int_min() <= secs && secs <= time_t_size.signed_int_max()
+        });
+        let (Some(dt), Some(secs)) = (dt, secs) else {
+            return this.set_last_error_and_return(LibcError("EOVERFLOW"), dest);
+        };
+        if !this.write_tm(&dt, &tm)? {
+            return this.set_last_error_and_return(LibcError("EOVERFLOW"), dest);
+        }
+
+        this.write_int(secs, dest)?;
+        interp_ok(())
+    }
+
+    // The mktime() function shall convert the broken-down time, expressed as local time, in the
+    // structure pointed to by timeptr, into a time since the Epoch value.
+    // https://pubs.opengroup.org/onlinepubs/9799919799/functions/mktime.html
+    fn mktime(&mut self, tm_op: &OpTy<'tcx>, dest: &MPlaceTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let tz = this.emulated_time_zone()?;
+        this.tm_to_time("mktime", tz, tm_op, dest)
+    }
+
+    // timegm() is the UTC counterpart of mktime(). It is not in POSIX, but all the Unixes we
+    // support provide it.
+    fn timegm(&mut self, tm_op: &OpTy<'tcx>, dest: &MPlaceTy<'tcx>) -> InterpResult<'tcx> {
+        self.eval_context_mut().tm_to_time("timegm", Tz::GMT, tm_op, dest)
+    }
+
+    fn tzset(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        this.assert_target_os_is_unix("tzset");
+
+        // We look up `TZ` on every conversion, so there is nothing to cache here. We do not
+        // support the `tzname`, `timezone` and `daylight` globals that `tzset` would update.
+        this.emulated_time_zone()?;
+        interp_ok(())
+    }
+
+    fn difftime(&mut self, time1: &OpTy<'tcx>, time0: &OpTy<'tcx>) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let time_t_size = this.libc_ty_layout("time_t").size;
+        let time1 = this.read_scalar(time1)?.to_int(time_t_size)?;
+        let time0 = this.read_scalar(time0)?.to_int(time_t_size)?;
+        // `time_t` is at most 64 bits, so this cannot overflow.
+        let diff = Double::from_i128(time1.strict_sub(time0)).value;
+        interp_ok(Scalar::from_f64(diff))
+    }
+
+    // The strftime() function shall place bytes into the array pointed to by s as controlled by
+    // the string pointed to by format.
+    // https://pubs.opengroup.org/onlinepubs/9799919799/functions/strftime.html
+    fn strftime(
+        &mut self,
+        s_op: &OpTy<'tcx>,
+        max_op: &OpTy<'tcx>,
+        format_op: &OpTy<'tcx>,
+        tm_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        this.assert_target_os_is_unix("strftime");
+
+        let s = this.read_pointer(s_op)?;
+        let max = this.read_target_usize(max_op)?;
+        let format = this.read_pointer(format_op)?;
+        let tm = this.deref_pointer_as(tm_op, this.libc_ty_layout("tm"))?;
+
+        let fields = read_tm(this, &tm)?;
+        let tz = this.emulated_time_zone()?;
+        let local = fields.to_naive().and_then(|naive| resolve_local(tz, naive, fields.isdst));
+
+        // `%z` and `%Z` are taken from the `struct tm` if the target has the fields for them,
+        // and otherwise computed from the current time zone.
+        let gmtoff = match this.try_project_field_named(&tm, "tm_gmtoff")? {
+            Some(field) => this.read_scalar(&field)?.to_int(field.layout.size)?.try_into().unwrap(),
+            None => local.as_ref().map_or(0, |dt| dt.offset().fix().local_minus_utc().into()),
+        };
+        let zone = match this.try_project_field_named(&tm, "tm_zone")? {
+            Some(field) => {
+                let ptr = this.read_pointer(&field)?;
+                if this.ptr_is_null(ptr)? { Vec::new() } else { this.read_c_str(ptr)?.to_owned() }
+            }
+            None => local.as_ref().map(zone_abbreviation).unwrap_or_default().into_bytes(),
+        };
+
+        let format = this.read_c_str(format)?;
+        let output = match strftime_format(
+            format,
+            &fields,
+            || local.map(|dt| dt.timestamp()),
+            gmtoff,
+            &zone,
+        ) {
+            Ok(output) => output,
+            Err(conv) =>
+                throw_unsup_format!(
+                    "`strftime`: unsupported conversion specification `%{}`",
+                    char::from(conv)
+                ),
+        };
+
+        // On success, the number of bytes written, excluding the null terminator, is returned.
+        // If the result does not fit, 0 is returned and the contents of the array are unspecified
+        // (we leave it untouched).
+        let (success, len) = this.write_c_str(&output, s, max)?;
+        let written = if success { len.strict_sub(1) } else { 0 };
+        interp_ok(Scalar::from_target_usize(written, this))
+    }
+
+    /// Reads a `FILETIME` as the number of 100ns intervals since the Windows epoch.
+    #[allow(non_snake_case, clippy::arithmetic_side_effects)]
+    fn read_filetime(&self, filetime_op: &OpTy<'tcx>) -> InterpResult<'tcx, u64> {
+        let this = self.eval_context_ref();
+        let filetime = this.deref_pointer_as(filetime_op, this.windows_ty_layout("FILETIME"))?;
+        let dwLowDateTime =
+            this.read_scalar(&this.project_field_named(&filetime, "dwLowDateTime")?)?.to_u32()?;
+        let dwHighDateTime =
+            this.read_scalar(&this.project_field_named(&filetime, "dwHighDateTime")?)?.to_u32()?;
+        interp_ok((u64::from(dwHighDateTime) << 32) | u64::from(dwLowDateTime))
+    }
+
+    /// Writes the number of 100ns intervals since the Windows epoch as a `FILETIME`.
+    #[allow(non_snake_case, clippy::arithmetic_side_effects)]
+    fn write_filetime(&mut self, ticks: u64, filetime_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let filetime = this.deref_pointer_as(filetime_op, this.windows_ty_layout("FILETIME"))?;
+        let dwLowDateTime = u32::try_from(ticks & 0x00000000FFFFFFFF).unwrap();
+        let dwHighDateTime = u32::try_from(ticks >> 32).unwrap();
+        this.write_int_fields(&[dwLowDateTime.into(), dwHighDateTime.into()], &filetime)
+    }
+
+    /// Shared implementation of `FileTimeToLocalFileTime` and `LocalFileTimeToFileTime`.
+    ///
+    /// Windows documents these to apply the *current* UTC offset rather than the one in effect at
+    /// the given time. Since we want the result to only depend on the input, we use the offset
+    /// at the given time instead, which is what `SystemTimeToTzSpecificLocalTime` does.
+    #[allow(non_snake_case)]
+    fn convert_filetime(
+        &mut self,
+        shim_name: &str,
+        to_local: bool,
+        input_op: &OpTy<'tcx>,
+        output_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        this.assert_target_os(Os::Windows, shim_name);
+
+        let INTERVALS_PER_SEC = this.eval_windows_u64("time", "INTERVALS_PER_SEC");
+        let INTERVALS_TO_UNIX_EPOCH = this.eval_windows_u64("time", "INTERVALS_TO_UNIX_EPOCH");
+
+        let ticks = this.read_filetime(input_op)?;
+        let tz = this.emulated_time_zone()?;
+
+        // Split into whole seconds since the Unix epoch and the sub-second remainder.
+        let since_unix_epoch = i128::from(ticks).strict_sub(INTERVALS_TO_UNIX_EPOCH.into());
+        let secs = since_unix_epoch.div_euclid(INTERVALS_PER_SEC.into());
+        let subsec = since_unix_epoch.rem_euclid(INTERVALS_PER_SEC.into());
+
+        let offset = i64::try_from(secs).ok().and_then(|secs| {
+            if to_local {
+                let utc = DateTime::from_timestamp(secs, 0)?.naive_utc();
+                Some(tz.offset_from_utc_datetime(&utc).fix().local_minus_utc())
+            } else {
+                let local = DateTime::from_timestamp(secs, 0)?.naive_utc();
+                let dt = resolve_local(tz, local, -1)?;
+                Some(dt.offset().fix().local_minus_utc().strict_neg())
+            }
+        });
+        let result = offset.and_then(|offset| {
+            let secs = secs.checked_add(offset.into())?;
+            let ticks = secs
+                .checked_mul(INTERVALS_PER_SEC.into())?
+                .checked_add(subsec)?
+                .checked_add(INTERVALS_TO_UNIX_EPOCH.into())?;
+            u64::try_from(ticks).ok()
+        });
+        let Some(result) = result else {
+            this.set_last_error(IoError::WindowsError("ERROR_INVALID_PARAMETER"))?;
+            return interp_ok(this.eval_windows("c", "FALSE"));
+        };
+
+        this.write_filetime(result, output_op)?;
+        interp_ok(this.eval_windows("c", "TRUE"))
+    }
+
+    #[allow(non_snake_case)]
+    fn FileTimeToLocalFileTime(
+        &mut self,
+        lpFileTime_op: &OpTy<'tcx>,
+        lpLocalFileTime_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        self.eval_context_mut().convert_filetime(
+            "FileTimeToLocalFileTime",
+            /* to_local */ true,
+            lpFileTime_op,
+            lpLocalFileTime_op,
+        )
+    }
+
+    #[allow(non_snake_case)]
+    fn LocalFileTimeToFileTime(
+        &mut self,
+        lpLocalFileTime_op: &OpTy<'tcx>,
+        lpFileTime_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        self.eval_context_mut().convert_filetime(
+            "LocalFileTimeToFileTime",
+            /* to_local */ false,
+            lpLocalFileTime_op,
+            lpFileTime_op,
+        )
+    }
+
+    #[allow(non_snake_case)]
+    fn GetTimeZoneInformation(
+        &mut self,
+        lpTimeZoneInformation_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        this.assert_target_os(Os::Windows, "GetTimeZoneInformation");
+
+        // The transition dates and the return value depend on the current date.
+        let tz = this.emulated_time_zone()?;
+        let now = system_time_to_duration(&this.system_time_now())?;
+        let Some(now) =
+            i64::try_from(now.as_secs()).ok().and_then(|secs| DateTime::from_timestamp(secs, 0))
+        else {
+            this.set_last_error(IoError::WindowsError("ERROR_INVALID_DATA"))?;
+            return interp_ok(Scalar::from_u32(u32::MAX)); // TIME_ZONE_ID_INVALID
+        };
+        let now = now.with_timezone(&tz);
+
+        let tzi_layout = this.layout_of(Ty::new_array(
+            *this.tcx,
+            this.tcx.types.u8,
+            TIME_ZONE_INFORMATION_SIZE,
+        ))?;
+        let tzi = this.deref_pointer_as(lpTimeZoneInformation_op, tzi_layout)?;
+        // Initialize with `0`.
+        this.write_bytes_ptr(tzi.ptr(), std::iter::repeat_n(0u8, tzi.layout.size.bytes_usize()))?;
+
+        // The fields are:
+        // LONG Bias; WCHAR StandardName[32]; SYSTEMTIME StandardDate; LONG StandardBias;
+        // WCHAR DaylightName[32]; SYSTEMTIME DaylightDate; LONG DaylightBias;
+        let i32_layout = this.machine.layouts.i32;
+        let u16_layout = this.machine.layouts.u16;
+        // All biases are in minutes, and defined as "UTC = local time + bias".
+        let bias = now.offset().base_utc_offset().num_minutes().strict_neg();
+        this.write_int(bias, &tzi.offset(Size::from_bytes(0), i32_layout, this)?)?;
+
+        // Find a date in standard time and one in daylight time (if any) to get the names and the
+        // DST offset.
+        let jan = first_of_month(tz, now.year(), 1).unwrap_or(now);
+        let jul = first_of_month(tz, now.year(), 7).unwrap_or(now);
+        let (standard, daylight) =
+            if jan.offset().dst_offset().is_zero() { (jan, jul) } else { (jul, jan) };
+        let transitions = dst_transitions(tz, now.year());
+        let (standard_name, daylight_name) = match &transitions {
+            Some(_) => (zone_abbreviation(&standard), zone_abbreviation(&daylight)),
+            None => (zone_abbreviation(&now), zone_abbreviation(&now)),
+        };
+        for (name, offset) in [(standard_name, 4), (daylight_name, 88)] {
+            // The names are fixed-size arrays of 32 `WCHAR` including the null terminator.
+            let name: Vec<u16> = name.encode_utf16().take(31).collect();
+            let ptr = tzi.offset(Size::from_bytes(offset), u16_layout, this)?.ptr();
+            this.write_wide_str(&name, ptr, 32)?;
+        }
+
+        let Some((daylight_date, standard_date)) = transitions else {
+            return interp_ok(Scalar::from_u32(0)); // TIME_ZONE_ID_UNKNOWN
+        };
+        for (rule, offset) in [(standard_date, 68), (daylight_date, 152)] {
+            let fields = [
+                0, // wYear
+                rule.month,
+                rule.day_of_week,
+                rule.week,
+                rule.hour,
+                rule.minute,
+                0, // wSecond
+                0, // wMilliseconds
+            ];
+            for (idx, field) in fields.into_iter().enumerate() {
+                let field_offset = offset.strict_add(idx.strict_mul(2));
+                let place = tzi.offset(Size::from_bytes(field_offset), u16_layout, this)?;
+                this.write_int(field, &place)?;
+            }
+        }
+        // StandardBias stays 0; DaylightBias is the extra offset during DST.
+        let daylight_bias = daylight.offset().dst_offset().num_minutes().strict_neg();
+        this.write_int(daylight_bias, &tzi.offset(Size::from_bytes(168), i32_layout, this)?)?;
+
+        interp_ok(if now.offset().dst_offset().is_zero() {
+            Scalar::from_u32(1) // TIME_ZONE_ID_STANDARD
+        } else {
+            Scalar::from_u32(2) // TIME_ZONE_ID_DAYLIGHT
+        })
+    }
+}
@@ -0,0 +1,195 @@
+//! Implement the handlers that run when the process exits: `atexit`, `__cxa_atexit` and
+//! `at_quick_exit`.
+//!
+//! When `main` returns, the handlers run on the main thread once its stack is empty. When the
+//! program calls `exit` or `quick_exit`, they run on the calling thread, as if `exit` called them
+//! one after the other.
+
+use rustc_abi::ExternAbi;
+use rustc_middle::mir;
+
+use crate::*;
+
+/// A function registered with `atexit`, `__cxa_atexit` or `at_quick_exit`.
+#[derive(Debug)]
+struct ExitHandler {
+    func: Pointer,
+    /// The argument passed to `__cxa_atexit`. The other functions take handlers without arguments.
+    arg: Option<Pointer>,
+}
+
+/// Who is running the exit handlers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExitCaller {
+    /// The main function returned.
+    MainReturn,
+    /// The program called `exit` or `quick_exit`.
+    Call,
+}
+
+#[derive(Debug, Default)]
+pub struct ExitHandlers {
+    /// The handlers for `exit` and returning from `main`, in the order they were registered.
+    atexit: Vec<ExitHandler>,
+    /// The handlers for `quick_exit`, in the order they were registered.
+    quick_exit: Vec<ExitHandler>,
+    /// Set once the process started exiting.
+    running: Option<ExitCaller>,
+}
+
+impl VisitProvenance for ExitHandlers {
+    fn visit_provenance(&self, visit: &mut VisitWith<'_>) {
+        let ExitHandlers { atexit, quick_exit, running: _ } = self;
+        for ExitHandler { func, arg } in atexit.iter().chain(quick_exit) {
+            func.visit_provenance(visit);
+            arg.visit_provenance(visit);
+        }
+    }
+}
+
+impl<'tcx> EvalContextExtPriv<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Marks the process as exiting on behalf of `caller`, and returns whether it just started
+    /// exiting. It is UB to start exiting twice; only the main thread asks again after every
+    /// handler that runs after `main` returned.
+    fn start_exit(&mut self, caller: ExitCaller, link_name: &str) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+        match this.machine.exit_handlers.running {
+            None => {
+                this.machine.exit_handlers.running = Some(caller);
+                interp_ok(true)
+            }
+            Some(ExitCaller::MainReturn) if caller == ExitCaller::MainReturn => interp_ok(false),
+            Some(_) =>
+                throw_ub_format!("`{link_name}` called while the process is already exiting"),
+        }
+    }
+
+    /// Warns if other threads are still running while the exit handlers run, since they can
+    /// observe the state that the handlers tear down.
+    fn check_live_threads_at_exit(&self) {
+        let this = self.eval_context_ref();
+        let live_threads = this.machine.threads.get_other_live_program_thread_count();
+        if live_threads > 0 {
+            this.emit_diagnostic(NonHaltingDiagnostic::ExitHandlerWithLiveThreads { live_threads });
+        }
+    }
+
+    /// Pushes a frame for the most recently registered handler that has not run yet. Returns
+    /// whether there was such a handler.
+    fn call_next_exit_handler(
+        &mut self,
+        quick: bool,
+        cont: ReturnContinuation,
+    ) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+        let handlers = &mut this.machine.exit_handlers;
+        let handler = if quick { handlers.quick_exit.pop() } else { handlers.atexit.pop() };
+        let Some(ExitHandler { func, arg }) = handler else {
+            return interp_ok(false);
+        };
+
+        // The signature is `extern "C" fn(*mut c_void)` for `__cxa_atexit` and `extern "C" fn()`
+        // otherwise.
+        let instance = this.get_ptr_fn(func)?.as_instance()?;
+        let args = match arg {
+            Some(arg) =>
+                vec![ImmTy::from_scalar(
+                    Scalar::from_maybe_pointer(arg, this),
+                    this.machine.layouts.mut_raw_ptr,
+                )],
+            None => vec![],
+        };
+        this.call_function(instance, ExternAbi::C { unwind: false }, &args, None, cont)?;
+        interp_ok(true)
+    }
+
+    /// Calls the next handler on behalf of `exit` or `quick_exit`, and once it returns, the one
+    /// after that. Once all handlers have run, the process terminates.
+    fn continue_exit(&mut self, code: i32, quick: bool) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        // `on_return` takes over once the handler returns.
+        let cont = ReturnContinuation::Goto { ret: None, unwind: mir::UnwindAction::Unreachable };
+        if this.call_next_exit_handler(quick, cont)? {
+            this.frame_mut().extra.on_return = Some(callback!(
+                @capture<'tcx> {
+                    code: i32,
+                    quick: bool,
+                }
+                |this, _unit: ()| this.continue_exit(code, quick)
+            ));
+            return interp_ok(());
+        }
+
+        if let Some(genmc_ctx) = this.machine.data_race.as_genmc_ref() {
+            // If there is no error, execution should continue (on a different thread).
+            genmc_ctx.handle_exit(
+                this.machine.threads.active_thread(),
+                code,
+                crate::concurrency::ExitType::ExitCalled,
+            )?;
+            todo!(); // FIXME(genmc): Add a way to return here that is allowed to not do progress (can't use existing EmulateItemResult variants).
+        }
+        throw_machine_stop!(TerminationInfo::Exit { code, leak_check: false });
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Registers a handler for `exit` (`quick: false`) or `quick_exit` (`quick: true`).
+    fn register_exit_handler(
+        &mut self,
+        func: Pointer,
+        arg: Option<Pointer>,
+        quick: bool,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        // Check that this is a function pointer right away, so the error points at the
+        // registration rather than at the exit.
+        this.get_ptr_fn(func)?;
+        let handler = ExitHandler { func, arg };
+        let handlers = &mut this.machine.exit_handlers;
+        if quick {
+            handlers.quick_exit.push(handler)
+        } else {
+            handlers.atexit.push(handler)
+        }
+        interp_ok(())
+    }
+
+    /// Called when the main thread's stack is empty after `main` returned. Runs the next `atexit`
+    /// handler, and returns whether there was one.
+    fn run_exit_handlers_after_main(&mut self) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+        let started = this.start_exit(ExitCaller::MainReturn, "exit")?;
+        let called = this.call_next_exit_handler(
+            /* quick */ false,
+            ReturnContinuation::Stop { cleanup: true },
+        )?;
+        if started && called {
+            // The stack was empty before, so we report this in the first handler.
+            this.check_live_threads_at_exit();
+        }
+        interp_ok(called)
+    }
+
+    /// Implements `exit` and `quick_exit`. This runs the handlers one by one on the active thread.
+    /// Once all handlers have run, the process terminates.
+    fn exit(
+        &mut self,
+        code: i32,
+        quick: bool,
+        link_name: &str,
+    ) -> InterpResult<'tcx, EmulateItemResult> {
+        let this = self.eval_context_mut();
+        this.start_exit(ExitCaller::Call, link_name)?;
+        let handlers = &this.machine.exit_handlers;
+        let has_handlers =
+            if quick { !handlers.quick_exit.is_empty() } else { !handlers.atexit.is_empty() };
+        if has_handlers {
+            this.check_live_threads_at_exit();
+        }
+        this.continue_exit(code, quick)?;
+        interp_ok(EmulateItemResult::AlreadyJumped)
+    }
+}
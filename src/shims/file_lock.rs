@@ -0,0 +1,362 @@
+//! Advisory file locks: `flock`, `fcntl` record locks, and `LockFileEx`.
+//!
+//! All lock state is tracked by the interpreter, keyed by the identity of the host file, so that
+//! locks taken through different file descriptions of the same file interact correctly. A thread
+//! that has to wait for a lock is blocked like for any other synchronization primitive, which means
+//! that contention between interpreted threads works and that deadlocks are detected.
+
+use std::fs::File;
+use std::io;
+
+use rustc_data_structures::fx::FxHashMap;
+
+use crate::shims::files::FdId;
+use crate::*;
+
+/// The identity of a host file: all file descriptions opened for the same file share it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId {
+    device: u64,
+    index: u64,
+}
+
+impl FileId {
+    pub fn of(file: &File) -> io::Result<FileId> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let metadata = file.metadata()?;
+            Ok(FileId { device: metadata.dev(), index: metadata.ino() })
+        }
+        #[cfg(windows)]
+        {
+            windows_file_id(file)
+        }
+        #[cfg(not(any(unix, windows)))]
+        {
+            let _ = file;
+            Err(io::ErrorKind::Unsupported.into())
+        }
+    }
+}
+
+/// The `MetadataExt` methods exposing the volume and file index are unstable, so we ask the host
+/// directly.
+#[cfg(windows)]
+fn windows_file_id(file: &File) -> io::Result<FileId> {
+    use std::os::windows::io::{AsRawHandle, RawHandle};
+
+    #[repr(C)]
+    #[allow(non_snake_case, dead_code)]
+    struct BY_HANDLE_FILE_INFORMATION {
+        dwFileAttributes: u32,
+        ftCreationTime: [u32; 2],
+        ftLastAccessTime: [u32; 2],
+        ftLastWriteTime: [u32; 2],
+        dwVolumeSerialNumber: u32,
+        nFileSizeHigh: u32,
+        nFileSizeLow: u32,
+        nNumberOfLinks: u32,
+        nFileIndexHigh: u32,
+        nFileIndexLow: u32,
+    }
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        fn GetFileInformationByHandle(
+            file: RawHandle,
+            info: *mut BY_HANDLE_FILE_INFORMATION,
+        ) -> i32;
+    }
+
+    let mut info = std::mem::MaybeUninit::<BY_HANDLE_FILE_INFORMATION>::uninit();
+    // SAFETY: the handle is valid for as long as `file` is, and `info` is a valid out pointer.
+    if unsafe { GetFileInformationByHandle(file.as_raw_handle(), info.as_mut_ptr()) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the call succeeded, so it initialized `info`.
+    let info = unsafe { info.assume_init() };
+    Ok(FileId {
+        device: info.dwVolumeSerialNumber.into(),
+        index: u64::from(info.nFileIndexHigh).strict_shl(32) | u64::from(info.nFileIndexLow),
+    })
+}
+
+impl VisitProvenance for FileId {
+    fn visit_provenance(&self, _visit: &mut VisitWith<'_>) {}
+}
+
+/// The different kinds of locks. Locks of different kinds never interact with each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LockKind {
+    /// Whole-file locks taken with `flock`.
+    Flock,
+    /// Byte-range locks taken with `fcntl`. Classic POSIX locks (`F_SETLK`) and open file
+    /// description locks (`F_OFD_SETLK`) live in the same namespace and conflict with each other.
+    Record,
+    /// Byte-range locks taken with `LockFileEx`.
+    Windows,
+}
+
+impl VisitProvenance for LockKind {
+    fn visit_provenance(&self, _visit: &mut VisitWith<'_>) {}
+}
+
+/// Who holds a lock. Locks of the same owner do not conflict with each other (except for
+/// `LockFileEx` locks, where an exclusive lock conflicts even within the same handle).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockOwner {
+    /// The lock belongs to a file description, and is released when that description is closed.
+    Description(FdId),
+    /// The lock belongs to the whole process, and is released when *any* file descriptor for the
+    /// file is closed. This is the (infamous) behavior of classic POSIX record locks.
+    Process,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FileLock {
+    pub owner: LockOwner,
+    /// The first byte covered by this lock.
+    pub start: u64,
+    /// The first byte after the lock. `u64::MAX` means the lock extends to the end of the file,
+    /// however large it grows.
+    pub end: u64,
+    pub exclusive: bool,
+}
+
+impl VisitProvenance for FileLock {
+    fn visit_provenance(&self, _visit: &mut VisitWith<'_>) {}
+}
+
+impl FileLock {
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start < end && start < self.end
+    }
+
+    /// Whether the lock `self` cannot be acquired while `held` is held.
+    fn conflicts_with(&self, held: &FileLock, kind: LockKind) -> bool {
+        if !held.overlaps(self.start, self.end) {
+            return false;
+        }
+        match kind {
+            LockKind::Flock | LockKind::Record =>
+                self.owner != held.owner && (self.exclusive || held.exclusive),
+            // A handle can take a shared lock on top of its own exclusive lock, but any other
+            // overlap with an exclusive lock conflicts.
+            LockKind::Windows => self.exclusive || (held.exclusive && self.owner != held.owner),
+        }
+    }
+}
+
+/// The global table of all file locks.
+#[derive(Debug, Default)]
+pub struct FileLocks {
+    locks: FxHashMap<(FileId, LockKind), Vec<FileLock>>,
+    /// The threads waiting for some lock on the given file to be released.
+    waiters: FxHashMap<FileId, Vec<ThreadId>>,
+}
+
+impl FileLocks {
+    /// Returns a held lock that prevents `lock` from being acquired, if any.
+    pub fn conflict(&self, file: FileId, kind: LockKind, lock: &FileLock) -> Option<FileLock> {
+        let held = self.locks.get(&(file, kind))?;
+        held.iter().find(|held| lock.conflicts_with(held, kind)).copied()
+    }
+
+    /// Returns whether `owner` holds some lock of the given kind on `file`, and if so, whether any
+    /// of them is exclusive.
+    pub fn held_by(&self, file: FileId, kind: LockKind, owner: LockOwner) -> Option<bool> {
+        let held = self.locks.get(&(file, kind))?;
+        held.iter()
+            .filter(|held| held.owner == owner)
+            .map(|held| held.exclusive)
+            .reduce(|a, b| a || b)
+    }
+
+    /// Returns whether anyone but `owner` holds a lock of the given kind on `file`.
+    pub fn held_by_others(&self, file: FileId, kind: LockKind, owner: LockOwner) -> bool {
+        self.locks
+            .get(&(file, kind))
+            .is_some_and(|held| held.iter().any(|held| held.owner != owner))
+    }
+
+    /// Adds `lock`, which must not conflict with any held lock.
+    fn insert(&mut self, file: FileId, kind: LockKind, lock: FileLock) {
+        let held = self.locks.entry((file, kind)).or_default();
+        match kind {
+            // A description holds at most one `flock` lock; acquiring another converts it.
+            LockKind::Flock => held.retain(|held| held.owner != lock.owner),
+            // The new lock replaces whatever the owner held in that range before.
+            LockKind::Record => {
+                remove_range(held, lock.owner, lock.start, lock.end);
+            }
+            // Windows locks stack up and have to be released one by one.
+            LockKind::Windows => {}
+        }
+        held.push(lock);
+    }
+
+    /// Releases the locks of `owner` in the given range. Returns whether anything was unlocked.
+    fn unlock(
+        &mut self,
+        file: FileId,
+        kind: LockKind,
+        owner: LockOwner,
+        start: u64,
+        end: u64,
+    ) -> bool {
+        let Some(held) = self.locks.get_mut(&(file, kind)) else {
+            return false;
+        };
+        let unlocked = match kind {
+            LockKind::Flock | LockKind::Record => remove_range(held, owner, start, end),
+            // Windows requires the range to exactly match a locked range. If the handle holds both
+            // an exclusive and a shared lock there, the exclusive one is released first.
+            LockKind::Windows => {
+                let matches =
+                    |lock: &FileLock| lock.owner == owner && lock.start == start && lock.end == end;
+                let idx = held
+                    .iter()
+                    .position(|lock| matches(lock) && lock.exclusive)
+                    .or_else(|| held.iter().position(matches));
+                match idx {
+                    Some(idx) => {
+                        held.remove(idx);
+                        true
+                    }
+                    None => false,
+                }
+            }
+        };
+        if held.is_empty() {
+            self.locks.remove(&(file, kind));
+        }
+        unlocked
+    }
+
+    /// Releases all locks of `owner` on the given file (or on all files), and returns the files
+    /// where something was released.
+    fn release_all(&mut self, owner: LockOwner, file: Option<FileId>) -> Vec<FileId> {
+        let mut released = Vec::new();
+        self.locks.retain(|&(held_file, _kind), held| {
+            if file.is_some_and(|file| file != held_file) {
+                return true;
+            }
+            let len = held.len();
+            held.retain(|held| held.owner != owner);
+            if held.len() != len && !released.contains(&held_file) {
+                released.push(held_file);
+            }
+            !held.is_empty()
+        });
+        released
+    }
+}
+
+/// Removes the range `[start, end)` from all locks of `owner`, splitting locks where necessary.
+/// Returns whether any lock was affected.
+fn remove_range(held: &mut Vec<FileLock>, owner: LockOwner, start: u64, end: u64) -> bool {
+    let mut removed = false;
+    let mut remaining = Vec::with_capacity(held.len());
+    for lock in held.drain(..) {
+        if lock.owner != owner || !lock.overlaps(start, end) {
+            remaining.push(lock);
+            continue;
+        }
+        removed = true;
+        if lock.start < start {
+            remaining.push(FileLock { end: start, ..lock });
+        }
+        if end < lock.end {
+            remaining.push(FileLock { start: end, ..lock });
+        }
+    }
+    *held = remaining;
+    removed
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Acquires `lock` on `file`. If the lock is currently unavailable, then either the active
+    /// thread is blocked until it can be acquired (if `blocking` is set), or `finish` is called with
+    /// `EWOULDBLOCK`.
+    fn acquire_file_lock(
+        &mut self,
+        file: FileId,
+        kind: LockKind,
+        lock: FileLock,
+        blocking: bool,
+        finish: DynMachineCallback<'tcx, Result<(), IoError>>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let file_locks = &mut this.machine.file_locks;
+
+        if file_locks.conflict(file, kind, &lock).is_none() {
+            file_locks.insert(file, kind, lock);
+            return finish.call(this, Ok(()));
+        }
+        if !blocking {
+            return finish.call(this, Err(io::ErrorKind::WouldBlock.into()));
+        }
+
+        // Wait until something on this file is released, then try again.
+        file_locks.waiters.entry(file).or_default().push(this.active_thread());
+        this.block_thread(
+            BlockReason::FileLock,
+            None,
+            callback!(
+                @capture<'tcx> {
+                    file: FileId,
+                    kind: LockKind,
+                    lock: FileLock,
+                    finish: DynMachineCallback<'tcx, Result<(), IoError>>,
+                }
+                |this, unblock: UnblockKind| {
+                    assert_eq!(unblock, UnblockKind::Ready);
+                    this.acquire_file_lock(file, kind, lock, /* blocking */ true, finish)
+                }
+            ),
+        );
+        interp_ok(())
+    }
+
+    /// Releases the locks `owner` holds on `[start, end)` of `file`. Returns whether any lock was
+    /// released.
+    fn release_file_lock(
+        &mut self,
+        file: FileId,
+        kind: LockKind,
+        owner: LockOwner,
+        start: u64,
+        end: u64,
+    ) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+        let unlocked = this.machine.file_locks.unlock(file, kind, owner, start, end);
+        if unlocked {
+            this.wake_file_lock_waiters(file)?;
+        }
+        interp_ok(unlocked)
+    }
+
+    /// Releases all locks held by `owner`, either on all files or only on `file`.
+    fn release_file_locks_of(
+        &mut self,
+        owner: LockOwner,
+        file: Option<FileId>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        for file in this.machine.file_locks.release_all(owner, file) {
+            this.wake_file_lock_waiters(file)?;
+        }
+        interp_ok(())
+    }
+
+    fn wake_file_lock_waiters(&mut self, file: FileId) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let waiters = this.machine.file_locks.waiters.remove(&file).unwrap_or_default();
+        for thread in waiters {
+            this.unblock_thread(thread, BlockReason::FileLock)?;
+        }
+        interp_ok(())
+    }
+}
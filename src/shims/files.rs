@@ -9,6 +9,7 @@ use std::{fs, io};
 
 use rustc_abi::Size;
 
+use crate::shims::file_lock::{EvalContextExt as _, LockOwner};
 use crate::shims::unix::UnixFileDescription;
 use crate::*;
 
@@ -109,6 +110,8 @@ impl<T: FileDescription + 'static> FileDescriptionExt for T {
             Some(fd) => {
                 // There might have been epolls interested in this FD. Remove that.
                 ecx.machine.epoll_interests.remove_epolls(fd.id);
+                // Locks owned by this description are released when it is closed.
+                ecx.release_file_locks_of(LockOwner::Description(fd.id), None)?;
 
                 fd.inner.destroy(fd.id, communicate_allowed, ecx)
             }
@@ -532,6 +535,20 @@ impl FdTable {
     pub fn is_fd_num(&self, fd_num: FdNum) -> bool {
         self.fds.contains_key(&fd_num)
     }
+
+    /// Makes all file descriptors referring to the file description `old` refer to `new` instead.
+    pub fn replace_description(&mut self, old: FdId, new: DynFileDescriptionRef) {
+        for fd in self.fds.values_mut() {
+            if fd.id() == old {
+                *fd = new.clone();
+            }
+        }
+    }
+
+    /// The unused file descriptor numbers that are at least `min_fd_num`, in increasing order.
+    pub fn free_fd_nums(&self, min_fd_num: FdNum) -> impl Iterator<Item = FdNum> {
+        (min_fd_num..=FdNum::MAX).filter(|fd_num| !self.fds.contains_key(fd_num))
+    }
 }
 
 impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
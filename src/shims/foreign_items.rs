@@ -19,6 +19,8 @@ use rustc_target::spec::{Arch, Os};
 
 use super::alloc::EvalContextExt as _;
 use super::backtrace::EvalContextExt as _;
+use super::printf::{EvalContextExt as _, FormatArgs};
+use super::stdlib::EvalContextExt as _;
 use crate::concurrency::GenmcEvalContextExt as _;
 use crate::helpers::EvalContextExt as _;
 use crate::*;
@@ -74,7 +76,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let dest = this.force_allocation(dest)?;
 
         // The rest either implements the logic, or falls back to `lookup_exported_symbol`.
-        match this.emulate_foreign_item_inner(link_name, abi, args, &dest)? {
+        match this.emulate_foreign_item_inner(link_name, abi, args, &dest, ret)? {
             EmulateItemResult::NeedsReturn => {
                 trace!("{:?}", this.dump_place(&dest.clone().into()));
                 this.return_to_block(ret)?;
@@ -252,6 +254,7 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
         abi: &FnAbi<'tcx, Ty<'tcx>>,
         args: &[OpTy<'tcx>],
         dest: &MPlaceTy<'tcx>,
+        ret: Option<mir::BasicBlock>,
     ) -> InterpResult<'tcx, EmulateItemResult> {
         let this = self.eval_context_mut();
 
@@ -531,17 +534,20 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
             "exit" => {
                 let [code] = this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
                 let code = this.read_scalar(code)?.to_i32()?;
-                if let Some(genmc_ctx) = this.machine.data_race.as_genmc_ref() {
-                    // If there is no error, execution should continue (on a different thread).
-                    genmc_ctx.handle_exit(
-                        this.machine.threads.active_thread(),
-                        code,
-                        crate::concurrency::ExitType::ExitCalled,
-                    )?;
-                    todo!(); // FIXME(genmc): Add a way to return here that is allowed to not do progress (can't use existing EmulateItemResult variants).
-                }
+                return this.exit(code, /* quick */ false, link_name.as_str());
+            }
+            "_exit" | "_Exit" => {
+                // Unlike `exit`, these do not run any exit handlers.
+                let [code] = this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let code = this.read_scalar(code)?.to_i32()?;
                 throw_machine_stop!(TerminationInfo::Exit { code, leak_check: false });
             }
+            "atexit" => {
+                let [func] = this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let func = this.read_pointer(func)?;
+                this.register_exit_handler(func, None, /* quick */ false)?;
+                this.write_int(0, dest)?;
+            }
             "abort" => {
                 let [] = this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
                 throw_machine_stop!(TerminationInfo::Abort(
@@ -747,6 +753,103 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 this.write_bytes_ptr(ptr_dest, bytes)?;
                 this.write_pointer(ptr_dest, dest)?;
             }
+            "strncmp" => {
+                let [left, right, n] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let left = this.read_pointer(left)?;
+                let right = this.read_pointer(right)?;
+                let n = this.read_target_usize(n)?;
+
+                // Compare byte by byte, since the strings need not be NUL-terminated within `n`
+                // bytes, and we must not read past the first difference or NUL.
+                let mut result = 0i32;
+                for idx in 0..n {
+                    let offset = Size::from_bytes(idx);
+                    let l = this.read_bytes_ptr_strip_provenance(
+                        left.wrapping_offset(offset, this),
+                        Size::from_bytes(1),
+                    )?[0];
+                    let r = this.read_bytes_ptr_strip_provenance(
+                        right.wrapping_offset(offset, this),
+                        Size::from_bytes(1),
+                    )?[0];
+                    if l != r {
+                        result = i32::from(l).strict_sub(i32::from(r)).signum();
+                        break;
+                    }
+                    if l == 0 {
+                        break;
+                    }
+                }
+                this.write_scalar(Scalar::from_i32(result), dest)?;
+            }
+            "strdup" | "_strdup" => {
+                let [ptr] = this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                // This reads at least 1 byte, so we are already enforcing that this is a valid pointer.
+                let n = this.read_c_str(ptr)?.len().strict_add(1).to_u64();
+                let res = this.malloc(n, AllocInit::Uninit)?;
+                if !this.ptr_is_null(res)? {
+                    this.mem_copy(ptr, res, Size::from_bytes(n), /*nonoverlapping*/ true)?;
+                }
+                this.write_pointer(res, dest)?;
+            }
+
+            // C stdlib and stdio functions
+            "strtol" | "strtoll" | "strtoul" | "strtoull" => {
+                let [nptr, endptr, base] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let signed = matches!(link_name.as_str(), "strtol" | "strtoll");
+                this.strtol(nptr, endptr, base, signed, dest)?;
+            }
+            "strtod" | "strtof" => {
+                let [nptr, endptr] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                this.strtod(nptr, endptr, dest, link_name.as_str())?;
+            }
+            "qsort" => {
+                let [base, nmemb, size, compar] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                return this.qsort(base, nmemb, size, compar, ret);
+            }
+            "bsearch" => {
+                let [key, base, nmemb, size, compar] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                return this.bsearch(key, base, nmemb, size, compar, dest, ret);
+            }
+            "snprintf" => {
+                let ([buf, size, fmt], varargs) =
+                    this.check_shim_sig_variadic_lenient(abi, CanonAbi::C, link_name, args)?;
+                let args = FormatArgs::Varargs(varargs);
+                let res = this.sprintf(buf, Some(size), fmt, args, link_name.as_str())?;
+                this.write_scalar(res, dest)?;
+            }
+            "sprintf" => {
+                let ([buf, fmt], varargs) =
+                    this.check_shim_sig_variadic_lenient(abi, CanonAbi::C, link_name, args)?;
+                let args = FormatArgs::Varargs(varargs);
+                let res = this.sprintf(buf, None, fmt, args, link_name.as_str())?;
+                this.write_scalar(res, dest)?;
+            }
+            "vsnprintf" => {
+                let [buf, size, fmt, va_list] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let args = FormatArgs::VaList(va_list);
+                let res = this.sprintf(buf, Some(size), fmt, args, link_name.as_str())?;
+                this.write_scalar(res, dest)?;
+            }
+            "vsprintf" => {
+                let [buf, fmt, va_list] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let args = FormatArgs::VaList(va_list);
+                let res = this.sprintf(buf, None, fmt, args, link_name.as_str())?;
+                this.write_scalar(res, dest)?;
+            }
+            "vfprintf" => {
+                throw_unsup_format!(
+                    "`vfprintf` is not supported, since Miri does not support `FILE` streams; use `vdprintf` instead"
+                );
+            }
 
             // LLVM intrinsics
             "llvm.prefetch" => {
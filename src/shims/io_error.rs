@@ -27,9 +27,13 @@ impl IoError {
                     ErrorKind::QuotaExceeded => 0xC000007F,
                     // STATUS_ACCESS_DENIED
                     ErrorKind::PermissionDenied => 0xC0000022,
+                    // STATUS_PIPE_BROKEN
+                    ErrorKind::BrokenPipe => 0xC000014B,
                     // For the default error code we arbitrarily pick 0xC0000185, STATUS_IO_DEVICE_ERROR.
                     _ => 0xC0000185,
                 },
+            // STATUS_PIPE_LISTENING
+            WindowsError("ERROR_PIPE_LISTENING") => 0xC00000B3,
             // For the default error code we arbitrarily pick 0xC0000185, STATUS_IO_DEVICE_ERROR.
             _ => 0xC0000185,
         };
@@ -193,15 +197,22 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         }
     }
 
-    /// Sets the last error variable.
-    fn set_last_error(&mut self, err: impl Into<IoError>) -> InterpResult<'tcx> {
-        let this = self.eval_context_mut();
-        let errno = match err.into() {
+    /// Returns the error code for `err`, i.e. what the last error variable would be set to. This is
+    /// for functions like `posix_spawn` that return the error code instead of setting `errno`.
+    fn io_error_to_errnum_scalar(&self, err: impl Into<IoError>) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_ref();
+        interp_ok(match err.into() {
             HostError(err) => this.io_error_to_errnum(err)?,
             LibcError(name) => this.eval_libc(name),
             WindowsError(name) => this.eval_windows("c", name),
             Raw(val) => val,
-        };
+        })
+    }
+
+    /// Sets the last error variable.
+    fn set_last_error(&mut self, err: impl Into<IoError>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let errno = this.io_error_to_errnum_scalar(err)?;
         let errno_place = this.last_error_place()?;
         this.write_scalar(errno, &errno_place)
     }
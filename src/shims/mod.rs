@@ -3,15 +3,22 @@
 mod aarch64;
 mod alloc;
 mod backtrace;
+mod file_lock;
 mod files;
 mod math;
 #[cfg(all(unix, feature = "native-lib"))]
 mod native_lib;
+mod network;
+mod printf;
+mod process;
+mod stdlib;
 mod unix;
 mod windows;
 mod x86;
 
+pub mod calendar;
 pub mod env;
+pub mod exit;
 pub mod extern_static;
 pub mod foreign_items;
 pub mod global_ctor;
@@ -23,10 +30,16 @@ pub mod time;
 pub mod tls;
 pub mod unwind;
 
+pub use self::alloc::AllocFailures;
+pub use self::exit::ExitHandlers;
+pub use self::file_lock::FileLocks;
 pub use self::files::FdTable;
 #[cfg(all(unix, feature = "native-lib"))]
 pub use self::native_lib::trace::{init_sv, register_retcode_sv};
-pub use self::unix::{DirTable, EpollInterestTable};
+pub use self::network::LoopbackNetwork;
+pub use self::process::ProcessTable;
+pub use self::unix::{DirTable, EpollInterestTable, LocaleState, ResourceLimit, ResourceLimits};
+pub use self::windows::{WindowsConsole, WindowsThreadPool};
 
 /// What needs to be done after emulating an item (a shim or an intrinsic) is done.
 pub enum EmulateItemResult {
@@ -0,0 +1,756 @@
+//! A loopback-only network shared by all threads of the interpreted program.
+//!
+//! Sockets can bind to loopback (or unspecified) addresses and talk to each other over TCP-like
+//! streams and UDP-like datagrams, but they never reach the host network. The model is
+//! OS-independent: the OS-specific shims translate their socket APIs to the operations here, and
+//! map [`NetError`] to their own error codes.
+//!
+//! Blocking operations register the thread as a network waiter. Any change of the network state
+//! (new data, a new connection, a shutdown or a closed socket) wakes up all waiters, which then
+//! retry their operation.
+
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::time::Duration;
+
+use rustc_data_structures::fx::FxHashMap;
+
+use crate::concurrency::VClock;
+use crate::shims::files::{FdId, FileDescription, FileDescriptionRef, WeakFileDescriptionRef};
+use crate::*;
+
+/// The first port handed out when binding to port 0.
+const EPHEMERAL_PORT_START: u16 = 49152;
+
+/// The largest payload of a UDP datagram over IPv4.
+const MAX_DATAGRAM_SIZE: usize = 65507;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SocketType {
+    Stream,
+    Datagram,
+}
+
+/// The errors of socket operations, to be translated by the OS-specific shims.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetError {
+    /// The address is already bound by another socket.
+    AddrInUse,
+    /// The address is not a local address, or cannot be connected to.
+    AddrNotAvailable,
+    /// The stream socket is already connected.
+    AlreadyConnected,
+    /// The socket was closed while the operation was blocked on it.
+    Interrupted,
+    /// Nobody listens at the address.
+    ConnectionRefused,
+    /// The other end of the connection is gone.
+    ConnectionReset,
+    /// The operation does not make sense in the socket's current state.
+    InvalidInput,
+    /// The datagram is too large.
+    MessageTooLong,
+    /// The address is not reachable over the loopback network.
+    NetworkUnreachable,
+    /// The stream socket is not connected.
+    NotConnected,
+    /// This direction of the connection was shut down.
+    Shutdown,
+    /// The read timeout of the socket expired.
+    TimedOut,
+    /// The socket is non-blocking, and the operation would block.
+    WouldBlock,
+}
+
+/// The data returned by a receive operation.
+#[derive(Debug)]
+pub struct Received {
+    pub data: Vec<u8>,
+    /// The sender, for datagram sockets.
+    pub from: Option<SocketAddr>,
+    /// Whether the datagram was longer than the buffer, and the rest of it was discarded.
+    pub truncated: bool,
+}
+
+/// What `select`-like functions report about a socket.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Readiness {
+    /// Receiving (or accepting) would not block.
+    pub readable: bool,
+    /// Sending would not block.
+    pub writable: bool,
+}
+
+#[derive(Debug)]
+struct Datagram {
+    from: SocketAddr,
+    data: Vec<u8>,
+    clock: VClock,
+}
+
+#[derive(Debug, Default)]
+struct StreamBuffer {
+    buf: VecDeque<u8>,
+    clock: VClock,
+}
+
+/// A socket of the loopback network.
+#[derive(Debug)]
+pub struct LoopbackSocket {
+    pub ty: SocketType,
+    /// Whether this is an IPv6 socket.
+    pub ipv6: bool,
+    local: Cell<Option<SocketAddr>>,
+    /// The address this socket is connected to.
+    peer: Cell<Option<SocketAddr>>,
+    /// For connected stream sockets, the other end of the connection.
+    stream_peer: RefCell<Option<WeakFileDescriptionRef<LoopbackSocket>>>,
+    /// For listening stream sockets, the connections that were not accepted yet.
+    backlog: RefCell<Option<VecDeque<FileDescriptionRef<LoopbackSocket>>>>,
+    /// For stream sockets, the data that was sent to this socket and not received yet.
+    stream_buf: RefCell<StreamBuffer>,
+    /// For datagram sockets, the datagrams that were sent to this socket and not received yet.
+    datagrams: RefCell<VecDeque<Datagram>>,
+    /// Whether receiving was shut down.
+    read_shutdown: Cell<bool>,
+    /// Whether sending was shut down.
+    write_shutdown: Cell<bool>,
+    pub nonblocking: Cell<bool>,
+    /// How long receiving blocks before it fails with [`NetError::TimedOut`].
+    pub read_timeout: Cell<Option<Duration>>,
+    /// The send timeout. Sending never blocks on the loopback network, so this is only stored.
+    pub write_timeout: Cell<Option<Duration>>,
+    /// Socket options that do not affect the loopback network, as set by the program. They are
+    /// interpreted by the OS-specific shims.
+    pub options: RefCell<FxHashMap<(i32, i32), Vec<u8>>>,
+}
+
+impl LoopbackSocket {
+    pub fn new(ty: SocketType, ipv6: bool) -> Self {
+        LoopbackSocket {
+            ty,
+            ipv6,
+            local: Cell::new(None),
+            peer: Cell::new(None),
+            stream_peer: RefCell::new(None),
+            backlog: RefCell::new(None),
+            stream_buf: RefCell::default(),
+            datagrams: RefCell::default(),
+            read_shutdown: Cell::new(false),
+            write_shutdown: Cell::new(false),
+            nonblocking: Cell::new(false),
+            read_timeout: Cell::new(None),
+            write_timeout: Cell::new(None),
+            options: RefCell::default(),
+        }
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local.get()
+    }
+
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer.get()
+    }
+
+    pub fn is_listening(&self) -> bool {
+        self.backlog.borrow().is_some()
+    }
+
+    /// Whether the other end of the connection will not send anything any more.
+    fn peer_finished(&self) -> bool {
+        match self.stream_peer.borrow().as_ref().map(|peer| peer.upgrade()) {
+            Some(Some(peer)) => peer.write_shutdown.get(),
+            Some(None) => true,
+            None => false,
+        }
+    }
+
+    /// How many bytes can be received without blocking: the buffered stream data, or the size of
+    /// the next datagram.
+    pub fn available(&self) -> usize {
+        match self.ty {
+            SocketType::Stream => self.stream_buf.borrow().buf.len(),
+            SocketType::Datagram => self.datagrams.borrow().front().map_or(0, |d| d.data.len()),
+        }
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        match self.ty {
+            SocketType::Stream if self.is_listening() =>
+                Readiness {
+                    readable: self.backlog.borrow().as_ref().is_some_and(|b| !b.is_empty()),
+                    writable: false,
+                },
+            SocketType::Stream if self.stream_peer.borrow().is_some() =>
+                Readiness {
+                    readable: !self.stream_buf.borrow().buf.is_empty()
+                        || self.read_shutdown.get()
+                        || self.peer_finished(),
+                    writable: true,
+                },
+            SocketType::Stream => Readiness::default(),
+            SocketType::Datagram =>
+                Readiness { readable: !self.datagrams.borrow().is_empty(), writable: true },
+        }
+    }
+}
+
+impl FileDescription for LoopbackSocket {
+    fn name(&self) -> &'static str {
+        "socket"
+    }
+
+    fn destroy<'tcx>(
+        self,
+        _self_id: FdId,
+        _communicate_allowed: bool,
+        ecx: &mut MiriInterpCx<'tcx>,
+    ) -> InterpResult<'tcx, io::Result<()>> {
+        // Connections that were never accepted are reset.
+        drop(self.backlog.take());
+        // Release the address, and let the peer and blocked threads notice that we are gone.
+        ecx.machine.network.bound.retain(|_, socket| socket.upgrade().is_some());
+        ecx.wake_network_waiters()?;
+        interp_ok(Ok(()))
+    }
+}
+
+/// The state of the loopback network.
+#[derive(Debug)]
+pub struct LoopbackNetwork {
+    /// The bound sockets, by type and local address.
+    bound: FxHashMap<(SocketType, SocketAddr), WeakFileDescriptionRef<LoopbackSocket>>,
+    /// The next port to try when binding to port 0.
+    next_ephemeral_port: u16,
+    /// The threads blocked on a socket operation.
+    waiters: Vec<ThreadId>,
+}
+
+impl Default for LoopbackNetwork {
+    fn default() -> Self {
+        LoopbackNetwork {
+            bound: FxHashMap::default(),
+            next_ephemeral_port: EPHEMERAL_PORT_START,
+            waiters: Vec::new(),
+        }
+    }
+}
+
+impl LoopbackNetwork {
+    /// Finds the socket bound to a local address that a packet to `addr` is delivered to.
+    fn lookup(
+        &self,
+        ty: SocketType,
+        addr: SocketAddr,
+    ) -> Option<FileDescriptionRef<LoopbackSocket>> {
+        let unspecified = SocketAddr::new(unspecified_ip(addr.is_ipv6()), addr.port());
+        self.bound
+            .get(&(ty, addr))
+            .or_else(|| self.bound.get(&(ty, unspecified)))
+            .and_then(|socket| socket.upgrade())
+    }
+
+    /// Whether binding to `addr` would conflict with an existing binding.
+    fn in_use(&self, ty: SocketType, addr: SocketAddr) -> bool {
+        self.bound.iter().any(|((bound_ty, bound), socket)| {
+            *bound_ty == ty
+                && bound.port() == addr.port()
+                && bound.is_ipv6() == addr.is_ipv6()
+                && (bound.ip() == addr.ip()
+                    || bound.ip().is_unspecified()
+                    || addr.ip().is_unspecified())
+                && socket.upgrade().is_some()
+        })
+    }
+
+    fn ephemeral_port(&mut self, ty: SocketType, ip: IpAddr) -> Option<u16> {
+        let ports = u16::MAX.strict_sub(EPHEMERAL_PORT_START);
+        for _ in 0..=ports {
+            let port = self.next_ephemeral_port;
+            self.next_ephemeral_port = port.checked_add(1).unwrap_or(EPHEMERAL_PORT_START);
+            if !self.in_use(ty, SocketAddr::new(ip, port)) {
+                return Some(port);
+            }
+        }
+        None
+    }
+}
+
+fn unspecified_ip(ipv6: bool) -> IpAddr {
+    if ipv6 { Ipv6Addr::UNSPECIFIED.into() } else { Ipv4Addr::UNSPECIFIED.into() }
+}
+
+fn loopback_ip(ipv6: bool) -> IpAddr {
+    if ipv6 { Ipv6Addr::LOCALHOST.into() } else { Ipv4Addr::LOCALHOST.into() }
+}
+
+/// Checks that `addr` can be sent to over the loopback network.
+fn check_reachable(socket: &LoopbackSocket, addr: SocketAddr) -> Result<(), NetError> {
+    if addr.is_ipv6() != socket.ipv6 {
+        return Err(NetError::InvalidInput);
+    }
+    if addr.ip().is_unspecified() || addr.port() == 0 {
+        return Err(NetError::AddrNotAvailable);
+    }
+    if !addr.ip().is_loopback() {
+        return Err(NetError::NetworkUnreachable);
+    }
+    Ok(())
+}
+
+/// Resolves a host name for `getaddrinfo`-like functions. Only numeric addresses and `localhost`
+/// are known.
+pub fn resolve_host(name: &str) -> Option<Vec<IpAddr>> {
+    if let Ok(ip) = name.parse::<IpAddr>() {
+        return Some(vec![ip]);
+    }
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if name.eq_ignore_ascii_case("localhost") {
+        return Some(vec![loopback_ip(false), loopback_ip(true)]);
+    }
+    None
+}
+
+/// Decodes a `sockaddr_in` or `sockaddr_in6` in the layout used by Linux and Windows: a
+/// native-endian 16-bit family followed by the port and address in network byte order.
+pub fn sockaddr_from_bytes(bytes: &[u8], af_inet: u16, af_inet6: u16) -> Option<SocketAddr> {
+    let family = u16::from_ne_bytes(bytes.get(0..2)?.try_into().unwrap());
+    let port = u16::from_be_bytes(bytes.get(2..4)?.try_into().unwrap());
+    if family == af_inet {
+        let ip: [u8; 4] = bytes.get(4..8)?.try_into().unwrap();
+        Some(SocketAddrV4::new(ip.into(), port).into())
+    } else if family == af_inet6 {
+        let flowinfo = u32::from_be_bytes(bytes.get(4..8)?.try_into().unwrap());
+        let ip: [u8; 16] = bytes.get(8..24)?.try_into().unwrap();
+        let scope_id = u32::from_ne_bytes(bytes.get(24..28)?.try_into().unwrap());
+        Some(SocketAddrV6::new(ip.into(), port, flowinfo, scope_id).into())
+    } else {
+        None
+    }
+}
+
+/// Encodes an address in the layout decoded by [`sockaddr_from_bytes`].
+pub fn sockaddr_to_bytes(addr: SocketAddr, af_inet: u16, af_inet6: u16) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    match addr {
+        SocketAddr::V4(addr) => {
+            bytes.extend(af_inet.to_ne_bytes());
+            bytes.extend(addr.port().to_be_bytes());
+            bytes.extend(addr.ip().octets());
+            bytes.extend([0; 8]);
+        }
+        SocketAddr::V6(addr) => {
+            bytes.extend(af_inet6.to_ne_bytes());
+            bytes.extend(addr.port().to_be_bytes());
+            bytes.extend(addr.flowinfo().to_be_bytes());
+            bytes.extend(addr.ip().octets());
+            bytes.extend(addr.scope_id().to_ne_bytes());
+        }
+    }
+    bytes
+}
+
+impl<'tcx> EvalContextExtPriv<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Binds the socket to a free ephemeral port if it is not bound yet.
+    fn autobind(&mut self, socket: &FileDescriptionRef<LoopbackSocket>) -> Result<(), NetError> {
+        let this = self.eval_context_mut();
+        if socket.local.get().is_some() {
+            return Ok(());
+        }
+        let ip = loopback_ip(socket.ipv6);
+        let port =
+            this.machine.network.ephemeral_port(socket.ty, ip).ok_or(NetError::AddrNotAvailable)?;
+        let addr = SocketAddr::new(ip, port);
+        socket.local.set(Some(addr));
+        this.machine.network.bound.insert((socket.ty, addr), FileDescriptionRef::downgrade(socket));
+        Ok(())
+    }
+
+    fn socket_recv_until(
+        &mut self,
+        socket: FileDescriptionRef<LoopbackSocket>,
+        len: usize,
+        peek: bool,
+        deadline: Option<Duration>,
+        finish: DynMachineCallback<'tcx, Result<Received, NetError>>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let received = match socket.ty {
+            SocketType::Stream => {
+                if socket.read_shutdown.get() {
+                    return finish.call(this, Err(NetError::Shutdown));
+                }
+                if socket.stream_peer.borrow().is_none() {
+                    return finish.call(this, Err(NetError::NotConnected));
+                }
+                let mut stream_buf = socket.stream_buf.borrow_mut();
+                if !stream_buf.buf.is_empty() || len == 0 {
+                    // Synchronize with all previous sends to this socket.
+                    this.acquire_clock(&stream_buf.clock)?;
+                    let size = len.min(stream_buf.buf.len());
+                    let data = if peek {
+                        stream_buf.buf.range(..size).copied().collect()
+                    } else {
+                        stream_buf.buf.drain(..size).collect()
+                    };
+                    Some(Received { data, from: None, truncated: false })
+                } else if socket.peer_finished() {
+                    // End of file.
+                    Some(Received { data: Vec::new(), from: None, truncated: false })
+                } else {
+                    None
+                }
+            }
+            SocketType::Datagram => {
+                if socket.local.get().is_none() {
+                    return finish.call(this, Err(NetError::InvalidInput));
+                }
+                let mut datagrams = socket.datagrams.borrow_mut();
+                if let Some(datagram) = datagrams.front() {
+                    this.acquire_clock(&datagram.clock)?;
+                    let truncated = datagram.data.len() > len;
+                    let data = datagram.data[..len.min(datagram.data.len())].to_vec();
+                    let from = Some(datagram.from);
+                    if !peek {
+                        datagrams.pop_front();
+                    }
+                    Some(Received { data, from, truncated })
+                } else {
+                    None
+                }
+            }
+        };
+        if let Some(received) = received {
+            return finish.call(this, Ok(received));
+        }
+
+        if socket.nonblocking.get() {
+            return finish.call(this, Err(NetError::WouldBlock));
+        }
+        let weak_socket = FileDescriptionRef::downgrade(&socket);
+        this.block_on_network(
+            deadline,
+            callback!(
+                @capture<'tcx> {
+                    weak_socket: WeakFileDescriptionRef<LoopbackSocket>,
+                    len: usize,
+                    peek: bool,
+                    deadline: Option<Duration>,
+                    finish: DynMachineCallback<'tcx, Result<Received, NetError>>,
+                }
+                |this, unblock: UnblockKind| {
+                    let Some(socket) = weak_socket.upgrade() else {
+                        return finish.call(this, Err(NetError::Interrupted));
+                    };
+                    match unblock {
+                        UnblockKind::Ready =>
+                            this.socket_recv_until(socket, len, peek, deadline, finish),
+                        UnblockKind::TimedOut => finish.call(this, Err(NetError::TimedOut)),
+                    }
+                }
+            ),
+        );
+        interp_ok(())
+    }
+
+    fn socket_accept_until(
+        &mut self,
+        socket: FileDescriptionRef<LoopbackSocket>,
+        deadline: Option<Duration>,
+        finish: DynMachineCallback<'tcx, Result<FileDescriptionRef<LoopbackSocket>, NetError>>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let connection = match &mut *socket.backlog.borrow_mut() {
+            None => return finish.call(this, Err(NetError::InvalidInput)),
+            Some(backlog) => backlog.pop_front(),
+        };
+        if let Some(connection) = connection {
+            return finish.call(this, Ok(connection));
+        }
+
+        if socket.nonblocking.get() {
+            return finish.call(this, Err(NetError::WouldBlock));
+        }
+        let weak_socket = FileDescriptionRef::downgrade(&socket);
+        this.block_on_network(
+            deadline,
+            callback!(
+                @capture<'tcx> {
+                    weak_socket: WeakFileDescriptionRef<LoopbackSocket>,
+                    deadline: Option<Duration>,
+                    finish: DynMachineCallback<'tcx, Result<FileDescriptionRef<LoopbackSocket>, NetError>>,
+                }
+                |this, unblock: UnblockKind| {
+                    let Some(socket) = weak_socket.upgrade() else {
+                        return finish.call(this, Err(NetError::Interrupted));
+                    };
+                    match unblock {
+                        UnblockKind::Ready => this.socket_accept_until(socket, deadline, finish),
+                        UnblockKind::TimedOut => finish.call(this, Err(NetError::TimedOut)),
+                    }
+                }
+            ),
+        );
+        interp_ok(())
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Wakes up all threads blocked on the network, so they can check whether they can make
+    /// progress now.
+    fn wake_network_waiters(&mut self) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        for thread in std::mem::take(&mut this.machine.network.waiters) {
+            this.unblock_thread(thread, BlockReason::Network)?;
+        }
+        interp_ok(())
+    }
+
+    /// Blocks the active thread until the network changes or the deadline passes, and then
+    /// calls `retry`. The deadline is relative to the epoch of the monotonic clock.
+    fn block_on_network(&mut self, deadline: Option<Duration>, retry: DynUnblockCallback<'tcx>) {
+        let this = self.eval_context_mut();
+        let thread = this.active_thread();
+        this.machine.network.waiters.push(thread);
+        let now = this.machine.monotonic_clock.elapsed();
+        let timeout = deadline.map(|deadline| {
+            (TimeoutClock::Monotonic, TimeoutAnchor::Relative, deadline.saturating_sub(now))
+        });
+        this.block_thread(
+            BlockReason::Network,
+            timeout,
+            callback!(
+                @capture<'tcx> {
+                    retry: DynUnblockCallback<'tcx>,
+                }
+                |this, unblock: UnblockKind| {
+                    if unblock == UnblockKind::TimedOut {
+                        let thread = this.active_thread();
+                        this.machine.network.waiters.retain(|&waiter| waiter != thread);
+                    }
+                    retry.call(this, unblock)
+                }
+            ),
+        );
+    }
+
+    fn socket_bind(
+        &mut self,
+        socket: &FileDescriptionRef<LoopbackSocket>,
+        addr: SocketAddr,
+    ) -> Result<(), NetError> {
+        let this = self.eval_context_mut();
+        if socket.local.get().is_some() || addr.is_ipv6() != socket.ipv6 {
+            return Err(NetError::InvalidInput);
+        }
+        if !addr.ip().is_loopback() && !addr.ip().is_unspecified() {
+            return Err(NetError::AddrNotAvailable);
+        }
+        let network = &mut this.machine.network;
+        let port = if addr.port() == 0 {
+            network.ephemeral_port(socket.ty, addr.ip()).ok_or(NetError::AddrNotAvailable)?
+        } else if network.in_use(socket.ty, addr) {
+            return Err(NetError::AddrInUse);
+        } else {
+            addr.port()
+        };
+        let addr = SocketAddr::new(addr.ip(), port);
+        socket.local.set(Some(addr));
+        network.bound.insert((socket.ty, addr), FileDescriptionRef::downgrade(socket));
+        Ok(())
+    }
+
+    fn socket_listen(
+        &mut self,
+        socket: &FileDescriptionRef<LoopbackSocket>,
+    ) -> Result<(), NetError> {
+        if socket.ty != SocketType::Stream
+            || socket.local.get().is_none()
+            || socket.stream_peer.borrow().is_some()
+        {
+            return Err(NetError::InvalidInput);
+        }
+        let mut backlog = socket.backlog.borrow_mut();
+        if backlog.is_none() {
+            *backlog = Some(VecDeque::new());
+        }
+        Ok(())
+    }
+
+    /// Connects the socket. On the loopback network, stream connections are established right
+    /// away, even for non-blocking sockets.
+    fn socket_connect(
+        &mut self,
+        socket: &FileDescriptionRef<LoopbackSocket>,
+        addr: SocketAddr,
+    ) -> InterpResult<'tcx, Result<(), NetError>> {
+        let this = self.eval_context_mut();
+        if let Err(err) = check_reachable(socket, addr) {
+            return interp_ok(Err(err));
+        }
+
+        if socket.ty == SocketType::Datagram {
+            // This only sets the default destination and filters what we receive.
+            if let Err(err) = this.autobind(socket) {
+                return interp_ok(Err(err));
+            }
+            socket.peer.set(Some(addr));
+            return interp_ok(Ok(()));
+        }
+
+        if socket.is_listening() {
+            return interp_ok(Err(NetError::InvalidInput));
+        }
+        if socket.stream_peer.borrow().is_some() {
+            return interp_ok(Err(NetError::AlreadyConnected));
+        }
+        let Some(listener) =
+            this.machine.network.lookup(SocketType::Stream, addr).filter(|l| l.is_listening())
+        else {
+            return interp_ok(Err(NetError::ConnectionRefused));
+        };
+        if let Err(err) = this.autobind(socket) {
+            return interp_ok(Err(err));
+        }
+
+        // The listener's end of the connection, handed out by `accept`. It inherits the
+        // properties of the listener.
+        let server = this.machine.fds.new_ref(LoopbackSocket::new(SocketType::Stream, socket.ipv6));
+        server.local.set(Some(SocketAddr::new(addr.ip(), addr.port())));
+        server.peer.set(socket.local.get());
+        server.nonblocking.set(listener.nonblocking.get());
+        *server.options.borrow_mut() = listener.options.borrow().clone();
+        *server.stream_peer.borrow_mut() = Some(FileDescriptionRef::downgrade(socket));
+        socket.peer.set(Some(addr));
+        *socket.stream_peer.borrow_mut() = Some(FileDescriptionRef::downgrade(&server));
+
+        listener.backlog.borrow_mut().as_mut().unwrap().push_back(server);
+        this.wake_network_waiters()?;
+        interp_ok(Ok(()))
+    }
+
+    /// Accepts a connection. Blocks unless the socket is non-blocking.
+    fn socket_accept(
+        &mut self,
+        socket: FileDescriptionRef<LoopbackSocket>,
+        finish: DynMachineCallback<'tcx, Result<FileDescriptionRef<LoopbackSocket>, NetError>>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        this.socket_accept_until(socket, None, finish)
+    }
+
+    /// Receives up to `len` bytes, or one datagram. Blocks unless the socket is non-blocking, or
+    /// until its read timeout expires.
+    fn socket_recv(
+        &mut self,
+        socket: FileDescriptionRef<LoopbackSocket>,
+        len: usize,
+        peek: bool,
+        finish: DynMachineCallback<'tcx, Result<Received, NetError>>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let deadline = socket
+            .read_timeout
+            .get()
+            .map(|timeout| this.machine.monotonic_clock.elapsed().saturating_add(timeout));
+        this.socket_recv_until(socket, len, peek, deadline, finish)
+    }
+
+    /// Sends data on a connected socket. Sending never blocks on the loopback network.
+    fn socket_send(
+        &mut self,
+        socket: &FileDescriptionRef<LoopbackSocket>,
+        data: &[u8],
+    ) -> InterpResult<'tcx, Result<usize, NetError>> {
+        let this = self.eval_context_mut();
+        if socket.ty == SocketType::Datagram {
+            let Some(peer) = socket.peer.get() else {
+                return interp_ok(Err(NetError::NotConnected));
+            };
+            return this.socket_send_to(socket, data, peer);
+        }
+
+        if socket.write_shutdown.get() {
+            return interp_ok(Err(NetError::Shutdown));
+        }
+        let Some(peer) = socket.stream_peer.borrow().as_ref().map(|peer| peer.upgrade()) else {
+            return interp_ok(Err(NetError::NotConnected));
+        };
+        let Some(peer) = peer else {
+            return interp_ok(Err(NetError::ConnectionReset));
+        };
+        let mut stream_buf = peer.stream_buf.borrow_mut();
+        // Remember this clock so that receiving synchronizes with us.
+        this.release_clock(|clock| stream_buf.clock.join(clock))?;
+        stream_buf.buf.extend(data);
+        drop(stream_buf);
+        this.wake_network_waiters()?;
+        interp_ok(Ok(data.len()))
+    }
+
+    /// Sends a datagram to `addr`. Like real UDP, datagrams sent to an address nobody is bound to
+    /// are silently dropped. Stream sockets ignore the address.
+    fn socket_send_to(
+        &mut self,
+        socket: &FileDescriptionRef<LoopbackSocket>,
+        data: &[u8],
+        addr: SocketAddr,
+    ) -> InterpResult<'tcx, Result<usize, NetError>> {
+        let this = self.eval_context_mut();
+        if socket.ty == SocketType::Stream {
+            return this.socket_send(socket, data);
+        }
+        if data.len() > MAX_DATAGRAM_SIZE {
+            return interp_ok(Err(NetError::MessageTooLong));
+        }
+        if let Err(err) = check_reachable(socket, addr).and_then(|()| this.autobind(socket)) {
+            return interp_ok(Err(err));
+        }
+        let from = socket.local.get().unwrap();
+        let from = SocketAddr::new(
+            if from.ip().is_unspecified() { loopback_ip(socket.ipv6) } else { from.ip() },
+            from.port(),
+        );
+
+        if let Some(target) = this.machine.network.lookup(SocketType::Datagram, addr) {
+            // Connected datagram sockets only receive from their peer.
+            if target.peer.get().is_none_or(|peer| peer == from) {
+                let clock = this.release_clock(|clock| clock.clone())?.unwrap_or_default();
+                target.datagrams.borrow_mut().push_back(Datagram {
+                    from,
+                    data: data.to_vec(),
+                    clock,
+                });
+                this.wake_network_waiters()?;
+            }
+        }
+        interp_ok(Ok(data.len()))
+    }
+
+    fn socket_shutdown(
+        &mut self,
+        socket: &FileDescriptionRef<LoopbackSocket>,
+        read: bool,
+        write: bool,
+    ) -> InterpResult<'tcx, Result<(), NetError>> {
+        let this = self.eval_context_mut();
+        if socket.ty == SocketType::Stream && socket.stream_peer.borrow().is_none() {
+            return interp_ok(Err(NetError::NotConnected));
+        }
+        if read {
+            socket.read_shutdown.set(true);
+        }
+        if write {
+            socket.write_shutdown.set(true);
+        }
+        this.wake_network_waiters()?;
+        interp_ok(Ok(()))
+    }
+}
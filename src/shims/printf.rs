@@ -0,0 +1,829 @@
+//! The `printf` family of functions, formatting C varargs or a `va_list` against interpreter memory.
+
+use rustc_abi::{Align, Size};
+use rustc_middle::mir::interpret::AllocInit;
+use rustc_target::spec::{Arch, Os};
+
+use crate::helpers::ToHost;
+use crate::*;
+
+/// We refuse to format fields wider or more precise than this, to not exhaust host memory.
+const MAX_FIELD: u64 = 1 << 20;
+
+/// The length modifier of a conversion specification.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Length {
+    None,
+    /// `hh`
+    Char,
+    /// `h`
+    Short,
+    /// `l`
+    Long,
+    /// `ll`
+    LongLong,
+    /// `j`
+    IntMax,
+    /// `z`
+    Size,
+    /// `t`
+    PtrDiff,
+}
+
+/// A field width or precision.
+#[derive(Clone, Copy, Debug)]
+enum Count {
+    Absent,
+    Given(u64),
+    /// `*`: taken from the next argument.
+    FromArg,
+}
+
+/// A parsed conversion specification, such as `%-08.3lx`.
+#[derive(Clone, Debug)]
+struct Conversion {
+    /// The specification as written in the format string, for error messages.
+    text: String,
+    left: bool,
+    plus: bool,
+    space: bool,
+    alt: bool,
+    zero: bool,
+    width: Count,
+    precision: Count,
+    length: Length,
+    /// The conversion specifier, e.g. `b'd'`.
+    specifier: u8,
+}
+
+impl Conversion {
+    /// How many arguments this conversion consumes.
+    fn arg_count(&self) -> usize {
+        let counts = [
+            matches!(self.width, Count::FromArg),
+            matches!(self.precision, Count::FromArg),
+            self.specifier != b'%',
+        ];
+        counts.into_iter().filter(|&c| c).count()
+    }
+
+    fn is_signed(&self) -> bool {
+        matches!(self.specifier, b'd' | b'i')
+    }
+}
+
+#[derive(Debug)]
+enum Piece {
+    Literal(Vec<u8>),
+    Conversion(Conversion),
+}
+
+/// The kind of argument a conversion expects, after the default argument promotions.
+#[derive(Clone, Copy, Debug)]
+enum ArgKind {
+    Int(Size),
+    Double,
+    Pointer,
+}
+
+/// Where the arguments of a `printf`-like function come from.
+#[derive(Clone, Copy)]
+pub enum FormatArgs<'a, 'tcx> {
+    /// The variadic arguments of the call itself.
+    Varargs(&'a [OpTy<'tcx>]),
+    /// A `va_list`, as taken by `vprintf` and friends.
+    VaList(&'a OpTy<'tcx>),
+}
+
+/// The arguments that have not been consumed yet.
+enum ArgCursor<'a, 'tcx> {
+    Varargs(std::slice::Iter<'a, OpTy<'tcx>>),
+    VaList(VaList),
+}
+
+/// A cursor into a `va_list`, following the target's C calling convention. Unlike varargs, a
+/// `va_list` carries no types, so we read whatever is stored where an argument of the type the
+/// conversion expects would be.
+enum VaList {
+    /// Windows, Apple AArch64, 32-bit x86 and ARM: a `char *` to the arguments, each in a slot
+    /// whose size is rounded up to the pointer size.
+    Slots(Pointer),
+    /// x86-64 System V: `{ gp_offset, fp_offset, overflow_arg_area, reg_save_area }`.
+    X86_64 { gp_offset: u64, fp_offset: u64, overflow: Pointer, reg_save: Pointer },
+    /// AArch64: `{ __stack, __gr_top, __vr_top, __gr_offs, __vr_offs }`.
+    AArch64 { stack: Pointer, gr_top: Pointer, vr_top: Pointer, gr_offs: i64, vr_offs: i64 },
+}
+
+/// Parses a run of decimal digits starting at `*pos`.
+fn parse_digits(fmt: &[u8], pos: &mut usize) -> Option<u64> {
+    let digits = fmt[*pos..].iter().take_while(|b| b.is_ascii_digit()).count();
+    if digits == 0 {
+        return None;
+    }
+    let value = fmt[*pos..]
+        .iter()
+        .take(digits)
+        .fold(0u64, |acc, &b| acc.saturating_mul(10).saturating_add(u64::from(b.strict_sub(b'0'))));
+    *pos = pos.strict_add(digits);
+    Some(value)
+}
+
+/// Splits a format string into literal text and conversion specifications.
+fn parse_format<'tcx>(fmt: &[u8], name: &str) -> InterpResult<'tcx, Vec<Piece>> {
+    let mut pieces = Vec::new();
+    let mut pos = 0;
+    while let Some(offset) = fmt[pos..].iter().position(|&b| b == b'%') {
+        let start = pos.strict_add(offset);
+        if start > pos {
+            pieces.push(Piece::Literal(fmt[pos..start].to_vec()));
+        }
+        pos = start.strict_add(1);
+
+        let mut conv = Conversion {
+            text: String::new(),
+            left: false,
+            plus: false,
+            space: false,
+            alt: false,
+            zero: false,
+            width: Count::Absent,
+            precision: Count::Absent,
+            length: Length::None,
+            specifier: 0,
+        };
+        while let Some(&b) = fmt.get(pos) {
+            match b {
+                b'-' => conv.left = true,
+                b'+' => conv.plus = true,
+                b' ' => conv.space = true,
+                b'#' => conv.alt = true,
+                b'0' => conv.zero = true,
+                _ => break,
+            }
+            pos = pos.strict_add(1);
+        }
+        if fmt.get(pos) == Some(&b'*') {
+            conv.width = Count::FromArg;
+            pos = pos.strict_add(1);
+        } else if let Some(width) = parse_digits(fmt, &mut pos) {
+            conv.width = Count::Given(width);
+        }
+        if fmt.get(pos) == Some(&b'.') {
+            pos = pos.strict_add(1);
+            if fmt.get(pos) == Some(&b'*') {
+                conv.precision = Count::FromArg;
+                pos = pos.strict_add(1);
+            } else {
+                // A lone `.` means a precision of zero.
+                conv.precision = Count::Given(parse_digits(fmt, &mut pos).unwrap_or(0));
+            }
+        }
+        let (length, len) = match (fmt.get(pos), fmt.get(pos.strict_add(1))) {
+            (Some(b'h'), Some(b'h')) => (Length::Char, 2),
+            (Some(b'h'), _) => (Length::Short, 1),
+            (Some(b'l'), Some(b'l')) => (Length::LongLong, 2),
+            (Some(b'l'), _) => (Length::Long, 1),
+            (Some(b'j'), _) => (Length::IntMax, 1),
+            (Some(b'z'), _) => (Length::Size, 1),
+            (Some(b't'), _) => (Length::PtrDiff, 1),
+            (Some(b'L'), _) =>
+                throw_unsup_format!("`{name}`: formatting `long double` is not supported"),
+            _ => (Length::None, 0),
+        };
+        conv.length = length;
+        pos = pos.strict_add(len);
+
+        let Some(&specifier) = fmt.get(pos) else {
+            throw_ub_format!(
+                "the format string passed to `{name}` ends in an incomplete conversion specification"
+            );
+        };
+        pos = pos.strict_add(1);
+        conv.specifier = specifier;
+        conv.text = String::from_utf8_lossy(&fmt[start..pos]).into_owned();
+
+        match (specifier, length) {
+            (b'%', _) => {}
+            (b'd' | b'i' | b'u' | b'o' | b'x' | b'X', _) => {}
+            (b'f' | b'F' | b'e' | b'E' | b'g' | b'G', Length::None | Length::Long) => {}
+            (b'c' | b's' | b'p', Length::None) => {}
+            (b'c' | b's', Length::Long) =>
+                throw_unsup_format!("`{name}`: formatting wide characters is not supported"),
+            (b'n', _) => throw_unsup_format!("`{name}`: the `%n` conversion is not supported"),
+            (b'a' | b'A', _) =>
+                throw_unsup_format!("`{name}`: formatting hexadecimal floats is not supported"),
+            (b'$', _) =>
+                throw_unsup_format!(
+                    "`{name}`: positional arguments in format strings are not supported"
+                ),
+            _ =>
+                throw_ub_format!(
+                    "invalid conversion specification `{}` in the format string passed to `{name}`",
+                    conv.text
+                ),
+        }
+        pieces.push(Piece::Conversion(conv));
+    }
+    if pos < fmt.len() {
+        pieces.push(Piece::Literal(fmt[pos..].to_vec()));
+    }
+    interp_ok(pieces)
+}
+
+/// Writes `prefix` and `body` to `out`, padded to `width` according to the conversion's flags.
+fn pad(
+    out: &mut Vec<u8>,
+    conv: &Conversion,
+    width: u64,
+    prefix: &[u8],
+    body: &[u8],
+    zero_ok: bool,
+) {
+    let len = u64::try_from(prefix.len().strict_add(body.len())).unwrap();
+    let fill = usize::try_from(width.saturating_sub(len)).unwrap();
+    if conv.left {
+        out.extend_from_slice(prefix);
+        out.extend_from_slice(body);
+        out.extend(std::iter::repeat_n(b' ', fill));
+    } else if conv.zero && zero_ok {
+        out.extend_from_slice(prefix);
+        out.extend(std::iter::repeat_n(b'0', fill));
+        out.extend_from_slice(body);
+    } else {
+        out.extend(std::iter::repeat_n(b' ', fill));
+        out.extend_from_slice(prefix);
+        out.extend_from_slice(body);
+    }
+}
+
+fn format_int(
+    out: &mut Vec<u8>,
+    conv: &Conversion,
+    width: u64,
+    precision: Option<u64>,
+    negative: bool,
+    magnitude: u128,
+) {
+    let mut digits = match conv.specifier {
+        b'o' => format!("{magnitude:o}"),
+        b'x' => format!("{magnitude:x}"),
+        b'X' => format!("{magnitude:X}"),
+        _ => format!("{magnitude}"),
+    }
+    .into_bytes();
+    if precision == Some(0) && magnitude == 0 {
+        digits.clear();
+    }
+    if let Some(precision) = precision {
+        let missing = usize::try_from(precision).unwrap().saturating_sub(digits.len());
+        let mut padded = vec![b'0'; missing];
+        padded.append(&mut digits);
+        digits = padded;
+    }
+    if conv.alt && conv.specifier == b'o' && digits.first() != Some(&b'0') {
+        digits.insert(0, b'0');
+    }
+    let prefix: &[u8] = if negative {
+        b"-"
+    } else if conv.is_signed() && conv.plus {
+        b"+"
+    } else if conv.is_signed() && conv.space {
+        b" "
+    } else if conv.alt && magnitude != 0 && conv.specifier == b'x' {
+        b"0x"
+    } else if conv.alt && magnitude != 0 && conv.specifier == b'X' {
+        b"0X"
+    } else {
+        b""
+    };
+    // The `0` flag is ignored when a precision is given.
+    pad(out, conv, width, prefix, &digits, precision.is_none());
+}
+
+/// `%e`: `d.ddde±dd`.
+fn format_exponential(value: f64, precision: usize, alt: bool) -> String {
+    let formatted = format!("{value:.precision$e}");
+    let (mantissa, exponent) = formatted.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let dot = if alt && precision == 0 { "." } else { "" };
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!("{mantissa}{dot}e{sign}{:02}", exponent.unsigned_abs())
+}
+
+/// `%f`: `ddd.ddd`.
+fn format_fixed(value: f64, precision: usize, alt: bool) -> String {
+    let dot = if alt && precision == 0 { "." } else { "" };
+    format!("{value:.precision$}{dot}")
+}
+
+/// `%g`: whichever of `%e` and `%f` is more appropriate for the magnitude, without trailing zeros.
+fn format_general(value: f64, precision: usize, alt: bool) -> String {
+    let precision = precision.max(1);
+    // The exponent the value has in `%e` notation with this precision.
+    let exponent: i64 = if value == 0.0 {
+        0
+    } else {
+        let formatted = format!("{value:.*e}", precision.strict_sub(1));
+        formatted.split_once('e').unwrap().1.parse().unwrap()
+    };
+    let precision_i64 = i64::try_from(precision).unwrap();
+    let formatted = if (-4..precision_i64).contains(&exponent) {
+        let decimals = precision_i64.strict_sub(1).strict_sub(exponent);
+        format_fixed(value, usize::try_from(decimals).unwrap(), alt)
+    } else {
+        format_exponential(value, precision.strict_sub(1), alt)
+    };
+    if alt {
+        return formatted;
+    }
+    let (mantissa, exponent) = match formatted.find('e') {
+        Some(idx) => formatted.split_at(idx),
+        None => (formatted.as_str(), ""),
+    };
+    let mantissa = if mantissa.contains('.') {
+        mantissa.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        mantissa
+    };
+    format!("{mantissa}{exponent}")
+}
+
+fn format_float(
+    out: &mut Vec<u8>,
+    conv: &Conversion,
+    width: u64,
+    precision: Option<u64>,
+    value: f64,
+) {
+    let prefix: &[u8] = if value.is_sign_negative() {
+        b"-"
+    } else if conv.plus {
+        b"+"
+    } else if conv.space {
+        b" "
+    } else {
+        b""
+    };
+    let upper = conv.specifier.is_ascii_uppercase();
+    if !value.is_finite() {
+        let body = match (value.is_nan(), upper) {
+            (true, false) => "nan",
+            (true, true) => "NAN",
+            (false, false) => "inf",
+            (false, true) => "INF",
+        };
+        pad(out, conv, width, prefix, body.as_bytes(), false);
+        return;
+    }
+    let value = value.abs();
+    let precision = usize::try_from(precision.unwrap_or(6)).unwrap();
+    let body = match conv.specifier.to_ascii_lowercase() {
+        b'f' => format_fixed(value, precision, conv.alt),
+        b'e' => format_exponential(value, precision, conv.alt),
+        b'g' => format_general(value, precision, conv.alt),
+        _ => unreachable!(),
+    };
+    let body = if upper { body.to_ascii_uppercase() } else { body };
+    pad(out, conv, width, prefix, body.as_bytes(), true);
+}
+
+impl<'tcx> EvalContextExtPriv<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// The argument a conversion expects, and the name of its C type.
+    fn printf_arg_kind(&self, conv: &Conversion) -> (ArgKind, &'static str) {
+        let this = self.eval_context_ref();
+        let int = Size::from_bytes(4);
+        let long = if this.tcx.sess.target.os == Os::Windows {
+            Size::from_bytes(4)
+        } else {
+            this.pointer_size()
+        };
+        let long_long = Size::from_bytes(8);
+        let size = this.pointer_size();
+        match conv.specifier {
+            b'd' | b'i' =>
+                match conv.length {
+                    Length::None | Length::Char | Length::Short => (ArgKind::Int(int), "int"),
+                    Length::Long => (ArgKind::Int(long), "long"),
+                    Length::LongLong => (ArgKind::Int(long_long), "long long"),
+                    Length::IntMax => (ArgKind::Int(long_long), "intmax_t"),
+                    Length::Size => (ArgKind::Int(size), "ssize_t"),
+                    Length::PtrDiff => (ArgKind::Int(size), "ptrdiff_t"),
+                },
+            b'u' | b'o' | b'x' | b'X' =>
+                match conv.length {
+                    Length::None | Length::Char | Length::Short =>
+                        (ArgKind::Int(int), "unsigned int"),
+                    Length::Long => (ArgKind::Int(long), "unsigned long"),
+                    Length::LongLong => (ArgKind::Int(long_long), "unsigned long long"),
+                    Length::IntMax => (ArgKind::Int(long_long), "uintmax_t"),
+                    Length::Size => (ArgKind::Int(size), "size_t"),
+                    Length::PtrDiff => (ArgKind::Int(size), "ptrdiff_t"),
+                },
+            b'c' => (ArgKind::Int(int), "int"),
+            b's' => (ArgKind::Pointer, "char *"),
+            b'p' => (ArgKind::Pointer, "void *"),
+            _ => (ArgKind::Double, "double"),
+        }
+    }
+
+    /// Reads the state of the `va_list` passed as `va_list`.
+    fn va_list_start(&self, va_list: &OpTy<'tcx>) -> InterpResult<'tcx, VaList> {
+        let this = self.eval_context_ref();
+        let target = &this.tcx.sess.target;
+        // On x86-64 and AArch64, the `va_list` points to a struct; elsewhere it is the `char *`.
+        let ptr = this.read_pointer(va_list)?;
+        let read_u32 = |offset: u64| {
+            let place = this.ptr_to_mplace(
+                ptr.wrapping_offset(Size::from_bytes(offset), this),
+                this.machine.layouts.u32,
+            );
+            this.read_scalar(&place)?.to_u32()
+        };
+        let read_i32 = |offset: u64| {
+            let place = this.ptr_to_mplace(
+                ptr.wrapping_offset(Size::from_bytes(offset), this),
+                this.machine.layouts.i32,
+            );
+            this.read_scalar(&place)?.to_i32()
+        };
+        let read_ptr = |offset: u64| {
+            let place = this.ptr_to_mplace(
+                ptr.wrapping_offset(Size::from_bytes(offset), this),
+                this.machine.layouts.mut_raw_ptr,
+            );
+            this.read_pointer(&place)
+        };
+        let list = match target.arch {
+            Arch::X86_64 if target.os != Os::Windows =>
+                VaList::X86_64 {
+                    gp_offset: read_u32(0)?.into(),
+                    fp_offset: read_u32(4)?.into(),
+                    overflow: read_ptr(8)?,
+                    reg_save: read_ptr(16)?,
+                },
+            Arch::AArch64 if !target.is_like_darwin && target.os != Os::Windows =>
+                VaList::AArch64 {
+                    stack: read_ptr(0)?,
+                    gr_top: read_ptr(8)?,
+                    vr_top: read_ptr(16)?,
+                    gr_offs: read_i32(24)?.into(),
+                    vr_offs: read_i32(28)?.into(),
+                },
+            Arch::X86 | Arch::X86_64 | Arch::Arm | Arch::AArch64 => VaList::Slots(ptr),
+            _ => throw_unsup_format!("reading a `va_list` is not supported on this target"),
+        };
+        interp_ok(list)
+    }
+
+    /// Reads the next argument of type `kind` from `list`, like `va_arg` does.
+    fn va_arg(&self, list: &mut VaList, kind: ArgKind) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_ref();
+        let ptr_size = this.pointer_size();
+        let size = match kind {
+            ArgKind::Int(size) => size,
+            ArgKind::Double => Size::from_bytes(8),
+            ArgKind::Pointer => ptr_size,
+        };
+        let ptr = match list {
+            VaList::Slots(next) => {
+                // 32-bit ARM aligns 8-byte arguments to 8 bytes; elsewhere, slots are aligned to
+                // the pointer size.
+                let align = if this.tcx.sess.target.arch == Arch::Arm { size } else { ptr_size };
+                let align = align.bytes();
+                let misalign = next.addr().bytes() % align;
+                let ptr = if misalign == 0 {
+                    *next
+                } else {
+                    next.wrapping_offset(Size::from_bytes(align.strict_sub(misalign)), this)
+                };
+                *next = ptr.wrapping_offset(
+                    Size::from_bytes(size.bytes().next_multiple_of(ptr_size.bytes())),
+                    this,
+                );
+                ptr
+            }
+            VaList::X86_64 { gp_offset, fp_offset, overflow, reg_save } =>
+                match kind {
+                    // The register save area holds six 8-byte general purpose registers, followed
+                    // by eight 16-byte vector registers.
+                    ArgKind::Double if *fp_offset < 176 => {
+                        let ptr = reg_save.wrapping_offset(Size::from_bytes(*fp_offset), this);
+                        *fp_offset = fp_offset.strict_add(16);
+                        ptr
+                    }
+                    ArgKind::Int(_) | ArgKind::Pointer if *gp_offset < 48 => {
+                        let ptr = reg_save.wrapping_offset(Size::from_bytes(*gp_offset), this);
+                        *gp_offset = gp_offset.strict_add(8);
+                        ptr
+                    }
+                    _ => {
+                        let ptr = *overflow;
+                        *overflow = overflow.wrapping_offset(Size::from_bytes(8), this);
+                        ptr
+                    }
+                },
+            VaList::AArch64 { stack, gr_top, vr_top, gr_offs, vr_offs } =>
+                match kind {
+                    // The offsets are negative while there are saved registers left.
+                    ArgKind::Double if *vr_offs <= -16 => {
+                        let ptr = vr_top.wrapping_signed_offset(*vr_offs, this);
+                        *vr_offs = vr_offs.strict_add(16);
+                        ptr
+                    }
+                    ArgKind::Int(_) | ArgKind::Pointer if *gr_offs <= -8 => {
+                        let ptr = gr_top.wrapping_signed_offset(*gr_offs, this);
+                        *gr_offs = gr_offs.strict_add(8);
+                        ptr
+                    }
+                    _ => {
+                        let ptr = *stack;
+                        *stack = stack.wrapping_offset(Size::from_bytes(8), this);
+                        ptr
+                    }
+                },
+        };
+        let layout = match kind {
+            ArgKind::Int(size) => this.machine.layouts.uint(size).unwrap(),
+            ArgKind::Double => this.layout_of(this.tcx.types.f64)?,
+            ArgKind::Pointer => this.machine.layouts.mut_raw_ptr,
+        };
+        this.read_scalar(&this.ptr_to_mplace(ptr, layout))
+    }
+
+    /// Returns the next argument, which should have the type `kind`. We can only check the type
+    /// of varargs, not of arguments in a `va_list`.
+    fn printf_arg(
+        &self,
+        args: &mut ArgCursor<'_, 'tcx>,
+        kind: ArgKind,
+        expected: &str,
+        conv: &Conversion,
+        name: &str,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_ref();
+        let arg = match args {
+            ArgCursor::Varargs(args) => args.next().unwrap(),
+            ArgCursor::VaList(list) => return this.va_arg(list, kind),
+        };
+        let ty = arg.layout.ty;
+        let matches = match kind {
+            ArgKind::Int(size) => ty.is_integral() && arg.layout.size == size,
+            ArgKind::Double => ty.is_floating_point() && arg.layout.size == Size::from_bytes(8),
+            ArgKind::Pointer => ty.is_any_ptr(),
+        };
+        if !matches {
+            throw_ub_format!(
+                "`{name}` conversion `{}` expects an argument of type `{expected}`, but got `{ty}`",
+                conv.text
+            );
+        }
+        this.read_scalar(arg)
+    }
+
+    /// Reads a `*` width or precision.
+    fn printf_count_arg(
+        &self,
+        args: &mut ArgCursor<'_, 'tcx>,
+        conv: &Conversion,
+        name: &str,
+    ) -> InterpResult<'tcx, i32> {
+        let this = self.eval_context_ref();
+        this.printf_arg(args, ArgKind::Int(Size::from_bytes(4)), "int", conv, name)?.to_i32()
+    }
+
+    /// Reads the string for a `%s` conversion: up to `precision` bytes, stopping at a NUL.
+    fn printf_str(&self, ptr: Pointer, precision: Option<u64>) -> InterpResult<'tcx, Vec<u8>> {
+        let this = self.eval_context_ref();
+        let Some(precision) = precision else {
+            return interp_ok(this.read_c_str(ptr)?.to_owned());
+        };
+        // With a precision, the string does not need to be NUL-terminated, so we must not read
+        // beyond what is printed.
+        let mut bytes = Vec::new();
+        for i in 0..precision {
+            let byte = this.read_bytes_ptr_strip_provenance(
+                ptr.wrapping_offset(Size::from_bytes(i), this),
+                Size::from_bytes(1),
+            )?[0];
+            if byte == 0 {
+                break;
+            }
+            bytes.push(byte);
+        }
+        interp_ok(bytes)
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Formats `args` according to the format string at `fmt`, as `printf` does.
+    fn c_format(
+        &self,
+        fmt: Pointer,
+        args: FormatArgs<'_, 'tcx>,
+        name: &str,
+    ) -> InterpResult<'tcx, Vec<u8>> {
+        let this = self.eval_context_ref();
+        // The radix character depends on the locale.
+        crate::shims::LocaleState::note_access(this)?;
+        let fmt = this.read_c_str(fmt)?.to_owned();
+        let pieces = parse_format(&fmt, name)?;
+
+        let needed = pieces
+            .iter()
+            .map(|piece| {
+                match piece {
+                    Piece::Literal(_) => 0,
+                    Piece::Conversion(conv) => conv.arg_count(),
+                }
+            })
+            .sum::<usize>();
+        let mut args = match args {
+            FormatArgs::Varargs(args) => {
+                if args.len() < needed {
+                    throw_ub_format!(
+                        "not enough variadic arguments for `{name}`: got {}, expected at least {needed}",
+                        args.len()
+                    );
+                }
+                ArgCursor::Varargs(args.iter())
+            }
+            FormatArgs::VaList(va_list) => ArgCursor::VaList(this.va_list_start(va_list)?),
+        };
+        let mut out = Vec::new();
+        for piece in pieces {
+            let mut conv = match piece {
+                Piece::Literal(bytes) => {
+                    out.extend(bytes);
+                    continue;
+                }
+                Piece::Conversion(conv) => conv,
+            };
+            let width = match conv.width {
+                Count::Absent => 0,
+                Count::Given(width) => width,
+                Count::FromArg => {
+                    let width = this.printf_count_arg(&mut args, &conv, name)?;
+                    // A negative width is taken as a `-` flag.
+                    if width < 0 {
+                        conv.left = true;
+                    }
+                    u64::from(width.unsigned_abs())
+                }
+            };
+            let precision = match conv.precision {
+                Count::Absent => None,
+                Count::Given(precision) => Some(precision),
+                Count::FromArg => {
+                    // A negative precision is taken as if it was omitted.
+                    let precision = this.printf_count_arg(&mut args, &conv, name)?;
+                    u64::try_from(precision).ok()
+                }
+            };
+            if width > MAX_FIELD || precision.is_some_and(|p| p > MAX_FIELD) {
+                throw_unsup_format!(
+                    "`{name}`: the field width or precision of `{}` is too large",
+                    conv.text
+                );
+            }
+            if conv.specifier == b'%' {
+                out.push(b'%');
+                continue;
+            }
+
+            let (kind, expected) = this.printf_arg_kind(&conv);
+            let value = this.printf_arg(&mut args, kind, expected, &conv, name)?;
+            match conv.specifier {
+                b'd' | b'i' | b'u' | b'o' | b'x' | b'X' => {
+                    let ArgKind::Int(size) = kind else { unreachable!() };
+                    let bits = value.to_bits(size)?;
+                    // `hh` and `h` convert the promoted argument back to the smaller type.
+                    let size = match conv.length {
+                        Length::Char => Size::from_bytes(1),
+                        Length::Short => Size::from_bytes(2),
+                        _ => size,
+                    };
+                    let (negative, magnitude) = if conv.is_signed() {
+                        let value = size.sign_extend(bits);
+                        (value < 0, value.unsigned_abs())
+                    } else {
+                        (false, size.truncate(bits))
+                    };
+                    format_int(&mut out, &conv, width, precision, negative, magnitude);
+                }
+                b'c' => {
+                    let byte = Size::from_bytes(1).truncate(value.to_bits(Size::from_bytes(4))?);
+                    pad(&mut out, &conv, width, b"", &[u8::try_from(byte).unwrap()], false);
+                }
+                b's' => {
+                    let ptr = value.to_pointer(this)?;
+                    let bytes = this.printf_str(ptr, precision)?;
+                    pad(&mut out, &conv, width, b"", &bytes, false);
+                }
+                b'p' => {
+                    let ptr = value.to_pointer(this)?;
+                    let addr = ptr.addr().bytes();
+                    let body = if addr == 0 && this.tcx.sess.target.os == Os::Linux {
+                        "(nil)".to_owned()
+                    } else {
+                        format!("0x{addr:x}")
+                    };
+                    pad(&mut out, &conv, width, b"", body.as_bytes(), false);
+                }
+                _ => {
+                    let value = value.to_f64()?.to_host();
+                    format_float(&mut out, &conv, width, precision, value);
+                }
+            }
+        }
+        interp_ok(out)
+    }
+
+    /// `snprintf` and `vsnprintf` (with `size`), and `sprintf` and `vsprintf` (without). Returns the length of the complete
+    /// output, even if it got truncated.
+    fn sprintf(
+        &mut self,
+        buf: &OpTy<'tcx>,
+        size: Option<&OpTy<'tcx>>,
+        fmt: &OpTy<'tcx>,
+        args: FormatArgs<'_, 'tcx>,
+        name: &str,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+        let buf = this.read_pointer(buf)?;
+        let size = size.map(|size| this.read_target_usize(size)).transpose()?;
+        let fmt = this.read_pointer(fmt)?;
+
+        let out = this.c_format(fmt, args, name)?;
+        let Ok(len) = i32::try_from(out.len()) else {
+            if this.target_os_is_unix() {
+                this.set_last_error(LibcError("EOVERFLOW"))?;
+            }
+            return interp_ok(Scalar::from_i32(-1));
+        };
+        let written = match size {
+            None => out.len(),
+            Some(0) => return interp_ok(Scalar::from_i32(len)),
+            Some(size) => {
+                // The whole buffer has to be valid, even if we do not write all of it.
+                this.check_ptr_access(buf, Size::from_bytes(size), CheckInAllocMsg::MemoryAccess)?;
+                out.len().min(usize::try_from(size.strict_sub(1)).unwrap())
+            }
+        };
+        this.write_bytes_ptr(buf, out[..written].iter().copied().chain(std::iter::once(0)))?;
+        interp_ok(Scalar::from_i32(len))
+    }
+
+    /// `printf`, `dprintf` and their `va_list` variants: writes the formatted output to the given file descriptor.
+    fn fd_printf(
+        &mut self,
+        fd_num: i32,
+        fmt: &OpTy<'tcx>,
+        args: FormatArgs<'_, 'tcx>,
+        name: &str,
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let fmt = this.read_pointer(fmt)?;
+        let out = this.c_format(fmt, args, name)?;
+        if i32::try_from(out.len()).is_err() {
+            return this.set_last_error_and_return(LibcError("EOVERFLOW"), dest);
+        }
+        let Some(fd) = this.machine.fds.get(fd_num) else {
+            return this.set_last_error_and_return(LibcError("EBADF"), dest);
+        };
+        if out.is_empty() {
+            return this.write_int(0, dest);
+        }
+
+        // Stage the output in a temporary buffer, so that we can write it like `write` would.
+        let len = out.len();
+        let buf = this.allocate_ptr(
+            Size::from_bytes(len),
+            Align::ONE,
+            MiriMemoryKind::Runtime.into(),
+            AllocInit::Uninit,
+        )?;
+        let buf = Pointer::from(buf);
+        this.write_bytes_ptr(buf, out)?;
+        let finish = {
+            let dest = dest.clone();
+            callback!(
+                @capture<'tcx> {
+                    buf: Pointer,
+                    dest: MPlaceTy<'tcx>,
+                }
+                |this, result: Result<usize, IoError>| {
+                    this.deallocate_ptr(buf, None, MiriMemoryKind::Runtime.into())?;
+                    match result {
+                        Ok(written) => this.write_int(u64::try_from(written).unwrap(), &dest),
+                        Err(e) => this.set_last_error_and_return(e, &dest),
+                    }
+                }
+            )
+        };
+        let communicate = this.machine.communicate();
+        fd.write(communicate, buf, len, this, finish)
+    }
+}
@@ -0,0 +1,670 @@
+//! Child processes, started with `posix_spawn` or `CreateProcessW`.
+//!
+//! We cannot run arbitrary host programs as children of the interpreted program, but we can run
+//! the interpreted program itself (which is what "re-exec" tests do): the child is a nested Miri
+//! process, started with the same flags as this one except for those in `PARENT_ONLY_FLAGS`. The
+//! child's stdio can be inherited, be a file, or be a pipe whose other end the parent keeps; in the
+//! latter case, the parent's end of the pipe is replaced by a file description that talks to the
+//! host pipe of the child process.
+//!
+//! The child interpreter keeps Miri's host environment, which it needs to find the sysroot, and
+//! gets the environment of the interpreted program through `-Zmiri-env-set`.
+//!
+//! The child runs on the host, so host threads collect its output and watch for it to exit. The
+//! threads of the interpreted program that wait for either are blocked until one of those host
+//! threads reports that something happened, and then check again. If all threads wait for child
+//! processes, the interpreter waits for such a report.
+//!
+//! `fork` and `vfork` are not supported: the child would have to continue with a copy of the
+//! entire interpreter state, which a nested interpreter cannot provide. Neither is the `exec`
+//! family, which would replace the interpreted program.
+
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, VecDeque};
+use std::ffi::OsString;
+use std::io::{self, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+use crate::shims::files::{
+    DynFileDescriptionRef, FdId, FileDescription, FileDescriptionRef, FileHandle, NullOutput,
+    WeakFileDescriptionRef,
+};
+use crate::shims::unix::{EpollEvents, UnixFileDescription, pipe_peer};
+use crate::*;
+
+/// How often the host thread that watches a child process checks whether it exited. `Child::wait`
+/// would keep the child locked, so that it could not be killed in the meantime.
+const CHILD_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// How long the interpreter waits for a child process at a time when there is nothing else to do,
+/// so that it still notices Ctrl-C.
+const CHILD_WAIT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The flags of this interpreter that are not passed on to child interpreters. The output files
+/// would be overwritten by the child, a replayed schedule belongs to this run of the program, and
+/// the environment of the child is given by the parent program instead.
+const PARENT_ONLY_FLAGS: &[&str] = &[
+    "-Zmiri-record-schedule=",
+    "-Zmiri-replay-schedule=",
+    "-Zmiri-dpor-schedule=",
+    "-Zmiri-deadlock-report=",
+    "-Zmiri-thread-timeline=",
+    "-Zmiri-env-forward=",
+    "-Zmiri-env-set=",
+];
+
+/// An action to perform in the child process before it starts running
+/// (`posix_spawn_file_actions_t`, or the `STARTUPINFOW` of `CreateProcessW`).
+#[derive(Debug, Clone)]
+pub enum FileAction {
+    Dup2 { fd: i32, new_fd: i32 },
+    Close { fd: i32 },
+    Chdir(PathBuf),
+}
+
+#[derive(Debug)]
+struct ChildProcess {
+    /// Shared with the host thread that watches for the child to exit.
+    child: Arc<Mutex<Child>>,
+    /// The signal (or, on Windows, the exit code) we terminated this process with, if any.
+    killed_by: Option<i32>,
+}
+
+/// Whether a child process made progress, i.e. wrote output or exited, since the interpreter last
+/// checked. This is set by the host threads that watch the child processes.
+#[derive(Debug, Default)]
+struct ChildEvents {
+    pending: Mutex<bool>,
+    /// Notified whenever `pending` is set.
+    signal: Condvar,
+}
+
+impl ChildEvents {
+    fn notify(&self) {
+        *self.pending.lock().unwrap() = true;
+        self.signal.notify_all();
+    }
+}
+
+/// All child processes that have not been waited for yet.
+#[derive(Debug)]
+pub struct ProcessTable {
+    /// The working directory and command-line arguments (up to `--`) of this interpreter, which
+    /// are used to start child interpreters.
+    invocation: Option<(PathBuf, Vec<String>)>,
+    children: BTreeMap<i32, ChildProcess>,
+    events: Arc<ChildEvents>,
+    /// The number of threads that are blocked on child processes.
+    waiters: usize,
+    /// The contents of all `posix_spawn_file_actions_t`, indexed by the id we stored in them.
+    /// `None` means the object has been destroyed.
+    pub(crate) file_actions: Vec<Option<Vec<FileAction>>>,
+}
+
+impl VisitProvenance for ProcessTable {
+    fn visit_provenance(&self, _visit: &mut VisitWith<'_>) {
+        // There are no pointers in here.
+    }
+}
+
+impl ProcessTable {
+    pub(crate) fn new(invocation: Option<(PathBuf, Vec<String>)>) -> Self {
+        ProcessTable {
+            invocation,
+            children: BTreeMap::new(),
+            events: Arc::default(),
+            waiters: 0,
+            file_actions: Vec::new(),
+        }
+    }
+
+    /// Whether there are threads that wait for child processes.
+    pub fn has_waiters(&self) -> bool {
+        self.waiters > 0
+    }
+
+    /// Returns whether a child process made progress since the last call, if there are threads
+    /// that wait for that.
+    pub fn take_events(&self) -> bool {
+        self.has_waiters() && std::mem::take(&mut *self.events.pending.lock().unwrap())
+    }
+
+    /// Waits on the host until a child process makes progress or `timeout` passes.
+    pub fn wait_for_events(&self, timeout: Option<Duration>) {
+        let timeout =
+            timeout.map_or(CHILD_WAIT_INTERVAL, |timeout| timeout.min(CHILD_WAIT_INTERVAL));
+        let pending = self.events.pending.lock().unwrap();
+        drop(self.events.signal.wait_timeout_while(pending, timeout, |pending| !*pending).unwrap());
+    }
+
+    /// The pids of all child processes that have not been reaped yet.
+    pub fn children(&self) -> impl Iterator<Item = i32> {
+        self.children.keys().copied()
+    }
+
+    /// Whether `pid` is a child process that has not been reaped yet.
+    pub fn contains(&self, pid: i32) -> bool {
+        self.children.contains_key(&pid)
+    }
+
+    /// Returns how the child process `pid` exited, or `None` if it is still running.
+    pub fn try_wait(&self, pid: i32) -> io::Result<Option<ExitStatus>> {
+        self.children[&pid].child.lock().unwrap().try_wait()
+    }
+
+    /// The signal (or, on Windows, the exit code) we killed the child process `pid` with, if any.
+    pub fn killed_by(&self, pid: i32) -> Option<i32> {
+        self.children[&pid].killed_by
+    }
+
+    /// Forgets about the child process `pid`, and returns the signal we killed it with, if any.
+    pub fn reap(&mut self, pid: i32) -> Option<i32> {
+        self.children.remove(&pid).unwrap().killed_by
+    }
+
+    /// Kills the child process `pid`. `signal` is what `waitpid` reports as the reason, or what
+    /// `GetExitCodeProcess` reports as the exit code.
+    pub fn kill(&mut self, pid: i32, signal: i32) -> io::Result<()> {
+        let child = self.children.get_mut(&pid).unwrap();
+        child.child.lock().unwrap().kill()?;
+        child.killed_by.get_or_insert(signal);
+        Ok(())
+    }
+}
+
+/// The data a host thread read from the stdout and/or stderr of a child process.
+#[derive(Debug, Default)]
+struct OutputBuffer {
+    data: VecDeque<u8>,
+    /// The number of host threads still reading from the child. Once this reaches 0, the
+    /// buffer only needs to be drained and then signals the end of the file.
+    writers: usize,
+}
+
+/// The parent's read end of a pipe connected to the stdout and/or stderr of a child process.
+#[derive(Debug)]
+struct ChildOutput {
+    buffer: Arc<Mutex<OutputBuffer>>,
+    is_nonblock: Cell<bool>,
+}
+
+impl ChildOutput {
+    /// Starts a host thread that moves everything `output` produces to `buffer`, and tells
+    /// `events` about it.
+    fn forward(
+        buffer: &Arc<Mutex<OutputBuffer>>,
+        events: &Arc<ChildEvents>,
+        mut output: impl Read + Send + 'static,
+    ) {
+        let mut locked = buffer.lock().unwrap();
+        locked.writers = locked.writers.strict_add(1);
+        drop(locked);
+        let buffer = Arc::clone(buffer);
+        let events = Arc::clone(events);
+        std::thread::spawn(move || {
+            let mut bytes = [0; 4096];
+            loop {
+                match output.read(&mut bytes) {
+                    Ok(0) => break,
+                    Ok(len) => buffer.lock().unwrap().data.extend(&bytes[..len]),
+                    Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                }
+                events.notify();
+            }
+            let mut locked = buffer.lock().unwrap();
+            locked.writers = locked.writers.strict_sub(1);
+            drop(locked);
+            events.notify();
+        });
+    }
+}
+
+impl FileDescription for ChildOutput {
+    fn name(&self) -> &'static str {
+        "pipe"
+    }
+
+    fn read<'tcx>(
+        self: FileDescriptionRef<Self>,
+        _communicate_allowed: bool,
+        ptr: Pointer,
+        len: usize,
+        ecx: &mut MiriInterpCx<'tcx>,
+        finish: DynMachineCallback<'tcx, Result<usize, IoError>>,
+    ) -> InterpResult<'tcx> {
+        let mut buffer = self.buffer.lock().unwrap();
+        if len == 0 || !buffer.data.is_empty() {
+            let result = ecx.read_from_host(&mut buffer.data, len, ptr)?;
+            drop(buffer);
+            return finish.call(ecx, result);
+        }
+        if buffer.writers == 0 {
+            // The child is done writing; this is the end of the file.
+            drop(buffer);
+            return finish.call(ecx, Ok(0));
+        }
+        drop(buffer);
+        if self.is_nonblock.get() {
+            return finish.call(ecx, Err(ErrorKind::WouldBlock.into()));
+        }
+        let weak_self_ref = FileDescriptionRef::downgrade(&self);
+        ecx.block_on_child_process(
+            None,
+            callback!(
+                @capture<'tcx> {
+                    weak_self_ref: WeakFileDescriptionRef<ChildOutput>,
+                    ptr: Pointer,
+                    len: usize,
+                    finish: DynMachineCallback<'tcx, Result<usize, IoError>>,
+                }
+                |this, _unblock: ()| {
+                    match weak_self_ref.upgrade() {
+                        Some(self_ref) =>
+                            self_ref.read(/* communicate_allowed */ true, ptr, len, this, finish),
+                        // Another thread closed the pipe while we were waiting, so no more data can
+                        // arrive.
+                        None => finish.call(this, Ok(0)),
+                    }
+                }
+            ),
+        );
+        interp_ok(())
+    }
+
+    fn destroy<'tcx>(
+        self,
+        _self_id: FdId,
+        _communicate_allowed: bool,
+        _ecx: &mut MiriInterpCx<'tcx>,
+    ) -> InterpResult<'tcx, io::Result<()>> {
+        // The host threads keep draining the child's output until it exits.
+        interp_ok(Ok(()))
+    }
+
+    fn as_unix<'tcx>(&self, _ecx: &MiriInterpCx<'tcx>) -> &dyn UnixFileDescription {
+        self
+    }
+
+    fn get_flags<'tcx>(&self, ecx: &mut MiriInterpCx<'tcx>) -> InterpResult<'tcx, Scalar> {
+        let mut flags = ecx.eval_libc_i32("O_RDONLY");
+        if self.is_nonblock.get() {
+            flags |= ecx.eval_libc_i32("O_NONBLOCK");
+        }
+        interp_ok(Scalar::from_i32(flags))
+    }
+
+    fn set_flags<'tcx>(
+        &self,
+        flag: i32,
+        ecx: &mut MiriInterpCx<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        set_pipe_flags(&self.is_nonblock, flag, ecx)
+    }
+}
+
+impl UnixFileDescription for ChildOutput {
+    fn epoll_active_events<'tcx>(&self) -> InterpResult<'tcx, EpollEvents> {
+        let buffer = self.buffer.lock().unwrap();
+        let mut events = EpollEvents::new();
+        events.epollin = !buffer.data.is_empty() || buffer.writers == 0;
+        events.epollhup = buffer.writers == 0;
+        interp_ok(events)
+    }
+}
+
+/// The parent's write end of a pipe connected to the stdin of a child process.
+#[derive(Debug)]
+struct ChildInput {
+    stdin: RefCell<ChildStdin>,
+    is_nonblock: Cell<bool>,
+}
+
+impl FileDescription for ChildInput {
+    fn name(&self) -> &'static str {
+        "pipe"
+    }
+
+    fn write<'tcx>(
+        self: FileDescriptionRef<Self>,
+        _communicate_allowed: bool,
+        ptr: Pointer,
+        len: usize,
+        ecx: &mut MiriInterpCx<'tcx>,
+        finish: DynMachineCallback<'tcx, Result<usize, IoError>>,
+    ) -> InterpResult<'tcx> {
+        // The child's output is drained by host threads, so this cannot deadlock.
+        let result = ecx.write_to_host(&mut *self.stdin.borrow_mut(), len, ptr)?;
+        finish.call(ecx, result)
+    }
+
+    fn destroy<'tcx>(
+        self,
+        _self_id: FdId,
+        _communicate_allowed: bool,
+        _ecx: &mut MiriInterpCx<'tcx>,
+    ) -> InterpResult<'tcx, io::Result<()>> {
+        // Dropping the host pipe lets the child see the end of its input.
+        drop(self.stdin);
+        interp_ok(Ok(()))
+    }
+
+    fn as_unix<'tcx>(&self, _ecx: &MiriInterpCx<'tcx>) -> &dyn UnixFileDescription {
+        self
+    }
+
+    fn get_flags<'tcx>(&self, ecx: &mut MiriInterpCx<'tcx>) -> InterpResult<'tcx, Scalar> {
+        let mut flags = ecx.eval_libc_i32("O_WRONLY");
+        if self.is_nonblock.get() {
+            flags |= ecx.eval_libc_i32("O_NONBLOCK");
+        }
+        interp_ok(Scalar::from_i32(flags))
+    }
+
+    fn set_flags<'tcx>(
+        &self,
+        flag: i32,
+        ecx: &mut MiriInterpCx<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        set_pipe_flags(&self.is_nonblock, flag, ecx)
+    }
+}
+
+impl UnixFileDescription for ChildInput {
+    fn epoll_active_events<'tcx>(&self) -> InterpResult<'tcx, EpollEvents> {
+        // We do not know how full the host pipe is, so we just claim that there is space.
+        let mut events = EpollEvents::new();
+        events.epollout = true;
+        interp_ok(events)
+    }
+}
+
+/// `F_SETFL` for the pipes to child processes: only `O_NONBLOCK` can be changed.
+fn set_pipe_flags<'tcx>(
+    is_nonblock: &Cell<bool>,
+    mut flag: i32,
+    ecx: &mut MiriInterpCx<'tcx>,
+) -> InterpResult<'tcx, Scalar> {
+    let o_nonblock = ecx.eval_libc_i32("O_NONBLOCK");
+    let o_rdonly = ecx.eval_libc_i32("O_RDONLY");
+    let o_wronly = ecx.eval_libc_i32("O_WRONLY");
+    let o_rdwr = ecx.eval_libc_i32("O_RDWR");
+
+    is_nonblock.set(flag & o_nonblock == o_nonblock);
+    flag &= !(o_nonblock | o_rdonly | o_wronly | o_rdwr);
+    if flag != 0 {
+        throw_unsup_format!("fcntl: only O_NONBLOCK is supported for F_SETFL on pipes");
+    }
+    interp_ok(Scalar::from_i32(0))
+}
+
+/// Returns whether `fd` is a pipe that receives data from a child process, i.e. whether waiting
+/// for it to become readable can make progress.
+pub fn is_child_output(fd: &DynFileDescriptionRef) -> bool {
+    fd.clone().downcast::<ChildOutput>().is_some()
+}
+
+/// How to set up one of the standard streams of a child process.
+enum ChildStdio {
+    Host(Stdio),
+    /// A pipe; the parent's end of it will be replaced by a connection to the host pipe.
+    Pipe {
+        parent_end: DynFileDescriptionRef,
+    },
+}
+
+impl<'tcx> EvalContextPrivExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextPrivExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Checks whether `program` is the program we are interpreting, which is the only one we can
+    /// run as a child process: either the path `std::env::current_exe` returns, which is the Miri
+    /// binary, or exactly the `argv[0]` the program was started with.
+    fn is_interpreted_program(&self, program: &Path) -> bool {
+        let this = self.eval_context_ref();
+        if let Ok(exe) = std::env::current_exe()
+            && let (Ok(exe), Ok(program)) = (exe.canonicalize(), program.canonicalize())
+            && exe == program
+        {
+            return true;
+        }
+        program.as_os_str() == this.tcx.sess.io.input.filestem()
+    }
+
+    /// Determines how to pass on the parent's file description `fd` as the standard stream
+    /// `child_fd` of a child process.
+    fn child_stdio(
+        &self,
+        child_fd: i32,
+        fd: Option<DynFileDescriptionRef>,
+    ) -> InterpResult<'tcx, ChildStdio> {
+        let Some(fd) = fd else {
+            // The stream is closed in the child. We cannot do that, so this is the best we have.
+            return interp_ok(ChildStdio::Host(Stdio::null()));
+        };
+        let stdio = if child_fd == 0 && fd.clone().downcast::<io::Stdin>().is_some() {
+            Stdio::inherit()
+        } else if fd.clone().downcast::<io::Stdout>().is_some() {
+            io::stdout().into()
+        } else if fd.clone().downcast::<io::Stderr>().is_some() {
+            io::stderr().into()
+        } else if fd.clone().downcast::<NullOutput>().is_some() {
+            Stdio::null()
+        } else if let Some(file) = fd.clone().downcast::<FileHandle>() {
+            match file.file.try_clone() {
+                Ok(file) => file.into(),
+                Err(err) => throw_unsup_format!("cannot pass file to child process: {err}"),
+            }
+        } else if let Some(peer) = pipe_peer(fd.clone(), /* read_end */ child_fd == 0) {
+            match peer {
+                Some(parent_end) => return interp_ok(ChildStdio::Pipe { parent_end }),
+                // Nobody is on the other end of the pipe.
+                None => Stdio::null(),
+            }
+        } else {
+            throw_unsup_format!(
+                "cannot pass {} as file descriptor {child_fd} to a child process",
+                fd.name()
+            );
+        };
+        interp_ok(ChildStdio::Host(stdio))
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Starts a nested interpreter running `argv` and returns its pid. `envp` is the environment
+    /// of the child, as `NAME=VALUE` strings, and `actions` set up its standard streams and its
+    /// working directory.
+    fn spawn_child(
+        &mut self,
+        program: &Path,
+        argv: Vec<OsString>,
+        envp: Vec<OsString>,
+        actions: Vec<FileAction>,
+    ) -> InterpResult<'tcx, Result<i32, IoError>> {
+        let this = self.eval_context_mut();
+
+        if !this.is_interpreted_program(program) {
+            throw_unsup_format!(
+                "only the interpreted program itself can be run as a child process, not `{}`; \
+                other binaries, even those of the same workspace, are not supported",
+                program.display()
+            );
+        }
+        let Some((miri_cwd, miri_args)) = this.machine.processes.invocation.clone() else {
+            throw_unsup_format!("child processes are not supported in this configuration");
+        };
+
+        // Figure out what the child's standard streams are.
+        let mut stdio: [Option<DynFileDescriptionRef>; 3] =
+            [this.machine.fds.get(0), this.machine.fds.get(1), this.machine.fds.get(2)];
+        let mut cwd = None;
+        for action in actions {
+            match action {
+                FileAction::Dup2 { fd, new_fd } => {
+                    let source = match usize::try_from(fd) {
+                        Ok(idx) if idx < stdio.len() => stdio[idx].clone(),
+                        _ => this.machine.fds.get(fd),
+                    };
+                    let Some(source) = source else {
+                        return interp_ok(Err(LibcError("EBADF")));
+                    };
+                    // File descriptors other than the standard streams are not passed on.
+                    if let Ok(idx) = usize::try_from(new_fd)
+                        && idx < stdio.len()
+                    {
+                        stdio[idx] = Some(source);
+                    }
+                }
+                FileAction::Close { fd } =>
+                    if let Ok(idx) = usize::try_from(fd)
+                        && idx < stdio.len()
+                    {
+                        stdio[idx] = None;
+                    },
+                FileAction::Chdir(path) => cwd = Some(path),
+            }
+        }
+        let [stdin, stdout, stderr] = stdio;
+        let stdin = this.child_stdio(0, stdin)?;
+        let stdout = this.child_stdio(1, stdout)?;
+        let stderr = this.child_stdio(2, stderr)?;
+
+        // The child interpreter starts in a different directory, so this must be absolute.
+        let cwd = match std::env::current_dir() {
+            Ok(current) => cwd.map_or(current.clone(), |cwd| current.join(cwd)),
+            Err(err) => return interp_ok(Err(err.into())),
+        };
+        let exe = match std::env::current_exe() {
+            Ok(exe) => exe,
+            Err(err) => return interp_ok(Err(err.into())),
+        };
+
+        // The child interpreter is compiled from the same arguments in the same directory as
+        // this one, and runs in the same host environment. The environment of the interpreted
+        // program is passed with `-Zmiri-env-set`, and `MIRI_CWD` makes the child switch to the
+        // right directory once interpretation starts.
+        let mut command = Command::new(exe);
+        command
+            .current_dir(miri_cwd)
+            .args(
+                miri_args
+                    .iter()
+                    .skip(1)
+                    .filter(|arg| !PARENT_ONLY_FLAGS.iter().any(|flag| arg.starts_with(flag))),
+            )
+            .env("MIRI_CWD", &cwd);
+        for var in &envp {
+            let var = var.to_string_lossy();
+            // Windows keeps the working directory of each drive in variables whose names start
+            // with `=`. `-Zmiri-env-set` cannot express those, and the child gets its working
+            // directory from `MIRI_CWD` anyway.
+            if !var.starts_with('=') && var.contains('=') {
+                command.arg(format!("-Zmiri-env-set={var}"));
+            }
+        }
+        command.arg("--").args(argv.iter().skip(1));
+        let mut parent_ends = Vec::new();
+        let mut host_stdio = |idx: usize, stdio: ChildStdio| {
+            match stdio {
+                ChildStdio::Host(stdio) => stdio,
+                ChildStdio::Pipe { parent_end } => {
+                    parent_ends.push((idx, parent_end));
+                    Stdio::piped()
+                }
+            }
+        };
+        command.stdin(host_stdio(0, stdin));
+        command.stdout(host_stdio(1, stdout));
+        command.stderr(host_stdio(2, stderr));
+
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(err) => return interp_ok(Err(err.into())),
+        };
+        let pid = i32::try_from(child.id()).unwrap();
+
+        // Connect the parent's ends of the pipes. If stdout and stderr go to the same pipe, they
+        // share one buffer.
+        let mut outputs: Vec<(FdId, Arc<Mutex<OutputBuffer>>)> = Vec::new();
+        for (idx, parent_end) in parent_ends {
+            let id = parent_end.id();
+            if idx == 0 {
+                let stdin = child.stdin.take().unwrap();
+                let input = this.machine.fds.new_ref(ChildInput {
+                    stdin: RefCell::new(stdin),
+                    is_nonblock: Cell::new(false),
+                });
+                this.machine.fds.replace_description(id, input);
+                continue;
+            }
+            let (buffer, is_new) = match outputs.iter().find(|(other, _)| *other == id) {
+                Some((_, buffer)) => (Arc::clone(buffer), false),
+                None => (Arc::default(), true),
+            };
+            let events = &this.machine.processes.events;
+            if idx == 1 {
+                ChildOutput::forward(&buffer, events, child.stdout.take().unwrap());
+            } else {
+                ChildOutput::forward(&buffer, events, child.stderr.take().unwrap());
+            }
+            if is_new {
+                let output = this.machine.fds.new_ref(ChildOutput {
+                    buffer: Arc::clone(&buffer),
+                    is_nonblock: Cell::new(false),
+                });
+                this.machine.fds.replace_description(id, output);
+                outputs.push((id, buffer));
+            }
+        }
+        // Nobody can be interested in the file descriptions we just replaced any more.
+        for (id, _) in &outputs {
+            this.machine.epoll_interests.remove_epolls(*id);
+        }
+
+        // Watch for the child to exit.
+        let child = Arc::new(Mutex::new(child));
+        let watched = Arc::clone(&child);
+        let events = Arc::clone(&this.machine.processes.events);
+        std::thread::spawn(move || {
+            loop {
+                let status = watched.lock().unwrap().try_wait();
+                if !matches!(status, Ok(None)) {
+                    break;
+                }
+                std::thread::sleep(CHILD_POLL_INTERVAL);
+            }
+            events.notify();
+        });
+
+        this.machine.processes.children.insert(pid, ChildProcess { child, killed_by: None });
+        interp_ok(Ok(pid))
+    }
+
+    /// Blocks the active thread until a child process made progress or `deadline` (relative to
+    /// the epoch of the monotonic clock) passed, and then calls `retry`, which checks whether the
+    /// child process that the thread waits for is among those that made progress.
+    fn block_on_child_process(
+        &mut self,
+        deadline: Option<Duration>,
+        retry: DynMachineCallback<'tcx, ()>,
+    ) {
+        let this = self.eval_context_mut();
+        this.machine.processes.waiters = this.machine.processes.waiters.strict_add(1);
+        this.block_thread(
+            BlockReason::ChildProcess,
+            deadline.map(|deadline| (TimeoutClock::Monotonic, TimeoutAnchor::Absolute, deadline)),
+            callback!(
+                @capture<'tcx> {
+                    retry: DynMachineCallback<'tcx, ()>,
+                }
+                |this, _unblock: UnblockKind| {
+                    this.machine.processes.waiters = this.machine.processes.waiters.strict_sub(1);
+                    retry.call(this, ())
+                }
+            ),
+        );
+    }
+}
@@ -0,0 +1,474 @@
+//! Helpers from C's `stdlib.h`: number parsing, sorting and searching.
+
+use std::cmp::Ordering;
+
+use rustc_abi::{Align, ExternAbi, HasDataLayout, Size};
+use rustc_middle::mir;
+use rustc_middle::mir::interpret::AllocInit;
+
+use crate::helpers::ToSoft;
+use crate::*;
+
+/// A running `qsort` or `bsearch` call.
+///
+/// The comparison function is called on the thread that called `qsort` or `bsearch`, as if the
+/// shim called it directly. Its frame gets a continuation that takes the result into account once
+/// it returns, and then either calls the comparison function again or returns from the shim.
+#[derive(Debug)]
+struct Comparison<'tcx> {
+    compar: Pointer,
+    /// The array and the size of its elements.
+    base: Pointer,
+    size: u64,
+    algorithm: Algorithm,
+    /// Where the comparison function puts its result.
+    result: MPlaceTy<'tcx>,
+    /// Where `bsearch` puts the element it found.
+    dest: Option<MPlaceTy<'tcx>>,
+    /// The block that the caller of `qsort` or `bsearch` continues with.
+    ret: Option<mir::BasicBlock>,
+}
+
+#[derive(Debug)]
+enum Algorithm {
+    /// `qsort` does a binary insertion sort, so that it needs few comparisons and is stable.
+    Sort {
+        nmemb: u64,
+        /// The elements sorted so far, as indices into the array.
+        order: Vec<u64>,
+        /// The element that is being inserted into `order`.
+        next: u64,
+        /// The range of `order` that `next` still needs to be compared with.
+        lo: usize,
+        hi: usize,
+    },
+    Search {
+        key: Pointer,
+        /// The range of the array that may still contain the key.
+        lo: u64,
+        hi: u64,
+        /// The index of the element that compared equal, once there is one.
+        found: Option<u64>,
+    },
+}
+
+impl VisitProvenance for Comparison<'_> {
+    fn visit_provenance(&self, visit: &mut VisitWith<'_>) {
+        let Comparison { compar, base, size: _, algorithm, result, dest, ret: _ } = self;
+        compar.visit_provenance(visit);
+        base.visit_provenance(visit);
+        result.visit_provenance(visit);
+        dest.visit_provenance(visit);
+        if let Algorithm::Search { key, .. } = algorithm {
+            key.visit_provenance(visit);
+        }
+    }
+}
+
+impl Comparison<'_> {
+    fn elem(&self, idx: u64, cx: &impl HasDataLayout) -> Pointer {
+        elem(self.base, self.size, idx, cx)
+    }
+}
+
+/// The element at index `idx` of the array at `base` with elements of the given size.
+fn elem(base: Pointer, size: u64, idx: u64, cx: &impl HasDataLayout) -> Pointer {
+    base.wrapping_offset(Size::from_bytes(idx.strict_mul(size)), cx)
+}
+
+/// Whitespace as recognized by `isspace` in the "C" locale.
+fn is_c_space(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | 0x0b | 0x0c | b'\r')
+}
+
+/// Parses an integer the way `strtol` does. Returns whether there was a minus sign, the absolute
+/// value (`None` if it does not fit in a `u128`), and how many bytes were consumed, which is 0 if
+/// there was no number.
+fn parse_c_int(bytes: &[u8], base: u32) -> (bool, Option<u128>, usize) {
+    let mut pos = bytes.iter().take_while(|&&b| is_c_space(b)).count();
+    let negative = bytes.get(pos) == Some(&b'-');
+    if matches!(bytes.get(pos), Some(b'-' | b'+')) {
+        pos = pos.strict_add(1);
+    }
+    // A `0x` prefix only counts if a hex digit follows it; otherwise we just parse the `0`.
+    let has_hex_prefix = bytes.get(pos) == Some(&b'0')
+        && matches!(bytes.get(pos.strict_add(1)), Some(b'x' | b'X'))
+        && bytes.get(pos.strict_add(2)).is_some_and(|b| b.is_ascii_hexdigit());
+    let base = match base {
+        0 if has_hex_prefix => 16,
+        0 if bytes.get(pos) == Some(&b'0') => 8,
+        0 => 10,
+        base => base,
+    };
+    if base == 16 && has_hex_prefix {
+        pos = pos.strict_add(2);
+    }
+
+    let digits = bytes[pos..].iter().take_while(|b| char::from(**b).is_digit(base)).count();
+    if digits == 0 {
+        return (false, Some(0), 0);
+    }
+    let value = bytes[pos..].iter().take(digits).try_fold(0u128, |acc, &b| {
+        let digit = char::from(b).to_digit(base).unwrap();
+        acc.checked_mul(u128::from(base))?.checked_add(u128::from(digit))
+    });
+    (negative, value, pos.strict_add(digits))
+}
+
+/// Finds the longest prefix of `bytes` that `strtod` parses as a decimal float, and returns the
+/// number as a string that Rust's float parsing understands, along with the consumed length.
+fn scan_c_float(bytes: &[u8]) -> Option<(String, usize)> {
+    let start = bytes.iter().take_while(|&&b| is_c_space(b)).count();
+    let mut pos = start;
+    if matches!(bytes.get(pos), Some(b'-' | b'+')) {
+        pos = pos.strict_add(1);
+    }
+    let rest = &bytes[pos..];
+    let starts_with =
+        |word: &[u8]| rest.len() >= word.len() && rest[..word.len()].eq_ignore_ascii_case(word);
+    let end = if starts_with(b"infinity") {
+        pos.strict_add(8)
+    } else if starts_with(b"inf") || starts_with(b"nan") {
+        pos.strict_add(3)
+    } else {
+        let int_digits = rest.iter().take_while(|b| b.is_ascii_digit()).count();
+        let mut end = pos.strict_add(int_digits);
+        let mut digits = int_digits;
+        if bytes.get(end) == Some(&b'.') {
+            let frac_digits =
+                bytes[end.strict_add(1)..].iter().take_while(|b| b.is_ascii_digit()).count();
+            digits = digits.strict_add(frac_digits);
+            end = end.strict_add(1).strict_add(frac_digits);
+        }
+        if digits == 0 {
+            return None;
+        }
+        // The exponent only counts if it has digits.
+        if matches!(bytes.get(end), Some(b'e' | b'E')) {
+            let mut exp_end = end.strict_add(1);
+            if matches!(bytes.get(exp_end), Some(b'-' | b'+')) {
+                exp_end = exp_end.strict_add(1);
+            }
+            let exp_digits = bytes[exp_end..].iter().take_while(|b| b.is_ascii_digit()).count();
+            if exp_digits > 0 {
+                end = exp_end.strict_add(exp_digits);
+            }
+        }
+        end
+    };
+    let text = str::from_utf8(&bytes[start..end]).unwrap();
+    Some((text.to_owned(), end))
+}
+
+impl<'tcx> EvalContextExtPriv<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Starts a `qsort` or `bsearch` call, which returns to `ret` once it is done. If `dest` is
+    /// given, the element that was found is stored there.
+    fn start_comparison(
+        &mut self,
+        compar: Pointer,
+        base: Pointer,
+        size: u64,
+        algorithm: Algorithm,
+        dest: Option<&MPlaceTy<'tcx>>,
+        ret: Option<mir::BasicBlock>,
+    ) -> InterpResult<'tcx, EmulateItemResult> {
+        let this = self.eval_context_mut();
+        // Check that this is a function pointer right away, so the error points at the caller.
+        let instance = this.get_ptr_fn(compar)?.as_instance()?;
+        if this.tcx.is_foreign_item(instance.def_id()) {
+            // Shims do not push a frame that could continue the algorithm once they return.
+            throw_unsup_format!(
+                "`qsort` and `bsearch` only support comparison functions defined in the program"
+            );
+        }
+        let result = this.allocate(this.machine.layouts.i32, MiriMemoryKind::Runtime.into())?;
+        let comparison =
+            Comparison { compar, base, size, algorithm, result, dest: dest.cloned(), ret };
+        this.step_comparison(comparison, None)?;
+        interp_ok(EmulateItemResult::AlreadyJumped)
+    }
+
+    /// Takes the result of the last call of the comparison function into account, if there was
+    /// one, and calls the comparison function again if needed. Once the algorithm is done, this
+    /// stores its result and returns from the `qsort` or `bsearch` call.
+    fn step_comparison(
+        &mut self,
+        mut comparison: Comparison<'tcx>,
+        ordering: Option<i32>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let (base, size) = (comparison.base, comparison.size);
+        let operands = match &mut comparison.algorithm {
+            Algorithm::Sort { nmemb, order, next, lo, hi } => {
+                if let Some(ordering) = ordering {
+                    let mid = lo.midpoint(*hi);
+                    // Elements that compare equal stay in their original order.
+                    if ordering < 0 {
+                        *hi = mid;
+                    } else {
+                        *lo = mid.strict_add(1);
+                    }
+                }
+                // Insert elements into `order` until we need the comparison function again.
+                while lo == hi {
+                    order.insert(*lo, *next);
+                    *next = next.strict_add(1);
+                    if next == nmemb {
+                        break;
+                    }
+                    (*lo, *hi) = (0, order.len());
+                }
+                (next != nmemb).then(|| {
+                    (elem(base, size, *next, this), elem(base, size, order[lo.midpoint(*hi)], this))
+                })
+            }
+            Algorithm::Search { key, lo, hi, found } => {
+                if let Some(ordering) = ordering {
+                    let mid = lo.midpoint(*hi);
+                    match ordering.cmp(&0) {
+                        Ordering::Less => *hi = mid,
+                        Ordering::Greater => *lo = mid.strict_add(1),
+                        Ordering::Equal => *found = Some(mid),
+                    }
+                }
+                // The key is always the left operand.
+                (found.is_none() && lo != hi)
+                    .then(|| (*key, elem(base, size, lo.midpoint(*hi), this)))
+            }
+        };
+
+        let Some((left, right)) = operands else {
+            if let Algorithm::Sort { nmemb, order, .. } = &comparison.algorithm {
+                // Everything is sorted. Now rearrange the elements through a temporary copy, so
+                // that they keep their provenance.
+                let total = Size::from_bytes(nmemb.strict_mul(comparison.size));
+                let tmp = this.allocate_ptr(
+                    total,
+                    Align::ONE,
+                    MiriMemoryKind::Runtime.into(),
+                    AllocInit::Uninit,
+                )?;
+                for (idx, &src) in order.iter().enumerate() {
+                    let dest = tmp.wrapping_offset(
+                        Size::from_bytes(comparison.size.strict_mul(idx.to_u64())),
+                        this,
+                    );
+                    this.mem_copy(
+                        comparison.elem(src, this),
+                        dest.into(),
+                        Size::from_bytes(comparison.size),
+                        /*nonoverlapping*/ true,
+                    )?;
+                }
+                this.mem_copy(tmp.into(), comparison.base, total, /*nonoverlapping*/ true)?;
+                this.deallocate_ptr(tmp.into(), None, MiriMemoryKind::Runtime.into())?;
+            }
+            this.deallocate_ptr(comparison.result.ptr(), None, MiriMemoryKind::Runtime.into())?;
+            if let Some(dest) = &comparison.dest {
+                match comparison.algorithm {
+                    Algorithm::Search { found: Some(idx), .. } =>
+                        this.write_pointer(comparison.elem(idx, this), dest)?,
+                    _ => this.write_null(dest)?,
+                }
+            }
+            return this.return_to_block(comparison.ret);
+        };
+
+        // The signature of the comparison function is `int (*)(const void *, const void *)`.
+        let instance = this.get_ptr_fn(comparison.compar)?.as_instance()?;
+        let ptr_layout = this.machine.layouts.const_raw_ptr;
+        this.call_function(
+            instance,
+            ExternAbi::C { unwind: false },
+            &[
+                ImmTy::from_scalar(Scalar::from_maybe_pointer(left, this), ptr_layout),
+                ImmTy::from_scalar(Scalar::from_maybe_pointer(right, this), ptr_layout),
+            ],
+            Some(&comparison.result),
+            // `on_return` takes over once the comparison function returns.
+            ReturnContinuation::Goto { ret: None, unwind: mir::UnwindAction::Unreachable },
+        )?;
+        this.frame_mut().extra.on_return = Some(callback!(
+            @capture<'tcx> {
+                comparison: Comparison<'tcx>,
+            }
+            |this, _unit: ()| {
+                let ordering = this.read_scalar(&comparison.result)?.to_i32()?;
+                this.step_comparison(comparison, Some(ordering))
+            }
+        ));
+        interp_ok(())
+    }
+
+    /// Stores where number parsing stopped in `*endptr`, unless `endptr` is null.
+    fn write_endptr(
+        &mut self,
+        endptr: Pointer,
+        nptr: Pointer,
+        consumed: usize,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        if this.ptr_is_null(endptr)? {
+            return interp_ok(());
+        }
+        let end = nptr.wrapping_offset(Size::from_bytes(consumed), this);
+        let endptr = this.ptr_to_mplace(endptr, this.machine.layouts.mut_raw_ptr);
+        this.write_pointer(end, &endptr)
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// `strtol` and friends. The width of the result is taken from `dest`.
+    fn strtol(
+        &mut self,
+        nptr: &OpTy<'tcx>,
+        endptr: &OpTy<'tcx>,
+        base: &OpTy<'tcx>,
+        signed: bool,
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let nptr = this.read_pointer(nptr)?;
+        let endptr = this.read_pointer(endptr)?;
+        let base = this.read_scalar(base)?.to_i32()?;
+
+        let Ok(base @ (0 | 2..=36)) = u32::try_from(base) else {
+            this.set_last_error(LibcError("EINVAL"))?;
+            this.write_endptr(endptr, nptr, 0)?;
+            return this.write_int(0, dest);
+        };
+        let (negative, magnitude, consumed) = parse_c_int(this.read_c_str(nptr)?, base);
+
+        let size = dest.layout.size;
+        let value = if signed {
+            let (min, max) = (size.signed_int_min(), size.signed_int_max());
+            match magnitude.and_then(|m| i128::try_from(m).ok()) {
+                Some(m) if negative && m.strict_neg() >= min => m.strict_neg(),
+                Some(m) if !negative && m <= max => m,
+                _ => {
+                    this.set_last_error(LibcError("ERANGE"))?;
+                    if negative { min } else { max }
+                }
+            }
+        } else {
+            // For the unsigned functions, a minus sign negates the result in the result type.
+            let max = size.unsigned_int_max();
+            match magnitude {
+                Some(m) if m <= max && negative => size.truncate(m.wrapping_neg()).cast_signed(),
+                Some(m) if m <= max => m.cast_signed(),
+                _ => {
+                    this.set_last_error(LibcError("ERANGE"))?;
+                    max.cast_signed()
+                }
+            }
+        };
+        this.write_endptr(endptr, nptr, consumed)?;
+        this.write_int(value, dest)
+    }
+
+    /// `strtod` and `strtof`. The precision of the result is taken from `dest`.
+    fn strtod(
+        &mut self,
+        nptr: &OpTy<'tcx>,
+        endptr: &OpTy<'tcx>,
+        dest: &MPlaceTy<'tcx>,
+        link_name: &str,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        // The radix character depends on the locale.
+        crate::shims::LocaleState::note_access(this)?;
+        let nptr = this.read_pointer(nptr)?;
+        let endptr = this.read_pointer(endptr)?;
+        let bytes = this.read_c_str(nptr)?;
+
+        let mut digits = bytes.iter().skip_while(|&&b| is_c_space(b) || b == b'-' || b == b'+');
+        if digits.next() == Some(&b'0')
+            && matches!(digits.next(), Some(b'x' | b'X'))
+            && digits.next().is_some_and(|b| b.is_ascii_hexdigit() || *b == b'.')
+        {
+            throw_unsup_format!(
+                "`{link_name}` does not support hexadecimal floating-point numbers"
+            );
+        }
+
+        let Some((text, consumed)) = scan_c_float(bytes) else {
+            this.write_endptr(endptr, nptr, 0)?;
+            return this.write_scalar(Scalar::from_uint(0u32, dest.layout.size), dest);
+        };
+        let is_infinity = text.trim_start_matches(['-', '+']).starts_with(['i', 'I']);
+        let mantissa = text.split(['e', 'E']).next().unwrap();
+        let has_nonzero_digit = mantissa.bytes().any(|b| matches!(b, b'1'..=b'9'));
+        // Overflow yields infinity and underflow yields zero, and both are reported with `ERANGE`.
+        let (value, out_of_range) = match dest.layout.size.bytes() {
+            4 => {
+                let value: f32 = text.parse().unwrap();
+                let out_of_range =
+                    (value.is_infinite() && !is_infinity) || (value == 0.0 && has_nonzero_digit);
+                (Scalar::from_f32(value.to_soft()), out_of_range)
+            }
+            8 => {
+                let value: f64 = text.parse().unwrap();
+                let out_of_range =
+                    (value.is_infinite() && !is_infinity) || (value == 0.0 && has_nonzero_digit);
+                (Scalar::from_f64(value.to_soft()), out_of_range)
+            }
+            size => bug!("`{link_name}` returning a float of size {size}"),
+        };
+        if out_of_range {
+            this.set_last_error(LibcError("ERANGE"))?;
+        }
+        this.write_endptr(endptr, nptr, consumed)?;
+        this.write_scalar(value, dest)
+    }
+
+    /// `qsort`. The sort is stable, which the standard does not require, but which is what glibc
+    /// usually does as well.
+    fn qsort(
+        &mut self,
+        base: &OpTy<'tcx>,
+        nmemb: &OpTy<'tcx>,
+        size: &OpTy<'tcx>,
+        compar: &OpTy<'tcx>,
+        ret: Option<mir::BasicBlock>,
+    ) -> InterpResult<'tcx, EmulateItemResult> {
+        let this = self.eval_context_mut();
+        let base = this.read_pointer(base)?;
+        let nmemb = this.read_target_usize(nmemb)?;
+        let size = this.read_target_usize(size)?;
+        let compar = this.read_pointer(compar)?;
+
+        if nmemb <= 1 {
+            return interp_ok(EmulateItemResult::NeedsReturn);
+        }
+        let algorithm = Algorithm::Sort { nmemb, order: vec![0], next: 1, lo: 0, hi: 1 };
+        this.start_comparison(compar, base, size, algorithm, None, ret)
+    }
+
+    /// `bsearch`.
+    fn bsearch(
+        &mut self,
+        key: &OpTy<'tcx>,
+        base: &OpTy<'tcx>,
+        nmemb: &OpTy<'tcx>,
+        size: &OpTy<'tcx>,
+        compar: &OpTy<'tcx>,
+        dest: &MPlaceTy<'tcx>,
+        ret: Option<mir::BasicBlock>,
+    ) -> InterpResult<'tcx, EmulateItemResult> {
+        let this = self.eval_context_mut();
+        let key = this.read_pointer(key)?;
+        let base = this.read_pointer(base)?;
+        let nmemb = this.read_target_usize(nmemb)?;
+        let size = this.read_target_usize(size)?;
+        let compar = this.read_pointer(compar)?;
+
+        if nmemb == 0 {
+            this.write_null(dest)?;
+            return interp_ok(EmulateItemResult::NeedsReturn);
+        }
+        let algorithm = Algorithm::Search { key, lo: 0, hi: nmemb, found: None };
+        this.start_comparison(compar, base, size, algorithm, Some(dest), ret)
+    }
+}
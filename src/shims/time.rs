@@ -1,14 +1,23 @@
-use std::ffi::{OsStr, OsString};
-use std::fmt::Write;
-use std::str::FromStr;
 use std::time::{Duration, SystemTime};
 
-use chrono::{DateTime, Datelike, Offset, Timelike, Utc};
-use chrono_tz::Tz;
 use rustc_target::spec::Os;
 
 use crate::*;
 
+/// The number of clock ticks per second reported by `sysconf(_SC_CLK_TCK)` and used by `times`.
+pub const CLOCK_TICKS_PER_SEC: u64 = 100;
+
+/// A clock measuring the CPU time used by the process or one of its threads.
+///
+/// We derive CPU time from the number of basic blocks that were executed (see
+/// `cpu_time_for_basic_blocks`), so it is deterministic and reflects which thread did the work.
+/// All of it counts as user time; Miri never spends time in the "kernel".
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CpuClock {
+    Process,
+    Thread(ThreadId),
+}
+
 /// Returns the time elapsed between the provided time and the unix epoch as a `Duration`.
 pub fn system_time_to_duration<'tcx>(time: &SystemTime) -> InterpResult<'tcx, Duration> {
     time.duration_since(SystemTime::UNIX_EPOCH)
@@ -18,6 +27,13 @@ pub fn system_time_to_duration<'tcx>(time: &SystemTime) -> InterpResult<'tcx, Du
 
 impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
 pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// The current wall clock time. With isolation, this is a virtual clock that advances along
+    /// with the monotonic clock (see `RealtimeClock`).
+    fn system_time_now(&self) -> SystemTime {
+        let this = self.eval_context_ref();
+        this.machine.realtime_clock.now(&this.machine.monotonic_clock)
+    }
+
     fn parse_clockid(&self, clk_id: Scalar) -> Option<TimeoutClock> {
         // This clock support is deliberately minimal because a lot of clock types have fiddly
         // properties (is it possible for Miri to be suspended independently of the host?). If you
@@ -57,6 +73,129 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         None
     }
 
+    /// Parses the CPU time clocks: `CLOCK_PROCESS_CPUTIME_ID`, `CLOCK_THREAD_CPUTIME_ID`, and the
+    /// clock IDs handed out by `pthread_getcpuclockid`.
+    #[allow(clippy::arithmetic_side_effects)]
+    fn parse_cpu_clockid(&self, clk_id: Scalar) -> InterpResult<'tcx, Option<CpuClock>> {
+        let this = self.eval_context_ref();
+
+        if !matches!(this.tcx.sess.target.os, Os::Linux | Os::FreeBsd | Os::Android | Os::MacOs) {
+            return interp_ok(None);
+        }
+        if clk_id == this.eval_libc("CLOCK_PROCESS_CPUTIME_ID") {
+            return interp_ok(Some(CpuClock::Process));
+        } else if clk_id == this.eval_libc("CLOCK_THREAD_CPUTIME_ID") {
+            return interp_ok(Some(CpuClock::Thread(this.active_thread())));
+        }
+
+        // See `pthread_getcpuclockid` for the encoding of per-thread clocks.
+        if this.tcx.sess.target.os != Os::MacOs {
+            let clk_id = clk_id.to_int(this.libc_ty_layout("clockid_t").size)?;
+            if clk_id < 0 && clk_id & 7 == 6 {
+                return interp_ok(
+                    this.thread_id_try_from(!(clk_id >> 3)).ok().map(CpuClock::Thread),
+                );
+            }
+        }
+
+        interp_ok(None)
+    }
+
+    /// The CPU time used so far by the process or thread measured by `clock`.
+    fn cpu_time(&self, clock: CpuClock) -> Duration {
+        let this = self.eval_context_ref();
+        let blocks = match clock {
+            CpuClock::Process => this.machine.basic_block_count,
+            CpuClock::Thread(thread) => this.machine.threads.get_thread_basic_block_count(thread),
+        };
+        cpu_time_for_basic_blocks(blocks)
+    }
+
+    fn pthread_getcpuclockid(
+        &mut self,
+        thread_op: &OpTy<'tcx>,
+        clock_id_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let thread = this.read_scalar(thread_op)?.to_int(this.libc_ty_layout("pthread_t").size)?;
+        let Ok(thread) = this.thread_id_try_from(thread) else {
+            return interp_ok(this.eval_libc("ESRCH"));
+        };
+        let clock_id = this.deref_pointer_as(clock_id_op, this.libc_ty_layout("clockid_t"))?;
+
+        // We use the same encoding as glibc: the bitwise negation of the thread ID, shifted left
+        // by 3, with the lower bits set to `CPUCLOCK_PERTHREAD_MASK | CPUCLOCK_SCHED`. This keeps
+        // these IDs clear of all the (non-negative) predefined clocks.
+        let id = (!i64::from(thread.to_u32())).strict_mul(8) | 6;
+        this.write_int(id, &clock_id)?;
+
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn getrusage(
+        &mut self,
+        who_op: &OpTy<'tcx>,
+        usage_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        this.assert_target_os_is_unix("getrusage");
+
+        let who = this.read_scalar(who_op)?.to_i32()?;
+        let usage = this.deref_pointer_as(usage_op, this.libc_ty_layout("rusage"))?;
+
+        let utime = if who == this.eval_libc_i32("RUSAGE_SELF") {
+            this.cpu_time(CpuClock::Process)
+        } else if who == this.eval_libc_i32("RUSAGE_CHILDREN") {
+            // We never have any children.
+            Duration::ZERO
+        } else if matches!(this.tcx.sess.target.os, Os::Linux | Os::FreeBsd | Os::Android)
+            && who == this.eval_libc_i32("RUSAGE_THREAD")
+        {
+            this.cpu_time(CpuClock::Thread(this.active_thread()))
+        } else {
+            return this.set_last_error_and_return_i32(LibcError("EINVAL"));
+        };
+
+        // All the other statistics (memory usage, page faults, context switches, ...) are zero.
+        this.write_bytes_ptr(
+            usage.ptr(),
+            std::iter::repeat_n(0u8, usage.layout.size.bytes_usize()),
+        )?;
+        let ru_utime = this.project_field_named(&usage, "ru_utime")?;
+        this.write_int_fields(&[utime.as_secs().into(), utime.subsec_micros().into()], &ru_utime)?;
+
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    #[allow(clippy::arithmetic_side_effects)]
+    fn times(&mut self, buf_op: &OpTy<'tcx>, dest: &MPlaceTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        this.assert_target_os_is_unix("times");
+
+        let buf = this.deref_pointer_as(buf_op, this.libc_ty_layout("tms"))?;
+        let to_ticks = |duration: Duration| {
+            let ticks = duration.as_nanos() / u128::from(1_000_000_000 / CLOCK_TICKS_PER_SEC);
+            i64::try_from(ticks).map_err(|_| {
+                err_unsup_format!("programs running longer than 2^63 clock ticks are not supported")
+            })
+        };
+
+        let utime = to_ticks(this.cpu_time(CpuClock::Process))?;
+        this.write_int_fields_named(
+            &[("tms_utime", utime.into()), ("tms_stime", 0), ("tms_cutime", 0), ("tms_cstime", 0)],
+            &buf,
+        )?;
+
+        // The return value is the elapsed real time since some arbitrary point in the past.
+        let elapsed = this.machine.monotonic_clock.elapsed();
+        this.write_int(to_ticks(elapsed)?, dest)?;
+
+        interp_ok(())
+    }
+
     fn clock_gettime(
         &mut self,
         clk_id_op: &OpTy<'tcx>,
@@ -70,11 +209,18 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let clk_id = this.read_scalar(clk_id_op)?;
         let tp = this.deref_pointer_as(tp_op, this.libc_ty_layout("timespec"))?;
 
+        if let Some(cpu_clock) = this.parse_cpu_clockid(clk_id)? {
+            let duration = this.cpu_time(cpu_clock);
+            this.write_int_fields(
+                &[duration.as_secs().into(), duration.subsec_nanos().into()],
+                &tp,
+            )?;
+            this.write_int(0, dest)?;
+            return interp_ok(());
+        }
+
         let duration = match this.parse_clockid(clk_id) {
-            Some(TimeoutClock::RealTime) => {
-                this.check_no_isolation("`clock_gettime` with `REALTIME` clocks")?;
-                system_time_to_duration(&SystemTime::now())?
-            }
+            Some(TimeoutClock::RealTime) => system_time_to_duration(&this.system_time_now())?,
             Some(TimeoutClock::Monotonic) =>
                 this.machine
                     .monotonic_clock
@@ -102,7 +248,6 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let this = self.eval_context_mut();
 
         this.assert_target_os_is_unix("gettimeofday");
-        this.check_no_isolation("`gettimeofday`")?;
 
         let tv = this.deref_pointer_as(tv_op, this.libc_ty_layout("timeval"))?;
 
@@ -112,7 +257,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             return this.set_last_error_and_return_i32(LibcError("EINVAL"));
         }
 
-        let duration = system_time_to_duration(&SystemTime::now())?;
+        let duration = system_time_to_duration(&this.system_time_now())?;
         let tv_sec = duration.as_secs();
         let tv_usec = duration.subsec_micros();
 
@@ -121,93 +266,6 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         interp_ok(Scalar::from_i32(0))
     }
 
-    // The localtime() function shall convert the time in seconds since the Epoch pointed to by
-    // timer into a broken-down time, expressed as a local time.
-    // https://linux.die.net/man/3/localtime_r
-    fn localtime_r(
-        &mut self,
-        timep: &OpTy<'tcx>,
-        result_op: &OpTy<'tcx>,
-    ) -> InterpResult<'tcx, Pointer> {
-        let this = self.eval_context_mut();
-
-        this.assert_target_os_is_unix("localtime_r");
-        this.check_no_isolation("`localtime_r`")?;
-
-        let time_layout = this.libc_ty_layout("time_t");
-        let timep = this.deref_pointer_as(timep, time_layout)?;
-        let result = this.deref_pointer_as(result_op, this.libc_ty_layout("tm"))?;
-
-        // The input "represents the number of seconds elapsed since the Epoch,
-        // 1970-01-01 00:00:00 +0000 (UTC)".
-        let sec_since_epoch: i64 =
-            this.read_scalar(&timep)?.to_int(time_layout.size)?.try_into().unwrap();
-        let dt_utc: DateTime<Utc> =
-            DateTime::from_timestamp(sec_since_epoch, 0).expect("Invalid timestamp");
-
-        // Figure out what time zone is in use
-        let tz = this.get_env_var(OsStr::new("TZ"))?.unwrap_or_else(|| OsString::from("UTC"));
-        let tz = match tz.into_string() {
-            Ok(tz) => Tz::from_str(&tz).unwrap_or(Tz::UTC),
-            _ => Tz::UTC,
-        };
-
-        // Convert that to local time, then return the broken-down time value.
-        let dt: DateTime<Tz> = dt_utc.with_timezone(&tz);
-
-        // This value is always set to -1, because there is no way to know if dst is in effect with
-        // chrono crate yet.
-        // This may not be consistent with libc::localtime_r's result.
-        let tm_isdst = -1;
-        this.write_int_fields_named(
-            &[
-                ("tm_sec", dt.second().into()),
-                ("tm_min", dt.minute().into()),
-                ("tm_hour", dt.hour().into()),
-                ("tm_mday", dt.day().into()),
-                ("tm_mon", dt.month0().into()),
-                ("tm_year", dt.year().strict_sub(1900).into()),
-                ("tm_wday", dt.weekday().num_days_from_sunday().into()),
-                ("tm_yday", dt.ordinal0().into()),
-                ("tm_isdst", tm_isdst),
-            ],
-            &result,
-        )?;
-
-        // solaris/illumos system tm struct does not have
-        // the additional tm_zone/tm_gmtoff fields.
-        // https://docs.oracle.com/cd/E36784_01/html/E36874/localtime-r-3c.html
-        if !matches!(&this.tcx.sess.target.os, Os::Solaris | Os::Illumos) {
-            // tm_zone represents the timezone value in the form of: +0730, +08, -0730 or -08.
-            // This may not be consistent with libc::localtime_r's result.
-
-            let offset_in_seconds = dt.offset().fix().local_minus_utc();
-            let tm_gmtoff = offset_in_seconds;
-            let mut tm_zone = String::new();
-            if offset_in_seconds < 0 {
-                tm_zone.push('-');
-            } else {
-                tm_zone.push('+');
-            }
-            let offset_hour = offset_in_seconds.abs() / 3600;
-            write!(tm_zone, "{offset_hour:02}").unwrap();
-            let offset_min = (offset_in_seconds.abs() % 3600) / 60;
-            if offset_min != 0 {
-                write!(tm_zone, "{offset_min:02}").unwrap();
-            }
-
-            // Add null terminator for C string compatibility.
-            tm_zone.push('\0');
-
-            // Deduplicate and allocate the string.
-            let tm_zone_ptr = this.allocate_bytes_dedup(tm_zone.as_bytes())?;
-
-            // Write the timezone pointer and offset into the result structure.
-            this.write_pointer(tm_zone_ptr, &this.project_field_named(&result, "tm_zone")?)?;
-            this.write_int_fields_named(&[("tm_gmtoff", tm_gmtoff.into())], &result)?;
-        }
-        interp_ok(result.ptr())
-    }
     #[allow(non_snake_case, clippy::arithmetic_side_effects)]
     fn GetSystemTimeAsFileTime(
         &mut self,
@@ -217,11 +275,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let this = self.eval_context_mut();
 
         this.assert_target_os(Os::Windows, shim_name);
-        this.check_no_isolation(shim_name)?;
 
         let filetime = this.deref_pointer_as(LPFILETIME_op, this.windows_ty_layout("FILETIME"))?;
 
-        let duration = this.system_time_since_windows_epoch(&SystemTime::now())?;
+        let duration = this.system_time_since_windows_epoch(&this.system_time_now())?;
         let duration_ticks = this.windows_ticks_for(duration)?;
 
         let dwLowDateTime = u32::try_from(duration_ticks & 0x00000000FFFFFFFF).unwrap();
@@ -231,6 +288,49 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         interp_ok(())
     }
 
+    /// Shared implementation of `GetThreadTimes` and `GetProcessTimes`. For the process, we report
+    /// the lifetime of the main thread.
+    #[allow(non_snake_case)]
+    fn write_windows_cpu_times(
+        &mut self,
+        clock: CpuClock,
+        lpCreationTime_op: &OpTy<'tcx>,
+        lpExitTime_op: &OpTy<'tcx>,
+        lpKernelTime_op: &OpTy<'tcx>,
+        lpUserTime_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let thread = match clock {
+            CpuClock::Process => ThreadId::MAIN_THREAD,
+            CpuClock::Thread(thread) => thread,
+        };
+        let (created_at, terminated_at) = this.machine.threads.get_thread_lifetime(thread);
+
+        // Creation and exit time are points in time, so we convert them to the wall clock.
+        let now = this.machine.monotonic_clock.elapsed();
+        let wall_now = this.system_time_now();
+        let to_filetime = |this: &MiriInterpCx<'tcx>, at: Duration| -> InterpResult<'tcx, u64> {
+            let time =
+                wall_now.checked_sub(now.saturating_sub(at)).unwrap_or(SystemTime::UNIX_EPOCH);
+            this.windows_ticks_for(this.system_time_since_windows_epoch(&time)?)
+        };
+        let creation_time = to_filetime(this, created_at)?;
+        // The exit time is undefined for threads that are still running; we use zero.
+        let exit_time = match terminated_at {
+            Some(at) => to_filetime(this, at)?,
+            None => 0,
+        };
+        let user_time = this.windows_ticks_for(this.cpu_time(clock))?;
+
+        this.write_filetime(creation_time, lpCreationTime_op)?;
+        this.write_filetime(exit_time, lpExitTime_op)?;
+        this.write_filetime(0, lpKernelTime_op)?;
+        this.write_filetime(user_time, lpUserTime_op)?;
+
+        interp_ok(this.eval_windows("c", "TRUE"))
+    }
+
     #[allow(non_snake_case)]
     fn QueryPerformanceCounter(
         &mut self,
@@ -1,14 +1,20 @@
 //! General management of file descriptors, and support for
 //! standard file descriptors (stdin/stdout/stderr).
 
-use std::io;
-use std::io::ErrorKind;
+use std::cmp::Ordering;
+use std::fs::TryLockError;
+use std::io::{ErrorKind, Seek};
+use std::time::Duration;
 
 use rand::Rng;
 use rustc_abi::Size;
 use rustc_target::spec::Os;
 
-use crate::shims::files::FileDescription;
+use crate::shims::file_lock::{EvalContextExt as _, FileId, FileLock, LockKind, LockOwner};
+use crate::shims::files::{
+    DynFileDescriptionRef, FileDescription, FileDescriptionRef, FileHandle, WeakFileDescriptionRef,
+};
+use crate::shims::process::{EvalContextExt as _, is_child_output};
 use crate::shims::sig::check_min_vararg_count;
 use crate::shims::unix::linux_like::epoll::EpollEvents;
 use crate::shims::unix::*;
@@ -54,20 +60,282 @@ pub trait UnixFileDescription: FileDescription {
         throw_unsup_format!("cannot pwrite to {}", self.name());
     }
 
-    fn flock<'tcx>(
-        &self,
-        _communicate_allowed: bool,
-        _op: FlockOp,
-    ) -> InterpResult<'tcx, io::Result<()>> {
-        throw_unsup_format!("cannot flock {}", self.name());
-    }
-
     /// Return which epoll events are currently active.
     fn epoll_active_events<'tcx>(&self) -> InterpResult<'tcx, EpollEvents> {
         throw_unsup_format!("{}: epoll does not support this file description", self.name());
     }
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum RecordLockOp {
+    /// `F_GETLK`: find a lock that would conflict with the given one.
+    Get,
+    /// `F_SETLK` (non-blocking) and `F_SETLKW` (blocking): acquire or release a lock.
+    Set { blocking: bool },
+}
+
+impl<'tcx> EvalContextPrivExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextPrivExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Checks the `nfds` entries of the `pollfd` array `fds`, like `poll`. If none of them is
+    /// ready, this blocks until one is or until `deadline` (relative to the epoch of the
+    /// monotonic clock) passes.
+    fn poll_until(
+        &mut self,
+        fds: Pointer,
+        nfds: u64,
+        deadline: Option<Duration>,
+        dest: MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let pollin = this.eval_libc("POLLIN").to_i16()?;
+        let pollout = this.eval_libc("POLLOUT").to_i16()?;
+        let pollerr = this.eval_libc("POLLERR").to_i16()?;
+        let pollhup = this.eval_libc("POLLHUP").to_i16()?;
+        let pollnval = this.eval_libc("POLLNVAL").to_i16()?;
+
+        let pollfd_layout = this.libc_ty_layout("pollfd");
+        let mut ready = 0i32;
+        let mut can_wait = false;
+        for idx in 0..nfds {
+            let pollfd = this
+                .ptr_to_mplace(fds.wrapping_offset(pollfd_layout.size * idx, this), pollfd_layout);
+            let fd_num = this.read_scalar(&this.project_field_named(&pollfd, "fd")?)?.to_i32()?;
+            let events =
+                this.read_scalar(&this.project_field_named(&pollfd, "events")?)?.to_i16()?;
+            let revents = if fd_num < 0 {
+                0
+            } else if let Some(fd) = this.machine.fds.get(fd_num) {
+                can_wait |= is_child_output(&fd);
+                let active = fd.as_unix(this).epoll_active_events()?;
+                let mut revents = 0;
+                if active.epollin {
+                    revents |= events & pollin;
+                }
+                if active.epollout {
+                    revents |= events & pollout;
+                }
+                // These are always reported.
+                if active.epollhup {
+                    revents |= pollhup;
+                }
+                if active.epollerr {
+                    revents |= pollerr;
+                }
+                revents
+            } else {
+                pollnval
+            };
+            if revents != 0 {
+                ready = ready.strict_add(1);
+            }
+            this.write_int(revents, &this.project_field_named(&pollfd, "revents")?)?;
+        }
+        if ready > 0 {
+            return this.write_int(ready, &dest);
+        }
+        if deadline.is_some_and(|deadline| this.machine.monotonic_clock.elapsed() >= deadline) {
+            return this.write_int(0, &dest);
+        }
+        // Only data from child processes can arrive while this thread is blocked.
+        if !can_wait {
+            throw_unsup_format!(
+                "poll: blocking is only supported on pipes connected to child processes"
+            );
+        }
+        this.block_on_child_process(
+            deadline,
+            callback!(
+                @capture<'tcx> {
+                    fds: Pointer,
+                    nfds: u64,
+                    deadline: Option<Duration>,
+                    dest: MPlaceTy<'tcx>,
+                }
+                |this, _unblock: ()| this.poll_until(fds, nfds, deadline, dest)
+            ),
+        );
+        interp_ok(())
+    }
+
+    /// If `cmd` is a record locking command, returns its name, what it does, and whether it
+    /// is an open file description lock command (`F_OFD_*`).
+    fn record_lock_cmd(&self, cmd: i32) -> Option<(&'static str, RecordLockOp, bool)> {
+        let this = self.eval_context_ref();
+
+        let mut cmds = vec![
+            ("F_GETLK", RecordLockOp::Get, false),
+            ("F_SETLK", RecordLockOp::Set { blocking: false }, false),
+            ("F_SETLKW", RecordLockOp::Set { blocking: true }, false),
+        ];
+        if this.tcx.sess.target.os == Os::Linux {
+            cmds.extend([
+                ("F_OFD_GETLK", RecordLockOp::Get, true),
+                ("F_OFD_SETLK", RecordLockOp::Set { blocking: false }, true),
+                ("F_OFD_SETLKW", RecordLockOp::Set { blocking: true }, true),
+            ]);
+        }
+        cmds.into_iter().find(|(name, ..)| this.eval_libc_i32(name) == cmd)
+    }
+
+    fn fcntl_record_lock(
+        &mut self,
+        fd_num: i32,
+        op: RecordLockOp,
+        ofd: bool,
+        lock_op: &OpTy<'tcx>,
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let lock_place = this.deref_pointer_as(lock_op, this.libc_ty_layout("flock"))?;
+        let l_type = this.read_scalar(&this.project_field_named(&lock_place, "l_type")?)?;
+        let l_type = l_type.to_int(l_type.size())?;
+        let l_whence = this.read_scalar(&this.project_field_named(&lock_place, "l_whence")?)?;
+        let l_whence = l_whence.to_int(l_whence.size())?;
+        let l_start = this.read_scalar(&this.project_field_named(&lock_place, "l_start")?)?;
+        let l_start = l_start.to_int(l_start.size())?;
+        let l_len = this.read_scalar(&this.project_field_named(&lock_place, "l_len")?)?;
+        let l_len = l_len.to_int(l_len.size())?;
+        let l_pid = this.read_scalar(&this.project_field_named(&lock_place, "l_pid")?)?;
+        let l_pid = l_pid.to_int(l_pid.size())?;
+
+        let Some(fd) = this.machine.fds.get(fd_num) else {
+            return this.set_last_error_and_return(LibcError("EBADF"), dest);
+        };
+        let Some(file) = fd.downcast::<FileHandle>() else {
+            throw_unsup_format!(
+                "record locks are only supported on files, not on {}",
+                this.machine.fds.get(fd_num).unwrap().name()
+            );
+        };
+        let file_id = match FileId::of(&file.file) {
+            Ok(file_id) => file_id,
+            Err(err) => return this.set_last_error_and_return(err, dest),
+        };
+        // Open file description locks require `l_pid` to be zero.
+        if ofd && l_pid != 0 {
+            return this.set_last_error_and_return(LibcError("EINVAL"), dest);
+        }
+        let owner = if ofd { LockOwner::Description(file.id()) } else { LockOwner::Process };
+
+        let exclusive = if l_type == i128::from(this.eval_libc_i32("F_RDLCK")) {
+            Some(false)
+        } else if l_type == i128::from(this.eval_libc_i32("F_WRLCK")) {
+            Some(true)
+        } else if l_type == i128::from(this.eval_libc_i32("F_UNLCK")) {
+            None
+        } else {
+            return this.set_last_error_and_return(LibcError("EINVAL"), dest);
+        };
+
+        // Determine the affected range. `l_start` is relative to `l_whence`, and `l_len` may be
+        // negative (the range then ends at `l_start`) or zero (the range extends to infinity).
+        let base = if l_whence == i128::from(this.eval_libc_i32("SEEK_SET")) {
+            0
+        } else if l_whence == i128::from(this.eval_libc_i32("SEEK_CUR")) {
+            match (&mut &file.file).stream_position() {
+                Ok(pos) => i128::from(pos),
+                Err(err) => return this.set_last_error_and_return(err, dest),
+            }
+        } else if l_whence == i128::from(this.eval_libc_i32("SEEK_END")) {
+            match file.file.metadata() {
+                Ok(metadata) => i128::from(metadata.len()),
+                Err(err) => return this.set_last_error_and_return(err, dest),
+            }
+        } else {
+            return this.set_last_error_and_return(LibcError("EINVAL"), dest);
+        };
+        let start = base.strict_add(l_start);
+        let (start, end) = match l_len.cmp(&0) {
+            Ordering::Greater => (start, start.strict_add(l_len)),
+            Ordering::Equal => (start, i128::from(u64::MAX)),
+            Ordering::Less => (start.strict_add(l_len), start),
+        };
+        let (Ok(start), Ok(end)) = (u64::try_from(start), u64::try_from(end)) else {
+            return this.set_last_error_and_return(LibcError("EINVAL"), dest);
+        };
+
+        match op {
+            RecordLockOp::Get => {
+                let Some(exclusive) = exclusive else {
+                    return this.set_last_error_and_return(LibcError("EINVAL"), dest);
+                };
+                let lock = FileLock { owner, start, end, exclusive };
+                match this.machine.file_locks.conflict(file_id, LockKind::Record, &lock) {
+                    Some(held) => {
+                        let l_type = if held.exclusive { "F_WRLCK" } else { "F_RDLCK" };
+                        let l_len =
+                            if held.end == u64::MAX { 0 } else { held.end.strict_sub(held.start) };
+                        // Open file description locks do not have an owning process.
+                        let l_pid = match held.owner {
+                            LockOwner::Process => i128::from(this.get_pid()),
+                            LockOwner::Description(_) => -1,
+                        };
+                        this.write_int_fields_named(
+                            &[
+                                ("l_type", this.eval_libc_i32(l_type).into()),
+                                ("l_whence", this.eval_libc_i32("SEEK_SET").into()),
+                                ("l_start", held.start.into()),
+                                ("l_len", l_len.into()),
+                                ("l_pid", l_pid),
+                            ],
+                            &lock_place,
+                        )?;
+                    }
+                    None => {
+                        this.write_int_fields_named(
+                            &[("l_type", this.eval_libc_i32("F_UNLCK").into())],
+                            &lock_place,
+                        )?;
+                    }
+                }
+                this.write_scalar(Scalar::from_i32(0), dest)
+            }
+            RecordLockOp::Set { blocking } => {
+                let Some(exclusive) = exclusive else {
+                    this.release_file_lock(file_id, LockKind::Record, owner, start, end)?;
+                    return this.write_scalar(Scalar::from_i32(0), dest);
+                };
+                // A write lock requires the file to be open for writing.
+                if exclusive && !file.writable {
+                    return this.set_last_error_and_return(LibcError("EBADF"), dest);
+                }
+                let lock = FileLock { owner, start, end, exclusive };
+                this.acquire_file_lock(
+                    file_id,
+                    LockKind::Record,
+                    lock,
+                    blocking,
+                    callback!(
+                        @capture<'tcx> {
+                            dest: MPlaceTy<'tcx>,
+                        }
+                        |this, result: Result<(), IoError>| {
+                            match result {
+                                Ok(()) => this.write_scalar(Scalar::from_i32(0), &dest),
+                                Err(err) => this.set_last_error_and_return(err, &dest),
+                            }
+                        }
+                    ),
+                )
+            }
+        }
+    }
+
+    /// Classic POSIX record locks are released as soon as *any* file descriptor for the file is
+    /// closed by the process, even one referring to a different file description.
+    fn release_process_record_locks(&mut self, fd: DynFileDescriptionRef) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        if let Some(file) = fd.downcast::<FileHandle>()
+            && let Ok(file_id) = FileId::of(&file.file)
+        {
+            this.release_file_locks_of(LockOwner::Process, Some(file_id))?;
+        }
+        interp_ok(())
+    }
+}
+
 impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
 pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
     fn dup(&mut self, old_fd_num: i32) -> InterpResult<'tcx, Scalar> {
@@ -76,6 +344,9 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let Some(fd) = this.machine.fds.get(old_fd_num) else {
             return this.set_last_error_and_return_i32(LibcError("EBADF"));
         };
+        if !this.fd_limit_allows(1, 0) {
+            return this.set_last_error_and_return_i32(LibcError("EMFILE"));
+        }
         interp_ok(Scalar::from_i32(this.machine.fds.insert(fd)))
     }
 
@@ -85,10 +356,21 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let Some(fd) = this.machine.fds.get(old_fd_num) else {
             return this.set_last_error_and_return_i32(LibcError("EBADF"));
         };
+        if new_fd_num < 0
+            || this
+                .machine
+                .rlimits
+                .nofile
+                .soft
+                .is_some_and(|limit| u64::try_from(new_fd_num).unwrap() >= limit)
+        {
+            return this.set_last_error_and_return_i32(LibcError("EBADF"));
+        }
         if new_fd_num != old_fd_num {
             // Close new_fd if it is previously opened.
             // If old_fd and new_fd point to the same description, then `dup_fd` ensures we keep the underlying file description alive.
             if let Some(old_new_fd) = this.machine.fds.fds.insert(new_fd_num, fd) {
+                this.release_process_record_locks(old_new_fd.clone())?;
                 // Ignore close error (not interpreter's) according to dup2() doc.
                 old_new_fd.close_ref(this.machine.communicate(), this)?.ok();
             }
@@ -96,10 +378,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         interp_ok(Scalar::from_i32(new_fd_num))
     }
 
-    fn flock(&mut self, fd_num: i32, op: i32) -> InterpResult<'tcx, Scalar> {
+    fn flock(&mut self, fd_num: i32, op: i32, dest: &MPlaceTy<'tcx>) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
         let Some(fd) = this.machine.fds.get(fd_num) else {
-            return this.set_last_error_and_return_i32(LibcError("EBADF"));
+            return this.set_last_error_and_return(LibcError("EBADF"), dest);
         };
 
         // We need to check that there aren't unsupported options in `op`.
@@ -123,10 +405,85 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             throw_unsup_format!("unsupported flags {:#x}", op);
         };
 
-        let result = fd.as_unix(this).flock(this.machine.communicate(), parsed_op)?;
-        // return `0` if flock is successful
-        let result = result.map(|()| 0i32);
-        interp_ok(Scalar::from_i32(this.try_unwrap_io_result(result)?))
+        let Some(file) = fd.downcast::<FileHandle>() else {
+            throw_unsup_format!("cannot flock {}", this.machine.fds.get(fd_num).unwrap().name());
+        };
+        let file_id = match FileId::of(&file.file) {
+            Ok(file_id) => file_id,
+            Err(err) => return this.set_last_error_and_return(err, dest),
+        };
+        let owner = LockOwner::Description(file.id());
+
+        let (exclusive, nonblocking) = match parsed_op {
+            SharedLock { nonblocking } => (false, nonblocking),
+            ExclusiveLock { nonblocking } => (true, nonblocking),
+            Unlock => {
+                this.release_file_lock(file_id, LockKind::Flock, owner, 0, u64::MAX)?;
+                let result = file.file.unlock().map(|()| 0i32);
+                let result = this.try_unwrap_io_result(result)?;
+                return this.write_scalar(Scalar::from_i32(result), dest);
+            }
+        };
+
+        // Like on Linux, converting a lock first drops the old one. This avoids a deadlock when
+        // two shared lock holders both try to upgrade.
+        this.release_file_lock(file_id, LockKind::Flock, owner, 0, u64::MAX)?;
+        // The lock is tracked by the interpreter so that interpreted threads can wait for each
+        // other. Once we have it, we also take it on the host so that other processes see it.
+        let lock = FileLock { owner, start: 0, end: u64::MAX, exclusive };
+        let file = FileDescriptionRef::downgrade(&file);
+        this.acquire_file_lock(
+            file_id,
+            LockKind::Flock,
+            lock,
+            !nonblocking,
+            callback!(
+                @capture<'tcx> {
+                    file: WeakFileDescriptionRef<FileHandle>,
+                    file_id: FileId,
+                    lock: FileLock,
+                    nonblocking: bool,
+                    dest: MPlaceTy<'tcx>,
+                }
+                |this, result: Result<(), IoError>| {
+                    let result = match result {
+                        Ok(()) => {
+                            let Some(file) = file.upgrade() else {
+                                // The file got closed while we were waiting for the lock.
+                                return this.set_last_error_and_return(LibcError("EBADF"), &dest);
+                            };
+                            // Drop any lock we already hold first: on some hosts, locks do not
+                            // convert but stack up.
+                            let _ = file.file.unlock();
+                            let host_result = if lock.exclusive {
+                                file.file.try_lock()
+                            } else {
+                                file.file.try_lock_shared()
+                            };
+                            match host_result {
+                                Ok(()) => Ok(()),
+                                Err(TryLockError::Error(err)) => Err(err.into()),
+                                Err(TryLockError::WouldBlock) if nonblocking =>
+                                    Err(ErrorKind::WouldBlock.into()),
+                                Err(TryLockError::WouldBlock) =>
+                                    throw_unsup_format!(
+                                        "blocking `flock` on a file that is locked by another process is not supported"
+                                    ),
+                            }
+                        }
+                        Err(err) => Err(err),
+                    };
+                    match result {
+                        Ok(()) => this.write_scalar(Scalar::from_i32(0), &dest),
+                        Err(err) => {
+                            // We do not hold the lock on the host, so do not keep it here either.
+                            this.release_file_lock(file_id, LockKind::Flock, lock.owner, 0, u64::MAX)?;
+                            this.set_last_error_and_return(err, &dest)
+                        }
+                    }
+                }
+            ),
+        )
     }
 
     fn fcntl(
@@ -134,7 +491,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         fd_num: &OpTy<'tcx>,
         cmd: &OpTy<'tcx>,
         varargs: &[OpTy<'tcx>],
-    ) -> InterpResult<'tcx, Scalar> {
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
         let this = self.eval_context_mut();
 
         let fd_num = this.read_scalar(fd_num)?.to_i32()?;
@@ -146,8 +504,13 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let f_getfl = this.eval_libc_i32("F_GETFL");
         let f_setfl = this.eval_libc_i32("F_SETFL");
 
-        // We only support getting the flags for a descriptor.
-        match cmd {
+        if let Some((name, op, ofd)) = this.record_lock_cmd(cmd) {
+            let cmd_name = format!("fcntl(fd, {name}, ...)");
+            let [lock] = check_min_vararg_count(&cmd_name, varargs)?;
+            return this.fcntl_record_lock(fd_num, op, ofd, lock, dest);
+        }
+
+        let result = match cmd {
             cmd if cmd == f_getfd => {
                 // Currently this is the only flag that `F_GETFD` returns. It is OK to just return the
                 // `FD_CLOEXEC` value without checking if the flag is set for the file because `std`
@@ -174,6 +537,11 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let start = this.read_scalar(start)?.to_i32()?;
 
                 if let Some(fd) = this.machine.fds.get(fd_num) {
+                    if !this.fd_limit_allows(1, start) {
+                        // Linux uses `EINVAL` if `start` itself is out of range, but we do not
+                        // bother distinguishing that case.
+                        return this.set_last_error_and_return(LibcError("EMFILE"), dest);
+                    }
                     interp_ok(Scalar::from_i32(this.machine.fds.insert_with_min_num(fd, start)))
                 } else {
                     this.set_last_error_and_return_i32(LibcError("EBADF"))
@@ -182,7 +550,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             cmd if cmd == f_getfl => {
                 // Check if this is a valid open file descriptor.
                 let Some(fd) = this.machine.fds.get(fd_num) else {
-                    return this.set_last_error_and_return_i32(LibcError("EBADF"));
+                    return this.set_last_error_and_return(LibcError("EBADF"), dest);
                 };
 
                 fd.get_flags(this)
@@ -190,7 +558,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             cmd if cmd == f_setfl => {
                 // Check if this is a valid open file descriptor.
                 let Some(fd) = this.machine.fds.get(fd_num) else {
-                    return this.set_last_error_and_return_i32(LibcError("EBADF"));
+                    return this.set_last_error_and_return(LibcError("EBADF"), dest);
                 };
 
                 let [flag] = check_min_vararg_count("fcntl(fd, F_SETFL, ...)", varargs)?;
@@ -204,7 +572,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 // Reject if isolation is enabled.
                 if let IsolatedOp::Reject(reject_with) = this.machine.isolated_op {
                     this.reject_in_isolation("`fcntl`", reject_with)?;
-                    return this.set_last_error_and_return_i32(ErrorKind::PermissionDenied);
+                    return this.set_last_error_and_return(ErrorKind::PermissionDenied, dest);
                 }
 
                 this.ffullsync_fd(fd_num)
@@ -212,9 +580,69 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             cmd => {
                 throw_unsup_format!("fcntl: unsupported command {cmd:#x}");
             }
+        };
+        this.write_scalar(result?, dest)
+    }
+
+    fn ioctl(
+        &mut self,
+        fd_num: &OpTy<'tcx>,
+        cmd: &OpTy<'tcx>,
+        varargs: &[OpTy<'tcx>],
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        // The type of the request differs between targets, so we compare the raw bits.
+        let libc_cmd = |name| {
+            let cmd = this.eval_libc(name);
+            cmd.to_uint(cmd.size())
+        };
+        let fioclex = libc_cmd("FIOCLEX")?;
+        let fionbio = libc_cmd("FIONBIO")?;
+
+        let fd_num = this.read_scalar(fd_num)?.to_i32()?;
+        let cmd = this.read_scalar(cmd)?.to_uint(cmd.layout.size)?;
+
+        let Some(fd) = this.machine.fds.get(fd_num) else {
+            return this.set_last_error_and_return_i32(LibcError("EBADF"));
+        };
+        if cmd == fioclex {
+            // We always assume the FD_CLOEXEC flag is set, so this is a NOP.
+            interp_ok(Scalar::from_i32(0))
+        } else if cmd == fionbio {
+            let [nonblocking] = check_min_vararg_count("ioctl(fd, FIONBIO, ...)", varargs)?;
+            let nonblocking = this.deref_pointer_as(nonblocking, this.machine.layouts.i32)?;
+            let nonblocking = this.read_scalar(&nonblocking)?.to_i32()? != 0;
+
+            let o_nonblock = this.eval_libc_i32("O_NONBLOCK");
+            let flags = fd.get_flags(this)?.to_i32()?;
+            let flags = if nonblocking { flags | o_nonblock } else { flags & !o_nonblock };
+            fd.set_flags(flags, this)
+        } else {
+            throw_unsup_format!("ioctl: unsupported command {cmd:#x}");
         }
     }
 
+    fn poll(
+        &mut self,
+        fds_op: &OpTy<'tcx>,
+        nfds_op: &OpTy<'tcx>,
+        timeout_op: &OpTy<'tcx>,
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let fds = this.read_pointer(fds_op)?;
+        let nfds = this.read_scalar(nfds_op)?.to_uint(nfds_op.layout.size)?;
+        let timeout = this.read_scalar(timeout_op)?.to_i32()?;
+
+        let now = this.machine.monotonic_clock.elapsed();
+        let deadline = u64::try_from(timeout)
+            .ok()
+            .map(|timeout| now.saturating_add(Duration::from_millis(timeout)));
+        this.poll_until(fds, u64::try_from(nfds).unwrap(), deadline, dest.clone())
+    }
+
     fn close(&mut self, fd_op: &OpTy<'tcx>) -> InterpResult<'tcx, Scalar> {
         let this = self.eval_context_mut();
 
@@ -223,6 +651,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let Some(fd) = this.machine.fds.remove(fd_num) else {
             return this.set_last_error_and_return_i32(LibcError("EBADF"));
         };
+        this.release_process_record_locks(fd.clone())?;
         let result = fd.close_ref(this.machine.communicate(), this)?;
         // return `0` if close is successful
         let result = result.map(|()| 0i32);
@@ -14,6 +14,7 @@ use self::shims::unix::macos::foreign_items as macos;
 use self::shims::unix::solarish::foreign_items as solarish;
 use crate::concurrency::cpu_affinity::CpuAffinityMask;
 use crate::shims::alloc::EvalContextExt as _;
+use crate::shims::printf::{EvalContextExt as _, FormatArgs};
 use crate::shims::unix::*;
 use crate::{shim_sig, *};
 
@@ -26,6 +27,8 @@ pub fn is_dyn_sym(name: &str, target_os: &Os) -> bool {
         "signal" => true,
         // needed at least on macOS to avoid file-based fallback in getrandom
         "getentropy" | "getrandom" => true,
+        // std needs this to run child processes in a different directory.
+        "posix_spawn_file_actions_addchdir_np" => true,
         // Give specific OSes a chance to allow their symbols.
         _ =>
             match *target_os {
@@ -60,11 +63,20 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             // 512 seems to be a reasonable default. The value is not critical, in
             // the sense that getpwuid_r takes and checks the buffer length.
             ("_SC_GETPW_R_SIZE_MAX", |this| Scalar::from_int(512, this.pointer_size())),
-            // Miri doesn't have a fixed limit on FDs, but we may be limited in terms of how
-            // many *host* FDs we can open. Just use some arbitrary, pretty big value;
-            // this can be adjusted if it causes problems.
-            // The spec imposes a minimum of `_POSIX_OPEN_MAX` (20).
-            ("_SC_OPEN_MAX", |this| Scalar::from_int(2_i32.pow(16), this.pointer_size())),
+            // This reflects `RLIMIT_NOFILE`; -1 means there is no limit.
+            ("_SC_OPEN_MAX", |this| {
+                let limit = this
+                    .machine
+                    .rlimits
+                    .nofile
+                    .soft
+                    .map_or(-1, |limit| i64::try_from(limit).unwrap_or(i64::MAX));
+                Scalar::from_int(limit, this.pointer_size())
+            }),
+            // This is what Linux uses, and what `times` is based on.
+            ("_SC_CLK_TCK", |this| {
+                Scalar::from_int(shims::time::CLOCK_TICKS_PER_SEC, this.pointer_size())
+            }),
         ];
         for &(sysconf_name, value) in sysconfs {
             let sysconf_name = this.eval_libc_i32(sysconf_name);
@@ -182,6 +194,93 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let result = this.sysconf(val)?;
                 this.write_scalar(result, dest)?;
             }
+            // Locales
+            "setlocale" => {
+                let [category, locale] = this.check_shim_sig(
+                    shim_sig!(extern "C" fn(i32, *const _) -> *mut _),
+                    link_name,
+                    abi,
+                    args,
+                )?;
+                let result = this.setlocale(category, locale)?;
+                this.write_pointer(result, dest)?;
+            }
+            "localeconv" => {
+                let [] = this.check_shim_sig(
+                    shim_sig!(extern "C" fn() -> *mut _),
+                    link_name,
+                    abi,
+                    args,
+                )?;
+                let result = this.localeconv()?;
+                this.write_pointer(result, dest)?;
+            }
+            "newlocale" => {
+                this.check_target_os(&[Os::Linux, Os::Android, Os::MacOs, Os::FreeBsd], link_name)?;
+                let [mask, locale, base] = this.check_shim_sig(
+                    shim_sig!(extern "C" fn(i32, *const _, *mut _) -> *mut _),
+                    link_name,
+                    abi,
+                    args,
+                )?;
+                let result = this.newlocale(mask, locale, base)?;
+                this.write_pointer(result, dest)?;
+            }
+            "duplocale" => {
+                this.check_target_os(&[Os::Linux, Os::Android, Os::MacOs, Os::FreeBsd], link_name)?;
+                let [locale] = this.check_shim_sig(
+                    shim_sig!(extern "C" fn(*mut _) -> *mut _),
+                    link_name,
+                    abi,
+                    args,
+                )?;
+                let result = this.duplocale(locale)?;
+                this.write_pointer(result, dest)?;
+            }
+            "freelocale" => {
+                this.check_target_os(&[Os::Linux, Os::Android, Os::MacOs, Os::FreeBsd], link_name)?;
+                let [locale] = this.check_shim_sig(
+                    shim_sig!(extern "C" fn(*mut _) -> ()),
+                    link_name,
+                    abi,
+                    args,
+                )?;
+                this.freelocale(locale)?;
+            }
+            "uselocale" => {
+                this.check_target_os(&[Os::Linux, Os::Android, Os::MacOs, Os::FreeBsd], link_name)?;
+                let [locale] = this.check_shim_sig(
+                    shim_sig!(extern "C" fn(*mut _) -> *mut _),
+                    link_name,
+                    abi,
+                    args,
+                )?;
+                let result = this.uselocale(locale)?;
+                this.write_pointer(result, dest)?;
+            }
+            "nl_langinfo" => {
+                this.check_target_os(&[Os::Linux, Os::Android, Os::MacOs, Os::FreeBsd], link_name)?;
+                let [item] = this.check_shim_sig(
+                    shim_sig!(extern "C" fn(i32) -> *mut _),
+                    link_name,
+                    abi,
+                    args,
+                )?;
+                let result = this.nl_langinfo(item, None, link_name.as_str())?;
+                this.write_pointer(result, dest)?;
+            }
+            "nl_langinfo_l" => {
+                this.check_target_os(&[Os::Linux, Os::Android, Os::MacOs, Os::FreeBsd], link_name)?;
+                let [item, locale] = this.check_shim_sig(
+                    shim_sig!(extern "C" fn(i32, *mut _) -> *mut _),
+                    link_name,
+                    abi,
+                    args,
+                )?;
+                let result = this.nl_langinfo(item, Some(locale), link_name.as_str())?;
+                this.write_pointer(result, dest)?;
+            }
+
             // File descriptors
             "read" => {
                 let [fd, buf, count] = this.check_shim_sig(
@@ -208,6 +307,51 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 trace!("Called write({:?}, {:?}, {:?})", fd, buf, count);
                 this.write(fd, buf, count, None, dest)?;
             }
+            // Process exit handlers
+            "__cxa_atexit" => {
+                let [func, arg, _dso_handle] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let func = this.read_pointer(func)?;
+                let arg = this.read_pointer(arg)?;
+                this.register_exit_handler(func, Some(arg), /* quick */ false)?;
+                this.write_int(0, dest)?;
+            }
+            "at_quick_exit" => {
+                let [func] = this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let func = this.read_pointer(func)?;
+                this.register_exit_handler(func, None, /* quick */ true)?;
+                this.write_int(0, dest)?;
+            }
+            "quick_exit" => {
+                let [code] = this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let code = this.read_scalar(code)?.to_i32()?;
+                return this.exit(code, /* quick */ true, link_name.as_str());
+            }
+
+            "printf" => {
+                let ([fmt], varargs) =
+                    this.check_shim_sig_variadic_lenient(abi, CanonAbi::C, link_name, args)?;
+                this.fd_printf(1, fmt, FormatArgs::Varargs(varargs), link_name.as_str(), dest)?;
+            }
+            "vprintf" => {
+                let [fmt, va_list] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let args = FormatArgs::VaList(va_list);
+                this.fd_printf(1, fmt, args, link_name.as_str(), dest)?;
+            }
+            "dprintf" => {
+                let ([fd, fmt], varargs) =
+                    this.check_shim_sig_variadic_lenient(abi, CanonAbi::C, link_name, args)?;
+                let fd = this.read_scalar(fd)?.to_i32()?;
+                this.fd_printf(fd, fmt, FormatArgs::Varargs(varargs), link_name.as_str(), dest)?;
+            }
+            "vdprintf" => {
+                let [fd, fmt, va_list] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let fd = this.read_scalar(fd)?.to_i32()?;
+                let args = FormatArgs::VaList(va_list);
+                this.fd_printf(fd, fmt, args, link_name.as_str(), dest)?;
+            }
             "pread" => {
                 let [fd, buf, count, offset] = this.check_shim_sig(
                     shim_sig!(extern "C" fn(i32, *mut _, usize, libc::off_t) -> isize),
@@ -275,9 +419,19 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             "fcntl" => {
                 let ([fd_num, cmd], varargs) =
                     this.check_shim_sig_variadic_lenient(abi, CanonAbi::C, link_name, args)?;
-                let result = this.fcntl(fd_num, cmd, varargs)?;
+                this.fcntl(fd_num, cmd, varargs, dest)?;
+            }
+            "ioctl" => {
+                let ([fd_num, cmd], varargs) =
+                    this.check_shim_sig_variadic_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.ioctl(fd_num, cmd, varargs)?;
                 this.write_scalar(result, dest)?;
             }
+            "poll" => {
+                let [fds, nfds, timeout] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                this.poll(fds, nfds, timeout, dest)?;
+            }
             "dup" => {
                 let [old_fd] = this.check_shim_sig(
                     shim_sig!(extern "C" fn(i32) -> i32),
@@ -312,8 +466,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 )?;
                 let fd = this.read_scalar(fd)?.to_i32()?;
                 let op = this.read_scalar(op)?.to_i32()?;
-                let result = this.flock(fd, op)?;
-                this.write_scalar(result, dest)?;
+                this.flock(fd, op, dest)?;
             }
 
             // File and file system access
@@ -545,6 +698,93 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 this.write_scalar(result, dest)?;
             }
 
+            // Child processes
+            "posix_spawn" | "posix_spawnp" => {
+                // We can only run the interpreted program itself, so there is no `PATH` to search.
+                let [pid, path, file_actions, attrp, argv, envp] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.posix_spawn(pid, path, file_actions, attrp, argv, envp)?;
+                this.write_scalar(result, dest)?;
+            }
+            "posix_spawn_file_actions_init" => {
+                let [file_actions] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.posix_spawn_file_actions_init(file_actions)?;
+                this.write_scalar(result, dest)?;
+            }
+            "posix_spawn_file_actions_destroy" => {
+                let [file_actions] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.posix_spawn_file_actions_destroy(file_actions)?;
+                this.write_scalar(result, dest)?;
+            }
+            "posix_spawn_file_actions_adddup2" => {
+                let [file_actions, fd, new_fd] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.posix_spawn_file_actions_adddup2(file_actions, fd, new_fd)?;
+                this.write_scalar(result, dest)?;
+            }
+            "posix_spawn_file_actions_addclose" => {
+                let [file_actions, fd] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.posix_spawn_file_actions_addclose(file_actions, fd)?;
+                this.write_scalar(result, dest)?;
+            }
+            "posix_spawn_file_actions_addchdir_np" | "posix_spawn_file_actions_addchdir" => {
+                let [file_actions, path] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.posix_spawn_file_actions_addchdir(file_actions, path)?;
+                this.write_scalar(result, dest)?;
+            }
+            "posix_spawnattr_init" | "posix_spawnattr_destroy" => {
+                let [attr] = this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                // We ignore all attributes, see `posix_spawn`.
+                this.read_pointer(attr)?;
+                this.write_null(dest)?;
+            }
+            "posix_spawnattr_setflags"
+            | "posix_spawnattr_setsigmask"
+            | "posix_spawnattr_setsigdefault"
+            | "posix_spawnattr_setpgroup" => {
+                let [attr, _value] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                this.read_pointer(attr)?;
+                this.write_null(dest)?;
+            }
+            "waitpid" => {
+                let [pid, status, options] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                this.waitpid(pid, status, options, dest)?;
+            }
+            "kill" => {
+                let [pid, sig] = this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.kill(pid, sig)?;
+                this.write_scalar(result, dest)?;
+            }
+            "fork" | "vfork" => {
+                throw_unsup_format!(
+                    "`{link_name}` is not supported; child processes can only be started with `posix_spawn`"
+                );
+            }
+            "execve" | "execv" | "execvp" | "execvpe" | "fexecve" | "execl" | "execlp"
+            | "execle" => {
+                throw_unsup_format!(
+                    "`{link_name}` is not supported: it would replace the interpreted program; \
+                    child processes can only be started with `posix_spawn`"
+                );
+            }
+            "sigemptyset" => {
+                let [set] = this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.sigemptyset(set)?;
+                this.write_scalar(result, dest)?;
+            }
+            "sigaddset" => {
+                let [set, signo] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.sigaddset(set, signo)?;
+                this.write_scalar(result, dest)?;
+            }
+
             // Time
             "gettimeofday" => {
                 let [tv, tz] = this.check_shim_sig(
@@ -566,6 +806,55 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let result = this.localtime_r(timep, result_op)?;
                 this.write_pointer(result, dest)?;
             }
+            "gmtime_r" => {
+                let [timep, result_op] = this.check_shim_sig(
+                    shim_sig!(extern "C" fn(*const _, *mut _) -> *mut _),
+                    link_name,
+                    abi,
+                    args,
+                )?;
+                let result = this.gmtime_r(timep, result_op)?;
+                this.write_pointer(result, dest)?;
+            }
+            "mktime" => {
+                let [tm] = this.check_shim_sig(
+                    shim_sig!(extern "C" fn(*mut _) -> libc::time_t),
+                    link_name,
+                    abi,
+                    args,
+                )?;
+                this.mktime(tm, dest)?;
+            }
+            "timegm" => {
+                let [tm] = this.check_shim_sig(
+                    shim_sig!(extern "C" fn(*mut _) -> libc::time_t),
+                    link_name,
+                    abi,
+                    args,
+                )?;
+                this.timegm(tm, dest)?;
+            }
+            "tzset" => {
+                let [] =
+                    this.check_shim_sig(shim_sig!(extern "C" fn() -> ()), link_name, abi, args)?;
+                this.tzset()?;
+            }
+            "difftime" => {
+                let [time1, time0] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.difftime(time1, time0)?;
+                this.write_scalar(result, dest)?;
+            }
+            "strftime" => {
+                let [s, max, format, tm] = this.check_shim_sig(
+                    shim_sig!(extern "C" fn(*mut _, usize, *const _, *const _) -> usize),
+                    link_name,
+                    abi,
+                    args,
+                )?;
+                let result = this.strftime(s, max, format, tm)?;
+                this.write_scalar(result, dest)?;
+            }
             "clock_gettime" => {
                 let [clk_id, tp] = this.check_shim_sig(
                     shim_sig!(extern "C" fn(libc::clockid_t, *mut _) -> i32),
@@ -575,6 +864,48 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 )?;
                 this.clock_gettime(clk_id, tp, dest)?;
             }
+            "pthread_getcpuclockid" => {
+                // Currently this function does not exist on all Unixes, e.g. on macOS.
+                this.check_target_os(&[Os::Linux, Os::FreeBsd, Os::Android], link_name)?;
+                let [thread, clock_id] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.pthread_getcpuclockid(thread, clock_id)?;
+                this.write_scalar(result, dest)?;
+            }
+            "getrusage" => {
+                let [who, usage] = this.check_shim_sig(
+                    shim_sig!(extern "C" fn(i32, *mut _) -> i32),
+                    link_name,
+                    abi,
+                    args,
+                )?;
+                let result = this.getrusage(who, usage)?;
+                this.write_scalar(result, dest)?;
+            }
+            "getrlimit" => {
+                let [resource, rlim] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.getrlimit(resource, rlim)?;
+                this.write_scalar(result, dest)?;
+            }
+            "setrlimit" => {
+                let [resource, rlim] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.setrlimit(resource, rlim)?;
+                this.write_scalar(result, dest)?;
+            }
+            "prlimit" | "prlimit64" => {
+                // Currently this function does not exist on all Unixes, e.g. on macOS.
+                this.check_target_os(&[Os::Linux, Os::Android], link_name)?;
+                let [pid, resource, new_limit, old_limit] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.prlimit(pid, resource, new_limit, old_limit)?;
+                this.write_scalar(result, dest)?;
+            }
+            "times" => {
+                let [buf] = this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                this.times(buf, dest)?;
+            }
 
             // Allocation
             "posix_memalign" => {
@@ -630,6 +961,94 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let res = this.aligned_alloc(align, size)?;
                 this.write_pointer(res, dest)?;
             }
+            "malloc_usable_size" => {
+                this.check_target_os(&[Os::Linux, Os::FreeBsd, Os::Android], link_name)?;
+                let [ptr] = this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                let size = if this.ptr_is_null(ptr)? {
+                    0
+                } else {
+                    this.malloc_usable_size_of(ptr, link_name.as_str())?
+                };
+                this.write_scalar(Scalar::from_target_usize(size, this), dest)?;
+            }
+            "malloc_trim" => {
+                // This is a glibc extension.
+                this.check_target_os(&[Os::Linux], link_name)?;
+                let [pad] = this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                this.read_target_usize(pad)?;
+                // Miri never holds on to freed memory, so there is never anything to release.
+                this.write_scalar(Scalar::from_i32(0), dest)?;
+            }
+
+            // jemalloc's non-standard API. This is native on FreeBSD; elsewhere programs get it by
+            // linking jemalloc without a symbol prefix, and we provide it on top of our `malloc`.
+            "mallocx" => {
+                let [size, flags] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let size = this.read_target_usize(size)?;
+                let flags = this.read_scalar(flags)?.to_i32()?;
+                let res = this.mallocx(size, flags)?;
+                this.write_pointer(res, dest)?;
+            }
+            "rallocx" => {
+                let [ptr, size, flags] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                let size = this.read_target_usize(size)?;
+                let flags = this.read_scalar(flags)?.to_i32()?;
+                let res = this.rallocx(ptr, size, flags)?;
+                this.write_pointer(res, dest)?;
+            }
+            "sallocx" => {
+                let [ptr, flags] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                this.read_scalar(flags)?.to_i32()?;
+                let size = this.malloc_usable_size_of(ptr, link_name.as_str())?;
+                this.write_scalar(Scalar::from_target_usize(size, this), dest)?;
+            }
+            "nallocx" => {
+                let [size, flags] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let size = this.read_target_usize(size)?;
+                let flags = this.read_scalar(flags)?.to_i32()?;
+                if size == 0 {
+                    throw_ub_format!("`nallocx` with a size of zero");
+                }
+                // We never round up, so the real size is the requested size, unless the request
+                // could not be satisfied at all.
+                let size = if this.mallocx_align(size, flags).is_some()
+                    && size <= this.max_size_of_val().bytes()
+                {
+                    size
+                } else {
+                    0
+                };
+                this.write_scalar(Scalar::from_target_usize(size, this), dest)?;
+            }
+            "dallocx" => {
+                let [ptr, flags] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                this.read_scalar(flags)?.to_i32()?;
+                this.malloc_usable_size_of(ptr, link_name.as_str())?;
+                this.deallocate_ptr(ptr, None, MiriMemoryKind::C.into())?;
+            }
+            "sdallocx" => {
+                let [ptr, size, flags] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let ptr = this.read_pointer(ptr)?;
+                let size = this.read_target_usize(size)?;
+                this.read_scalar(flags)?.to_i32()?;
+                this.sdallocx(ptr, size)?;
+            }
+            "mallctl" => {
+                let [name, oldp, oldlenp, newp, newlen] =
+                    this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                let result = this.mallctl(name, oldp, oldlenp, newp, newlen)?;
+                this.write_scalar(result, dest)?;
+            }
 
             // Dynamic symbol loading
             "dlsym" => {
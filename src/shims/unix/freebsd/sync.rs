@@ -117,9 +117,6 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
                             Some((umtx_time.timeout_clock, anchor, umtx_time.timeout))
                         } else if uaddr == timespec_layout.size.bytes() {
-                            // RealTime clock can't be used in isolation mode.
-                            this.check_no_isolation("`_umtx_op` with `timespec` timeout")?;
-
                             // `uaddr2` points to a `struct timespec`.
                             let timespec = this.ptr_to_mplace(uaddr2, timespec_layout);
                             let duration = match this.read_timespec(&timespec)? {
@@ -234,10 +231,6 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let Some(timeout_clock) = this.parse_clockid(clock_id) else {
             throw_unsup_format!("unsupported clock")
         };
-        if timeout_clock == TimeoutClock::RealTime {
-            this.check_no_isolation("`_umtx_op` with `CLOCK_REALTIME`")?;
-        }
-
         interp_ok(Some(UmtxTime { timeout: duration, abs_time: abs_time_flag, timeout_clock }))
     }
 }
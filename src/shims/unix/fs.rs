@@ -2,10 +2,9 @@
 
 use std::borrow::Cow;
 use std::fs::{
-    DirBuilder, File, FileType, OpenOptions, ReadDir, TryLockError, read_dir, remove_dir,
-    remove_file, rename,
+    DirBuilder, File, FileType, OpenOptions, ReadDir, read_dir, remove_dir, remove_file, rename,
 };
-use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
@@ -17,7 +16,8 @@ use self::shims::time::system_time_to_duration;
 use crate::shims::files::FileHandle;
 use crate::shims::os_str::bytes_to_os_str;
 use crate::shims::sig::check_min_vararg_count;
-use crate::shims::unix::fd::{FlockOp, UnixFileDescription};
+use crate::shims::unix::fd::UnixFileDescription;
+use crate::shims::unix::resource::EvalContextExt as _;
 use crate::*;
 
 impl UnixFileDescription for FileHandle {
@@ -85,35 +85,6 @@ impl UnixFileDescription for FileHandle {
         let result = f();
         finish.call(ecx, result.map_err(IoError::HostError))
     }
-
-    fn flock<'tcx>(
-        &self,
-        communicate_allowed: bool,
-        op: FlockOp,
-    ) -> InterpResult<'tcx, io::Result<()>> {
-        assert!(communicate_allowed, "isolation should have prevented even opening a file");
-
-        use FlockOp::*;
-        // We must not block the interpreter loop, so we always `try_lock`.
-        let (res, nonblocking) = match op {
-            SharedLock { nonblocking } => (self.file.try_lock_shared(), nonblocking),
-            ExclusiveLock { nonblocking } => (self.file.try_lock(), nonblocking),
-            Unlock => {
-                return interp_ok(self.file.unlock());
-            }
-        };
-
-        match res {
-            Ok(()) => interp_ok(Ok(())),
-            Err(TryLockError::Error(err)) => interp_ok(Err(err)),
-            Err(TryLockError::WouldBlock) =>
-                if nonblocking {
-                    interp_ok(Err(ErrorKind::WouldBlock.into()))
-                } else {
-                    throw_unsup_format!("blocking `flock` is not currently supported");
-                },
-        }
-    }
 }
 
 impl<'tcx> EvalContextExtPrivate<'tcx> for crate::MiriInterpCx<'tcx> {}
@@ -432,6 +403,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             return this.set_last_error_and_return_i32(ErrorKind::PermissionDenied);
         }
 
+        if !this.fd_limit_allows(1, 0) {
+            return this.set_last_error_and_return_i32(LibcError("EMFILE"));
+        }
+
         let fd = options
             .open(path)
             .map(|file| this.machine.fds.insert_new(FileHandle { file, writable }));
@@ -1469,6 +1444,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             return this.set_last_error_and_return_i32(LibcError("EINVAL"));
         }
 
+        if !this.fd_limit_allows(1, 0) {
+            return this.set_last_error_and_return_i32(LibcError("EMFILE"));
+        }
+
         // At this point we know we have 6 ASCII 'X' characters as a suffix.
 
         // From <https://github.com/lattera/glibc/blob/895ef79e04a953cac1493863bcae29ad85657ee1/sysdeps/posix/tempname.c#L175>
@@ -18,7 +18,7 @@ use crate::*;
 const TASK_COMM_LEN: u64 = 16;
 
 pub fn is_dyn_sym(name: &str) -> bool {
-    matches!(name, "gettid" | "statx")
+    matches!(name, "gettid" | "statx" | "gnu_get_libc_version")
 }
 
 impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
@@ -123,6 +123,13 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let result = this.unix_gettid(link_name.as_str())?;
                 this.write_scalar(result, dest)?;
             }
+            "gnu_get_libc_version" => {
+                let [] = this.check_shim_sig_lenient(abi, CanonAbi::C, link_name, args)?;
+                // std only uses `posix_spawn` if glibc is recent enough to report `exec` failures,
+                // so claim to be a recent version.
+                let version = this.allocate_bytes_dedup(b"2.39\0")?;
+                this.write_pointer(version, dest)?;
+            }
 
             // Dynamically invoked syscalls
             "syscall" => {
@@ -10,6 +10,7 @@ use crate::shims::files::{
     DynFileDescriptionRef, FdId, FdNum, FileDescription, FileDescriptionRef, WeakFileDescriptionRef,
 };
 use crate::shims::unix::UnixFileDescription;
+use crate::shims::unix::resource::EvalContextExt as _;
 use crate::*;
 
 type EpollEventKey = (FdId, FdNum);
@@ -215,6 +216,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             );
         }
 
+        if !this.fd_limit_allows(1, 0) {
+            return this.set_last_error_and_return_i32(LibcError("EMFILE"));
+        }
+
         let fd = this.machine.fds.insert_new(Epoll::default());
         interp_ok(Scalar::from_i32(fd))
     }
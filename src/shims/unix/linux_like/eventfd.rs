@@ -7,6 +7,7 @@ use crate::concurrency::VClock;
 use crate::shims::files::{FdId, FileDescription, FileDescriptionRef, WeakFileDescriptionRef};
 use crate::shims::unix::UnixFileDescription;
 use crate::shims::unix::linux_like::epoll::{EpollEvents, EvalContextExt as _};
+use crate::shims::unix::resource::EvalContextExt as _;
 use crate::*;
 
 /// Maximum value that the eventfd counter can hold.
@@ -166,6 +167,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             throw_unsup_format!("eventfd: encountered unknown unsupported flags {:#x}", flags);
         }
 
+        if !this.fd_limit_allows(1, 0) {
+            return this.set_last_error_and_return_i32(LibcError("EMFILE"));
+        }
+
         let fds = &mut this.machine.fds;
 
         let fd_value = fds.insert_new(EventFd {
@@ -78,9 +78,6 @@ pub fn futex<'tcx>(
                     }
                 };
                 let timeout_clock = if op & futex_realtime == futex_realtime {
-                    ecx.check_no_isolation(
-                        "`futex` syscall with `op=FUTEX_WAIT` and non-null timeout with `FUTEX_CLOCK_REALTIME`",
-                    )?;
                     TimeoutClock::RealTime
                 } else {
                     TimeoutClock::Monotonic
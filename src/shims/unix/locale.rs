@@ -0,0 +1,534 @@
+//! Locales: `setlocale`, the `newlocale`/`uselocale` family, `nl_langinfo` and `localeconv`.
+//!
+//! Miri knows the "C" locale (also called "POSIX") and one UTF-8 locale, whose name can be set with
+//! `-Zmiri-utf8-locale`. We do not have any locale data, so apart from the character set the UTF-8
+//! locale follows the conventions of the "C" locale.
+
+use std::ffi::OsStr;
+
+use rustc_abi::{FieldIdx, Size};
+use rustc_data_structures::fx::FxHashMap;
+use rustc_target::spec::Os;
+
+use crate::*;
+
+/// The categories that make up a locale, in the order glibc lists them in composite locale names.
+const CATEGORIES: [&str; 6] =
+    ["LC_CTYPE", "LC_NUMERIC", "LC_TIME", "LC_COLLATE", "LC_MONETARY", "LC_MESSAGES"];
+const LC_CTYPE: usize = 0;
+const LC_NUMERIC: usize = 1;
+const LC_TIME: usize = 2;
+const LC_MONETARY: usize = 4;
+const LC_MESSAGES: usize = 5;
+
+const DAYS: [&str; 7] =
+    ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Locale {
+    C,
+    Utf8,
+}
+
+/// The locale of each category.
+type Categories = [Locale; CATEGORIES.len()];
+
+pub struct LocaleState<'tcx> {
+    /// The name of the UTF-8 locale.
+    utf8_name: String,
+    /// The global locale, as set by `setlocale`.
+    global: Categories,
+    /// Memory that `setlocale` writes to and that everything depending on the global locale reads
+    /// from, so that the data race detector can see conflicting accesses. Only exists on Unix
+    /// targets.
+    global_place: Option<MPlaceTy<'tcx>>,
+    /// The string returned by the last `setlocale` call. The next call frees it.
+    setlocale_result: Option<Pointer>,
+    /// The locale objects created by `newlocale` and `duplocale`.
+    objects: FxHashMap<AllocId, Categories>,
+    /// The strings returned by `nl_langinfo`.
+    langinfo: FxHashMap<(Locale, i32), Pointer>,
+    /// The `struct lconv` returned by `localeconv`.
+    lconv: Option<Pointer>,
+}
+
+impl VisitProvenance for LocaleState<'_> {
+    fn visit_provenance(&self, visit: &mut VisitWith<'_>) {
+        let LocaleState {
+            utf8_name: _,
+            global: _,
+            global_place,
+            setlocale_result,
+            objects,
+            langinfo,
+            lconv,
+        } = self;
+
+        global_place.visit_provenance(visit);
+        setlocale_result.visit_provenance(visit);
+        for alloc_id in objects.keys() {
+            alloc_id.visit_provenance(visit);
+        }
+        for ptr in langinfo.values() {
+            ptr.visit_provenance(visit);
+        }
+        lconv.visit_provenance(visit);
+    }
+}
+
+impl<'tcx> LocaleState<'tcx> {
+    pub(crate) fn new(utf8_name: String) -> Self {
+        LocaleState {
+            utf8_name,
+            global: [Locale::C; CATEGORIES.len()],
+            global_place: None,
+            setlocale_result: None,
+            objects: FxHashMap::default(),
+            langinfo: FxHashMap::default(),
+            lconv: None,
+        }
+    }
+
+    pub(crate) fn init(ecx: &mut MiriInterpCx<'tcx>) -> InterpResult<'tcx> {
+        if !ecx.target_os_is_unix() {
+            return interp_ok(());
+        }
+        let place = ecx.allocate(ecx.machine.layouts.u8, MiriMemoryKind::Machine.into())?;
+        ecx.write_scalar(Scalar::from_u8(0), &place)?;
+        ecx.machine.locale.global_place = Some(place);
+        interp_ok(())
+    }
+
+    /// Called by everything that depends on the locale of the active thread. If that thread uses
+    /// the global locale, this reads the memory that `setlocale` writes to, so that calls racing
+    /// with `setlocale` are reported.
+    pub(crate) fn note_access(ecx: &MiriInterpCx<'tcx>) -> InterpResult<'tcx> {
+        if ecx.active_thread_ref().locale.is_some() {
+            return interp_ok(());
+        }
+        Self::read_global(ecx)
+    }
+
+    fn read_global(ecx: &MiriInterpCx<'tcx>) -> InterpResult<'tcx> {
+        if let Some(place) = &ecx.machine.locale.global_place {
+            ecx.read_scalar(place)?;
+        }
+        interp_ok(())
+    }
+
+    fn parse(&self, name: &[u8]) -> Option<Locale> {
+        match name {
+            b"C" | b"POSIX" => Some(Locale::C),
+            name if name == self.utf8_name.as_bytes() => Some(Locale::Utf8),
+            _ => None,
+        }
+    }
+
+    fn name(&self, locale: Locale) -> &str {
+        match locale {
+            Locale::C => "C",
+            Locale::Utf8 => &self.utf8_name,
+        }
+    }
+
+    /// The name `setlocale` returns for the given categories: a plain name if they all agree, and
+    /// a composite name listing every category otherwise.
+    fn composite_name(&self, categories: &Categories) -> String {
+        if categories.iter().all(|&locale| locale == categories[0]) {
+            return self.name(categories[0]).to_owned();
+        }
+        CATEGORIES
+            .iter()
+            .zip(categories)
+            .map(|(category, &locale)| format!("{category}={}", self.name(locale)))
+            .collect::<Vec<_>>()
+            .join(";")
+    }
+}
+
+impl<'tcx> EvalContextExtPriv<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Maps an `LC_*` value other than `LC_ALL` to the index of its category.
+    fn locale_category(&self, category: i32) -> Option<usize> {
+        let this = self.eval_context_ref();
+        CATEGORIES.iter().position(|name| this.eval_libc_i32(name) == category)
+    }
+
+    /// The `LC_*_MASK` value of the category with the given index.
+    fn locale_category_mask(&self, index: usize) -> i32 {
+        let this = self.eval_context_ref();
+        this.eval_libc_i32(&format!("{}_MASK", CATEGORIES[index]))
+    }
+
+    /// Resolves the name of a locale for the given category. The empty name selects the locale
+    /// from the environment, like glibc does.
+    fn resolve_locale(&mut self, name: &[u8], index: usize) -> InterpResult<'tcx, Option<Locale>> {
+        let this = self.eval_context_mut();
+        if !name.is_empty() {
+            return interp_ok(this.machine.locale.parse(name));
+        }
+        for var in ["LC_ALL", CATEGORIES[index], "LANG"] {
+            if let Some(value) = this.get_env_var(OsStr::new(var))?
+                && !value.is_empty()
+            {
+                return interp_ok(this.machine.locale.parse(value.as_encoded_bytes()));
+            }
+        }
+        interp_ok(Some(Locale::C))
+    }
+
+    /// The value of `LC_GLOBAL_LOCALE`.
+    fn lc_global_locale(&self) -> Pointer {
+        let this = self.eval_context_ref();
+        Pointer::without_provenance(this.target_usize_max())
+    }
+
+    /// Returns the categories of a `locale_t`, which is either a locale object or
+    /// `LC_GLOBAL_LOCALE` (if `allow_global` is set).
+    fn locale_object(
+        &self,
+        ptr: Pointer,
+        allow_global: bool,
+        link_name: &str,
+    ) -> InterpResult<'tcx, Categories> {
+        let this = self.eval_context_ref();
+        if allow_global && ptr.addr() == this.lc_global_locale().addr() {
+            LocaleState::read_global(this)?;
+            return interp_ok(this.machine.locale.global);
+        }
+        if let Ok((alloc_id, offset, _extra)) = this.ptr_try_get_alloc_id(ptr, 0)
+            && offset == Size::ZERO
+            && let Some(categories) = this.machine.locale.objects.get(&alloc_id)
+        {
+            return interp_ok(*categories);
+        }
+        throw_ub_format!("`{link_name}` called with an invalid locale object")
+    }
+
+    /// The locale the active thread uses for the given category.
+    fn current_locale(&self, index: usize, link_name: &str) -> InterpResult<'tcx, Locale> {
+        let this = self.eval_context_ref();
+        let categories = match this.active_thread_ref().locale {
+            Some(object) => this.locale_object(object, /* allow_global */ false, link_name)?,
+            None => {
+                LocaleState::note_access(this)?;
+                this.machine.locale.global
+            }
+        };
+        interp_ok(categories[index])
+    }
+
+    /// Allocates a new locale object.
+    fn alloc_locale_object(&mut self, categories: Categories) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+        // Programs treat `locale_t` as opaque, so the contents do not matter.
+        let place =
+            this.allocate(this.machine.layouts.mut_raw_ptr, MiriMemoryKind::Machine.into())?;
+        this.write_null(&place)?;
+        let (alloc_id, _offset, _extra) = this.ptr_get_alloc_id(place.ptr(), 0)?;
+        this.machine.locale.objects.insert(alloc_id, categories);
+        interp_ok(place.ptr())
+    }
+
+    /// The `nl_langinfo` item with the given value, as its category and its value in `locale`.
+    /// Returns `None` for unknown items.
+    fn langinfo_item(&self, item: i32) -> Option<(usize, Box<dyn Fn(Locale) -> String>)> {
+        let this = self.eval_context_ref();
+        let c_codeset = if matches!(this.tcx.sess.target.os, Os::Linux | Os::Android) {
+            "ANSI_X3.4-1968"
+        } else {
+            "US-ASCII"
+        };
+        let fixed = |index, value: &str| {
+            let value = value.to_owned();
+            Some((index, Box::new(move |_| value.clone()) as Box<dyn Fn(Locale) -> String>))
+        };
+
+        if item == this.eval_libc_i32("CODESET") {
+            return Some((
+                LC_CTYPE,
+                Box::new(move |locale| {
+                    match locale {
+                        Locale::C => c_codeset,
+                        Locale::Utf8 => "UTF-8",
+                    }
+                    .to_owned()
+                }),
+            ));
+        }
+        let fixed_items = [
+            ("RADIXCHAR", LC_NUMERIC, "."),
+            ("THOUSEP", LC_NUMERIC, ""),
+            ("D_T_FMT", LC_TIME, "%a %b %e %H:%M:%S %Y"),
+            ("D_FMT", LC_TIME, "%m/%d/%y"),
+            ("T_FMT", LC_TIME, "%H:%M:%S"),
+            ("T_FMT_AMPM", LC_TIME, "%I:%M:%S %p"),
+            ("AM_STR", LC_TIME, "AM"),
+            ("PM_STR", LC_TIME, "PM"),
+            ("ERA", LC_TIME, ""),
+            ("ERA_D_FMT", LC_TIME, ""),
+            ("ERA_D_T_FMT", LC_TIME, ""),
+            ("ERA_T_FMT", LC_TIME, ""),
+            ("ALT_DIGITS", LC_TIME, ""),
+            ("YESEXPR", LC_MESSAGES, "^[yY]"),
+            ("NOEXPR", LC_MESSAGES, "^[nN]"),
+            ("CRNCYSTR", LC_MONETARY, "-"),
+        ];
+        for (name, index, value) in fixed_items {
+            if item == this.eval_libc_i32(name) {
+                return fixed(index, value);
+            }
+        }
+        for (i, day) in DAYS.iter().enumerate() {
+            let n = i.strict_add(1);
+            if item == this.eval_libc_i32(&format!("DAY_{n}")) {
+                return fixed(LC_TIME, day);
+            }
+            if item == this.eval_libc_i32(&format!("ABDAY_{n}")) {
+                return fixed(LC_TIME, &day[..3]);
+            }
+        }
+        for (i, month) in MONTHS.iter().enumerate() {
+            let n = i.strict_add(1);
+            if item == this.eval_libc_i32(&format!("MON_{n}")) {
+                return fixed(LC_TIME, month);
+            }
+            if item == this.eval_libc_i32(&format!("ABMON_{n}")) {
+                return fixed(LC_TIME, &month[..3]);
+            }
+        }
+        None
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    fn setlocale(
+        &mut self,
+        category_op: &OpTy<'tcx>,
+        locale_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+        let category = this.read_scalar(category_op)?.to_i32()?;
+        let locale = this.read_pointer(locale_op)?;
+
+        let all = category == this.eval_libc_i32("LC_ALL");
+        let index = if all {
+            None
+        } else if let Some(index) = this.locale_category(category) {
+            Some(index)
+        } else {
+            this.set_last_error(LibcError("EINVAL"))?;
+            return interp_ok(Pointer::null());
+        };
+
+        let mut categories = this.machine.locale.global;
+        if this.ptr_is_null(locale)? {
+            LocaleState::note_access(this)?;
+        } else {
+            let name = this.read_c_str(locale)?.to_owned();
+            if all && name.contains(&b';') {
+                // A composite name as returned by an earlier call.
+                for part in name.split(|&b| b == b';') {
+                    let parsed = part.iter().position(|&b| b == b'=').and_then(|eq| {
+                        let index = CATEGORIES.iter().position(|c| c.as_bytes() == &part[..eq])?;
+                        let locale = this.machine.locale.parse(&part[eq.strict_add(1)..])?;
+                        Some((index, locale))
+                    });
+                    let Some((index, locale)) = parsed else {
+                        return interp_ok(Pointer::null());
+                    };
+                    categories[index] = locale;
+                }
+            } else {
+                for i in index.map_or(0..CATEGORIES.len(), |i| i..i.strict_add(1)) {
+                    let Some(locale) = this.resolve_locale(&name, i)? else {
+                        return interp_ok(Pointer::null());
+                    };
+                    categories[i] = locale;
+                }
+            }
+            // Changing the global locale is a write, so it races with everything that depends on it.
+            let place = this.machine.locale.global_place.clone().unwrap();
+            this.write_scalar(Scalar::from_u8(0), &place)?;
+            this.machine.locale.global = categories;
+        }
+
+        let state = &this.machine.locale;
+        let name = match index {
+            Some(index) => state.name(categories[index]).to_owned(),
+            None => state.composite_name(&categories),
+        };
+        let result =
+            this.alloc_os_str_as_c_str(OsStr::new(&name), MiriMemoryKind::Machine.into())?;
+        // POSIX allows the next call to invalidate the string we returned before.
+        if let Some(old) = this.machine.locale.setlocale_result.replace(result) {
+            this.deallocate_ptr(old, None, MiriMemoryKind::Machine.into())?;
+        }
+        interp_ok(result)
+    }
+
+    fn newlocale(
+        &mut self,
+        mask_op: &OpTy<'tcx>,
+        locale_op: &OpTy<'tcx>,
+        base_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+        let mask = this.read_scalar(mask_op)?.to_i32()?;
+        let locale = this.read_pointer(locale_op)?;
+        let base = this.read_pointer(base_op)?;
+
+        let masks: Vec<i32> = (0..CATEGORIES.len()).map(|i| this.locale_category_mask(i)).collect();
+        let known = masks.iter().fold(0, |acc, mask| acc | mask);
+        if mask & !known != 0 || this.ptr_is_null(locale)? {
+            this.set_last_error(LibcError("EINVAL"))?;
+            return interp_ok(Pointer::null());
+        }
+        let mut categories = if this.ptr_is_null(base)? {
+            [Locale::C; CATEGORIES.len()]
+        } else {
+            this.locale_object(base, /* allow_global */ false, "newlocale")?
+        };
+
+        let name = this.read_c_str(locale)?.to_owned();
+        for (i, category_mask) in masks.into_iter().enumerate() {
+            if mask & category_mask == 0 {
+                continue;
+            }
+            let Some(locale) = this.resolve_locale(&name, i)? else {
+                // `base` stays valid when we fail.
+                this.set_last_error(LibcError("ENOENT"))?;
+                return interp_ok(Pointer::null());
+            };
+            categories[i] = locale;
+        }
+
+        if this.ptr_is_null(base)? {
+            this.alloc_locale_object(categories)
+        } else {
+            // Like glibc, we modify and return `base`.
+            let (alloc_id, _offset, _extra) = this.ptr_get_alloc_id(base, 0)?;
+            this.machine.locale.objects.insert(alloc_id, categories);
+            interp_ok(base)
+        }
+    }
+
+    fn duplocale(&mut self, locale_op: &OpTy<'tcx>) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+        let locale = this.read_pointer(locale_op)?;
+        let categories = this.locale_object(locale, /* allow_global */ true, "duplocale")?;
+        this.alloc_locale_object(categories)
+    }
+
+    fn freelocale(&mut self, locale_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let locale = this.read_pointer(locale_op)?;
+        this.locale_object(locale, /* allow_global */ false, "freelocale")?;
+        if this.active_thread_ref().locale.is_some_and(|used| used.addr() == locale.addr()) {
+            throw_ub_format!(
+                "`freelocale` called on the locale object that is in use by this thread"
+            );
+        }
+        let (alloc_id, _offset, _extra) = this.ptr_get_alloc_id(locale, 0)?;
+        this.machine.locale.objects.remove(&alloc_id);
+        this.deallocate_ptr(locale, None, MiriMemoryKind::Machine.into())
+    }
+
+    fn uselocale(&mut self, locale_op: &OpTy<'tcx>) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+        let locale = this.read_pointer(locale_op)?;
+        let global = this.lc_global_locale();
+        let previous = this.active_thread_ref().locale.unwrap_or(global);
+        if this.ptr_is_null(locale)? {
+            // Only query the locale.
+            return interp_ok(previous);
+        }
+        let new = if locale.addr() == global.addr() {
+            None
+        } else {
+            this.locale_object(locale, /* allow_global */ false, "uselocale")?;
+            Some(locale)
+        };
+        this.active_thread_mut().locale = new;
+        interp_ok(previous)
+    }
+
+    /// Implements `nl_langinfo` and, if `locale_op` is given, `nl_langinfo_l`.
+    fn nl_langinfo(
+        &mut self,
+        item_op: &OpTy<'tcx>,
+        locale_op: Option<&OpTy<'tcx>>,
+        link_name: &str,
+    ) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+        let item = this.read_scalar(item_op)?.to_i32()?;
+        let (locale, value) = match this.langinfo_item(item) {
+            Some((index, value)) => {
+                let locale = match locale_op {
+                    Some(locale_op) => {
+                        let locale = this.read_pointer(locale_op)?;
+                        this.locale_object(locale, /* allow_global */ true, link_name)?[index]
+                    }
+                    None => this.current_locale(index, link_name)?,
+                };
+                (locale, value(locale))
+            }
+            // glibc returns the empty string for unknown items.
+            None => (Locale::C, String::new()),
+        };
+        if let Some(&ptr) = this.machine.locale.langinfo.get(&(locale, item)) {
+            return interp_ok(ptr);
+        }
+        let ptr = this.alloc_os_str_as_c_str(OsStr::new(&value), MiriMemoryKind::Machine.into())?;
+        this.machine.locale.langinfo.insert((locale, item), ptr);
+        interp_ok(ptr)
+    }
+
+    fn localeconv(&mut self) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+        this.current_locale(LC_NUMERIC, "localeconv")?;
+        if let Some(lconv) = this.machine.locale.lconv {
+            return interp_ok(lconv);
+        }
+
+        // Both of our locales use the conventions of the "C" locale: all strings are empty except
+        // for the decimal point, and all `char` fields are `CHAR_MAX`, meaning "not available".
+        let layout = this.libc_ty_layout("lconv");
+        let lconv = this.allocate(layout, MiriMemoryKind::Machine.into())?;
+        let empty = this.alloc_os_str_as_c_str(OsStr::new(""), MiriMemoryKind::Machine.into())?;
+        for i in 0..layout.fields.count() {
+            let field = this.project_field(&lconv, FieldIdx::from_usize(i))?;
+            if field.layout.ty.is_raw_ptr() {
+                this.write_pointer(empty, &field)?;
+            } else if field.layout.ty.is_signed() {
+                let max = field.layout.size.signed_int_max();
+                this.write_scalar(Scalar::from_int(max, field.layout.size), &field)?;
+            } else {
+                let max = field.layout.size.unsigned_int_max();
+                this.write_scalar(Scalar::from_uint(max, field.layout.size), &field)?;
+            }
+        }
+        let decimal_point =
+            this.alloc_os_str_as_c_str(OsStr::new("."), MiriMemoryKind::Machine.into())?;
+        let field = this.project_field_named(&lconv, "decimal_point")?;
+        this.write_pointer(decimal_point, &field)?;
+
+        this.machine.locale.lconv = Some(lconv.ptr());
+        interp_ok(lconv.ptr())
+    }
+}
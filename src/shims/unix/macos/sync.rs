@@ -353,6 +353,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         if mutex_ref.owner().is_some() {
             // Contrary to the blocking lock function, this does not check for reentrancy.
+            this.mutex_lock_failed(&mutex_ref);
             this.write_scalar(Scalar::from_bool(false), dest)?;
         } else {
             this.mutex_lock(&mutex_ref)?;
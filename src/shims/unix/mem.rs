@@ -17,6 +17,7 @@
 use rustc_abi::Size;
 use rustc_target::spec::Os;
 
+use crate::shims::alloc::EvalContextExt as _;
 use crate::*;
 
 impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
@@ -111,6 +112,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             this.set_last_error(LibcError("EINVAL"))?;
             return interp_ok(this.eval_libc("MAP_FAILED"));
         }
+        if !this.check_heap_limit_for_alloc(map_length, None)? {
+            this.set_last_error(LibcError("ENOMEM"))?;
+            return interp_ok(this.eval_libc("MAP_FAILED"));
+        }
 
         let ptr = this.allocate_ptr(
             Size::from_bytes(map_length),
@@ -3,7 +3,10 @@ pub mod foreign_items;
 mod env;
 mod fd;
 mod fs;
+mod locale;
 mod mem;
+mod process;
+mod resource;
 mod sync;
 mod thread;
 mod unnamed_socket;
@@ -19,11 +22,16 @@ mod solarish;
 pub use self::env::{EvalContextExt as _, UnixEnvVars};
 pub use self::fd::{EvalContextExt as _, UnixFileDescription};
 pub use self::fs::{DirTable, EvalContextExt as _};
-pub use self::linux_like::epoll::EpollInterestTable;
+pub use self::linux_like::epoll::{EpollEvents, EpollInterestTable};
+pub use self::locale::{EvalContextExt as _, LocaleState};
 pub use self::mem::EvalContextExt as _;
+pub use self::process::EvalContextExt as _;
+pub use self::resource::{EvalContextExt as _, ResourceLimit, ResourceLimits};
 pub use self::sync::EvalContextExt as _;
 pub use self::thread::{EvalContextExt as _, ThreadNameResult};
-pub use self::unnamed_socket::EvalContextExt as _;
+pub use self::unnamed_socket::{
+    EvalContextExt as _, anonsocket_peek, new_anonsocket_pair, pipe_peer,
+};
 
 // Make up some constants.
 const UID: u32 = 1000;
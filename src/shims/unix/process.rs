@@ -0,0 +1,330 @@
+//! `posix_spawn`, `waitpid` and the signal functions that go with them. The child processes
+//! themselves are managed in `shims::process`.
+
+use std::ffi::OsString;
+use std::process::ExitStatus;
+
+use crate::shims::process::{EvalContextExt as _, FileAction};
+use crate::*;
+
+/// Computes the "wait status" that `waitpid` reports for a terminated process.
+fn wait_status(status: ExitStatus, killed_by: Option<i32>, sigkill: i32) -> i32 {
+    if let Some(code) = status.code() {
+        return (code & 0xff).strict_shl(8);
+    }
+    #[cfg(unix)]
+    if let Some(signal) = std::os::unix::process::ExitStatusExt::signal(&status) {
+        return signal & 0x7f;
+    }
+    killed_by.unwrap_or(sigkill) & 0x7f
+}
+
+impl<'tcx> EvalContextPrivExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextPrivExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Reads a null-terminated array of C strings, like `argv`.
+    fn read_c_str_array(&self, ptr: Pointer) -> InterpResult<'tcx, Vec<OsString>> {
+        let this = self.eval_context_ref();
+        let ptr_layout = this.machine.layouts.mut_raw_ptr;
+        let mut strings = Vec::new();
+        if this.ptr_is_null(ptr)? {
+            return interp_ok(strings);
+        }
+        for idx in 0u64.. {
+            let place =
+                this.ptr_to_mplace(ptr.wrapping_offset(ptr_layout.size * idx, this), ptr_layout);
+            let str_ptr = this.read_pointer(&place)?;
+            if this.ptr_is_null(str_ptr)? {
+                break;
+            }
+            strings.push(this.read_os_str_from_c_str(str_ptr)?.to_owned());
+        }
+        interp_ok(strings)
+    }
+
+    /// Returns the file actions stored in the given `posix_spawn_file_actions_t`.
+    fn file_actions_mut(
+        &mut self,
+        file_actions_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, &mut Vec<FileAction>> {
+        let this = self.eval_context_mut();
+        // We store our id in the first 4 bytes, which exist on all targets.
+        let id_place = this.deref_pointer_as(file_actions_op, this.machine.layouts.u32)?;
+        let id = this.read_scalar(&id_place)?.to_u32()?;
+        let Some(Some(actions)) =
+            this.machine.processes.file_actions.get_mut(usize::try_from(id).unwrap())
+        else {
+            throw_ub_format!("`posix_spawn_file_actions_t` used without being initialized");
+        };
+        interp_ok(actions)
+    }
+
+    /// Reaps a child process that `pid` (a pid, or -1 for any child) matches and that has
+    /// terminated, like `waitpid`. If there is none, this blocks until there is, unless `nohang`
+    /// is set.
+    fn waitpid_until_exit(
+        &mut self,
+        pid: i32,
+        status: Pointer,
+        nohang: bool,
+        dest: MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let candidates: Vec<i32> =
+            this.machine.processes.children().filter(|&child| pid == -1 || child == pid).collect();
+        if candidates.is_empty() {
+            return this.set_last_error_and_return(LibcError("ECHILD"), &dest);
+        }
+        for child in candidates {
+            match this.machine.processes.try_wait(child) {
+                Ok(Some(exit_status)) => {
+                    let killed_by = this.machine.processes.reap(child);
+                    if !this.ptr_is_null(status)? {
+                        let sigkill = this.eval_libc_i32("SIGKILL");
+                        let status_place = this.ptr_to_mplace(status, this.machine.layouts.i32);
+                        this.write_int(
+                            wait_status(exit_status, killed_by, sigkill),
+                            &status_place,
+                        )?;
+                    }
+                    return this.write_int(child, &dest);
+                }
+                Ok(None) => {}
+                Err(err) => return this.set_last_error_and_return(err, &dest),
+            }
+        }
+        if nohang {
+            return this.write_int(0, &dest);
+        }
+        this.block_on_child_process(
+            None,
+            callback!(
+                @capture<'tcx> {
+                    pid: i32,
+                    status: Pointer,
+                    dest: MPlaceTy<'tcx>,
+                }
+                |this, _unblock: ()| this.waitpid_until_exit(pid, status, /* nohang */ false, dest)
+            ),
+        );
+        interp_ok(())
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Implements `posix_spawn` and, if `search_path` is set, `posix_spawnp`. We only support
+    /// running the interpreted program itself, so there is nothing to search for.
+    fn posix_spawn(
+        &mut self,
+        pid_op: &OpTy<'tcx>,
+        path_op: &OpTy<'tcx>,
+        file_actions_op: &OpTy<'tcx>,
+        attrp_op: &OpTy<'tcx>,
+        argv_op: &OpTy<'tcx>,
+        envp_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        this.check_no_isolation("`posix_spawn`")?;
+
+        let pid_place = this.deref_pointer_as(pid_op, this.libc_ty_layout("pid_t"))?;
+        let path = this.read_pointer(path_op)?;
+        let file_actions = this.read_pointer(file_actions_op)?;
+        // We ignore the attributes: they concern signal handling and process groups, which the
+        // child interpreter does not care about.
+        let _attrp = this.read_pointer(attrp_op)?;
+        let argv = this.read_pointer(argv_op)?;
+        let envp = this.read_pointer(envp_op)?;
+
+        let path = this.read_path_from_c_str(path)?.into_owned();
+        let actions = if this.ptr_is_null(file_actions)? {
+            Vec::new()
+        } else {
+            this.file_actions_mut(file_actions_op)?.clone()
+        };
+        let argv = this.read_c_str_array(argv)?;
+        let envp = this.read_c_str_array(envp)?;
+
+        match this.spawn_child(&path, argv, envp, actions)? {
+            Ok(pid) => {
+                this.write_int(pid, &pid_place)?;
+                interp_ok(Scalar::from_i32(0))
+            }
+            // `posix_spawn` returns the error code instead of setting `errno`.
+            Err(err) => this.io_error_to_errnum_scalar(err),
+        }
+    }
+
+    fn posix_spawn_file_actions_init(
+        &mut self,
+        file_actions_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let id_place = this.deref_pointer_as(file_actions_op, this.machine.layouts.u32)?;
+        let processes = &mut this.machine.processes;
+        let id = u32::try_from(processes.file_actions.len()).unwrap();
+        processes.file_actions.push(Some(Vec::new()));
+        this.write_scalar(Scalar::from_u32(id), &id_place)?;
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn posix_spawn_file_actions_destroy(
+        &mut self,
+        file_actions_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        // Make sure this is a valid object before we destroy it.
+        this.file_actions_mut(file_actions_op)?;
+        let id_place = this.deref_pointer_as(file_actions_op, this.machine.layouts.u32)?;
+        let id = this.read_scalar(&id_place)?.to_u32()?;
+        this.machine.processes.file_actions[usize::try_from(id).unwrap()] = None;
+        this.write_uninit(&id_place)?;
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn posix_spawn_file_actions_adddup2(
+        &mut self,
+        file_actions_op: &OpTy<'tcx>,
+        fd_op: &OpTy<'tcx>,
+        new_fd_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        let new_fd = this.read_scalar(new_fd_op)?.to_i32()?;
+        if fd < 0 || new_fd < 0 {
+            return interp_ok(this.eval_libc("EBADF"));
+        }
+        this.file_actions_mut(file_actions_op)?.push(FileAction::Dup2 { fd, new_fd });
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn posix_spawn_file_actions_addclose(
+        &mut self,
+        file_actions_op: &OpTy<'tcx>,
+        fd_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let fd = this.read_scalar(fd_op)?.to_i32()?;
+        if fd < 0 {
+            return interp_ok(this.eval_libc("EBADF"));
+        }
+        this.file_actions_mut(file_actions_op)?.push(FileAction::Close { fd });
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn posix_spawn_file_actions_addchdir(
+        &mut self,
+        file_actions_op: &OpTy<'tcx>,
+        path_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let path = this.read_pointer(path_op)?;
+        let path = this.read_path_from_c_str(path)?.into_owned();
+        this.file_actions_mut(file_actions_op)?.push(FileAction::Chdir(path));
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn waitpid(
+        &mut self,
+        pid_op: &OpTy<'tcx>,
+        status_op: &OpTy<'tcx>,
+        options_op: &OpTy<'tcx>,
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let pid = this.read_scalar(pid_op)?.to_i32()?;
+        let status = this.read_pointer(status_op)?;
+        let options = this.read_scalar(options_op)?.to_i32()?;
+
+        let wnohang = this.eval_libc_i32("WNOHANG");
+        if options & !wnohang != 0 {
+            throw_unsup_format!("waitpid: unsupported options {options:#x}");
+        }
+        if pid == 0 || pid < -1 {
+            throw_unsup_format!("waitpid: waiting for process groups is not supported");
+        }
+
+        this.waitpid_until_exit(pid, status, options & wnohang != 0, dest.clone())
+    }
+
+    fn kill(&mut self, pid_op: &OpTy<'tcx>, sig_op: &OpTy<'tcx>) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let pid = this.read_scalar(pid_op)?.to_i32()?;
+        let sig = this.read_scalar(sig_op)?.to_i32()?;
+
+        if pid <= 0 {
+            throw_unsup_format!("kill: sending signals to process groups is not supported");
+        }
+        if u32::try_from(pid) == Ok(this.get_pid()) {
+            if sig == 0 {
+                return interp_ok(Scalar::from_i32(0));
+            }
+            throw_unsup_format!("kill: sending signals to the current process is not supported");
+        }
+        let sigkill = this.eval_libc_i32("SIGKILL");
+        let sigterm = this.eval_libc_i32("SIGTERM");
+        if !this.machine.processes.contains(pid) {
+            return this.set_last_error_and_return_i32(LibcError("ESRCH"));
+        }
+        if sig == 0 {
+            // Just checking whether the process exists.
+        } else if sig == sigkill || sig == sigterm {
+            // The child interpreter does not handle signals, so both just end it.
+            if let Err(err) = this.machine.processes.kill(pid, sig) {
+                return this.set_last_error_and_return_i32(err);
+            }
+        } else {
+            throw_unsup_format!("kill: unsupported signal {sig}");
+        }
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn sigemptyset(&mut self, set_op: &OpTy<'tcx>) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let set = this.deref_pointer_as(set_op, this.libc_ty_layout("sigset_t"))?;
+        this.write_bytes_ptr(set.ptr(), std::iter::repeat_n(0u8, set.layout.size.bytes_usize()))?;
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn sigaddset(
+        &mut self,
+        set_op: &OpTy<'tcx>,
+        signo_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let set = this.deref_pointer_as(set_op, this.libc_ty_layout("sigset_t"))?;
+        let signo = this.read_scalar(signo_op)?.to_i32()?;
+
+        // The set is an array of `unsigned long` (or a single 32-bit integer on some targets),
+        // where signal `n` is bit `n - 1`.
+        let word_layout =
+            if set.layout.size >= this.pointer_size() && this.pointer_size().bytes() == 8 {
+                this.machine.layouts.u64
+            } else {
+                this.machine.layouts.u32
+            };
+        let word_bits = word_layout.size.bits();
+        let Some(bit) = u64::try_from(signo)
+            .ok()
+            .and_then(|signo| signo.checked_sub(1))
+            .filter(|&bit| bit < set.layout.size.bits())
+        else {
+            return this.set_last_error_and_return_i32(LibcError("EINVAL"));
+        };
+        let word = set.offset(word_layout.size * bit.strict_div(word_bits), word_layout, this)?;
+        let mask = 1u128.strict_shl(u32::try_from(bit.strict_rem(word_bits)).unwrap());
+        let value = this.read_scalar(&word)?.to_uint(word_layout.size)?;
+        this.write_scalar(Scalar::from_uint(value | mask, word_layout.size), &word)?;
+        interp_ok(Scalar::from_i32(0))
+    }
+}
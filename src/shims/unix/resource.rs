@@ -0,0 +1,260 @@
+//! Resource limits, i.e. `getrlimit` and friends.
+//!
+//! Unlike on a real system, most limits are not associated with any behavior, but a few of them
+//! are enforced so that programs can test how they deal with hitting them:
+//! - `RLIMIT_NOFILE` bounds the file descriptor numbers handed out for new file descriptions.
+//! - `RLIMIT_AS` and `RLIMIT_DATA` bound the total size of live heap allocations; allocation
+//!   functions return null (or the target's equivalent) when the limit would be exceeded.
+//! - `RLIMIT_STACK` bounds the number of stack frames of the main thread.
+
+use std::collections::BTreeMap;
+
+use crate::*;
+
+/// We have no idea how large a stack frame would be when compiled natively, so we just pretend
+/// they are all the same size for the purpose of enforcing `RLIMIT_STACK`.
+const STACK_BYTES_PER_FRAME: u64 = 256;
+
+/// A soft and a hard limit. `None` means there is no limit (`RLIM_INFINITY`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResourceLimit {
+    pub soft: Option<u64>,
+    pub hard: Option<u64>,
+}
+
+impl ResourceLimit {
+    pub const UNLIMITED: Self = ResourceLimit { soft: None, hard: None };
+}
+
+/// The resource limits of the interpreted process.
+#[derive(Clone, Debug)]
+pub struct ResourceLimits {
+    /// `RLIMIT_NOFILE`: one more than the largest file descriptor number that can be allocated.
+    pub nofile: ResourceLimit,
+    /// `RLIMIT_AS`: the maximum size of the address space, in bytes.
+    pub address_space: ResourceLimit,
+    /// `RLIMIT_DATA`: the maximum size of the data segment (which includes the heap), in bytes.
+    pub data: ResourceLimit,
+    /// `RLIMIT_STACK`: the maximum size of the main thread's stack, in bytes.
+    pub stack: ResourceLimit,
+    /// All the other limits, indexed by the target's resource number. We only remember them.
+    other: BTreeMap<i32, ResourceLimit>,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        ResourceLimits {
+            // This used to be what `sysconf(_SC_OPEN_MAX)` returned before we had resource
+            // limits. It is large enough to never get in the way of a normal program.
+            nofile: ResourceLimit { soft: Some(1 << 16), hard: Some(1 << 16) },
+            address_space: ResourceLimit::UNLIMITED,
+            data: ResourceLimit::UNLIMITED,
+            stack: ResourceLimit::UNLIMITED,
+            other: BTreeMap::new(),
+        }
+    }
+}
+
+impl ResourceLimits {
+    /// Looks up a limit by the name used on the command line.
+    pub fn by_name_mut(&mut self, name: &str) -> Option<&mut ResourceLimit> {
+        match name {
+            "nofile" => Some(&mut self.nofile),
+            "as" => Some(&mut self.address_space),
+            "data" => Some(&mut self.data),
+            "stack" => Some(&mut self.stack),
+            _ => None,
+        }
+    }
+
+    /// The maximum total size of live heap allocations, if any.
+    pub fn max_heap_bytes(&self) -> Option<u64> {
+        match (self.address_space.soft, self.data.soft) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// The maximum number of stack frames of the main thread, if any.
+    pub fn max_stack_frames(&self) -> Option<u64> {
+        self.stack.soft.map(|bytes| bytes / STACK_BYTES_PER_FRAME)
+    }
+}
+
+impl<'tcx> EvalContextPrivExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextPrivExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Returns the limit for the given target resource number.
+    fn resource_limit_mut(&mut self, resource: i32) -> &mut ResourceLimit {
+        let this = self.eval_context_mut();
+        let name = [
+            ("RLIMIT_NOFILE", "nofile"),
+            ("RLIMIT_AS", "as"),
+            ("RLIMIT_DATA", "data"),
+            ("RLIMIT_STACK", "stack"),
+        ]
+        .into_iter()
+        .find(|&(libc_name, _)| resource == this.eval_libc_i32(libc_name))
+        .map(|(_, name)| name);
+        let limits = &mut this.machine.rlimits;
+        match name {
+            Some(name) => limits.by_name_mut(name).unwrap(),
+            None => limits.other.entry(resource).or_insert(ResourceLimit::UNLIMITED),
+        }
+    }
+
+    /// Checks whether `resource` is a valid resource number for this target.
+    fn is_valid_resource(&self, resource: i32) -> bool {
+        let this = self.eval_context_ref();
+        // All targets number their resources consecutively from 0.
+        let count = this.eval_libc_i32("RLIM_NLIMITS");
+        (0..count).contains(&resource)
+    }
+
+    fn read_rlimit(&self, rlim: &MPlaceTy<'tcx>) -> InterpResult<'tcx, ResourceLimit> {
+        let this = self.eval_context_ref();
+        let size = this.libc_ty_layout("rlim_t").size;
+        let infinity = this.eval_libc("RLIM_INFINITY").to_uint(size)?;
+        let read = |name| -> InterpResult<'tcx, Option<u64>> {
+            let val = this.read_scalar(&this.project_field_named(rlim, name)?)?.to_uint(size)?;
+            interp_ok(if val == infinity { None } else { Some(u64::try_from(val).unwrap()) })
+        };
+        interp_ok(ResourceLimit { soft: read("rlim_cur")?, hard: read("rlim_max")? })
+    }
+
+    fn write_rlimit(&mut self, limit: ResourceLimit, rlim: &MPlaceTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let size = this.libc_ty_layout("rlim_t").size;
+        let infinity = this.eval_libc("RLIM_INFINITY").to_uint(size)?;
+        let to_rlim = |val: Option<u64>| val.map_or(infinity, u128::from).min(infinity);
+        this.write_scalar(
+            Scalar::from_uint(to_rlim(limit.soft), size),
+            &this.project_field_named(rlim, "rlim_cur")?,
+        )?;
+        this.write_scalar(
+            Scalar::from_uint(to_rlim(limit.hard), size),
+            &this.project_field_named(rlim, "rlim_max")?,
+        )?;
+        interp_ok(())
+    }
+
+    /// Shared implementation of `getrlimit`, `setrlimit` and `prlimit`. Returns the errno on
+    /// failure.
+    fn update_rlimit(
+        &mut self,
+        resource: i32,
+        new_limit: Option<&MPlaceTy<'tcx>>,
+        old_limit: Option<&MPlaceTy<'tcx>>,
+    ) -> InterpResult<'tcx, Result<(), IoError>> {
+        let this = self.eval_context_mut();
+
+        if !this.is_valid_resource(resource) {
+            return interp_ok(Err(LibcError("EINVAL")));
+        }
+        let new_limit = new_limit.map(|rlim| this.read_rlimit(rlim)).transpose()?;
+
+        let current = *this.resource_limit_mut(resource);
+        if let Some(old_limit) = old_limit {
+            this.write_rlimit(current, old_limit)?;
+        }
+        if let Some(new_limit) = new_limit {
+            // "unlimited" is the largest possible limit, which `Option` orders as the smallest.
+            let le =
+                |a: Option<u64>, b: Option<u64>| b.is_none() || a.is_some_and(|a| Some(a) <= b);
+            if !le(new_limit.soft, new_limit.hard) {
+                return interp_ok(Err(LibcError("EINVAL")));
+            }
+            // We are not a privileged process, so we cannot raise the hard limit.
+            if !le(new_limit.hard, current.hard) {
+                return interp_ok(Err(LibcError("EPERM")));
+            }
+            *this.resource_limit_mut(resource) = new_limit;
+        }
+
+        interp_ok(Ok(()))
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    fn getrlimit(
+        &mut self,
+        resource_op: &OpTy<'tcx>,
+        rlim_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let resource = this.read_scalar(resource_op)?.to_i32()?;
+        let rlim = this.deref_pointer_as(rlim_op, this.libc_ty_layout("rlimit"))?;
+
+        match this.update_rlimit(resource, None, Some(&rlim))? {
+            Ok(()) => interp_ok(Scalar::from_i32(0)),
+            Err(err) => this.set_last_error_and_return_i32(err),
+        }
+    }
+
+    fn setrlimit(
+        &mut self,
+        resource_op: &OpTy<'tcx>,
+        rlim_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let resource = this.read_scalar(resource_op)?.to_i32()?;
+        let rlim = this.deref_pointer_as(rlim_op, this.libc_ty_layout("rlimit"))?;
+
+        match this.update_rlimit(resource, Some(&rlim), None)? {
+            Ok(()) => interp_ok(Scalar::from_i32(0)),
+            Err(err) => this.set_last_error_and_return_i32(err),
+        }
+    }
+
+    fn prlimit(
+        &mut self,
+        pid_op: &OpTy<'tcx>,
+        resource_op: &OpTy<'tcx>,
+        new_limit_op: &OpTy<'tcx>,
+        old_limit_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let pid = this.read_scalar(pid_op)?.to_i32()?;
+        let resource = this.read_scalar(resource_op)?.to_i32()?;
+        let new_limit = this.read_pointer(new_limit_op)?;
+        let old_limit = this.read_pointer(old_limit_op)?;
+
+        // There are no other processes.
+        if pid != 0 && u32::try_from(pid) != Ok(this.get_pid()) {
+            return this.set_last_error_and_return_i32(LibcError("ESRCH"));
+        }
+
+        let rlimit_layout = this.libc_ty_layout("rlimit");
+        let new_limit = if this.ptr_is_null(new_limit)? {
+            None
+        } else {
+            Some(this.ptr_to_mplace(new_limit, rlimit_layout))
+        };
+        let old_limit = if this.ptr_is_null(old_limit)? {
+            None
+        } else {
+            Some(this.ptr_to_mplace(old_limit, rlimit_layout))
+        };
+
+        match this.update_rlimit(resource, new_limit.as_ref(), old_limit.as_ref())? {
+            Ok(()) => interp_ok(Scalar::from_i32(0)),
+            Err(err) => this.set_last_error_and_return_i32(err),
+        }
+    }
+
+    /// Checks whether `count` more file descriptors, numbered at least `min_fd_num`, can be
+    /// allocated without exceeding `RLIMIT_NOFILE` or running out of file descriptor numbers.
+    fn fd_limit_allows(&self, count: usize, min_fd_num: i32) -> bool {
+        let this = self.eval_context_ref();
+        let Some(last) = count.checked_sub(1) else {
+            return true;
+        };
+        let Some(fd_num) = this.machine.fds.free_fd_nums(min_fd_num).nth(last) else {
+            return false;
+        };
+        this.machine.rlimits.nofile.soft.is_none_or(|limit| u64::try_from(fd_num).unwrap() < limit)
+    }
+}
@@ -562,11 +562,14 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         interp_ok(Scalar::from_i32(if let Some(owner_thread) = mutex.mutex_ref.owner() {
             if owner_thread != this.active_thread() {
+                this.mutex_lock_failed(&mutex.mutex_ref);
                 this.eval_libc_i32("EBUSY")
             } else {
                 match mutex.kind {
-                    MutexKind::Default | MutexKind::Normal | MutexKind::ErrorCheck =>
-                        this.eval_libc_i32("EBUSY"),
+                    MutexKind::Default | MutexKind::Normal | MutexKind::ErrorCheck => {
+                        this.mutex_lock_failed(&mutex.mutex_ref);
+                        this.eval_libc_i32("EBUSY")
+                    }
                     MutexKind::Recursive => {
                         this.mutex_lock(&mutex.mutex_ref)?;
                         0
@@ -639,8 +642,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         if rwlock.rwlock_ref.is_write_locked() {
             this.rwlock_enqueue_and_block_reader(
                 rwlock.rwlock_ref,
-                Scalar::from_i32(0),
-                dest.clone(),
+                Some((Scalar::from_i32(0), dest.clone())),
             );
         } else {
             this.rwlock_reader_lock(&rwlock.rwlock_ref)?;
@@ -656,6 +658,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let rwlock = rwlock_get_data(this, rwlock_op)?.clone();
 
         if rwlock.rwlock_ref.is_write_locked() {
+            this.rwlock_lock_failed(&rwlock.rwlock_ref);
             interp_ok(Scalar::from_i32(this.eval_libc_i32("EBUSY")))
         } else {
             this.rwlock_reader_lock(&rwlock.rwlock_ref)?;
@@ -687,8 +690,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             // an error.)
             this.rwlock_enqueue_and_block_writer(
                 rwlock.rwlock_ref,
-                Scalar::from_i32(0),
-                dest.clone(),
+                Some((Scalar::from_i32(0), dest.clone())),
             );
         } else {
             this.rwlock_writer_lock(&rwlock.rwlock_ref)?;
@@ -704,6 +706,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let rwlock = rwlock_get_data(this, rwlock_op)?.clone();
 
         if rwlock.rwlock_ref.is_locked() {
+            this.rwlock_lock_failed(&rwlock.rwlock_ref);
             interp_ok(Scalar::from_i32(this.eval_libc_i32("EBUSY")))
         } else {
             this.rwlock_writer_lock(&rwlock.rwlock_ref)?;
@@ -903,10 +906,6 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             // monotonic clock, regardless of the condvar clock.
             (TimeoutClock::Monotonic, TimeoutAnchor::Relative)
         } else {
-            if data.clock == TimeoutClock::RealTime {
-                this.check_no_isolation("`pthread_cond_timedwait` with `CLOCK_REALTIME`")?;
-            }
-
             (data.clock, TimeoutAnchor::Absolute)
         };
 
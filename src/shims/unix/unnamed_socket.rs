@@ -11,10 +11,12 @@ use rustc_target::spec::Os;
 
 use crate::concurrency::VClock;
 use crate::shims::files::{
-    EvalContextExt as _, FdId, FileDescription, FileDescriptionRef, WeakFileDescriptionRef,
+    DynFileDescriptionRef, EvalContextExt as _, FdId, FdTable, FileDescription, FileDescriptionRef,
+    WeakFileDescriptionRef,
 };
 use crate::shims::unix::UnixFileDescription;
 use crate::shims::unix::linux_like::epoll::{EpollEvents, EvalContextExt as _};
+use crate::shims::unix::resource::EvalContextExt as _;
 use crate::*;
 
 /// The maximum capacity of the socketpair buffer in bytes.
@@ -199,6 +201,81 @@ impl FileDescription for AnonSocket {
     }
 }
 
+/// If `fd` is the read end (or, if `read_end` is false, the write end) of a pipe, returns the
+/// other end of that pipe, if it is still open.
+pub fn pipe_peer(
+    fd: DynFileDescriptionRef,
+    read_end: bool,
+) -> Option<Option<DynFileDescriptionRef>> {
+    let fd = fd.downcast::<AnonSocket>()?;
+    let expected = if read_end { AnonSocketType::PipeRead } else { AnonSocketType::PipeWrite };
+    if fd.fd_type != expected {
+        return None;
+    }
+    Some(fd.peer_fd().upgrade().map(|peer| peer as DynFileDescriptionRef))
+}
+
+/// Creates two connected unnamed sockets, or (if `pipe` is set) the read end and the write end
+/// of a pipe. The file descriptions are not yet inserted into the fd table.
+pub fn new_anonsocket_pair(
+    fds: &mut FdTable,
+    pipe: bool,
+    is_nonblock: bool,
+) -> (DynFileDescriptionRef, DynFileDescriptionRef) {
+    let (type0, type1) = if pipe {
+        (AnonSocketType::PipeRead, AnonSocketType::PipeWrite)
+    } else {
+        (AnonSocketType::Socketpair, AnonSocketType::Socketpair)
+    };
+    let new_socket = |fd_type: AnonSocketType| {
+        AnonSocket {
+            // The write end of a pipe does not have a buffer.
+            readbuf: (fd_type != AnonSocketType::PipeWrite).then(|| RefCell::new(Buffer::new())),
+            peer_fd: OnceCell::new(),
+            peer_lost_data: Cell::new(false),
+            blocked_read_tid: RefCell::new(Vec::new()),
+            blocked_write_tid: RefCell::new(Vec::new()),
+            is_nonblock: Cell::new(is_nonblock),
+            fd_type,
+        }
+    };
+    let fd0 = fds.new_ref(new_socket(type0));
+    let fd1 = fds.new_ref(new_socket(type1));
+
+    // Make the file descriptions point to each other.
+    fd0.peer_fd.set(FileDescriptionRef::downgrade(&fd1)).unwrap();
+    fd1.peer_fd.set(FileDescriptionRef::downgrade(&fd0)).unwrap();
+
+    (fd0, fd1)
+}
+
+/// Copies up to `len` bytes of the data that is ready to be read from `fd` to `ptr`, without
+/// consuming it. Returns how many bytes were copied and how many are available in total, or `None`
+/// if `fd` is not an unnamed socket or pipe.
+pub fn anonsocket_peek<'tcx>(
+    fd: DynFileDescriptionRef,
+    ptr: Pointer,
+    len: usize,
+    ecx: &mut MiriInterpCx<'tcx>,
+) -> InterpResult<'tcx, Option<Result<(usize, usize), IoError>>> {
+    let Some(fd) = fd.downcast::<AnonSocket>() else {
+        return interp_ok(None);
+    };
+    let Some(readbuf) = &fd.readbuf else {
+        return interp_ok(Some(Err(ErrorKind::PermissionDenied.into())));
+    };
+    let readbuf = readbuf.borrow();
+    if readbuf.buf.is_empty() && fd.peer_fd().upgrade().is_none() {
+        return interp_ok(Some(Err(ErrorKind::BrokenPipe.into())));
+    }
+    // Looking at the data synchronizes with the writes that produced it, just like reading does.
+    ecx.acquire_clock(&readbuf.clock)?;
+    let available = readbuf.buf.len();
+    let peek_size = len.min(available);
+    ecx.write_bytes_ptr(ptr, readbuf.buf.range(..peek_size).copied())?;
+    interp_ok(Some(Ok((peek_size, available))))
+}
+
 /// Write to AnonSocket based on the space available and return the written byte size.
 fn anonsocket_write<'tcx>(
     self_ref: FileDescriptionRef<AnonSocket>,
@@ -309,6 +386,10 @@ fn anonsocket_read<'tcx>(
 
     if readbuf.borrow_mut().buf.is_empty() {
         if self_ref.peer_fd().upgrade().is_none() {
+            if ecx.tcx.sess.target.os == Os::Windows {
+                // On Windows, reading from a pipe whose other end is closed is an error.
+                return finish.call(ecx, Err(ErrorKind::BrokenPipe.into()));
+            }
             // Socketpair with no peer and empty buffer.
             // 0 bytes successfully read indicates end-of-file.
             return finish.call(ecx, Ok(0));
@@ -494,30 +575,13 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             );
         }
 
+        if !this.fd_limit_allows(2, 0) {
+            return this.set_last_error_and_return_i32(LibcError("EMFILE"));
+        }
+
         // Generate file descriptions.
         let fds = &mut this.machine.fds;
-        let fd0 = fds.new_ref(AnonSocket {
-            readbuf: Some(RefCell::new(Buffer::new())),
-            peer_fd: OnceCell::new(),
-            peer_lost_data: Cell::new(false),
-            blocked_read_tid: RefCell::new(Vec::new()),
-            blocked_write_tid: RefCell::new(Vec::new()),
-            is_nonblock: Cell::new(is_sock_nonblock),
-            fd_type: AnonSocketType::Socketpair,
-        });
-        let fd1 = fds.new_ref(AnonSocket {
-            readbuf: Some(RefCell::new(Buffer::new())),
-            peer_fd: OnceCell::new(),
-            peer_lost_data: Cell::new(false),
-            blocked_read_tid: RefCell::new(Vec::new()),
-            blocked_write_tid: RefCell::new(Vec::new()),
-            is_nonblock: Cell::new(is_sock_nonblock),
-            fd_type: AnonSocketType::Socketpair,
-        });
-
-        // Make the file descriptions point to each other.
-        fd0.peer_fd.set(FileDescriptionRef::downgrade(&fd1)).unwrap();
-        fd1.peer_fd.set(FileDescriptionRef::downgrade(&fd0)).unwrap();
+        let (fd0, fd1) = new_anonsocket_pair(fds, /* pipe */ false, is_sock_nonblock);
 
         // Insert the file description to the fd table, generating the file descriptors.
         let sv0 = fds.insert(fd0);
@@ -563,31 +627,14 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             throw_unsup_format!("unsupported flags in `pipe2`");
         }
 
+        if !this.fd_limit_allows(2, 0) {
+            return this.set_last_error_and_return_i32(LibcError("EMFILE"));
+        }
+
         // Generate file descriptions.
         // pipefd[0] refers to the read end of the pipe.
         let fds = &mut this.machine.fds;
-        let fd0 = fds.new_ref(AnonSocket {
-            readbuf: Some(RefCell::new(Buffer::new())),
-            peer_fd: OnceCell::new(),
-            peer_lost_data: Cell::new(false),
-            blocked_read_tid: RefCell::new(Vec::new()),
-            blocked_write_tid: RefCell::new(Vec::new()),
-            is_nonblock: Cell::new(is_nonblock),
-            fd_type: AnonSocketType::PipeRead,
-        });
-        let fd1 = fds.new_ref(AnonSocket {
-            readbuf: None,
-            peer_fd: OnceCell::new(),
-            peer_lost_data: Cell::new(false),
-            blocked_read_tid: RefCell::new(Vec::new()),
-            blocked_write_tid: RefCell::new(Vec::new()),
-            is_nonblock: Cell::new(is_nonblock),
-            fd_type: AnonSocketType::PipeWrite,
-        });
-
-        // Make the file descriptions point to each other.
-        fd0.peer_fd.set(FileDescriptionRef::downgrade(&fd1)).unwrap();
-        fd1.peer_fd.set(FileDescriptionRef::downgrade(&fd0)).unwrap();
+        let (fd0, fd1) = new_anonsocket_pair(fds, /* pipe */ true, is_nonblock);
 
         // Insert the file description to the fd table, generating the file descriptors.
         let pipefd0 = fds.insert(fd0);
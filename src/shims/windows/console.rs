@@ -0,0 +1,272 @@
+//! Console handles. Miri does not emulate a console of its own; the standard handles count as
+//! console handles if (and only if) the host stream they refer to is a terminal, which requires
+//! isolation to be disabled.
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Read, Write};
+
+use rustc_abi::Size;
+
+use crate::shims::windows::handle::{EvalContextExt as _, Handle};
+use crate::*;
+
+// Console mode flags; std's Windows bindings only contain a few of these.
+const ENABLE_PROCESSED_INPUT: u32 = 0x1;
+const ENABLE_LINE_INPUT: u32 = 0x2;
+const ENABLE_ECHO_INPUT: u32 = 0x4;
+const ENABLE_EXTENDED_FLAGS: u32 = 0x80;
+/// All valid input mode flags.
+const INPUT_MODE_MASK: u32 = 0x3FF;
+const ENABLE_PROCESSED_OUTPUT: u32 = 0x1;
+const ENABLE_WRAP_AT_EOL_OUTPUT: u32 = 0x2;
+/// All valid output mode flags.
+const OUTPUT_MODE_MASK: u32 = 0x1F;
+
+/// The state of the console that the standard handles are attached to.
+#[derive(Debug)]
+pub struct WindowsConsole {
+    /// The mode of the input buffer, as set by `SetConsoleMode`.
+    input_mode: u32,
+    /// The mode of the screen buffer, shared by stdout and stderr.
+    output_mode: u32,
+    /// Input that has been read from the host but not yet returned by `ReadConsoleW`.
+    pending_input: VecDeque<u16>,
+}
+
+impl Default for WindowsConsole {
+    fn default() -> Self {
+        // These are the defaults of a freshly created console.
+        WindowsConsole {
+            input_mode: ENABLE_PROCESSED_INPUT
+                | ENABLE_LINE_INPUT
+                | ENABLE_ECHO_INPUT
+                | 0x10 // ENABLE_MOUSE_INPUT
+                | 0x20 // ENABLE_INSERT_MODE
+                | 0x40 // ENABLE_QUICK_EDIT_MODE
+                | ENABLE_EXTENDED_FLAGS
+                | 0x100, // ENABLE_AUTO_POSITION
+            output_mode: ENABLE_PROCESSED_OUTPUT | ENABLE_WRAP_AT_EOL_OUTPUT,
+            pending_input: VecDeque::new(),
+        }
+    }
+}
+
+/// Which buffer of the console a handle refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConsoleBuffer {
+    Input,
+    Output { stderr: bool },
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+#[allow(non_snake_case)]
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    fn GetConsoleMode(
+        &mut self,
+        console: &OpTy<'tcx>, // HANDLE
+        mode: &OpTy<'tcx>,    // LPDWORD
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+        let console = this.read_handle(console, "GetConsoleMode")?;
+        let mode = this.deref_pointer_as(mode, this.machine.layouts.u32)?;
+
+        let value = match this.console_buffer(console)? {
+            Some(ConsoleBuffer::Input) => this.machine.console.input_mode,
+            Some(ConsoleBuffer::Output { .. }) => this.machine.console.output_mode,
+            None => {
+                this.set_last_error(IoError::WindowsError("ERROR_INVALID_HANDLE"))?;
+                return interp_ok(this.eval_windows("c", "FALSE"));
+            }
+        };
+        this.write_scalar(Scalar::from_u32(value), &mode)?;
+        interp_ok(this.eval_windows("c", "TRUE"))
+    }
+
+    fn SetConsoleMode(
+        &mut self,
+        console: &OpTy<'tcx>, // HANDLE
+        mode: &OpTy<'tcx>,    // DWORD
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+        let console = this.read_handle(console, "SetConsoleMode")?;
+        let mode = this.read_scalar(mode)?.to_u32()?;
+
+        let Some(buffer) = this.console_buffer(console)? else {
+            this.set_last_error(IoError::WindowsError("ERROR_INVALID_HANDLE"))?;
+            return interp_ok(this.eval_windows("c", "FALSE"));
+        };
+        let valid = match buffer {
+            // Echoing is only possible in line mode.
+            ConsoleBuffer::Input =>
+                mode & !INPUT_MODE_MASK == 0
+                    && (mode & ENABLE_ECHO_INPUT == 0 || mode & ENABLE_LINE_INPUT != 0),
+            ConsoleBuffer::Output { .. } => mode & !OUTPUT_MODE_MASK == 0,
+        };
+        if !valid {
+            this.set_last_error(IoError::WindowsError("ERROR_INVALID_PARAMETER"))?;
+            return interp_ok(this.eval_windows("c", "FALSE"));
+        }
+
+        // The host terminal keeps its own mode; we only track the mode to decide how to read.
+        match buffer {
+            ConsoleBuffer::Input => this.machine.console.input_mode = mode,
+            ConsoleBuffer::Output { .. } => this.machine.console.output_mode = mode,
+        }
+        interp_ok(this.eval_windows("c", "TRUE"))
+    }
+
+    fn ReadConsoleW(
+        &mut self,
+        console: &OpTy<'tcx>,       // HANDLE
+        buffer: &OpTy<'tcx>,        // LPVOID
+        chars_to_read: &OpTy<'tcx>, // DWORD
+        chars_read: &OpTy<'tcx>,    // LPDWORD
+        input_control: &OpTy<'tcx>, // PCONSOLE_READCONSOLE_CONTROL
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+        let console = this.read_handle(console, "ReadConsoleW")?;
+        let buffer = this.read_pointer(buffer)?;
+        let chars_to_read = this.read_scalar(chars_to_read)?.to_u32()?;
+        let chars_read = this.read_pointer(chars_read)?;
+        let input_control = this.read_pointer(input_control)?;
+
+        if this.console_buffer(console)? != Some(ConsoleBuffer::Input) {
+            this.set_last_error(IoError::WindowsError("ERROR_INVALID_HANDLE"))?;
+            return interp_ok(this.eval_windows("c", "FALSE"));
+        }
+        if !this.ptr_is_null(input_control)? {
+            // std uses this to make Ctrl-Z end the read. We never receive a Ctrl-Z from the host
+            // terminal, so only continuing a previous read needs special support.
+            let input_control = this.ptr_to_mplace(
+                input_control,
+                this.windows_ty_layout("CONSOLE_READCONSOLE_CONTROL"),
+            );
+            let initial_chars = this.project_field_named(&input_control, "nInitialChars")?;
+            if this.read_scalar(&initial_chars)?.to_u32()? != 0 {
+                throw_unsup_format!("`ReadConsoleW`: `nInitialChars` is not supported");
+            }
+        }
+
+        if this.machine.console.pending_input.is_empty() {
+            let line_mode = this.machine.console.input_mode & ENABLE_LINE_INPUT != 0;
+            let input = if line_mode {
+                // The console delivers whole lines, terminated by "\r\n".
+                let mut line = String::new();
+                match io::stdin().lock().read_line(&mut line) {
+                    Ok(_) => {}
+                    Err(e) => {
+                        this.set_last_error(e)?;
+                        return interp_ok(this.eval_windows("c", "FALSE"));
+                    }
+                }
+                if let Some(stripped) = line.strip_suffix('\n') {
+                    line = format!("{}\r\n", stripped.strip_suffix('\r').unwrap_or(stripped));
+                }
+                line
+            } else {
+                // Without line input, we hand out whatever the host has available.
+                let mut bytes = vec![0; 1024];
+                match io::stdin().lock().read(&mut bytes) {
+                    Ok(n) => bytes.truncate(n),
+                    Err(e) => {
+                        this.set_last_error(e)?;
+                        return interp_ok(this.eval_windows("c", "FALSE"));
+                    }
+                }
+                String::from_utf8_lossy(&bytes).into_owned()
+            };
+            this.machine.console.pending_input.extend(input.encode_utf16());
+        }
+
+        let pending = &mut this.machine.console.pending_input;
+        let len = pending.len().min(chars_to_read.try_into().unwrap());
+        let chars: Vec<u16> = pending.drain(..len).collect();
+        if !chars.is_empty() {
+            let size2 = Size::from_bytes(2);
+            this.check_ptr_align(buffer, this.machine.layouts.u16.align.abi)?;
+            let mut alloc = this
+                .get_ptr_alloc_mut(buffer, size2 * u64::try_from(chars.len()).unwrap())?
+                .unwrap(); // not a ZST, so we will get a result
+            for (offset, wchar) in chars.iter().copied().enumerate() {
+                let offset = u64::try_from(offset).unwrap();
+                alloc.write_scalar(alloc_range(size2 * offset, size2), Scalar::from_u16(wchar))?;
+            }
+        }
+
+        if !this.ptr_is_null(chars_read)? {
+            let chars_read = this.ptr_to_mplace(chars_read, this.machine.layouts.u32);
+            this.write_scalar(Scalar::from_u32(len.try_into().unwrap()), &chars_read)?;
+        }
+        interp_ok(this.eval_windows("c", "TRUE"))
+    }
+
+    fn WriteConsoleW(
+        &mut self,
+        console: &OpTy<'tcx>,        // HANDLE
+        buffer: &OpTy<'tcx>,         // const VOID*
+        chars_to_write: &OpTy<'tcx>, // DWORD
+        chars_written: &OpTy<'tcx>,  // LPDWORD
+        reserved: &OpTy<'tcx>,       // LPVOID
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+        let console = this.read_handle(console, "WriteConsoleW")?;
+        let buffer = this.read_pointer(buffer)?;
+        let chars_to_write = this.read_scalar(chars_to_write)?.to_u32()?;
+        let chars_written = this.read_pointer(chars_written)?;
+        let _ = this.read_pointer(reserved)?;
+
+        let Some(ConsoleBuffer::Output { stderr }) = this.console_buffer(console)? else {
+            this.set_last_error(IoError::WindowsError("ERROR_INVALID_HANDLE"))?;
+            return interp_ok(this.eval_windows("c", "FALSE"));
+        };
+
+        let mut chars = Vec::with_capacity(chars_to_write.try_into().unwrap());
+        let u16_layout = this.machine.layouts.u16;
+        for i in 0..u64::from(chars_to_write) {
+            let ptr = buffer.wrapping_offset(u16_layout.size * i, this);
+            let place = this.ptr_to_mplace(ptr, u16_layout);
+            chars.push(this.read_scalar(&place)?.to_u16()?);
+        }
+        let text = String::from_utf16_lossy(&chars);
+        let result = if stderr {
+            io::stderr().write_all(text.as_bytes()).and_then(|()| io::stderr().flush())
+        } else {
+            io::stdout().write_all(text.as_bytes()).and_then(|()| io::stdout().flush())
+        };
+        if let Err(e) = result {
+            this.set_last_error(e)?;
+            return interp_ok(this.eval_windows("c", "FALSE"));
+        }
+
+        if !this.ptr_is_null(chars_written)? {
+            let chars_written = this.ptr_to_mplace(chars_written, this.machine.layouts.u32);
+            this.write_scalar(Scalar::from_u32(chars_to_write), &chars_written)?;
+        }
+        interp_ok(this.eval_windows("c", "TRUE"))
+    }
+}
+
+impl<'tcx> EvalContextPrivExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextPrivExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Determines which console buffer `handle` refers to, if any.
+    fn console_buffer(&mut self, handle: Handle) -> InterpResult<'tcx, Option<ConsoleBuffer>> {
+        let this = self.eval_context_mut();
+        let Handle::File(fd_num) = handle else { return interp_ok(None) };
+        let Some(desc) = this.machine.fds.get(fd_num) else { return interp_ok(None) };
+        if !desc.is_tty(this.machine.communicate()) {
+            return interp_ok(None);
+        }
+        let buffer = match desc.name() {
+            "stdin" => ConsoleBuffer::Input,
+            "stdout" => ConsoleBuffer::Output { stderr: false },
+            "stderr" => ConsoleBuffer::Output { stderr: true },
+            // Files opened with `CreateFileW` (such as `CONOUT$`) are not treated as consoles.
+            _ => return interp_ok(None),
+        };
+        interp_ok(Some(buffer))
+    }
+}
@@ -28,6 +28,19 @@ impl WindowsEnvVars {
         interp_ok(Self { map: env_vars })
     }
 
+    /// The environment as `NAME=VALUE` strings, which is what child processes inherit.
+    pub(crate) fn env_strings(&self) -> Vec<OsString> {
+        self.map
+            .iter()
+            .map(|(name, value)| {
+                let mut var = name.clone();
+                var.push("=");
+                var.push(value);
+                var
+            })
+            .collect()
+    }
+
     /// Implementation detail for [`InterpCx::get_env_var`].
     pub(crate) fn get<'tcx>(&self, name: &OsStr) -> InterpResult<'tcx, Option<OsString>> {
         interp_ok(self.map.get(name).cloned())
@@ -9,7 +9,9 @@ use rustc_target::callconv::FnAbi;
 use rustc_target::spec::{Arch, Env};
 
 use self::shims::windows::handle::{Handle, PseudoHandle};
+use crate::shims::alloc::EvalContextExt as _;
 use crate::shims::os_str::bytes_to_os_str;
+use crate::shims::time::CpuClock;
 use crate::shims::windows::*;
 use crate::*;
 
@@ -319,6 +321,106 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     this.SetFilePointerEx(file, distance_to_move, new_file_pointer, move_method)?;
                 this.write_scalar(res, dest)?;
             }
+            "CreatePipe" => {
+                let [read_pipe, write_pipe, pipe_attributes, size] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.CreatePipe(read_pipe, write_pipe, pipe_attributes, size)?;
+                this.write_scalar(res, dest)?;
+            }
+            "CreateNamedPipeW" => {
+                let [
+                    name,
+                    open_mode,
+                    pipe_mode,
+                    max_instances,
+                    out_buffer_size,
+                    in_buffer_size,
+                    default_timeout,
+                    security_attributes,
+                ] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let handle = this.CreateNamedPipeW(
+                    name,
+                    open_mode,
+                    pipe_mode,
+                    max_instances,
+                    out_buffer_size,
+                    in_buffer_size,
+                    default_timeout,
+                    security_attributes,
+                )?;
+                this.write_scalar(handle.to_scalar(this), dest)?;
+            }
+            "ConnectNamedPipe" => {
+                let [pipe, overlapped] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.ConnectNamedPipe(pipe, overlapped, dest)?;
+            }
+            "DisconnectNamedPipe" => {
+                let [pipe] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.DisconnectNamedPipe(pipe)?;
+                this.write_scalar(res, dest)?;
+            }
+            "PeekNamedPipe" => {
+                let [pipe, buffer, buffer_size, bytes_read, total_bytes_avail, bytes_left] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.PeekNamedPipe(
+                    pipe,
+                    buffer,
+                    buffer_size,
+                    bytes_read,
+                    total_bytes_avail,
+                    bytes_left,
+                )?;
+                this.write_scalar(res, dest)?;
+            }
+            "GetConsoleMode" => {
+                let [console, mode] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.GetConsoleMode(console, mode)?;
+                this.write_scalar(res, dest)?;
+            }
+            "SetConsoleMode" => {
+                let [console, mode] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.SetConsoleMode(console, mode)?;
+                this.write_scalar(res, dest)?;
+            }
+            "ReadConsoleW" => {
+                let [console, buffer, chars_to_read, chars_read, input_control] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res =
+                    this.ReadConsoleW(console, buffer, chars_to_read, chars_read, input_control)?;
+                this.write_scalar(res, dest)?;
+            }
+            "WriteConsoleW" => {
+                let [console, buffer, chars_to_write, chars_written, reserved] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res =
+                    this.WriteConsoleW(console, buffer, chars_to_write, chars_written, reserved)?;
+                this.write_scalar(res, dest)?;
+            }
+            "LockFileEx" => {
+                let [file, flags, reserved, len_low, len_high, overlapped] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.LockFileEx(file, flags, reserved, len_low, len_high, overlapped, dest)?;
+            }
+            "LockFile" => {
+                let [file, offset_low, offset_high, len_low, len_high] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.LockFile(file, offset_low, offset_high, len_low, len_high, dest)?;
+            }
+            "UnlockFileEx" => {
+                let [file, reserved, len_low, len_high, overlapped] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.UnlockFileEx(file, reserved, len_low, len_high, overlapped)?;
+                this.write_scalar(res, dest)?;
+            }
+            "UnlockFile" => {
+                let [file, offset_low, offset_high, len_low, len_high] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.UnlockFile(file, offset_low, offset_high, len_low, len_high)?;
+                this.write_scalar(res, dest)?;
+            }
 
             // Allocation
             "HeapAlloc" => {
@@ -336,6 +438,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 // Alignment is twice the pointer size.
                 // Source: <https://learn.microsoft.com/en-us/windows/win32/api/heapapi/nf-heapapi-heapalloc>
                 let align = this.tcx.pointer_size().bytes().strict_mul(2);
+                if !this.check_heap_limit_for_alloc(size, None)? {
+                    this.write_null(dest)?;
+                    return interp_ok(EmulateItemResult::NeedsReturn);
+                }
                 let ptr = this.allocate_ptr(
                     Size::from_bytes(size),
                     Align::from_bytes(align).unwrap(),
@@ -368,6 +474,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 // The docs say that `old_ptr` must come from an earlier HeapAlloc or HeapReAlloc,
                 // so unlike C `realloc` we do *not* allow a NULL here.
                 // (https://learn.microsoft.com/en-us/windows/win32/api/heapapi/nf-heapapi-heaprealloc)
+                if !this.check_heap_limit_for_alloc(size, Some(old_ptr))? {
+                    this.write_null(dest)?;
+                    return interp_ok(EmulateItemResult::NeedsReturn);
+                }
                 let new_ptr = this.reallocate_ptr(
                     old_ptr,
                     None,
@@ -414,6 +524,10 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                     0xC0000185 => 1117,
                     // STATUS_ACCESS_DENIED => ERROR_ACCESS_DENIED
                     0xC0000022 => 5,
+                    // STATUS_PIPE_BROKEN => ERROR_BROKEN_PIPE
+                    0xC000014B => 109,
+                    // STATUS_PIPE_LISTENING => ERROR_PIPE_LISTENING
+                    0xC00000B3 => 536,
                     // Anything without an error code => ERROR_MR_MID_NOT_FOUND
                     _ => 317,
                 };
@@ -491,6 +605,65 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let [LPFILETIME] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
                 this.GetSystemTimeAsFileTime(link_name.as_str(), LPFILETIME)?;
             }
+            "FileTimeToLocalFileTime" => {
+                #[allow(non_snake_case)]
+                let [lpFileTime, lpLocalFileTime] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let result = this.FileTimeToLocalFileTime(lpFileTime, lpLocalFileTime)?;
+                this.write_scalar(result, dest)?;
+            }
+            "LocalFileTimeToFileTime" => {
+                #[allow(non_snake_case)]
+                let [lpLocalFileTime, lpFileTime] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let result = this.LocalFileTimeToFileTime(lpLocalFileTime, lpFileTime)?;
+                this.write_scalar(result, dest)?;
+            }
+            "GetTimeZoneInformation" => {
+                #[allow(non_snake_case)]
+                let [lpTimeZoneInformation] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let result = this.GetTimeZoneInformation(lpTimeZoneInformation)?;
+                this.write_scalar(result, dest)?;
+            }
+            "GetThreadTimes" => {
+                #[allow(non_snake_case)]
+                let [hThread, lpCreationTime, lpExitTime, lpKernelTime, lpUserTime] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let thread = match this.read_handle(hThread, "GetThreadTimes")? {
+                    Handle::Thread(thread) => thread,
+                    Handle::Pseudo(PseudoHandle::CurrentThread) => this.active_thread(),
+                    _ => this.invalid_handle("GetThreadTimes")?,
+                };
+                let result = this.write_windows_cpu_times(
+                    CpuClock::Thread(thread),
+                    lpCreationTime,
+                    lpExitTime,
+                    lpKernelTime,
+                    lpUserTime,
+                )?;
+                this.write_scalar(result, dest)?;
+            }
+            "GetProcessTimes" => {
+                #[allow(non_snake_case)]
+                let [hProcess, lpCreationTime, lpExitTime, lpKernelTime, lpUserTime] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                if this.read_handle(hProcess, "GetProcessTimes")?
+                    != Handle::Pseudo(PseudoHandle::CurrentProcess)
+                {
+                    throw_unsup_format!(
+                        "`GetProcessTimes` is only supported on the current process"
+                    );
+                }
+                let result = this.write_windows_cpu_times(
+                    CpuClock::Process,
+                    lpCreationTime,
+                    lpExitTime,
+                    lpKernelTime,
+                    lpUserTime,
+                )?;
+                this.write_scalar(result, dest)?;
+            }
             "QueryPerformanceCounter" => {
                 #[allow(non_snake_case)]
                 let [lpPerformanceCount] =
@@ -534,6 +707,92 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let result = this.InitOnceComplete(ptr, flags, context)?;
                 this.write_scalar(result, dest)?;
             }
+            "InitializeSRWLock" => {
+                let [lock] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.InitializeSRWLock(lock)?;
+            }
+            "AcquireSRWLockExclusive" => {
+                let [lock] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.AcquireSRWLockExclusive(lock)?;
+            }
+            "AcquireSRWLockShared" => {
+                let [lock] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.AcquireSRWLockShared(lock)?;
+            }
+            "TryAcquireSRWLockExclusive" => {
+                let [lock] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let ret = this.TryAcquireSRWLockExclusive(lock)?;
+                this.write_scalar(ret, dest)?;
+            }
+            "TryAcquireSRWLockShared" => {
+                let [lock] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let ret = this.TryAcquireSRWLockShared(lock)?;
+                this.write_scalar(ret, dest)?;
+            }
+            "ReleaseSRWLockExclusive" => {
+                let [lock] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.ReleaseSRWLockExclusive(lock)?;
+            }
+            "ReleaseSRWLockShared" => {
+                let [lock] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.ReleaseSRWLockShared(lock)?;
+            }
+            "InitializeCriticalSection" => {
+                let [cs] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.InitializeCriticalSection(cs)?;
+            }
+            "InitializeCriticalSectionAndSpinCount" => {
+                let [cs, spin_count] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let _spin_count = this.read_scalar(spin_count)?.to_u32()?;
+                this.InitializeCriticalSection(cs)?;
+                this.write_scalar(this.eval_windows("c", "TRUE"), dest)?;
+            }
+            "InitializeCriticalSectionEx" => {
+                let [cs, spin_count, flags] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let ret = this.InitializeCriticalSectionEx(cs, spin_count, flags)?;
+                this.write_scalar(ret, dest)?;
+            }
+            "EnterCriticalSection" => {
+                let [cs] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.EnterCriticalSection(cs)?;
+            }
+            "TryEnterCriticalSection" => {
+                let [cs] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let ret = this.TryEnterCriticalSection(cs)?;
+                this.write_scalar(ret, dest)?;
+            }
+            "LeaveCriticalSection" => {
+                let [cs] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.LeaveCriticalSection(cs)?;
+            }
+            "DeleteCriticalSection" => {
+                let [cs] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.DeleteCriticalSection(cs)?;
+            }
+            "InitializeConditionVariable" => {
+                let [condvar] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.InitializeConditionVariable(condvar)?;
+            }
+            "SleepConditionVariableSRW" => {
+                let [condvar, lock, timeout, flags] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.SleepConditionVariableSRW(condvar, lock, timeout, flags, dest)?;
+            }
+            "SleepConditionVariableCS" => {
+                let [condvar, cs, timeout] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.SleepConditionVariableCS(condvar, cs, timeout, dest)?;
+            }
+            "WakeConditionVariable" => {
+                let [condvar] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.WakeConditionVariable(condvar)?;
+            }
+            "WakeAllConditionVariable" => {
+                let [condvar] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.WakeAllConditionVariable(condvar)?;
+            }
             "WaitOnAddress" => {
                 let [ptr_op, compare_op, size_op, timeout_op] =
                     this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
@@ -568,6 +827,47 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 }
             }
 
+            // Child processes
+            "CreateProcessW" => {
+                let [
+                    application_name,
+                    command_line,
+                    process_attributes,
+                    thread_attributes,
+                    inherit_handles,
+                    creation_flags,
+                    environment,
+                    current_directory,
+                    startup_info,
+                    process_information,
+                ] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.CreateProcessW(
+                    application_name,
+                    command_line,
+                    process_attributes,
+                    thread_attributes,
+                    inherit_handles,
+                    creation_flags,
+                    environment,
+                    current_directory,
+                    startup_info,
+                    process_information,
+                )?;
+                this.write_scalar(res, dest)?;
+            }
+            "GetExitCodeProcess" => {
+                let [process, exit_code] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.GetExitCodeProcess(process, exit_code)?;
+                this.write_scalar(res, dest)?;
+            }
+            "TerminateProcess" => {
+                let [process, exit_code] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.TerminateProcess(process, exit_code)?;
+                this.write_scalar(res, dest)?;
+            }
+
             // Threading
             "CreateThread" => {
                 let [security, stacksize, start, arg, flags, thread] =
@@ -656,6 +956,231 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 let tid = this.get_tid(thread);
                 this.write_scalar(Scalar::from_u32(tid), dest)?;
             }
+            "QueueUserAPC" => {
+                let [func, thread, data] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.QueueUserAPC(func, thread, data)?;
+                this.write_scalar(res, dest)?;
+            }
+            "SleepEx" => {
+                let [timeout, alertable] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.SleepEx(timeout, alertable, dest)?;
+            }
+
+            // Thread pool
+            "CreateThreadpoolWork" => {
+                let [callback, context, environment] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let work = this.CreateThreadpoolWork(callback, context, environment)?;
+                this.write_pointer(work, dest)?;
+            }
+            "SubmitThreadpoolWork" => {
+                let [work] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.SubmitThreadpoolWork(work)?;
+            }
+            "WaitForThreadpoolWorkCallbacks" => {
+                let [work, cancel] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.WaitForThreadpoolWorkCallbacks(work, cancel)?;
+            }
+            "CloseThreadpoolWork" => {
+                let [work] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.CloseThreadpoolWork(work)?;
+            }
+            "CreateThreadpoolTimer" => {
+                let [callback, context, environment] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let timer = this.CreateThreadpoolTimer(callback, context, environment)?;
+                this.write_pointer(timer, dest)?;
+            }
+            "SetThreadpoolTimer" => {
+                let [timer, due_time, period, window] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.SetThreadpoolTimer(timer, due_time, period, window)?;
+            }
+            "IsThreadpoolTimerSet" => {
+                let [timer] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.IsThreadpoolTimerSet(timer)?;
+                this.write_scalar(res, dest)?;
+            }
+            "WaitForThreadpoolTimerCallbacks" => {
+                let [timer, cancel] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.WaitForThreadpoolTimerCallbacks(timer, cancel)?;
+            }
+            "CloseThreadpoolTimer" => {
+                let [timer] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.CloseThreadpoolTimer(timer)?;
+            }
+
+            // Sockets
+            "WSAStartup" => {
+                let [version, data] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.WSAStartup(version, data)?;
+                this.write_scalar(res, dest)?;
+            }
+            "WSACleanup" => {
+                let [] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.WSACleanup()?;
+                this.write_scalar(res, dest)?;
+            }
+            "WSAGetLastError" => {
+                let [] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let last_error = this.get_last_error()?;
+                this.write_scalar(last_error, dest)?;
+            }
+            "WSASetLastError" => {
+                let [error] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let error = this.read_scalar(error)?;
+                this.set_last_error(error)?;
+            }
+            "socket" => {
+                let [af, ty, protocol] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let socket = this.socket(af, ty, protocol)?;
+                this.write_scalar(socket, dest)?;
+            }
+            "WSASocketW" => {
+                let [af, ty, protocol, protocol_info, group, flags] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let socket = this.WSASocketW(af, ty, protocol, protocol_info, group, flags)?;
+                this.write_scalar(socket, dest)?;
+            }
+            "WSADuplicateSocketW" => {
+                let [socket, process_id, protocol_info] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.WSADuplicateSocketW(socket, process_id, protocol_info)?;
+                this.write_scalar(res, dest)?;
+            }
+            "closesocket" => {
+                let [socket] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.closesocket(socket)?;
+                this.write_scalar(res, dest)?;
+            }
+            "bind" => {
+                let [socket, addr, addr_len] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.bind(socket, addr, addr_len)?;
+                this.write_scalar(res, dest)?;
+            }
+            "listen" => {
+                let [socket, backlog] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.listen(socket, backlog)?;
+                this.write_scalar(res, dest)?;
+            }
+            "connect" => {
+                let [socket, addr, addr_len] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.connect(socket, addr, addr_len)?;
+                this.write_scalar(res, dest)?;
+            }
+            "accept" => {
+                let [socket, addr, addr_len] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.accept(socket, addr, addr_len, dest)?;
+            }
+            "send" => {
+                let [socket, buf, len, flags] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.send(socket, buf, len, flags)?;
+                this.write_scalar(res, dest)?;
+            }
+            "sendto" => {
+                let [socket, buf, len, flags, to, to_len] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.sendto(socket, buf, len, flags, to, to_len)?;
+                this.write_scalar(res, dest)?;
+            }
+            "WSASend" => {
+                let [socket, buffers, buffer_count, bytes_sent, flags, overlapped, completion] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.WSASend(
+                    socket,
+                    buffers,
+                    buffer_count,
+                    bytes_sent,
+                    flags,
+                    overlapped,
+                    completion,
+                )?;
+                this.write_scalar(res, dest)?;
+            }
+            "recv" => {
+                let [socket, buf, len, flags] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.recv(socket, buf, len, flags, dest)?;
+            }
+            "recvfrom" => {
+                let [socket, buf, len, flags, from, from_len] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.recvfrom(socket, buf, len, flags, from, from_len, dest)?;
+            }
+            "WSARecv" => {
+                let [socket, buffers, buffer_count, bytes_received, flags, overlapped, completion] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.WSARecv(
+                    socket,
+                    buffers,
+                    buffer_count,
+                    bytes_received,
+                    flags,
+                    overlapped,
+                    completion,
+                    dest,
+                )?;
+            }
+            "shutdown" => {
+                let [socket, how] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.shutdown(socket, how)?;
+                this.write_scalar(res, dest)?;
+            }
+            "getsockname" => {
+                let [socket, addr, addr_len] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.getsockname(socket, addr, addr_len)?;
+                this.write_scalar(res, dest)?;
+            }
+            "getpeername" => {
+                let [socket, addr, addr_len] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.getpeername(socket, addr, addr_len)?;
+                this.write_scalar(res, dest)?;
+            }
+            "setsockopt" => {
+                let [socket, level, name, value, len] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.setsockopt(socket, level, name, value, len)?;
+                this.write_scalar(res, dest)?;
+            }
+            "getsockopt" => {
+                let [socket, level, name, value, len] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.getsockopt(socket, level, name, value, len)?;
+                this.write_scalar(res, dest)?;
+            }
+            "ioctlsocket" => {
+                let [socket, cmd, arg] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.ioctlsocket(socket, cmd, arg)?;
+                this.write_scalar(res, dest)?;
+            }
+            "select" => {
+                let [nfds, read_set, write_set, except_set, timeout] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.select(nfds, read_set, write_set, except_set, timeout, dest)?;
+            }
+            "getaddrinfo" => {
+                let [node, service, hints, result] =
+                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                let res = this.getaddrinfo(node, service, hints, result)?;
+                this.write_scalar(res, dest)?;
+            }
+            "freeaddrinfo" => {
+                let [info] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
+                this.freeaddrinfo(info)?;
+            }
 
             // Miscellaneous
             "ExitProcess" => {
@@ -859,14 +1384,6 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 // Pretend these does not exist / nothing happened, by returning zero.
                 this.write_null(dest)?;
             }
-            "GetConsoleMode" if this.frame_in_std() => {
-                let [console, mode] =
-                    this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
-                this.read_target_isize(console)?;
-                this.deref_pointer_as(mode, this.machine.layouts.u32)?;
-                // Indicate an error.
-                this.write_null(dest)?;
-            }
             "GetFileType" if this.frame_in_std() => {
                 #[allow(non_snake_case)]
                 let [_hFile] = this.check_shim_sig_lenient(abi, sys_conv, link_name, args)?;
@@ -1,4 +1,4 @@
-use std::fs::{Metadata, OpenOptions};
+use std::fs::{Metadata, OpenOptions, TryLockError};
 use std::io;
 use std::io::SeekFrom;
 use std::path::PathBuf;
@@ -7,8 +7,12 @@ use std::time::SystemTime;
 use bitflags::bitflags;
 use rustc_target::spec::Os;
 
-use crate::shims::files::{FdId, FileDescription, FileHandle};
+use crate::shims::file_lock::{EvalContextExt as _, FileId, FileLock, LockKind, LockOwner};
+use crate::shims::files::{
+    FdId, FileDescription, FileDescriptionRef, FileHandle, WeakFileDescriptionRef,
+};
 use crate::shims::windows::handle::{EvalContextExt as _, Handle};
+use crate::shims::windows::pipe::{EvalContextExt as _, is_pipe_name};
 use crate::*;
 
 #[derive(Debug)]
@@ -168,6 +172,26 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         let this = self.eval_context_mut();
         this.assert_target_os(Os::Windows, "CreateFileW");
+
+        // Named pipes live entirely inside the interpreter, so they work even with isolation.
+        let raw_name = this.read_wide_str(this.read_pointer(file_name)?)?;
+        let raw_name = String::from_utf16_lossy(&raw_name);
+        if is_pipe_name(&raw_name) {
+            let desired_access = this.read_scalar(desired_access)?.to_u32()?;
+            let read = desired_access & this.eval_windows_u32("c", "GENERIC_READ") != 0;
+            let write = desired_access & this.eval_windows_u32("c", "GENERIC_WRITE") != 0;
+            return match this.open_named_pipe_client(&raw_name, read, write)? {
+                Ok(handle) => {
+                    this.set_last_error(IoError::Raw(Scalar::from_i32(0)))?;
+                    interp_ok(handle)
+                }
+                Err(e) => {
+                    this.set_last_error(e)?;
+                    interp_ok(Handle::Invalid)
+                }
+            };
+        }
+
         this.check_no_isolation("`CreateFileW`")?;
 
         // This function appears to always set the error to 0. This is important for some flag
@@ -659,6 +683,291 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
             }
         }
     }
+
+    fn LockFileEx(
+        &mut self,
+        file: &OpTy<'tcx>,       // HANDLE
+        flags: &OpTy<'tcx>,      // DWORD
+        reserved: &OpTy<'tcx>,   // DWORD
+        len_low: &OpTy<'tcx>,    // DWORD
+        len_high: &OpTy<'tcx>,   // DWORD
+        overlapped: &OpTy<'tcx>, // LPOVERLAPPED
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+        let file = this.read_handle(file, "LockFileEx")?;
+        let flags = this.read_scalar(flags)?.to_u32()?;
+        let reserved = this.read_scalar(reserved)?.to_u32()?;
+        let len_low = this.read_scalar(len_low)?.to_u32()?;
+        let len_high = this.read_scalar(len_high)?.to_u32()?;
+        let overlapped = this.deref_pointer_as(overlapped, this.windows_ty_layout("OVERLAPPED"))?;
+
+        let exclusive_lock = this.eval_windows_u32("c", "LOCKFILE_EXCLUSIVE_LOCK");
+        let fail_immediately = this.eval_windows_u32("c", "LOCKFILE_FAIL_IMMEDIATELY");
+        if flags & !(exclusive_lock | fail_immediately) != 0 {
+            throw_unsup_format!("`LockFileEx`: unsupported flags {flags:#x}");
+        }
+        if reserved != 0 {
+            this.set_last_error(IoError::WindowsError("ERROR_INVALID_PARAMETER"))?;
+            return this.write_scalar(this.eval_windows("c", "FALSE"), dest);
+        }
+
+        let offset = this.read_overlapped_offset(&overlapped)?;
+        this.lock_file_range(
+            file,
+            "LockFileEx",
+            offset,
+            u64_from_parts(len_low, len_high),
+            flags & exclusive_lock != 0,
+            flags & fail_immediately == 0,
+            dest,
+        )
+    }
+
+    fn LockFile(
+        &mut self,
+        file: &OpTy<'tcx>,        // HANDLE
+        offset_low: &OpTy<'tcx>,  // DWORD
+        offset_high: &OpTy<'tcx>, // DWORD
+        len_low: &OpTy<'tcx>,     // DWORD
+        len_high: &OpTy<'tcx>,    // DWORD
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+        let file = this.read_handle(file, "LockFile")?;
+        let offset_low = this.read_scalar(offset_low)?.to_u32()?;
+        let offset_high = this.read_scalar(offset_high)?.to_u32()?;
+        let len_low = this.read_scalar(len_low)?.to_u32()?;
+        let len_high = this.read_scalar(len_high)?.to_u32()?;
+
+        // `LockFile` always takes an exclusive lock and never waits.
+        this.lock_file_range(
+            file,
+            "LockFile",
+            u64_from_parts(offset_low, offset_high),
+            u64_from_parts(len_low, len_high),
+            /* exclusive */ true,
+            /* blocking */ false,
+            dest,
+        )
+    }
+
+    fn UnlockFileEx(
+        &mut self,
+        file: &OpTy<'tcx>,       // HANDLE
+        reserved: &OpTy<'tcx>,   // DWORD
+        len_low: &OpTy<'tcx>,    // DWORD
+        len_high: &OpTy<'tcx>,   // DWORD
+        overlapped: &OpTy<'tcx>, // LPOVERLAPPED
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+        let file = this.read_handle(file, "UnlockFileEx")?;
+        let reserved = this.read_scalar(reserved)?.to_u32()?;
+        let len_low = this.read_scalar(len_low)?.to_u32()?;
+        let len_high = this.read_scalar(len_high)?.to_u32()?;
+        let overlapped = this.deref_pointer_as(overlapped, this.windows_ty_layout("OVERLAPPED"))?;
+
+        if reserved != 0 {
+            this.set_last_error(IoError::WindowsError("ERROR_INVALID_PARAMETER"))?;
+            return interp_ok(this.eval_windows("c", "FALSE"));
+        }
+
+        let offset = this.read_overlapped_offset(&overlapped)?;
+        this.unlock_file_range(file, "UnlockFileEx", offset, u64_from_parts(len_low, len_high))
+    }
+
+    fn UnlockFile(
+        &mut self,
+        file: &OpTy<'tcx>,        // HANDLE
+        offset_low: &OpTy<'tcx>,  // DWORD
+        offset_high: &OpTy<'tcx>, // DWORD
+        len_low: &OpTy<'tcx>,     // DWORD
+        len_high: &OpTy<'tcx>,    // DWORD
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+        let file = this.read_handle(file, "UnlockFile")?;
+        let offset_low = this.read_scalar(offset_low)?.to_u32()?;
+        let offset_high = this.read_scalar(offset_high)?.to_u32()?;
+        let len_low = this.read_scalar(len_low)?.to_u32()?;
+        let len_high = this.read_scalar(len_high)?.to_u32()?;
+
+        this.unlock_file_range(
+            file,
+            "UnlockFile",
+            u64_from_parts(offset_low, offset_high),
+            u64_from_parts(len_low, len_high),
+        )
+    }
+}
+
+impl<'tcx> EvalContextPrivExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextPrivExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Reads the file offset stored in an `OVERLAPPED` structure.
+    fn read_overlapped_offset(&self, overlapped: &MPlaceTy<'tcx>) -> InterpResult<'tcx, u64> {
+        let this = self.eval_context_ref();
+        let offset = this.project_field_named(overlapped, "Anonymous")?;
+        let offset = this.project_field_named(&offset, "Anonymous")?;
+        let low = this.read_scalar(&this.project_field_named(&offset, "Offset")?)?.to_u32()?;
+        let high = this.read_scalar(&this.project_field_named(&offset, "OffsetHigh")?)?.to_u32()?;
+        interp_ok(u64_from_parts(low, high))
+    }
+
+    /// Finds the file and the lock owner behind a handle passed to a file locking function.
+    fn file_lock_target(
+        &mut self,
+        file: Handle,
+        function_name: &str,
+    ) -> InterpResult<'tcx, Result<(FileDescriptionRef<FileHandle>, FileId), IoError>> {
+        let this = self.eval_context_mut();
+        let Handle::File(fd_num) = file else { this.invalid_handle(function_name)? };
+        let Some(desc) = this.machine.fds.get(fd_num) else { this.invalid_handle(function_name)? };
+        let Some(desc) = desc.downcast::<FileHandle>() else {
+            throw_unsup_format!("`{function_name}` is only supported on file handles");
+        };
+        interp_ok(FileId::of(&desc.file).map(|file_id| (desc, file_id)).map_err(IoError::HostError))
+    }
+
+    /// Makes the host lock of `desc` match the `LockFileEx` locks it holds in the interpreter.
+    ///
+    /// Like for `flock`, the host lock covers the whole file: it is shared or exclusive depending
+    /// on the strongest lock the handle holds, and released once the handle holds none. Byte ranges
+    /// are only tracked by the interpreter, so when another handle of this program already locked
+    /// the file, a conflict on the host is not a conflict with another process.
+    fn sync_host_file_lock(
+        &self,
+        desc: &FileDescriptionRef<FileHandle>,
+        file_id: FileId,
+    ) -> Result<(), TryLockError> {
+        let this = self.eval_context_ref();
+        let owner = LockOwner::Description(desc.id());
+        // Drop any lock we already hold first: on some hosts, locks do not convert but stack up.
+        let _ = desc.file.unlock();
+        let Some(exclusive) = this.machine.file_locks.held_by(file_id, LockKind::Windows, owner)
+        else {
+            return Ok(());
+        };
+        let result = if exclusive { desc.file.try_lock() } else { desc.file.try_lock_shared() };
+        match result {
+            Err(TryLockError::WouldBlock)
+                if this.machine.file_locks.held_by_others(file_id, LockKind::Windows, owner) =>
+                Ok(()),
+            result => result,
+        }
+    }
+
+    fn lock_file_range(
+        &mut self,
+        file: Handle,
+        function_name: &str,
+        offset: u64,
+        len: u64,
+        exclusive: bool,
+        blocking: bool,
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let (desc, file_id) = match this.file_lock_target(file, function_name)? {
+            Ok(target) => target,
+            Err(err) => {
+                this.set_last_error(err)?;
+                return this.write_scalar(this.eval_windows("c", "FALSE"), dest);
+            }
+        };
+
+        // The lock is tracked by the interpreter so that interpreted threads can wait for each
+        // other. Once we have it, we also take it on the host so that other processes see it.
+        let owner = LockOwner::Description(desc.id());
+        let lock = FileLock { owner, start: offset, end: offset.saturating_add(len), exclusive };
+        let desc = FileDescriptionRef::downgrade(&desc);
+        this.acquire_file_lock(
+            file_id,
+            LockKind::Windows,
+            lock,
+            blocking,
+            callback!(
+                @capture<'tcx> {
+                    desc: WeakFileDescriptionRef<FileHandle>,
+                    file_id: FileId,
+                    lock: FileLock,
+                    blocking: bool,
+                    dest: MPlaceTy<'tcx>,
+                }
+                |this, result: Result<(), IoError>| {
+                    let err = match result {
+                        Ok(()) => {
+                            let Some(desc) = desc.upgrade() else {
+                                // The handle got closed while we were waiting for the lock.
+                                this.set_last_error(IoError::WindowsError("ERROR_INVALID_HANDLE"))?;
+                                return this.write_scalar(this.eval_windows("c", "FALSE"), &dest);
+                            };
+                            let err = match this.sync_host_file_lock(&desc, file_id) {
+                                Ok(()) =>
+                                    return this.write_scalar(this.eval_windows("c", "TRUE"), &dest),
+                                Err(TryLockError::Error(err)) => IoError::HostError(err),
+                                Err(TryLockError::WouldBlock) if !blocking =>
+                                    IoError::WindowsError("ERROR_LOCK_VIOLATION"),
+                                Err(TryLockError::WouldBlock) =>
+                                    throw_unsup_format!(
+                                        "blocking `LockFileEx` on a file that is locked by another process is not supported"
+                                    ),
+                            };
+                            // We do not hold the lock on the host, so do not keep it here either.
+                            this.release_file_lock(
+                                file_id,
+                                LockKind::Windows,
+                                lock.owner,
+                                lock.start,
+                                lock.end,
+                            )?;
+                            let _ = this.sync_host_file_lock(&desc, file_id);
+                            err
+                        }
+                        // The only way to fail is that the lock is held by someone else.
+                        Err(_) => IoError::WindowsError("ERROR_LOCK_VIOLATION"),
+                    };
+                    this.set_last_error(err)?;
+                    this.write_scalar(this.eval_windows("c", "FALSE"), &dest)
+                }
+            ),
+        )
+    }
+
+    fn unlock_file_range(
+        &mut self,
+        file: Handle,
+        function_name: &str,
+        offset: u64,
+        len: u64,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+        let (desc, file_id) = match this.file_lock_target(file, function_name)? {
+            Ok(target) => target,
+            Err(err) => {
+                this.set_last_error(err)?;
+                return interp_ok(this.eval_windows("c", "FALSE"));
+            }
+        };
+
+        let owner = LockOwner::Description(desc.id());
+        let end = offset.saturating_add(len);
+        if this.release_file_lock(file_id, LockKind::Windows, owner, offset, end)? {
+            // Releasing never makes the host lock stronger, so this can only fail if another
+            // process grabbed the file in between; the handle then simply stops holding it.
+            let _ = this.sync_host_file_lock(&desc, file_id);
+            interp_ok(this.eval_windows("c", "TRUE"))
+        } else {
+            this.set_last_error(IoError::WindowsError("ERROR_NOT_LOCKED"))?;
+            interp_ok(this.eval_windows("c", "FALSE"))
+        }
+    }
+}
+
+fn u64_from_parts(low: u32, high: u32) -> u64 {
+    u64::from(high).strict_shl(32) | u64::from(low)
 }
 
 /// Windows FILETIME is measured in 100-nanosecs since 1601
@@ -19,6 +19,8 @@ pub enum Handle {
     Pseudo(PseudoHandle),
     Thread(ThreadId),
     File(FdNum),
+    /// A child process, identified by its pid.
+    Process(i32),
     Invalid,
 }
 
@@ -55,6 +57,7 @@ impl Handle {
     const PSEUDO_DISCRIMINANT: u32 = 1;
     const THREAD_DISCRIMINANT: u32 = 2;
     const FILE_DISCRIMINANT: u32 = 3;
+    const PROCESS_DISCRIMINANT: u32 = 4;
     // Chosen to ensure Handle::Invalid encodes to -1. Update this value if there are ever more than
     // 8 discriminants.
     const INVALID_DISCRIMINANT: u32 = 7;
@@ -65,6 +68,7 @@ impl Handle {
             Self::Pseudo(_) => Self::PSEUDO_DISCRIMINANT,
             Self::Thread(_) => Self::THREAD_DISCRIMINANT,
             Self::File(_) => Self::FILE_DISCRIMINANT,
+            Self::Process(_) => Self::PROCESS_DISCRIMINANT,
             Self::Invalid => Self::INVALID_DISCRIMINANT,
         }
     }
@@ -75,6 +79,7 @@ impl Handle {
             Self::Pseudo(pseudo_handle) => pseudo_handle.value(),
             Self::Thread(thread) => thread.to_u32(),
             Self::File(fd) => fd.cast_unsigned(),
+            Self::Process(pid) => pid.cast_unsigned(),
             // INVALID_HANDLE_VALUE is -1. This fact is explicitly declared or implied in several
             // pages of Windows documentation.
             // 1: https://learn.microsoft.com/en-us/dotnet/api/microsoft.win32.safehandles.safefilehandle?view=net-9.0
@@ -132,11 +137,19 @@ impl Handle {
                 assert_eq!(size_of_val(&data), size_of::<FdNum>());
                 Some(Self::File(data.cast_signed()))
             }
+            Self::PROCESS_DISCRIMINANT => Some(Self::Process(data.cast_signed())),
             Self::INVALID_DISCRIMINANT => Some(Self::Invalid),
             _ => None,
         }
     }
 
+    /// Returns the handle for the child process `pid`, unless the pid is too large to be stored in
+    /// a handle.
+    pub fn process(pid: i32) -> Option<Self> {
+        let data_size = u32::BITS.strict_sub(Self::packed_disc_size());
+        (pid.cast_unsigned() < 2u32.pow(data_size)).then_some(Self::Process(pid))
+    }
+
     /// see docs for `to_packed`
     fn from_packed(handle: u32) -> Option<Self> {
         let disc_size = Self::packed_disc_size();
@@ -191,6 +204,10 @@ impl Handle {
                     Err(e) => interp_ok(Err(HandleError::ThreadNotFound(e))),
                 }
             }
+            // We never forget about child processes on Windows, so this only fails for handles
+            // that were never valid.
+            Some(Self::Process(pid)) if !cx.machine.processes.contains(pid) =>
+                interp_ok(Err(HandleError::InvalidHandle)),
             Some(handle) => interp_ok(Ok(handle)),
             None => interp_ok(Err(HandleError::InvalidHandle)),
         }
@@ -222,6 +239,15 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         }
     }
 
+    /// Like `read_handle`, but returns `None` for invalid handles instead of aborting execution.
+    /// This is for APIs that report invalid handles as a regular error, like Winsock does with
+    /// `WSAENOTSOCK`.
+    fn read_handle_lenient(&self, handle: &OpTy<'tcx>) -> InterpResult<'tcx, Option<Handle>> {
+        let this = self.eval_context_ref();
+        let handle = this.read_scalar(handle)?;
+        interp_ok(Handle::try_from_scalar(handle, this)?.ok())
+    }
+
     fn invalid_handle(&mut self, function_name: &str) -> InterpResult<'tcx, !> {
         throw_machine_stop!(TerminationInfo::Abort(format!(
             "invalid handle passed to `{function_name}`"
@@ -271,8 +297,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         let target_handle_ptr = this.read_pointer(target_handle)?;
         // Since we only support DUPLICATE_SAME_ACCESS, this value is ignored, but should be valid
         let _ = this.read_scalar(desired_access)?.to_u32()?;
-        // We don't support the CreateProcess API, so inheritable or not means nothing.
-        // If we ever add CreateProcess support, this will need to be implemented.
+        // Child processes only get the standard streams that are passed to `CreateProcessW`
+        // explicitly, so inheritable or not means nothing.
         let _ = this.read_scalar(inherit)?;
         let options = this.read_scalar(options)?;
 
@@ -315,6 +341,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 );
             }
             Handle::Pseudo(pseudo) => Handle::Pseudo(pseudo),
+            // Closing a process handle does nothing, so all handles can be the same.
+            Handle::Process(pid) => Handle::Process(pid),
             Handle::Null | Handle::Invalid => this.invalid_handle("DuplicateHandle")?,
         };
 
@@ -345,6 +373,8 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
                 } else {
                     this.invalid_handle("CloseHandle")?
                 },
+            // The child process keeps running, and its exit code stays available.
+            Handle::Process(_) => this.eval_windows("c", "TRUE"),
             _ => this.invalid_handle("CloseHandle")?,
         };
 
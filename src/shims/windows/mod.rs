@@ -1,14 +1,24 @@
 pub mod foreign_items;
 
+mod console;
 mod env;
 mod fs;
 mod handle;
+mod pipe;
+mod process;
 mod sync;
 mod thread;
+mod threadpool;
+mod winsock;
 
 // All the Windows-specific extension traits
+pub use self::console::{EvalContextExt as _, WindowsConsole};
 pub use self::env::{EvalContextExt as _, WindowsEnvVars};
 pub use self::fs::EvalContextExt as _;
 pub use self::handle::EvalContextExt as _;
+pub use self::pipe::EvalContextExt as _;
+pub use self::process::EvalContextExt as _;
 pub use self::sync::EvalContextExt as _;
 pub use self::thread::EvalContextExt as _;
+pub use self::threadpool::{EvalContextExt as _, WindowsThreadPool};
+pub use self::winsock::EvalContextExt as _;
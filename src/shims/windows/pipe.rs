@@ -0,0 +1,454 @@
+//! Anonymous and named pipes. Data transfer is entirely implemented inside Miri, reusing the
+//! unnamed sockets that back `pipe` and `socketpair` on Unix targets.
+
+use std::cell::RefCell;
+use std::io;
+
+use crate::shims::files::{
+    DynFileDescriptionRef, FdId, FileDescription, FileDescriptionRef, WeakFileDescriptionRef,
+};
+use crate::shims::unix::{anonsocket_peek, new_anonsocket_pair};
+use crate::shims::windows::handle::{EvalContextExt as _, Handle};
+use crate::*;
+
+// These are not all available in std's Windows bindings, so we define them ourselves.
+const PIPE_ACCESS_INBOUND: u32 = 0x1;
+const PIPE_ACCESS_OUTBOUND: u32 = 0x2;
+const PIPE_ACCESS_DUPLEX: u32 = 0x3;
+const FILE_FLAG_FIRST_PIPE_INSTANCE: u32 = 0x0008_0000;
+const FILE_FLAG_WRITE_THROUGH: u32 = 0x8000_0000;
+const PIPE_REJECT_REMOTE_CLIENTS: u32 = 0x8;
+const PIPE_UNLIMITED_INSTANCES: u32 = 255;
+
+/// The prefix of all named pipe paths.
+const PIPE_PREFIX: &str = r"\\.\pipe\";
+
+/// Returns whether `name` refers to a named pipe.
+pub fn is_pipe_name(name: &str) -> bool {
+    name.get(..PIPE_PREFIX.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(PIPE_PREFIX))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PipeDirection {
+    /// Data flows from the client to the server.
+    Inbound,
+    /// Data flows from the server to the client.
+    Outbound,
+    Duplex,
+}
+
+#[derive(Debug)]
+enum NamedPipeState {
+    /// Waiting for a client to connect.
+    Listening,
+    /// A client is connected, and this is the server's end of the connection. `acknowledged`
+    /// records whether `ConnectNamedPipe` has reported the connection yet.
+    Connected { end: DynFileDescriptionRef, acknowledged: bool },
+}
+
+/// The server end of one instance of a named pipe.
+#[derive(Debug)]
+struct NamedPipe {
+    /// The name of the pipe, in lowercase since pipe names are case-insensitive.
+    name: String,
+    direction: PipeDirection,
+    max_instances: u32,
+    state: RefCell<NamedPipeState>,
+    /// Threads blocked in `ConnectNamedPipe`.
+    blocked_connect_tid: RefCell<Vec<ThreadId>>,
+}
+
+impl NamedPipe {
+    fn connection(&self) -> Option<DynFileDescriptionRef> {
+        match &*self.state.borrow() {
+            NamedPipeState::Listening => None,
+            NamedPipeState::Connected { end, .. } => Some(end.clone()),
+        }
+    }
+}
+
+impl FileDescription for NamedPipe {
+    fn name(&self) -> &'static str {
+        "named pipe"
+    }
+
+    fn read<'tcx>(
+        self: FileDescriptionRef<Self>,
+        communicate_allowed: bool,
+        ptr: Pointer,
+        len: usize,
+        ecx: &mut MiriInterpCx<'tcx>,
+        finish: DynMachineCallback<'tcx, Result<usize, IoError>>,
+    ) -> InterpResult<'tcx> {
+        let Some(end) = self.connection() else {
+            return finish.call(ecx, Err(IoError::WindowsError("ERROR_PIPE_LISTENING")));
+        };
+        end.read(communicate_allowed, ptr, len, ecx, finish)
+    }
+
+    fn write<'tcx>(
+        self: FileDescriptionRef<Self>,
+        communicate_allowed: bool,
+        ptr: Pointer,
+        len: usize,
+        ecx: &mut MiriInterpCx<'tcx>,
+        finish: DynMachineCallback<'tcx, Result<usize, IoError>>,
+    ) -> InterpResult<'tcx> {
+        let Some(end) = self.connection() else {
+            return finish.call(ecx, Err(IoError::WindowsError("ERROR_PIPE_LISTENING")));
+        };
+        end.write(communicate_allowed, ptr, len, ecx, finish)
+    }
+
+    fn destroy<'tcx>(
+        self,
+        _self_id: FdId,
+        communicate_allowed: bool,
+        ecx: &mut MiriInterpCx<'tcx>,
+    ) -> InterpResult<'tcx, io::Result<()>> {
+        // Threads waiting for a client will notice that the pipe is gone.
+        for thread in self.blocked_connect_tid.take() {
+            ecx.unblock_thread(thread, BlockReason::NamedPipe)?;
+        }
+        match self.state.into_inner() {
+            NamedPipeState::Listening => interp_ok(Ok(())),
+            NamedPipeState::Connected { end, .. } => end.close_ref(communicate_allowed, ecx),
+        }
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+#[allow(non_snake_case)]
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    fn CreatePipe(
+        &mut self,
+        read_pipe: &OpTy<'tcx>,       // PHANDLE
+        write_pipe: &OpTy<'tcx>,      // PHANDLE
+        pipe_attributes: &OpTy<'tcx>, // LPSECURITY_ATTRIBUTES
+        size: &OpTy<'tcx>,            // DWORD
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+        let read_pipe = this.deref_pointer_as(read_pipe, this.machine.layouts.usize)?;
+        let write_pipe = this.deref_pointer_as(write_pipe, this.machine.layouts.usize)?;
+        // We do not support `CreateProcess`, so whether the handles are inheritable does not
+        // matter. The buffer size is only a hint.
+        let _ = this.read_pointer(pipe_attributes)?;
+        let _ = this.read_scalar(size)?.to_u32()?;
+
+        let (read_end, write_end) = new_anonsocket_pair(
+            &mut this.machine.fds,
+            /* pipe */ true,
+            /* is_nonblock */ false,
+        );
+        let read_end = Handle::File(this.machine.fds.insert(read_end));
+        let write_end = Handle::File(this.machine.fds.insert(write_end));
+        this.write_scalar(read_end.to_scalar(this), &read_pipe)?;
+        this.write_scalar(write_end.to_scalar(this), &write_pipe)?;
+
+        interp_ok(this.eval_windows("c", "TRUE"))
+    }
+
+    fn CreateNamedPipeW(
+        &mut self,
+        name: &OpTy<'tcx>,                // LPCWSTR
+        open_mode: &OpTy<'tcx>,           // DWORD
+        pipe_mode: &OpTy<'tcx>,           // DWORD
+        max_instances: &OpTy<'tcx>,       // DWORD
+        out_buffer_size: &OpTy<'tcx>,     // DWORD
+        in_buffer_size: &OpTy<'tcx>,      // DWORD
+        default_timeout: &OpTy<'tcx>,     // DWORD
+        security_attributes: &OpTy<'tcx>, // LPSECURITY_ATTRIBUTES
+    ) -> InterpResult<'tcx, Handle> {
+        // ^ Returns HANDLE
+        let this = self.eval_context_mut();
+        let name = this.read_wide_str(this.read_pointer(name)?)?;
+        let mut open_mode = this.read_scalar(open_mode)?.to_u32()?;
+        let pipe_mode = this.read_scalar(pipe_mode)?.to_u32()?;
+        let max_instances = this.read_scalar(max_instances)?.to_u32()?;
+        // The buffer sizes are only hints, and timeouts only matter for `WaitNamedPipe`.
+        let _ = this.read_scalar(out_buffer_size)?.to_u32()?;
+        let _ = this.read_scalar(in_buffer_size)?.to_u32()?;
+        let _ = this.read_scalar(default_timeout)?.to_u32()?;
+        let _ = this.read_pointer(security_attributes)?;
+
+        let name = String::from_utf16_lossy(&name).to_lowercase();
+        if !is_pipe_name(&name) || name.len() == PIPE_PREFIX.len() {
+            this.set_last_error(IoError::WindowsError("ERROR_INVALID_NAME"))?;
+            return interp_ok(Handle::Invalid);
+        }
+
+        let first_instance = open_mode & FILE_FLAG_FIRST_PIPE_INSTANCE != 0;
+        open_mode &= !(FILE_FLAG_FIRST_PIPE_INSTANCE | FILE_FLAG_WRITE_THROUGH);
+        let direction = match open_mode {
+            PIPE_ACCESS_INBOUND => PipeDirection::Inbound,
+            PIPE_ACCESS_OUTBOUND => PipeDirection::Outbound,
+            PIPE_ACCESS_DUPLEX => PipeDirection::Duplex,
+            _ => throw_unsup_format!("`CreateNamedPipeW`: unsupported open mode {open_mode:#x}"),
+        };
+        // Byte mode with blocking operations is the only supported mode; remote clients do not
+        // exist anyway.
+        if pipe_mode & !PIPE_REJECT_REMOTE_CLIENTS != 0 {
+            throw_unsup_format!("`CreateNamedPipeW`: unsupported pipe mode {pipe_mode:#x}");
+        }
+        if max_instances == 0 || max_instances > PIPE_UNLIMITED_INSTANCES {
+            this.set_last_error(IoError::WindowsError("ERROR_INVALID_PARAMETER"))?;
+            return interp_ok(Handle::Invalid);
+        }
+
+        let instances = this.named_pipe_instances(&name);
+        if let Some(instance) = instances.first() {
+            if first_instance {
+                this.set_last_error(IoError::WindowsError("ERROR_ACCESS_DENIED"))?;
+                return interp_ok(Handle::Invalid);
+            }
+            if instance.direction != direction {
+                this.set_last_error(IoError::WindowsError("ERROR_ACCESS_DENIED"))?;
+                return interp_ok(Handle::Invalid);
+            }
+            if u32::try_from(instances.len()).unwrap() >= instance.max_instances {
+                this.set_last_error(IoError::WindowsError("ERROR_PIPE_BUSY"))?;
+                return interp_ok(Handle::Invalid);
+            }
+        }
+
+        let fd_num = this.machine.fds.insert_new(NamedPipe {
+            name,
+            direction,
+            max_instances,
+            state: RefCell::new(NamedPipeState::Listening),
+            blocked_connect_tid: RefCell::new(Vec::new()),
+        });
+        interp_ok(Handle::File(fd_num))
+    }
+
+    fn ConnectNamedPipe(
+        &mut self,
+        pipe: &OpTy<'tcx>,       // HANDLE
+        overlapped: &OpTy<'tcx>, // LPOVERLAPPED
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+        let pipe = this.read_handle(pipe, "ConnectNamedPipe")?;
+        if !this.ptr_is_null(this.read_pointer(overlapped)?)? {
+            throw_unsup_format!("`ConnectNamedPipe`: overlapped operation is not supported");
+        }
+        let pipe = this.named_pipe_of(pipe, "ConnectNamedPipe")?;
+        this.connect_named_pipe(
+            FileDescriptionRef::downgrade(&pipe),
+            /* waited */ false,
+            dest.clone(),
+        )
+    }
+
+    fn DisconnectNamedPipe(
+        &mut self,
+        pipe: &OpTy<'tcx>, // HANDLE
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+        let pipe = this.read_handle(pipe, "DisconnectNamedPipe")?;
+        let pipe = this.named_pipe_of(pipe, "DisconnectNamedPipe")?;
+
+        // The client end of the connection becomes broken; any unread data is lost.
+        let state = pipe.state.replace(NamedPipeState::Listening);
+        if let NamedPipeState::Connected { end, .. } = state {
+            end.close_ref(this.machine.communicate(), this)?.ok();
+        }
+        interp_ok(this.eval_windows("c", "TRUE"))
+    }
+
+    fn PeekNamedPipe(
+        &mut self,
+        pipe: &OpTy<'tcx>,              // HANDLE
+        buffer: &OpTy<'tcx>,            // LPVOID
+        buffer_size: &OpTy<'tcx>,       // DWORD
+        bytes_read: &OpTy<'tcx>,        // LPDWORD
+        total_bytes_avail: &OpTy<'tcx>, // LPDWORD
+        bytes_left: &OpTy<'tcx>,        // LPDWORD
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+        let pipe = this.read_handle(pipe, "PeekNamedPipe")?;
+        let buffer = this.read_pointer(buffer)?;
+        let buffer_size = this.read_scalar(buffer_size)?.to_u32()?;
+        let bytes_read = this.read_pointer(bytes_read)?;
+        let total_bytes_avail = this.read_pointer(total_bytes_avail)?;
+        let bytes_left = this.read_pointer(bytes_left)?;
+
+        let Handle::File(fd_num) = pipe else { this.invalid_handle("PeekNamedPipe")? };
+        let Some(desc) = this.machine.fds.get(fd_num) else {
+            this.invalid_handle("PeekNamedPipe")?
+        };
+        // For named pipes, we peek at the server's end of the connection.
+        let desc = match desc.clone().downcast::<NamedPipe>() {
+            Some(pipe) =>
+                match pipe.connection() {
+                    Some(end) => end,
+                    None => {
+                        this.set_last_error(IoError::WindowsError("ERROR_PIPE_LISTENING"))?;
+                        return interp_ok(this.eval_windows("c", "FALSE"));
+                    }
+                },
+            None => desc,
+        };
+
+        // Without a buffer, we only report how much data is available.
+        let len = if this.ptr_is_null(buffer)? { 0 } else { buffer_size };
+        let result = anonsocket_peek(desc, buffer, len.try_into().unwrap(), this)?;
+        let Some(result) = result else {
+            throw_unsup_format!("`PeekNamedPipe` is only supported on pipes");
+        };
+        let (peeked, available) = match result {
+            Ok(sizes) => sizes,
+            Err(err) => {
+                this.set_last_error(err)?;
+                return interp_ok(this.eval_windows("c", "FALSE"));
+            }
+        };
+
+        // We only support byte mode, so there never is an unfinished message.
+        for (ptr, value) in [(bytes_read, peeked), (total_bytes_avail, available), (bytes_left, 0)]
+        {
+            if !this.ptr_is_null(ptr)? {
+                let place = this.ptr_to_mplace(ptr, this.machine.layouts.u32);
+                this.write_scalar(Scalar::from_u32(value.try_into().unwrap()), &place)?;
+            }
+        }
+        interp_ok(this.eval_windows("c", "TRUE"))
+    }
+
+    /// Connects to the named pipe `name` as a client, for `CreateFileW`. `read` and `write`
+    /// indicate the desired access.
+    fn open_named_pipe_client(
+        &mut self,
+        name: &str,
+        read: bool,
+        write: bool,
+    ) -> InterpResult<'tcx, Result<Handle, IoError>> {
+        let this = self.eval_context_mut();
+        let name = name.to_lowercase();
+
+        let instances = this.named_pipe_instances(&name);
+        if instances.is_empty() {
+            return interp_ok(Err(IoError::WindowsError("ERROR_FILE_NOT_FOUND")));
+        }
+        let Some(pipe) = instances
+            .into_iter()
+            .find(|pipe| matches!(*pipe.state.borrow(), NamedPipeState::Listening))
+        else {
+            return interp_ok(Err(IoError::WindowsError("ERROR_PIPE_BUSY")));
+        };
+        // The client can only use the pipe in the direction the server allows.
+        let allowed = match pipe.direction {
+            PipeDirection::Inbound => !read,
+            PipeDirection::Outbound => !write,
+            PipeDirection::Duplex => true,
+        };
+        if !allowed {
+            return interp_ok(Err(IoError::WindowsError("ERROR_ACCESS_DENIED")));
+        }
+
+        let fds = &mut this.machine.fds;
+        let (server_end, client_end) = match pipe.direction {
+            PipeDirection::Duplex => new_anonsocket_pair(fds, /* pipe */ false, false),
+            PipeDirection::Inbound => new_anonsocket_pair(fds, /* pipe */ true, false),
+            PipeDirection::Outbound => {
+                let (read_end, write_end) = new_anonsocket_pair(fds, /* pipe */ true, false);
+                (write_end, read_end)
+            }
+        };
+        pipe.state.replace(NamedPipeState::Connected { end: server_end, acknowledged: false });
+        let client = Handle::File(this.machine.fds.insert(client_end));
+
+        // Wake up the server if it is waiting for us.
+        for thread in pipe.blocked_connect_tid.take() {
+            this.unblock_thread(thread, BlockReason::NamedPipe)?;
+        }
+        interp_ok(Ok(client))
+    }
+}
+
+impl<'tcx> EvalContextPrivExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextPrivExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Returns all open instances of the named pipe `name`, which must be lowercase.
+    fn named_pipe_instances(&self, name: &str) -> Vec<FileDescriptionRef<NamedPipe>> {
+        let this = self.eval_context_ref();
+        let mut instances: Vec<FileDescriptionRef<NamedPipe>> = Vec::new();
+        for fd in this.machine.fds.fds.values() {
+            if let Some(pipe) = fd.clone().downcast::<NamedPipe>()
+                && pipe.name == name
+                && !instances.iter().any(|instance| instance.id() == pipe.id())
+            {
+                instances.push(pipe);
+            }
+        }
+        instances
+    }
+
+    fn named_pipe_of(
+        &mut self,
+        handle: Handle,
+        function_name: &str,
+    ) -> InterpResult<'tcx, FileDescriptionRef<NamedPipe>> {
+        let this = self.eval_context_mut();
+        let Handle::File(fd_num) = handle else { this.invalid_handle(function_name)? };
+        let Some(desc) = this.machine.fds.get(fd_num) else { this.invalid_handle(function_name)? };
+        let Some(pipe) = desc.downcast::<NamedPipe>() else {
+            throw_unsup_format!("`{function_name}` is only supported on named pipe handles");
+        };
+        interp_ok(pipe)
+    }
+
+    /// Waits until a client connects to `pipe`, then writes the result of `ConnectNamedPipe`.
+    /// `waited` indicates whether this thread was already blocked waiting for the client.
+    fn connect_named_pipe(
+        &mut self,
+        pipe: WeakFileDescriptionRef<NamedPipe>,
+        waited: bool,
+        dest: MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let Some(pipe_ref) = pipe.upgrade() else {
+            // The pipe was closed while we were waiting.
+            this.set_last_error(IoError::WindowsError("ERROR_BROKEN_PIPE"))?;
+            return this.write_scalar(this.eval_windows("c", "FALSE"), &dest);
+        };
+
+        let mut state = pipe_ref.state.borrow_mut();
+        match &mut *state {
+            NamedPipeState::Listening => {
+                drop(state);
+                pipe_ref.blocked_connect_tid.borrow_mut().push(this.active_thread());
+                this.block_thread(
+                    BlockReason::NamedPipe,
+                    None,
+                    callback!(
+                        @capture<'tcx> {
+                            pipe: WeakFileDescriptionRef<NamedPipe>,
+                            dest: MPlaceTy<'tcx>,
+                        }
+                        |this, unblock: UnblockKind| {
+                            assert_eq!(unblock, UnblockKind::Ready);
+                            this.connect_named_pipe(pipe, /* waited */ true, dest)
+                        }
+                    ),
+                );
+                interp_ok(())
+            }
+            NamedPipeState::Connected { acknowledged, .. } => {
+                // If the client connected before we started waiting, this is reported as an
+                // error, even though the pipe is now connected.
+                let reported = std::mem::replace(acknowledged, true);
+                drop(state);
+                if reported || !waited {
+                    this.set_last_error(IoError::WindowsError("ERROR_PIPE_CONNECTED"))?;
+                    this.write_scalar(this.eval_windows("c", "FALSE"), &dest)
+                } else {
+                    this.write_scalar(this.eval_windows("c", "TRUE"), &dest)
+                }
+            }
+        }
+    }
+}
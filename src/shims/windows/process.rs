@@ -0,0 +1,316 @@
+//! `CreateProcessW` and the functions that wait for and inspect the resulting child process. The
+//! child processes themselves are managed in `shims::process`.
+
+use std::ffi::OsString;
+use std::time::Duration;
+
+use rustc_abi::Size;
+
+use self::shims::windows::handle::{EvalContextExt as _, Handle};
+use crate::shims::process::{EvalContextExt as _, FileAction};
+use crate::*;
+
+/// The exit code `GetExitCodeProcess` reports for a process that is still running.
+const STILL_ACTIVE: u32 = 259;
+
+/// Splits a command line into its arguments, following the rules of the Microsoft C runtime
+/// (which is also what `std::env::args` uses).
+fn split_command_line(cmd: &str) -> Vec<OsString> {
+    let mut args = Vec::new();
+    let mut chars = cmd.chars().peekable();
+    // The program name ends at the first whitespace unless it is quoted, and backslashes are not
+    // special in it.
+    let mut program = String::new();
+    if chars.next_if_eq(&'"').is_some() {
+        program.extend(chars.by_ref().take_while(|&c| c != '"'));
+    } else {
+        while let Some(c) = chars.next_if(|&c| c != ' ' && c != '\t') {
+            program.push(c);
+        }
+    }
+    args.push(program.into());
+    loop {
+        while chars.next_if(|&c| c == ' ' || c == '\t').is_some() {}
+        if chars.peek().is_none() {
+            return args;
+        }
+        let mut arg = String::new();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            match c {
+                ' ' | '\t' if !quoted => break,
+                '\\' => {
+                    let mut backslashes = 1usize;
+                    while chars.next_if_eq(&'\\').is_some() {
+                        backslashes = backslashes.strict_add(1);
+                    }
+                    // Backslashes are only special in front of a quote.
+                    if chars.peek() == Some(&'"') {
+                        arg.extend(std::iter::repeat_n('\\', backslashes.strict_div(2)));
+                        if backslashes.strict_rem(2) == 1 {
+                            chars.next();
+                            arg.push('"');
+                        }
+                    } else {
+                        arg.extend(std::iter::repeat_n('\\', backslashes));
+                    }
+                }
+                '"' if quoted && chars.next_if_eq(&'"').is_some() => arg.push('"'),
+                '"' => quoted = !quoted,
+                c => arg.push(c),
+            }
+        }
+        args.push(arg.into());
+    }
+}
+
+impl<'tcx> EvalContextPrivExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextPrivExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Reads an environment block of `CreateProcessW`: `NAME=VALUE` strings, each terminated by a
+    /// null character, followed by another null character.
+    fn read_env_block(&self, ptr: Pointer) -> InterpResult<'tcx, Vec<OsString>> {
+        let this = self.eval_context_ref();
+        let mut vars = Vec::new();
+        let mut ptr = ptr;
+        loop {
+            let len = this.read_wide_str(ptr)?.len();
+            if len == 0 {
+                return interp_ok(vars);
+            }
+            vars.push(this.read_os_str_from_wide_str(ptr)?);
+            ptr = ptr.wrapping_offset(Size::from_bytes(len.strict_add(1).strict_mul(2)), this);
+        }
+    }
+
+    /// Reports the exit of the child process `pid` like `WaitForSingleObject`, once it exited or
+    /// `deadline` (relative to the epoch of the monotonic clock) passed.
+    fn wait_for_child_exit(
+        &mut self,
+        pid: i32,
+        deadline: Option<Duration>,
+        dest: MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        match this.machine.processes.try_wait(pid) {
+            Ok(Some(_)) =>
+                return this.write_scalar(this.eval_windows("c", "WAIT_OBJECT_0"), &dest),
+            Ok(None) => {}
+            Err(err) => {
+                this.set_last_error(err)?;
+                // WAIT_FAILED
+                return this.write_scalar(Scalar::from_u32(u32::MAX), &dest);
+            }
+        }
+        if deadline.is_some_and(|deadline| this.machine.monotonic_clock.elapsed() >= deadline) {
+            return this.write_scalar(this.eval_windows("c", "WAIT_TIMEOUT"), &dest);
+        }
+        this.block_on_child_process(
+            deadline,
+            callback!(
+                @capture<'tcx> {
+                    pid: i32,
+                    deadline: Option<Duration>,
+                    dest: MPlaceTy<'tcx>,
+                }
+                |this, _unblock: ()| this.wait_for_child_exit(pid, deadline, dest)
+            ),
+        );
+        interp_ok(())
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+
+#[allow(non_snake_case)]
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Starts a child process. Like `posix_spawn`, this can only run the interpreted program
+    /// itself.
+    fn CreateProcessW(
+        &mut self,
+        application_name_op: &OpTy<'tcx>,    // LPCWSTR
+        command_line_op: &OpTy<'tcx>,        // LPWSTR
+        process_attributes_op: &OpTy<'tcx>,  // LPSECURITY_ATTRIBUTES
+        thread_attributes_op: &OpTy<'tcx>,   // LPSECURITY_ATTRIBUTES
+        inherit_handles_op: &OpTy<'tcx>,     // BOOL
+        creation_flags_op: &OpTy<'tcx>,      // DWORD
+        environment_op: &OpTy<'tcx>,         // LPVOID
+        current_directory_op: &OpTy<'tcx>,   // LPCWSTR
+        startup_info_op: &OpTy<'tcx>,        // LPSTARTUPINFOW
+        process_information_op: &OpTy<'tcx>, // LPPROCESS_INFORMATION
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+
+        this.check_no_isolation("`CreateProcessW`")?;
+
+        let application_name = this.read_pointer(application_name_op)?;
+        let command_line = this.read_pointer(command_line_op)?;
+        // Security attributes and handle inheritance do not matter for the child interpreter: it
+        // only gets the standard streams from `lpStartupInfo`.
+        let _ = this.read_pointer(process_attributes_op)?;
+        let _ = this.read_pointer(thread_attributes_op)?;
+        let _ = this.read_scalar(inherit_handles_op)?.to_i32()?;
+        let creation_flags = this.read_scalar(creation_flags_op)?.to_u32()?;
+        let environment = this.read_pointer(environment_op)?;
+        let current_directory = this.read_pointer(current_directory_op)?;
+        // `STARTUPINFOEXW` starts with a `STARTUPINFOW`, so we do not care which one we got.
+        let startup_info =
+            this.deref_pointer_as(startup_info_op, this.windows_ty_layout("STARTUPINFOW"))?;
+        let process_information = this.deref_pointer_as(
+            process_information_op,
+            this.windows_ty_layout("PROCESS_INFORMATION"),
+        )?;
+
+        let mut argv = if this.ptr_is_null(command_line)? {
+            Vec::new()
+        } else {
+            let Ok(command_line) = this.read_os_str_from_wide_str(command_line)?.into_string()
+            else {
+                throw_unsup_format!(
+                    "`CreateProcessW` with a command line that is not valid UTF-16"
+                );
+            };
+            split_command_line(&command_line)
+        };
+        let program = if this.ptr_is_null(application_name)? {
+            match argv.first() {
+                Some(program) => program.clone().into(),
+                None => {
+                    this.set_last_error(IoError::WindowsError("ERROR_INVALID_PARAMETER"))?;
+                    return interp_ok(this.eval_windows("c", "FALSE"));
+                }
+            }
+        } else {
+            this.read_path_from_wide_str(application_name)?
+        };
+        if argv.is_empty() {
+            argv.push(program.clone().into_os_string());
+        }
+
+        // The other flags concern consoles, process groups and debugging, which do not matter
+        // for the child interpreter.
+        let envp = if this.ptr_is_null(environment)? {
+            this.machine.env_vars.windows().env_strings()
+        } else if creation_flags & this.eval_windows_u32("c", "CREATE_UNICODE_ENVIRONMENT") != 0 {
+            this.read_env_block(environment)?
+        } else {
+            throw_unsup_format!("`CreateProcessW` with an ANSI environment block");
+        };
+
+        let mut actions = Vec::new();
+        if !this.ptr_is_null(current_directory)? {
+            actions.push(FileAction::Chdir(this.read_path_from_wide_str(current_directory)?));
+        }
+        let flags = this.project_field_named(&startup_info, "dwFlags")?;
+        let flags = this.read_scalar(&flags)?.to_u32()?;
+        if flags & this.eval_windows_u32("c", "STARTF_USESTDHANDLES") != 0 {
+            for (new_fd, field) in ["hStdInput", "hStdOutput", "hStdError"].into_iter().enumerate()
+            {
+                let new_fd = i32::try_from(new_fd).unwrap();
+                let handle = this.project_field_named(&startup_info, field)?;
+                match this.read_handle(&handle.into(), "CreateProcessW")? {
+                    Handle::File(fd) => actions.push(FileAction::Dup2 { fd, new_fd }),
+                    Handle::Null | Handle::Invalid =>
+                        actions.push(FileAction::Close { fd: new_fd }),
+                    _ => this.invalid_handle("CreateProcessW")?,
+                }
+            }
+        }
+
+        let pid = match this.spawn_child(&program, argv, envp, actions)? {
+            Ok(pid) => pid,
+            Err(err) => {
+                this.set_last_error(err)?;
+                return interp_ok(this.eval_windows("c", "FALSE"));
+            }
+        };
+        let Some(handle) = Handle::process(pid) else {
+            throw_unsup_format!(
+                "`CreateProcessW` started a child process with pid {pid}, which is too large for a handle"
+            );
+        };
+        // There is no handle for the main thread of the child interpreter, so waiting for that
+        // thread waits for the process instead, and the thread ID is the process ID.
+        this.write_int_fields_named(
+            &[("dwProcessId", pid.into()), ("dwThreadId", pid.into())],
+            &process_information,
+        )?;
+        for field in ["hProcess", "hThread"] {
+            let place = this.project_field_named(&process_information, field)?;
+            this.write_scalar(handle.to_scalar(this), &place)?;
+        }
+        interp_ok(this.eval_windows("c", "TRUE"))
+    }
+
+    fn GetExitCodeProcess(
+        &mut self,
+        process_op: &OpTy<'tcx>,   // HANDLE
+        exit_code_op: &OpTy<'tcx>, // LPDWORD
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+
+        let Handle::Process(pid) = this.read_handle(process_op, "GetExitCodeProcess")? else {
+            this.invalid_handle("GetExitCodeProcess")?
+        };
+        let exit_code_place = this.deref_pointer_as(exit_code_op, this.machine.layouts.u32)?;
+
+        let exit_code = match this.machine.processes.try_wait(pid) {
+            Ok(None) => STILL_ACTIVE,
+            Ok(Some(status)) =>
+                this.machine
+                    .processes
+                    .killed_by(pid)
+                    .or(status.code())
+                    .map_or(1, |code| code.cast_unsigned()),
+            Err(err) => {
+                this.set_last_error(err)?;
+                return interp_ok(this.eval_windows("c", "FALSE"));
+            }
+        };
+        this.write_scalar(Scalar::from_u32(exit_code), &exit_code_place)?;
+        interp_ok(this.eval_windows("c", "TRUE"))
+    }
+
+    fn TerminateProcess(
+        &mut self,
+        process_op: &OpTy<'tcx>,   // HANDLE
+        exit_code_op: &OpTy<'tcx>, // UINT
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+
+        let pid = match this.read_handle(process_op, "TerminateProcess")? {
+            Handle::Process(pid) => pid,
+            Handle::Pseudo(_) =>
+                throw_unsup_format!("`TerminateProcess` on the current process is not supported"),
+            _ => this.invalid_handle("TerminateProcess")?,
+        };
+        let exit_code = this.read_scalar(exit_code_op)?.to_u32()?;
+
+        if let Err(err) = this.machine.processes.kill(pid, exit_code.cast_signed()) {
+            this.set_last_error(err)?;
+            return interp_ok(this.eval_windows("c", "FALSE"));
+        }
+        interp_ok(this.eval_windows("c", "TRUE"))
+    }
+
+    /// `WaitForSingleObject` on the handle of the child process `pid`.
+    fn wait_for_child_process(
+        &mut self,
+        pid: i32,
+        timeout_ms: u32,
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let deadline = (timeout_ms != this.eval_windows_u32("c", "INFINITE")).then(|| {
+            this.machine
+                .monotonic_clock
+                .elapsed()
+                .saturating_add(Duration::from_millis(timeout_ms.into()))
+        });
+        this.wait_for_child_exit(pid, deadline, dest.clone())
+    }
+}
@@ -1,6 +1,8 @@
 use std::time::Duration;
 
 use rustc_abi::{FieldIdx, Size};
+use rustc_middle::ty::Ty;
+use rustc_middle::ty::layout::{LayoutOf, TyAndLayout};
 
 use crate::concurrency::init_once::{EvalContextExt as _, InitOnceStatus};
 use crate::concurrency::sync::{AccessKind, FutexRef, SyncObj};
@@ -32,9 +34,69 @@ struct WindowsFutex {
 
 impl SyncObj for WindowsFutex {}
 
+#[derive(Clone)]
+struct WindowsSrwLock {
+    rwlock_ref: RwLockRef,
+}
+
+impl SyncObj for WindowsSrwLock {
+    fn on_access<'tcx>(&self, access_kind: AccessKind) -> InterpResult<'tcx> {
+        if !self.rwlock_ref.queue_is_empty() {
+            throw_ub_format!(
+                "{access_kind} of `SRWLOCK` is forbidden while the queue is non-empty"
+            );
+        }
+        interp_ok(())
+    }
+
+    fn delete_on_write(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Clone)]
+struct WindowsCriticalSection {
+    mutex_ref: MutexRef,
+}
+
+impl SyncObj for WindowsCriticalSection {
+    fn on_access<'tcx>(&self, access_kind: AccessKind) -> InterpResult<'tcx> {
+        if !self.mutex_ref.queue_is_empty() {
+            throw_ub_format!(
+                "{access_kind} of `CRITICAL_SECTION` is forbidden while the queue is non-empty"
+            );
+        }
+        interp_ok(())
+    }
+
+    fn delete_on_write(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Clone)]
+struct WindowsCondvar {
+    condvar_ref: CondvarRef,
+}
+
+impl SyncObj for WindowsCondvar {
+    fn on_access<'tcx>(&self, access_kind: AccessKind) -> InterpResult<'tcx> {
+        if !self.condvar_ref.queue_is_empty() {
+            throw_ub_format!(
+                "{access_kind} of `CONDITION_VARIABLE` is forbidden while the queue is non-empty"
+            );
+        }
+        interp_ok(())
+    }
+
+    fn delete_on_write(&self) -> bool {
+        true
+    }
+}
+
 impl<'tcx> EvalContextExtPriv<'tcx> for crate::MiriInterpCx<'tcx> {}
-trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
-    // Windows sync primitives are pointer sized.
+pub(super) trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    // Windows sync primitives are pointer sized (except for `CRITICAL_SECTION`).
     // We only use the first byte for the "init" flag.
 
     fn init_once_get_data<'a>(
@@ -67,6 +129,108 @@ trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
         )
     }
 
+    fn srwlock_get_data<'a>(
+        &'a mut self,
+        srwlock_ptr: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, &'a WindowsSrwLock>
+    where
+        'tcx: 'a,
+    {
+        let this = self.eval_context_mut();
+
+        let srwlock = this.deref_pointer_as(srwlock_ptr, this.windows_ty_layout("SRWLOCK"))?;
+        this.get_immovable_sync_with_static_init(
+            &srwlock,
+            Size::ZERO,
+            /* uninit_val */ 0,
+            /* init_val */ 1,
+            |this| {
+                // `SRWLOCK_INIT` is all-zero.
+                let ptr_field = this.project_field(&srwlock, FieldIdx::from_u32(0))?;
+                let val = this.read_target_usize(&ptr_field)?;
+                if val == 0 {
+                    interp_ok(WindowsSrwLock { rwlock_ref: RwLockRef::new() })
+                } else {
+                    throw_ub_format!("`SRWLOCK` was not properly initialized at this location, or it got overwritten");
+                }
+            },
+        )
+    }
+
+    /// The layout of `CRITICAL_SECTION`, which is not part of std's bindings.
+    fn critical_section_layout(&self) -> TyAndLayout<'tcx> {
+        let this = self.eval_context_ref();
+        // A pointer, two `i32`s, two handles and a `usize`.
+        let words = if this.pointer_size().bytes() == 8 { 5 } else { 6 };
+        let array_ty = Ty::new_array(*this.tcx, this.tcx.types.usize, words);
+        this.layout_of(array_ty).unwrap()
+    }
+
+    fn critical_section_get_data<'a>(
+        &'a mut self,
+        cs_ptr: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, &'a WindowsCriticalSection>
+    where
+        'tcx: 'a,
+    {
+        let this = self.eval_context_mut();
+
+        let cs = this.deref_pointer_as(cs_ptr, this.critical_section_layout())?;
+        // Unlike the other primitives, critical sections have no static initializer, so we only
+        // find the metadata if `InitializeCriticalSection` was called at this location.
+        this.get_immovable_sync_with_static_init(
+            &cs,
+            Size::ZERO,
+            /* uninit_val */ 0,
+            /* init_val */ 1,
+            |_| {
+                throw_ub_format!(
+                    "`CRITICAL_SECTION` was not initialized with `InitializeCriticalSection` at this location, or it got overwritten"
+                )
+            },
+        )
+    }
+
+    fn condvar_get_data<'a>(
+        &'a mut self,
+        condvar_ptr: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, &'a WindowsCondvar>
+    where
+        'tcx: 'a,
+    {
+        let this = self.eval_context_mut();
+
+        let condvar =
+            this.deref_pointer_as(condvar_ptr, this.windows_ty_layout("CONDITION_VARIABLE"))?;
+        this.get_immovable_sync_with_static_init(
+            &condvar,
+            Size::ZERO,
+            /* uninit_val */ 0,
+            /* init_val */ 1,
+            |this| {
+                // `CONDITION_VARIABLE_INIT` is all-zero.
+                let ptr_field = this.project_field(&condvar, FieldIdx::from_u32(0))?;
+                let val = this.read_target_usize(&ptr_field)?;
+                if val == 0 {
+                    interp_ok(WindowsCondvar { condvar_ref: CondvarRef::new() })
+                } else {
+                    throw_ub_format!("`CONDITION_VARIABLE` was not properly initialized at this location, or it got overwritten");
+                }
+            },
+        )
+    }
+
+    /// Converts a timeout in milliseconds to the representation used for blocking.
+    fn windows_timeout(&self, timeout_ms: u32) -> Option<(TimeoutClock, TimeoutAnchor, Duration)> {
+        let this = self.eval_context_ref();
+        if timeout_ms == this.eval_windows_u32("c", "INFINITE") {
+            None
+        } else {
+            let duration = Duration::from_millis(timeout_ms.into());
+            Some((TimeoutClock::Monotonic, TimeoutAnchor::Relative, duration))
+        }
+    }
+
     /// Returns `true` if we were succssful, `false` if we would block.
     fn init_once_try_begin(
         &mut self,
@@ -209,12 +373,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
         };
         let size = Size::from_bytes(size);
 
-        let timeout = if timeout_ms == this.eval_windows_u32("c", "INFINITE") {
-            None
-        } else {
-            let duration = Duration::from_millis(timeout_ms.into());
-            Some((TimeoutClock::Monotonic, TimeoutAnchor::Relative, duration))
-        };
+        let timeout = this.windows_timeout(timeout_ms);
 
         // See the Linux futex implementation for why this fence exists.
         this.atomic_fence(AtomicFenceOrd::SeqCst)?;
@@ -303,4 +462,292 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         interp_ok(())
     }
+
+    fn InitializeSRWLock(&mut self, lock_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let lock = this.deref_pointer_as(lock_op, this.windows_ty_layout("SRWLOCK"))?;
+        this.init_immovable_sync(
+            &lock,
+            Size::ZERO,
+            /* init_val */ 1,
+            WindowsSrwLock { rwlock_ref: RwLockRef::new() },
+        )?;
+        interp_ok(())
+    }
+
+    fn AcquireSRWLockExclusive(&mut self, lock_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let rwlock = this.srwlock_get_data(lock_op)?.rwlock_ref.clone();
+        let thread = this.active_thread();
+        if rwlock.writer() == Some(thread) || rwlock.is_read_locked_by(thread) {
+            throw_ub_format!(
+                "`AcquireSRWLockExclusive` on an SRW lock that is already held by the current thread"
+            );
+        }
+
+        if rwlock.is_locked() {
+            this.rwlock_enqueue_and_block_writer(rwlock, None);
+        } else {
+            this.rwlock_writer_lock(&rwlock)?;
+        }
+        interp_ok(())
+    }
+
+    fn AcquireSRWLockShared(&mut self, lock_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let rwlock = this.srwlock_get_data(lock_op)?.rwlock_ref.clone();
+        let thread = this.active_thread();
+        // Recursive shared acquisition is not allowed either: it deadlocks if a writer queues up
+        // in between.
+        if rwlock.writer() == Some(thread) || rwlock.is_read_locked_by(thread) {
+            throw_ub_format!(
+                "`AcquireSRWLockShared` on an SRW lock that is already held by the current thread"
+            );
+        }
+
+        if rwlock.is_write_locked() {
+            this.rwlock_enqueue_and_block_reader(rwlock, None);
+        } else {
+            this.rwlock_reader_lock(&rwlock)?;
+        }
+        interp_ok(())
+    }
+
+    fn TryAcquireSRWLockExclusive(&mut self, lock_op: &OpTy<'tcx>) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOLEAN (u8 on Windows)
+        let this = self.eval_context_mut();
+
+        let rwlock = this.srwlock_get_data(lock_op)?.rwlock_ref.clone();
+        // This also fails if the current thread holds the lock.
+        if rwlock.is_locked() {
+            this.rwlock_lock_failed(&rwlock);
+            return interp_ok(Scalar::from_u8(0));
+        }
+        this.rwlock_writer_lock(&rwlock)?;
+        interp_ok(Scalar::from_u8(1))
+    }
+
+    fn TryAcquireSRWLockShared(&mut self, lock_op: &OpTy<'tcx>) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOLEAN (u8 on Windows)
+        let this = self.eval_context_mut();
+
+        let rwlock = this.srwlock_get_data(lock_op)?.rwlock_ref.clone();
+        if rwlock.is_write_locked() {
+            this.rwlock_lock_failed(&rwlock);
+            return interp_ok(Scalar::from_u8(0));
+        }
+        this.rwlock_reader_lock(&rwlock)?;
+        interp_ok(Scalar::from_u8(1))
+    }
+
+    fn ReleaseSRWLockExclusive(&mut self, lock_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let rwlock = this.srwlock_get_data(lock_op)?.rwlock_ref.clone();
+        if !this.rwlock_writer_unlock(&rwlock)? {
+            throw_ub_format!(
+                "`ReleaseSRWLockExclusive` on an SRW lock that is not held exclusively by the current thread"
+            );
+        }
+        interp_ok(())
+    }
+
+    fn ReleaseSRWLockShared(&mut self, lock_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let rwlock = this.srwlock_get_data(lock_op)?.rwlock_ref.clone();
+        if !this.rwlock_reader_unlock(&rwlock)? {
+            throw_ub_format!(
+                "`ReleaseSRWLockShared` on an SRW lock that is not held in shared mode by the current thread"
+            );
+        }
+        interp_ok(())
+    }
+
+    fn InitializeCriticalSection(&mut self, cs_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let cs = this.deref_pointer_as(cs_op, this.critical_section_layout())?;
+        this.init_immovable_sync(
+            &cs,
+            Size::ZERO,
+            /* init_val */ 1,
+            WindowsCriticalSection { mutex_ref: MutexRef::new() },
+        )?;
+        interp_ok(())
+    }
+
+    fn InitializeCriticalSectionEx(
+        &mut self,
+        cs_op: &OpTy<'tcx>,
+        spin_count_op: &OpTy<'tcx>,
+        flags_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+
+        // The spin count only matters for performance, and the debug info is not observable.
+        let _spin_count = this.read_scalar(spin_count_op)?.to_u32()?;
+        let flags = this.read_scalar(flags_op)?.to_u32()?;
+        // CRITICAL_SECTION_NO_DEBUG_INFO
+        if flags & !0x0100_0000 != 0 {
+            throw_unsup_format!("unsupported `Flags` {flags:#x} in `InitializeCriticalSectionEx`");
+        }
+
+        this.InitializeCriticalSection(cs_op)?;
+        interp_ok(this.eval_windows("c", "TRUE"))
+    }
+
+    fn EnterCriticalSection(&mut self, cs_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let mutex = this.critical_section_get_data(cs_op)?.mutex_ref.clone();
+        // Critical sections are recursive.
+        match mutex.owner() {
+            Some(owner) if owner != this.active_thread() =>
+                this.mutex_enqueue_and_block(mutex, None),
+            _ => this.mutex_lock(&mutex)?,
+        }
+        interp_ok(())
+    }
+
+    fn TryEnterCriticalSection(&mut self, cs_op: &OpTy<'tcx>) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns BOOL (i32 on Windows)
+        let this = self.eval_context_mut();
+
+        let mutex = this.critical_section_get_data(cs_op)?.mutex_ref.clone();
+        match mutex.owner() {
+            Some(owner) if owner != this.active_thread() => {
+                this.mutex_lock_failed(&mutex);
+                interp_ok(this.eval_windows("c", "FALSE"))
+            }
+            _ => {
+                this.mutex_lock(&mutex)?;
+                interp_ok(this.eval_windows("c", "TRUE"))
+            }
+        }
+    }
+
+    fn LeaveCriticalSection(&mut self, cs_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let mutex = this.critical_section_get_data(cs_op)?.mutex_ref.clone();
+        if this.mutex_unlock(&mutex)?.is_none() {
+            throw_ub_format!(
+                "`LeaveCriticalSection` on a critical section that is not owned by the current thread"
+            );
+        }
+        interp_ok(())
+    }
+
+    fn DeleteCriticalSection(&mut self, cs_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        // Reading the data also has the side-effect that we detect double-delete, since we make
+        // the memory uninit below.
+        let mutex = this.critical_section_get_data(cs_op)?.mutex_ref.clone();
+        if mutex.owner().is_some() {
+            throw_ub_format!("`DeleteCriticalSection` on a critical section that is still owned");
+        }
+
+        // This write also deletes the interpreter state for this critical section.
+        let cs = this.deref_pointer_as(cs_op, this.critical_section_layout())?;
+        this.write_uninit(&cs)?;
+        interp_ok(())
+    }
+
+    fn InitializeConditionVariable(&mut self, condvar_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let condvar =
+            this.deref_pointer_as(condvar_op, this.windows_ty_layout("CONDITION_VARIABLE"))?;
+        this.init_immovable_sync(
+            &condvar,
+            Size::ZERO,
+            /* init_val */ 1,
+            WindowsCondvar { condvar_ref: CondvarRef::new() },
+        )?;
+        interp_ok(())
+    }
+
+    fn SleepConditionVariableSRW(
+        &mut self,
+        condvar_op: &OpTy<'tcx>,
+        lock_op: &OpTy<'tcx>,
+        timeout_op: &OpTy<'tcx>,
+        flags_op: &OpTy<'tcx>,
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let condvar = this.condvar_get_data(condvar_op)?.condvar_ref.clone();
+        let rwlock = this.srwlock_get_data(lock_op)?.rwlock_ref.clone();
+        let timeout_ms = this.read_scalar(timeout_op)?.to_u32()?;
+        let flags = this.read_scalar(flags_op)?.to_u32()?;
+
+        let shared = if flags == 0 {
+            false
+        } else if flags == 1 {
+            // CONDITION_VARIABLE_LOCKMODE_SHARED
+            true
+        } else {
+            throw_unsup_format!("unsupported `Flags` {flags} in `SleepConditionVariableSRW`");
+        };
+
+        // The last error is only meaningful if we time out, so we can already set it now.
+        this.set_last_error(IoError::WindowsError("ERROR_TIMEOUT"))?;
+        this.condvar_wait_rwlock(
+            condvar,
+            rwlock,
+            shared,
+            this.windows_timeout(timeout_ms),
+            this.eval_windows("c", "TRUE"),
+            this.eval_windows("c", "FALSE"),
+            dest.clone(),
+        )
+    }
+
+    fn SleepConditionVariableCS(
+        &mut self,
+        condvar_op: &OpTy<'tcx>,
+        cs_op: &OpTy<'tcx>,
+        timeout_op: &OpTy<'tcx>,
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let condvar = this.condvar_get_data(condvar_op)?.condvar_ref.clone();
+        let mutex = this.critical_section_get_data(cs_op)?.mutex_ref.clone();
+        let timeout_ms = this.read_scalar(timeout_op)?.to_u32()?;
+
+        // The last error is only meaningful if we time out, so we can already set it now.
+        this.set_last_error(IoError::WindowsError("ERROR_TIMEOUT"))?;
+        this.condvar_wait(
+            condvar,
+            mutex,
+            this.windows_timeout(timeout_ms),
+            this.eval_windows("c", "TRUE"),
+            this.eval_windows("c", "FALSE"),
+            dest.clone(),
+        )
+    }
+
+    fn WakeConditionVariable(&mut self, condvar_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let condvar = this.condvar_get_data(condvar_op)?.condvar_ref.clone();
+        this.condvar_signal(&condvar)?;
+        interp_ok(())
+    }
+
+    fn WakeAllConditionVariable(&mut self, condvar_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let condvar = this.condvar_get_data(condvar_op)?.condvar_ref.clone();
+        while this.condvar_signal(&condvar)? {}
+        interp_ok(())
+    }
 }
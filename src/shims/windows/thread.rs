@@ -1,6 +1,7 @@
 use rustc_abi::ExternAbi;
 
 use self::shims::windows::handle::{EvalContextExt as _, Handle, PseudoHandle};
+use self::shims::windows::process::EvalContextExt as _;
 use crate::*;
 
 impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
@@ -67,6 +68,7 @@ pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
 
         let joined_thread_id = match handle {
             Handle::Thread(thread) => thread,
+            Handle::Process(pid) => return this.wait_for_child_process(pid, timeout, return_dest),
             // Unlike on posix, the outcome of joining the current thread is not documented.
             // On current Windows, it just deadlocks.
             Handle::Pseudo(PseudoHandle::CurrentThread) => this.active_thread(),
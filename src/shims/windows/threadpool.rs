@@ -0,0 +1,685 @@
+//! Emulation of the Windows thread pool (`CreateThreadpoolWork`, `CreateThreadpoolTimer`, ...) and
+//! of user-mode APCs (`QueueUserAPC` and alertable waits).
+//!
+//! Every pool callback runs on an interpreter thread that starts out with an empty stack; its
+//! `on_stack_empty` hook decides which callback to run next. When a callback returns, the worker
+//! releases its clock into the pool object, and `WaitForThreadpool*Callbacks` acquires that clock,
+//! so waiting for callbacks synchronizes with them the same way joining a thread would.
+
+use std::collections::VecDeque;
+use std::task::Poll;
+use std::time::Duration;
+
+use rustc_abi::ExternAbi;
+use rustc_data_structures::either::Either;
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_middle::mir;
+
+use self::shims::windows::handle::{EvalContextExt as _, Handle, PseudoHandle};
+use self::shims::windows::sync::EvalContextExtPriv as _;
+use crate::concurrency::VClock;
+use crate::shims::tls;
+use crate::*;
+
+/// `WAIT_IO_COMPLETION`, returned by alertable waits that ran APCs.
+const WAIT_IO_COMPLETION: u32 = 0xC0;
+
+#[derive(Debug)]
+enum PoolObjectKind {
+    /// A `PTP_WORK`. Every submission spawns its own worker.
+    Work,
+    /// A `PTP_TIMER`, which has at most one worker that waits for it to expire.
+    Timer {
+        /// When the timer expires next, relative to the epoch of the monotonic clock, or `None`
+        /// if the timer is not set.
+        due: Option<Duration>,
+        /// The period of the timer, or zero for a one-shot timer.
+        period: Duration,
+        /// The worker of this timer, if it has one.
+        worker: Option<ThreadId>,
+        /// Whether the worker is blocked until `due`.
+        waiting: bool,
+    },
+}
+
+#[derive(Debug)]
+struct PoolObject {
+    kind: PoolObjectKind,
+    /// The pointer that was handed out to the program, and that is passed to the callback.
+    handle: Pointer,
+    callback: Pointer,
+    context: Scalar,
+    /// The number of callbacks that were submitted or started, but have not returned yet.
+    outstanding: u32,
+    /// Whether the handle was closed. The object lives on until its callbacks are done.
+    closed: bool,
+    /// The clock of the last submission (or timer update), acquired before running a callback.
+    submit_clock: VClock,
+    /// The joined clocks of all workers that returned from a callback.
+    done_clock: VClock,
+    /// Threads blocked in `WaitForThreadpool*Callbacks`.
+    waiters: Vec<ThreadId>,
+}
+
+/// An APC queued with `QueueUserAPC`.
+#[derive(Debug)]
+struct Apc {
+    func: Pointer,
+    data: Scalar,
+    /// The clock of the thread that queued the APC.
+    clock: VClock,
+}
+
+/// The state of the emulated thread pool and of the APC queues.
+#[derive(Debug, Default)]
+pub struct WindowsThreadPool {
+    /// Maps the address of each open handle to its pool object.
+    handles: FxHashMap<u64, u64>,
+    /// All pool objects, indexed by a unique id. Workers refer to objects by id, so that a closed
+    /// object whose address gets reused is never confused with a new one.
+    objects: FxHashMap<u64, PoolObject>,
+    next_id: u64,
+    /// The APCs queued for each thread, in order.
+    apcs: FxHashMap<ThreadId, VecDeque<Apc>>,
+    /// The threads blocked in an alertable wait.
+    alertable: FxHashSet<ThreadId>,
+}
+
+impl VisitProvenance for WindowsThreadPool {
+    fn visit_provenance(&self, visit: &mut VisitWith<'_>) {
+        let WindowsThreadPool { handles: _, objects, next_id: _, apcs, alertable: _ } = self;
+
+        for obj in objects.values() {
+            obj.handle.visit_provenance(visit);
+            obj.callback.visit_provenance(visit);
+            obj.context.visit_provenance(visit);
+        }
+        for apc in apcs.values().flatten() {
+            apc.func.visit_provenance(visit);
+            apc.data.visit_provenance(visit);
+        }
+    }
+}
+
+/// What a worker does the next time its stack is empty.
+#[derive(Debug)]
+enum WorkerState<'tcx> {
+    /// Run the next callback of the pool object, once it is due.
+    Ready,
+    /// A callback is running.
+    Running,
+    /// The worker is done; run the TLS destructors and exit.
+    Exiting(tls::TlsDtorsState<'tcx>),
+}
+
+#[derive(Debug)]
+struct Worker<'tcx> {
+    object: u64,
+    state: WorkerState<'tcx>,
+}
+
+impl<'tcx> Worker<'tcx> {
+    fn on_stack_empty(&mut self, this: &mut MiriInterpCx<'tcx>) -> InterpResult<'tcx, Poll<()>> {
+        loop {
+            match &mut self.state {
+                WorkerState::Ready =>
+                    match this.start_pool_callback(self.object)? {
+                        CallbackStart::Started => {
+                            self.state = WorkerState::Running;
+                            return interp_ok(Poll::Pending);
+                        }
+                        // We look at the timer again once the worker is unblocked.
+                        CallbackStart::Waiting => return interp_ok(Poll::Pending),
+                        CallbackStart::Done =>
+                            self.state = WorkerState::Exiting(Default::default()),
+                    },
+                WorkerState::Running => {
+                    this.finish_pool_callback(self.object)?;
+                    self.state = match this.machine.threadpool.objects.get(&self.object) {
+                        Some(PoolObject { kind: PoolObjectKind::Timer { .. }, .. }) =>
+                            WorkerState::Ready,
+                        _ => WorkerState::Exiting(Default::default()),
+                    };
+                }
+                WorkerState::Exiting(tls) => return tls.on_stack_empty(this),
+            }
+        }
+    }
+}
+
+/// The outcome of a worker trying to start the next callback.
+enum CallbackStart {
+    /// The callback was pushed onto the worker's stack.
+    Started,
+    /// The worker was blocked until the timer expires.
+    Waiting,
+    /// There is nothing left to do for this worker.
+    Done,
+}
+
+impl<'tcx> EvalContextExtPriv<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    fn create_pool_object(
+        &mut self,
+        kind: PoolObjectKind,
+        callback_op: &OpTy<'tcx>,
+        context_op: &OpTy<'tcx>,
+        environment_op: &OpTy<'tcx>,
+        name: &str,
+    ) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+
+        let callback = this.read_pointer(callback_op)?;
+        let context = this.read_scalar(context_op)?;
+        let environment = this.read_pointer(environment_op)?;
+        if !this.ptr_is_null(environment)? {
+            throw_unsup_format!("`{name}` with a non-null callback environment");
+        }
+        // Check that the callback is a function pointer right away.
+        this.get_ptr_fn(callback)?;
+
+        // The handle is an opaque pointer. We hand out a small allocation so that it is unique
+        // while it is open, and so that forgetting to close it is reported as a leak.
+        let handle =
+            this.allocate(this.machine.layouts.usize, MiriMemoryKind::Runtime.into())?.ptr();
+
+        let pool = &mut this.machine.threadpool;
+        let id = pool.next_id;
+        pool.next_id = pool.next_id.strict_add(1);
+        pool.handles.insert(handle.addr().bytes(), id);
+        pool.objects.insert(
+            id,
+            PoolObject {
+                kind,
+                handle,
+                callback,
+                context,
+                outstanding: 0,
+                closed: false,
+                submit_clock: VClock::default(),
+                done_clock: VClock::default(),
+                waiters: Vec::new(),
+            },
+        );
+        interp_ok(handle)
+    }
+
+    /// Returns the id of the pool object the handle refers to.
+    fn read_pool_object(&self, handle_op: &OpTy<'tcx>, name: &str) -> InterpResult<'tcx, u64> {
+        let this = self.eval_context_ref();
+        let handle = this.read_pointer(handle_op)?;
+        let Some(&id) = this.machine.threadpool.handles.get(&handle.addr().bytes()) else {
+            throw_ub_format!("`{name}` called on a handle that is not an open thread pool object");
+        };
+        interp_ok(id)
+    }
+
+    fn pool_object(&mut self, id: u64) -> &mut PoolObject {
+        let this = self.eval_context_mut();
+        this.machine.threadpool.objects.get_mut(&id).unwrap()
+    }
+
+    /// Records the clock of the active thread as the clock of the latest submission.
+    fn release_submit_clock(&mut self, id: u64) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        if let Some(clock) = this.release_clock(|clock| clock.clone())? {
+            this.pool_object(id).submit_clock.join(&clock);
+        }
+        interp_ok(())
+    }
+
+    fn spawn_worker(&mut self, id: u64) -> InterpResult<'tcx, ThreadId> {
+        let this = self.eval_context_mut();
+        let mut worker = Worker { object: id, state: WorkerState::Ready };
+        this.start_thread_with_empty_stack(Box::new(move |this| worker.on_stack_empty(this)))
+    }
+
+    /// Called by a worker that is ready to run the next callback of the pool object.
+    fn start_pool_callback(&mut self, id: u64) -> InterpResult<'tcx, CallbackStart> {
+        let this = self.eval_context_mut();
+        let now = this.machine.monotonic_clock.elapsed();
+        let active_thread = this.active_thread();
+
+        let Some(obj) = this.machine.threadpool.objects.get_mut(&id) else {
+            return interp_ok(CallbackStart::Done);
+        };
+        // For work objects, the submission already counted this callback as outstanding.
+        if let PoolObjectKind::Timer { due, period, worker, waiting } = &mut obj.kind {
+            if *worker != Some(active_thread) {
+                return interp_ok(CallbackStart::Done);
+            }
+            match *due {
+                None => {
+                    // The timer was cancelled, so its worker is done.
+                    *worker = None;
+                    this.maybe_free_pool_object(id)?;
+                    return interp_ok(CallbackStart::Done);
+                }
+                Some(due_at) if due_at > now => {
+                    *waiting = true;
+                    this.block_thread(
+                        BlockReason::ThreadPool,
+                        Some((
+                            TimeoutClock::Monotonic,
+                            TimeoutAnchor::Relative,
+                            due_at.saturating_sub(now),
+                        )),
+                        callback!(
+                            @capture<'tcx> { id: u64 }
+                            |this, _unblock: UnblockKind| {
+                                // Whether the timer expired or was changed, the worker looks at
+                                // it again once its stack is empty.
+                                if let Some(PoolObject {
+                                    kind: PoolObjectKind::Timer { waiting, .. }, ..
+                                }) = this.machine.threadpool.objects.get_mut(&id) {
+                                    *waiting = false;
+                                }
+                                interp_ok(())
+                            }
+                        ),
+                    );
+                    return interp_ok(CallbackStart::Waiting);
+                }
+                Some(due_at) => {
+                    *due =
+                        if period.is_zero() { None } else { Some(due_at.saturating_add(*period)) };
+                    obj.outstanding = obj.outstanding.strict_add(1);
+                }
+            }
+        }
+
+        let callback = obj.callback;
+        let context = obj.context;
+        let handle = obj.handle;
+        let submit_clock = obj.submit_clock.clone();
+        this.acquire_clock(&submit_clock)?;
+
+        // The signature of the callbacks is
+        // `unsafe extern "system" fn(instance: PTP_CALLBACK_INSTANCE, context: *mut c_void, object: PTP_*)`.
+        // FIXME: we pass a null `instance`, so the `*WhenCallbackReturns` functions are not supported.
+        let instance = this.get_ptr_fn(callback)?.as_instance()?;
+        let ptr_layout = this.machine.layouts.mut_raw_ptr;
+        this.call_function(
+            instance,
+            ExternAbi::System { unwind: false },
+            &[
+                ImmTy::from_scalar(Scalar::null_ptr(this), ptr_layout),
+                ImmTy::from_scalar(context, ptr_layout),
+                ImmTy::from_scalar(Scalar::from_maybe_pointer(handle, this), ptr_layout),
+            ],
+            None,
+            ReturnContinuation::Stop { cleanup: true },
+        )?;
+        interp_ok(CallbackStart::Started)
+    }
+
+    /// Called by a worker whose callback returned.
+    fn finish_pool_callback(&mut self, id: u64) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let clock = this.release_clock(|clock| clock.clone())?;
+
+        let obj = this.pool_object(id);
+        if let Some(clock) = clock {
+            obj.done_clock.join(&clock);
+        }
+        obj.outstanding = obj.outstanding.strict_sub(1);
+        if obj.outstanding == 0 {
+            for waiter in std::mem::take(&mut obj.waiters) {
+                this.unblock_thread(waiter, BlockReason::ThreadPool)?;
+            }
+        }
+        this.maybe_free_pool_object(id)
+    }
+
+    /// Forgets about a closed object once nothing refers to it any more.
+    fn maybe_free_pool_object(&mut self, id: u64) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let obj = this.pool_object(id);
+        let has_worker = matches!(obj.kind, PoolObjectKind::Timer { worker: Some(_), .. });
+        if obj.closed && obj.outstanding == 0 && !has_worker {
+            this.machine.threadpool.objects.remove(&id);
+        }
+        interp_ok(())
+    }
+
+    /// Blocks the active thread until all outstanding callbacks of the object have returned, and
+    /// synchronizes with them.
+    fn wait_for_pool_callbacks(&mut self, id: u64) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let active_thread = this.active_thread();
+        let obj = this.pool_object(id);
+        if obj.outstanding == 0 {
+            let done_clock = obj.done_clock.clone();
+            return this.acquire_clock(&done_clock);
+        }
+
+        obj.waiters.push(active_thread);
+        this.block_thread(
+            BlockReason::ThreadPool,
+            None,
+            callback!(
+                @capture<'tcx> { id: u64 }
+                |this, unblock: UnblockKind| {
+                    assert_eq!(unblock, UnblockKind::Ready);
+                    if let Some(obj) = this.machine.threadpool.objects.get(&id) {
+                        let done_clock = obj.done_clock.clone();
+                        this.acquire_clock(&done_clock)?;
+                    }
+                    interp_ok(())
+                }
+            ),
+        );
+        interp_ok(())
+    }
+
+    /// Changes when the timer expires next, and makes its worker notice the change.
+    fn update_timer(
+        &mut self,
+        id: u64,
+        new_due: Option<Duration>,
+        new_period: Duration,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let PoolObjectKind::Timer { due, period, worker, waiting } = &mut this.pool_object(id).kind
+        else {
+            unreachable!()
+        };
+        *due = new_due;
+        *period = new_period;
+        match *worker {
+            Some(thread) if *waiting => {
+                *waiting = false;
+                this.unblock_thread(thread, BlockReason::ThreadPool)?;
+            }
+            // The worker will look at the timer once its current callback returns.
+            Some(_) => {}
+            None if new_due.is_some() => {
+                let thread = this.spawn_worker(id)?;
+                let PoolObjectKind::Timer { worker, .. } = &mut this.pool_object(id).kind else {
+                    unreachable!()
+                };
+                *worker = Some(thread);
+            }
+            None => {}
+        }
+        interp_ok(())
+    }
+
+    fn close_pool_object(&mut self, handle_op: &OpTy<'tcx>, name: &str) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let id = this.read_pool_object(handle_op, name)?;
+        let obj = this.pool_object(id);
+        let handle = obj.handle;
+        obj.closed = true;
+        this.machine.threadpool.handles.remove(&handle.addr().bytes());
+        this.deallocate_ptr(handle, None, MiriMemoryKind::Runtime.into())?;
+        if matches!(this.pool_object(id).kind, PoolObjectKind::Timer { .. }) {
+            this.update_timer(id, None, Duration::ZERO)?;
+        }
+        this.maybe_free_pool_object(id)
+    }
+
+    /// Converts the `pftDueTime` of `SetThreadpoolTimer` into a time relative to the epoch of the
+    /// monotonic clock. Negative values are relative, in 100ns intervals; other values are an
+    /// absolute `FILETIME`.
+    #[allow(non_snake_case)]
+    fn timer_due_time(&self, filetime_op: &OpTy<'tcx>) -> InterpResult<'tcx, Duration> {
+        let this = self.eval_context_ref();
+
+        let filetime = this.deref_pointer_as(filetime_op, this.windows_ty_layout("FILETIME"))?;
+        let low = this.read_scalar(&this.project_field_named(&filetime, "dwLowDateTime")?)?;
+        let high = this.read_scalar(&this.project_field_named(&filetime, "dwHighDateTime")?)?;
+        let ticks = ((u64::from(high.to_u32()?) << 32) | u64::from(low.to_u32()?)).cast_signed();
+
+        let delay_ticks = if ticks < 0 {
+            ticks.unsigned_abs()
+        } else {
+            let now = this.system_time_since_windows_epoch(&this.system_time_now())?;
+            ticks.cast_unsigned().saturating_sub(this.windows_ticks_for(now)?)
+        };
+        let NANOS_PER_SEC = this.eval_windows_u64("time", "NANOS_PER_SEC");
+        let INTERVALS_PER_SEC = this.eval_windows_u64("time", "INTERVALS_PER_SEC");
+        let NANOS_PER_INTERVAL = NANOS_PER_SEC.strict_div(INTERVALS_PER_SEC);
+        let delay = Duration::from_nanos_u128(
+            u128::from(delay_ticks).strict_mul(NANOS_PER_INTERVAL.into()),
+        );
+
+        interp_ok(this.machine.monotonic_clock.elapsed().saturating_add(delay))
+    }
+
+    /// Runs all APCs queued for the active thread, which is returning from an alertable wait.
+    /// Returns whether there were any.
+    fn run_queued_apcs(&mut self) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+        let active_thread = this.active_thread();
+        let Some(apcs) = this.machine.threadpool.apcs.remove(&active_thread) else {
+            return interp_ok(false);
+        };
+
+        // The APCs run as if they were called by the wait function. We push them in reverse order,
+        // so that the APC queued first is on top of the stack and runs first. The last APC returns
+        // to the caller of the wait, and every other APC returns to the start of the APC below it,
+        // which is where that frame is still waiting to begin.
+        let Either::Left(caller_loc) = this.frame().current_loc() else {
+            unreachable!("alertable waits are called from MIR")
+        };
+        for (i, apc) in apcs.into_iter().rev().enumerate() {
+            this.acquire_clock(&apc.clock)?;
+            let ret = if i == 0 { caller_loc.block } else { mir::START_BLOCK };
+            // The signature of the APC is `unsafe extern "system" fn(data: usize)`.
+            let instance = this.get_ptr_fn(apc.func)?.as_instance()?;
+            this.call_function(
+                instance,
+                ExternAbi::System { unwind: false },
+                &[ImmTy::from_scalar(apc.data, this.machine.layouts.usize)],
+                None,
+                ReturnContinuation::Goto { ret: Some(ret), unwind: mir::UnwindAction::Unreachable },
+            )?;
+        }
+        interp_ok(true)
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+#[allow(non_snake_case)]
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    fn CreateThreadpoolWork(
+        &mut self,
+        callback_op: &OpTy<'tcx>,
+        context_op: &OpTy<'tcx>,
+        environment_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+        this.create_pool_object(
+            PoolObjectKind::Work,
+            callback_op,
+            context_op,
+            environment_op,
+            "CreateThreadpoolWork",
+        )
+    }
+
+    fn SubmitThreadpoolWork(&mut self, work_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let id = this.read_pool_object(work_op, "SubmitThreadpoolWork")?;
+
+        let obj = this.pool_object(id);
+        obj.outstanding = obj.outstanding.strict_add(1);
+        this.release_submit_clock(id)?;
+        this.spawn_worker(id)?;
+        interp_ok(())
+    }
+
+    fn WaitForThreadpoolWorkCallbacks(
+        &mut self,
+        work_op: &OpTy<'tcx>,
+        cancel_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let id = this.read_pool_object(work_op, "WaitForThreadpoolWorkCallbacks")?;
+        // Every submitted callback immediately gets a worker, so there are never any callbacks
+        // that have not started yet and could be cancelled. That is a legal outcome of the race
+        // between cancellation and the pool picking up callbacks.
+        this.read_scalar(cancel_op)?.to_i32()?;
+
+        this.wait_for_pool_callbacks(id)
+    }
+
+    fn CloseThreadpoolWork(&mut self, work_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        this.close_pool_object(work_op, "CloseThreadpoolWork")
+    }
+
+    fn CreateThreadpoolTimer(
+        &mut self,
+        callback_op: &OpTy<'tcx>,
+        context_op: &OpTy<'tcx>,
+        environment_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Pointer> {
+        let this = self.eval_context_mut();
+        this.create_pool_object(
+            PoolObjectKind::Timer {
+                due: None,
+                period: Duration::ZERO,
+                worker: None,
+                waiting: false,
+            },
+            callback_op,
+            context_op,
+            environment_op,
+            "CreateThreadpoolTimer",
+        )
+    }
+
+    fn SetThreadpoolTimer(
+        &mut self,
+        timer_op: &OpTy<'tcx>,
+        due_time_op: &OpTy<'tcx>,
+        period_op: &OpTy<'tcx>,
+        window_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let id = this.read_pool_object(timer_op, "SetThreadpoolTimer")?;
+        let due_time = this.read_pointer(due_time_op)?;
+        let period = Duration::from_millis(this.read_scalar(period_op)?.to_u32()?.into());
+        // The window only allows the system to batch timers, so we can ignore it.
+        this.read_scalar(window_op)?.to_u32()?;
+
+        // A null due time cancels the timer.
+        let due = if this.ptr_is_null(due_time)? {
+            None
+        } else {
+            Some(this.timer_due_time(due_time_op)?)
+        };
+        this.release_submit_clock(id)?;
+        this.update_timer(id, due, period)
+    }
+
+    fn IsThreadpoolTimerSet(&mut self, timer_op: &OpTy<'tcx>) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+        let id = this.read_pool_object(timer_op, "IsThreadpoolTimerSet")?;
+        let PoolObjectKind::Timer { due, .. } = this.pool_object(id).kind else {
+            throw_ub_format!("`IsThreadpoolTimerSet` called on a handle that is not a timer");
+        };
+        interp_ok(Scalar::from_i32(due.is_some().into()))
+    }
+
+    fn WaitForThreadpoolTimerCallbacks(
+        &mut self,
+        timer_op: &OpTy<'tcx>,
+        cancel_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let id = this.read_pool_object(timer_op, "WaitForThreadpoolTimerCallbacks")?;
+        let cancel = this.read_scalar(cancel_op)?.to_i32()? != 0;
+
+        if cancel {
+            this.update_timer(id, None, Duration::ZERO)?;
+        }
+        this.wait_for_pool_callbacks(id)
+    }
+
+    fn CloseThreadpoolTimer(&mut self, timer_op: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        this.close_pool_object(timer_op, "CloseThreadpoolTimer")
+    }
+
+    fn QueueUserAPC(
+        &mut self,
+        func_op: &OpTy<'tcx>,
+        thread_op: &OpTy<'tcx>,
+        data_op: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+
+        let func = this.read_pointer(func_op)?;
+        let thread = match this.read_handle(thread_op, "QueueUserAPC")? {
+            Handle::Thread(thread) => thread,
+            Handle::Pseudo(PseudoHandle::CurrentThread) => this.active_thread(),
+            _ => this.invalid_handle("QueueUserAPC")?,
+        };
+        let data = this.read_scalar(data_op)?;
+        // Check that the APC is a function pointer right away.
+        this.get_ptr_fn(func)?;
+
+        let clock = this.release_clock(|clock| clock.clone())?.unwrap_or_default();
+        this.machine.threadpool.apcs.entry(thread).or_default().push_back(Apc {
+            func,
+            data,
+            clock,
+        });
+        if this.machine.threadpool.alertable.remove(&thread) {
+            this.unblock_thread(thread, BlockReason::Alertable)?;
+        }
+
+        interp_ok(Scalar::from_u32(1))
+    }
+
+    fn SleepEx(
+        &mut self,
+        timeout_op: &OpTy<'tcx>,
+        alertable_op: &OpTy<'tcx>,
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+
+        let timeout_ms = this.read_scalar(timeout_op)?.to_u32()?;
+        let alertable = this.read_scalar(alertable_op)?.to_i32()? != 0;
+
+        let active_thread = this.active_thread();
+        let (reason, timeout) = if !alertable {
+            (BlockReason::Sleep, this.windows_timeout(timeout_ms))
+        } else {
+            this.machine.threadpool.alertable.insert(active_thread);
+            if this.machine.threadpool.apcs.contains_key(&active_thread) {
+                // The APCs have to run after we returned from this shim, so we wake up right away.
+                let timeout = (TimeoutClock::Monotonic, TimeoutAnchor::Relative, Duration::ZERO);
+                (BlockReason::Alertable, Some(timeout))
+            } else {
+                (BlockReason::Alertable, this.windows_timeout(timeout_ms))
+            }
+        };
+
+        this.block_thread(
+            reason,
+            timeout,
+            callback!(
+                @capture<'tcx> {
+                    alertable: bool,
+                    dest: MPlaceTy<'tcx>,
+                }
+                |this, _unblock: UnblockKind| {
+                    let active_thread = this.active_thread();
+                    this.machine.threadpool.alertable.remove(&active_thread);
+                    let ret = if alertable && this.run_queued_apcs()? {
+                        WAIT_IO_COMPLETION
+                    } else {
+                        0
+                    };
+                    this.write_scalar(Scalar::from_u32(ret), &dest)
+                }
+            ),
+        );
+        interp_ok(())
+    }
+}
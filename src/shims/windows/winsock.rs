@@ -0,0 +1,1315 @@
+//! Winsock (`ws2_32`), on top of the loopback network shared with the other targets. Sockets are
+//! file handles, but only the Winsock functions operate on them.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use rustc_abi::{Align, Size};
+
+use crate::shims::files::{FdNum, FileDescriptionRef};
+use crate::shims::network::{
+    self, EvalContextExt as _, LoopbackSocket, NetError, Received, SocketType,
+};
+use crate::shims::windows::handle::{EvalContextExt as _, Handle};
+use crate::*;
+
+// These are not all available in std's Windows bindings, so we define them ourselves.
+const AF_UNSPEC: i32 = 0;
+const AF_INET: i32 = 2;
+const AF_INET6: i32 = 23;
+const SOCK_STREAM: i32 = 1;
+const SOCK_DGRAM: i32 = 2;
+const IPPROTO_IP: i32 = 0;
+const IPPROTO_TCP: i32 = 6;
+const IPPROTO_UDP: i32 = 17;
+const IPPROTO_IPV6: i32 = 41;
+const SOL_SOCKET: i32 = 0xffff;
+const SO_REUSEADDR: i32 = 0x4;
+const SO_KEEPALIVE: i32 = 0x8;
+const SO_BROADCAST: i32 = 0x20;
+const SO_LINGER: i32 = 0x80;
+const SO_SNDBUF: i32 = 0x1001;
+const SO_RCVBUF: i32 = 0x1002;
+const SO_SNDTIMEO: i32 = 0x1005;
+const SO_RCVTIMEO: i32 = 0x1006;
+const SO_ERROR: i32 = 0x1007;
+const SO_TYPE: i32 = 0x1008;
+const TCP_NODELAY: i32 = 1;
+const IP_TTL: i32 = 4;
+const IP_MULTICAST_TTL: i32 = 10;
+const IP_MULTICAST_LOOP: i32 = 11;
+const IPV6_UNICAST_HOPS: i32 = 4;
+const IPV6_MULTICAST_HOPS: i32 = 10;
+const IPV6_MULTICAST_LOOP: i32 = 11;
+const IPV6_V6ONLY: i32 = 27;
+const MSG_PEEK: i32 = 0x2;
+const SD_RECEIVE: i32 = 0;
+const SD_SEND: i32 = 1;
+const SD_BOTH: i32 = 2;
+const FIONREAD: u32 = 0x4004_667F;
+const FIONBIO: u32 = 0x8004_667E;
+const WSA_FLAG_OVERLAPPED: u32 = 0x1;
+const WSA_FLAG_NO_HANDLE_INHERIT: u32 = 0x80;
+const AI_PASSIVE: i32 = 0x1;
+const AI_NUMERICHOST: i32 = 0x4;
+const SOCKET_ERROR: i32 = -1;
+
+// Winsock error codes.
+const WSAEINTR: i32 = 10004;
+const WSAEFAULT: i32 = 10014;
+const WSAEINVAL: i32 = 10022;
+const WSAENOTSOCK: i32 = 10038;
+const WSAEMSGSIZE: i32 = 10040;
+const WSAENOPROTOOPT: i32 = 10042;
+const WSAEPROTONOSUPPORT: i32 = 10043;
+const WSAESOCKTNOSUPPORT: i32 = 10044;
+const WSAEOPNOTSUPP: i32 = 10045;
+const WSAEAFNOSUPPORT: i32 = 10047;
+const WSAEISCONN: i32 = 10056;
+const WSAESHUTDOWN: i32 = 10058;
+const WSAVERNOTSUPPORTED: i32 = 10092;
+const WSANOTINITIALISED: i32 = 10093;
+const WSAHOST_NOT_FOUND: i32 = 11001;
+const WSATYPE_NOT_FOUND: i32 = 10109;
+
+/// The Winsock version we implement: 2.2.
+const WINSOCK_VERSION: u16 = 0x0202;
+
+/// The size of a `SOCKADDR_IN6`, the largest address we support.
+const SOCKADDR_IN6_SIZE: u64 = 28;
+
+fn wsa_error(code: i32) -> IoError {
+    IoError::Raw(Scalar::from_i32(code))
+}
+
+fn net_error_to_wsa(err: NetError) -> IoError {
+    match err {
+        NetError::AddrInUse => IoError::WindowsError("WSAEADDRINUSE"),
+        NetError::AddrNotAvailable => IoError::WindowsError("WSAEADDRNOTAVAIL"),
+        NetError::AlreadyConnected => wsa_error(WSAEISCONN),
+        NetError::Interrupted => wsa_error(WSAEINTR),
+        NetError::ConnectionRefused => IoError::WindowsError("WSAECONNREFUSED"),
+        NetError::ConnectionReset => IoError::WindowsError("WSAECONNRESET"),
+        NetError::InvalidInput => IoError::WindowsError("WSAEINVAL"),
+        NetError::MessageTooLong => wsa_error(WSAEMSGSIZE),
+        NetError::NetworkUnreachable => IoError::WindowsError("WSAENETUNREACH"),
+        NetError::NotConnected => IoError::WindowsError("WSAENOTCONN"),
+        NetError::Shutdown => wsa_error(WSAESHUTDOWN),
+        NetError::TimedOut => IoError::WindowsError("WSAETIMEDOUT"),
+        NetError::WouldBlock => IoError::WindowsError("WSAEWOULDBLOCK"),
+    }
+}
+
+/// The value of a socket option that is only stored, before the program sets it.
+fn option_default(level: i32, name: i32) -> Option<Vec<u8>> {
+    let int = |value: i32| Some(value.to_ne_bytes().to_vec());
+    match (level, name) {
+        (SOL_SOCKET, SO_REUSEADDR | SO_KEEPALIVE | SO_BROADCAST) => int(0),
+        (SOL_SOCKET, SO_SNDBUF | SO_RCVBUF) => int(65536),
+        // A `LINGER` with `l_onoff` and `l_linger` unset.
+        (SOL_SOCKET, SO_LINGER) => Some(vec![0; 4]),
+        (IPPROTO_TCP, TCP_NODELAY) => int(0),
+        (IPPROTO_IP, IP_TTL) => int(128),
+        (IPPROTO_IP, IP_MULTICAST_TTL | IP_MULTICAST_LOOP) => int(1),
+        (IPPROTO_IPV6, IPV6_UNICAST_HOPS) => int(128),
+        (IPPROTO_IPV6, IPV6_MULTICAST_HOPS | IPV6_MULTICAST_LOOP | IPV6_V6ONLY) => int(1),
+        _ => None,
+    }
+}
+
+fn timeout_to_millis(timeout: Option<Duration>) -> u32 {
+    timeout.map_or(0, |timeout| u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX))
+}
+
+impl<'tcx> EvalContextExtPriv<'tcx> for crate::MiriInterpCx<'tcx> {}
+trait EvalContextExtPriv<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    /// Checks that `WSAStartup` was called, and sets the last error otherwise.
+    fn winsock_started(&mut self) -> InterpResult<'tcx, bool> {
+        let this = self.eval_context_mut();
+        if this.machine.winsock_startups == 0 {
+            this.set_last_error(wsa_error(WSANOTINITIALISED))?;
+            return interp_ok(false);
+        }
+        interp_ok(true)
+    }
+
+    /// Reads a `SOCKET`, and returns its file descriptor number along with the socket. Returns
+    /// `None` and sets the last error if it is not a socket, or if `WSAStartup` was not called.
+    fn read_socket_fd(
+        &mut self,
+        socket: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Option<(FdNum, FileDescriptionRef<LoopbackSocket>)>> {
+        let this = self.eval_context_mut();
+        if !this.winsock_started()? {
+            return interp_ok(None);
+        }
+        let socket = match this.read_handle_lenient(socket)? {
+            Some(Handle::File(fd_num)) =>
+                this.machine
+                    .fds
+                    .get(fd_num)
+                    .and_then(|fd| fd.downcast::<LoopbackSocket>())
+                    .map(|socket| (fd_num, socket)),
+            _ => None,
+        };
+        if socket.is_none() {
+            this.set_last_error(wsa_error(WSAENOTSOCK))?;
+        }
+        interp_ok(socket)
+    }
+
+    /// Like `read_socket_fd`, but only returns the socket.
+    fn read_socket(
+        &mut self,
+        socket: &OpTy<'tcx>,
+    ) -> InterpResult<'tcx, Option<FileDescriptionRef<LoopbackSocket>>> {
+        let this = self.eval_context_mut();
+        interp_ok(this.read_socket_fd(socket)?.map(|(_, socket)| socket))
+    }
+
+    /// Reads a `SOCKADDR` of `len` bytes for a socket of the given family.
+    fn read_sockaddr(
+        &self,
+        ptr: Pointer,
+        len: i32,
+        ipv6: bool,
+    ) -> InterpResult<'tcx, Result<SocketAddr, IoError>> {
+        let this = self.eval_context_ref();
+        let Ok(len) = u64::try_from(len) else { return interp_ok(Err(wsa_error(WSAEFAULT))) };
+        if len < 2 {
+            return interp_ok(Err(wsa_error(WSAEFAULT)));
+        }
+        let bytes = this
+            .read_bytes_ptr_strip_provenance(ptr, Size::from_bytes(len.min(SOCKADDR_IN6_SIZE)))?;
+        let family = i32::from(u16::from_ne_bytes([bytes[0], bytes[1]]));
+        if family != (if ipv6 { AF_INET6 } else { AF_INET }) {
+            return interp_ok(Err(wsa_error(WSAEAFNOSUPPORT)));
+        }
+        let addr = network::sockaddr_from_bytes(bytes, AF_INET as u16, AF_INET6 as u16);
+        interp_ok(addr.ok_or_else(|| wsa_error(WSAEFAULT)))
+    }
+
+    /// Writes a `SOCKADDR` to `ptr`, which has room for as many bytes as `len_ptr` (an `int*`)
+    /// points to. `len_ptr` is updated to the size of the address.
+    fn write_sockaddr(
+        &mut self,
+        addr: SocketAddr,
+        ptr: Pointer,
+        len_ptr: Pointer,
+    ) -> InterpResult<'tcx, Result<(), IoError>> {
+        let this = self.eval_context_mut();
+        if this.ptr_is_null(ptr)? || this.ptr_is_null(len_ptr)? {
+            return interp_ok(Err(wsa_error(WSAEFAULT)));
+        }
+        let len_place = this.ptr_to_mplace(len_ptr, this.machine.layouts.i32);
+        let len = this.read_scalar(&len_place)?.to_i32()?;
+        let bytes = network::sockaddr_to_bytes(addr, AF_INET as u16, AF_INET6 as u16);
+        let size = i32::try_from(bytes.len()).unwrap();
+        if len < size {
+            return interp_ok(Err(wsa_error(WSAEFAULT)));
+        }
+        this.write_bytes_ptr(ptr, bytes)?;
+        this.write_int(size, &len_place)?;
+        interp_ok(Ok(()))
+    }
+
+    /// Reads the flags of a receive operation, and returns whether to peek.
+    fn read_recv_flags(&self, flags: i32, function_name: &str) -> InterpResult<'tcx, bool> {
+        if flags & !MSG_PEEK != 0 {
+            throw_unsup_format!("`{function_name}`: unsupported flags {flags:#x}");
+        }
+        interp_ok(flags & MSG_PEEK != 0)
+    }
+
+    /// Reads the `WSABUF` array of `WSASend` and `WSARecv`.
+    fn read_wsabufs(&self, bufs: Pointer, count: u32) -> InterpResult<'tcx, Vec<(Pointer, u64)>> {
+        let this = self.eval_context_ref();
+        let layout = this.windows_ty_layout("WSABUF");
+        let mut result = Vec::new();
+        for i in 0..u64::from(count) {
+            let place = this.ptr_to_mplace(bufs.wrapping_offset(layout.size * i, this), layout);
+            let len = this.read_scalar(&this.project_field_named(&place, "len")?)?.to_u32()?;
+            let buf = this.read_pointer(&this.project_field_named(&place, "buf")?)?;
+            result.push((buf, u64::from(len)));
+        }
+        interp_ok(result)
+    }
+
+    /// Completes `recv`, `recvfrom` and `WSARecv`. For `WSARecv`, `bufs` is the `WSABUF` array
+    /// and `received_ptr` receives the number of bytes; otherwise `bufs` is the single buffer, and
+    /// the number of bytes is returned.
+    fn finish_recv(
+        &mut self,
+        result: Result<Received, NetError>,
+        buffers: Vec<(Pointer, u64)>,
+        from: Pointer,
+        from_len: Pointer,
+        received_ptr: Option<Pointer>,
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let received = match result {
+            Ok(received) => received,
+            Err(err) => return this.set_last_error_and_return(net_error_to_wsa(err), dest),
+        };
+
+        let mut data = received.data.as_slice();
+        for (buf, len) in buffers {
+            let (chunk, rest) = data.split_at(data.len().min(usize::try_from(len).unwrap()));
+            this.write_bytes_ptr(buf, chunk.iter().copied())?;
+            data = rest;
+        }
+        if let Some(addr) = received.from
+            && !this.ptr_is_null(from)?
+            && let Err(err) = this.write_sockaddr(addr, from, from_len)?
+        {
+            return this.set_last_error_and_return(err, dest);
+        }
+        // The truncated datagram is still delivered, but reported as an error.
+        if received.truncated {
+            return this.set_last_error_and_return(wsa_error(WSAEMSGSIZE), dest);
+        }
+
+        let len = i32::try_from(received.data.len()).unwrap();
+        match received_ptr {
+            Some(received_ptr) => {
+                let received_place = this.ptr_to_mplace(received_ptr, this.machine.layouts.u32);
+                this.write_int(len, &received_place)?;
+                this.write_null(dest)
+            }
+            None => this.write_int(len, dest),
+        }
+    }
+
+    /// Implements `recv` and `recvfrom`.
+    fn recvfrom_impl(
+        &mut self,
+        socket: &OpTy<'tcx>,
+        buf: Pointer,
+        len: i32,
+        flags: i32,
+        from: Pointer,
+        from_len: Pointer,
+        dest: &MPlaceTy<'tcx>,
+        function_name: &str,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let Some(socket) = this.read_socket(socket)? else {
+            return this.write_int(SOCKET_ERROR, dest);
+        };
+        let peek = this.read_recv_flags(flags, function_name)?;
+        let Ok(len) = u64::try_from(len) else {
+            return this.set_last_error_and_return(wsa_error(WSAEINVAL), dest);
+        };
+        this.socket_recv(
+            socket,
+            usize::try_from(len).unwrap(),
+            peek,
+            callback!(
+                @capture<'tcx> {
+                    buf: Pointer,
+                    len: u64,
+                    from: Pointer,
+                    from_len: Pointer,
+                    dest: MPlaceTy<'tcx>,
+                }
+                |this, result: Result<Received, NetError>| {
+                    this.finish_recv(result, vec![(buf, len)], from, from_len, None, &dest)
+                }
+            ),
+        )
+    }
+
+    /// Reports which of the sockets in the `FD_SET`s are ready, like `select` does. Blocks until
+    /// one of them is ready if none is, and the deadline has not passed yet.
+    fn select_until(
+        &mut self,
+        sets: [Pointer; 3],
+        deadline: Option<Duration>,
+        dest: MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        let this = self.eval_context_mut();
+        let fd_set_layout = this.windows_ty_layout("FD_SET");
+
+        // Which sockets of each set are ready. Exceptional conditions never occur on the loopback
+        // network.
+        let mut ready = [Vec::new(), Vec::new(), Vec::new()];
+        for (set_idx, set) in sets.into_iter().enumerate() {
+            if this.ptr_is_null(set)? {
+                continue;
+            }
+            let set = this.ptr_to_mplace(set, fd_set_layout);
+            let count = this.read_scalar(&this.project_field_named(&set, "fd_count")?)?.to_u32()?;
+            let array = this.project_field_named(&set, "fd_array")?;
+            if u64::from(count) > array.len(this)? {
+                return this.set_last_error_and_return(wsa_error(WSAEINVAL), &dest);
+            }
+            for i in 0..u64::from(count) {
+                let entry = this.project_index(&array, i)?;
+                let socket = this.read_scalar(&entry)?;
+                let Some(desc) = this.read_socket(&entry.into())? else {
+                    return this.write_int(SOCKET_ERROR, &dest);
+                };
+                let readiness = desc.readiness();
+                let is_ready = match set_idx {
+                    0 => readiness.readable,
+                    1 => readiness.writable,
+                    _ => false,
+                };
+                if is_ready {
+                    ready[set_idx].push(socket);
+                }
+            }
+        }
+
+        let total: usize = ready.iter().map(Vec::len).sum();
+        let now = this.machine.monotonic_clock.elapsed();
+        if total == 0 && deadline.is_none_or(|deadline| deadline > now) {
+            let [read_set, write_set, except_set] = sets;
+            this.block_on_network(
+                deadline,
+                callback!(
+                    @capture<'tcx> {
+                        read_set: Pointer,
+                        write_set: Pointer,
+                        except_set: Pointer,
+                        deadline: Option<Duration>,
+                        dest: MPlaceTy<'tcx>,
+                    }
+                    |this, unblock: UnblockKind| {
+                        match unblock {
+                            UnblockKind::Ready =>
+                                this.select_until([read_set, write_set, except_set], deadline, dest),
+                            // Report that nothing is ready.
+                            UnblockKind::TimedOut =>
+                                this.select_until(
+                                    [read_set, write_set, except_set],
+                                    Some(Duration::ZERO),
+                                    dest,
+                                ),
+                        }
+                    }
+                ),
+            );
+            return interp_ok(());
+        }
+
+        // Leave only the ready sockets in the sets.
+        for (set, ready) in sets.into_iter().zip(ready) {
+            if this.ptr_is_null(set)? {
+                continue;
+            }
+            let set = this.ptr_to_mplace(set, fd_set_layout);
+            this.write_int(
+                u32::try_from(ready.len()).unwrap(),
+                &this.project_field_named(&set, "fd_count")?,
+            )?;
+            let array = this.project_field_named(&set, "fd_array")?;
+            for (i, socket) in ready.into_iter().enumerate() {
+                this.write_scalar(socket, &this.project_index(&array, u64::try_from(i).unwrap())?)?;
+            }
+        }
+        this.write_int(i32::try_from(total).unwrap(), &dest)
+    }
+
+    /// Creates a socket for `socket` and `WSASocketW`.
+    fn create_socket(&mut self, af: i32, ty: i32, protocol: i32) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+        let ipv6 = match af {
+            AF_INET => false,
+            AF_INET6 => true,
+            _ => return this.invalid_socket(wsa_error(WSAEAFNOSUPPORT)),
+        };
+        let ty = match (ty, protocol) {
+            (SOCK_STREAM, 0 | IPPROTO_TCP) => SocketType::Stream,
+            (SOCK_DGRAM, 0 | IPPROTO_UDP) => SocketType::Datagram,
+            (SOCK_STREAM | SOCK_DGRAM, _) =>
+                return this.invalid_socket(wsa_error(WSAEPROTONOSUPPORT)),
+            _ => return this.invalid_socket(wsa_error(WSAESOCKTNOSUPPORT)),
+        };
+        let fd_num = this.machine.fds.insert_new(LoopbackSocket::new(ty, ipv6));
+        interp_ok(Handle::File(fd_num).to_scalar(this))
+    }
+
+    /// Sets the last error, and returns `INVALID_SOCKET`.
+    fn invalid_socket(&mut self, err: IoError) -> InterpResult<'tcx, Scalar> {
+        let this = self.eval_context_mut();
+        this.set_last_error(err)?;
+        interp_ok(Scalar::from_target_isize(-1, this))
+    }
+}
+
+impl<'tcx> EvalContextExt<'tcx> for crate::MiriInterpCx<'tcx> {}
+#[allow(non_snake_case)]
+pub trait EvalContextExt<'tcx>: crate::MiriInterpCxExt<'tcx> {
+    fn WSAStartup(
+        &mut self,
+        version: &OpTy<'tcx>, // WORD
+        data: &OpTy<'tcx>,    // LPWSADATA
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let version = this.read_scalar(version)?.to_u16()?;
+        let data = this.read_pointer(data)?;
+
+        // The low byte is the major version. We only implement Winsock 2.
+        if version & 0xff < 2 {
+            return interp_ok(Scalar::from_i32(WSAVERNOTSUPPORTED));
+        }
+        if this.ptr_is_null(data)? {
+            return interp_ok(Scalar::from_i32(WSAEFAULT));
+        }
+        let data = this.ptr_to_mplace(data, this.windows_ty_layout("WSADATA"));
+        this.write_bytes_ptr(data.ptr(), std::iter::repeat_n(0u8, data.layout.size.bytes_usize()))?;
+        this.write_int_fields_named(
+            &[("wVersion", WINSOCK_VERSION.into()), ("wHighVersion", WINSOCK_VERSION.into())],
+            &data,
+        )?;
+        this.machine.winsock_startups = this.machine.winsock_startups.strict_add(1);
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn WSACleanup(&mut self) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        if !this.winsock_started()? {
+            return interp_ok(Scalar::from_i32(SOCKET_ERROR));
+        }
+        this.machine.winsock_startups = this.machine.winsock_startups.strict_sub(1);
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn socket(
+        &mut self,
+        af: &OpTy<'tcx>,       // int
+        ty: &OpTy<'tcx>,       // int
+        protocol: &OpTy<'tcx>, // int
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns SOCKET
+        let this = self.eval_context_mut();
+        let af = this.read_scalar(af)?.to_i32()?;
+        let ty = this.read_scalar(ty)?.to_i32()?;
+        let protocol = this.read_scalar(protocol)?.to_i32()?;
+        if !this.winsock_started()? {
+            return interp_ok(Scalar::from_target_isize(-1, this));
+        }
+        this.create_socket(af, ty, protocol)
+    }
+
+    fn WSASocketW(
+        &mut self,
+        af: &OpTy<'tcx>,            // int
+        ty: &OpTy<'tcx>,            // int
+        protocol: &OpTy<'tcx>,      // int
+        protocol_info: &OpTy<'tcx>, // LPWSAPROTOCOL_INFOW
+        group: &OpTy<'tcx>,         // GROUP
+        flags: &OpTy<'tcx>,         // DWORD
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns SOCKET
+        let this = self.eval_context_mut();
+        let af = this.read_scalar(af)?.to_i32()?;
+        let ty = this.read_scalar(ty)?.to_i32()?;
+        let protocol = this.read_scalar(protocol)?.to_i32()?;
+        let protocol_info = this.read_pointer(protocol_info)?;
+        let group = this.read_scalar(group)?.to_u32()?;
+        let flags = this.read_scalar(flags)?.to_u32()?;
+
+        if !this.winsock_started()? {
+            return interp_ok(Scalar::from_target_isize(-1, this));
+        }
+        if group != 0 {
+            throw_unsup_format!("`WSASocketW`: socket groups are not supported");
+        }
+        // Overlapped operations are not supported, but the sockets work fine for synchronous
+        // operations. We do not support `CreateProcess`, so inheritance does not matter.
+        if flags & !(WSA_FLAG_OVERLAPPED | WSA_FLAG_NO_HANDLE_INHERIT) != 0 {
+            throw_unsup_format!("`WSASocketW`: unsupported flags {flags:#x}");
+        }
+
+        if !this.ptr_is_null(protocol_info)? {
+            // This is the second half of duplicating a socket: `WSADuplicateSocketW` recorded
+            // which socket to duplicate.
+            let info =
+                this.ptr_to_mplace(protocol_info, this.windows_ty_layout("WSAPROTOCOL_INFOW"));
+            let fd_num = this
+                .read_scalar(&this.project_field_named(&info, "dwProviderReserved")?)?
+                .to_u32()?;
+            let socket = i32::try_from(fd_num)
+                .ok()
+                .and_then(|fd_num| this.machine.fds.get(fd_num))
+                .filter(|fd| fd.clone().downcast::<LoopbackSocket>().is_some());
+            let Some(socket) = socket else {
+                return this.invalid_socket(wsa_error(WSAEINVAL));
+            };
+            return interp_ok(Handle::File(this.machine.fds.insert(socket)).to_scalar(this));
+        }
+        this.create_socket(af, ty, protocol)
+    }
+
+    fn WSADuplicateSocketW(
+        &mut self,
+        socket: &OpTy<'tcx>,        // SOCKET
+        process_id: &OpTy<'tcx>,    // DWORD
+        protocol_info: &OpTy<'tcx>, // LPWSAPROTOCOL_INFOW
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let process_id = this.read_scalar(process_id)?.to_u32()?;
+        let protocol_info = this.read_pointer(protocol_info)?;
+        let Some((fd_num, desc)) = this.read_socket_fd(socket)? else {
+            return interp_ok(Scalar::from_i32(SOCKET_ERROR));
+        };
+        if process_id != this.get_pid() {
+            throw_unsup_format!(
+                "`WSADuplicateSocketW`: sharing sockets with other processes is not supported"
+            );
+        }
+        if this.ptr_is_null(protocol_info)? {
+            return this.set_last_error_and_return_i32(wsa_error(WSAEFAULT));
+        }
+
+        // `WSASocketW` does the actual duplication, using the socket we record in the reserved
+        // field here.
+        let info = this.ptr_to_mplace(protocol_info, this.windows_ty_layout("WSAPROTOCOL_INFOW"));
+        this.write_bytes_ptr(info.ptr(), std::iter::repeat_n(0u8, info.layout.size.bytes_usize()))?;
+        let (ty, protocol) = match desc.ty {
+            SocketType::Stream => (SOCK_STREAM, IPPROTO_TCP),
+            SocketType::Datagram => (SOCK_DGRAM, IPPROTO_UDP),
+        };
+        this.write_int_fields_named(
+            &[
+                ("iAddressFamily", (if desc.ipv6 { AF_INET6 } else { AF_INET }).into()),
+                ("iSocketType", ty.into()),
+                ("iProtocol", protocol.into()),
+                ("dwProviderReserved", fd_num.into()),
+            ],
+            &info,
+        )?;
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn closesocket(&mut self, socket: &OpTy<'tcx>) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let Some((fd_num, desc)) = this.read_socket_fd(socket)? else {
+            return interp_ok(Scalar::from_i32(SOCKET_ERROR));
+        };
+        drop(desc);
+        let fd = this.machine.fds.remove(fd_num).unwrap();
+        if let Err(err) = fd.close_ref(this.machine.communicate(), this)? {
+            return this.set_last_error_and_return_i32(err);
+        }
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn bind(
+        &mut self,
+        socket: &OpTy<'tcx>,   // SOCKET
+        addr: &OpTy<'tcx>,     // const SOCKADDR*
+        addr_len: &OpTy<'tcx>, // int
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let addr = this.read_pointer(addr)?;
+        let addr_len = this.read_scalar(addr_len)?.to_i32()?;
+        let Some(socket) = this.read_socket(socket)? else {
+            return interp_ok(Scalar::from_i32(SOCKET_ERROR));
+        };
+        let addr = match this.read_sockaddr(addr, addr_len, socket.ipv6)? {
+            Ok(addr) => addr,
+            Err(err) => return this.set_last_error_and_return_i32(err),
+        };
+        match this.socket_bind(&socket, addr) {
+            Ok(()) => interp_ok(Scalar::from_i32(0)),
+            Err(err) => this.set_last_error_and_return_i32(net_error_to_wsa(err)),
+        }
+    }
+
+    fn listen(
+        &mut self,
+        socket: &OpTy<'tcx>,  // SOCKET
+        backlog: &OpTy<'tcx>, // int
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        // The backlog is only a hint; we never refuse connections.
+        let _ = this.read_scalar(backlog)?.to_i32()?;
+        let Some(socket) = this.read_socket(socket)? else {
+            return interp_ok(Scalar::from_i32(SOCKET_ERROR));
+        };
+        if socket.ty != SocketType::Stream {
+            return this.set_last_error_and_return_i32(wsa_error(WSAEOPNOTSUPP));
+        }
+        match this.socket_listen(&socket) {
+            Ok(()) => interp_ok(Scalar::from_i32(0)),
+            Err(err) => this.set_last_error_and_return_i32(net_error_to_wsa(err)),
+        }
+    }
+
+    fn connect(
+        &mut self,
+        socket: &OpTy<'tcx>,   // SOCKET
+        addr: &OpTy<'tcx>,     // const SOCKADDR*
+        addr_len: &OpTy<'tcx>, // int
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let addr = this.read_pointer(addr)?;
+        let addr_len = this.read_scalar(addr_len)?.to_i32()?;
+        let Some(socket) = this.read_socket(socket)? else {
+            return interp_ok(Scalar::from_i32(SOCKET_ERROR));
+        };
+        let addr = match this.read_sockaddr(addr, addr_len, socket.ipv6)? {
+            Ok(addr) => addr,
+            Err(err) => return this.set_last_error_and_return_i32(err),
+        };
+        match this.socket_connect(&socket, addr)? {
+            Ok(()) => interp_ok(Scalar::from_i32(0)),
+            Err(err) => this.set_last_error_and_return_i32(net_error_to_wsa(err)),
+        }
+    }
+
+    fn accept(
+        &mut self,
+        socket: &OpTy<'tcx>,   // SOCKET
+        addr: &OpTy<'tcx>,     // SOCKADDR*
+        addr_len: &OpTy<'tcx>, // int*
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        // ^ Returns SOCKET
+        let this = self.eval_context_mut();
+        let addr = this.read_pointer(addr)?;
+        let addr_len = this.read_pointer(addr_len)?;
+        let Some(socket) = this.read_socket(socket)? else {
+            return this.write_scalar(Scalar::from_target_isize(-1, this), dest);
+        };
+        this.socket_accept(
+            socket,
+            callback!(
+                @capture<'tcx> {
+                    addr: Pointer,
+                    addr_len: Pointer,
+                    dest: MPlaceTy<'tcx>,
+                }
+                |this, result: Result<FileDescriptionRef<LoopbackSocket>, NetError>| {
+                    let connection = match result {
+                        Ok(connection) => connection,
+                        Err(err) => {
+                            let socket = this.invalid_socket(net_error_to_wsa(err))?;
+                            return this.write_scalar(socket, &dest);
+                        }
+                    };
+                    if !this.ptr_is_null(addr)? {
+                        let peer = connection.peer_addr().unwrap();
+                        if let Err(err) = this.write_sockaddr(peer, addr, addr_len)? {
+                            // The connection is lost.
+                            connection.close_ref(this.machine.communicate(), this)?.ok();
+                            let socket = this.invalid_socket(err)?;
+                            return this.write_scalar(socket, &dest);
+                        }
+                    }
+                    let fd_num = this.machine.fds.insert(connection);
+                    this.write_scalar(Handle::File(fd_num).to_scalar(this), &dest)
+                }
+            ),
+        )
+    }
+
+    fn send(
+        &mut self,
+        socket: &OpTy<'tcx>, // SOCKET
+        buf: &OpTy<'tcx>,    // const char*
+        len: &OpTy<'tcx>,    // int
+        flags: &OpTy<'tcx>,  // int
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let buf = this.read_pointer(buf)?;
+        let len = this.read_scalar(len)?.to_i32()?;
+        let flags = this.read_scalar(flags)?.to_i32()?;
+        let Some(socket) = this.read_socket(socket)? else {
+            return interp_ok(Scalar::from_i32(SOCKET_ERROR));
+        };
+        if flags != 0 {
+            throw_unsup_format!("`send`: unsupported flags {flags:#x}");
+        }
+        let Ok(len) = u64::try_from(len) else {
+            return this.set_last_error_and_return_i32(wsa_error(WSAEINVAL));
+        };
+        let data = this.read_bytes_ptr_strip_provenance(buf, Size::from_bytes(len))?.to_vec();
+        match this.socket_send(&socket, &data)? {
+            Ok(sent) => interp_ok(Scalar::from_i32(i32::try_from(sent).unwrap())),
+            Err(err) => this.set_last_error_and_return_i32(net_error_to_wsa(err)),
+        }
+    }
+
+    fn sendto(
+        &mut self,
+        socket: &OpTy<'tcx>, // SOCKET
+        buf: &OpTy<'tcx>,    // const char*
+        len: &OpTy<'tcx>,    // int
+        flags: &OpTy<'tcx>,  // int
+        to: &OpTy<'tcx>,     // const SOCKADDR*
+        to_len: &OpTy<'tcx>, // int
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let buf = this.read_pointer(buf)?;
+        let len = this.read_scalar(len)?.to_i32()?;
+        let flags = this.read_scalar(flags)?.to_i32()?;
+        let to = this.read_pointer(to)?;
+        let to_len = this.read_scalar(to_len)?.to_i32()?;
+        let Some(socket) = this.read_socket(socket)? else {
+            return interp_ok(Scalar::from_i32(SOCKET_ERROR));
+        };
+        if flags != 0 {
+            throw_unsup_format!("`sendto`: unsupported flags {flags:#x}");
+        }
+        let Ok(len) = u64::try_from(len) else {
+            return this.set_last_error_and_return_i32(wsa_error(WSAEINVAL));
+        };
+        let to = match this.read_sockaddr(to, to_len, socket.ipv6)? {
+            Ok(to) => to,
+            Err(err) => return this.set_last_error_and_return_i32(err),
+        };
+        let data = this.read_bytes_ptr_strip_provenance(buf, Size::from_bytes(len))?.to_vec();
+        match this.socket_send_to(&socket, &data, to)? {
+            Ok(sent) => interp_ok(Scalar::from_i32(i32::try_from(sent).unwrap())),
+            Err(err) => this.set_last_error_and_return_i32(net_error_to_wsa(err)),
+        }
+    }
+
+    fn WSASend(
+        &mut self,
+        socket: &OpTy<'tcx>,             // SOCKET
+        buffers: &OpTy<'tcx>,            // LPWSABUF
+        buffer_count: &OpTy<'tcx>,       // DWORD
+        bytes_sent: &OpTy<'tcx>,         // LPDWORD
+        flags: &OpTy<'tcx>,              // DWORD
+        overlapped: &OpTy<'tcx>,         // LPWSAOVERLAPPED
+        completion_routine: &OpTy<'tcx>, // LPWSAOVERLAPPED_COMPLETION_ROUTINE
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let buffers = this.read_pointer(buffers)?;
+        let buffer_count = this.read_scalar(buffer_count)?.to_u32()?;
+        let bytes_sent = this.read_pointer(bytes_sent)?;
+        let flags = this.read_scalar(flags)?.to_u32()?;
+        let overlapped = this.read_pointer(overlapped)?;
+        let completion_routine = this.read_pointer(completion_routine)?;
+        let Some(socket) = this.read_socket(socket)? else {
+            return interp_ok(Scalar::from_i32(SOCKET_ERROR));
+        };
+        if !this.ptr_is_null(overlapped)? || !this.ptr_is_null(completion_routine)? {
+            throw_unsup_format!("`WSASend`: overlapped operation is not supported");
+        }
+        if flags != 0 {
+            throw_unsup_format!("`WSASend`: unsupported flags {flags:#x}");
+        }
+
+        let mut data = Vec::new();
+        for (buf, len) in this.read_wsabufs(buffers, buffer_count)? {
+            data.extend_from_slice(
+                this.read_bytes_ptr_strip_provenance(buf, Size::from_bytes(len))?,
+            );
+        }
+        match this.socket_send(&socket, &data)? {
+            Ok(sent) => {
+                let bytes_sent = this.ptr_to_mplace(bytes_sent, this.machine.layouts.u32);
+                this.write_int(u32::try_from(sent).unwrap(), &bytes_sent)?;
+                interp_ok(Scalar::from_i32(0))
+            }
+            Err(err) => this.set_last_error_and_return_i32(net_error_to_wsa(err)),
+        }
+    }
+
+    fn recv(
+        &mut self,
+        socket: &OpTy<'tcx>, // SOCKET
+        buf: &OpTy<'tcx>,    // char*
+        len: &OpTy<'tcx>,    // int
+        flags: &OpTy<'tcx>,  // int
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let buf = this.read_pointer(buf)?;
+        let len = this.read_scalar(len)?.to_i32()?;
+        let flags = this.read_scalar(flags)?.to_i32()?;
+        this.recvfrom_impl(socket, buf, len, flags, Pointer::null(), Pointer::null(), dest, "recv")
+    }
+
+    fn recvfrom(
+        &mut self,
+        socket: &OpTy<'tcx>,   // SOCKET
+        buf: &OpTy<'tcx>,      // char*
+        len: &OpTy<'tcx>,      // int
+        flags: &OpTy<'tcx>,    // int
+        from: &OpTy<'tcx>,     // SOCKADDR*
+        from_len: &OpTy<'tcx>, // int*
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let buf = this.read_pointer(buf)?;
+        let len = this.read_scalar(len)?.to_i32()?;
+        let flags = this.read_scalar(flags)?.to_i32()?;
+        let from = this.read_pointer(from)?;
+        let from_len = this.read_pointer(from_len)?;
+        this.recvfrom_impl(socket, buf, len, flags, from, from_len, dest, "recvfrom")
+    }
+
+    fn WSARecv(
+        &mut self,
+        socket: &OpTy<'tcx>,             // SOCKET
+        buffers: &OpTy<'tcx>,            // LPWSABUF
+        buffer_count: &OpTy<'tcx>,       // DWORD
+        bytes_received: &OpTy<'tcx>,     // LPDWORD
+        flags: &OpTy<'tcx>,              // LPDWORD
+        overlapped: &OpTy<'tcx>,         // LPWSAOVERLAPPED
+        completion_routine: &OpTy<'tcx>, // LPWSAOVERLAPPED_COMPLETION_ROUTINE
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let buffers = this.read_pointer(buffers)?;
+        let buffer_count = this.read_scalar(buffer_count)?.to_u32()?;
+        let bytes_received = this.read_pointer(bytes_received)?;
+        let flags = this.deref_pointer_as(flags, this.machine.layouts.u32)?;
+        let overlapped = this.read_pointer(overlapped)?;
+        let completion_routine = this.read_pointer(completion_routine)?;
+        let Some(socket) = this.read_socket(socket)? else {
+            return this.write_int(SOCKET_ERROR, dest);
+        };
+        if !this.ptr_is_null(overlapped)? || !this.ptr_is_null(completion_routine)? {
+            throw_unsup_format!("`WSARecv`: overlapped operation is not supported");
+        }
+        let peek = this.read_recv_flags(this.read_scalar(&flags)?.to_i32()?, "WSARecv")?;
+        // On return, the flags would report partial messages, which we never produce.
+        this.write_null(&flags)?;
+
+        let len =
+            this.read_wsabufs(buffers, buffer_count)?.iter().map(|&(_, len)| len).sum::<u64>();
+        this.socket_recv(
+            socket,
+            usize::try_from(len).unwrap(),
+            peek,
+            callback!(
+                @capture<'tcx> {
+                    buffers: Pointer,
+                    buffer_count: u32,
+                    bytes_received: Pointer,
+                    dest: MPlaceTy<'tcx>,
+                }
+                |this, result: Result<Received, NetError>| {
+                    // The buffers are still valid, as the program is blocked in this call.
+                    let buffers = this.read_wsabufs(buffers, buffer_count)?;
+                    this.finish_recv(
+                        result,
+                        buffers,
+                        Pointer::null(),
+                        Pointer::null(),
+                        Some(bytes_received),
+                        &dest,
+                    )
+                }
+            ),
+        )
+    }
+
+    fn shutdown(
+        &mut self,
+        socket: &OpTy<'tcx>, // SOCKET
+        how: &OpTy<'tcx>,    // int
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let how = this.read_scalar(how)?.to_i32()?;
+        let Some(socket) = this.read_socket(socket)? else {
+            return interp_ok(Scalar::from_i32(SOCKET_ERROR));
+        };
+        let (read, write) = match how {
+            SD_RECEIVE => (true, false),
+            SD_SEND => (false, true),
+            SD_BOTH => (true, true),
+            _ => return this.set_last_error_and_return_i32(wsa_error(WSAEINVAL)),
+        };
+        match this.socket_shutdown(&socket, read, write)? {
+            Ok(()) => interp_ok(Scalar::from_i32(0)),
+            Err(err) => this.set_last_error_and_return_i32(net_error_to_wsa(err)),
+        }
+    }
+
+    fn getsockname(
+        &mut self,
+        socket: &OpTy<'tcx>,   // SOCKET
+        addr: &OpTy<'tcx>,     // SOCKADDR*
+        addr_len: &OpTy<'tcx>, // int*
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let addr = this.read_pointer(addr)?;
+        let addr_len = this.read_pointer(addr_len)?;
+        let Some(socket) = this.read_socket(socket)? else {
+            return interp_ok(Scalar::from_i32(SOCKET_ERROR));
+        };
+        let Some(local) = socket.local_addr() else {
+            return this.set_last_error_and_return_i32(wsa_error(WSAEINVAL));
+        };
+        match this.write_sockaddr(local, addr, addr_len)? {
+            Ok(()) => interp_ok(Scalar::from_i32(0)),
+            Err(err) => this.set_last_error_and_return_i32(err),
+        }
+    }
+
+    fn getpeername(
+        &mut self,
+        socket: &OpTy<'tcx>,   // SOCKET
+        addr: &OpTy<'tcx>,     // SOCKADDR*
+        addr_len: &OpTy<'tcx>, // int*
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let addr = this.read_pointer(addr)?;
+        let addr_len = this.read_pointer(addr_len)?;
+        let Some(socket) = this.read_socket(socket)? else {
+            return interp_ok(Scalar::from_i32(SOCKET_ERROR));
+        };
+        let Some(peer) = socket.peer_addr() else {
+            return this.set_last_error_and_return_i32(IoError::WindowsError("WSAENOTCONN"));
+        };
+        match this.write_sockaddr(peer, addr, addr_len)? {
+            Ok(()) => interp_ok(Scalar::from_i32(0)),
+            Err(err) => this.set_last_error_and_return_i32(err),
+        }
+    }
+
+    fn setsockopt(
+        &mut self,
+        socket: &OpTy<'tcx>, // SOCKET
+        level: &OpTy<'tcx>,  // int
+        name: &OpTy<'tcx>,   // int
+        value: &OpTy<'tcx>,  // const char*
+        len: &OpTy<'tcx>,    // int
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let level = this.read_scalar(level)?.to_i32()?;
+        let name = this.read_scalar(name)?.to_i32()?;
+        let value = this.read_pointer(value)?;
+        let len = this.read_scalar(len)?.to_i32()?;
+        let Some(socket) = this.read_socket(socket)? else {
+            return interp_ok(Scalar::from_i32(SOCKET_ERROR));
+        };
+        let Ok(len) = u64::try_from(len) else {
+            return this.set_last_error_and_return_i32(wsa_error(WSAEFAULT));
+        };
+        let value = this.read_bytes_ptr_strip_provenance(value, Size::from_bytes(len))?.to_vec();
+
+        match (level, name) {
+            (SOL_SOCKET, SO_RCVTIMEO | SO_SNDTIMEO) => {
+                let Some(&millis) = value.first_chunk::<4>() else {
+                    return this.set_last_error_and_return_i32(wsa_error(WSAEFAULT));
+                };
+                // The timeout is in milliseconds, and 0 means no timeout.
+                let millis = u32::from_ne_bytes(millis);
+                let timeout = (millis != 0).then(|| Duration::from_millis(millis.into()));
+                if name == SO_RCVTIMEO {
+                    socket.read_timeout.set(timeout);
+                } else {
+                    socket.write_timeout.set(timeout);
+                }
+            }
+            (SOL_SOCKET, SO_ERROR | SO_TYPE) =>
+                return this.set_last_error_and_return_i32(wsa_error(WSAENOPROTOOPT)),
+            _ => {
+                let Some(mut stored) = option_default(level, name) else {
+                    throw_unsup_format!("`setsockopt`: unsupported option {name} at level {level}");
+                };
+                // Boolean options may be passed as a single byte.
+                if value.is_empty() || value.len() > stored.len() {
+                    return this.set_last_error_and_return_i32(wsa_error(WSAEFAULT));
+                }
+                stored.fill(0);
+                stored[..value.len()].copy_from_slice(&value);
+                socket.options.borrow_mut().insert((level, name), stored);
+            }
+        }
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn getsockopt(
+        &mut self,
+        socket: &OpTy<'tcx>, // SOCKET
+        level: &OpTy<'tcx>,  // int
+        name: &OpTy<'tcx>,   // int
+        value: &OpTy<'tcx>,  // char*
+        len: &OpTy<'tcx>,    // int*
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let level = this.read_scalar(level)?.to_i32()?;
+        let name = this.read_scalar(name)?.to_i32()?;
+        let value_ptr = this.read_pointer(value)?;
+        let len = this.deref_pointer_as(len, this.machine.layouts.i32)?;
+        let Some(socket) = this.read_socket(socket)? else {
+            return interp_ok(Scalar::from_i32(SOCKET_ERROR));
+        };
+
+        let value = match (level, name) {
+            (SOL_SOCKET, SO_RCVTIMEO) =>
+                timeout_to_millis(socket.read_timeout.get()).to_ne_bytes().to_vec(),
+            (SOL_SOCKET, SO_SNDTIMEO) =>
+                timeout_to_millis(socket.write_timeout.get()).to_ne_bytes().to_vec(),
+            // Errors are always reported synchronously, so there never is a pending error.
+            (SOL_SOCKET, SO_ERROR) => 0i32.to_ne_bytes().to_vec(),
+            (SOL_SOCKET, SO_TYPE) => {
+                let ty = match socket.ty {
+                    SocketType::Stream => SOCK_STREAM,
+                    SocketType::Datagram => SOCK_DGRAM,
+                };
+                ty.to_ne_bytes().to_vec()
+            }
+            _ => {
+                let stored = socket.options.borrow().get(&(level, name)).cloned();
+                let Some(value) = stored.or_else(|| option_default(level, name)) else {
+                    throw_unsup_format!("`getsockopt`: unsupported option {name} at level {level}");
+                };
+                value
+            }
+        };
+        let mut size = i32::try_from(value.len()).unwrap();
+        let available = this.read_scalar(&len)?.to_i32()?;
+        // Like Windows, let boolean options be read into a single byte.
+        if available == 1 && value[1..].iter().all(|&byte| byte == 0) {
+            size = 1;
+        }
+        if available < size {
+            return this.set_last_error_and_return_i32(wsa_error(WSAEFAULT));
+        }
+        this.write_bytes_ptr(value_ptr, value[..size.try_into().unwrap()].iter().copied())?;
+        this.write_int(size, &len)?;
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn ioctlsocket(
+        &mut self,
+        socket: &OpTy<'tcx>, // SOCKET
+        cmd: &OpTy<'tcx>,    // long
+        arg: &OpTy<'tcx>,    // u_long*
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let cmd = this.read_scalar(cmd)?.to_i32()?.cast_unsigned();
+        let arg = this.deref_pointer_as(arg, this.machine.layouts.u32)?;
+        let Some(socket) = this.read_socket(socket)? else {
+            return interp_ok(Scalar::from_i32(SOCKET_ERROR));
+        };
+        match cmd {
+            FIONBIO => socket.nonblocking.set(this.read_scalar(&arg)?.to_u32()? != 0),
+            FIONREAD =>
+                this.write_int(u32::try_from(socket.available()).unwrap_or(u32::MAX), &arg)?,
+            _ => throw_unsup_format!("`ioctlsocket`: unsupported command {cmd:#x}"),
+        }
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn select(
+        &mut self,
+        nfds: &OpTy<'tcx>,       // int
+        read_set: &OpTy<'tcx>,   // FD_SET*
+        write_set: &OpTy<'tcx>,  // FD_SET*
+        except_set: &OpTy<'tcx>, // FD_SET*
+        timeout: &OpTy<'tcx>,    // const TIMEVAL*
+        dest: &MPlaceTy<'tcx>,
+    ) -> InterpResult<'tcx> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        // Ignored, for compatibility with Berkeley sockets.
+        let _ = this.read_scalar(nfds)?.to_i32()?;
+        let sets = [
+            this.read_pointer(read_set)?,
+            this.read_pointer(write_set)?,
+            this.read_pointer(except_set)?,
+        ];
+        let timeout = this.read_pointer(timeout)?;
+        if !this.winsock_started()? {
+            return this.write_int(SOCKET_ERROR, dest);
+        }
+        // Waiting on nothing is an error on Windows.
+        let mut empty = true;
+        for set in sets {
+            if !this.ptr_is_null(set)? {
+                empty = false;
+            }
+        }
+        if empty {
+            return this.set_last_error_and_return(wsa_error(WSAEINVAL), dest);
+        }
+
+        let deadline = if this.ptr_is_null(timeout)? {
+            None
+        } else {
+            let timeout = this.ptr_to_mplace(timeout, this.windows_ty_layout("TIMEVAL"));
+            let secs =
+                this.read_scalar(&this.project_field_named(&timeout, "tv_sec")?)?.to_i32()?;
+            let micros =
+                this.read_scalar(&this.project_field_named(&timeout, "tv_usec")?)?.to_i32()?;
+            let (Ok(secs), Ok(micros)) = (u64::try_from(secs), u64::try_from(micros)) else {
+                return this.set_last_error_and_return(wsa_error(WSAEINVAL), dest);
+            };
+            let timeout = Duration::from_secs(secs).saturating_add(Duration::from_micros(micros));
+            Some(this.machine.monotonic_clock.elapsed().saturating_add(timeout))
+        };
+        this.select_until(sets, deadline, dest.clone())
+    }
+
+    fn getaddrinfo(
+        &mut self,
+        node: &OpTy<'tcx>,    // PCSTR
+        service: &OpTy<'tcx>, // PCSTR
+        hints: &OpTy<'tcx>,   // const ADDRINFOA*
+        result: &OpTy<'tcx>,  // ADDRINFOA**
+    ) -> InterpResult<'tcx, Scalar> {
+        // ^ Returns int
+        let this = self.eval_context_mut();
+        let node = this.read_pointer(node)?;
+        let service = this.read_pointer(service)?;
+        let hints = this.read_pointer(hints)?;
+        let result = this.deref_pointer_as(result, this.machine.layouts.mut_raw_ptr)?;
+        // Unlike the other functions, this one returns the error code.
+        let fail = |this: &mut MiriInterpCx<'tcx>, code: i32| {
+            this.set_last_error(wsa_error(code))?;
+            interp_ok(Scalar::from_i32(code))
+        };
+        if !this.winsock_started()? {
+            return fail(this, WSANOTINITIALISED);
+        }
+
+        let addrinfo_layout = this.windows_ty_layout("ADDRINFOA");
+        let (flags, family, socktype, protocol) = if this.ptr_is_null(hints)? {
+            (0, AF_UNSPEC, 0, 0)
+        } else {
+            let hints = this.ptr_to_mplace(hints, addrinfo_layout);
+            let mut fields = [0; 4];
+            for (field, name) in
+                fields.iter_mut().zip(["ai_flags", "ai_family", "ai_socktype", "ai_protocol"])
+            {
+                *field = this.read_scalar(&this.project_field_named(&hints, name)?)?.to_i32()?;
+            }
+            let [flags, family, socktype, protocol] = fields;
+            (flags, family, socktype, protocol)
+        };
+        if flags & !(AI_PASSIVE | AI_NUMERICHOST) != 0 {
+            throw_unsup_format!("`getaddrinfo`: unsupported flags {flags:#x}");
+        }
+        if !matches!(family, AF_UNSPEC | AF_INET | AF_INET6) {
+            return fail(this, WSAEAFNOSUPPORT);
+        }
+        let socket_types = match socktype {
+            0 => vec![(SOCK_STREAM, IPPROTO_TCP), (SOCK_DGRAM, IPPROTO_UDP)],
+            SOCK_STREAM => vec![(SOCK_STREAM, IPPROTO_TCP)],
+            SOCK_DGRAM => vec![(SOCK_DGRAM, IPPROTO_UDP)],
+            _ => return fail(this, WSAESOCKTNOSUPPORT),
+        };
+
+        let ips = if this.ptr_is_null(node)? {
+            if this.ptr_is_null(service)? {
+                return fail(this, WSAHOST_NOT_FOUND);
+            }
+            // Without a host name, this is the address to bind to or to connect to locally.
+            if flags & AI_PASSIVE != 0 {
+                vec![IpAddr::from(Ipv6Addr::UNSPECIFIED), Ipv4Addr::UNSPECIFIED.into()]
+            } else {
+                vec![IpAddr::from(Ipv6Addr::LOCALHOST), Ipv4Addr::LOCALHOST.into()]
+            }
+        } else {
+            let name = String::from_utf8_lossy(this.read_c_str(node)?).into_owned();
+            let ips = if flags & AI_NUMERICHOST != 0 {
+                name.parse::<IpAddr>().ok().map(|ip| vec![ip])
+            } else {
+                network::resolve_host(&name)
+            };
+            let Some(ips) = ips else { return fail(this, WSAHOST_NOT_FOUND) };
+            ips
+        };
+        let port = if this.ptr_is_null(service)? {
+            0
+        } else {
+            let service = String::from_utf8_lossy(this.read_c_str(service)?).into_owned();
+            // Service names would need a services database.
+            let Ok(port) = service.parse::<u16>() else { return fail(this, WSATYPE_NOT_FOUND) };
+            port
+        };
+        let addrs: Vec<SocketAddr> = ips
+            .into_iter()
+            .filter(|ip| {
+                match family {
+                    AF_INET => ip.is_ipv4(),
+                    AF_INET6 => ip.is_ipv6(),
+                    _ => true,
+                }
+            })
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect();
+        if addrs.is_empty() {
+            return fail(this, WSAHOST_NOT_FOUND);
+        }
+
+        // Build the list back to front, so that each entry can point to the next one.
+        let mut next = Pointer::null();
+        for addr in addrs.iter().rev() {
+            for &(socktype, protocol_for_type) in socket_types.iter().rev() {
+                let bytes = network::sockaddr_to_bytes(*addr, AF_INET as u16, AF_INET6 as u16);
+                let addr_len = u64::try_from(bytes.len()).unwrap();
+                let addr_ptr = this.allocate_ptr(
+                    Size::from_bytes(addr_len),
+                    Align::from_bytes(4).unwrap(),
+                    MiriMemoryKind::Runtime.into(),
+                    AllocInit::Uninit,
+                )?;
+                this.write_bytes_ptr(addr_ptr.into(), bytes)?;
+
+                let entry = this.allocate(addrinfo_layout, MiriMemoryKind::Runtime.into())?;
+                this.write_bytes_ptr(
+                    entry.ptr(),
+                    std::iter::repeat_n(0u8, addrinfo_layout.size.bytes_usize()),
+                )?;
+                let family = if addr.is_ipv6() { AF_INET6 } else { AF_INET };
+                this.write_int_fields_named(
+                    &[
+                        ("ai_family", family.into()),
+                        ("ai_socktype", socktype.into()),
+                        (
+                            "ai_protocol",
+                            (if protocol == 0 { protocol_for_type } else { protocol }).into(),
+                        ),
+                        ("ai_addrlen", addr_len.into()),
+                    ],
+                    &entry,
+                )?;
+                this.write_pointer(addr_ptr, &this.project_field_named(&entry, "ai_addr")?)?;
+                this.write_pointer(next, &this.project_field_named(&entry, "ai_next")?)?;
+                next = entry.ptr();
+            }
+        }
+        this.write_pointer(next, &result)?;
+        interp_ok(Scalar::from_i32(0))
+    }
+
+    fn freeaddrinfo(&mut self, info: &OpTy<'tcx>) -> InterpResult<'tcx> {
+        // ^ Returns void
+        let this = self.eval_context_mut();
+        let addrinfo_layout = this.windows_ty_layout("ADDRINFOA");
+        let mut entry = this.read_pointer(info)?;
+        while !this.ptr_is_null(entry)? {
+            let place = this.ptr_to_mplace(entry, addrinfo_layout);
+            let addr = this.read_pointer(&this.project_field_named(&place, "ai_addr")?)?;
+            let next = this.read_pointer(&this.project_field_named(&place, "ai_next")?)?;
+            this.deallocate_ptr(addr, None, MiriMemoryKind::Runtime.into())?;
+            this.deallocate_ptr(entry, None, MiriMemoryKind::Runtime.into())?;
+            entry = next;
+        }
+        interp_ok(())
+    }
+}
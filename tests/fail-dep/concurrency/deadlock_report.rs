@@ -0,0 +1,28 @@
+//@ignore-target: windows # No pthreads on Windows
+//@ignore-host: windows # writes the report to `/dev/stderr`
+//@error-in-other-file: deadlock
+// We are making scheduler assumptions here.
+//@compile-flags: -Zmiri-deterministic-concurrency -Zmiri-deadlock-report=/dev/stderr
+
+use std::cell::UnsafeCell;
+use std::sync::Arc;
+use std::thread;
+
+struct Mutex(UnsafeCell<libc::pthread_mutex_t>);
+
+unsafe impl Send for Mutex {}
+unsafe impl Sync for Mutex {}
+
+fn main() {
+    unsafe {
+        let lock = Arc::new(Mutex(UnsafeCell::new(libc::PTHREAD_MUTEX_INITIALIZER)));
+        assert_eq!(libc::pthread_mutex_lock(lock.0.get()), 0);
+
+        let lock_copy = lock.clone();
+        thread::spawn(move || {
+            assert_eq!(libc::pthread_mutex_lock(lock_copy.0.get()), 0); //~ ERROR: deadlock
+        })
+        .join()
+        .unwrap();
+    }
+}
@@ -0,0 +1,40 @@
+//@ignore-target: windows # No pthreads on Windows
+//@compile-flags: -Zmiri-dpor
+//! The threads only synchronize through a mutex, so the explorer has to try both orders in which
+//! they acquire it to reach the UB.
+
+use std::ptr;
+
+static mut MUTEX: libc::pthread_mutex_t = libc::PTHREAD_MUTEX_INITIALIZER;
+static mut FIRST: usize = 0;
+
+extern "C" fn record(arg: *mut libc::c_void) -> *mut libc::c_void {
+    unsafe {
+        assert_eq!(libc::pthread_mutex_lock(&raw mut MUTEX), 0);
+        if FIRST == 0 {
+            FIRST = arg.addr();
+        }
+        assert_eq!(libc::pthread_mutex_unlock(&raw mut MUTEX), 0);
+    }
+    ptr::null_mut()
+}
+
+#[allow(deref_nullptr)]
+fn main() {
+    unsafe {
+        // Initialize the mutex before there are other threads.
+        assert_eq!(libc::pthread_mutex_lock(&raw mut MUTEX), 0);
+        assert_eq!(libc::pthread_mutex_unlock(&raw mut MUTEX), 0);
+        let mut t1 = 0;
+        let mut t2 = 0;
+        let arg1 = ptr::without_provenance_mut(1);
+        let arg2 = ptr::without_provenance_mut(2);
+        assert_eq!(libc::pthread_create(&mut t1, ptr::null(), record, arg1), 0);
+        assert_eq!(libc::pthread_create(&mut t2, ptr::null(), record, arg2), 0);
+        assert_eq!(libc::pthread_join(t1, ptr::null_mut()), 0);
+        assert_eq!(libc::pthread_join(t2, ptr::null_mut()), 0);
+        if FIRST == 2 {
+            let _x: i32 = *ptr::null(); //~ ERROR: null pointer
+        }
+    }
+}
@@ -0,0 +1,34 @@
+//@ignore-target: windows # No pthreads on Windows
+//@compile-flags: -Zmiri-dpor
+//! The default schedule runs the first thread first, so only the second execution of the DPOR
+//! explorer reaches the UB.
+
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static X: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn store_1(_arg: *mut libc::c_void) -> *mut libc::c_void {
+    X.store(1, Ordering::Relaxed);
+    ptr::null_mut()
+}
+
+extern "C" fn store_2(_arg: *mut libc::c_void) -> *mut libc::c_void {
+    X.store(2, Ordering::Relaxed);
+    ptr::null_mut()
+}
+
+#[allow(deref_nullptr)]
+fn main() {
+    unsafe {
+        let mut t1 = 0;
+        let mut t2 = 0;
+        assert_eq!(libc::pthread_create(&mut t1, ptr::null(), store_1, ptr::null_mut()), 0);
+        assert_eq!(libc::pthread_create(&mut t2, ptr::null(), store_2, ptr::null_mut()), 0);
+        assert_eq!(libc::pthread_join(t1, ptr::null_mut()), 0);
+        assert_eq!(libc::pthread_join(t2, ptr::null_mut()), 0);
+        if X.load(Ordering::Relaxed) == 1 {
+            let _x: i32 = *ptr::null(); //~ ERROR: null pointer
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//@ignore-target: windows # No pthreads on Windows
+//@compile-flags: -Zmiri-pct-steps=1 -Zmiri-seed=7
+//@revisions: depth2 depth3
+//@[depth2]compile-flags: -Zmiri-pct=2
+//@[depth3]compile-flags: -Zmiri-pct=3
+//! The UB needs two orderings: each thread must store before the main thread loads. With only one
+//! estimated step, every change point lands on the first steps of the main thread, which drops
+//! below all threads it creates afterwards. So each new thread runs to completion right away, and
+//! PCT finds the bug in every run.
+
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static X: AtomicUsize = AtomicUsize::new(0);
+static Y: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn store_x(_arg: *mut libc::c_void) -> *mut libc::c_void {
+    X.store(1, Ordering::Relaxed);
+    ptr::null_mut()
+}
+
+extern "C" fn store_y(_arg: *mut libc::c_void) -> *mut libc::c_void {
+    Y.store(1, Ordering::Relaxed);
+    ptr::null_mut()
+}
+
+#[allow(deref_nullptr)]
+fn main() {
+    unsafe {
+        let mut t1 = 0;
+        let mut t2 = 0;
+        assert_eq!(libc::pthread_create(&mut t1, ptr::null(), store_x, ptr::null_mut()), 0);
+        let x = X.load(Ordering::Relaxed);
+        assert_eq!(libc::pthread_create(&mut t2, ptr::null(), store_y, ptr::null_mut()), 0);
+        let y = Y.load(Ordering::Relaxed);
+        if x == 1 && y == 1 {
+            let _x: i32 = *ptr::null(); //~ ERROR: null pointer
+        }
+        assert_eq!(libc::pthread_join(t1, ptr::null_mut()), 0);
+        assert_eq!(libc::pthread_join(t2, ptr::null_mut()), 0);
+    }
+}
@@ -0,0 +1,19 @@
+//@only-target: windows # Uses win32 api functions
+
+/// Test that moving an SRW lock between uses fails.
+use windows_sys::Win32::System::Threading::{
+    AcquireSRWLockExclusive, ReleaseSRWLockExclusive, SRWLOCK_INIT,
+};
+
+fn main() {
+    unsafe {
+        let mut lock = SRWLOCK_INIT;
+        AcquireSRWLockExclusive(&mut lock);
+        ReleaseSRWLockExclusive(&mut lock);
+
+        // move the SRWLOCK
+        let mut lock2 = lock;
+
+        AcquireSRWLockExclusive(&mut lock2); //~ ERROR: not properly initialized
+    }
+}
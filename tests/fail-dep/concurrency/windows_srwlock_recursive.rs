@@ -0,0 +1,13 @@
+//@only-target: windows # Uses win32 api functions
+
+use std::cell::UnsafeCell;
+
+use windows_sys::Win32::System::Threading::{AcquireSRWLockExclusive, SRWLOCK_INIT};
+
+fn main() {
+    let lock = UnsafeCell::new(SRWLOCK_INIT);
+    unsafe {
+        AcquireSRWLockExclusive(lock.get());
+        AcquireSRWLockExclusive(lock.get()); //~ ERROR: already held by the current thread
+    }
+}
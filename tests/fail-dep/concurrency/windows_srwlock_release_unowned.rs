@@ -0,0 +1,16 @@
+//@only-target: windows # Uses win32 api functions
+
+use std::cell::UnsafeCell;
+
+use windows_sys::Win32::System::Threading::{
+    AcquireSRWLockShared, ReleaseSRWLockExclusive, SRWLOCK_INIT,
+};
+
+fn main() {
+    let lock = UnsafeCell::new(SRWLOCK_INIT);
+    unsafe {
+        AcquireSRWLockShared(lock.get());
+        // The lock is held, but not in the right mode.
+        ReleaseSRWLockExclusive(lock.get()); //~ ERROR: not held exclusively by the current thread
+    }
+}
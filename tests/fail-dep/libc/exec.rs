@@ -0,0 +1,11 @@
+//@ignore-target: windows # No exec on Windows
+
+use std::ptr;
+
+fn main() {
+    let path = c"exec";
+    let argv = [path.as_ptr(), ptr::null()];
+    unsafe {
+        libc::execv(path.as_ptr(), argv.as_ptr()); //~ ERROR: `execv` is not supported
+    }
+}
@@ -0,0 +1,11 @@
+//@ignore-target: windows # No libc `exit` on Windows
+extern "C" fn handler() {
+    unsafe { libc::exit(1) }; //~ ERROR: already exiting
+}
+
+fn main() {
+    unsafe {
+        assert_eq!(libc::atexit(handler), 0);
+        libc::exit(0);
+    }
+}
@@ -0,0 +1,16 @@
+//@ignore-target: windows # No posix_spawn on Windows
+//@compile-flags: -Zmiri-disable-isolation
+
+use std::ptr;
+
+fn main() {
+    // A binary whose name merely starts with the name of this crate is a different program.
+    let path = c"posix_spawn_other_binary_helper".as_ptr();
+    let argv = [path.cast_mut(), ptr::null_mut()];
+    let envp = [ptr::null_mut()];
+    let (argv, envp) = (argv.as_ptr(), envp.as_ptr());
+    let mut pid = 0;
+    unsafe {
+        libc::posix_spawn(&mut pid, path, ptr::null(), ptr::null(), argv, envp); //~ ERROR: only the interpreted program itself
+    }
+}
@@ -0,0 +1,9 @@
+//@ignore-target: windows # only very limited libc on Windows
+use std::ffi::c_char;
+
+fn main() {
+    let mut buf = [0 as c_char; 16];
+    unsafe {
+        libc::snprintf(buf.as_mut_ptr(), buf.len(), c"%d".as_ptr(), 1.5f64); //~ERROR: expects an argument of type `int`, but got `f64`
+    }
+}
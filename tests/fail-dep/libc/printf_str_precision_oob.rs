@@ -0,0 +1,12 @@
+//@ignore-target: windows # only very limited libc on Windows
+use std::ffi::c_char;
+
+// With a precision, `%s` does not need a NUL terminator, but it must not read past the end of the
+// buffer either.
+fn main() {
+    let mut buf = [0 as c_char; 16];
+    let s = [b'a' as c_char; 4];
+    unsafe {
+        libc::snprintf(buf.as_mut_ptr(), buf.len(), c"%.8s".as_ptr(), s.as_ptr()); //~ERROR: at or beyond the end of the allocation
+    }
+}
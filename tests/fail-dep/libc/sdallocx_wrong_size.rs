@@ -0,0 +1,13 @@
+//@only-target: linux # jemalloc's API is not provided by the system allocator everywhere
+
+extern "C" {
+    fn mallocx(size: usize, flags: i32) -> *mut libc::c_void;
+    fn sdallocx(ptr: *mut libc::c_void, size: usize, flags: i32);
+}
+
+fn main() {
+    unsafe {
+        let p = mallocx(16, 0);
+        sdallocx(p, 8, 0); //~ERROR: `sdallocx` called with size 8, but the allocation has size 16
+    }
+}
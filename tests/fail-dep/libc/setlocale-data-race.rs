@@ -0,0 +1,16 @@
+//@compile-flags: -Zmiri-deterministic-concurrency
+//@only-target: linux apple freebsd # `nl_langinfo` is not supported everywhere
+
+use std::thread;
+
+fn main() {
+    let t = thread::spawn(|| unsafe {
+        // Query the global locale while the main thread changes it.
+        libc::nl_langinfo(libc::CODESET); //~ERROR: Data race detected
+    });
+    unsafe {
+        libc::setlocale(libc::LC_ALL, c"C.UTF-8".as_ptr());
+    }
+
+    t.join().unwrap();
+}
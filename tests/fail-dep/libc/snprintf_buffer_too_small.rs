@@ -0,0 +1,10 @@
+//@ignore-target: windows # only very limited libc on Windows
+use std::ffi::c_char;
+
+// `snprintf` may write anywhere in the `size` bytes it is given, even if the output is shorter.
+fn main() {
+    let mut buf = [0 as c_char; 4];
+    unsafe {
+        libc::snprintf(buf.as_mut_ptr(), 16, c"%d".as_ptr(), 1); //~ERROR: only 4 bytes from the end of the allocation
+    }
+}
@@ -0,0 +1,28 @@
+//@compile-flags: -Zmiri-fixed-schedule -Zmiri-fair-scheduling -Zmiri-livelock-detection=100
+//@normalize-stderr-test: "\| +\^+" -> "| ^"
+#![feature(core_intrinsics)]
+
+use std::intrinsics::{AtomicOrdering, atomic_load};
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering::Relaxed;
+use std::thread;
+
+static A: AtomicU8 = AtomicU8::new(0);
+static B: AtomicU8 = AtomicU8::new(0);
+
+// The loads call the intrinsic directly, so that the threads spin in this file rather than in
+// the standard library.
+fn wait_for(flag: &AtomicU8) {
+    while unsafe { atomic_load::<_, { AtomicOrdering::Relaxed }>(flag.as_ptr()) } == 0 {} //~ ERROR: livelocked
+}
+
+fn main() {
+    let t = thread::spawn(|| {
+        // Waits for the main thread, which in turn waits for this thread.
+        while unsafe { atomic_load::<_, { AtomicOrdering::Relaxed }>(A.as_ptr()) } == 0 {} //~ ERROR: livelocked
+        B.store(1, Relaxed);
+    });
+    wait_for(&B);
+    A.store(1, Relaxed);
+    t.join().unwrap();
+}
@@ -0,0 +1,24 @@
+//@compile-flags: -Zmiri-fixed-schedule -Zmiri-livelock-detection=100
+//@normalize-stderr-test: "src/sys/.*\.rs" -> "$$FILE"
+//@normalize-stderr-test: "(--> RUSTLIB/.*\n +\|\n)LL \| .*" -> "${1}LL | $$CODE"
+//@normalize-stderr-test: "\| +\^+" -> "| ^"
+#![feature(core_intrinsics)]
+
+use std::intrinsics::{AtomicOrdering, atomic_load};
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering::Relaxed;
+use std::thread;
+
+static FLAG: AtomicU8 = AtomicU8::new(0);
+
+// The load calls the intrinsic directly, so that the thread spins in this file rather than in the
+// standard library.
+fn wait_for(flag: &AtomicU8) {
+    while unsafe { atomic_load::<_, { AtomicOrdering::Relaxed }>(flag.as_ptr()) } == 0 {} //~ ERROR: starving threads
+}
+
+fn main() {
+    let _t = thread::spawn(|| FLAG.store(1, Relaxed));
+    // Without preemption, the other thread never gets to set the flag.
+    wait_for(&FLAG);
+}
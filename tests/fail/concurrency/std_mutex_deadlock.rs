@@ -0,0 +1,14 @@
+//@only-target: linux # The standard library's `Mutex` blocks on a futex here
+//@error-in-other-file: deadlock
+//@compile-flags: -Zmiri-deadlock-prediction
+//@normalize-stderr-test: "src/sys/.*\.rs" -> "$$FILE"
+//@normalize-stderr-test: "(--> RUSTLIB/.*\n +\|\n)LL \| .*\n +\| +\^+" -> "${1}LL | $$CODE\n   | ^"
+//@normalize-stderr-test: "\n *= note: inside `std::.*" -> ""
+use std::sync::Mutex;
+
+fn main() {
+    let m = Mutex::new(0);
+    let _first = m.lock().unwrap();
+    // The mutex is not reentrant, so this blocks forever.
+    let _second = m.lock();
+}
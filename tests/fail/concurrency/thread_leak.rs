@@ -0,0 +1,20 @@
+//@compile-flags: -Zmiri-report-thread-leaks=error
+//@error-in-other-file: the main thread exited without joining it
+//@normalize-stderr-test: "src/sys/.*\.rs" -> "$$FILE"
+//@normalize-stderr-test: "(--> RUSTLIB/.*\n +\|\n)LL \| .*\n +\| +\^+" -> "${1}LL | $$CODE\n   | ^"
+//@normalize-stderr-test: "\n *= note: inside `std::.*" -> ""
+use std::{mem, thread};
+
+fn park_forever() {
+    // Nobody ever unparks this thread.
+    loop {
+        thread::park();
+    }
+}
+
+fn main() {
+    let builder = thread::Builder::new().name("worker".into());
+    let handle = builder.spawn(park_forever).unwrap();
+    // Neither join nor detach the thread.
+    mem::forget(handle);
+}
@@ -0,0 +1,34 @@
+// We want to control preemption here. Stacked borrows interferes by having its own accesses.
+//@compile-flags: -Zmiri-deterministic-concurrency -Zmiri-disable-stacked-borrows -Zmiri-race-backtraces
+
+// The race is not at the first byte of the later access.
+
+use std::thread::spawn;
+
+#[derive(Copy, Clone)]
+struct EvilSend<T>(pub T);
+
+unsafe impl<T> Send for EvilSend<T> {}
+unsafe impl<T> Sync for EvilSend<T> {}
+
+fn store_high(p: *mut u32) {
+    unsafe { *p.cast::<u16>().add(1) = 1 }
+}
+
+fn writer(c: EvilSend<*mut u32>) {
+    store_high(c.0);
+}
+
+fn store(p: *mut u32) {
+    unsafe { *p = 64 } //~ ERROR: Data race detected between (1) non-atomic write on thread `unnamed-1` and (2) non-atomic write on thread `unnamed-2`
+}
+
+fn main() {
+    let mut a = 0u32;
+    let b = &mut a as *mut u32;
+    let c = EvilSend(b);
+    let j1 = spawn(move || writer(c));
+    let j2 = spawn(move || store(c.0));
+    j1.join().unwrap();
+    j2.join().unwrap();
+}
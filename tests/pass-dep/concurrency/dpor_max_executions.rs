@@ -0,0 +1,27 @@
+//@ignore-target: windows # No pthreads on Windows
+//@compile-flags: -Zmiri-dpor -Zmiri-dpor-max-executions=2
+//! Three threads storing to the same atomic have six orders, so the exploration stops early.
+
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static X: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn store(arg: *mut libc::c_void) -> *mut libc::c_void {
+    X.store(arg.addr(), Ordering::Relaxed);
+    ptr::null_mut()
+}
+
+fn main() {
+    unsafe {
+        let mut threads = [0; 3];
+        for (i, thread) in threads.iter_mut().enumerate() {
+            let arg = ptr::without_provenance_mut(i + 1);
+            assert_eq!(libc::pthread_create(thread, ptr::null(), store, arg), 0);
+        }
+        for thread in threads {
+            assert_eq!(libc::pthread_join(thread, ptr::null_mut()), 0);
+        }
+    }
+    assert!((1..=3).contains(&X.load(Ordering::Relaxed)));
+}
@@ -0,0 +1,30 @@
+//@ignore-target: windows # No pthreads on Windows
+//@compile-flags: -Zmiri-dpor-schedule=2,2
+//! The schedule runs the second thread first, and only this one execution is run.
+
+use std::ptr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static X: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn store_1(_arg: *mut libc::c_void) -> *mut libc::c_void {
+    X.store(1, Ordering::Relaxed);
+    ptr::null_mut()
+}
+
+extern "C" fn store_2(_arg: *mut libc::c_void) -> *mut libc::c_void {
+    X.store(2, Ordering::Relaxed);
+    ptr::null_mut()
+}
+
+fn main() {
+    unsafe {
+        let mut t1 = 0;
+        let mut t2 = 0;
+        assert_eq!(libc::pthread_create(&mut t1, ptr::null(), store_1, ptr::null_mut()), 0);
+        assert_eq!(libc::pthread_create(&mut t2, ptr::null(), store_2, ptr::null_mut()), 0);
+        assert_eq!(libc::pthread_join(t1, ptr::null_mut()), 0);
+        assert_eq!(libc::pthread_join(t2, ptr::null_mut()), 0);
+    }
+    assert_eq!(X.load(Ordering::Relaxed), 1);
+}
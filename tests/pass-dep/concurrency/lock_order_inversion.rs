@@ -0,0 +1,44 @@
+//@ignore-target: windows # No pthreads on Windows
+//@compile-flags: -Zmiri-deterministic-concurrency -Zmiri-deadlock-prediction
+#![feature(sync_unsafe_cell)]
+
+use std::cell::SyncUnsafeCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+type Mutex = SyncUnsafeCell<libc::pthread_mutex_t>;
+
+static A: Mutex = SyncUnsafeCell::new(libc::PTHREAD_MUTEX_INITIALIZER);
+static B: Mutex = SyncUnsafeCell::new(libc::PTHREAD_MUTEX_INITIALIZER);
+static C: Mutex = SyncUnsafeCell::new(libc::PTHREAD_MUTEX_INITIALIZER);
+static D: Mutex = SyncUnsafeCell::new(libc::PTHREAD_MUTEX_INITIALIZER);
+
+static DONE: AtomicBool = AtomicBool::new(false);
+
+fn lock_both(first: &Mutex, second: &Mutex) {
+    unsafe {
+        assert_eq!(libc::pthread_mutex_lock(first.get()), 0);
+        assert_eq!(libc::pthread_mutex_lock(second.get()), 0);
+        assert_eq!(libc::pthread_mutex_unlock(second.get()), 0);
+        assert_eq!(libc::pthread_mutex_unlock(first.get()), 0);
+    }
+}
+
+fn main() {
+    // Joining the thread orders its acquisitions before those of the main thread, so the
+    // opposite order cannot deadlock.
+    thread::spawn(|| lock_both(&C, &D)).join().unwrap();
+    lock_both(&D, &C);
+
+    // Here, only the mutexes themselves order the acquisitions. The threads do not deadlock in
+    // this execution, since the main thread waits for the other one, but they could.
+    let t = thread::spawn(|| {
+        lock_both(&A, &B);
+        DONE.store(true, Ordering::Relaxed);
+    });
+    while !DONE.load(Ordering::Relaxed) {
+        thread::yield_now();
+    }
+    lock_both(&B, &A);
+    t.join().unwrap();
+}
@@ -0,0 +1,60 @@
+//@ignore-target: windows # No pthreads on Windows
+//@compile-flags: -Zmiri-deterministic-concurrency -Zmiri-deadlock-prediction
+#![feature(sync_unsafe_cell)]
+
+use std::cell::SyncUnsafeCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+type RwLock = SyncUnsafeCell<libc::pthread_rwlock_t>;
+
+static A: RwLock = SyncUnsafeCell::new(libc::PTHREAD_RWLOCK_INITIALIZER);
+static B: RwLock = SyncUnsafeCell::new(libc::PTHREAD_RWLOCK_INITIALIZER);
+static C: RwLock = SyncUnsafeCell::new(libc::PTHREAD_RWLOCK_INITIALIZER);
+static D: RwLock = SyncUnsafeCell::new(libc::PTHREAD_RWLOCK_INITIALIZER);
+
+fn lock(lock: &RwLock, write: bool) {
+    unsafe {
+        if write {
+            assert_eq!(libc::pthread_rwlock_wrlock(lock.get()), 0);
+        } else {
+            assert_eq!(libc::pthread_rwlock_rdlock(lock.get()), 0);
+        }
+    }
+}
+
+fn unlock(lock: &RwLock) {
+    unsafe {
+        assert_eq!(libc::pthread_rwlock_unlock(lock.get()), 0);
+    }
+}
+
+/// Takes `first` and then `second` in the given modes, once on another thread and then in the
+/// opposite order on the main thread, while the other thread has finished but is not joined.
+fn inversion(first: (&'static RwLock, bool), second: (&'static RwLock, bool)) {
+    static DONE: AtomicBool = AtomicBool::new(false);
+    DONE.store(false, Ordering::Relaxed);
+    let t = thread::spawn(move || {
+        lock(first.0, first.1);
+        lock(second.0, second.1);
+        unlock(second.0);
+        unlock(first.0);
+        DONE.store(true, Ordering::Relaxed);
+    });
+    while !DONE.load(Ordering::Relaxed) {
+        thread::yield_now();
+    }
+    lock(second.0, second.1);
+    lock(first.0, first.1);
+    unlock(first.0);
+    unlock(second.0);
+    t.join().unwrap();
+}
+
+fn main() {
+    // Both threads can hold `A` at the same time, so they cannot block each other.
+    inversion((&A, false), (&B, false));
+    inversion((&C, false), (&D, true));
+    // Here, they can.
+    inversion((&C, true), (&D, true));
+}
@@ -0,0 +1,52 @@
+//@only-target: windows # Uses win32 api functions
+//@compile-flags: -Zmiri-deterministic-concurrency -Zmiri-deadlock-prediction
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use windows_sys::Win32::System::Threading::{
+    AcquireSRWLockExclusive, CRITICAL_SECTION, EnterCriticalSection, InitializeCriticalSection,
+    LeaveCriticalSection, ReleaseSRWLockExclusive, SRWLOCK, SRWLOCK_INIT,
+};
+
+struct Lock<T>(UnsafeCell<T>);
+
+unsafe impl<T> Sync for Lock<T> {}
+
+static SRW: Lock<SRWLOCK> = Lock(UnsafeCell::new(SRWLOCK_INIT));
+static CS: Lock<MaybeUninit<CRITICAL_SECTION>> = Lock(UnsafeCell::new(MaybeUninit::uninit()));
+
+static DONE: AtomicBool = AtomicBool::new(false);
+
+fn srw_then_cs() {
+    unsafe {
+        AcquireSRWLockExclusive(SRW.0.get());
+        EnterCriticalSection(CS.0.get().cast());
+        LeaveCriticalSection(CS.0.get().cast());
+        ReleaseSRWLockExclusive(SRW.0.get());
+    }
+}
+
+fn cs_then_srw() {
+    unsafe {
+        EnterCriticalSection(CS.0.get().cast());
+        AcquireSRWLockExclusive(SRW.0.get());
+        ReleaseSRWLockExclusive(SRW.0.get());
+        LeaveCriticalSection(CS.0.get().cast());
+    }
+}
+
+fn main() {
+    unsafe { InitializeCriticalSection(CS.0.get().cast()) };
+    let t = thread::spawn(|| {
+        srw_then_cs();
+        DONE.store(true, Ordering::Relaxed);
+    });
+    while !DONE.load(Ordering::Relaxed) {
+        thread::yield_now();
+    }
+    cs_then_srw();
+    t.join().unwrap();
+}
@@ -0,0 +1,179 @@
+//@only-target: windows # Uses win32 api functions
+// We are making scheduler assumptions here.
+//@compile-flags: -Zmiri-deterministic-concurrency
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::thread;
+
+use windows_sys::Win32::Foundation::{ERROR_TIMEOUT, FALSE, GetLastError, TRUE};
+use windows_sys::Win32::System::Threading::{
+    AcquireSRWLockExclusive, AcquireSRWLockShared, CONDITION_VARIABLE, CONDITION_VARIABLE_INIT,
+    CONDITION_VARIABLE_LOCKMODE_SHARED, CRITICAL_SECTION, DeleteCriticalSection,
+    EnterCriticalSection, INFINITE, InitializeConditionVariable, InitializeCriticalSection,
+    InitializeSRWLock, LeaveCriticalSection, ReleaseSRWLockExclusive, ReleaseSRWLockShared,
+    SRWLOCK, SRWLOCK_INIT, SleepConditionVariableCS, SleepConditionVariableSRW,
+    TryAcquireSRWLockExclusive, TryAcquireSRWLockShared, TryEnterCriticalSection,
+    WakeAllConditionVariable, WakeConditionVariable,
+};
+
+#[derive(Copy, Clone)]
+struct SendPtr<T>(*mut T);
+
+unsafe impl<T> Send for SendPtr<T> {}
+
+fn srwlock() {
+    let lock = UnsafeCell::new(SRWLOCK_INIT);
+    let lock = lock.get();
+    unsafe {
+        AcquireSRWLockExclusive(lock);
+        // The lock is not recursive.
+        assert_eq!(TryAcquireSRWLockExclusive(lock), 0);
+        assert_eq!(TryAcquireSRWLockShared(lock), 0);
+        ReleaseSRWLockExclusive(lock);
+
+        // Multiple readers, but no writer.
+        AcquireSRWLockShared(lock);
+        assert_ne!(TryAcquireSRWLockShared(lock), 0);
+        assert_eq!(TryAcquireSRWLockExclusive(lock), 0);
+        ReleaseSRWLockShared(lock);
+        ReleaseSRWLockShared(lock);
+
+        assert_ne!(TryAcquireSRWLockExclusive(lock), 0);
+        ReleaseSRWLockExclusive(lock);
+    }
+
+    // An explicitly initialized lock works just the same.
+    let mut lock = MaybeUninit::<SRWLOCK>::uninit();
+    unsafe {
+        InitializeSRWLock(lock.as_mut_ptr());
+        AcquireSRWLockExclusive(lock.as_mut_ptr());
+        ReleaseSRWLockExclusive(lock.as_mut_ptr());
+    }
+}
+
+fn srwlock_contended() {
+    static mut LOCK: SRWLOCK = SRWLOCK_INIT;
+    static mut DATA: u32 = 0;
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            thread::spawn(|| unsafe {
+                AcquireSRWLockExclusive(&raw mut LOCK);
+                // The lock makes this a critical section, so there is no data race.
+                let value = DATA;
+                thread::yield_now();
+                DATA = value + 1;
+                ReleaseSRWLockExclusive(&raw mut LOCK);
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    unsafe {
+        AcquireSRWLockShared(&raw mut LOCK);
+        assert_eq!(DATA, 4);
+        ReleaseSRWLockShared(&raw mut LOCK);
+    }
+}
+
+fn critical_section() {
+    let mut cs = MaybeUninit::<CRITICAL_SECTION>::uninit();
+    let cs_ptr = SendPtr(cs.as_mut_ptr());
+    let data = UnsafeCell::new(0);
+    let data_ptr = SendPtr(data.get());
+    unsafe {
+        InitializeCriticalSection(cs_ptr.0);
+        // Critical sections are recursive.
+        EnterCriticalSection(cs_ptr.0);
+        assert_eq!(TryEnterCriticalSection(cs_ptr.0), TRUE);
+        LeaveCriticalSection(cs_ptr.0);
+
+        let t = thread::spawn(move || {
+            let (cs_ptr, data_ptr) = (cs_ptr, data_ptr);
+            assert_eq!(TryEnterCriticalSection(cs_ptr.0), FALSE);
+            EnterCriticalSection(cs_ptr.0);
+            assert_eq!(*data_ptr.0, 1);
+            *data_ptr.0 = 2;
+            LeaveCriticalSection(cs_ptr.0);
+        });
+        thread::yield_now();
+        *data_ptr.0 = 1;
+        LeaveCriticalSection(cs_ptr.0);
+        t.join().unwrap();
+
+        EnterCriticalSection(cs_ptr.0);
+        assert_eq!(*data_ptr.0, 2);
+        LeaveCriticalSection(cs_ptr.0);
+        DeleteCriticalSection(cs_ptr.0);
+    }
+}
+
+fn condvar_srw() {
+    static mut LOCK: SRWLOCK = SRWLOCK_INIT;
+    static mut CONDVAR: CONDITION_VARIABLE = CONDITION_VARIABLE_INIT;
+    static mut READY: bool = false;
+
+    let waiters: Vec<_> = [0, CONDITION_VARIABLE_LOCKMODE_SHARED]
+        .into_iter()
+        .map(|flags| {
+            thread::spawn(move || unsafe {
+                if flags == 0 {
+                    AcquireSRWLockExclusive(&raw mut LOCK);
+                } else {
+                    AcquireSRWLockShared(&raw mut LOCK);
+                }
+                while !READY {
+                    assert_eq!(
+                        SleepConditionVariableSRW(&raw mut CONDVAR, &raw mut LOCK, INFINITE, flags),
+                        TRUE
+                    );
+                }
+                if flags == 0 {
+                    ReleaseSRWLockExclusive(&raw mut LOCK);
+                } else {
+                    ReleaseSRWLockShared(&raw mut LOCK);
+                }
+            })
+        })
+        .collect();
+    thread::yield_now();
+
+    unsafe {
+        AcquireSRWLockExclusive(&raw mut LOCK);
+        READY = true;
+        ReleaseSRWLockExclusive(&raw mut LOCK);
+        WakeAllConditionVariable(&raw mut CONDVAR);
+    }
+    for waiter in waiters {
+        waiter.join().unwrap();
+    }
+}
+
+fn condvar_cs_timeout() {
+    let mut cs = MaybeUninit::<CRITICAL_SECTION>::uninit();
+    let mut condvar = MaybeUninit::<CONDITION_VARIABLE>::uninit();
+    unsafe {
+        InitializeCriticalSection(cs.as_mut_ptr());
+        InitializeConditionVariable(condvar.as_mut_ptr());
+
+        EnterCriticalSection(cs.as_mut_ptr());
+        // Nobody wakes us up, so this times out, but we get the lock back.
+        assert_eq!(SleepConditionVariableCS(condvar.as_mut_ptr(), cs.as_mut_ptr(), 10), FALSE);
+        assert_eq!(GetLastError(), ERROR_TIMEOUT);
+        LeaveCriticalSection(cs.as_mut_ptr());
+
+        // Waking without waiters does nothing.
+        WakeConditionVariable(condvar.as_mut_ptr());
+        DeleteCriticalSection(cs.as_mut_ptr());
+    }
+}
+
+fn main() {
+    srwlock();
+    srwlock_contended();
+    critical_section();
+    condvar_srw();
+    condvar_cs_timeout();
+}
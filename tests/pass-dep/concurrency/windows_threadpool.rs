@@ -0,0 +1,163 @@
+//@only-target: windows # Uses win32 api functions
+//@compile-flags: -Zmiri-deterministic-concurrency
+
+use std::ffi::c_void;
+use std::os::windows::io::AsRawHandle;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::{ptr, thread};
+
+use windows_sys::Win32::Foundation::{FALSE, FILETIME, TRUE, WAIT_IO_COMPLETION};
+use windows_sys::Win32::System::Threading::{
+    CloseThreadpoolTimer, CloseThreadpoolWork, CreateThreadpoolTimer, CreateThreadpoolWork,
+    GetCurrentThread, INFINITE, IsThreadpoolTimerSet, PTP_CALLBACK_INSTANCE, PTP_TIMER, PTP_WORK,
+    QueueUserAPC, SetThreadpoolTimer, Sleep, SleepEx, SubmitThreadpoolWork,
+    WaitForThreadpoolTimerCallbacks, WaitForThreadpoolWorkCallbacks,
+};
+
+fn main() {
+    unsafe {
+        test_work();
+        test_one_shot_timer();
+        test_periodic_timer();
+        test_apc();
+        test_apc_other_thread();
+    }
+}
+
+/// A due time relative to now, in milliseconds.
+fn relative_due_time(ms: i64) -> FILETIME {
+    let ticks = -ms * 10_000;
+    FILETIME { dwLowDateTime: ticks as u32, dwHighDateTime: (ticks >> 32) as u32 }
+}
+
+unsafe extern "system" fn increment(
+    _instance: PTP_CALLBACK_INSTANCE,
+    context: *mut c_void,
+    _work: PTP_WORK,
+) {
+    // Not atomic: waiting for the callbacks has to synchronize with them.
+    *context.cast::<u32>() += 1;
+}
+
+unsafe fn test_work() {
+    let counter = Box::into_raw(Box::new(0u32));
+    let work = CreateThreadpoolWork(Some(increment), counter.cast(), ptr::null());
+    assert!(!work.is_null());
+
+    for expected in 1..=3 {
+        SubmitThreadpoolWork(work);
+        WaitForThreadpoolWorkCallbacks(work, FALSE);
+        assert_eq!(*counter, expected);
+    }
+
+    // Waiting without any outstanding callbacks returns immediately.
+    WaitForThreadpoolWorkCallbacks(work, TRUE);
+    CloseThreadpoolWork(work);
+    assert_eq!(*Box::from_raw(counter), 3);
+}
+
+unsafe extern "system" fn timer_increment(
+    _instance: PTP_CALLBACK_INSTANCE,
+    context: *mut c_void,
+    _timer: PTP_TIMER,
+) {
+    (*context.cast::<AtomicU32>()).fetch_add(1, Ordering::Relaxed);
+}
+
+unsafe fn test_one_shot_timer() {
+    let counter = AtomicU32::new(0);
+    let timer = CreateThreadpoolTimer(
+        Some(timer_increment),
+        ptr::from_ref(&counter).cast_mut().cast(),
+        ptr::null(),
+    );
+    assert!(!timer.is_null());
+    assert_eq!(IsThreadpoolTimerSet(timer), FALSE);
+
+    let due = relative_due_time(10);
+    SetThreadpoolTimer(timer, &due, 0, 0);
+    assert_eq!(IsThreadpoolTimerSet(timer), TRUE);
+    // The timer does not fire before it is due.
+    assert_eq!(counter.load(Ordering::Relaxed), 0);
+
+    Sleep(50);
+    WaitForThreadpoolTimerCallbacks(timer, FALSE);
+    assert_eq!(counter.load(Ordering::Relaxed), 1);
+    assert_eq!(IsThreadpoolTimerSet(timer), FALSE);
+
+    // A timer that is cancelled before it is due never fires.
+    let due = relative_due_time(10);
+    SetThreadpoolTimer(timer, &due, 0, 0);
+    SetThreadpoolTimer(timer, ptr::null(), 0, 0);
+    assert_eq!(IsThreadpoolTimerSet(timer), FALSE);
+    Sleep(50);
+    assert_eq!(counter.load(Ordering::Relaxed), 1);
+
+    CloseThreadpoolTimer(timer);
+}
+
+unsafe fn test_periodic_timer() {
+    let counter = AtomicU32::new(0);
+    let timer = CreateThreadpoolTimer(
+        Some(timer_increment),
+        ptr::from_ref(&counter).cast_mut().cast(),
+        ptr::null(),
+    );
+
+    let due = relative_due_time(1);
+    SetThreadpoolTimer(timer, &due, 5, 0);
+    while counter.load(Ordering::Relaxed) < 3 {
+        Sleep(5);
+    }
+    // The timer keeps running until it is cancelled.
+    assert_eq!(IsThreadpoolTimerSet(timer), TRUE);
+    WaitForThreadpoolTimerCallbacks(timer, TRUE);
+    assert_eq!(IsThreadpoolTimerSet(timer), FALSE);
+
+    let fired = counter.load(Ordering::Relaxed);
+    Sleep(50);
+    assert_eq!(counter.load(Ordering::Relaxed), fired);
+
+    CloseThreadpoolTimer(timer);
+}
+
+static mut APC_LOG: Vec<usize> = Vec::new();
+
+unsafe extern "system" fn log_apc(data: usize) {
+    (*ptr::addr_of_mut!(APC_LOG)).push(data);
+}
+
+unsafe fn test_apc() {
+    assert_ne!(QueueUserAPC(Some(log_apc), GetCurrentThread(), 1), 0);
+    assert_ne!(QueueUserAPC(Some(log_apc), GetCurrentThread(), 2), 0);
+
+    // APCs only run during alertable waits.
+    assert_eq!(SleepEx(0, FALSE), 0);
+    assert!((*ptr::addr_of!(APC_LOG)).is_empty());
+
+    // All queued APCs run in order, and the wait returns right away.
+    assert_eq!(SleepEx(INFINITE, TRUE), WAIT_IO_COMPLETION);
+    assert_eq!(*ptr::addr_of!(APC_LOG), [1, 2]);
+
+    // Without queued APCs, an alertable wait just times out.
+    assert_eq!(SleepEx(10, TRUE), 0);
+    assert_eq!(*ptr::addr_of!(APC_LOG), [1, 2]);
+}
+
+static OTHER_APC: AtomicU32 = AtomicU32::new(0);
+
+unsafe extern "system" fn other_thread_apc(data: usize) {
+    OTHER_APC.store(data as u32, Ordering::Relaxed);
+}
+
+unsafe fn test_apc_other_thread() {
+    let waiter = thread::spawn(|| {
+        // This blocks until the main thread queues an APC.
+        assert_eq!(SleepEx(INFINITE, TRUE), WAIT_IO_COMPLETION);
+        assert_eq!(OTHER_APC.load(Ordering::Relaxed), 42);
+    });
+    // Let the other thread start waiting.
+    thread::yield_now();
+    assert_ne!(QueueUserAPC(Some(other_thread_apc), waiter.as_raw_handle(), 42), 0);
+    waiter.join().unwrap();
+}
@@ -0,0 +1,44 @@
+//@ignore-target: windows # no libc on Windows
+//@compile-flags: -Zmiri-alloc-fail-nth=2 -Zmiri-alloc-fail-above=65536 -Zmiri-heap-limit=100000
+//@compile-flags: -Zmiri-force-page-size=4
+// We start at `miri_start` so that the runtime does not make any allocations before us.
+#![no_main]
+use std::ptr;
+
+fn errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap()
+}
+
+#[no_mangle]
+fn miri_start(_argc: isize, _argv: *const *const u8) -> isize {
+    unsafe {
+        let a = libc::malloc(16);
+        assert!(!a.is_null());
+        // The second allocation fails.
+        let b = libc::malloc(16);
+        assert!(b.is_null());
+        assert_eq!(errno(), libc::ENOMEM);
+
+        // So do all allocations that are too large.
+        assert!(libc::malloc(100_000).is_null());
+        let c = libc::malloc(60_000);
+        assert!(!c.is_null());
+        let c = libc::realloc(c, 65_000);
+        assert!(!c.is_null());
+
+        // And those that would exceed the heap limit.
+        assert!(libc::malloc(40_000).is_null());
+        assert_eq!(errno(), libc::ENOMEM);
+
+        // `mmap` is subject to this as well.
+        let prot = libc::PROT_READ | libc::PROT_WRITE;
+        let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+        let map = libc::mmap(ptr::null_mut(), 70_000, prot, flags, -1, 0);
+        assert_eq!(map, libc::MAP_FAILED);
+        assert_eq!(errno(), libc::ENOMEM);
+
+        libc::free(a);
+        libc::free(c);
+    }
+    0
+}
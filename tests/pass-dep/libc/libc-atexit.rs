@@ -0,0 +1,41 @@
+//@ignore-target: windows # `__cxa_atexit` is not available on Windows
+use std::ffi::c_void;
+use std::ptr;
+
+extern "C" {
+    fn __cxa_atexit(
+        func: extern "C" fn(*mut c_void),
+        arg: *mut c_void,
+        dso_handle: *mut c_void,
+    ) -> libc::c_int;
+}
+
+extern "C" fn first() {
+    println!("first");
+}
+
+extern "C" fn second() {
+    println!("second");
+    // A handler registered while the handlers run is called next.
+    assert_eq!(unsafe { libc::atexit(nested) }, 0);
+}
+
+extern "C" fn nested() {
+    println!("nested");
+}
+
+extern "C" fn with_arg(arg: *mut c_void) {
+    // Freeing this here avoids a leak report, so the leak check happens after the handlers.
+    let arg = unsafe { Box::from_raw(arg.cast::<String>()) };
+    println!("with_arg: {arg}");
+}
+
+fn main() {
+    unsafe {
+        assert_eq!(libc::atexit(first), 0);
+        let arg = Box::into_raw(Box::new(String::from("hello")));
+        assert_eq!(__cxa_atexit(with_arg, arg.cast(), ptr::null_mut()), 0);
+        assert_eq!(libc::atexit(second), 0);
+    }
+    println!("main");
+}
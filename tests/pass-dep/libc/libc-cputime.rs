@@ -0,0 +1,86 @@
+//@ignore-target: windows # no libc time APIs on Windows
+use std::{mem, thread};
+
+fn cpu_time(clock: libc::clockid_t) -> u128 {
+    let mut ts: libc::timespec = unsafe { mem::zeroed() };
+    assert_eq!(unsafe { libc::clock_gettime(clock, &mut ts) }, 0);
+    ts.tv_sec as u128 * 1_000_000_000 + ts.tv_nsec as u128
+}
+
+fn busy_work(n: usize) {
+    for _ in 0..n {
+        drop(std::hint::black_box(vec![42]));
+    }
+}
+
+fn test_clock_gettime() {
+    let process = cpu_time(libc::CLOCK_PROCESS_CPUTIME_ID);
+    let thread = cpu_time(libc::CLOCK_THREAD_CPUTIME_ID);
+    assert!(thread <= process);
+    busy_work(10);
+    assert!(cpu_time(libc::CLOCK_PROCESS_CPUTIME_ID) > process);
+    assert!(cpu_time(libc::CLOCK_THREAD_CPUTIME_ID) > thread);
+}
+
+/// CPU time only counts the work a thread did itself.
+fn test_per_thread() {
+    let main_before = cpu_time(libc::CLOCK_THREAD_CPUTIME_ID);
+    let process_before = cpu_time(libc::CLOCK_PROCESS_CPUTIME_ID);
+    let child = thread::spawn(|| {
+        let start = cpu_time(libc::CLOCK_THREAD_CPUTIME_ID);
+        busy_work(1000);
+        cpu_time(libc::CLOCK_THREAD_CPUTIME_ID) - start
+    })
+    .join()
+    .unwrap();
+    let main = cpu_time(libc::CLOCK_THREAD_CPUTIME_ID) - main_before;
+    let process = cpu_time(libc::CLOCK_PROCESS_CPUTIME_ID) - process_before;
+    assert!(child > main);
+    assert!(process >= child + main);
+}
+
+// `pthread_getcpuclockid` does not exist on macOS.
+#[cfg(not(target_os = "macos"))]
+fn test_pthread_getcpuclockid() {
+    let mut clock: libc::clockid_t = 0;
+    assert_eq!(unsafe { libc::pthread_getcpuclockid(libc::pthread_self(), &mut clock) }, 0);
+    let own = cpu_time(libc::CLOCK_THREAD_CPUTIME_ID);
+    assert!(cpu_time(clock) >= own);
+}
+
+fn test_getrusage() {
+    let mut usage: libc::rusage = unsafe { mem::zeroed() };
+    assert_eq!(unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) }, 0);
+    let before = usage.ru_utime.tv_sec * 1_000_000 + usage.ru_utime.tv_usec as libc::time_t;
+    busy_work(1000);
+    assert_eq!(unsafe { libc::getrusage(libc::RUSAGE_SELF, &mut usage) }, 0);
+    let after = usage.ru_utime.tv_sec * 1_000_000 + usage.ru_utime.tv_usec as libc::time_t;
+    assert!(after > before);
+    assert_eq!((usage.ru_stime.tv_sec, usage.ru_stime.tv_usec), (0, 0));
+
+    assert_eq!(unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) }, 0);
+    assert_eq!((usage.ru_utime.tv_sec, usage.ru_utime.tv_usec), (0, 0));
+
+    assert_eq!(unsafe { libc::getrusage(1234, &mut usage) }, -1);
+    assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+}
+
+fn test_times() {
+    let ticks_per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    assert!(ticks_per_sec > 0);
+    let mut buf: libc::tms = unsafe { mem::zeroed() };
+    let start = unsafe { libc::times(&mut buf) };
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    let end = unsafe { libc::times(&mut buf) };
+    assert!(end - start >= ticks_per_sec as libc::clock_t);
+    assert_eq!((buf.tms_stime, buf.tms_cutime, buf.tms_cstime), (0, 0, 0));
+}
+
+fn main() {
+    test_clock_gettime();
+    test_per_thread();
+    #[cfg(not(target_os = "macos"))]
+    test_pthread_getcpuclockid();
+    test_getrusage();
+    test_times();
+}
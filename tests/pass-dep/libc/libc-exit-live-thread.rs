@@ -0,0 +1,27 @@
+//@ignore-target: windows # No libc `exit` on Windows
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::thread;
+
+static HANDLED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handler() {
+    HANDLED.store(true, Ordering::Relaxed);
+}
+
+extern "C" fn check_handled() {
+    // Handlers run in reverse order of registration.
+    assert!(HANDLED.load(Ordering::Relaxed));
+}
+
+fn main() {
+    let (_tx, rx) = mpsc::channel::<()>();
+    let _thread = thread::spawn(move || {
+        let _ = rx.recv();
+    });
+    unsafe {
+        assert_eq!(libc::atexit(check_handled), 0);
+        assert_eq!(libc::atexit(handler), 0);
+        libc::exit(0);
+    }
+}
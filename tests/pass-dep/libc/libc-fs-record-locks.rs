@@ -0,0 +1,134 @@
+//@ignore-target: windows # File handling is not implemented yet
+//@compile-flags: -Zmiri-disable-isolation
+
+use std::fs::{File, OpenOptions};
+use std::io::Error;
+use std::os::fd::AsRawFd;
+use std::path::Path;
+
+#[path = "../../utils/mod.rs"]
+mod utils;
+
+fn main() {
+    let path =
+        utils::prepare_with_content("miri_test_libc_fs_record_locks.txt", b"Hello, World!\n");
+
+    test_process_locks(&path);
+    #[cfg(target_os = "linux")]
+    {
+        test_ofd_locks(&path);
+        test_ofd_lock_blocking(&path);
+    }
+}
+
+fn open_rw(path: &Path) -> File {
+    OpenOptions::new().read(true).write(true).open(path).unwrap()
+}
+
+fn lock(l_type: libc::c_int, start: libc::off_t, len: libc::off_t) -> libc::flock {
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = l_type.try_into().unwrap();
+    lock.l_whence = libc::SEEK_SET.try_into().unwrap();
+    lock.l_start = start;
+    lock.l_len = len;
+    lock
+}
+
+fn fcntl_lock(file: &File, cmd: libc::c_int, lock: &mut libc::flock) -> Result<(), Error> {
+    let ret = unsafe { libc::fcntl(file.as_raw_fd(), cmd, lock as *mut libc::flock) };
+    if ret == 0 { Ok(()) } else { Err(Error::last_os_error()) }
+}
+
+fn test_process_locks(path: &Path) {
+    let file1 = open_rw(path);
+    let file2 = open_rw(path);
+
+    // Locks of the same process never conflict with each other.
+    fcntl_lock(&file1, libc::F_SETLK, &mut lock(libc::F_WRLCK, 0, 10)).unwrap();
+    fcntl_lock(&file2, libc::F_SETLK, &mut lock(libc::F_WRLCK, 5, 10)).unwrap();
+    let mut query = lock(libc::F_WRLCK, 0, 0);
+    fcntl_lock(&file2, libc::F_GETLK, &mut query).unwrap();
+    assert_eq!(i32::from(query.l_type), libc::F_UNLCK);
+
+    // Unlocking parts of a range works.
+    fcntl_lock(&file1, libc::F_SETLK, &mut lock(libc::F_UNLCK, 2, 3)).unwrap();
+
+    // Write locks need a writable file.
+    let read_only = File::open(path).unwrap();
+    let err = fcntl_lock(&read_only, libc::F_SETLK, &mut lock(libc::F_WRLCK, 0, 1)).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EBADF));
+
+    // Invalid ranges are rejected.
+    let err = fcntl_lock(&file1, libc::F_SETLK, &mut lock(libc::F_RDLCK, 0, -1)).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+
+    // Closing *any* descriptor for the file releases all locks of the process.
+    drop(read_only);
+    #[cfg(target_os = "linux")]
+    {
+        let mut query = lock(libc::F_WRLCK, 0, 0);
+        fcntl_lock(&file1, libc::F_OFD_GETLK, &mut query).unwrap();
+        assert_eq!(i32::from(query.l_type), libc::F_UNLCK);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn test_ofd_locks(path: &Path) {
+    let file1 = open_rw(path);
+    let file2 = open_rw(path);
+
+    fcntl_lock(&file1, libc::F_SETLK, &mut lock(libc::F_RDLCK, 0, 10)).unwrap();
+    fcntl_lock(&file1, libc::F_OFD_SETLK, &mut lock(libc::F_WRLCK, 20, 0)).unwrap();
+
+    // Process locks and open file description locks conflict with each other.
+    let mut query = lock(libc::F_WRLCK, 5, 1);
+    fcntl_lock(&file2, libc::F_OFD_GETLK, &mut query).unwrap();
+    assert_eq!(i32::from(query.l_type), libc::F_RDLCK);
+    assert_eq!((query.l_start, query.l_len), (0, 10));
+    assert_eq!(query.l_pid, i32::try_from(std::process::id()).unwrap());
+
+    let mut query = lock(libc::F_RDLCK, 30, 1);
+    fcntl_lock(&file2, libc::F_GETLK, &mut query).unwrap();
+    assert_eq!(i32::from(query.l_type), libc::F_WRLCK);
+    assert_eq!((query.l_start, query.l_len), (20, 0));
+    assert_eq!(query.l_pid, -1);
+
+    // Shared locks are compatible, exclusive ones are not.
+    fcntl_lock(&file2, libc::F_OFD_SETLK, &mut lock(libc::F_RDLCK, 0, 10)).unwrap();
+    let err = fcntl_lock(&file2, libc::F_OFD_SETLK, &mut lock(libc::F_WRLCK, 15, 10)).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EAGAIN));
+    fcntl_lock(&file2, libc::F_OFD_SETLK, &mut lock(libc::F_WRLCK, 10, 10)).unwrap();
+
+    // `l_pid` must be zero for open file description locks.
+    let mut bad = lock(libc::F_RDLCK, 0, 1);
+    bad.l_pid = 1;
+    let err = fcntl_lock(&file2, libc::F_OFD_SETLK, &mut bad).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(libc::EINVAL));
+
+    // Closing the description releases its locks.
+    drop(file1);
+    fcntl_lock(&file2, libc::F_OFD_SETLK, &mut lock(libc::F_WRLCK, 20, 0)).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+fn test_ofd_lock_blocking(path: &Path) {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    static RELEASED: AtomicBool = AtomicBool::new(false);
+
+    let file1 = open_rw(path);
+    let file2 = open_rw(path);
+    fcntl_lock(&file1, libc::F_OFD_SETLK, &mut lock(libc::F_WRLCK, 0, 0)).unwrap();
+
+    let waiter = thread::spawn(move || {
+        // This blocks until the main thread releases its lock.
+        fcntl_lock(&file2, libc::F_OFD_SETLKW, &mut lock(libc::F_WRLCK, 0, 1)).unwrap();
+        assert!(RELEASED.load(Ordering::Relaxed));
+    });
+
+    thread::yield_now();
+    RELEASED.store(true, Ordering::Relaxed);
+    fcntl_lock(&file1, libc::F_OFD_SETLK, &mut lock(libc::F_UNLCK, 0, 0)).unwrap();
+    waiter.join().unwrap();
+}
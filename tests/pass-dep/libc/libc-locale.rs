@@ -0,0 +1,100 @@
+//@only-target: linux apple freebsd # the `newlocale` family is not supported everywhere
+//@compile-flags: -Zmiri-env-set=LANG=C.UTF-8
+use std::ffi::{CStr, c_char};
+use std::{io, ptr, thread};
+
+fn str(ptr: *const c_char) -> &'static str {
+    assert!(!ptr.is_null());
+    unsafe { CStr::from_ptr(ptr) }.to_str().unwrap()
+}
+
+fn test_setlocale() {
+    unsafe {
+        assert_eq!(str(libc::setlocale(libc::LC_ALL, ptr::null())), "C");
+        assert_eq!(
+            str(libc::nl_langinfo(libc::CODESET)),
+            if cfg!(target_os = "linux") { "ANSI_X3.4-1968" } else { "US-ASCII" }
+        );
+
+        // The empty name selects the locale from the environment.
+        assert_eq!(str(libc::setlocale(libc::LC_ALL, c"".as_ptr())), "C.UTF-8");
+        assert_eq!(str(libc::nl_langinfo(libc::CODESET)), "UTF-8");
+
+        // Categories can differ.
+        assert_eq!(str(libc::setlocale(libc::LC_NUMERIC, c"POSIX".as_ptr())), "C");
+        let all = str(libc::setlocale(libc::LC_ALL, ptr::null())).to_owned();
+        assert!(all.contains("LC_CTYPE=C.UTF-8;LC_NUMERIC=C;"), "{all}");
+        assert_eq!(str(libc::setlocale(libc::LC_ALL, c"C".as_ptr())), "C");
+        // A composite name restores the locale it was returned for.
+        let all = std::ffi::CString::new(all).unwrap();
+        assert!(!libc::setlocale(libc::LC_ALL, all.as_ptr()).is_null());
+        assert_eq!(str(libc::setlocale(libc::LC_CTYPE, ptr::null())), "C.UTF-8");
+        assert_eq!(str(libc::setlocale(libc::LC_NUMERIC, ptr::null())), "C");
+
+        // Unknown locales are rejected without changing anything.
+        assert!(libc::setlocale(libc::LC_ALL, c"de_DE.UTF-8".as_ptr()).is_null());
+        assert_eq!(str(libc::setlocale(libc::LC_CTYPE, ptr::null())), "C.UTF-8");
+        libc::setlocale(libc::LC_ALL, c"C".as_ptr());
+    }
+}
+
+fn test_langinfo() {
+    unsafe {
+        assert_eq!(str(libc::nl_langinfo(libc::RADIXCHAR)), ".");
+        assert_eq!(str(libc::nl_langinfo(libc::THOUSEP)), "");
+        assert_eq!(str(libc::nl_langinfo(libc::D_T_FMT)), "%a %b %e %H:%M:%S %Y");
+        assert_eq!(str(libc::nl_langinfo(libc::DAY_1)), "Sunday");
+        assert_eq!(str(libc::nl_langinfo(libc::ABMON_12)), "Dec");
+        assert_eq!(str(libc::nl_langinfo(libc::YESEXPR)), "^[yY]");
+
+        let lconv = &*libc::localeconv();
+        assert_eq!(str(lconv.decimal_point), ".");
+        assert_eq!(str(lconv.thousands_sep), "");
+        assert_eq!(str(lconv.currency_symbol), "");
+        assert_eq!(lconv.frac_digits, c_char::MAX);
+    }
+}
+
+fn test_locale_objects() {
+    unsafe {
+        // Invalid names and masks.
+        assert!(libc::newlocale(libc::LC_ALL_MASK, c"xx".as_ptr(), ptr::null_mut()).is_null());
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::ENOENT));
+        assert!(libc::newlocale(-1, c"C".as_ptr(), ptr::null_mut()).is_null());
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+
+        let utf8 = libc::newlocale(libc::LC_CTYPE_MASK, c"C.UTF-8".as_ptr(), ptr::null_mut());
+        assert!(!utf8.is_null());
+        // Extend the object with another category.
+        let utf8 = libc::newlocale(libc::LC_TIME_MASK, c"C.UTF-8".as_ptr(), utf8);
+        let copy = libc::duplocale(utf8);
+
+        // `uselocale` only affects the current thread.
+        assert_eq!(libc::uselocale(copy), libc::LC_GLOBAL_LOCALE);
+        assert_eq!(libc::uselocale(ptr::null_mut()), copy);
+        assert_eq!(str(libc::nl_langinfo(libc::CODESET)), "UTF-8");
+        thread::spawn(|| {
+            assert_eq!(libc::uselocale(ptr::null_mut()), libc::LC_GLOBAL_LOCALE);
+            assert_ne!(str(libc::nl_langinfo(libc::CODESET)), "UTF-8");
+        })
+        .join()
+        .unwrap();
+        assert_eq!(libc::uselocale(libc::LC_GLOBAL_LOCALE), copy);
+        assert_ne!(str(libc::nl_langinfo(libc::CODESET)), "UTF-8");
+
+        let global = libc::duplocale(libc::LC_GLOBAL_LOCALE);
+        libc::uselocale(global);
+        assert_ne!(str(libc::nl_langinfo(libc::CODESET)), "UTF-8");
+        libc::uselocale(libc::LC_GLOBAL_LOCALE);
+
+        libc::freelocale(global);
+        libc::freelocale(copy);
+        libc::freelocale(utf8);
+    }
+}
+
+fn main() {
+    test_setlocale();
+    test_langinfo();
+    test_locale_objects();
+}
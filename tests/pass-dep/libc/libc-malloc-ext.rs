@@ -0,0 +1,128 @@
+//@only-target: linux # `malloc_usable_size` and `malloc_trim` are glibc extensions
+#![feature(pointer_is_aligned_to)]
+use std::{ptr, slice};
+
+// jemalloc's non-standard API, as used by e.g. `tikv-jemallocator` without symbol prefixes.
+extern "C" {
+    fn mallocx(size: usize, flags: i32) -> *mut libc::c_void;
+    fn rallocx(ptr: *mut libc::c_void, size: usize, flags: i32) -> *mut libc::c_void;
+    fn sallocx(ptr: *const libc::c_void, flags: i32) -> usize;
+    fn nallocx(size: usize, flags: i32) -> usize;
+    fn dallocx(ptr: *mut libc::c_void, flags: i32);
+    fn sdallocx(ptr: *mut libc::c_void, size: usize, flags: i32);
+    fn mallctl(
+        name: *const libc::c_char,
+        oldp: *mut libc::c_void,
+        oldlenp: *mut usize,
+        newp: *mut libc::c_void,
+        newlen: usize,
+    ) -> i32;
+}
+
+const MALLOCX_ZERO: i32 = 0x40;
+
+const fn mallocx_lg_align(la: i32) -> i32 {
+    la
+}
+
+fn test_usable_size() {
+    unsafe {
+        assert_eq!(libc::malloc_usable_size(ptr::null_mut()), 0);
+        let p = libc::malloc(13);
+        assert_eq!(libc::malloc_usable_size(p), 13);
+        let p = libc::realloc(p, 100);
+        assert_eq!(libc::malloc_usable_size(p), 100);
+        libc::free(p);
+
+        assert_eq!(libc::malloc_trim(0), 0);
+    }
+}
+
+fn test_mallocx() {
+    unsafe {
+        let p = mallocx(24, 0);
+        assert!(!p.is_null());
+        assert_eq!(sallocx(p, 0), 24);
+        dallocx(p, 0);
+
+        // Alignment requests are honored.
+        let p = mallocx(8, mallocx_lg_align(12));
+        assert!(p.is_aligned_to(4096));
+        // ... and so is zeroing, also when growing.
+        let p = rallocx(p, 4, MALLOCX_ZERO);
+        let p = rallocx(p, 64, MALLOCX_ZERO | mallocx_lg_align(6)) as *mut u8;
+        assert!(p.is_aligned_to(64));
+        assert!(slice::from_raw_parts(p.add(4), 60).iter().all(|&b| b == 0));
+        sdallocx(p.cast(), 64, mallocx_lg_align(6));
+
+        let p = mallocx(32, MALLOCX_ZERO) as *mut u8;
+        assert!(slice::from_raw_parts(p, 32).iter().all(|&b| b == 0));
+        // These are interchangeable with the standard functions.
+        libc::free(p.cast());
+
+        assert_eq!(nallocx(7, 0), 7);
+        assert_eq!(nallocx(usize::MAX, 0), 0);
+        assert!(mallocx(usize::MAX, 0).is_null());
+    }
+}
+
+fn test_mallctl() {
+    unsafe fn read<T: Default>(name: &str) -> Result<T, i32> {
+        let name = std::ffi::CString::new(name).unwrap();
+        let mut value = T::default();
+        let mut len = size_of::<T>();
+        match mallctl(name.as_ptr(), (&raw mut value).cast(), &mut len, ptr::null_mut(), 0) {
+            0 => Ok(value),
+            err => Err(err),
+        }
+    }
+
+    unsafe {
+        assert_eq!(read::<bool>("config.stats"), Ok(true));
+        assert_eq!(read::<u32>("arenas.narenas"), Ok(1));
+
+        let before = read::<usize>("stats.allocated").unwrap();
+        let p = libc::malloc(1000);
+        assert_eq!(read::<usize>("stats.allocated").unwrap(), before + 1000);
+        assert_eq!(read::<usize>("stats.active").unwrap(), before + 1000);
+        libc::free(p);
+        assert_eq!(read::<usize>("stats.allocated").unwrap(), before);
+
+        // Advancing the epoch works like it does with `tikv-jemalloc-ctl`.
+        let mut epoch = 1u64;
+        let mut len = size_of::<u64>();
+        assert_eq!(
+            mallctl(
+                c"epoch".as_ptr(),
+                (&raw mut epoch).cast(),
+                &mut len,
+                (&raw mut epoch).cast(),
+                size_of::<u64>(),
+            ),
+            0
+        );
+        assert_eq!(epoch, 1);
+        assert_eq!(read::<u64>("epoch"), Ok(1));
+
+        // Errors.
+        assert_eq!(read::<u64>("stats.no.such.thing"), Err(libc::ENOENT));
+        assert_eq!(read::<u16>("stats.allocated"), Err(libc::EINVAL));
+        let mut value = 0usize;
+        assert_eq!(
+            mallctl(
+                c"stats.allocated".as_ptr(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                (&raw mut value).cast(),
+                size_of::<usize>(),
+            ),
+            libc::EPERM
+        );
+    }
+}
+
+fn main() {
+    test_usable_size();
+    test_mallocx();
+    test_mallctl();
+}
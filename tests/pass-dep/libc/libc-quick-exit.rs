@@ -0,0 +1,37 @@
+//@only-target: linux # `quick_exit` is not available on all targets
+use std::cell::Cell;
+use std::io;
+
+thread_local! {
+    static IN_MAIN_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+extern "C" {
+    fn at_quick_exit(func: extern "C" fn()) -> libc::c_int;
+    fn quick_exit(code: libc::c_int) -> !;
+}
+
+extern "C" fn quick_first() {
+    // The handlers run on the thread that called `quick_exit`, which still holds the stdout lock.
+    assert!(IN_MAIN_THREAD.get());
+    println!("quick_first");
+}
+
+extern "C" fn quick_second() {
+    println!("quick_second");
+}
+
+extern "C" fn not_called() {
+    println!("this is only called by `exit`");
+}
+
+fn main() {
+    IN_MAIN_THREAD.set(true);
+    let _lock = io::stdout().lock();
+    unsafe {
+        assert_eq!(libc::atexit(not_called), 0);
+        assert_eq!(at_quick_exit(quick_first), 0);
+        assert_eq!(at_quick_exit(quick_second), 0);
+        quick_exit(0);
+    }
+}
@@ -0,0 +1,47 @@
+//@ignore-target: windows # no libc resource limits on Windows
+//@compile-flags: -Zmiri-rlimit=as=1048576
+use std::alloc::{Layout, alloc, dealloc};
+
+fn errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap()
+}
+
+fn main() {
+    unsafe {
+        // A single allocation above the limit fails.
+        let p = libc::malloc(2 << 20);
+        assert!(p.is_null());
+        assert_eq!(errno(), libc::ENOMEM);
+
+        // So does growing an allocation beyond it.
+        let p = libc::malloc(512 << 10);
+        assert!(!p.is_null());
+        assert!(libc::realloc(p, 2 << 20).is_null());
+        assert_eq!(errno(), libc::ENOMEM);
+        // ... and allocating more in total.
+        let q = libc::malloc(768 << 10);
+        assert!(q.is_null());
+        // Once memory is freed, it can be used again.
+        libc::free(p);
+        let q = libc::malloc(768 << 10);
+        assert!(!q.is_null());
+        libc::free(q);
+
+        // The Rust allocator reports failure by returning null as well.
+        let layout = Layout::from_size_align(2 << 20, 8).unwrap();
+        assert!(alloc(layout).is_null());
+        let layout = Layout::from_size_align(1024, 8).unwrap();
+        let p = alloc(layout);
+        assert!(!p.is_null());
+        dealloc(p, layout);
+
+        // Lowering the limit below what is in use only affects future allocations.
+        let p = libc::malloc(4096);
+        let rlim = libc::rlimit { rlim_cur: 1024, rlim_max: 1048576 };
+        assert_eq!(libc::setrlimit(libc::RLIMIT_AS as _, &rlim), 0);
+        assert!(libc::malloc(1).is_null());
+        libc::free(p);
+        let rlim = libc::rlimit { rlim_cur: 1048576, rlim_max: 1048576 };
+        assert_eq!(libc::setrlimit(libc::RLIMIT_AS as _, &rlim), 0);
+    }
+}
@@ -0,0 +1,98 @@
+//@ignore-target: windows # no libc resource limits on Windows
+//@compile-flags: -Zmiri-rlimit=nofile=64:128
+use std::mem;
+
+fn errno() -> i32 {
+    std::io::Error::last_os_error().raw_os_error().unwrap()
+}
+
+fn getrlimit(resource: libc::c_int) -> libc::rlimit {
+    let mut rlim: libc::rlimit = unsafe { mem::zeroed() };
+    assert_eq!(unsafe { libc::getrlimit(resource as _, &mut rlim) }, 0);
+    rlim
+}
+
+fn setrlimit(resource: libc::c_int, cur: libc::rlim_t, max: libc::rlim_t) -> libc::c_int {
+    let rlim = libc::rlimit { rlim_cur: cur, rlim_max: max };
+    unsafe { libc::setrlimit(resource as _, &rlim) }
+}
+
+fn test_get_set() {
+    let rlim = getrlimit(libc::RLIMIT_NOFILE as _);
+    assert_eq!((rlim.rlim_cur, rlim.rlim_max), (64, 128));
+    assert_eq!(unsafe { libc::sysconf(libc::_SC_OPEN_MAX) }, 64);
+
+    // Raising the soft limit up to the hard limit is fine.
+    assert_eq!(setrlimit(libc::RLIMIT_NOFILE as _, 128, 128), 0);
+    assert_eq!(unsafe { libc::sysconf(libc::_SC_OPEN_MAX) }, 128);
+    // Raising the hard limit is not.
+    assert_eq!(setrlimit(libc::RLIMIT_NOFILE as _, 128, 256), -1);
+    assert_eq!(errno(), libc::EPERM);
+    // The soft limit cannot exceed the hard limit.
+    assert_eq!(setrlimit(libc::RLIMIT_NOFILE as _, 100, 90), -1);
+    assert_eq!(errno(), libc::EINVAL);
+
+    // Limits we do not enforce are still remembered.
+    let core = getrlimit(libc::RLIMIT_CORE as _);
+    assert_eq!((core.rlim_cur, core.rlim_max), (libc::RLIM_INFINITY, libc::RLIM_INFINITY));
+    assert_eq!(setrlimit(libc::RLIMIT_CORE as _, 0, libc::RLIM_INFINITY), 0);
+    assert_eq!(getrlimit(libc::RLIMIT_CORE as _).rlim_cur, 0);
+
+    // Invalid resources are rejected.
+    let mut rlim: libc::rlimit = unsafe { mem::zeroed() };
+    assert_eq!(unsafe { libc::getrlimit(-1i32 as _, &mut rlim) }, -1);
+    assert_eq!(errno(), libc::EINVAL);
+}
+
+fn test_nofile_enforced() {
+    assert_eq!(setrlimit(libc::RLIMIT_NOFILE as _, 8, 8), 0);
+    // Fill up the fd table; stdin, stdout and stderr already take 0..3.
+    let mut fds = vec![];
+    loop {
+        let fd = unsafe { libc::dup(1) };
+        if fd == -1 {
+            assert_eq!(errno(), libc::EMFILE);
+            break;
+        }
+        fds.push(fd);
+    }
+    assert_eq!(fds.len(), 5);
+
+    let mut pipe = [0; 2];
+    assert_eq!(unsafe { libc::pipe(pipe.as_mut_ptr()) }, -1);
+    assert_eq!(errno(), libc::EMFILE);
+    assert_eq!(unsafe { libc::dup2(1, 8) }, -1);
+    assert_eq!(errno(), libc::EBADF);
+
+    // Closing two fds makes room for a pipe again.
+    for fd in fds.drain(..2) {
+        assert_eq!(unsafe { libc::close(fd) }, 0);
+    }
+    assert_eq!(unsafe { libc::pipe(pipe.as_mut_ptr()) }, 0);
+    for fd in pipe.into_iter().chain(fds) {
+        assert_eq!(unsafe { libc::close(fd) }, 0);
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn test_prlimit() {
+    let mut old: libc::rlimit = unsafe { mem::zeroed() };
+    let new = libc::rlimit { rlim_cur: 4, rlim_max: 8 };
+    assert_eq!(unsafe { libc::prlimit(0, libc::RLIMIT_NOFILE, &new, &mut old) }, 0);
+    assert_eq!((old.rlim_cur, old.rlim_max), (8, 8));
+    assert_eq!(unsafe { libc::prlimit(0, libc::RLIMIT_NOFILE, std::ptr::null(), &mut old) }, 0);
+    assert_eq!((old.rlim_cur, old.rlim_max), (4, 8));
+    // There is no other process.
+    assert_eq!(
+        unsafe { libc::prlimit(12345, libc::RLIMIT_NOFILE, std::ptr::null(), &mut old) },
+        -1
+    );
+    assert_eq!(errno(), libc::ESRCH);
+}
+
+fn main() {
+    test_get_set();
+    test_nofile_enforced();
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    test_prlimit();
+}
@@ -0,0 +1,247 @@
+//@ignore-target: windows # only very limited libc on Windows
+use std::cell::Cell;
+use std::ffi::{CStr, c_char, c_int, c_void};
+use std::{io, ptr};
+
+fn format(f: impl FnOnce(*mut c_char, usize) -> c_int) -> String {
+    let mut buf = [0 as c_char; 128];
+    let len = f(buf.as_mut_ptr(), buf.len());
+    let s = unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str().unwrap().to_owned();
+    assert_eq!(len, s.len() as c_int);
+    s
+}
+
+fn test_snprintf() {
+    unsafe {
+        let s = format(|buf, n| {
+            libc::snprintf(buf, n, c"%d|%5d|%-5d|%05d|%+d".as_ptr(), 42, 42, 42, 42, 42)
+        });
+        assert_eq!(s, "42|   42|42   |00042|+42");
+        let s = format(|buf, n| {
+            libc::snprintf(buf, n, c"%x %X %#x %o %#o".as_ptr(), 255, 255, 255, 255, 255)
+        });
+        assert_eq!(s, "ff FF 0xff 377 0377");
+        let s = format(|buf, n| {
+            libc::snprintf(
+                buf,
+                n,
+                c"%ld %lld %zu %hhd %u".as_ptr(),
+                -1 as libc::c_long,
+                1i64 << 40,
+                7usize,
+                300,
+                -1,
+            )
+        });
+        assert_eq!(s, "-1 1099511627776 7 44 4294967295");
+        let s = format(|buf, n| {
+            libc::snprintf(
+                buf,
+                n,
+                c"%.3f %e %g %g %.1f".as_ptr(),
+                3.14159,
+                12345.678,
+                0.0001,
+                1e20,
+                3.75,
+            )
+        });
+        assert_eq!(s, "3.142 1.234568e+04 0.0001 1e+20 3.8");
+        let s = format(|buf, n| {
+            libc::snprintf(
+                buf,
+                n,
+                c"%s|%.3s|%10s|%c|%%|%*d|%-*.*f|".as_ptr(),
+                c"hello".as_ptr(),
+                c"hello".as_ptr(),
+                c"hi".as_ptr(),
+                b'x' as c_int,
+                6,
+                7,
+                6,
+                1,
+                0.5,
+            )
+        });
+        assert_eq!(s, "hello|hel|        hi|x|%|     7|0.5   |");
+        // `%.Ns` may point to a buffer that is not NUL-terminated.
+        let bytes = *b"abc";
+        let s = format(|buf, n| libc::snprintf(buf, n, c"%.2s".as_ptr(), bytes.as_ptr()));
+        assert_eq!(s, "ab");
+
+        // Truncation.
+        let mut buf = [1 as c_char; 8];
+        assert_eq!(
+            libc::snprintf(buf.as_mut_ptr(), buf.len(), c"%s".as_ptr(), c"abcdefghij".as_ptr()),
+            10
+        );
+        assert_eq!(CStr::from_ptr(buf.as_ptr()), c"abcdefg");
+        assert_eq!(libc::snprintf(ptr::null_mut(), 0, c"%d".as_ptr(), 12345), 5);
+
+        let mut buf = [0 as c_char; 16];
+        assert_eq!(libc::sprintf(buf.as_mut_ptr(), c"%d-%s".as_ptr(), 12, c"ab".as_ptr()), 5);
+        assert_eq!(CStr::from_ptr(buf.as_ptr()), c"12-ab");
+    }
+}
+
+fn test_printf() {
+    unsafe {
+        assert_eq!(libc::printf(c"hello %s %d\n".as_ptr(), c"world".as_ptr(), 5), 14);
+        assert_eq!(libc::dprintf(1, c"%05.1f\n".as_ptr(), 2.5), 6);
+        assert_eq!(libc::dprintf(-1, c"%d\n".as_ptr(), 1), -1);
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EBADF));
+    }
+}
+
+fn test_strtol() {
+    unsafe {
+        let s = c"  -123abc";
+        let mut end = ptr::null_mut();
+        assert_eq!(libc::strtol(s.as_ptr(), &mut end, 10), -123);
+        assert_eq!(end.cast_const(), s.as_ptr().add(7));
+
+        assert_eq!(libc::strtol(c"0x1F".as_ptr(), ptr::null_mut(), 0), 31);
+        assert_eq!(libc::strtol(c"077".as_ptr(), ptr::null_mut(), 0), 63);
+        assert_eq!(libc::strtol(c"zz".as_ptr(), ptr::null_mut(), 36), 1295);
+        // `0x` without hex digits is just a `0`.
+        let s = c"0x";
+        assert_eq!(libc::strtol(s.as_ptr(), &mut end, 16), 0);
+        assert_eq!(end.cast_const(), s.as_ptr().add(1));
+        // No number at all.
+        let s = c"  abc";
+        assert_eq!(libc::strtol(s.as_ptr(), &mut end, 10), 0);
+        assert_eq!(end.cast_const(), s.as_ptr());
+
+        assert_eq!(libc::strtoll(c"-9223372036854775808".as_ptr(), ptr::null_mut(), 10), i64::MIN);
+        assert_eq!(libc::strtoull(c"18446744073709551615".as_ptr(), ptr::null_mut(), 10), u64::MAX);
+        assert_eq!(libc::strtoul(c"-1".as_ptr(), ptr::null_mut(), 10), libc::c_ulong::MAX);
+
+        // Errors.
+        assert_eq!(libc::strtoll(c"99999999999999999999".as_ptr(), ptr::null_mut(), 10), i64::MAX);
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::ERANGE));
+        assert_eq!(libc::strtol(c"1".as_ptr(), ptr::null_mut(), 1), 0);
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+    }
+}
+
+fn test_strtod() {
+    unsafe {
+        let s = c" 1.5e3x";
+        let mut end = ptr::null_mut();
+        assert_eq!(libc::strtod(s.as_ptr(), &mut end), 1500.0);
+        assert_eq!(end.cast_const(), s.as_ptr().add(6));
+        // An exponent without digits is not part of the number.
+        assert_eq!(libc::strtod(c"2e+".as_ptr(), &mut end), 2.0);
+        assert_eq!(libc::strtod(c"-.25".as_ptr(), ptr::null_mut()), -0.25);
+        assert_eq!(libc::strtod(c"-Infinity".as_ptr(), ptr::null_mut()), f64::NEG_INFINITY);
+        assert!(libc::strtod(c"nan".as_ptr(), ptr::null_mut()).is_nan());
+        assert_eq!(libc::strtof(c"0.1".as_ptr(), ptr::null_mut()), 0.1f32);
+
+        let s = c"abc";
+        assert_eq!(libc::strtod(s.as_ptr(), &mut end), 0.0);
+        assert_eq!(end.cast_const(), s.as_ptr());
+
+        assert_eq!(libc::strtod(c"1e999".as_ptr(), ptr::null_mut()), f64::INFINITY);
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::ERANGE));
+        assert_eq!(libc::strtof(c"1e-99".as_ptr(), ptr::null_mut()), 0.0);
+        assert_eq!(io::Error::last_os_error().raw_os_error(), Some(libc::ERANGE));
+    }
+}
+
+extern "C" fn compare_i32(a: *const c_void, b: *const c_void) -> c_int {
+    let (a, b) = unsafe { (*a.cast::<i32>(), *b.cast::<i32>()) };
+    a.cmp(&b) as c_int
+}
+
+extern "C" fn compare_first(a: *const c_void, b: *const c_void) -> c_int {
+    let (a, b) = unsafe { (*a.cast::<(u8, u8)>(), *b.cast::<(u8, u8)>()) };
+    a.0.cmp(&b.0) as c_int
+}
+
+extern "C" fn compare_refs(a: *const c_void, b: *const c_void) -> c_int {
+    let (a, b) = unsafe { (*a.cast::<&i32>(), *b.cast::<&i32>()) };
+    a.cmp(b) as c_int
+}
+
+thread_local! {
+    static COMPARISONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Counts its calls in a thread-local, and takes the stdout lock that the caller of `qsort` holds.
+extern "C" fn compare_on_caller(a: *const c_void, b: *const c_void) -> c_int {
+    COMPARISONS.set(COMPARISONS.get() + 1);
+    let _lock = io::stdout().lock();
+    compare_i32(a, b)
+}
+
+fn test_qsort() {
+    unsafe {
+        let mut values = [5, 3, 9, 1, 3, 7, -2];
+        libc::qsort(values.as_mut_ptr().cast(), values.len(), 4, Some(compare_i32));
+        assert_eq!(values, [-2, 1, 3, 3, 5, 7, 9]);
+
+        // The sort is stable.
+        let mut pairs = [(2u8, 0u8), (1, 1), (2, 2), (1, 3), (0, 4)];
+        libc::qsort(pairs.as_mut_ptr().cast(), pairs.len(), 2, Some(compare_first));
+        assert_eq!(pairs, [(0, 4), (1, 1), (1, 3), (2, 0), (2, 2)]);
+
+        // Sorting keeps the provenance of pointers.
+        let (a, b, c) = (3, 1, 2);
+        let mut refs = [&a, &b, &c];
+        libc::qsort(refs.as_mut_ptr().cast(), refs.len(), size_of::<&i32>(), Some(compare_refs));
+        assert_eq!(refs.map(|r| *r), [1, 2, 3]);
+
+        // Nothing to sort.
+        libc::qsort(ptr::null_mut(), 0, 4, Some(compare_i32));
+
+        // The comparison function runs on the thread that called `qsort`.
+        let mut small = [2, 1, 3];
+        let lock = io::stdout().lock();
+        libc::qsort(small.as_mut_ptr().cast(), small.len(), 4, Some(compare_on_caller));
+        drop(lock);
+        assert_eq!(small, [1, 2, 3]);
+        assert!(COMPARISONS.get() > 0);
+
+        let found = libc::bsearch(
+            (&raw const values[5]).cast(),
+            values.as_ptr().cast(),
+            values.len(),
+            4,
+            Some(compare_i32),
+        );
+        assert_eq!(found.cast_const(), (&raw const values[5]).cast());
+        let missing = 4;
+        let found = libc::bsearch(
+            (&raw const missing).cast(),
+            values.as_ptr().cast(),
+            values.len(),
+            4,
+            Some(compare_i32),
+        );
+        assert!(found.is_null());
+    }
+}
+
+fn test_strings() {
+    unsafe {
+        let copy = libc::strdup(c"hello".as_ptr());
+        assert_eq!(CStr::from_ptr(copy), c"hello");
+        libc::free(copy.cast());
+
+        assert_eq!(libc::strncmp(c"abcd".as_ptr(), c"abcf".as_ptr(), 3), 0);
+        assert!(libc::strncmp(c"abcd".as_ptr(), c"abcf".as_ptr(), 4) < 0);
+        assert!(libc::strncmp(c"ab".as_ptr(), c"a".as_ptr(), 5) > 0);
+        // Only the first `n` bytes need to be readable.
+        let (left, right) = (*b"xy", *b"xy");
+        assert_eq!(libc::strncmp(left.as_ptr().cast(), right.as_ptr().cast(), 2), 0);
+    }
+}
+
+fn main() {
+    test_snprintf();
+    test_printf();
+    test_strtol();
+    test_strtod();
+    test_qsort();
+    test_strings();
+}
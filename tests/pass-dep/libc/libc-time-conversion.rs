@@ -0,0 +1,150 @@
+//@ignore-target: windows # no libc time APIs on Windows
+// Time conversions only depend on their input and `TZ`, so they work under isolation.
+use std::ffi::CStr;
+use std::{env, mem};
+
+fn main() {
+    test_gmtime_r();
+    test_localtime_r_dst();
+    test_timegm_normalizes();
+    test_mktime_roundtrip();
+    test_mktime_ambiguous();
+    test_mktime_gap();
+    test_strftime();
+    test_difftime();
+    unsafe { libc::tzset() };
+}
+
+fn tm_for(year: i32, mon: i32, mday: i32, hour: i32, min: i32, isdst: i32) -> libc::tm {
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    tm.tm_year = year - 1900;
+    tm.tm_mon = mon - 1;
+    tm.tm_mday = mday;
+    tm.tm_hour = hour;
+    tm.tm_min = min;
+    tm.tm_isdst = isdst;
+    tm
+}
+
+#[allow(unused_variables)]
+fn check_zone(tm: &libc::tm, gmtoff: i64, zone: &str) {
+    #[cfg(not(any(target_os = "solaris", target_os = "illumos")))]
+    {
+        assert_eq!(tm.tm_gmtoff as i64, gmtoff);
+        assert_eq!(unsafe { CStr::from_ptr(tm.tm_zone) }.to_str().unwrap(), zone);
+    }
+}
+
+fn test_gmtime_r() {
+    env::set_var("TZ", "Europe/Berlin"); // must not affect `gmtime_r`
+    let t: libc::time_t = 1712475836; // 2024-04-07 07:43:56 UTC
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    let res = unsafe { libc::gmtime_r(&t, &mut tm) };
+    assert_eq!(res, &mut tm as *mut _);
+    assert_eq!((tm.tm_hour, tm.tm_min, tm.tm_sec), (7, 43, 56));
+    assert_eq!((tm.tm_year, tm.tm_mon, tm.tm_mday), (124, 3, 7));
+    assert_eq!((tm.tm_wday, tm.tm_yday, tm.tm_isdst), (0, 97, 0));
+    check_zone(&tm, 0, "GMT");
+    env::remove_var("TZ");
+}
+
+fn test_localtime_r_dst() {
+    env::set_var("TZ", "Europe/Berlin");
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+
+    // Summer: CEST.
+    let t: libc::time_t = 1719828000; // 2024-07-01 10:00:00 UTC
+    unsafe { libc::localtime_r(&t, &mut tm) };
+    assert_eq!((tm.tm_hour, tm.tm_isdst), (12, 1));
+    check_zone(&tm, 7200, "CEST");
+
+    // Winter: CET.
+    let t: libc::time_t = 1706745600; // 2024-02-01 00:00:00 UTC
+    unsafe { libc::localtime_r(&t, &mut tm) };
+    assert_eq!((tm.tm_hour, tm.tm_isdst), (1, 0));
+    check_zone(&tm, 3600, "CET");
+
+    // An unknown time zone falls back to UTC.
+    env::set_var("TZ", "Not/A_Zone");
+    unsafe { libc::localtime_r(&t, &mut tm) };
+    assert_eq!((tm.tm_hour, tm.tm_isdst), (0, 0));
+    env::remove_var("TZ");
+}
+
+fn test_timegm_normalizes() {
+    // January 32nd is February 1st.
+    let mut tm = tm_for(2024, 1, 32, 0, 0, -1);
+    let t = unsafe { libc::timegm(&mut tm) };
+    assert_eq!(t, 1706745600);
+    assert_eq!((tm.tm_mon, tm.tm_mday, tm.tm_wday, tm.tm_yday), (1, 1, 4, 31));
+
+    // Negative fields are fine, too: "0:-30" on February 1st is 23:30 on January 31st.
+    let mut tm = tm_for(2024, 2, 1, 0, -30, -1);
+    let t = unsafe { libc::timegm(&mut tm) };
+    assert_eq!(t, 1706745600 - 30 * 60);
+    assert_eq!((tm.tm_mon, tm.tm_mday, tm.tm_hour, tm.tm_min), (0, 31, 23, 30));
+}
+
+fn test_mktime_roundtrip() {
+    env::set_var("TZ", "Europe/Berlin");
+    let mut tm = tm_for(2024, 7, 1, 12, 0, -1);
+    let t = unsafe { libc::mktime(&mut tm) };
+    assert_eq!(t, 1719828000);
+    assert_eq!(tm.tm_isdst, 1);
+    check_zone(&tm, 7200, "CEST");
+    env::remove_var("TZ");
+}
+
+fn test_mktime_ambiguous() {
+    env::set_var("TZ", "Europe/Berlin");
+    // 02:30 happens twice on 2024-10-27; `tm_isdst` picks which one we mean.
+    let mut tm = tm_for(2024, 10, 27, 2, 30, 1);
+    assert_eq!(unsafe { libc::mktime(&mut tm) }, 1729989000);
+    assert_eq!(tm.tm_isdst, 1);
+    let mut tm = tm_for(2024, 10, 27, 2, 30, 0);
+    assert_eq!(unsafe { libc::mktime(&mut tm) }, 1729992600);
+    assert_eq!(tm.tm_isdst, 0);
+    env::remove_var("TZ");
+}
+
+fn test_mktime_gap() {
+    env::set_var("TZ", "Europe/Berlin");
+    // 02:30 does not exist on 2024-03-31; it gets moved past the gap.
+    let mut tm = tm_for(2024, 3, 31, 2, 30, -1);
+    assert_eq!(unsafe { libc::mktime(&mut tm) }, 1711848600);
+    assert_eq!((tm.tm_hour, tm.tm_min, tm.tm_isdst), (3, 30, 1));
+    env::remove_var("TZ");
+}
+
+fn strftime(format: &CStr, tm: &libc::tm) -> String {
+    let mut buf = [0u8; 128];
+    let len = unsafe { libc::strftime(buf.as_mut_ptr().cast(), buf.len(), format.as_ptr(), tm) };
+    assert!(len > 0);
+    String::from_utf8(buf[..len].to_vec()).unwrap()
+}
+
+fn test_strftime() {
+    env::set_var("TZ", "Europe/Berlin");
+    let t: libc::time_t = 1719828000; // 2024-07-01 10:00:00 UTC
+    let mut tm: libc::tm = unsafe { mem::zeroed() };
+    unsafe { libc::localtime_r(&t, &mut tm) };
+
+    assert_eq!(strftime(c"%Y-%m-%d %H:%M:%S", &tm), "2024-07-01 12:00:00");
+    assert_eq!(strftime(c"%a %b %j %e", &tm), "Mon Jul 183  1");
+    assert_eq!(strftime(c"%G-W%V %U %W %u", &tm), "2024-W27 26 27 1");
+    assert_eq!(strftime(c"%c|%D %r", &tm), "Mon Jul  1 12:00:00 2024|07/01/24 12:00:00 PM");
+    assert_eq!(strftime(c"%s %%", &tm), "1719828000 %");
+    #[cfg(not(any(target_os = "solaris", target_os = "illumos")))]
+    assert_eq!(strftime(c"%Z %z", &tm), "CEST +0200");
+
+    // If the result does not fit (including the null terminator), 0 is returned.
+    let mut buf = [0u8; 4];
+    let len = unsafe { libc::strftime(buf.as_mut_ptr().cast(), buf.len(), c"%Y".as_ptr(), &tm) };
+    assert_eq!(len, 0);
+    env::remove_var("TZ");
+}
+
+fn test_difftime() {
+    assert_eq!(unsafe { libc::difftime(1719828000, 1706745600) }, 13082400.0);
+    assert_eq!(unsafe { libc::difftime(0, 60) }, -60.0);
+}
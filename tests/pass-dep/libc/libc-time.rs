@@ -121,7 +121,7 @@ fn test_localtime_r_gmt() {
     assert_eq!(tm.tm_year, 124);
     assert_eq!(tm.tm_wday, 0);
     assert_eq!(tm.tm_yday, 97);
-    assert_eq!(tm.tm_isdst, -1);
+    assert_eq!(tm.tm_isdst, 0);
     #[cfg(any(
         target_os = "linux",
         target_os = "macos",
@@ -131,7 +131,7 @@ fn test_localtime_r_gmt() {
     {
         assert_eq!(tm.tm_gmtoff, 0);
         unsafe {
-            assert_eq!(std::ffi::CStr::from_ptr(tm.tm_zone).to_str().unwrap(), "+00");
+            assert_eq!(std::ffi::CStr::from_ptr(tm.tm_zone).to_str().unwrap(), "GMT");
         }
     }
 
@@ -160,7 +160,7 @@ fn test_localtime_r_pst() {
     assert_eq!(tm.tm_year, 124);
     assert_eq!(tm.tm_wday, 0);
     assert_eq!(tm.tm_yday, 97);
-    assert_eq!(tm.tm_isdst, -1); // DST information unavailable
+    assert_eq!(tm.tm_isdst, 1); // PDT is in effect
 
     #[cfg(any(
         target_os = "linux",
@@ -171,7 +171,7 @@ fn test_localtime_r_pst() {
     {
         assert_eq!(tm.tm_gmtoff, -7 * 3600); // -7 hours in seconds
         unsafe {
-            assert_eq!(std::ffi::CStr::from_ptr(tm.tm_zone).to_str().unwrap(), "-07");
+            assert_eq!(std::ffi::CStr::from_ptr(tm.tm_zone).to_str().unwrap(), "PDT");
         }
     }
 
@@ -197,7 +197,7 @@ fn test_localtime_r_epoch() {
     assert_eq!(tm.tm_year, 70);
     assert_eq!(tm.tm_wday, 4); // Thursday
     assert_eq!(tm.tm_yday, 0);
-    assert_eq!(tm.tm_isdst, -1);
+    assert_eq!(tm.tm_isdst, 0);
 
     #[cfg(any(
         target_os = "linux",
@@ -208,7 +208,7 @@ fn test_localtime_r_epoch() {
     {
         assert_eq!(tm.tm_gmtoff, 0);
         unsafe {
-            assert_eq!(std::ffi::CStr::from_ptr(tm.tm_zone).to_str().unwrap(), "+00");
+            assert_eq!(std::ffi::CStr::from_ptr(tm.tm_zone).to_str().unwrap(), "GMT");
         }
     }
 
@@ -238,7 +238,7 @@ fn test_localtime_r_future_64b() {
     assert_eq!(tm.tm_year, 150); // 2050 - 1900
     assert_eq!(tm.tm_wday, 6); // Saturday
     assert_eq!(tm.tm_yday, 0);
-    assert_eq!(tm.tm_isdst, -1);
+    assert_eq!(tm.tm_isdst, 0);
 
     #[cfg(any(
         target_os = "linux",
@@ -249,7 +249,7 @@ fn test_localtime_r_future_64b() {
     {
         assert_eq!(tm.tm_gmtoff, 0);
         unsafe {
-            assert_eq!(std::ffi::CStr::from_ptr(tm.tm_zone).to_str().unwrap(), "+00");
+            assert_eq!(std::ffi::CStr::from_ptr(tm.tm_zone).to_str().unwrap(), "GMT");
         }
     }
 
@@ -280,7 +280,7 @@ fn test_localtime_r_future_32b() {
     assert_eq!(tm.tm_year, 130); // 2030 - 1900
     assert_eq!(tm.tm_wday, 2); // Tuesday
     assert_eq!(tm.tm_yday, 0);
-    assert_eq!(tm.tm_isdst, -1);
+    assert_eq!(tm.tm_isdst, 0);
 
     #[cfg(any(
         target_os = "linux",
@@ -291,7 +291,7 @@ fn test_localtime_r_future_32b() {
     {
         assert_eq!(tm.tm_gmtoff, 0);
         unsafe {
-            assert_eq!(std::ffi::CStr::from_ptr(tm.tm_zone).to_str().unwrap(), "+00");
+            assert_eq!(std::ffi::CStr::from_ptr(tm.tm_zone).to_str().unwrap(), "GMT");
         }
     }
 
@@ -0,0 +1,86 @@
+//@only-target: x86_64-unknown-linux-gnu # builds a System V `va_list` by hand
+use std::ffi::{CStr, c_char, c_int};
+
+/// A saved register or stack slot.
+#[repr(C)]
+#[derive(Clone, Copy)]
+union Slot {
+    int: i64,
+    ptr: *const c_char,
+    double: f64,
+}
+
+const EMPTY: Slot = Slot { int: 0 };
+
+/// What `va_start` saves in a variadic function.
+#[repr(C, align(16))]
+struct RegSaveArea {
+    gp: [Slot; 6],
+    fp: [[Slot; 2]; 8],
+}
+
+/// The element type of `va_list` on x86-64 System V.
+#[repr(C)]
+struct VaListTag {
+    gp_offset: u32,
+    fp_offset: u32,
+    overflow_arg_area: *mut Slot,
+    reg_save_area: *mut RegSaveArea,
+}
+
+unsafe extern "C" {
+    fn vsnprintf(buf: *mut c_char, size: usize, fmt: *const c_char, ap: *mut VaListTag) -> c_int;
+    fn vsprintf(buf: *mut c_char, fmt: *const c_char, ap: *mut VaListTag) -> c_int;
+    fn vprintf(fmt: *const c_char, ap: *mut VaListTag) -> c_int;
+    fn vdprintf(fd: c_int, fmt: *const c_char, ap: *mut VaListTag) -> c_int;
+}
+
+/// Calls `f` with the `va_list` of a variadic function that took the format string in the first
+/// register and was then passed `42, "str", -1, 7, 8, 9, 1.5, 2.5`: the first five integers are in
+/// registers and the last one on the stack, the first double is in the last vector register and
+/// the second one on the stack.
+fn with_va_list(f: impl FnOnce(*mut VaListTag) -> c_int) -> c_int {
+    let mut regs = RegSaveArea { gp: [EMPTY; 6], fp: [[EMPTY; 2]; 8] };
+    regs.gp[1] = Slot { int: 42 };
+    regs.gp[2] = Slot { ptr: c"str".as_ptr() };
+    regs.gp[3] = Slot { int: -1 };
+    regs.gp[4] = Slot { int: 7 };
+    regs.gp[5] = Slot { int: 8 };
+    regs.fp[7][0] = Slot { double: 1.5 };
+    let mut stack = [Slot { int: 9 }, Slot { double: 2.5 }];
+    let mut tag = VaListTag {
+        gp_offset: 8,
+        fp_offset: 48 + 7 * 16,
+        overflow_arg_area: stack.as_mut_ptr(),
+        reg_save_area: &mut regs,
+    };
+    f(&mut tag)
+}
+
+const FMT: &CStr = c"%d %s %d %d %d %d %.1f %g\n";
+const EXPECTED: &str = "42 str -1 7 8 9 1.5 2.5\n";
+
+fn main() {
+    let mut buf = [0 as c_char; 64];
+    let len =
+        with_va_list(|ap| unsafe { vsnprintf(buf.as_mut_ptr(), buf.len(), FMT.as_ptr(), ap) });
+    assert_eq!(len, EXPECTED.len() as c_int);
+    assert_eq!(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str().unwrap(), EXPECTED);
+
+    // Truncated output still reports the full length.
+    let mut small = [0 as c_char; 8];
+    let len =
+        with_va_list(|ap| unsafe { vsnprintf(small.as_mut_ptr(), small.len(), FMT.as_ptr(), ap) });
+    assert_eq!(len, EXPECTED.len() as c_int);
+    assert_eq!(unsafe { CStr::from_ptr(small.as_ptr()) }.to_str().unwrap(), &EXPECTED[..7]);
+
+    let mut buf = [0 as c_char; 64];
+    let len = with_va_list(|ap| unsafe { vsprintf(buf.as_mut_ptr(), FMT.as_ptr(), ap) });
+    assert_eq!(len, EXPECTED.len() as c_int);
+    assert_eq!(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str().unwrap(), EXPECTED);
+
+    let len = with_va_list(|ap| unsafe { vprintf(FMT.as_ptr(), ap) });
+    assert_eq!(len, EXPECTED.len() as c_int);
+    let len = with_va_list(|ap| unsafe { vdprintf(libc::STDOUT_FILENO, FMT.as_ptr(), ap) });
+    assert_eq!(len, EXPECTED.len() as c_int);
+}
@@ -0,0 +1,200 @@
+//@only-target: windows # this directly tests windows-only functions
+#![allow(nonstandard_style)]
+
+use std::fs::File;
+use std::io::{ErrorKind, Read, Write};
+use std::os::windows::io::{AsRawHandle, FromRawHandle};
+use std::{ptr, thread};
+
+use windows_sys::Win32::Foundation::{
+    CloseHandle, ERROR_ACCESS_DENIED, ERROR_FILE_NOT_FOUND, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED,
+    FALSE, GENERIC_READ, GENERIC_WRITE, GetLastError, HANDLE, INVALID_HANDLE_VALUE, TRUE,
+};
+use windows_sys::Win32::Storage::FileSystem::{
+    CreateFileW, FILE_FLAG_FIRST_PIPE_INSTANCE, FILE_SHARE_DELETE, FILE_SHARE_READ,
+    FILE_SHARE_WRITE, OPEN_EXISTING, PIPE_ACCESS_DUPLEX, PIPE_ACCESS_INBOUND,
+};
+use windows_sys::Win32::System::Pipes::{
+    ConnectNamedPipe, CreateNamedPipeW, CreatePipe, DisconnectNamedPipe, PIPE_TYPE_BYTE, PIPE_WAIT,
+    PeekNamedPipe,
+};
+
+fn main() {
+    unsafe {
+        test_anonymous_pipe();
+        test_named_pipe();
+        test_named_pipe_connect_first();
+        test_named_pipe_errors();
+    }
+}
+
+unsafe fn test_anonymous_pipe() {
+    let mut read: HANDLE = ptr::null_mut();
+    let mut write: HANDLE = ptr::null_mut();
+    assert_eq!(CreatePipe(&mut read, &mut write, ptr::null(), 0), TRUE);
+    let mut read = File::from_raw_handle(read);
+    let mut write = File::from_raw_handle(write);
+
+    write.write_all(b"hello").unwrap();
+    // Peeking does not consume anything.
+    let mut buf = [0u8; 3];
+    let mut peeked = 0;
+    let mut available = 0;
+    assert_eq!(
+        PeekNamedPipe(
+            read.as_raw_handle(),
+            buf.as_mut_ptr().cast(),
+            buf.len() as u32,
+            &mut peeked,
+            &mut available,
+            ptr::null_mut(),
+        ),
+        TRUE
+    );
+    assert_eq!((peeked, available), (3, 5));
+    assert_eq!(&buf, b"hel");
+
+    let mut buf = [0u8; 5];
+    read.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+
+    // Once the write end is gone, reads report EOF.
+    drop(write);
+    assert_eq!(read.read(&mut buf).unwrap(), 0);
+}
+
+fn pipe_name(name: &str) -> Vec<u16> {
+    format!(r"\\.\pipe\miri-{name}").encode_utf16().chain([0]).collect()
+}
+
+unsafe fn open_client(name: &[u16], access: u32) -> HANDLE {
+    CreateFileW(
+        name.as_ptr(),
+        access,
+        FILE_SHARE_DELETE | FILE_SHARE_READ | FILE_SHARE_WRITE,
+        ptr::null_mut(),
+        OPEN_EXISTING,
+        0,
+        ptr::null_mut(),
+    )
+}
+
+unsafe fn test_named_pipe() {
+    let name = pipe_name("duplex");
+    let server = CreateNamedPipeW(
+        name.as_ptr(),
+        PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE,
+        PIPE_TYPE_BYTE | PIPE_WAIT,
+        1,
+        0,
+        0,
+        0,
+        ptr::null(),
+    );
+    assert_ne!(server, INVALID_HANDLE_VALUE);
+    let server = server as usize;
+
+    // The server waits for the client in a separate thread.
+    let server_thread = thread::spawn(move || {
+        let server = server as HANDLE;
+        assert_eq!(ConnectNamedPipe(server, ptr::null_mut()), TRUE);
+        let mut server = File::from_raw_handle(server);
+        let mut buf = [0u8; 4];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        server.write_all(b"pong").unwrap();
+    });
+    thread::yield_now();
+
+    let client = open_client(&name, GENERIC_READ | GENERIC_WRITE);
+    assert_ne!(client, INVALID_HANDLE_VALUE, "CreateFileW failed: {}", GetLastError());
+    let mut client = File::from_raw_handle(client);
+    client.write_all(b"ping").unwrap();
+    let mut buf = [0u8; 4];
+    client.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"pong");
+
+    server_thread.join().unwrap();
+    // The server end is closed now.
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+    assert_eq!(client.write(b"x").unwrap_err().kind(), ErrorKind::BrokenPipe);
+}
+
+unsafe fn test_named_pipe_connect_first() {
+    let name = pipe_name("inbound");
+    let server = CreateNamedPipeW(
+        name.as_ptr(),
+        PIPE_ACCESS_INBOUND,
+        PIPE_TYPE_BYTE | PIPE_WAIT,
+        1,
+        0,
+        0,
+        0,
+        ptr::null(),
+    );
+    assert_ne!(server, INVALID_HANDLE_VALUE);
+
+    // Inbound pipes cannot be read by the client.
+    assert_eq!(open_client(&name, GENERIC_READ), INVALID_HANDLE_VALUE);
+    assert_eq!(GetLastError(), ERROR_ACCESS_DENIED);
+
+    // A client that connects before `ConnectNamedPipe` is reported as `ERROR_PIPE_CONNECTED`.
+    let client = open_client(&name, GENERIC_WRITE);
+    assert_ne!(client, INVALID_HANDLE_VALUE);
+    assert_eq!(ConnectNamedPipe(server, ptr::null_mut()), FALSE);
+    assert_eq!(GetLastError(), ERROR_PIPE_CONNECTED);
+
+    // There is only one instance, and it is taken.
+    assert_eq!(open_client(&name, GENERIC_WRITE), INVALID_HANDLE_VALUE);
+    assert_eq!(GetLastError(), ERROR_PIPE_BUSY);
+
+    let mut client = File::from_raw_handle(client);
+    client.write_all(b"data").unwrap();
+    let mut available = 0;
+    assert_eq!(
+        PeekNamedPipe(server, ptr::null_mut(), 0, ptr::null_mut(), &mut available, ptr::null_mut()),
+        TRUE
+    );
+    assert_eq!(available, 4);
+
+    // After disconnecting, the instance can be reused by the next client.
+    assert_eq!(DisconnectNamedPipe(server), TRUE);
+    assert_eq!(client.write(b"x").unwrap_err().kind(), ErrorKind::BrokenPipe);
+    let client = open_client(&name, GENERIC_WRITE);
+    assert_ne!(client, INVALID_HANDLE_VALUE);
+    assert_eq!(CloseHandle(client), TRUE);
+    assert_eq!(CloseHandle(server), TRUE);
+}
+
+unsafe fn test_named_pipe_errors() {
+    let name = pipe_name("missing");
+    assert_eq!(open_client(&name, GENERIC_READ), INVALID_HANDLE_VALUE);
+    assert_eq!(GetLastError(), ERROR_FILE_NOT_FOUND);
+
+    let name = pipe_name("first");
+    let server = CreateNamedPipeW(
+        name.as_ptr(),
+        PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE,
+        PIPE_TYPE_BYTE | PIPE_WAIT,
+        2,
+        0,
+        0,
+        0,
+        ptr::null(),
+    );
+    assert_ne!(server, INVALID_HANDLE_VALUE);
+    // A second instance cannot claim to be the first.
+    let second = CreateNamedPipeW(
+        name.as_ptr(),
+        PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE,
+        PIPE_TYPE_BYTE | PIPE_WAIT,
+        2,
+        0,
+        0,
+        0,
+        ptr::null(),
+    );
+    assert_eq!(second, INVALID_HANDLE_VALUE);
+    assert_eq!(GetLastError(), ERROR_ACCESS_DENIED);
+    assert_eq!(CloseHandle(server), TRUE);
+}
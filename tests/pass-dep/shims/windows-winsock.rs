@@ -0,0 +1,307 @@
+//@only-target: windows # this directly tests windows-only functions
+#![allow(nonstandard_style)]
+
+use std::io::{ErrorKind, IoSlice, IoSliceMut, Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::os::windows::io::AsRawSocket;
+use std::time::Duration;
+use std::{mem, ptr, thread};
+
+use windows_sys::Win32::Networking::WinSock::{
+    AF_INET, FD_SET, INVALID_SOCKET, IPPROTO_TCP, SOCK_STREAM, SOCKET, SOCKET_ERROR, TIMEVAL,
+    WSAEADDRINUSE, WSAECONNREFUSED, WSAEMSGSIZE, WSAENOTCONN, WSAENOTSOCK, WSAETIMEDOUT,
+    WSAEWOULDBLOCK, WSAGetLastError, WSANOTINITIALISED, closesocket, select, socket,
+};
+
+fn main() {
+    // This has to come first, before std initializes Winsock.
+    unsafe { test_not_initialised() };
+
+    test_tcp();
+    test_tcp_localhost();
+    test_tcp_vectored();
+    test_tcp_errors();
+    test_tcp_nonblocking();
+    test_tcp_timeout();
+    test_tcp_clone();
+    test_tcp_options();
+    test_udp();
+    test_udp_connected();
+    unsafe { test_select() };
+    unsafe { test_invalid_socket() };
+}
+
+unsafe fn test_not_initialised() {
+    let sock = socket(AF_INET.into(), SOCK_STREAM, IPPROTO_TCP);
+    assert_eq!(sock, INVALID_SOCKET);
+    assert_eq!(WSAGetLastError(), WSANOTINITIALISED);
+}
+
+fn test_tcp() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    assert_eq!(addr.ip(), Ipv4Addr::LOCALHOST);
+    assert_ne!(addr.port(), 0);
+
+    let client = thread::spawn(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+        stream.write_all(b"hello").unwrap();
+        // The server echoes the data back, and then closes its end.
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"hello");
+        stream.local_addr().unwrap()
+    });
+
+    let (mut stream, peer) = listener.accept().unwrap();
+    // Peeking does not consume anything.
+    let mut buf = [0u8; 5];
+    let mut peeked = 0;
+    while peeked < 5 {
+        peeked = stream.peek(&mut buf).unwrap();
+    }
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"hello");
+    stream.write_all(&buf).unwrap();
+    stream.shutdown(Shutdown::Write).unwrap();
+
+    let client_addr = client.join().unwrap();
+    assert_eq!(peer, client_addr);
+    assert_eq!(stream.local_addr().unwrap(), addr);
+}
+
+fn test_tcp_localhost() {
+    // This goes through `getaddrinfo`.
+    let listener = TcpListener::bind("localhost:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let client = thread::spawn(move || {
+        let mut stream = TcpStream::connect(("localhost", port)).unwrap();
+        stream.write_all(&[42]).unwrap();
+    });
+    let (mut stream, _) = listener.accept().unwrap();
+    let mut buf = [0u8];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(buf, [42]);
+    client.join().unwrap();
+
+    // Only `localhost` is known.
+    assert!(TcpStream::connect("example.com:80").is_err());
+}
+
+fn test_tcp_vectored() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(addr_of(&listener)).unwrap();
+    let (mut server, _) = listener.accept().unwrap();
+
+    // This uses `WSASend` and `WSARecv`.
+    let written = client.write_vectored(&[IoSlice::new(b"ab"), IoSlice::new(b"cde")]).unwrap();
+    assert_eq!(written, 5);
+    let mut first = [0u8; 3];
+    let mut second = [0u8; 3];
+    let read =
+        server.read_vectored(&mut [IoSliceMut::new(&mut first), IoSliceMut::new(&mut second)]);
+    assert_eq!(read.unwrap(), 5);
+    assert_eq!(&first, b"abc");
+    assert_eq!(&second[..2], b"de");
+}
+
+fn test_tcp_errors() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // The address is taken.
+    let err = TcpListener::bind(addr).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::AddrInUse);
+    assert_eq!(err.raw_os_error(), Some(WSAEADDRINUSE));
+
+    // Nobody listens here any more.
+    drop(listener);
+    let err = TcpStream::connect(addr).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::ConnectionRefused);
+    assert_eq!(err.raw_os_error(), Some(WSAECONNREFUSED));
+
+    // The peer closing the connection is the end of the data.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(addr_of(&listener)).unwrap();
+    drop(listener.accept().unwrap());
+    let mut buf = [0u8; 4];
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+
+    // After shutting down reading, there is nothing more to read.
+    let mut client = TcpStream::connect(addr_of(&listener)).unwrap();
+    client.shutdown(Shutdown::Read).unwrap();
+    assert_eq!(client.read(&mut buf).unwrap(), 0);
+}
+
+fn addr_of(listener: &TcpListener) -> SocketAddr {
+    listener.local_addr().unwrap()
+}
+
+fn test_tcp_nonblocking() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    listener.set_nonblocking(true).unwrap();
+    let err = listener.accept().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    assert_eq!(err.raw_os_error(), Some(WSAEWOULDBLOCK));
+
+    // Connections are established right away on the loopback network.
+    let mut client = TcpStream::connect(addr_of(&listener)).unwrap();
+    let (mut server, _) = listener.accept().unwrap();
+    server.set_nonblocking(true).unwrap();
+    let mut buf = [0u8; 4];
+    assert_eq!(server.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+    client.write_all(b"data").unwrap();
+    assert_eq!(server.read(&mut buf).unwrap(), 4);
+}
+
+fn test_tcp_timeout() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(addr_of(&listener)).unwrap();
+    let (mut server, _) = listener.accept().unwrap();
+
+    assert_eq!(server.read_timeout().unwrap(), None);
+    server.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+    assert_eq!(server.read_timeout().unwrap(), Some(Duration::from_millis(10)));
+    let mut buf = [0u8; 4];
+    let err = server.read(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert_eq!(err.raw_os_error(), Some(WSAETIMEDOUT));
+    drop(client);
+}
+
+fn test_tcp_clone() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(addr_of(&listener)).unwrap();
+    let (mut server, _) = listener.accept().unwrap();
+
+    // This goes through `WSADuplicateSocketW`.
+    let mut clone = client.try_clone().unwrap();
+    assert_ne!(clone.as_raw_socket(), client.as_raw_socket());
+    drop(client);
+    // The connection stays open while the clone is alive.
+    clone.write_all(b"x").unwrap();
+    let mut buf = [0u8];
+    server.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"x");
+}
+
+fn test_tcp_options() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(addr_of(&listener)).unwrap();
+
+    assert!(!stream.nodelay().unwrap());
+    stream.set_nodelay(true).unwrap();
+    assert!(stream.nodelay().unwrap());
+    stream.set_ttl(42).unwrap();
+    assert_eq!(stream.ttl().unwrap(), 42);
+    assert!(stream.take_error().unwrap().is_none());
+}
+
+fn test_udp() {
+    let a = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let b = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let b_addr = b.local_addr().unwrap();
+
+    a.send_to(b"first", b_addr).unwrap();
+    a.send_to(b"second", b_addr).unwrap();
+
+    // Datagrams keep their boundaries.
+    let mut buf = [0u8; 16];
+    let (len, from) = b.peek_from(&mut buf).unwrap();
+    assert_eq!((&buf[..len], from), (&b"first"[..], a.local_addr().unwrap()));
+    let (len, from) = b.recv_from(&mut buf).unwrap();
+    assert_eq!((&buf[..len], from), (&b"first"[..], a.local_addr().unwrap()));
+
+    // A datagram that does not fit is truncated, and reported as an error.
+    let mut small = [0u8; 3];
+    let err = b.recv_from(&mut small).unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(WSAEMSGSIZE));
+    assert_eq!(&small, b"sec");
+
+    // Datagrams to nowhere are lost without an error.
+    drop(b);
+    a.send_to(b"lost", b_addr).unwrap();
+
+    a.set_read_timeout(Some(Duration::from_millis(5))).unwrap();
+    assert_eq!(a.recv(&mut buf).unwrap_err().raw_os_error(), Some(WSAETIMEDOUT));
+}
+
+fn test_udp_connected() {
+    let a = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let b = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let c = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+    let err = a.send(b"x").unwrap_err();
+    assert_eq!(err.raw_os_error(), Some(WSAENOTCONN));
+
+    a.connect(b.local_addr().unwrap()).unwrap();
+    assert_eq!(a.peer_addr().unwrap(), b.local_addr().unwrap());
+    // A connected socket only receives from its peer.
+    c.send_to(b"ignored", a.local_addr().unwrap()).unwrap();
+    b.send_to(b"pong", a.local_addr().unwrap()).unwrap();
+
+    let receiver = thread::spawn(move || {
+        let mut buf = [0u8; 8];
+        let len = a.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"pong");
+        a.send(b"ping").unwrap();
+    });
+    receiver.join().unwrap();
+    let mut buf = [0u8; 8];
+    let len = b.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"ping");
+}
+
+unsafe fn test_select() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut client = TcpStream::connect(addr_of(&listener)).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    let server_socket = server.as_raw_socket() as SOCKET;
+
+    let fd_set = |socket: SOCKET| {
+        let mut set: FD_SET = mem::zeroed();
+        set.fd_count = 1;
+        set.fd_array[0] = socket;
+        set
+    };
+
+    // Nothing to read yet, but we can write.
+    let mut read_set = fd_set(server_socket);
+    let mut write_set = fd_set(server_socket);
+    let no_time = TIMEVAL { tv_sec: 0, tv_usec: 0 };
+    assert_eq!(select(0, &mut read_set, &mut write_set, ptr::null_mut(), &no_time), 1);
+    assert_eq!(read_set.fd_count, 0);
+    assert_eq!(write_set.fd_count, 1);
+    assert_eq!(write_set.fd_array[0], server_socket);
+
+    // `select` blocks until there is something to read.
+    let writer = thread::spawn(move || {
+        client.write_all(b"wake up").unwrap();
+    });
+    let mut read_set = fd_set(server_socket);
+    assert_eq!(select(0, &mut read_set, ptr::null_mut(), ptr::null_mut(), ptr::null()), 1);
+    assert_eq!(read_set.fd_array[0], server_socket);
+    writer.join().unwrap();
+
+    // A listener is readable when a connection is pending.
+    let listener_socket = listener.as_raw_socket() as SOCKET;
+    let mut read_set = fd_set(listener_socket);
+    let timeout = TIMEVAL { tv_sec: 0, tv_usec: 1000 };
+    assert_eq!(select(0, &mut read_set, ptr::null_mut(), ptr::null_mut(), &timeout), 0);
+    let _client = TcpStream::connect(addr_of(&listener)).unwrap();
+    let mut read_set = fd_set(listener_socket);
+    assert_eq!(select(0, &mut read_set, ptr::null_mut(), ptr::null_mut(), &timeout), 1);
+}
+
+unsafe fn test_invalid_socket() {
+    // Bad sockets are reported with a Winsock error, not as an invalid handle.
+    assert_eq!(closesocket(12345), SOCKET_ERROR);
+    assert_eq!(WSAGetLastError(), WSAENOTSOCK);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let socket = listener.as_raw_socket() as SOCKET;
+    drop(listener);
+    assert_eq!(closesocket(socket), SOCKET_ERROR);
+    assert_eq!(WSAGetLastError(), WSAENOTSOCK);
+}
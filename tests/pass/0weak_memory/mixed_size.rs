@@ -0,0 +1,77 @@
+//@compile-flags: -Zmiri-ignore-leaks -Zmiri-fixed-schedule -Zmiri-mixed-size-atomics
+
+// Tests the weak memory behaviours of racing mixed-size atomic accesses.
+
+use std::sync::atomic::Ordering::*;
+use std::sync::atomic::{AtomicU16, AtomicU32};
+use std::thread::spawn;
+
+#[path = "../../utils/mod.rs"]
+mod utils;
+use utils::check_all_outcomes;
+
+// We can't create static items because we need to run each test multiple times.
+fn static_atomic(val: u32) -> &'static AtomicU32 {
+    Box::leak(Box::new(AtomicU32::new(val)))
+}
+
+fn split(a: &AtomicU32) -> &[AtomicU16; 2] {
+    unsafe { std::mem::transmute(a) }
+}
+
+/// A load from a part of an atomic object can observe older stores to the whole object.
+fn load_part() {
+    check_all_outcomes([0, 0x0101, 0x0202], || {
+        let x = static_atomic(0);
+        x.store(0, Relaxed);
+        let j1 = spawn(move || {
+            x.store(0x0101_0101, Relaxed);
+            x.store(0x0202_0202, Relaxed);
+        });
+
+        let j2 = spawn(move || split(x)[0].load(Relaxed));
+
+        j1.join().unwrap();
+        j2.join().unwrap()
+    });
+}
+
+/// A store to a part of an atomic object is a store to the whole object, which keeps the other
+/// part.
+fn store_part() {
+    let merged = u32::from_ne_bytes([0xff, 0xff, 0x11, 0x11]);
+    check_all_outcomes([0x1111_1111, merged], || {
+        let x = static_atomic(0);
+        x.store(0x1111_1111, Relaxed);
+        let j1 = spawn(move || {
+            split(x)[0].store(0xffff, Relaxed);
+        });
+
+        let j2 = spawn(move || x.load(Relaxed));
+
+        j1.join().unwrap();
+        j2.join().unwrap()
+    });
+}
+
+/// A load spanning several atomic objects reads the latest value of each.
+fn load_whole() {
+    check_all_outcomes([0x0202_0202], || {
+        let x = static_atomic(0);
+        let j1 = spawn(move || {
+            split(x)[0].store(0x0202, Relaxed);
+            split(x)[1].store(0x0202, Relaxed);
+        });
+
+        let j2 = spawn(move || x.load(Relaxed));
+
+        j1.join().unwrap();
+        j2.join().unwrap()
+    });
+}
+
+fn main() {
+    load_part();
+    store_part();
+    load_whole();
+}
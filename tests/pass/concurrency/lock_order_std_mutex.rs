@@ -0,0 +1,30 @@
+//@only-target: linux # The standard library's `Mutex` is built on futexes here
+//@compile-flags: -Zmiri-deterministic-concurrency -Zmiri-deadlock-prediction
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+static A: Mutex<()> = Mutex::new(());
+static B: Mutex<()> = Mutex::new(());
+
+static DONE: AtomicBool = AtomicBool::new(false);
+
+fn lock_both(first: &Mutex<()>, second: &Mutex<()>) {
+    let _first = first.lock().unwrap();
+    let _second = second.lock().unwrap();
+}
+
+fn main() {
+    // The standard library's `Mutex` does not use Miri's mutexes on this target, so this checks
+    // that its acquisitions are recognized.
+    let t = thread::spawn(|| {
+        lock_both(&A, &B);
+        DONE.store(true, Ordering::Relaxed);
+    });
+    while !DONE.load(Ordering::Relaxed) {
+        thread::yield_now();
+    }
+    lock_both(&B, &A);
+    t.join().unwrap();
+}
@@ -0,0 +1,84 @@
+//@compile-flags: -Zmiri-pct=3 -Zmiri-pct-steps=2000
+//@revisions: seed1 seed2 seed3
+//@[seed1]compile-flags: -Zmiri-seed=1
+//@[seed2]compile-flags: -Zmiri-seed=2
+//@[seed3]compile-flags: -Zmiri-seed=3
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::{hint, thread};
+
+/// A thread spinning on a flag must let the thread that sets it run.
+fn spin_wait() {
+    static READY: AtomicBool = AtomicBool::new(false);
+    let waiter = thread::spawn(|| {
+        while !READY.load(Ordering::Acquire) {
+            hint::spin_loop();
+        }
+    });
+    let setter = thread::spawn(|| READY.store(true, Ordering::Release));
+    waiter.join().unwrap();
+    setter.join().unwrap();
+}
+
+fn mutex_counter() {
+    let counter = Arc::new(Mutex::new(0));
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let counter = Arc::clone(&counter);
+            thread::spawn(move || {
+                for _ in 0..3 {
+                    *counter.lock().unwrap() += 1;
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(*counter.lock().unwrap(), 12);
+}
+
+fn channel() {
+    let (tx, rx) = mpsc::channel();
+    let senders: Vec<_> = (0..3)
+        .map(|i| {
+            let tx = tx.clone();
+            thread::spawn(move || tx.send(i).unwrap())
+        })
+        .collect();
+    drop(tx);
+    let mut received: Vec<i32> = rx.iter().collect();
+    received.sort();
+    assert_eq!(received, [0, 1, 2]);
+    for sender in senders {
+        sender.join().unwrap();
+    }
+}
+
+fn yield_loop() {
+    static TURN: AtomicUsize = AtomicUsize::new(0);
+    let handles: Vec<_> = (0..2)
+        .map(|me| {
+            thread::spawn(move || {
+                for _ in 0..3 {
+                    while TURN.load(Ordering::SeqCst) % 2 != me {
+                        thread::yield_now();
+                    }
+                    TURN.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(TURN.load(Ordering::SeqCst), 6);
+}
+
+fn main() {
+    spin_wait();
+    mutex_counter();
+    channel();
+    yield_loop();
+}
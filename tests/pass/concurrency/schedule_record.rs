@@ -0,0 +1,12 @@
+//@ignore-host: windows # writes the trace to `/dev/stderr`
+//@compile-flags: -Zmiri-record-schedule=/dev/stderr
+//@compile-flags: -Zmiri-preemption-rate=0 -Zmiri-disable-weak-memory-emulation
+//@compile-flags: -Zmiri-compare-exchange-weak-failure-rate=0
+// With only one thread enabled at every switch, the recorded trace does not depend on the seed.
+
+use std::thread;
+
+fn main() {
+    let t = thread::spawn(|| {});
+    t.join().unwrap();
+}
@@ -0,0 +1,20 @@
+//@compile-flags: -Zmiri-replay-schedule=tests/pass/concurrency/schedule_replay.trace
+//@compile-flags: -Zmiri-preemption-rate=0 -Zmiri-disable-weak-memory-emulation
+//@compile-flags: -Zmiri-compare-exchange-weak-failure-rate=0
+// The replayed trace fixes the order in which the threads run, whatever the seed.
+//@revisions: seed1 seed2
+//@[seed1]compile-flags: -Zmiri-seed=1
+//@[seed2]compile-flags: -Zmiri-seed=2
+
+use std::sync::Mutex;
+use std::thread;
+
+static ORDER: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+fn main() {
+    let t1 = thread::spawn(|| ORDER.lock().unwrap().push(1));
+    let t2 = thread::spawn(|| ORDER.lock().unwrap().push(2));
+    t1.join().unwrap();
+    t2.join().unwrap();
+    assert_eq!(*ORDER.lock().unwrap(), [2, 1]);
+}
@@ -0,0 +1,11 @@
+//@compile-flags: -Zmiri-replay-schedule=tests/pass/concurrency/schedule_replay_diverged.trace
+//@compile-flags: -Zmiri-preemption-rate=0 -Zmiri-disable-weak-memory-emulation
+//@compile-flags: -Zmiri-compare-exchange-weak-failure-rate=0
+// A trace that does not fit the program is reported, and the program still runs to completion.
+
+use std::thread;
+
+fn main() {
+    let t = thread::spawn(|| {});
+    t.join().unwrap();
+}
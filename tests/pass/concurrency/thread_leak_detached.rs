@@ -0,0 +1,13 @@
+//@compile-flags: -Zmiri-report-thread-leaks=error
+// A detached thread may keep running when the main thread exits.
+
+use std::thread;
+
+fn main() {
+    // Dropping the `JoinHandle` detaches the thread.
+    drop(thread::spawn(|| {
+        loop {
+            thread::park();
+        }
+    }));
+}
@@ -0,0 +1,23 @@
+//@only-target: linux # The standard library's `Mutex` is built on futexes here
+//@ignore-host: windows # writes the timeline to `/dev/stderr`
+//@compile-flags: -Zmiri-deterministic-concurrency -Zmiri-thread-timeline=/dev/stderr
+// Timestamps depend on how many basic blocks the standard library executes.
+//@normalize-stderr-test: ": [0-9]+\.[0-9]{3}" -> ": T"
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+static M: Mutex<()> = Mutex::new(());
+
+fn main() {
+    let guard = M.lock().unwrap();
+    let t = thread::spawn(|| {
+        // This blocks until `main` releases the mutex, and only then acquires it.
+        drop(M.lock().unwrap());
+    });
+    // Let the other thread run until it blocks on the mutex.
+    thread::sleep(Duration::from_millis(10));
+    drop(guard);
+    t.join().unwrap();
+}
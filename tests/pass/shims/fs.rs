@@ -37,8 +37,11 @@ fn main() {
         test_canonicalize();
         #[cfg(unix)]
         test_pread_pwrite();
-        #[cfg(not(any(target_os = "solaris", target_os = "illumos")))]
+    }
+    #[cfg(not(any(target_os = "solaris", target_os = "illumos")))]
+    {
         test_flock();
+        test_flock_blocking();
     }
 }
 
@@ -410,3 +413,28 @@ fn test_flock() {
     // Unlock exclusive lock
     file1.unlock().unwrap();
 }
+
+#[cfg(not(any(target_os = "solaris", target_os = "illumos")))]
+fn test_flock_blocking() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    static RELEASED: AtomicBool = AtomicBool::new(false);
+
+    let bytes = b"Hello, World!\n";
+    let path = utils::prepare_with_content("miri_test_fs_flock_blocking.txt", bytes);
+    let file1 = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+    let file2 = OpenOptions::new().read(true).write(true).open(&path).unwrap();
+
+    file1.lock().unwrap();
+    let waiter = thread::spawn(move || {
+        // This blocks until the main thread releases its lock.
+        file2.lock_shared().unwrap();
+        assert!(RELEASED.load(Ordering::Relaxed));
+        file2.unlock().unwrap();
+    });
+    thread::yield_now();
+    RELEASED.store(true, Ordering::Relaxed);
+    file1.unlock().unwrap();
+    waiter.join().unwrap();
+}
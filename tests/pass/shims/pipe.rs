@@ -1,5 +1,3 @@
-//@ignore-target: windows
-
 use std::io::{Read, Write, pipe};
 
 fn main() {
@@ -0,0 +1,107 @@
+//@ignore-target: windows # child processes are only supported on Unix
+//@compile-flags: -Zmiri-disable-isolation
+use std::io::{Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{self, Command, Stdio};
+use std::{env, thread};
+
+fn me() -> Command {
+    Command::new(env::args().next().unwrap())
+}
+
+fn test_exit_status() {
+    let status = me().arg("exit").stdout(Stdio::null()).status().unwrap();
+    assert_eq!(status.code(), Some(42));
+}
+
+fn test_pipes() {
+    let mut child = me()
+        .arg("shout")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"hello child").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"HELLO CHILD");
+    assert!(String::from_utf8(output.stderr).unwrap().contains("done"));
+}
+
+fn test_threads_run_while_waiting() {
+    let mut child = me()
+        .arg("shout")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    // The main thread waits for the output while another thread provides the input, so waiting
+    // must not block that thread.
+    let writer = thread::spawn(move || stdin.write_all(b"hello").unwrap());
+    let mut output = String::new();
+    child.stdout.take().unwrap().read_to_string(&mut output).unwrap();
+    writer.join().unwrap();
+    assert_eq!(output, "HELLO");
+    assert!(child.wait().unwrap().success());
+}
+
+fn test_env_and_args() {
+    let output = me().args(["env", "a b"]).env("MIRI_CHILD_TEST", "42").output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "a b 42");
+}
+
+fn test_cleared_env() {
+    // The child interpreter must not depend on the environment of the interpreted program. With
+    // a cleared `PATH`, the program has to be given as a path; `current_exe` is the Miri binary,
+    // which also runs the interpreted program.
+    let output = Command::new(env::current_exe().unwrap())
+        .args(["env", "cleared"])
+        .env_clear()
+        .env("MIRI_CHILD_TEST", "7")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "cleared 7");
+}
+
+fn test_kill() {
+    let mut child = me().arg("wait").stdin(Stdio::piped()).spawn().unwrap();
+    assert!(child.try_wait().unwrap().is_none());
+    child.kill().unwrap();
+    let status = child.wait().unwrap();
+    assert_eq!(status.code(), None);
+    assert_eq!(status.signal(), Some(9));
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        None => {
+            test_exit_status();
+            test_pipes();
+            test_threads_run_while_waiting();
+            test_env_and_args();
+            test_cleared_env();
+            test_kill();
+        }
+        Some("exit") => process::exit(42),
+        Some("shout") => {
+            let mut input = String::new();
+            std::io::stdin().read_to_string(&mut input).unwrap();
+            print!("{}", input.to_uppercase());
+            eprint!("done");
+        }
+        Some("env") => {
+            print!("{} {}", args.next().unwrap(), env::var("MIRI_CHILD_TEST").unwrap());
+        }
+        Some("wait") => {
+            // Wait for input that never comes.
+            std::io::stdin().read_to_end(&mut Vec::new()).unwrap();
+        }
+        Some(arg) => panic!("unexpected argument {arg}"),
+    }
+}
@@ -0,0 +1,18 @@
+//@compile-flags: -Zmiri-realtime-jump=1000:-3600000 -Zmiri-realtime-jump=2000:7200000
+use std::time::{Duration, SystemTime};
+
+fn main() {
+    let start = SystemTime::now();
+
+    // After one second, the clock goes back by an hour.
+    std::thread::sleep(Duration::from_secs(1));
+    let back = SystemTime::now();
+    let diff = start.duration_since(back).unwrap();
+    assert!(diff > Duration::from_secs(3598) && diff < Duration::from_secs(3600));
+
+    // After two seconds, it goes forward by two hours.
+    std::thread::sleep(Duration::from_secs(1));
+    let forward = SystemTime::now();
+    let diff = forward.duration_since(start).unwrap();
+    assert!(diff > Duration::from_secs(3601) && diff < Duration::from_secs(3603));
+}
@@ -0,0 +1,27 @@
+//@compile-flags: -Zmiri-realtime-epoch=1700000000
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The virtual wall clock starts at the configured epoch.
+fn test_epoch() {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    assert!(since_epoch >= Duration::from_secs(1700000000));
+    assert!(since_epoch < Duration::from_secs(1700000000 + 60));
+}
+
+/// The virtual wall clock advances along with the monotonic clock.
+fn test_sleep() {
+    let before = SystemTime::now();
+    let instant = Instant::now();
+    std::thread::sleep(Duration::from_secs(3600));
+    let elapsed = instant.elapsed();
+    let after = SystemTime::now();
+    let diff = after.duration_since(before).unwrap();
+    assert!(diff >= Duration::from_secs(3600));
+    // Both clocks move in lockstep.
+    assert!(diff <= elapsed);
+}
+
+fn main() {
+    test_epoch();
+    test_sleep();
+}
@@ -0,0 +1,78 @@
+//@only-target: windows # this directly tests windows-only functions
+#![allow(nonstandard_style)]
+use std::thread;
+
+// Windows API definitions.
+type HANDLE = *mut std::ffi::c_void;
+type BOOL = i32;
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct FILETIME {
+    dwLowDateTime: u32,
+    dwHighDateTime: u32,
+}
+impl FILETIME {
+    fn ticks(self) -> u64 {
+        (u64::from(self.dwHighDateTime) << 32) | u64::from(self.dwLowDateTime)
+    }
+}
+extern "system" {
+    fn GetCurrentThread() -> HANDLE;
+    fn GetCurrentProcess() -> HANDLE;
+    fn GetThreadTimes(
+        hThread: HANDLE,
+        lpCreationTime: *mut FILETIME,
+        lpExitTime: *mut FILETIME,
+        lpKernelTime: *mut FILETIME,
+        lpUserTime: *mut FILETIME,
+    ) -> BOOL;
+    fn GetProcessTimes(
+        hProcess: HANDLE,
+        lpCreationTime: *mut FILETIME,
+        lpExitTime: *mut FILETIME,
+        lpKernelTime: *mut FILETIME,
+        lpUserTime: *mut FILETIME,
+    ) -> BOOL;
+}
+
+type GetTimes = unsafe extern "system" fn(
+    HANDLE,
+    *mut FILETIME,
+    *mut FILETIME,
+    *mut FILETIME,
+    *mut FILETIME,
+) -> BOOL;
+
+/// Returns (creation time, user time).
+fn times(f: GetTimes, handle: HANDLE) -> (u64, u64) {
+    let [mut creation, mut exit, mut kernel, mut user] = [FILETIME::default(); 4];
+    assert_ne!(unsafe { f(handle, &mut creation, &mut exit, &mut kernel, &mut user) }, 0);
+    assert_eq!(kernel.ticks(), 0);
+    (creation.ticks(), user.ticks())
+}
+
+fn busy_work(n: usize) {
+    for _ in 0..n {
+        drop(std::hint::black_box(vec![42]));
+    }
+}
+
+fn main() {
+    let (process_created, process_before) = times(GetProcessTimes, unsafe { GetCurrentProcess() });
+    let (main_created, main_before) = times(GetThreadTimes, unsafe { GetCurrentThread() });
+    assert_eq!(process_created, main_created);
+    assert!(process_created > 0);
+
+    // The work done by the child thread only shows up in its own user time.
+    let (child_created, child_time) = thread::spawn(|| {
+        busy_work(1000);
+        times(GetThreadTimes, unsafe { GetCurrentThread() })
+    })
+    .join()
+    .unwrap();
+    assert!(child_created > main_created);
+    let (_, main_after) = times(GetThreadTimes, unsafe { GetCurrentThread() });
+    let (_, process_after) = times(GetProcessTimes, unsafe { GetCurrentProcess() });
+    assert!(child_time > main_after - main_before);
+    assert!(process_after - process_before >= child_time);
+}
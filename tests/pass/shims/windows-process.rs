@@ -0,0 +1,51 @@
+//@only-target: windows # this directly tests windows-only functions
+//@compile-flags: -Zmiri-disable-isolation
+use std::process::{self, Command};
+use std::time::Duration;
+use std::{env, thread};
+
+fn me() -> Command {
+    Command::new(env::current_exe().unwrap())
+}
+
+fn test_exit_code() {
+    let status = me().arg("exit").status().unwrap();
+    assert_eq!(status.code(), Some(42));
+}
+
+fn test_env_and_args() {
+    // The arguments have to survive being quoted into a command line and split up again.
+    let status =
+        me().args(["env", "a \"b\" \\c\\", ""]).env("MIRI_CHILD_TEST", "42").status().unwrap();
+    assert!(status.success());
+}
+
+fn test_kill() {
+    let mut child = me().arg("wait").spawn().unwrap();
+    assert!(child.try_wait().unwrap().is_none());
+    child.kill().unwrap();
+    // `Child::kill` uses 1 as the exit code.
+    assert_eq!(child.wait().unwrap().code(), Some(1));
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    match args.next().as_deref() {
+        None => {
+            test_exit_code();
+            test_env_and_args();
+            test_kill();
+        }
+        Some("exit") => process::exit(42),
+        Some("env") => {
+            assert_eq!(args.next().unwrap(), "a \"b\" \\c\\");
+            assert_eq!(args.next().unwrap(), "");
+            assert_eq!(env::var("MIRI_CHILD_TEST").unwrap(), "42");
+        }
+        Some("wait") =>
+            loop {
+                thread::sleep(Duration::from_secs(1));
+            },
+        Some(arg) => panic!("unexpected argument {arg}"),
+    }
+}
@@ -0,0 +1,121 @@
+//@only-target: windows # this directly tests windows-only functions
+#![allow(nonstandard_style)]
+use std::env;
+
+// Windows API definitions.
+type BOOL = i32;
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FILETIME {
+    dwLowDateTime: u32,
+    dwHighDateTime: u32,
+}
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct SYSTEMTIME {
+    wYear: u16,
+    wMonth: u16,
+    wDayOfWeek: u16,
+    wDay: u16,
+    wHour: u16,
+    wMinute: u16,
+    wSecond: u16,
+    wMilliseconds: u16,
+}
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct TIME_ZONE_INFORMATION {
+    Bias: i32,
+    StandardName: [u16; 32],
+    StandardDate: SYSTEMTIME,
+    StandardBias: i32,
+    DaylightName: [u16; 32],
+    DaylightDate: SYSTEMTIME,
+    DaylightBias: i32,
+}
+const TIME_ZONE_ID_UNKNOWN: u32 = 0;
+const TIME_ZONE_ID_STANDARD: u32 = 1;
+const TIME_ZONE_ID_DAYLIGHT: u32 = 2;
+extern "system" {
+    fn GetTimeZoneInformation(lpTimeZoneInformation: *mut TIME_ZONE_INFORMATION) -> u32;
+    fn FileTimeToLocalFileTime(lpFileTime: *const FILETIME, lpLocalFileTime: *mut FILETIME)
+    -> BOOL;
+    fn LocalFileTimeToFileTime(lpLocalFileTime: *const FILETIME, lpFileTime: *mut FILETIME)
+    -> BOOL;
+}
+
+const INTERVALS_PER_SEC: u64 = 10_000_000;
+const SECONDS_TO_UNIX_EPOCH: u64 = 11_644_473_600;
+
+fn filetime(unix_secs: u64) -> FILETIME {
+    let ticks = (unix_secs + SECONDS_TO_UNIX_EPOCH) * INTERVALS_PER_SEC + 1234;
+    FILETIME { dwLowDateTime: ticks as u32, dwHighDateTime: (ticks >> 32) as u32 }
+}
+
+fn wide_name(name: &[u16; 32]) -> String {
+    let len = name.iter().position(|&c| c == 0).unwrap();
+    String::from_utf16(&name[..len]).unwrap()
+}
+
+/// The transition dates are "day-in-month" rules: the `wDay`-th (5 = last) `wDayOfWeek` of `wMonth`.
+fn rule(month: u16, day_of_week: u16, week: u16, hour: u16) -> SYSTEMTIME {
+    SYSTEMTIME {
+        wYear: 0,
+        wMonth: month,
+        wDayOfWeek: day_of_week,
+        wDay: week,
+        wHour: hour,
+        ..Default::default()
+    }
+}
+
+fn test_time_zone_information() {
+    env::set_var("TZ", "Europe/Berlin");
+    let mut tzi = TIME_ZONE_INFORMATION::default();
+    let id = unsafe { GetTimeZoneInformation(&mut tzi) };
+    // Which one it is depends on the current date.
+    assert!(id == TIME_ZONE_ID_STANDARD || id == TIME_ZONE_ID_DAYLIGHT);
+    assert_eq!(tzi.Bias, -60);
+    assert_eq!(tzi.StandardBias, 0);
+    assert_eq!(tzi.DaylightBias, -60);
+    assert_eq!(wide_name(&tzi.StandardName), "CET");
+    assert_eq!(wide_name(&tzi.DaylightName), "CEST");
+    // DST starts on the last Sunday of March at 02:00 and ends on the last Sunday of October at
+    // 03:00, both in the local time before the transition.
+    assert_eq!(tzi.DaylightDate, rule(3, 0, 5, 2));
+    assert_eq!(tzi.StandardDate, rule(10, 0, 5, 3));
+
+    // A zone without DST has no transition dates.
+    env::set_var("TZ", "Asia/Kolkata");
+    let mut tzi = TIME_ZONE_INFORMATION::default();
+    assert_eq!(unsafe { GetTimeZoneInformation(&mut tzi) }, TIME_ZONE_ID_UNKNOWN);
+    assert_eq!(tzi.Bias, -330);
+    assert_eq!(tzi.DaylightBias, 0);
+    assert_eq!(tzi.StandardDate, SYSTEMTIME::default());
+    assert_eq!(tzi.DaylightDate, SYSTEMTIME::default());
+
+    env::remove_var("TZ");
+}
+
+fn main() {
+    test_time_zone_information();
+
+    // Time conversions only depend on their input and `TZ`, so they work under isolation.
+    env::set_var("TZ", "Europe/Berlin");
+
+    // Summer (CEST, +2h) and winter (CET, +1h).
+    for (utc, offset) in [(1719828000, 7200), (1706745600, 3600)] {
+        let mut local = FILETIME { dwLowDateTime: 0, dwHighDateTime: 0 };
+        assert_ne!(unsafe { FileTimeToLocalFileTime(&filetime(utc), &mut local) }, 0);
+        assert_eq!(local, filetime(utc + offset));
+
+        let mut back = FILETIME { dwLowDateTime: 0, dwHighDateTime: 0 };
+        assert_ne!(unsafe { LocalFileTimeToFileTime(&local, &mut back) }, 0);
+        assert_eq!(back, filetime(utc));
+    }
+
+    env::remove_var("TZ");
+    let mut local = FILETIME { dwLowDateTime: 0, dwHighDateTime: 0 };
+    assert_ne!(unsafe { FileTimeToLocalFileTime(&filetime(1719828000), &mut local) }, 0);
+    assert_eq!(local, filetime(1719828000));
+}